@@ -0,0 +1,108 @@
+//! 消息列表的读取耗时
+//!
+//! `hydrate` 使用内存数据，测量按 ID 补全消息内容、标记和发送者的开销；设置
+//! `MALLCHAT_BENCH_DATABASE_URL` 后额外测量 MySQL 上房间 `MALLCHAT_BENCH_ROOM_ID`（默认为 1）
+//! 首页和最早一页的耗时，两者应当接近：
+//!
+//! ```shell
+//! cargo bench --bench message_page --features test-util
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use mallchat::handler::chat::MarkType;
+use mallchat::service::message::MessagePageService;
+use mallchat::storage::model::message;
+use mallchat::storage::repo::MessageRepo;
+use mallchat::testing::MemoryRepo;
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set};
+use tokio::runtime::Runtime;
+
+/// 内存数据中的消息数，即最大页大小
+const MESSAGES: u64 = 100;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("runtime")
+}
+
+fn hydrate(c: &mut Criterion) {
+    let rt = runtime();
+    let repo = MemoryRepo::default();
+    let uids: Vec<i64> = (0..10)
+        .map(|i| repo.add_user(&format!("open_id_{i}"), Some(&format!("user {i}"))))
+        .collect();
+    rt.block_on(async {
+        for i in 0..MESSAGES {
+            let from_uid = uids[i as usize % uids.len()];
+            MessageRepo::create(
+                &repo,
+                message::ActiveModel {
+                    room_id: Set(1),
+                    from_uid: Set(from_uid),
+                    content: Set(format!("message {i}")),
+                    status: Set(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("create message");
+            repo.add_mark(i + 1, from_uid, MarkType::Like as i32);
+        }
+    });
+
+    let service = MessagePageService::new(&repo, &repo, None);
+    let mut group = c.benchmark_group("hydrate");
+    for size in [20, 50, 100] {
+        let ids: Vec<u64> = (1..=size).rev().collect();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &ids, |b, ids| {
+            b.iter(|| {
+                rt.block_on(service.hydrate(black_box(ids), Some(uids[0])))
+                    .expect("hydrate")
+            })
+        });
+    }
+    group.finish();
+}
+
+fn mysql(c: &mut Criterion) {
+    let Ok(url) = std::env::var("MALLCHAT_BENCH_DATABASE_URL") else {
+        return;
+    };
+    let room_id = std::env::var("MALLCHAT_BENCH_ROOM_ID")
+        .ok()
+        .and_then(|room_id| room_id.parse().ok())
+        .unwrap_or(1);
+    let rt = runtime();
+    let db = rt.block_on(Database::connect(url)).expect("connect");
+    // 房间中最早的一页，表中数据需要事先准备
+    let deep: Option<u64> = rt
+        .block_on(
+            message::Entity::find()
+                .select_only()
+                .column(message::Column::Id)
+                .filter(message::Column::RoomId.eq(room_id))
+                .order_by_asc(message::Column::Id)
+                .offset(20)
+                .limit(1)
+                .into_tuple()
+                .one(&db),
+        )
+        .expect("deep cursor");
+
+    let service = MessagePageService::new(&db, &db, None);
+    let mut group = c.benchmark_group("mysql_page");
+    for (name, before) in [("first", None), ("deep", deep)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                rt.block_on(service.page(room_id, None, black_box(before), 21))
+                    .expect("page")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hydrate, mysql);
+criterion_main!(benches);
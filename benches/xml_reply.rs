@@ -0,0 +1,38 @@
+//! 公众号被动回复的序列化耗时
+//!
+//! 微信服务器 5 秒内收不到回复会重试，回复路径中序列化的耗时应当远小于这个时间：
+//!
+//! ```shell
+//! cargo bench --bench xml_reply
+//! ```
+
+use axum::response::IntoResponse;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mallchat::weixin::reply::WxReply;
+use mallchat::weixin::xml::{to_writer, Xml};
+
+fn xml_reply(c: &mut Criterion) {
+    let short = WxReply::text("gh_mallchat", "oUser", "登录成功");
+    let long = WxReply::text("gh_mallchat", "oUser", "欢迎使用 MallChat ]]> ".repeat(100));
+
+    c.bench_function("to_writer/short", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(512);
+            to_writer(&mut buf, black_box(&short)).expect("serialize");
+            buf
+        })
+    });
+    c.bench_function("to_writer/long", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(512);
+            to_writer(&mut buf, black_box(&long)).expect("serialize");
+            buf
+        })
+    });
+    c.bench_function("into_response/short", |b| {
+        b.iter(|| Xml(black_box(&short)).into_response())
+    });
+}
+
+criterion_group!(benches, xml_reply);
+criterion_main!(benches);
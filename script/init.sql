@@ -1,4 +1,6 @@
 SET NAMES utf8mb4;
+-- 时间列保存 UTC 时间，与服务端连接的会话时区相同
+SET time_zone = '+00:00';
 SET FOREIGN_KEY_CHECKS = 0;
 
 DROP DATABASE IF EXISTS mallchat;
@@ -30,6 +32,7 @@ INSERT INTO `item_config` VALUES (2, 2, 'https://cdn-icons-png.flaticon.com/128/
 INSERT INTO `item_config` VALUES (3, 2, 'https://cdn-icons-png.flaticon.com/512/6198/6198527.png ', '抹茶聊天前10名注册的用户才能获得的专属徽章', '2023-05-07 17:50:31.100', '2023-05-07 18:12:01.448');
 INSERT INTO `item_config` VALUES (4, 2, 'https://cdn-icons-png.flaticon.com/512/10232/10232583.png', '抹茶聊天前100名注册的用户才能获得的专属徽章', '2023-05-07 17:50:31.109', '2023-05-07 17:56:36.059');
 INSERT INTO `item_config` VALUES (5, 2, 'https://cdn-icons-png.flaticon.com/128/2909/2909937.png', '抹茶知识星球成员的专属徽章', '2023-05-07 17:50:31.109', '2023-05-07 17:56:36.059');
+INSERT INTO `item_config` VALUES (6, 2, NULL, '抹茶聊天前1000名注册的用户才能获得的专属徽章', '2023-08-02 00:00:00.000', '2023-08-02 00:00:00.000');
 
 -- ----------------------------
 -- Table structure for message
@@ -80,7 +83,7 @@ DROP TABLE IF EXISTS `room`;
 CREATE TABLE `room`  (
                          `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
                          `name` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '会话名',
-                         `type` int(11) NOT NULL COMMENT '会话类型 1大群聊 2沸点',
+                         `type` int(11) NOT NULL COMMENT '会话类型 1大群聊 2沸点 3单聊',
                          `active_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '最后活跃时间-排序',
                          `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
                          `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
@@ -137,6 +140,55 @@ CREATE TABLE `user_backpack`  (
                                   INDEX `idx_update_time`(`update_time`) USING BTREE
 ) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户背包表' ROW_FORMAT = Dynamic;
 
+DROP TABLE IF EXISTS `user_friend`;
+CREATE TABLE `user_friend`  (
+                                `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                                `uid` bigint(20) NOT NULL COMMENT 'uid',
+                                `friend_uid` bigint(20) NOT NULL COMMENT '好友uid',
+                                `delete_status` int(11) NOT NULL DEFAULT 0 COMMENT '逻辑删除 0正常 1删除',
+                                `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                PRIMARY KEY (`id`) USING BTREE,
+                                UNIQUE INDEX `uniq_uid_friend_uid`(`uid`, `friend_uid`) USING BTREE,
+                                INDEX `idx_create_time`(`create_time`) USING BTREE,
+                                INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户联系人表' ROW_FORMAT = Dynamic;
+
+DROP TABLE IF EXISTS `user_apply`;
+CREATE TABLE `user_apply`  (
+                               `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                               `uid` bigint(20) NOT NULL COMMENT '申请人uid',
+                               `type` int(11) NOT NULL COMMENT '申请类型 1加好友',
+                               `target_id` bigint(20) NOT NULL COMMENT '接收人uid',
+                               `msg` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '申请信息',
+                               `status` int(11) NOT NULL COMMENT '申请状态 1待审批 2同意 3拒绝',
+                               `read_status` int(11) NOT NULL COMMENT '阅读状态 1未读 2已读',
+                               `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                               `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                               PRIMARY KEY (`id`) USING BTREE,
+                               INDEX `idx_uid_target_id`(`uid`, `target_id`) USING BTREE,
+                               INDEX `idx_target_id_read_status`(`target_id`, `read_status`) USING BTREE,
+                               INDEX `idx_create_time`(`create_time`) USING BTREE,
+                               INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户申请表' ROW_FORMAT = Dynamic;
+
+DROP TABLE IF EXISTS `room_friend`;
+CREATE TABLE `room_friend`  (
+                                `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                                `room_id` bigint(20) NOT NULL COMMENT '房间id',
+                                `uid1` bigint(20) NOT NULL COMMENT 'uid1（更小的uid）',
+                                `uid2` bigint(20) NOT NULL COMMENT 'uid2（更大的uid）',
+                                `room_key` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '房间key由两个uid拼接，先做排序uid1_uid2',
+                                `status` int(11) NOT NULL COMMENT '房间状态 0正常 1禁用(删好友了禁用)',
+                                `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                PRIMARY KEY (`id`) USING BTREE,
+                                UNIQUE INDEX `uniq_room_key`(`room_key`) USING BTREE,
+                                INDEX `idx_room_id`(`room_id`) USING BTREE,
+                                INDEX `idx_create_time`(`create_time`) USING BTREE,
+                                INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '单聊房间表' ROW_FORMAT = Dynamic;
+
 DROP TABLE IF EXISTS `wx_msg`;
 CREATE TABLE `wx_msg`  (
                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
@@ -217,4 +269,206 @@ CREATE TABLE `user_role` (
 ) COMMENT='用户角色关系表';
 
 alter table `message` MODIFY COLUMN `type` int(11) DEFAULT '1' COMMENT '消息类型 1普通消息 2.撤回消息';
-alter table `message` MODIFY COLUMN `content` varchar(1024) COLLATE utf8mb4_unicode_ci DEFAULT NULL COMMENT '消息内容';
\ No newline at end of file
+alter table `message` MODIFY COLUMN `content` varchar(1024) COLLATE utf8mb4_unicode_ci DEFAULT NULL COMMENT '消息内容';
+
+CREATE TABLE `user_emoji`  (
+                               `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                               `uid` bigint(20) NOT NULL COMMENT '用户表ID',
+                               `expression_url` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '表情地址',
+                               `delete_status` int(1) NOT NULL DEFAULT 0 COMMENT '逻辑删除(0-正常,1-删除)',
+                               `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                               `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                               PRIMARY KEY (`id`) USING BTREE,
+                               INDEX `idx_uid`(`uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户表情包' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `statistics`  (
+                               `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                               `date` date NOT NULL COMMENT '统计日期',
+                               `new_users` bigint(20) NOT NULL DEFAULT 0 COMMENT '新增用户数',
+                               `active_users` bigint(20) NOT NULL DEFAULT 0 COMMENT '活跃用户数（发送过消息）',
+                               `messages` bigint(20) NOT NULL DEFAULT 0 COMMENT '消息数',
+                               `online_peak` bigint(20) NOT NULL DEFAULT 0 COMMENT '在线人数峰值',
+                               `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                               `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                               PRIMARY KEY (`id`) USING BTREE,
+                               UNIQUE INDEX `uniq_date`(`date`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '每日统计' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `message` ADD FULLTEXT INDEX `ft_content`(`content`) WITH PARSER ngram;
+
+CREATE TABLE `announcement`  (
+                                 `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                                 `uid` bigint(20) NOT NULL COMMENT '发布公告的管理员uid',
+                                 `content` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '公告内容',
+                                 `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                 `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                 PRIMARY KEY (`id`) USING BTREE,
+                                 INDEX `idx_create_time`(`create_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '系统公告' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `announcement_read`  (
+                                      `uid` bigint(20) NOT NULL COMMENT '用户uid',
+                                      `last_id` bigint(20) UNSIGNED NOT NULL COMMENT '已送达的最新公告id',
+                                      `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                      `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                      PRIMARY KEY (`uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户已送达的公告' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `user_credential`  (
+                                    `uid` bigint(20) NOT NULL COMMENT '用户uid',
+                                    `email` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '登录邮箱',
+                                    `password_hash` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT 'argon2 密码哈希',
+                                    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                    PRIMARY KEY (`uid`) USING BTREE,
+                                    UNIQUE INDEX `uniq_email`(`email`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '本地账号的登录凭据' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `room`
+    ADD COLUMN `last_msg_id` bigint(20) NULL DEFAULT NULL COMMENT '会话中的最后一条消息id' AFTER `active_time`,
+    ADD COLUMN `last_msg_abstract` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '最后一条消息的摘要' AFTER `last_msg_id`;
+
+CREATE TABLE `room_group`  (
+                               `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                               `room_id` bigint(20) NOT NULL COMMENT '房间id',
+                               `announcement` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '群公告',
+                               `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                               `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                               PRIMARY KEY (`id`) USING BTREE,
+                               UNIQUE INDEX `uniq_room_id`(`room_id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '群聊房间' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `group_member`  (
+                                 `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                                 `room_id` bigint(20) NOT NULL COMMENT '房间id',
+                                 `uid` bigint(20) NOT NULL COMMENT '成员uid',
+                                 `role` int(11) NOT NULL COMMENT '成员角色 1群主 2管理员 3普通成员',
+                                 `mute_until` datetime(3) NULL DEFAULT NULL COMMENT '禁言截止时间',
+                                 `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                                 `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                                 PRIMARY KEY (`id`) USING BTREE,
+                                 UNIQUE INDEX `uniq_room_id_uid`(`room_id`, `uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '群成员' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `message`
+    ADD INDEX `idx_room_status_id`(`room_id`, `status`, `id`) USING BTREE,
+    DROP INDEX `idx_room_id`;
+
+ALTER TABLE `message_mark`
+    ADD INDEX `idx_msg_status_type`(`msg_id`, `status`, `type`) USING BTREE,
+    DROP INDEX `idx_msg_id`;
+
+ALTER TABLE `message_mark` ADD UNIQUE INDEX `uniq_msg_uid_type`(`msg_id`, `uid`, `type`) USING BTREE;
+
+CREATE TABLE `room_read`  (
+                              `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                              `uid` bigint(20) NOT NULL COMMENT '用户uid',
+                              `room_id` bigint(20) NOT NULL COMMENT '房间id',
+                              `read_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '已读的最新消息id',
+                              `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                              `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                              PRIMARY KEY (`id`) USING BTREE,
+                              UNIQUE INDEX `uniq_uid_room_id`(`uid`, `room_id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '房间阅读进度' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `room`
+    ADD COLUMN `member_count` int(11) UNSIGNED NOT NULL DEFAULT 0 COMMENT '成员数，群聊为群成员记录数，单聊为 2' AFTER `type`;
+
+CREATE TABLE `webhook`  (
+                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                            `url` varchar(512) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '回调地址',
+                            `secret` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '签名密钥，为空时不签名',
+                            `events` varchar(256) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL DEFAULT '' COMMENT '订阅的事件，逗号分隔，为空时订阅全部',
+                            `uid` bigint(20) NOT NULL COMMENT '注册的管理员uid',
+                            `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                            PRIMARY KEY (`id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '事件Webhook' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `api_key`  (
+                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                            `name` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '名称',
+                            `prefix` varchar(16) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT 'Key的前缀，用于辨认',
+                            `key_hash` char(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT 'Key的SHA-256哈希',
+                            `scopes` varchar(256) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL DEFAULT '' COMMENT '授权范围，逗号分隔',
+                            `uid` bigint(20) NOT NULL COMMENT '创建的管理员uid',
+                            `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                            PRIMARY KEY (`id`) USING BTREE,
+                            UNIQUE INDEX `uniq_key_hash`(`key_hash`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = 'API Key' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `message_archive`  (
+                            `id` bigint(20) UNSIGNED NOT NULL COMMENT '原消息id',
+                            `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+                            `from_uid` bigint(20) NOT NULL COMMENT '消息发送者uid',
+                            `content` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci DEFAULT NULL COMMENT '消息内容',
+                            `reply_msg_id` bigint(20) NULL DEFAULT NULL COMMENT '回复的消息内容',
+                            `status` int(11) NOT NULL COMMENT '消息状态 0正常 1删除',
+                            `gap_count` int(11) NULL DEFAULT NULL COMMENT '与回复的消息间隔多少条',
+                            `type` int(11) NULL DEFAULT 1 COMMENT '消息类型 1正常文本 2.撤回消息',
+                            `extra` json DEFAULT NULL COMMENT '扩展信息',
+                            `create_time` datetime(3) NOT NULL COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL COMMENT '修改时间',
+                            `archive_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '归档时间',
+                            PRIMARY KEY (`id`) USING BTREE,
+                            INDEX `idx_room_id_id`(`room_id`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '归档的消息' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `archive_file`  (
+                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                            `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+                            `object_key` varchar(512) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '对象存储中的路径',
+                            `first_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '第一条消息id',
+                            `last_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '最后一条消息id',
+                            `message_count` int(11) UNSIGNED NOT NULL COMMENT '消息数',
+                            `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                            PRIMARY KEY (`id`) USING BTREE,
+                            INDEX `idx_room_id_id`(`room_id`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '归档到对象存储的消息文件' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `user`
+    ADD COLUMN `deleted_at` datetime(3) NULL DEFAULT NULL COMMENT '删除时间，为空时未删除' AFTER `update_time`;
+ALTER TABLE `message`
+    ADD COLUMN `deleted_at` datetime(3) NULL DEFAULT NULL COMMENT '删除时间，为空时未删除' AFTER `update_time`;
+ALTER TABLE `room`
+    ADD COLUMN `deleted_at` datetime(3) NULL DEFAULT NULL COMMENT '删除时间，为空时未删除' AFTER `update_time`;
+ALTER TABLE `user_friend`
+    ADD COLUMN `deleted_at` datetime(3) NULL DEFAULT NULL COMMENT '删除时间，为空时未删除' AFTER `update_time`;
+
+CREATE TABLE `moderation`  (
+                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                            `msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '被举报的消息id',
+                            `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+                            `from_uid` bigint(20) NOT NULL COMMENT '消息发送者uid',
+                            `reporter_uid` bigint(20) NOT NULL COMMENT '举报人uid',
+                            `reason` varchar(200) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '举报理由',
+                            `status` int(11) NOT NULL DEFAULT 0 COMMENT '处理状态 0待处理 1保留 2已删除',
+                            `operator_uid` bigint(20) NULL DEFAULT NULL COMMENT '处理的管理员uid',
+                            `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                            PRIMARY KEY (`id`) USING BTREE,
+                            UNIQUE INDEX `uniq_msg_id_reporter_uid`(`msg_id`, `reporter_uid`) USING BTREE,
+                            INDEX `idx_status_id`(`status`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '消息举报' ROW_FORMAT = Dynamic;
+
+CREATE TABLE `wx_welcome`  (
+                            `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+                            `app_id` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '公众号的开发者ID',
+                            `text` varchar(600) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '欢迎文本，为空时不发送',
+                            `news_media_id` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '永久图文素材的media_id，为空时不发送',
+                            `uid` bigint(20) NOT NULL COMMENT '最后修改的管理员uid',
+                            `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+                            `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+                            PRIMARY KEY (`id`) USING BTREE,
+                            UNIQUE INDEX `uniq_app_id`(`app_id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '公众号关注欢迎语' ROW_FORMAT = Dynamic;
+
+ALTER TABLE `room_read`
+    ADD INDEX `idx_room_read_uid`(`room_id`, `read_msg_id`, `uid`) USING BTREE;
+
+ALTER TABLE `user_apply`
+    ADD UNIQUE INDEX `uniq_uid_target_id_type`(`uid`, `target_id`, `type`) USING BTREE,
+    DROP INDEX `idx_uid_target_id`;
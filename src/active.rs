@@ -0,0 +1,156 @@
+//! # 用户活跃状态
+//!
+//! 已登录的 HTTP 请求和 WebSocket 请求（心跳除外）都会通过 [`ActiveTracker`] 记录用户活跃。
+//! 记录先在内存中合并，定时批量发布到 [`USER_ACTIVE_TOPIC`](crate::mq::active::USER_ACTIVE_TOPIC)，
+//! 再由 [`RecordActive`](crate::mq::active::RecordActive) 一次更新所有用户的 `user.last_opt_time`，
+//! 避免每个请求都写数据库。
+//!
+//! 成员列表中的在线状态分为三档，见 [`ActiveStatus`]，
+//! 在线、离开之间的变化由 [`PresenceRefresh`](crate::jobs::presence::PresenceRefresh) 定时推送。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashSet;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::handler::ws::SessionManager;
+use crate::mq::active::{UserActiveEvent, USER_ACTIVE_TOPIC};
+use crate::mq::{send_json, DynProducer, Producer};
+
+/// 用户活跃记录配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActiveConfig {
+    /// 批量发布活跃记录的间隔（秒）
+    #[serde(default = "default::flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+mod default {
+    pub fn flush_interval_secs() -> u64 {
+        10
+    }
+}
+
+impl Default for ActiveConfig {
+    fn default() -> Self {
+        Self {
+            flush_interval_secs: default::flush_interval_secs(),
+        }
+    }
+}
+
+/// 在线状态
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde_repr::Serialize_repr,
+    serde_repr::Deserialize_repr,
+    ToSchema,
+)]
+#[repr(u8)]
+pub enum ActiveStatus {
+    /// 在线：有连接，且最近有操作
+    Online = 1,
+    /// 离开：有连接，但所有连接都超过 `away_secs` 没有操作
+    Away = 2,
+    /// 离线：没有连接
+    Offline = 3,
+}
+
+/// # 用户活跃记录
+///
+/// 同时刷新用户在本实例所有 WebSocket 连接的最后操作时间
+#[derive(Debug, Clone)]
+pub struct ActiveTracker {
+    pending: Arc<DashSet<i64>>,
+    session_manager: SessionManager,
+}
+
+impl ActiveTracker {
+    /// 创建
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self {
+            pending: Arc::default(),
+            session_manager,
+        }
+    }
+
+    /// 记录用户活跃，不等待
+    pub fn record(&self, uid: i64) {
+        self.session_manager.touch_user(uid);
+        self.pending.insert(uid);
+    }
+
+    /// 取出上次发布以来活跃的用户
+    fn drain(&self) -> Vec<i64> {
+        let uids: Vec<i64> = self.pending.iter().map(|uid| *uid).collect();
+        for uid in &uids {
+            self.pending.remove(uid);
+        }
+        uids
+    }
+
+    /// 发布上次发布以来活跃的用户，返回用户数
+    pub async fn flush(&self, producer: &dyn Producer) -> anyhow::Result<usize> {
+        let uids = self.drain();
+        if uids.is_empty() {
+            return Ok(0);
+        }
+        let count = uids.len();
+        if let Err(error) = send_json(producer, USER_ACTIVE_TOPIC, &UserActiveEvent { uids }).await
+        {
+            // 只影响最后活跃时间的精度，不重试
+            tracing::warn!(%count, %error, "Failed to publish user active event.");
+            return Err(error);
+        }
+        Ok(count)
+    }
+
+    /// 启动定时发布的任务，停止时取消返回的任务即可
+    pub fn spawn(self, producer: DynProducer, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let _ = self.flush(producer.as_ref()).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::active::ActiveTracker;
+    use crate::handler::ws::SessionManager;
+    use crate::mq::active::{UserActiveEvent, USER_ACTIVE_TOPIC};
+    use crate::mq::memory::MemoryMq;
+    use crate::mq::Consumer;
+
+    #[tokio::test]
+    async fn flush() -> anyhow::Result<()> {
+        let mq = MemoryMq::default();
+        let consumer = mq.consumer(USER_ACTIVE_TOPIC, "test", 3);
+        let tracker = ActiveTracker::new(SessionManager::default());
+        assert_eq!(tracker.flush(&mq).await?, 0);
+
+        tracker.record(1);
+        tracker.record(2);
+        tracker.record(1);
+        assert_eq!(tracker.flush(&mq).await?, 2);
+        assert_eq!(tracker.flush(&mq).await?, 0);
+
+        let deliveries = consumer.poll().await?;
+        assert_eq!(deliveries.len(), 1);
+        let mut event: UserActiveEvent = serde_json::from_slice(&deliveries[0].payload)?;
+        event.uids.sort();
+        assert_eq!(event.uids, vec![1, 2]);
+        Ok(())
+    }
+}
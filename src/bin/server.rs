@@ -1,74 +1,765 @@
 mod service {
     use anyhow::Context;
+    use config::builder::{ConfigBuilder, DefaultState};
+    use mallchat::active::{ActiveConfig, ActiveTracker};
+    use mallchat::bot::BotConfig;
     use mallchat::cache::CacheConfig;
+    use mallchat::captcha::{ArithmeticCaptcha, Captcha};
     use mallchat::handler::auth::JwtKeys;
-    use mallchat::handler::HttpConfig;
-    use mallchat::log::LogConfig;
+    use mallchat::handler::client_ip::TrustedProxies;
+    use mallchat::handler::cors::CorsConfig;
+    use mallchat::handler::limit::LimitConfig;
+    use mallchat::handler::oss;
+    use mallchat::handler::ws::SessionManager;
+    use mallchat::handler::{HttpConfig, RouterBuilder};
+    use mallchat::i18n::{set_default_locale, I18nConfig};
+    use mallchat::ip::{IpConfig, IpTracker};
+    use mallchat::jobs::archive::{MessageArchive, RetentionConfig};
+    use mallchat::jobs::hot_room::HotRoomDecay;
+    use mallchat::jobs::member_count::{MemberCountMetrics, MemberCountReconcile};
+    use mallchat::jobs::presence::PresenceRefresh;
+    use mallchat::jobs::session::SessionCleanup;
+    use mallchat::jobs::stats::{DailyStatistics, OnlineSampling};
+    use mallchat::jobs::wx::AccessTokenRefresh;
+    use mallchat::jobs::{JobLock, JobsConfig, Scheduler};
+    use mallchat::live::{ConfigWatcher, Live, ReloadConfig, ReplyTimeouts};
+    use mallchat::log::{LogConfig, LogFilterHandle};
+    use mallchat::mq::active::{RecordActive, USER_ACTIVE_GROUP, USER_ACTIVE_TOPIC};
+    use mallchat::mq::announcement::{PushAnnouncement, ANNOUNCEMENT_TOPIC};
+    use mallchat::mq::bot::{BotMention, BotReply, BOT_MENTION_GROUP, BOT_REPLY_GROUP, BOT_TOPIC};
+    use mallchat::mq::group::{PushGroupChange, GROUP_TOPIC};
+    use mallchat::mq::kick::{KickSessions, KICK_TOPIC};
+    use mallchat::mq::message::{push_group, DiscoverUrl, HotRoom, OfflinePush, PushMessage};
+    use mallchat::mq::message::{
+        HOT_ROOM_GROUP, MESSAGE_TOPIC, MESSAGE_UPDATE_TOPIC, OFFLINE_PUSH_GROUP, URL_DISCOVER_GROUP,
+    };
+    use mallchat::mq::recall::{PushRecall, RECALL_TOPIC};
+    use mallchat::mq::typing::{PushTyping, TYPING_TOPIC};
+    use mallchat::mq::{MessageQueue, MqConfig};
+    use mallchat::push::PushConfig;
+    use mallchat::shortlink::ShortLink;
+    use mallchat::spam::{SpamConfig, SpamGuard};
+    use mallchat::storage::oss::OssConfig;
+    use mallchat::storage::repo::Repos;
+    use mallchat::storage::write_behind::WriteBehind;
     use mallchat::storage::StorageConfig;
-    use mallchat::weixin::{WxClient, WxConfig};
+    use mallchat::url_discover::{UrlDiscover, UrlDiscoverConfig};
+    use mallchat::webhook::{WebhookConfig, Webhooks, WEBHOOK_GROUP, WEBHOOK_TOPIC};
+    use mallchat::weixin::work::{WorkClient, WorkConfig};
+    use mallchat::weixin::{DynWxApi, WxClient, WxClientRegistry, WxConfig, WxConfigs};
     use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
     use std::net::SocketAddr;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
     use time::UtcOffset;
 
     #[derive(Debug, Serialize, Deserialize)]
     struct Config {
         http: HttpConfig,
-        wx: WxConfig,
+        wx: WxConfigs,
+        #[serde(default)]
+        wx_work: Option<WorkConfig>,
         storage: StorageConfig,
         cache: CacheConfig,
         log: LogConfig,
+        #[serde(default)]
+        ip: IpConfig,
+        #[serde(default)]
+        active: ActiveConfig,
+        #[serde(default)]
+        jobs: JobsConfig,
+        #[serde(default)]
+        mq: MqConfig,
+        #[serde(default)]
+        oss: OssConfig,
+        #[serde(default)]
+        url_discover: UrlDiscoverConfig,
+        #[serde(default)]
+        bot: BotConfig,
+        #[serde(default)]
+        push: PushConfig,
+        #[serde(default)]
+        reload: ReloadConfig,
+        #[serde(default)]
+        webhook: WebhookConfig,
+        #[serde(default)]
+        retention: RetentionConfig,
+        #[serde(default)]
+        spam: SpamConfig,
+        #[serde(default)]
+        i18n: I18nConfig,
     }
 
+    impl Config {
+        fn read(path: &Path) -> anyhow::Result<Self> {
+            config::Config::builder()
+                .add_source(config::File::from(path))
+                .add_source(config::Environment::with_prefix("MALLCHAT").separator("__"))
+                .build()
+                .context("read config")?
+                .try_deserialize()
+                .context("deserialize config")
+        }
+
+        /// 只从 `MALLCHAT__` 开头的环境变量读取配置，`source` 为 `None` 时读取进程的环境变量
+        ///
+        /// 默认不写日志文件，stdout 输出 JSON 格式日志，便于容器部署
+        fn env_builder(
+            source: Option<HashMap<String, String>>,
+        ) -> anyhow::Result<ConfigBuilder<DefaultState>> {
+            Ok(config::Config::builder()
+                .set_default("log.file", false)?
+                .set_default("log.stdout_format", "json")?
+                .add_source(
+                    config::Environment::with_prefix("MALLCHAT")
+                        .separator("__")
+                        .try_parsing(true)
+                        .source(source),
+                ))
+        }
+
+        /// 不读取配置文件，只从环境变量读取配置
+        #[cfg(not(feature = "shuttle"))]
+        fn read_env() -> anyhow::Result<Self> {
+            let mut config: Self = Self::env_builder(None)?
+                .build()
+                .context("read environment variables")?
+                .try_deserialize()
+                .context("deserialize config")?;
+            // 没有配置文件可以监听
+            config.reload.enabled = false;
+            Ok(config)
+        }
+
+        /// 解析各配置中的密钥，见 [`mallchat::secret`]
+        fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+            self.http.resolve_secrets()?;
+            self.wx.resolve_secrets()?;
+            if let Some(wx_work) = &mut self.wx_work {
+                wx_work.resolve_secrets()?;
+            }
+            self.storage.resolve_secrets()?;
+            self.cache.resolve_secrets()?;
+            Ok(())
+        }
+    }
+
+    /// 运行时可以修改的配置
+    #[derive(Clone)]
+    struct LiveConfig {
+        limits: Live<LimitConfig>,
+        cors: Live<CorsConfig>,
+        reply_timeouts: Live<ReplyTimeouts>,
+    }
+
+    impl LiveConfig {
+        fn new(http: &HttpConfig, wx: &[WxConfig]) -> Self {
+            Self {
+                limits: Live::new(http.limits.clone()),
+                cors: Live::new(http.cors.clone()),
+                reply_timeouts: Live::new(reply_timeouts(wx)),
+            }
+        }
+
+        /// 重新读取配置文件，发布日志级别、请求超时、跨域来源和微信被动回复超时，其他配置需要重启生效
+        fn reloader(
+            self,
+            path: PathBuf,
+            log_filter: LogFilterHandle,
+            mut directives: String,
+        ) -> impl FnMut() -> anyhow::Result<()> {
+            move || {
+                let Config { http, wx, log, .. } = Config::read(&path)?;
+                http.cors.layer()?;
+                // 只在配置文件中的日志级别变化时修改，保留通过管理接口设置的日志级别
+                let new_directives = log.filter_directives();
+                if new_directives != directives {
+                    log_filter.reload(&new_directives)?;
+                    directives = new_directives;
+                }
+                self.limits.store(http.limits);
+                self.cors.store(http.cors);
+                self.reply_timeouts
+                    .store(reply_timeouts(&Vec::<WxConfig>::from(wx)));
+                Ok(())
+            }
+        }
+    }
+
+    fn reply_timeouts(wx: &[WxConfig]) -> ReplyTimeouts {
+        wx.iter()
+            .map(|wx| {
+                (
+                    wx.app_id.clone(),
+                    Duration::from_millis(wx.reply_timeout_millis),
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "shuttle"))]
     #[tokio::main]
-    async fn tokio_start(config: Config, offset: UtcOffset) -> anyhow::Result<()> {
+    async fn tokio_start(config: Config, path: PathBuf, offset: UtcOffset) -> anyhow::Result<()> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.http.port));
+        serve(config, path, offset, addr).await
+    }
+
+    /// 初始化各组件并在 `addr` 上提供服务，直到收到退出信号
+    async fn serve(
+        config: Config,
+        path: PathBuf,
+        offset: UtcOffset,
+        addr: SocketAddr,
+    ) -> anyhow::Result<()> {
         let Config {
             http,
             wx,
+            wx_work,
             storage,
             cache,
             log,
+            ip,
+            active,
+            jobs,
+            mq,
+            oss,
+            url_discover,
+            bot,
+            push,
+            reload,
+            webhook,
+            retention,
+            spam,
+            i18n,
         } = config;
 
-        let _logger = log.init("mallchat", ".", offset, true).await?;
+        let log_directives = log.filter_directives();
+        let logger = log.init("mallchat", ".", offset, true).await?;
+        set_default_locale(i18n.default_locale);
 
         tracing::info!(?storage, "Connect to database.");
+        let write_behind = WriteBehind::new(storage.write_behind.clone());
         let storage = storage.connect().await?;
 
         tracing::info!(?cache, "Connect to redis.");
         let cache = cache.connect().await?;
 
-        let key = JwtKeys::try_from(http.jwt_secret.as_str())?;
-        let wx_client = WxClient::new(wx).await?;
-        tracing::info!(app_id = %wx_client.app_id(), "Retrieve weixin acccess token.");
+        let key = JwtKeys::try_from(http.jwt_secret.as_str())?.with_cache(cache.clone());
+        let wx = Vec::<WxConfig>::from(wx);
+        let live = LiveConfig::new(&http, &wx);
+        let mut wx_clients = Vec::new();
+        for wx in wx {
+            let wx_client = WxClient::new(wx).await?;
+            tracing::info!(app_id = %wx_client.app_id(), "Retrieve weixin acccess token.");
+            wx_clients.push(wx_client);
+        }
+        let wx_registry = WxClientRegistry::new(
+            wx_clients
+                .iter()
+                .map(|wx_client| Arc::new(wx_client.clone()) as DynWxApi)
+                .collect(),
+        )?;
+        let work_client = match wx_work {
+            Some(wx_work) => {
+                let work_client = WorkClient::new(wx_work).await?;
+                tracing::info!(corp_id = %work_client.config().corp_id, "Retrieve weixin work acccess token.");
+                Some(work_client)
+            }
+            None => None,
+        };
 
-        let addr = SocketAddr::from(([0, 0, 0, 0], http.port));
         tracing::info!(%addr, "Server start.");
 
-        let router =
-            mallchat::handler::router(true, http.static_files_path, storage, cache, key, wx_client);
-        axum::Server::bind(&addr)
-            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
-            .await?;
+        let session_manager = SessionManager::new(http.websocket.clone());
+        let repos = cache.local().repos(Repos::new(&storage));
+        let member_count_metrics = MemberCountMetrics::default();
+        let (object_store, local_store) = oss.build()?;
+        tracing::info!(?object_store, "Object store initialized.");
+        let scheduler = jobs.enabled.then(|| {
+            let lock = JobLock::new(Some(cache.clone()), jobs.lock_ttl_secs);
+            let mut scheduler = Scheduler::new(offset, lock)
+                .register(
+                    jobs.hot_room_decay,
+                    HotRoomDecay::new(cache.clone(), jobs.hot_room_decay_factor),
+                )
+                .register(
+                    jobs.session_cleanup,
+                    SessionCleanup::new(
+                        session_manager.clone(),
+                        Duration::from_secs(jobs.session_idle_secs),
+                    ),
+                )
+                .register(
+                    jobs.presence_refresh,
+                    PresenceRefresh::new(session_manager.clone(), repos.users.clone()),
+                )
+                .register(
+                    jobs.daily_statistics,
+                    DailyStatistics::new(storage.clone(), cache.clone(), offset),
+                )
+                .register(
+                    jobs.online_sampling,
+                    OnlineSampling::new(storage.clone(), cache.clone(), session_manager.clone()),
+                )
+                .register(
+                    jobs.access_token_refresh,
+                    AccessTokenRefresh::new(
+                        wx_clients.clone(),
+                        jobs.access_token_refresh_ahead_secs,
+                    ),
+                )
+                .register(
+                    jobs.member_count_reconcile,
+                    MemberCountReconcile::new(storage.clone(), member_count_metrics.clone()),
+                );
+            if retention.enabled {
+                tracing::info!(?retention, "Message archive enabled.");
+                scheduler = scheduler.register(
+                    jobs.message_archive,
+                    MessageArchive::new(storage.clone(), object_store.clone(), retention),
+                );
+            }
+            scheduler.start()
+        });
+        let mq = MessageQueue::new(mq, cache.clone())?;
+        let instance_id = mq.config().instance_id();
+        tracing::info!(backend = ?mq.config().backend, %instance_id, "Subscribe message queue.");
+        let mut subscriptions = vec![
+            mallchat::mq::subscribe(
+                mq.consumer(MESSAGE_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                PushMessage::new(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(MESSAGE_TOPIC, HOT_ROOM_GROUP, &instance_id)
+                    .await?,
+                HotRoom::new(cache.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(
+                    MESSAGE_UPDATE_TOPIC,
+                    &push_group(&instance_id),
+                    &instance_id,
+                )
+                .await?,
+                PushMessage::update(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(ANNOUNCEMENT_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                PushAnnouncement::new(session_manager.clone(), storage.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(GROUP_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                PushGroupChange::new(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(RECALL_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                PushRecall::new(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(TYPING_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                PushTyping::new(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(KICK_TOPIC, &push_group(&instance_id), &instance_id)
+                    .await?,
+                KickSessions::new(session_manager.clone()),
+            ),
+            mallchat::mq::subscribe(
+                mq.consumer(USER_ACTIVE_TOPIC, USER_ACTIVE_GROUP, &instance_id)
+                    .await?,
+                RecordActive::new(repos.users.clone()),
+            ),
+        ];
+        if url_discover.enabled {
+            subscriptions.push(mallchat::mq::subscribe(
+                mq.consumer(MESSAGE_TOPIC, URL_DISCOVER_GROUP, &instance_id)
+                    .await?,
+                DiscoverUrl::new(
+                    repos.messages.clone(),
+                    mq.producer(),
+                    UrlDiscover::new(url_discover),
+                ),
+            ));
+        }
+        if bot.enabled {
+            tracing::info!(uid = %bot.uid, name = %bot.name, "Chat bot enabled.");
+            let chat_bot = bot.build()?;
+            subscriptions.push(mallchat::mq::subscribe(
+                mq.consumer(MESSAGE_TOPIC, BOT_MENTION_GROUP, &instance_id)
+                    .await?,
+                BotMention::new(bot.clone(), cache.clone(), mq.producer()),
+            ));
+            subscriptions.push(mallchat::mq::subscribe(
+                mq.consumer(BOT_TOPIC, BOT_REPLY_GROUP, &instance_id)
+                    .await?,
+                BotReply::new(bot, chat_bot, repos.clone(), mq.producer()),
+            ));
+        }
+        if push.enabled {
+            let providers = push.build(&wx_registry)?;
+            tracing::info!(?providers, "Offline push enabled.");
+            subscriptions.push(mallchat::mq::subscribe(
+                mq.consumer(MESSAGE_TOPIC, OFFLINE_PUSH_GROUP, &instance_id)
+                    .await?,
+                OfflinePush::new(
+                    push,
+                    repos.users.clone(),
+                    session_manager.clone(),
+                    providers,
+                ),
+            ));
+        }
+
+        let webhooks = if webhook.enabled {
+            tracing::info!(endpoints = ?webhook.endpoints, "Webhook enabled.");
+            let webhooks = Webhooks::new(&webhook, Some(storage.clone()), Some(mq.producer()))?;
+            subscriptions.push(mallchat::mq::subscribe(
+                mq.consumer(WEBHOOK_TOPIC, WEBHOOK_GROUP, &instance_id)
+                    .await?,
+                webhooks.clone(),
+            ));
+            Some(webhooks)
+        } else {
+            None
+        };
+
+        let ip_tracker = IpTracker::new(repos.users.clone(), ip.load()?).with_cache(cache.clone());
+        let active_tracker = ActiveTracker::new(session_manager.clone());
+        let active_flush = active_tracker.clone().spawn(
+            mq.producer(),
+            Duration::from_secs(active.flush_interval_secs.max(1)),
+        );
+        let messages = repos.messages.clone();
+        let write_behind_flush = write_behind.clone().spawn(messages.clone());
+        let mut builder = RouterBuilder::new();
+        let watcher = if reload.enabled {
+            tracing::info!(?path, ?reload, "Watch config file.");
+            builder = builder
+                .cors(CorsConfig::live_layer(live.cors.clone())?)
+                .live_limits(live.limits.clone())
+                .reply_timeouts(live.reply_timeouts.clone());
+            let watcher = ConfigWatcher::new(&path, &reload);
+            Some(watcher.spawn(live.reloader(path, logger.filter_handle(), log_directives)))
+        } else {
+            if let Some(cors) = http.cors.layer()? {
+                builder = builder.cors(cors);
+            }
+            None
+        };
+        if let Some(local_store) = local_store {
+            builder = builder.upload(oss::local_route(local_store));
+        }
+        if let Some(work_client) = work_client {
+            builder = builder.work_client(work_client);
+        }
+        if http.captcha.enabled {
+            builder = builder.captcha(Captcha::new(
+                http.captcha,
+                Arc::new(ArithmeticCaptcha),
+                cache.clone(),
+            ));
+        }
+        let spam_purge = if spam.enabled {
+            tracing::info!(?spam, "Spam guard enabled.");
+            let spam_guard = SpamGuard::new(spam);
+            builder = builder.spam_guard(spam_guard.clone());
+            Some(spam_guard.spawn(Duration::from_secs(60)))
+        } else {
+            None
+        };
+        if http.shortlink.enabled {
+            builder = builder.shortlink(ShortLink::new(http.shortlink, cache.clone()));
+        }
+        if let Some(webhooks) = webhooks {
+            builder = builder.webhooks(webhooks);
+        }
+        let router = builder
+            .limits(http.limits)
+            .local_auth(http.local_auth)
+            .swagger(true)
+            .static_files(http.static_files_path)
+            .storage(storage.clone())
+            .repos(repos)
+            .cache(cache)
+            .jwt_keys(key)
+            .wx_clients(wx_registry)
+            .session_manager(session_manager.clone())
+            .log_filter(logger.filter_handle())
+            .trusted_proxies(TrustedProxies::new(&http.trusted_proxies)?)
+            .ip_tracker(ip_tracker)
+            .active_tracker(active_tracker.clone())
+            .write_behind(write_behind.clone())
+            .member_count_metrics(member_count_metrics)
+            .producer(mq.producer())
+            .object_store(object_store, oss)
+            .build();
+        let service = router.into_make_service_with_connect_info::<SocketAddr>();
+        let listener = http.listener.bind(addr)?;
+        let handle = axum_server::Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                shutdown_signal().await;
+                handle.graceful_shutdown(None);
+            }
+        });
+        tracing::info!(listener = ?http.listener, "Listen.");
+        match &http.tls {
+            Some(tls) => {
+                let tls_config = tls.load(http.listener.http2).await?;
+                let reload = tls.watch(tls_config.clone(), http.listener.http2);
+                tracing::info!(%addr, "Serve with tls.");
+                let mut server = axum_server::from_tcp_rustls(listener, tls_config)
+                    .map(|tls| http.listener.acceptor(tls))
+                    .handle(handle);
+                http.listener.configure_http(server.http_builder());
+                server.serve(service).await?;
+                if let Some(reload) = reload {
+                    reload.abort();
+                }
+            }
+            None => {
+                let mut server = axum_server::from_tcp(listener)
+                    .map(|plain| http.listener.acceptor(plain))
+                    .handle(handle);
+                http.listener.configure_http(server.http_builder());
+                server.serve(service).await?
+            }
+        }
+
+        if let Some(scheduler) = scheduler {
+            tracing::info!("Stop scheduled jobs.");
+            scheduler.shutdown();
+        }
+        active_flush.abort();
+        if let Some(spam_purge) = spam_purge {
+            spam_purge.abort();
+        }
+        // 发布最后一批活跃记录，由其他实例或下次启动后处理
+        let _ = active_tracker.flush(mq.producer().as_ref()).await;
+        for subscription in subscriptions {
+            subscription.abort();
+        }
+        if let Some(watcher) = watcher {
+            watcher.abort();
+        }
+
+        let timeout = Duration::from_secs(http.shutdown_timeout_secs);
+        tracing::info!(
+            sessions = session_manager.len(),
+            ?timeout,
+            "Draining websocket sessions."
+        );
+        let remaining = session_manager.close_all(timeout).await;
+        if remaining > 0 {
+            tracing::warn!(%remaining, "Websocket sessions not closed before timeout.");
+        }
+
+        // 等正在进行的写入完成后，写入最后一批消息标记和阅读进度
+        write_behind.stop();
+        if let Err(error) = write_behind_flush.await {
+            tracing::error!(%error, "Write-behind flush task failed.");
+        }
+        match write_behind.flush(messages.as_ref()).await {
+            Ok(count) => tracing::info!(%count, "Flush write-behind buffer."),
+            Err(error) => tracing::error!(%error, "Failed to flush write-behind buffer."),
+        }
+        tracing::info!("Close database connections.");
+        if let Err(error) = storage.close().await {
+            tracing::error!(%error, "Failed to close database connections.");
+        }
+
+        tracing::info!("Server stopped.");
+        // 确保日志全部写入文件
+        drop(logger);
         Ok(())
     }
 
+    /// 等待 CTRL-C 或 SIGTERM 信号
+    async fn shutdown_signal() {
+        let ctrl_c = async {
+            if let Err(error) = tokio::signal::ctrl_c().await {
+                tracing::error!(%error, "Failed to listen for CTRL-C.");
+                std::future::pending::<()>().await;
+            }
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut signal) => {
+                    signal.recv().await;
+                }
+                Err(error) => {
+                    tracing::error!(%error, "Failed to listen for SIGTERM.");
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => tracing::info!("Received CTRL-C, shutting down."),
+            _ = terminate => tracing::info!("Received SIGTERM, shutting down."),
+        }
+    }
+
+    #[cfg(not(feature = "shuttle"))]
     pub(crate) fn start() -> anyhow::Result<()> {
         let path = PathBuf::from("server.toml");
         let offset = UtcOffset::current_local_offset()?;
 
-        let config = config::Config::builder()
-            .add_source(config::File::from(path.as_path()))
-            .add_source(config::Environment::with_prefix("MALLCHAT").separator("__"))
-            .build()
-            .context("read config")?
-            .try_deserialize()
-            .context("deserialize config")?;
+        // 使用 `--env-only` 参数或设置 `MALLCHAT_CONFIG=env` 时不读取 server.toml
+        let env_only = std::env::args().skip(1).any(|arg| arg == "--env-only")
+            || std::env::var("MALLCHAT_CONFIG").is_ok_and(|config| config == "env");
+        let mut config = if env_only {
+            Config::read_env()?
+        } else {
+            Config::read(&path)?
+        };
+        config.resolve_secrets()?;
+
+        tokio_start(config, path, offset)
+    }
+
+    /// # 部署到 Shuttle
+    ///
+    /// 数据库使用 Shuttle 提供的 MySQL，其他配置从 `Secrets.toml` 读取，键与环境变量相同，如：
+    ///
+    /// ```toml
+    /// MALLCHAT__CACHE__HOST = "redis.example.com"
+    /// MALLCHAT__CACHE__PORT = "6379"
+    /// ```
+    ///
+    /// Shuttle 没有持久化磁盘，日志只输出到 stdout，也不监听配置文件
+    #[cfg(feature = "shuttle")]
+    pub(crate) mod shuttle {
+        use super::Config;
+        use anyhow::Context;
+        use mallchat::storage::StorageConfig;
+        use serde::Serialize;
+        use shuttle_secrets::SecretStore;
+        use shuttle_service::database::{AwsRdsEngine, Type as DatabaseType};
+        use shuttle_service::{DbInput, DbOutput, Factory, ResourceBuilder, Type};
+        use std::net::SocketAddr;
+        use std::path::PathBuf;
+        use time::UtcOffset;
+
+        /// Shuttle 提供的 MySQL 数据库，得到连接字符串
+        ///
+        /// `shuttle-aws-rds` 只提供 sqlx 的连接池，与 sea-orm 使用的 sqlx 版本不同，所以只取连接字符串
+        #[derive(Serialize)]
+        pub(crate) struct MySql {
+            config: DbInput,
+        }
+
+        #[async_trait::async_trait]
+        impl ResourceBuilder<String> for MySql {
+            const TYPE: Type = Type::Database(DatabaseType::AwsRds(AwsRdsEngine::MySql));
 
-        tokio_start(config, offset)
+            type Config = DbInput;
+            type Output = DbOutput;
+
+            fn new() -> Self {
+                Self {
+                    config: DbInput::default(),
+                }
+            }
+
+            fn config(&self) -> &Self::Config {
+                &self.config
+            }
+
+            async fn output(
+                self,
+                factory: &mut dyn Factory,
+            ) -> Result<Self::Output, shuttle_service::Error> {
+                Ok(DbOutput::Info(
+                    factory
+                        .get_db_connection(DatabaseType::AwsRds(AwsRdsEngine::MySql))
+                        .await?,
+                ))
+            }
+
+            async fn build(build_data: &Self::Output) -> Result<String, shuttle_service::Error> {
+                Ok(match build_data {
+                    DbOutput::Local(local_uri) => local_uri.clone(),
+                    DbOutput::Info(info) => info.connection_string_private(),
+                })
+            }
+        }
+
+        /// 在 Shuttle 分配的地址上运行的服务
+        pub(crate) struct MallchatService {
+            config: Config,
+        }
+
+        impl MallchatService {
+            /// 使用 Shuttle 提供的数据库和 secrets 构造配置
+            pub(crate) fn new(database_url: &str, secrets: SecretStore) -> anyhow::Result<Self> {
+                let storage = StorageConfig::from_url(database_url)?;
+                let mut config: Config = Config::env_builder(Some(secrets.into_iter().collect()))?
+                    .set_default("storage.auto_migrate", true)?
+                    .set_override("storage.host", storage.host)?
+                    .set_override("storage.port", storage.port)?
+                    .set_override("storage.username", storage.username)?
+                    .set_override("storage.password", storage.password)?
+                    .set_override("storage.database", storage.database)?
+                    .build()
+                    .context("read secrets")?
+                    .try_deserialize()
+                    .context("deserialize secrets")?;
+                config.log.file = false;
+                config.reload.enabled = false;
+                config.resolve_secrets()?;
+                Ok(Self { config })
+            }
+        }
+
+        #[shuttle_runtime::async_trait]
+        impl shuttle_runtime::Service for MallchatService {
+            async fn bind(self, addr: SocketAddr) -> Result<(), shuttle_runtime::Error> {
+                let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+                super::serve(self.config, PathBuf::from("server.toml"), offset, addr).await?;
+                Ok(())
+            }
+        }
     }
 }
 
+#[cfg(not(feature = "shuttle"))]
 fn main() -> anyhow::Result<()> {
-    service::start()
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        // 导出协议文档后退出：mallchat export-docs [目录，默认为 api-docs]
+        Some("export-docs") => {
+            let dir = args.next().unwrap_or_else(|| "api-docs".to_string());
+            for path in mallchat::handler::doc::export(std::path::Path::new(&dir))? {
+                println!("{}", path.display());
+            }
+            Ok(())
+        }
+        _ => service::start(),
+    }
+}
+
+#[cfg(feature = "shuttle")]
+#[shuttle_runtime::main]
+async fn main(
+    #[service::shuttle::MySql] database_url: String,
+    #[shuttle_secrets::Secrets] secrets: shuttle_secrets::SecretStore,
+) -> Result<service::shuttle::MallchatService, shuttle_runtime::Error> {
+    Ok(service::shuttle::MallchatService::new(
+        &database_url,
+        secrets,
+    )?)
 }
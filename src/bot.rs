@@ -0,0 +1,167 @@
+//! # 聊天机器人
+//!
+//! 消息中 @ 机器人时，[`BotMention`](crate::mq::bot::BotMention) 检查发送者的频率限制后发布回复请求，
+//! 由 [`BotReply`](crate::mq::bot::BotReply) 以机器人的身份在房间中发送一条占位消息，
+//! 生成过程中通过消息更新推送已生成的内容，生成结束后保存完整回复。
+//!
+//! 生成回复只依赖 [`ChatBot`] trait，默认提供兼容 OpenAI Chat Completions 接口的 [`openai::OpenAiBot`]。
+
+pub mod openai;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::bot::openai::{OpenAiBot, OpenAiConfig};
+
+/// 共享的聊天机器人
+pub type DynChatBot = Arc<dyn ChatBot>;
+
+/// 聊天机器人
+#[async_trait]
+pub trait ChatBot: Debug + Send + Sync {
+    /// 根据对话生成回复，返回完整内容；生成过程中的增量内容依次发送到 `delta`
+    async fn complete(
+        &self,
+        messages: &[ChatMessage],
+        delta: mpsc::UnboundedSender<String>,
+    ) -> anyhow::Result<String>;
+}
+
+/// 对话中消息的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// 系统提示
+    System,
+    /// 用户
+    User,
+    /// 机器人
+    Assistant,
+}
+
+/// 对话中的消息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// 角色
+    pub role: Role,
+    /// 内容
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// 创建
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+/// 聊天机器人配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+    /// 机器人的 uid，需要预先创建该用户
+    #[serde(default)]
+    pub uid: i64,
+    /// 机器人的昵称，消息中包含 `@昵称` 时触发回复
+    #[serde(default = "default::name")]
+    pub name: String,
+    /// 系统提示
+    #[serde(default = "default::system_prompt")]
+    pub system_prompt: String,
+    /// 每个用户在 `rate_limit_secs` 内最多请求的次数
+    #[serde(default = "default::rate_limit")]
+    pub rate_limit: u64,
+    /// 频率限制的时间窗口（秒）
+    #[serde(default = "default::rate_limit_secs")]
+    pub rate_limit_secs: u64,
+    /// 推送已生成内容的最小间隔（毫秒）
+    #[serde(default = "default::stream_interval_millis")]
+    pub stream_interval_millis: u64,
+    /// OpenAI 兼容接口
+    #[serde(default)]
+    pub openai: OpenAiConfig,
+}
+
+mod default {
+    pub fn name() -> String {
+        "MallChatBot".to_string()
+    }
+
+    pub fn system_prompt() -> String {
+        "你是 MallChat 聊天室中的助手，请使用简洁的中文回答。".to_string()
+    }
+
+    pub fn rate_limit() -> u64 {
+        5
+    }
+
+    pub fn rate_limit_secs() -> u64 {
+        60
+    }
+
+    pub fn stream_interval_millis() -> u64 {
+        500
+    }
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            uid: 0,
+            name: default::name(),
+            system_prompt: default::system_prompt(),
+            rate_limit: default::rate_limit(),
+            rate_limit_secs: default::rate_limit_secs(),
+            stream_interval_millis: default::stream_interval_millis(),
+            openai: OpenAiConfig::default(),
+        }
+    }
+}
+
+impl BotConfig {
+    /// 创建聊天机器人
+    pub fn build(&self) -> anyhow::Result<DynChatBot> {
+        Ok(Arc::new(OpenAiBot::new(self.openai.clone())?))
+    }
+
+    /// 消息 @ 了机器人时返回去掉 `@昵称` 后的问题，问题为空时返回 `None`
+    pub fn mention(&self, content: &str) -> Option<String> {
+        let mention = format!("@{}", self.name);
+        if !content.contains(&mention) {
+            return None;
+        }
+        let prompt = content.replace(&mention, " ");
+        let prompt = prompt.trim();
+        (!prompt.is_empty()).then(|| prompt.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::BotConfig;
+
+    #[test]
+    fn mention() {
+        let config = BotConfig {
+            name: "Bot".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.mention("@Bot 你好"), Some("你好".to_string()));
+        assert_eq!(
+            config.mention("请问 @Bot 什么是 Rust？"),
+            Some("请问   什么是 Rust？".to_string())
+        );
+        assert_eq!(config.mention("@Bot "), None);
+        assert_eq!(config.mention("Bot 你好"), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! # OpenAI 兼容接口
+//!
+//! 使用 Chat Completions 接口的流式输出（Server-Sent Events），兼容 OpenAI 以及提供相同接口的
+//! 其他模型服务。
+
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::bot::{ChatBot, ChatMessage};
+
+/// OpenAI 兼容接口配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiConfig {
+    /// 接口地址，不包括 `/chat/completions`
+    #[serde(default = "default::base_url")]
+    pub base_url: String,
+    /// API Key
+    #[serde(default)]
+    pub api_key: String,
+    /// 模型
+    #[serde(default = "default::model")]
+    pub model: String,
+    /// 回复的最大 token 数
+    #[serde(default = "default::max_tokens")]
+    pub max_tokens: u32,
+    /// 超时时间（秒）
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+mod default {
+    pub fn base_url() -> String {
+        "https://api.openai.com/v1".to_string()
+    }
+
+    pub fn model() -> String {
+        "gpt-3.5-turbo".to_string()
+    }
+
+    pub fn max_tokens() -> u32 {
+        512
+    }
+
+    pub fn timeout_secs() -> u64 {
+        60
+    }
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default::base_url(),
+            api_key: String::new(),
+            model: default::model(),
+            max_tokens: default::max_tokens(),
+            timeout_secs: default::timeout_secs(),
+        }
+    }
+}
+
+/// 请求体
+#[derive(Debug, Serialize)]
+struct CompletionReq<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    max_tokens: u32,
+    stream: bool,
+}
+
+/// 流式输出的一个事件
+#[derive(Debug, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+/// 将响应内容按行拆分为 Server-Sent Events 的 `data` 字段
+#[derive(Debug, Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    /// 追加收到的内容，返回其中完整的 `data` 字段
+    fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut data = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(value) = line.trim_end().strip_prefix("data:") {
+                data.push(value.trim_start().to_string());
+            }
+        }
+        data
+    }
+}
+
+/// OpenAI 兼容接口的聊天机器人
+pub struct OpenAiBot {
+    config: OpenAiConfig,
+    client: reqwest::Client,
+}
+
+impl Debug for OpenAiBot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiBot")
+            .field("base_url", &self.config.base_url)
+            .field("model", &self.config.model)
+            .finish()
+    }
+}
+
+impl OpenAiBot {
+    /// 创建
+    pub fn new(config: OpenAiConfig) -> anyhow::Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        Ok(Self { config, client })
+    }
+}
+
+#[async_trait]
+impl ChatBot for OpenAiBot {
+    async fn complete(
+        &self,
+        messages: &[ChatMessage],
+        delta: mpsc::UnboundedSender<String>,
+    ) -> anyhow::Result<String> {
+        let url = format!(
+            "{}/chat/completions",
+            self.config.base_url.trim_end_matches('/')
+        );
+        let mut response = self
+            .client
+            .post(url)
+            .bearer_auth(&self.config.api_key)
+            .json(&CompletionReq {
+                model: &self.config.model,
+                messages,
+                max_tokens: self.config.max_tokens,
+                stream: true,
+            })
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("chat completion failed: {status}: {body}");
+        }
+
+        let mut decoder = SseDecoder::default();
+        let mut content = String::new();
+        while let Some(chunk) = response.chunk().await? {
+            for data in decoder.feed(&chunk) {
+                if data == "[DONE]" {
+                    return Ok(content);
+                }
+                let chunk: CompletionChunk = serde_json::from_str(&data)?;
+                for choice in chunk.choices {
+                    if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                        content.push_str(&text);
+                        // 接收端关闭时继续生成完整回复
+                        let _ = delta.send(text);
+                    }
+                }
+            }
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::sync::mpsc;
+
+    use crate::bot::openai::{OpenAiBot, OpenAiConfig, SseDecoder};
+    use crate::bot::{ChatBot, ChatMessage, Role};
+
+    #[test]
+    fn sse() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"data: {\"a\"").is_empty());
+        assert_eq!(
+            decoder.feed(b":1}\n\n: comment\ndata: [DONE]\r\n"),
+            vec![r#"{"a":1}"#.to_string(), "[DONE]".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn complete() -> anyhow::Result<()> {
+        const BODY: &str = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"你\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"好\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let api = Router::new().route(
+            "/v1/chat/completions",
+            post(|| async { ([(header::CONTENT_TYPE, "text/event-stream")], BODY) }),
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, api).await });
+
+        let bot = OpenAiBot::new(OpenAiConfig {
+            base_url: format!("http://{addr}/v1/"),
+            ..Default::default()
+        })?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let content = bot
+            .complete(&[ChatMessage::new(Role::User, "hi")], sender)
+            .await?;
+        assert_eq!(content, "你好");
+        assert_eq!(receiver.recv().await.as_deref(), Some("你"));
+        assert_eq!(receiver.recv().await.as_deref(), Some("好"));
+        assert_eq!(receiver.recv().await, None);
+        server.abort();
+        Ok(())
+    }
+}
@@ -1,32 +1,735 @@
 //! # 外部缓存
+//!
+//! 支持三种部署方式，启动时根据配置选择：
+//!
+//! - 单机：连接 `host:port`；
+//! - 哨兵：配置 `[cache.sentinel]`，每次建立连接时向哨兵查询当前主节点，主从切换后新的连接自动连到新的主节点；
+//! - 集群：配置 `[cache.cluster]`，按键的哈希槽路由命令。集群只有 0 号数据库，
+//!   事务管道（`MULTI`）和 Lua 脚本中的多个键需要位于同一个哈希槽。
+//!
+//! 三种方式都可以启用 TLS，单机和哨兵可以选择数据库编号。
+//!
+//! 请求之间共享一个多路复用的连接，断线后自动重连，因连接错误失败的命令重试一次，
+//! 连接指标见 [`Cache::stats`]。
+//!
+//! [`local`] 是 Redis 和数据库前的进程内缓存，由 [`Cache::local`] 获取。
 
-use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+pub mod local;
+pub mod user_info;
+
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::cluster::{ClusterClient, ClusterClientBuilder};
+use redis::cluster_async::ClusterConnection;
+use redis::{
+    Cmd, ConnectionAddr, ConnectionInfo, ErrorKind, IntoConnectionInfo, Pipeline,
+    RedisConnectionInfo, RedisError, RedisFuture, RedisResult, Value,
+};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::cache::local::{LocalCache, LocalCacheConfig, LocalCacheStats};
+use crate::secret;
 
 /// 外部 Redis 缓存配置
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CacheConfig {
-    /// 主机
+    /// 主机，单机模式使用
+    #[serde(default = "default::host")]
     pub host: String,
-    /// 端口
+    /// 端口，单机模式使用
+    #[serde(default = "default::port")]
     pub port: u16,
-    /// 密码
+    /// 用户名，使用 ACL 时配置
+    #[serde(default)]
+    pub username: Option<String>,
+    /// 密码，可以使用 `${ENV}` 引用环境变量，为空时不认证
+    #[serde(default)]
     pub password: String,
+    /// 数据库编号，集群模式只能为 0
+    #[serde(default)]
+    pub db: i64,
+    /// 使用 TLS 连接（包括哨兵和集群节点）
+    #[serde(default)]
+    pub tls: bool,
+    /// TLS 不校验证书和主机名，仅用于测试环境
+    #[serde(default)]
+    pub tls_insecure: bool,
+    /// 哨兵模式
+    #[serde(default)]
+    pub sentinel: Option<SentinelConfig>,
+    /// 集群模式
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    /// 进程内缓存
+    #[serde(default)]
+    pub local: LocalCacheConfig,
+}
+
+/// 哨兵配置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SentinelConfig {
+    /// 主节点名称，即哨兵配置中的 `sentinel monitor <master-name>`
+    pub master_name: String,
+    /// 哨兵地址，`host:port` 形式，端口默认为 26379
+    pub nodes: Vec<String>,
+    /// 哨兵的密码，与数据节点不同时配置，可以使用 `${ENV}` 引用环境变量
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
+/// 集群配置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterConfig {
+    /// 初始节点地址，`host:port` 形式，端口默认为 6379，连接后自动发现其他节点
+    pub nodes: Vec<String>,
+}
+
+mod default {
+    pub fn host() -> String {
+        "localhost".to_string()
+    }
+
+    pub const fn port() -> u16 {
+        6379
+    }
+}
+
+/// 哨兵的默认端口
+const SENTINEL_PORT: u16 = 26379;
+
+/// 建立连接失败后的重试次数和重试间隔的系数（毫秒），间隔为 `系数 * 2^n` 以内的随机值
+///
+/// 建立共享连接时其他请求需要等待，Redis 不可用时尽快返回错误，不使用 [`ConnectionManager`] 默认的 6 次重试
+const CONNECT_RETRIES: usize = 1;
+const CONNECT_RETRY_MILLIS: u64 = 100;
+
 impl CacheConfig {
+    /// 解析 `password` 中的环境变量
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        self.password = secret::interpolate("cache.password", &self.password)?;
+        if let Some(password) = self
+            .sentinel
+            .as_mut()
+            .and_then(|sentinel| sentinel.password.as_mut())
+        {
+            *password = secret::interpolate("cache.sentinel.password", password)?;
+        }
+        Ok(())
+    }
+
+    /// 按配置的模式创建客户端，不建立连接
+    pub fn client(self) -> anyhow::Result<Cache> {
+        let redis = RedisConnectionInfo {
+            db: self.db,
+            username: self.username.filter(|username| !username.is_empty()),
+            password: Some(self.password).filter(|password| !password.is_empty()),
+        };
+        let tls = self.tls.then_some(self.tls_insecure);
+        let mode = match (self.sentinel, self.cluster) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("`cache.sentinel` and `cache.cluster` cannot both be configured")
+            }
+            (None, None) => Mode::Standalone(redis::Client::open(ConnectionInfo {
+                addr: address(self.host, self.port, tls),
+                redis,
+            })?),
+            (Some(sentinel), None) => {
+                anyhow::ensure!(
+                    !sentinel.nodes.is_empty(),
+                    "`cache.sentinel.nodes` is empty"
+                );
+                let sentinels = sentinel
+                    .nodes
+                    .iter()
+                    .map(|node| {
+                        let (host, port) = parse_node(node, SENTINEL_PORT)?;
+                        Ok(ConnectionInfo {
+                            addr: address(host, port, tls),
+                            redis: RedisConnectionInfo {
+                                db: 0,
+                                username: None,
+                                password: sentinel
+                                    .password
+                                    .clone()
+                                    .filter(|password| !password.is_empty()),
+                            },
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                Mode::Sentinel(Sentinel {
+                    master_name: sentinel.master_name,
+                    sentinels,
+                    redis,
+                    tls,
+                })
+            }
+            (None, Some(cluster)) => {
+                anyhow::ensure!(!cluster.nodes.is_empty(), "`cache.cluster.nodes` is empty");
+                anyhow::ensure!(
+                    redis.db == 0,
+                    "Redis Cluster only supports database 0, got `cache.db = {}`",
+                    redis.db
+                );
+                let nodes = cluster
+                    .nodes
+                    .iter()
+                    .map(|node| {
+                        let (host, port) = parse_node(node, default::port())?;
+                        Ok(ConnectionInfo {
+                            addr: address(host, port, tls),
+                            redis: redis.clone(),
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Mode::Cluster(ClusterClientBuilder::new(nodes).build()?)
+            }
+        };
+        Ok(Cache::new(mode, LocalCache::new(&self.local)))
+    }
+
     /// 连接 redis 数据库
-    pub async fn connect(self) -> anyhow::Result<redis::Client> {
-        let opts = ConnectionInfo {
-            addr: ConnectionAddr::Tcp(self.host, self.port),
-            redis: RedisConnectionInfo {
-                db: 0,
-                username: None,
-                password: Some(self.password),
+    pub async fn connect(self) -> anyhow::Result<Cache> {
+        let cache = self.client()?;
+        cache.connection().await.context("connect to redis")?;
+        Ok(cache)
+    }
+}
+
+/// `tls` 为是否跳过证书校验，不使用 TLS 时为 `None`
+fn address(host: String, port: u16, tls: Option<bool>) -> ConnectionAddr {
+    match tls {
+        Some(insecure) => ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure,
+            tls_params: None,
+        },
+        None => ConnectionAddr::Tcp(host, port),
+    }
+}
+
+/// 解析 `host:port`，没有端口时使用 `default_port`
+fn parse_node(node: &str, default_port: u16) -> anyhow::Result<(String, u16)> {
+    let node = node.trim();
+    // IPv6 地址需要写成 `[::1]:6379`
+    let (host, port) = match node.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => (
+            host,
+            port.parse()
+                .with_context(|| format!("Invalid redis node `{node}`"))?,
+        ),
+        _ => (node, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    anyhow::ensure!(!host.is_empty(), "Invalid redis node `{node}`");
+    Ok((host.to_string(), port))
+}
+
+/// Redis 客户端，按配置连接单机、哨兵的主节点或集群，可以低成本地克隆
+///
+/// [`connection`](Self::connection) 返回所有请求共享的多路复用连接，断线后自动重连，
+/// 阻塞命令（如 `XREADGROUP BLOCK`）需要使用 [`dedicated_connection`](Self::dedicated_connection)。
+#[derive(Clone)]
+pub struct Cache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    mode: Mode,
+    /// 共享连接及其版本，哨兵切换主节点后替换，版本加一
+    shared: Mutex<Option<(u64, Connection)>>,
+    metrics: Metrics,
+    local: LocalCache,
+}
+
+enum Mode {
+    Standalone(redis::Client),
+    Sentinel(Sentinel),
+    Cluster(ClusterClient),
+}
+
+struct Sentinel {
+    master_name: String,
+    sentinels: Vec<ConnectionInfo>,
+    redis: RedisConnectionInfo,
+    tls: Option<bool>,
+}
+
+impl Sentinel {
+    /// 依次询问哨兵，返回第一个得到的主节点
+    async fn master(&self) -> RedisResult<redis::Client> {
+        let mut last_error = None;
+        for sentinel in &self.sentinels {
+            match self.query(sentinel).await {
+                Ok(master) => return Ok(master),
+                Err(e) => {
+                    tracing::warn!(sentinel = %sentinel.addr, "Failed to query sentinel: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| (redis::ErrorKind::ClientError, "No sentinel configured").into()))
+    }
+
+    async fn query(&self, sentinel: &ConnectionInfo) -> RedisResult<redis::Client> {
+        let mut connection = redis::Client::open(sentinel.clone())?
+            .get_async_connection()
+            .await?;
+        let master: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("GET-MASTER-ADDR-BY-NAME")
+            .arg(&self.master_name)
+            .query_async(&mut connection)
+            .await?;
+        let Some((host, port)) = master else {
+            return Err((
+                redis::ErrorKind::ResponseError,
+                "Unknown master name",
+                self.master_name.clone(),
+            )
+                .into());
+        };
+        redis::Client::open(ConnectionInfo {
+            addr: address(host, port, self.tls),
+            redis: self.redis.clone(),
+        })
+    }
+}
+
+/// 连接指标的计数器
+#[derive(Default)]
+struct Metrics {
+    connects: AtomicU64,
+    connect_errors: AtomicU64,
+    connection_errors: AtomicU64,
+    retries: AtomicU64,
+    failovers: AtomicU64,
+    dedicated: AtomicU64,
+}
+
+/// 连接指标，计数均为启动以来的累计值
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    /// 部署方式：`standalone`、`sentinel` 或 `cluster`
+    pub mode: String,
+    /// 共享连接的版本，哨兵切换主节点后加一，尚未连接时为空
+    pub generation: Option<u64>,
+    /// 建立连接的次数，不包括断线后的自动重连
+    pub connects: u64,
+    /// 建立连接失败的次数
+    pub connect_errors: u64,
+    /// 命令因连接断开、拒绝等错误失败的次数
+    pub connection_errors: u64,
+    /// 连接错误后重试的命令数
+    pub retries: u64,
+    /// 重新向哨兵查询主节点的次数
+    pub failovers: u64,
+    /// 当前的独占连接数
+    pub dedicated: u64,
+    /// 进程内缓存
+    pub local: LocalCacheStats,
+}
+
+impl Cache {
+    /// 使用 `redis://` 形式的连接字符串创建单机客户端
+    pub fn open<T: IntoConnectionInfo>(params: T) -> RedisResult<Self> {
+        Ok(redis::Client::open(params)?.into())
+    }
+
+    fn new(mode: Mode, local: LocalCache) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                mode,
+                shared: Mutex::new(None),
+                metrics: Metrics::default(),
+                local,
+            }),
+        }
+    }
+
+    /// 进程内缓存
+    pub fn local(&self) -> &LocalCache {
+        &self.inner.local
+    }
+
+    /// 共享的连接，第一次使用时建立
+    ///
+    /// 连接断开后自动重连，因连接错误失败的命令重试一次；连接断开时命令可能已经执行，
+    /// 非幂等的命令（如 `INCR`）重试后可能执行两次。
+    pub async fn connection(&self) -> RedisResult<CacheConnection> {
+        let (generation, connection) = self.shared(None).await?;
+        Ok(CacheConnection {
+            connection,
+            generation: Some(generation),
+            cache: self.clone(),
+        })
+    }
+
+    /// 建立独占的连接，用于会阻塞连接的命令，同样会自动重连
+    pub async fn dedicated_connection(&self) -> RedisResult<CacheConnection> {
+        let connection = self.establish().await?;
+        self.inner.metrics.dedicated.fetch_add(1, Ordering::Relaxed);
+        Ok(CacheConnection {
+            connection,
+            generation: None,
+            cache: self.clone(),
+        })
+    }
+
+    /// 连接指标
+    pub async fn stats(&self) -> CacheStats {
+        let metrics = &self.inner.metrics;
+        CacheStats {
+            mode: match self.inner.mode {
+                Mode::Standalone(_) => "standalone",
+                Mode::Sentinel(_) => "sentinel",
+                Mode::Cluster(_) => "cluster",
+            }
+            .to_string(),
+            generation: self
+                .inner
+                .shared
+                .lock()
+                .await
+                .as_ref()
+                .map(|(generation, _)| *generation),
+            connects: metrics.connects.load(Ordering::Relaxed),
+            connect_errors: metrics.connect_errors.load(Ordering::Relaxed),
+            connection_errors: metrics.connection_errors.load(Ordering::Relaxed),
+            retries: metrics.retries.load(Ordering::Relaxed),
+            failovers: metrics.failovers.load(Ordering::Relaxed),
+            dedicated: metrics.dedicated.load(Ordering::Relaxed),
+            local: self.inner.local.stats(),
+        }
+    }
+
+    /// 获取共享连接，没有连接或版本为 `stale` 时建立新的连接
+    async fn shared(&self, stale: Option<u64>) -> RedisResult<(u64, Connection)> {
+        let mut shared = self.inner.shared.lock().await;
+        match shared.as_ref() {
+            Some((generation, connection)) if stale != Some(*generation) => {
+                return Ok((*generation, connection.clone()));
+            }
+            _ => {}
+        }
+        let connection = self.establish().await?;
+        let generation = shared.as_ref().map_or(0, |(generation, _)| generation + 1);
+        *shared = Some((generation, connection.clone()));
+        Ok((generation, connection))
+    }
+
+    async fn establish(&self) -> RedisResult<Connection> {
+        let connection = match &self.inner.mode {
+            Mode::Standalone(client) => managed(client.clone()).await,
+            Mode::Sentinel(sentinel) => match sentinel.master().await {
+                Ok(master) => managed(master).await,
+                Err(e) => Err(e),
             },
+            Mode::Cluster(client) => client.get_async_connection().await.map(Connection::Cluster),
         };
-        let client = redis::Client::open(opts)?;
-        client.get_async_connection().await?;
-        Ok(client)
+        let counter = match connection {
+            Ok(_) => &self.inner.metrics.connects,
+            Err(_) => &self.inner.metrics.connect_errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        connection
+    }
+}
+
+async fn managed(client: redis::Client) -> RedisResult<Connection> {
+    ConnectionManager::new_with_backoff(client, 2, CONNECT_RETRY_MILLIS, CONNECT_RETRIES)
+        .await
+        .map(Connection::Managed)
+}
+
+impl From<redis::Client> for Cache {
+    fn from(client: redis::Client) -> Self {
+        Self::new(Mode::Standalone(client), LocalCache::default())
+    }
+}
+
+impl Debug for Cache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.inner.mode {
+            Mode::Standalone(client) => f
+                .debug_tuple("Standalone")
+                .field(&client.get_connection_info().addr)
+                .finish(),
+            Mode::Sentinel(sentinel) => f
+                .debug_struct("Sentinel")
+                .field("master_name", &sentinel.master_name)
+                .field(
+                    "sentinels",
+                    &sentinel
+                        .sentinels
+                        .iter()
+                        .map(|sentinel| &sentinel.addr)
+                        .collect::<Vec<_>>(),
+                )
+                .finish(),
+            Mode::Cluster(_) => f.write_str("Cluster"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Connection {
+    /// 单机或哨兵的主节点，断线后自动重连
+    Managed(ConnectionManager),
+    /// 集群，自行处理节点的重连和重定向
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Connection::Managed(connection) => connection.req_packed_command(cmd),
+            Connection::Cluster(connection) => connection.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Connection::Managed(connection) => connection.req_packed_commands(cmd, offset, count),
+            Connection::Cluster(connection) => connection.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Connection::Managed(connection) => connection.get_db(),
+            Connection::Cluster(connection) => connection.get_db(),
+        }
+    }
+}
+
+/// Redis 连接，可以像 [`redis::aio::Connection`] 一样执行命令
+pub struct CacheConnection {
+    connection: Connection,
+    /// 共享连接的版本，独占连接为空
+    generation: Option<u64>,
+    cache: Cache,
+}
+
+impl CacheConnection {
+    /// 命令失败后是否可以重试
+    ///
+    /// 单机的连接由 [`ConnectionManager`] 自动重连，哨兵模式在连接错误或主节点变为只读的从节点时
+    /// 重新查询主节点，集群的连接自行重试。
+    async fn recover(&mut self, error: &RedisError) -> bool {
+        let metrics = &self.cache.inner.metrics;
+        let disconnected = is_connection_error(error);
+        if disconnected {
+            metrics.connection_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let retry = match self.cache.inner.mode {
+            Mode::Standalone(_) => disconnected,
+            Mode::Sentinel(_) if disconnected || error.kind() == ErrorKind::ReadOnly => {
+                metrics.failovers.fetch_add(1, Ordering::Relaxed);
+                let reopened = match self.generation {
+                    Some(generation) => self
+                        .cache
+                        .shared(Some(generation))
+                        .await
+                        .map(|(generation, connection)| (Some(generation), connection)),
+                    None => self
+                        .cache
+                        .establish()
+                        .await
+                        .map(|connection| (None, connection)),
+                };
+                match reopened {
+                    Ok((generation, connection)) => {
+                        self.generation = generation;
+                        self.connection = connection;
+                        true
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to reconnect to redis master: {e}");
+                        false
+                    }
+                }
+            }
+            Mode::Sentinel(_) | Mode::Cluster(_) => false,
+        };
+        if retry {
+            metrics.retries.fetch_add(1, Ordering::Relaxed);
+        }
+        retry
+    }
+}
+
+fn is_connection_error(error: &RedisError) -> bool {
+    error.is_io_error() || error.is_connection_dropped() || error.is_connection_refusal()
+}
+
+impl ConnectionLike for CacheConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            match self.connection.req_packed_command(cmd).await {
+                Err(e) if self.recover(&e).await => self.connection.req_packed_command(cmd).await,
+                result => result,
+            }
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            match self
+                .connection
+                .req_packed_commands(cmd, offset, count)
+                .await
+            {
+                Err(e) if self.recover(&e).await => {
+                    self.connection
+                        .req_packed_commands(cmd, offset, count)
+                        .await
+                }
+                result => result,
+            }
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.connection.get_db()
+    }
+}
+
+impl Drop for CacheConnection {
+    fn drop(&mut self) {
+        if self.generation.is_none() {
+            self.cache
+                .inner
+                .metrics
+                .dedicated
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::ConnectionAddr;
+
+    use crate::cache::{address, parse_node, Cache, CacheConfig};
+
+    fn config(toml: &str) -> anyhow::Result<CacheConfig> {
+        Ok(config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()?
+            .try_deserialize()?)
+    }
+
+    #[test]
+    fn node() -> anyhow::Result<()> {
+        assert_eq!(
+            parse_node("redis-1:7000", 6379)?,
+            ("redis-1".to_string(), 7000)
+        );
+        assert_eq!(
+            parse_node(" redis-1 ", 26379)?,
+            ("redis-1".to_string(), 26379)
+        );
+        assert_eq!(parse_node("[::1]:7001", 6379)?, ("::1".to_string(), 7001));
+        assert_eq!(parse_node("::1", 6379)?, ("::1".to_string(), 6379));
+        assert!(parse_node("redis-1:port", 6379).is_err());
+        assert!(parse_node(":6379", 6379).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn modes() -> anyhow::Result<()> {
+        let standalone = config(
+            r#"
+            host = "redis.internal"
+            password = "123456"
+            db = 2
+            tls = true
+            "#,
+        )?;
+        assert_eq!(standalone.port, 6379);
+        let cache = standalone.client()?;
+        assert!(format!("{cache:?}").starts_with("Standalone(TcpTls"));
+
+        let sentinel = config(
+            r#"
+            [sentinel]
+            master_name = "mymaster"
+            nodes = ["sentinel-1", "sentinel-2:26380"]
+            "#,
+        )?;
+        assert!(format!("{:?}", sentinel.client()?).starts_with("Sentinel"));
+
+        let cluster = config(
+            r#"
+            [cluster]
+            nodes = ["redis-1:7000", "redis-2:7000"]
+            "#,
+        )?;
+        assert_eq!(format!("{:?}", cluster.client()?), "Cluster");
+
+        let both = config(
+            r#"
+            [sentinel]
+            master_name = "mymaster"
+            nodes = ["sentinel-1"]
+            [cluster]
+            nodes = ["redis-1:7000"]
+            "#,
+        )?;
+        assert!(both.client().is_err());
+
+        let cluster_db = config(
+            r#"
+            db = 1
+            [cluster]
+            nodes = ["redis-1:7000"]
+            "#,
+        )?;
+        assert!(cluster_db.client().is_err());
+        assert!(config("[cluster]\nnodes = []")?.client().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stats() -> anyhow::Result<()> {
+        let cache = Cache::open("redis://127.0.0.1/")?;
+        let stats = cache.stats().await;
+        assert_eq!(stats.mode, "standalone");
+        assert_eq!(stats.generation, None);
+        assert_eq!(stats.connects + stats.connect_errors + stats.dedicated, 0);
+
+        let cluster = config("[cluster]\nnodes = [\"redis-1:7000\"]")?.client()?;
+        assert_eq!(cluster.stats().await.mode, "cluster");
+        Ok(())
+    }
+
+    #[test]
+    fn tls() {
+        assert_eq!(
+            address("localhost".to_string(), 6379, None),
+            ConnectionAddr::Tcp("localhost".to_string(), 6379)
+        );
+        assert!(matches!(
+            address("localhost".to_string(), 6379, Some(true)),
+            ConnectionAddr::TcpTls { insecure: true, .. }
+        ));
     }
 }
@@ -0,0 +1,445 @@
+//! # 进程内缓存
+//!
+//! 用户资料、房间、[用户信息](super::user_info)等读多写少的数据在数据库前加一层进程内缓存，按条数淘汰，写入 `ttl_secs` 秒后过期。
+//!
+//! [`LocalCache::repos`] 包装数据访问对象，通过包装后的对象修改数据时自动失效；
+//! 在事务中直接修改的，需要在提交后调用 [`LocalCache::invalidate_user`]、[`LocalCache::invalidate_room`]。
+//! 每个实例各自缓存，其他实例上的修改最多 `ttl_secs` 秒后可见。
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sea_orm::DbErr;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::cache::user_info::UserInfo;
+use crate::ip::IpDetail;
+use crate::storage::model::{message, room, user};
+use crate::storage::repo::{DynRoomRepo, DynUserRepo, Repos, RoomRepo, UserRepo};
+
+/// 进程内缓存配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalCacheConfig {
+    /// 每类数据缓存的最大条数，为 0 时不缓存
+    #[serde(default = "default::capacity")]
+    pub capacity: u64,
+    /// 写入后的过期时间（秒）
+    #[serde(default = "default::ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for LocalCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default::capacity(),
+            ttl_secs: default::ttl_secs(),
+        }
+    }
+}
+
+mod default {
+    pub const fn capacity() -> u64 {
+        10_000
+    }
+
+    pub const fn ttl_secs() -> u64 {
+        30
+    }
+}
+
+/// 进程内缓存，可以低成本地克隆
+#[derive(Clone)]
+pub struct LocalCache {
+    tiers: Option<Arc<Tiers>>,
+}
+
+struct Tiers {
+    users: Tier<i64, user::Model>,
+    rooms: Tier<i64, room::Model>,
+    user_infos: Tier<i64, UserInfo>,
+}
+
+/// 一类数据的缓存及命中计数
+pub(super) struct Tier<K, V> {
+    entries: moka::future::Cache<K, V>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> Tier<K, V>
+where
+    K: Hash + Eq + Copy + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn new(config: &LocalCacheConfig) -> Self {
+        Self {
+            entries: moka::future::Cache::builder()
+                .max_capacity(config.capacity)
+                .time_to_live(Duration::from_secs(config.ttl_secs))
+                .build(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// 读取缓存，未命中时加载，不缓存不存在的数据
+    async fn get_or_load<F>(&self, key: K, load: F) -> Result<Option<V>, DbErr>
+    where
+        F: std::future::Future<Output = Result<Option<V>, DbErr>>,
+    {
+        if let Some(value) = self.entries.get(&key).await {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(value));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = load.await?;
+        if let Some(value) = &value {
+            self.entries.insert(key, value.clone()).await;
+        }
+        Ok(value)
+    }
+
+    /// 批量读取缓存，只加载未命中的部分
+    pub(super) async fn get_many_or_load<F, Fut>(
+        &self,
+        keys: &[K],
+        key_of: impl Fn(&V) -> K,
+        load: F,
+    ) -> Result<Vec<V>, DbErr>
+    where
+        F: FnOnce(Vec<K>) -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<V>, DbErr>>,
+    {
+        let mut values = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for key in keys.iter().collect::<HashSet<_>>() {
+            match self.entries.get(key).await {
+                Some(value) => values.push(value),
+                None => missing.push(*key),
+            }
+        }
+        self.hits.fetch_add(values.len() as u64, Ordering::Relaxed);
+        if missing.is_empty() {
+            return Ok(values);
+        }
+        self.misses
+            .fetch_add(missing.len() as u64, Ordering::Relaxed);
+        for value in load(missing).await? {
+            self.entries.insert(key_of(&value), value.clone()).await;
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    fn stats(&self) -> TierStats {
+        TierStats {
+            entries: self.entries.entry_count(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 进程内缓存指标
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalCacheStats {
+    /// 用户资料
+    pub users: TierStats,
+    /// 房间
+    pub rooms: TierStats,
+    /// 用户信息
+    pub user_infos: TierStats,
+}
+
+/// 一类数据的缓存指标，命中数和未命中数为启动以来的累计值
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TierStats {
+    /// 当前缓存的条数（近似值）
+    pub entries: u64,
+    /// 命中数
+    pub hits: u64,
+    /// 未命中数
+    pub misses: u64,
+}
+
+impl LocalCache {
+    /// 按配置创建，`capacity` 为 0 时不缓存
+    pub fn new(config: &LocalCacheConfig) -> Self {
+        Self {
+            tiers: (config.capacity > 0).then(|| {
+                Arc::new(Tiers {
+                    users: Tier::new(config),
+                    rooms: Tier::new(config),
+                    user_infos: Tier::new(config),
+                })
+            }),
+        }
+    }
+
+    /// 用户和房间的数据访问对象读取时使用缓存，修改时失效
+    pub fn repos(&self, repos: Repos) -> Repos {
+        if self.tiers.is_none() {
+            return repos;
+        }
+        Repos {
+            users: Arc::new(CachedUserRepo {
+                inner: repos.users,
+                cache: self.clone(),
+            }),
+            rooms: Arc::new(CachedRoomRepo {
+                inner: repos.rooms,
+                cache: self.clone(),
+            }),
+            ..repos
+        }
+    }
+
+    /// 用户资料被修改，如改名、佩戴徽章、拉黑
+    pub async fn invalidate_user(&self, uid: i64) {
+        if let Some(tiers) = &self.tiers {
+            tiers.users.entries.invalidate(&uid).await;
+            tiers.user_infos.entries.invalidate(&uid).await;
+        }
+    }
+
+    /// 房间被修改，如收到新消息
+    pub async fn invalidate_room(&self, room_id: i64) {
+        if let Some(tiers) = &self.tiers {
+            tiers.rooms.entries.invalidate(&room_id).await;
+        }
+    }
+
+    pub(super) fn user_infos(&self) -> Option<&Tier<i64, UserInfo>> {
+        self.tiers.as_ref().map(|tiers| &tiers.user_infos)
+    }
+
+    /// 缓存指标
+    pub fn stats(&self) -> LocalCacheStats {
+        match &self.tiers {
+            Some(tiers) => LocalCacheStats {
+                users: tiers.users.stats(),
+                rooms: tiers.rooms.stats(),
+                user_infos: tiers.user_infos.stats(),
+            },
+            None => LocalCacheStats::default(),
+        }
+    }
+}
+
+impl Default for LocalCache {
+    fn default() -> Self {
+        Self::new(&LocalCacheConfig::default())
+    }
+}
+
+struct CachedUserRepo {
+    inner: DynUserRepo,
+    cache: LocalCache,
+}
+
+impl CachedUserRepo {
+    fn tier(&self) -> &Tier<i64, user::Model> {
+        &self
+            .cache
+            .tiers
+            .as_ref()
+            .expect("local cache enabled")
+            .users
+    }
+}
+
+#[async_trait]
+impl UserRepo for CachedUserRepo {
+    async fn find_by_id(&self, uid: i64) -> Result<Option<user::Model>, DbErr> {
+        self.tier()
+            .get_or_load(uid, self.inner.find_by_id(uid))
+            .await
+    }
+
+    async fn find_by_ids(&self, uids: &[i64]) -> Result<Vec<user::Model>, DbErr> {
+        self.tier()
+            .get_many_or_load(
+                uids,
+                |user| user.id as i64,
+                |missing| async move { self.inner.find_by_ids(&missing).await },
+            )
+            .await
+    }
+
+    async fn find_by_open_id(&self, open_id: &str) -> Result<Option<user::Model>, DbErr> {
+        self.inner.find_by_open_id(open_id).await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<user::Model>, DbErr> {
+        self.inner.find_by_name(name).await
+    }
+
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<user::Model>, DbErr> {
+        self.inner.page(offset, limit).await
+    }
+
+    async fn create(&self, open_id: &str) -> Result<user::Model, DbErr> {
+        self.inner.create(open_id).await
+    }
+
+    async fn update_name(&self, uid: i64, name: &str) -> Result<(), DbErr> {
+        self.inner.update_name(uid, name).await?;
+        self.cache.invalidate_user(uid).await;
+        Ok(())
+    }
+
+    async fn update_avatar(&self, uid: i64, avatar: &str) -> Result<(), DbErr> {
+        self.inner.update_avatar(uid, avatar).await?;
+        self.cache.invalidate_user(uid).await;
+        Ok(())
+    }
+
+    async fn update_ip(&self, uid: i64, detail: &IpDetail) -> Result<bool, DbErr> {
+        let updated = self.inner.update_ip(uid, detail).await?;
+        if updated {
+            self.cache.invalidate_user(uid).await;
+        }
+        Ok(updated)
+    }
+
+    /// 活跃的用户每次都会更新，为了保留缓存不失效，过期前可能读到旧的活跃时间
+    async fn refresh_active_time(&self, uids: &[i64]) -> Result<(), DbErr> {
+        self.inner.refresh_active_time(uids).await
+    }
+
+    async fn soft_delete(&self, uid: i64) -> Result<bool, DbErr> {
+        let deleted = self.inner.soft_delete(uid).await?;
+        self.cache.invalidate_user(uid).await;
+        Ok(deleted)
+    }
+
+    async fn restore(&self, uid: i64) -> Result<bool, DbErr> {
+        let restored = self.inner.restore(uid).await?;
+        self.cache.invalidate_user(uid).await;
+        Ok(restored)
+    }
+}
+
+struct CachedRoomRepo {
+    inner: DynRoomRepo,
+    cache: LocalCache,
+}
+
+impl CachedRoomRepo {
+    fn tier(&self) -> &Tier<i64, room::Model> {
+        &self
+            .cache
+            .tiers
+            .as_ref()
+            .expect("local cache enabled")
+            .rooms
+    }
+}
+
+#[async_trait]
+impl RoomRepo for CachedRoomRepo {
+    async fn find_by_id(&self, room_id: i64) -> Result<Option<room::Model>, DbErr> {
+        self.tier()
+            .get_or_load(room_id, self.inner.find_by_id(room_id))
+            .await
+    }
+
+    async fn find_by_ids(&self, room_ids: &[i64]) -> Result<Vec<room::Model>, DbErr> {
+        self.tier()
+            .get_many_or_load(
+                room_ids,
+                |room| room.id as i64,
+                |missing| async move { self.inner.find_by_ids(&missing).await },
+            )
+            .await
+    }
+
+    async fn page(
+        &self,
+        exclude: &[i64],
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<room::Model>, DbErr> {
+        self.inner.page(exclude, offset, limit).await
+    }
+
+    async fn refresh_last_message(&self, message: &message::Model) -> Result<(), DbErr> {
+        self.inner.refresh_last_message(message).await?;
+        self.cache.invalidate_room(message.room_id).await;
+        Ok(())
+    }
+
+    async fn is_group_member(&self, room_id: i64, uid: i64) -> Result<bool, DbErr> {
+        self.inner.is_group_member(room_id, uid).await
+    }
+
+    async fn member_room_ids(&self, uid: i64) -> Result<Vec<i64>, DbErr> {
+        self.inner.member_room_ids(uid).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::cache::local::{LocalCache, LocalCacheConfig};
+    use crate::storage::repo::{Repos, UserRepo};
+    use crate::testing::MemoryRepo;
+
+    fn repos(memory: &Arc<MemoryRepo>) -> Repos {
+        Repos {
+            users: memory.clone(),
+            messages: memory.clone(),
+            rooms: memory.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn cached_users() -> anyhow::Result<()> {
+        let memory = Arc::new(MemoryRepo::default());
+        let uid = memory.add_user("open_id", Some("name"));
+        let cache = LocalCache::default();
+        let users = cache.repos(repos(&memory)).users;
+
+        assert!(users.find_by_id(uid).await?.is_some());
+        assert!(users.find_by_id(uid + 1).await?.is_none());
+        // 绕过缓存修改，过期前读到旧值
+        UserRepo::update_name(memory.as_ref(), uid, "other").await?;
+        let user = users.find_by_id(uid).await?.expect("user");
+        assert_eq!(user.name.as_deref(), Some("name"));
+
+        cache.invalidate_user(uid).await;
+        let user = users.find_by_id(uid).await?.expect("user");
+        assert_eq!(user.name.as_deref(), Some("other"));
+
+        users.update_name(uid, "renamed").await?;
+        assert_eq!(users.find_by_ids(&[uid, uid]).await?.len(), 1);
+        let user = users.find_by_id(uid).await?.expect("user");
+        assert_eq!(user.name.as_deref(), Some("renamed"));
+
+        let stats = cache.stats().users;
+        assert_eq!((stats.hits, stats.misses), (2, 4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disabled() -> anyhow::Result<()> {
+        let memory = Arc::new(MemoryRepo::default());
+        let uid = memory.add_user("open_id", Some("name"));
+        let cache = LocalCache::new(&LocalCacheConfig {
+            capacity: 0,
+            ..Default::default()
+        });
+        let users = cache.repos(repos(&memory)).users;
+        assert!(users.find_by_id(uid).await?.is_some());
+        assert_eq!(cache.stats().users.misses, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! # 用户信息缓存
+//!
+//! 消息列表、成员列表需要批量获取发送者的昵称、头像和徽章。[`UserInfoCache::get_many`] 依次读取
+//! 进程内缓存、Redis（`MGET` [`USER_INFO_KEY`]`:{uid}`）和数据库，未命中的写回上一层。
+//!
+//! 每个用户有一个版本号，保存在 Redis 哈希表 [`USER_VERSION_KEY`] 中，修改资料后调用
+//! [`UserInfoCache::bump`] 更新版本号并删除缓存。版本号为最后修改的毫秒时间戳，保证递增；
+//! Redis 中没有记录时使用用户的修改时间。客户端保存用户信息及其版本号，版本号不同时才需要重新获取。
+
+use std::collections::HashMap;
+
+use redis::AsyncCommands;
+use sea_orm::DbErr;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::cache::Cache;
+use crate::ip::IpInfo;
+use crate::storage::model::user;
+use crate::storage::repo::UserRepo;
+
+/// 用户信息，后接 uid
+pub const USER_INFO_KEY: &str = "mallchat:user:info";
+
+/// 用户信息版本号的哈希表，字段为 uid
+pub const USER_VERSION_KEY: &str = "mallchat:user:version";
+
+/// 用户信息在 Redis 中的过期时间（秒）
+const USER_INFO_EXPIRE_SECONDS: usize = 24 * 60 * 60;
+
+/// 版本号更新为当前时间和原版本号加一中较大的一个
+const BUMP_SCRIPT: &str = r#"
+local old = tonumber(redis.call('HGET', KEYS[1], ARGV[1]) or '0')
+local version = math.max(tonumber(ARGV[2]), old + 1)
+redis.call('HSET', KEYS[1], ARGV[1], version)
+return version
+"#;
+
+/// 用户信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    /// 用户 ID
+    pub uid: i64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+    /// 佩戴的徽章 ID
+    pub badge: Option<i64>,
+    /// 最近登录的 IP 归属地
+    pub region: Option<String>,
+    /// 版本号，资料修改后变化
+    pub version: i64,
+}
+
+impl UserInfo {
+    fn new(user: user::Model, version: Option<i64>) -> Self {
+        Self {
+            uid: user.id as i64,
+            version: version.unwrap_or_else(|| millis(user.update_time.assume_utc())),
+            region: IpInfo::from_json(user.ip_info.as_ref()).region(),
+            name: user.name,
+            avatar: user.avatar,
+            badge: user.item_id,
+        }
+    }
+}
+
+/// 不经过缓存直接从数据库读取时使用，版本号为用户的修改时间
+impl From<user::Model> for UserInfo {
+    fn from(user: user::Model) -> Self {
+        Self::new(user, None)
+    }
+}
+
+fn millis(time: OffsetDateTime) -> i64 {
+    (time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn key(uid: i64) -> String {
+    format!("{USER_INFO_KEY}:{uid}")
+}
+
+/// 用户信息缓存
+#[derive(Clone, Copy)]
+pub struct UserInfoCache<'a> {
+    cache: &'a Cache,
+    users: &'a dyn UserRepo,
+}
+
+impl<'a> UserInfoCache<'a> {
+    /// 使用 Redis 客户端和用户数据访问对象构造
+    pub fn new(cache: &'a Cache, users: &'a dyn UserRepo) -> Self {
+        Self { cache, users }
+    }
+
+    /// 批量获取用户信息，不存在的用户忽略，不保证返回顺序
+    ///
+    /// Redis 不可用时直接读取数据库
+    pub async fn get_many(&self, uids: &[i64]) -> Result<Vec<UserInfo>, DbErr> {
+        match self.cache.local().user_infos() {
+            Some(tier) => {
+                tier.get_many_or_load(uids, |info| info.uid, |missing| self.load(missing))
+                    .await
+            }
+            None => self.load(uids.to_vec()).await,
+        }
+    }
+
+    /// 获取一个用户的信息
+    pub async fn get(&self, uid: i64) -> Result<Option<UserInfo>, DbErr> {
+        Ok(self.get_many(&[uid]).await?.pop())
+    }
+
+    /// 用户资料已修改，更新版本号并删除缓存，返回新的版本号
+    pub async fn bump(&self, uid: i64) -> redis::RedisResult<i64> {
+        let bumped = async {
+            let mut connection = self.cache.connection().await?;
+            let version: i64 = redis::Script::new(BUMP_SCRIPT)
+                .key(USER_VERSION_KEY)
+                .arg(uid)
+                .arg(millis(OffsetDateTime::now_utc()))
+                .invoke_async(&mut connection)
+                .await?;
+            connection.del::<_, ()>(key(uid)).await?;
+            Ok(version)
+        }
+        .await;
+        // Redis 更新后再失效本地缓存，避免重新读到 Redis 中的旧数据
+        self.cache.local().invalidate_user(uid).await;
+        bumped
+    }
+
+    /// 从 Redis 读取，未命中的从数据库读取并写回 Redis
+    async fn load(&self, uids: Vec<i64>) -> Result<Vec<UserInfo>, DbErr> {
+        let (mut infos, missing) = match self.load_cached(&uids).await {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                tracing::warn!(%error, "Failed to load user info from redis.");
+                (Vec::new(), uids)
+            }
+        };
+        if missing.is_empty() {
+            return Ok(infos);
+        }
+        let users = self.users.find_by_ids(&missing).await?;
+        let versions = match self.versions(&missing).await {
+            Ok(versions) => versions,
+            Err(error) => {
+                tracing::warn!(%error, "Failed to load user versions from redis.");
+                HashMap::new()
+            }
+        };
+        let loaded: Vec<_> = users
+            .into_iter()
+            .map(|user| {
+                let version = versions.get(&(user.id as i64)).copied();
+                UserInfo::new(user, version)
+            })
+            .collect();
+        if let Err(error) = self.store(&loaded).await {
+            tracing::warn!(%error, "Failed to store user info to redis.");
+        }
+        infos.extend(loaded);
+        Ok(infos)
+    }
+
+    /// 返回 Redis 中的用户信息和未命中的 uid
+    async fn load_cached(&self, uids: &[i64]) -> anyhow::Result<(Vec<UserInfo>, Vec<i64>)> {
+        let mut connection = self.cache.connection().await?;
+        let cached: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(uids.iter().map(|uid| key(*uid)).collect::<Vec<_>>())
+            .query_async(&mut connection)
+            .await?;
+        let mut infos = Vec::with_capacity(uids.len());
+        let mut missing = Vec::new();
+        for (uid, cached) in uids.iter().zip(cached) {
+            match cached.and_then(|json| serde_json::from_str(&json).ok()) {
+                Some(info) => infos.push(info),
+                None => missing.push(*uid),
+            }
+        }
+        Ok((infos, missing))
+    }
+
+    async fn versions(&self, uids: &[i64]) -> redis::RedisResult<HashMap<i64, i64>> {
+        let mut connection = self.cache.connection().await?;
+        let versions: Vec<Option<i64>> = redis::cmd("HMGET")
+            .arg(USER_VERSION_KEY)
+            .arg(uids)
+            .query_async(&mut connection)
+            .await?;
+        Ok(uids
+            .iter()
+            .zip(versions)
+            .filter_map(|(uid, version)| Some((*uid, version?)))
+            .collect())
+    }
+
+    async fn store(&self, infos: &[UserInfo]) -> anyhow::Result<()> {
+        if infos.is_empty() {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        for info in infos {
+            pipe.set_ex(
+                key(info.uid),
+                serde_json::to_string(info)?,
+                USER_INFO_EXPIRE_SECONDS,
+            )
+            .ignore();
+        }
+        let mut connection = self.cache.connection().await?;
+        pipe.query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::cache::user_info::UserInfoCache;
+    use crate::cache::Cache;
+    use crate::testing::MemoryRepo;
+
+    #[tokio::test]
+    async fn redis_unavailable() -> anyhow::Result<()> {
+        let users = Arc::new(MemoryRepo::default());
+        let uid = users.add_user("open_id", Some("name"));
+        let cache = Cache::open("redis://127.0.0.1:1/")?;
+        let user_info = UserInfoCache::new(&cache, users.as_ref());
+
+        let infos = user_info.get_many(&[uid, uid, uid + 1]).await?;
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].name.as_deref(), Some("name"));
+        assert!(infos[0].version > 0);
+
+        assert_eq!(user_info.get(uid).await?, Some(infos[0].clone()));
+        assert_eq!(cache.local().stats().user_infos.hits, 1);
+        assert!(user_info.bump(uid).await.is_err());
+        user_info.get(uid).await?;
+        assert_eq!(cache.local().stats().user_infos.misses, 3);
+        Ok(())
+    }
+}
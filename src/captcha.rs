@@ -0,0 +1,408 @@
+//! # 图形验证码
+//!
+//! 邮箱密码登录连续失败、频繁改名时要求客户端先通过 `/capi/captcha` 获取验证码，
+//! 在请求中携带验证码的 nonce 和答案。验证码答案保存在 Redis 中，验证一次后即失效。
+//!
+//! 验证码的生成只依赖 [`CaptchaGenerator`] trait，默认提供服务端生成 SVG 图片的算术验证码
+//! [`ArithmeticCaptcha`]。
+
+use std::fmt::{Debug, Write};
+use std::sync::Arc;
+
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+
+/// 验证码答案的键前缀，后接 nonce
+const ANSWER_KEY: &str = "mallchat:captcha:answer";
+/// 改名次数的键前缀，后接 uid
+const RENAME_KEY: &str = "mallchat:captcha:rename";
+
+/// 读取并删除验证码答案，保证每个验证码只能使用一次
+const TAKE_SCRIPT: &str = r#"
+local answer = redis.call('GET', KEYS[1])
+if answer then
+    redis.call('DEL', KEYS[1])
+end
+return answer
+"#;
+
+/// 计数，第一次计数时设置过期时间；在同一个脚本中执行，不会留下没有过期时间的计数
+const INCR_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+/// 共享的验证码生成器
+pub type DynCaptchaGenerator = Arc<dyn CaptchaGenerator>;
+
+/// 验证码生成器
+pub trait CaptchaGenerator: Debug + Send + Sync {
+    /// 生成验证码
+    fn generate(&self) -> Challenge;
+}
+
+/// 验证码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    /// 答案
+    pub answer: String,
+    /// SVG 图片
+    pub image: String,
+}
+
+/// 验证码配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptchaConfig {
+    /// 是否启用，需要 Redis
+    #[serde(default)]
+    pub enabled: bool,
+    /// 验证码的有效期（秒）
+    #[serde(default = "default::ttl_secs")]
+    pub ttl_secs: u64,
+    /// 邮箱密码登录连续失败多少次后要求验证码，0 表示总是要求
+    #[serde(default = "default::login_after_failures")]
+    pub login_after_failures: u64,
+    /// 统计时间内改名多少次后要求验证码，0 表示总是要求
+    #[serde(default = "default::rename_after_attempts")]
+    pub rename_after_attempts: u64,
+    /// 改名次数的统计时间（秒）
+    #[serde(default = "default::rename_window_secs")]
+    pub rename_window_secs: u64,
+}
+
+mod default {
+    pub fn ttl_secs() -> u64 {
+        300
+    }
+
+    pub fn login_after_failures() -> u64 {
+        3
+    }
+
+    pub fn rename_after_attempts() -> u64 {
+        3
+    }
+
+    pub fn rename_window_secs() -> u64 {
+        3600
+    }
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default::ttl_secs(),
+            login_after_failures: default::login_after_failures(),
+            rename_after_attempts: default::rename_after_attempts(),
+            rename_window_secs: default::rename_window_secs(),
+        }
+    }
+}
+
+/// 验证码的签发和验证
+#[derive(Debug, Clone)]
+pub struct Captcha {
+    config: CaptchaConfig,
+    generator: DynCaptchaGenerator,
+    cache: Cache,
+}
+
+impl Captcha {
+    /// 创建
+    pub fn new(config: CaptchaConfig, generator: DynCaptchaGenerator, cache: Cache) -> Self {
+        Self {
+            config,
+            generator,
+            cache,
+        }
+    }
+
+    /// 配置
+    pub fn config(&self) -> &CaptchaConfig {
+        &self.config
+    }
+
+    /// 生成验证码并保存答案，返回 nonce 和 SVG 图片
+    pub async fn issue(&self) -> anyhow::Result<(String, String)> {
+        let Challenge { answer, image } = self.generator.generate();
+        let nonce = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        let mut connection = self.cache.connection().await?;
+        connection
+            .set_ex::<_, _, ()>(
+                format!("{ANSWER_KEY}:{nonce}"),
+                answer,
+                self.config.ttl_secs as usize,
+            )
+            .await?;
+        Ok((nonce, image))
+    }
+
+    /// 验证答案，无论是否正确验证码都会失效
+    pub async fn verify(&self, nonce: &str, answer: &str) -> anyhow::Result<bool> {
+        let mut connection = self.cache.connection().await?;
+        let expected: Option<String> = redis::Script::new(TAKE_SCRIPT)
+            .key(format!("{ANSWER_KEY}:{nonce}"))
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(expected.is_some_and(|expected| is_answer_match(&expected, answer)))
+    }
+
+    /// 记录一次改名，返回是否需要验证码
+    pub async fn rename_attempt(&self, uid: i64) -> anyhow::Result<bool> {
+        let mut connection = self.cache.connection().await?;
+        let count: u64 = redis::Script::new(INCR_SCRIPT)
+            .key(format!("{RENAME_KEY}:{uid}"))
+            .arg(self.config.rename_window_secs.max(1))
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(count > self.config.rename_after_attempts)
+    }
+
+    /// 邮箱密码登录已经连续失败 `failures` 次，返回是否需要验证码
+    pub fn login_required(&self, failures: u64) -> bool {
+        failures >= self.config.login_after_failures
+    }
+}
+
+/// 忽略首尾空白比较答案
+fn is_answer_match(expected: &str, answer: &str) -> bool {
+    expected == answer.trim()
+}
+
+/// 算术验证码，计算图片中的加法、减法或乘法，结果为非负整数
+///
+/// 字符绘制为 `<path>` 笔画，每个顶点随机偏移，图片中不包含算式的文本
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArithmeticCaptcha;
+
+/// 图片宽度
+const WIDTH: u32 = 120;
+/// 图片高度
+const HEIGHT: u32 = 40;
+
+/// 字符的笔画，坐标在 10×16 的格子中，左上角为原点
+type Glyph = &'static [&'static [(f32, f32)]];
+
+/// 算式中可能出现的字符的笔画
+fn glyph(c: char) -> Glyph {
+    match c {
+        '0' => &[&[(0., 0.), (10., 0.), (10., 16.), (0., 16.), (0., 0.)]],
+        '1' => &[&[(3., 3.), (6., 0.), (6., 16.)], &[(3., 16.), (9., 16.)]],
+        '2' => &[&[
+            (0., 0.),
+            (10., 0.),
+            (10., 8.),
+            (0., 8.),
+            (0., 16.),
+            (10., 16.),
+        ]],
+        '3' => &[
+            &[(0., 0.), (10., 0.), (10., 16.), (0., 16.)],
+            &[(2., 8.), (10., 8.)],
+        ],
+        '4' => &[&[(0., 0.), (0., 8.), (10., 8.)], &[(8., 0.), (8., 16.)]],
+        '5' => &[&[
+            (10., 0.),
+            (0., 0.),
+            (0., 8.),
+            (10., 8.),
+            (10., 16.),
+            (0., 16.),
+        ]],
+        '6' => &[&[
+            (10., 0.),
+            (0., 0.),
+            (0., 16.),
+            (10., 16.),
+            (10., 8.),
+            (0., 8.),
+        ]],
+        '7' => &[&[(0., 0.), (10., 0.), (4., 16.)]],
+        '8' => &[
+            &[(0., 0.), (10., 0.), (10., 16.), (0., 16.), (0., 0.)],
+            &[(0., 8.), (10., 8.)],
+        ],
+        '9' => &[&[
+            (10., 8.),
+            (0., 8.),
+            (0., 0.),
+            (10., 0.),
+            (10., 16.),
+            (0., 16.),
+        ]],
+        '+' => &[&[(5., 3.), (5., 13.)], &[(0., 8.), (10., 8.)]],
+        '-' => &[&[(0., 8.), (10., 8.)]],
+        '×' => &[&[(1., 4.), (9., 12.)], &[(9., 4.), (1., 12.)]],
+        '=' => &[&[(0., 5.), (10., 5.)], &[(0., 11.), (10., 11.)]],
+        '?' => &[
+            &[
+                (0., 3.),
+                (0., 0.),
+                (10., 0.),
+                (10., 7.),
+                (5., 9.),
+                (5., 12.),
+            ],
+            &[(5., 15.), (5., 16.)],
+        ],
+        _ => &[],
+    }
+}
+
+impl ArithmeticCaptcha {
+    /// 随机生成算式，返回算式和结果
+    fn expression(rng: &mut impl Rng) -> (String, i64) {
+        match rng.gen_range(0..3) {
+            0 => {
+                let (a, b) = (rng.gen_range(1..=20), rng.gen_range(1..=20));
+                (format!("{a}+{b}=?"), a + b)
+            }
+            1 => {
+                let (a, b) = (rng.gen_range(1..=20), rng.gen_range(1..=20));
+                let (a, b) = (a.max(b), a.min(b));
+                (format!("{a}-{b}=?"), a - b)
+            }
+            _ => {
+                let (a, b) = (rng.gen_range(1..=9), rng.gen_range(1..=9));
+                (format!("{a}×{b}=?"), a * b)
+            }
+        }
+    }
+
+    /// 渲染为 SVG，每个字符的笔画随机缩放、旋转、偏移，顶点随机抖动，并加上同样由笔画组成的干扰线
+    fn render(text: &str, rng: &mut impl Rng) -> String {
+        let mut svg = format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}"><rect width="100%" height="100%" fill="#f5f5f5"/>"##
+        );
+        for _ in 0..4 {
+            let points: Vec<(f32, f32)> = (0..3)
+                .map(|_| {
+                    (
+                        rng.gen_range(0.0..WIDTH as f32),
+                        rng.gen_range(0.0..HEIGHT as f32),
+                    )
+                })
+                .collect();
+            Self::stroke(&mut svg, &points, 1.0, rng);
+        }
+        for (i, c) in text.chars().enumerate() {
+            let scale = rng.gen_range(1.1..1.4f32);
+            let (sin, cos) = rng.gen_range(-0.3..0.3f32).sin_cos();
+            let (cx, cy) = (
+                14.0 + i as f32 * 15.0 + rng.gen_range(-1.5..1.5),
+                20.0 + rng.gen_range(-3.0..3.0),
+            );
+            for line in glyph(c) {
+                let points: Vec<(f32, f32)> = line
+                    .iter()
+                    .map(|&(x, y)| {
+                        let (x, y) = ((x - 5.0) * scale, (y - 8.0) * scale);
+                        (
+                            cx + x * cos - y * sin + rng.gen_range(-0.8..0.8),
+                            cy + x * sin + y * cos + rng.gen_range(-0.8..0.8),
+                        )
+                    })
+                    .collect();
+                Self::stroke(&mut svg, &points, 2.0, rng);
+            }
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    /// 绘制一条折线
+    fn stroke(svg: &mut String, points: &[(f32, f32)], width: f32, rng: &mut impl Rng) {
+        let mut d = String::new();
+        for (i, (x, y)) in points.iter().enumerate() {
+            let _ = write!(d, "{}{x:.1} {y:.1}", if i == 0 { "M" } else { "L" });
+        }
+        let _ = write!(
+            svg,
+            r#"<path d="{d}" fill="none" stroke="{}" stroke-width="{width}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+            Self::color(rng),
+        );
+    }
+
+    /// 较深的随机颜色
+    fn color(rng: &mut impl Rng) -> String {
+        format!(
+            "rgb({},{},{})",
+            rng.gen_range(0..160),
+            rng.gen_range(0..160),
+            rng.gen_range(0..160)
+        )
+    }
+}
+
+impl CaptchaGenerator for ArithmeticCaptcha {
+    fn generate(&self) -> Challenge {
+        let mut rng = rand::thread_rng();
+        let (text, answer) = Self::expression(&mut rng);
+        Challenge {
+            answer: answer.to_string(),
+            image: Self::render(&text, &mut rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::captcha::{glyph, is_answer_match, ArithmeticCaptcha, CaptchaGenerator};
+
+    #[test]
+    fn arithmetic() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (text, answer) = ArithmeticCaptcha::expression(&mut rng);
+            let expression = text.trim_end_matches("=?");
+            let (a, op, b) = ['+', '-', '×']
+                .into_iter()
+                .find_map(|op| {
+                    let (a, b) = expression.split_once(op)?;
+                    Some((a.parse::<i64>().ok()?, op, b.parse::<i64>().ok()?))
+                })
+                .unwrap_or_else(|| panic!("invalid expression: {text}"));
+            let expected = match op {
+                '+' => a + b,
+                '-' => a - b,
+                _ => a * b,
+            };
+            assert_eq!(answer, expected, "{text}");
+            assert!(answer >= 0, "{text}");
+        }
+
+        let challenge = ArithmeticCaptcha.generate();
+        assert!(challenge.image.starts_with("<svg"));
+        assert!(challenge.image.ends_with("</svg>"));
+        assert!(challenge.answer.parse::<i64>().is_ok());
+    }
+
+    /// 图片中只有笔画，不能从标签中读出算式
+    #[test]
+    fn render_paths() {
+        for c in "0123456789+-×=?".chars() {
+            assert!(!glyph(c).is_empty(), "{c}");
+        }
+        let mut rng = rand::thread_rng();
+        let image = ArithmeticCaptcha::render("12+7=?", &mut rng);
+        assert!(!image.contains("<text"), "{image}");
+        assert!(!image.contains("12+7"), "{image}");
+        // 4 条干扰线，1、2、+、7、=、? 共 10 笔
+        assert_eq!(image.matches("<path").count(), 4 + 10);
+        assert_ne!(image, ArithmeticCaptcha::render("12+7=?", &mut rng));
+    }
+
+    #[test]
+    fn answer() {
+        assert!(is_answer_match("12", " 12 "));
+        assert!(!is_answer_match("12", "21"));
+        assert!(!is_answer_match("12", ""));
+    }
+}
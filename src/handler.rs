@@ -1,25 +1,69 @@
 //! # HTTP 请求处理器
 
+use crate::active::{self, ActiveTracker};
+use crate::cache::{self, Cache};
+use crate::captcha::{Captcha, CaptchaConfig};
+use crate::handler::auth::local::LocalAuthConfig;
 use crate::handler::auth::JwtKeys;
-use crate::handler::ws::SessionManager;
-use crate::weixin::WxClient;
+use crate::handler::client_ip::TrustedProxies;
+use crate::handler::cors::CorsConfig;
+use crate::handler::limit::LimitConfig;
+use crate::handler::listener::ListenerConfig;
+use crate::handler::tls::TlsConfig;
+use crate::handler::ws::{SessionManager, WsConfig};
+use crate::ip::IpTracker;
+use crate::jobs::member_count::{self, MemberCountMetrics};
+use crate::live::{Live, ReplyTimeouts};
+use crate::log::LogFilterHandle;
+use crate::mq::DynProducer;
+use crate::secret;
+use crate::service::stats;
+use crate::shortlink::{ShortLink, ShortLinkConfig};
+use crate::spam::SpamGuard;
+use crate::storage::oss::{DynObjectStore, OssConfig};
+use crate::storage::repo::Repos;
+use crate::storage::write_behind::WriteBehind;
+use crate::url_discover;
+use crate::webhook::{self, Webhooks};
+use crate::weixin::work::WorkClient;
+use crate::weixin::{DynWxApi, WxClientRegistry};
 use axum::http::Request;
 use axum::routing::get;
-use axum::{Extension, Router};
+use axum::{middleware, Extension, Router};
 use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
-use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, OnRequest, TraceLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::{DefaultOnResponse, OnRequest, TraceLayer};
 use tower_http::LatencyUnit;
 use tracing::{Level, Span};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+pub mod admin;
 pub mod api;
 pub mod auth;
+pub mod captcha;
 pub mod chat;
+pub mod client_ip;
+pub mod context;
+pub mod cors;
+// aliases 生成的类型别名没有文档
+#[allow(missing_docs)]
+pub mod doc;
+pub mod emoji;
+pub mod friend;
+pub mod limit;
+pub mod listener;
+pub mod open;
+pub mod oss;
+pub mod room;
+pub mod shortlink;
+pub mod tls;
 pub mod user;
+pub mod valid;
 pub mod wechat;
 pub mod ws;
 
@@ -30,8 +74,60 @@ pub struct HttpConfig {
     pub static_files_path: PathBuf,
     /// HTTP 监听端口
     pub port: u16,
-    /// JWT 签名密钥，base64 格式
+    /// JWT 签名密钥，base64 格式，可以使用 `${ENV}` 引用环境变量
+    #[serde(default)]
     pub jwt_secret: String,
+    /// 从文件读取 JWT 签名密钥，配置后忽略 `jwt_secret`
+    #[serde(default)]
+    pub jwt_secret_file: Option<PathBuf>,
+    /// 停机时等待 WebSocket 连接关闭的最长时间（秒）
+    #[serde(default = "default::shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// 跨域资源共享配置，未配置时 debug 构建允许任意来源，release 构建只允许同源访问
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// 请求体大小、处理时间限制
+    #[serde(default)]
+    pub limits: LimitConfig,
+    /// WebSocket 配置
+    #[serde(default)]
+    pub websocket: WsConfig,
+    /// 可信的反向代理 IP 或 CIDR，只有来自这些地址的请求才会解析 `X-Forwarded-For`、`X-Real-IP`
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// 邮箱密码登录，默认不启用
+    #[serde(default)]
+    pub local_auth: LocalAuthConfig,
+    /// 图形验证码，默认不启用
+    #[serde(default)]
+    pub captcha: CaptchaConfig,
+    /// 短链接，默认不启用
+    #[serde(default)]
+    pub shortlink: ShortLinkConfig,
+    /// HTTPS/WSS，未配置时使用 HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// 监听队列、TCP keepalive、HTTP/2 等参数
+    #[serde(default)]
+    pub listener: ListenerConfig,
+}
+
+mod default {
+    pub fn shutdown_timeout_secs() -> u64 {
+        10
+    }
+}
+
+impl HttpConfig {
+    /// 解析 `jwt_secret`，见 [`secret`](crate::secret)
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        self.jwt_secret = secret::resolve(
+            "http.jwt_secret",
+            &self.jwt_secret,
+            self.jwt_secret_file.as_deref(),
+        )?;
+        Ok(())
+    }
 }
 
 /// Open API Documentation
@@ -43,36 +139,595 @@ pub struct HttpConfig {
         chat::get_member_page,
         chat::get_member_statistic,
         chat::get_msg_page,
+        chat::search_message,
         chat::send_message,
+        chat::send_message_mark,
+        chat::report_message,
+        chat::read_message,
+        chat::get_read_count,
+        chat::get_read_page,
         user::get_user_info,
+        user::batch_user_info,
         user::modify_name,
+        user::modify_avatar,
         user::badges,
         user::wearing_badge,
+        user::devices,
+        user::logout_device,
+        friend::apply,
+        friend::approve,
+        friend::apply_page,
+        friend::friend_page,
+        friend::delete_friend,
+        emoji::list_emoji,
+        emoji::add_emoji,
+        emoji::delete_emoji,
+        room::get_or_create_single_room,
+        room::get_group,
+        room::create_group,
+        room::mute_member,
+        room::set_group_announcement,
+        room::transfer_group_owner,
+        room::dissolve_group,
+        room::leave_group,
+        room::create_invite,
+        room::join_group,
+        admin::get_log_level,
+        admin::set_log_level,
+        admin::get_ws_sessions,
+        admin::disconnect_ws_session,
+        admin::kick_user,
+        admin::ban_user,
+        admin::delete_user,
+        admin::restore_user,
+        admin::restore_group,
+        admin::delete_message,
+        admin::restore_message,
+        admin::grant_item,
+        admin::get_daily_stats,
+        admin::get_online_stats,
+        admin::get_member_count_stats,
+        admin::get_cache_stats,
+        admin::publish_announcement,
+        admin::list_webhooks,
+        admin::create_webhook,
+        admin::delete_webhook,
+        admin::get_archived_messages,
+        admin::get_archive_files,
+        admin::list_api_keys,
+        admin::create_api_key,
+        admin::delete_api_key,
+        admin::get_moderation_page,
+        admin::approve_moderation,
+        admin::delete_moderation,
+        admin::get_wx_welcome,
+        admin::set_wx_welcome,
+        open::publish_announcement,
+        open::get_daily_stats,
+        open::get_online_stats,
+        oss::get_upload_url,
+        auth::local::register,
+        auth::local::login,
+        captcha::get_captcha,
         // wechat::auth_get,
         // wechat::call_back,
         // wechat::wx_post,
-    )
+    ),
+    components(schemas(
+        chat::SendMessageReq,
+        chat::MessageMarkReq,
+        chat::MessageReportReq,
+        chat::MessageReadReq,
+        chat::MessageReadCountResp,
+        chat::MessageReaderResp,
+        chat::MessageResp,
+        chat::MessageMarkResp,
+        chat::ChatMessageResp,
+        doc::ChatMessagePage,
+        doc::MessageReaderPage,
+        url_discover::UrlInfo,
+        chat::MessageSearchResp,
+        doc::MessageSearchPage,
+        chat::RoomResp,
+        chat::MemberResp,
+        active::ActiveStatus,
+        user::ModifyNameReq,
+        user::UserInfoResp,
+        user::UserInfoVersion,
+        user::UserInfoBatchReq,
+        cache::user_info::UserInfo,
+        user::ModifyAvatarReq,
+        user::AvatarResp,
+        user::DeviceResp,
+        friend::ApplyStatus,
+        friend::FriendApplyReq,
+        friend::FriendApproveReq,
+        friend::FriendDeleteReq,
+        friend::FriendResp,
+        friend::FriendApplyResp,
+        emoji::EmojiAddReq,
+        emoji::EmojiDeleteReq,
+        emoji::EmojiResp,
+        room::SingleRoomReq,
+        room::SingleRoomResp,
+        room::CreateGroupReq,
+        room::GroupReq,
+        room::MuteMemberReq,
+        room::GroupAnnouncementReq,
+        room::TransferOwnerReq,
+        room::GroupResp,
+        room::InviteReq,
+        room::InviteResp,
+        room::JoinGroupReq,
+        admin::LogLevelReq,
+        admin::LogLevelResp,
+        admin::KickUserReq,
+        admin::UserRestoreReq,
+        admin::GroupRestoreReq,
+        admin::MessageIdReq,
+        admin::GrantItemReq,
+        admin::GrantItemResp,
+        admin::AnnouncementReq,
+        ws::push::Announcement,
+        admin::WebhookReq,
+        admin::WebhookResp,
+        webhook::WebhookEventType,
+        admin::ArchivedMessageResp,
+        admin::ArchiveFileResp,
+        doc::ArchivedMessagePage,
+        doc::ArchiveFilePage,
+        admin::ApiKeyReq,
+        admin::ApiKeyResp,
+        admin::ModerationResp,
+        admin::ModerationIdReq,
+        doc::ModerationPage,
+        admin::WxWelcomeReq,
+        admin::WxWelcomeResp,
+        auth::api_key::ApiScope,
+        stats::DailyStats,
+        stats::OnlineStats,
+        member_count::MemberCountStats,
+        cache::CacheStats,
+        cache::local::LocalCacheStats,
+        cache::local::TierStats,
+        oss::OssResp,
+        ws::SessionInfo,
+        valid::FieldError,
+        doc::ApiSuccess,
+        doc::ApiErrorResp,
+        doc::RoomPageData,
+        doc::MemberPageData,
+        doc::MessageData,
+        doc::MessageSearchPageData,
+        doc::ChatMessagePageData,
+        doc::MessageReadCountData,
+        doc::MessageReaderPageData,
+        doc::FriendPageData,
+        doc::FriendApplyPageData,
+        doc::SingleRoomData,
+        doc::GroupData,
+        doc::InviteData,
+        doc::LogLevelData,
+        doc::WsSessionListData,
+        doc::UserInfoData,
+        doc::UserInfoListData,
+        doc::DeviceListData,
+        doc::OssData,
+        doc::AvatarData,
+        doc::EmojiData,
+        doc::EmojiListData,
+        doc::GrantItemData,
+        doc::DailyStatsListData,
+        doc::OnlineStatsData,
+        doc::MemberCountStatsData,
+        doc::CacheStatsData,
+        doc::AnnouncementData,
+        doc::WebhookData,
+        doc::WebhookListData,
+        doc::ApiKeyData,
+        doc::ApiKeyListData,
+        doc::ArchivedMessagePageData,
+        doc::ArchiveFilePageData,
+        doc::ModerationPageData,
+        doc::WxWelcomeData,
+        auth::local::RegisterReq,
+        auth::local::LoginReq,
+        ws::push::LoginSuccess,
+        doc::LoginSuccessData,
+        captcha::CaptchaResp,
+        captcha::CaptchaAnswer,
+        doc::CaptchaData,
+    ))
 )]
 pub struct ApiDoc;
 
-/// 所有路由
-pub fn router<P: AsRef<std::path::Path>>(
-    with_swagger: bool,
-    static_files_path: P,
-    storage: DatabaseConnection,
-    cache: redis::Client,
-    key: JwtKeys,
-    wx_client: WxClient,
-) -> Router {
-    let router = Router::new()
-        .nest_service("/", ServeDir::new(static_files_path))
-        .route("/websocket", get(ws::websocket_on_connect))
-        .merge(chat::route())
-        .merge(user::route())
-        .merge(wechat::route())
-        .layer(
+/// # 路由构造器
+///
+/// 各子系统可以单独启用、禁用，依赖的 Extension 也分别提供，嵌入其他服务时只挂载需要的部分：
+///
+/// ```ignore
+/// let router = RouterBuilder::new()
+///     .prefix("/mallchat")
+///     .websocket(false)
+///     .storage(db.clone())
+///     .repos(Repos::new(&db))
+///     .jwt_keys(keys)
+///     .build();
+/// ```
+///
+/// 默认启用聊天、用户、微信、WebSocket、管理接口和开放接口，不启用 Swagger UI 和静态文件。
+/// 未提供的 Extension 不会注入，依赖它的接口会返回 500。
+#[derive(Clone)]
+pub struct RouterBuilder {
+    prefix: Option<String>,
+    static_files_path: Option<PathBuf>,
+    swagger: bool,
+    chat: bool,
+    user: bool,
+    wechat: bool,
+    websocket: bool,
+    admin: bool,
+    open: bool,
+    oss: bool,
+    storage: Option<DatabaseConnection>,
+    repos: Option<Repos>,
+    cache: Option<Cache>,
+    jwt_keys: Option<JwtKeys>,
+    wx_clients: Option<WxClientRegistry>,
+    work_client: Option<WorkClient>,
+    local_auth: Option<LocalAuthConfig>,
+    captcha: Option<Captcha>,
+    shortlink: Option<ShortLink>,
+    session_manager: Option<SessionManager>,
+    log_filter: Option<LogFilterHandle>,
+    trusted_proxies: Option<TrustedProxies>,
+    ip_tracker: Option<IpTracker>,
+    active_tracker: Option<ActiveTracker>,
+    spam_guard: Option<SpamGuard>,
+    write_behind: Option<WriteBehind>,
+    member_count_metrics: Option<MemberCountMetrics>,
+    webhooks: Option<Webhooks>,
+    producer: Option<DynProducer>,
+    object_store: Option<DynObjectStore>,
+    oss_config: Option<OssConfig>,
+    cors: Option<CorsLayer>,
+    limits: LimitConfig,
+    live_limits: Option<Live<LimitConfig>>,
+    reply_timeouts: Option<Live<ReplyTimeouts>>,
+    upload: Option<Router>,
+}
+
+impl Default for RouterBuilder {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            static_files_path: None,
+            swagger: false,
+            chat: true,
+            user: true,
+            wechat: true,
+            websocket: true,
+            admin: true,
+            open: true,
+            oss: true,
+            storage: None,
+            repos: None,
+            cache: None,
+            jwt_keys: None,
+            wx_clients: None,
+            work_client: None,
+            local_auth: None,
+            captcha: None,
+            shortlink: None,
+            session_manager: None,
+            log_filter: None,
+            trusted_proxies: None,
+            ip_tracker: None,
+            active_tracker: None,
+            spam_guard: None,
+            write_behind: None,
+            member_count_metrics: None,
+            webhooks: None,
+            producer: None,
+            object_store: None,
+            oss_config: None,
+            cors: None,
+            limits: LimitConfig::default(),
+            live_limits: None,
+            reply_timeouts: None,
+            upload: None,
+        }
+    }
+}
+
+impl RouterBuilder {
+    /// 创建构造器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 所有路由挂载到 `prefix` 下，例如 `/mallchat`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// 在根路径提供 `path` 目录下的静态文件
+    pub fn static_files(mut self, path: impl Into<PathBuf>) -> Self {
+        self.static_files_path = Some(path.into());
+        self
+    }
+
+    /// 是否提供 Swagger UI 和 OpenAPI 文档
+    pub fn swagger(mut self, enabled: bool) -> Self {
+        self.swagger = enabled;
+        self
+    }
+
+    /// 是否提供聊天、房间接口
+    pub fn chat(mut self, enabled: bool) -> Self {
+        self.chat = enabled;
+        self
+    }
+
+    /// 是否提供用户、好友、表情包接口
+    pub fn user(mut self, enabled: bool) -> Self {
+        self.user = enabled;
+        self
+    }
+
+    /// 是否提供微信公众号回调接口
+    pub fn wechat(mut self, enabled: bool) -> Self {
+        self.wechat = enabled;
+        self
+    }
+
+    /// 是否提供 WebSocket 接口
+    pub fn websocket(mut self, enabled: bool) -> Self {
+        self.websocket = enabled;
+        self
+    }
+
+    /// 是否提供管理接口
+    pub fn admin(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
+    }
+
+    /// 是否提供开放接口，见 [`open`]
+    pub fn open(mut self, enabled: bool) -> Self {
+        self.open = enabled;
+        self
+    }
+
+    /// 是否提供对象存储接口
+    pub fn oss(mut self, enabled: bool) -> Self {
+        self.oss = enabled;
+        self
+    }
+
+    /// 数据库连接
+    pub fn storage(mut self, storage: DatabaseConnection) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// 数据访问对象
+    pub fn repos(mut self, repos: Repos) -> Self {
+        self.repos = Some(repos);
+        self
+    }
+
+    /// Redis 客户端
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// JWT 密钥
+    pub fn jwt_keys(mut self, jwt_keys: JwtKeys) -> Self {
+        self.jwt_keys = Some(jwt_keys);
+        self
+    }
+
+    /// 微信公众平台接口，只有一个公众号
+    pub fn wx_client(self, wx_client: DynWxApi) -> Self {
+        self.wx_clients(WxClientRegistry::from(wx_client))
+    }
+
+    /// 多个公众号的微信公众平台接口，同时注入默认公众号的 [`DynWxApi`]
+    pub fn wx_clients(mut self, wx_clients: WxClientRegistry) -> Self {
+        self.wx_clients = Some(wx_clients);
+        self
+    }
+
+    /// 企业微信应用，设置后提供企业微信的回调和登录
+    pub fn work_client(mut self, work_client: WorkClient) -> Self {
+        self.work_client = Some(work_client);
+        self
+    }
+
+    /// 邮箱密码登录，配置未启用时不挂载
+    pub fn local_auth(mut self, config: LocalAuthConfig) -> Self {
+        self.local_auth = config.enabled.then_some(config);
+        self
+    }
+
+    /// 图形验证码，提供时邮箱密码登录、改名在超过阈值后需要验证码
+    pub fn captcha(mut self, captcha: Captcha) -> Self {
+        self.captcha = Some(captcha);
+        self
+    }
+
+    /// 短链接，提供时挂载 `/s/:slug` 重定向，群聊邀请同时返回短链接
+    pub fn shortlink(mut self, shortlink: ShortLink) -> Self {
+        self.shortlink = Some(shortlink);
+        self
+    }
+
+    /// WebSocket 连接管理
+    pub fn session_manager(mut self, session_manager: SessionManager) -> Self {
+        self.session_manager = Some(session_manager);
+        self
+    }
+
+    /// 日志过滤器
+    pub fn log_filter(mut self, log_filter: LogFilterHandle) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// 可信的反向代理，见 [`ClientIp`](client_ip::ClientIp)
+    pub fn trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(trusted_proxies);
+        self
+    }
+
+    /// 用户 IP 归属地记录
+    pub fn ip_tracker(mut self, ip_tracker: IpTracker) -> Self {
+        self.ip_tracker = Some(ip_tracker);
+        self
+    }
+
+    /// 用户活跃记录
+    pub fn active_tracker(mut self, active_tracker: ActiveTracker) -> Self {
+        self.active_tracker = Some(active_tracker);
+        self
+    }
+
+    /// 发送消息的反垃圾检查，未配置时不检查
+    pub fn spam_guard(mut self, spam_guard: SpamGuard) -> Self {
+        self.spam_guard = Some(spam_guard);
+        self
+    }
+
+    /// 消息标记、阅读进度的延迟批量写入，未配置时直接写数据库
+    pub fn write_behind(mut self, write_behind: WriteBehind) -> Self {
+        self.write_behind = Some(write_behind);
+        self
+    }
+
+    /// 房间成员数校验的指标
+    pub fn member_count_metrics(mut self, metrics: MemberCountMetrics) -> Self {
+        self.member_count_metrics = Some(metrics);
+        self
+    }
+
+    /// 事件 Webhook，未配置时不发布事件
+    pub fn webhooks(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// 消息队列生产者
+    pub fn producer(mut self, producer: DynProducer) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    /// 对象存储及其配置
+    pub fn object_store(mut self, object_store: DynObjectStore, config: OssConfig) -> Self {
+        self.object_store = Some(object_store);
+        self.oss_config = Some(config);
+        self
+    }
+
+    /// 跨域资源共享中间件，见 [`CorsConfig::layer`]
+    pub fn cors(mut self, cors: CorsLayer) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// 请求体大小、处理时间限制
+    pub fn limits(mut self, limits: LimitConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 可以热更新的请求限制，设置后请求超时时间按最新的配置计算，请求体大小限制仍使用构造时的值
+    pub fn live_limits(mut self, limits: Live<LimitConfig>) -> Self {
+        self.limits = LimitConfig::clone(&limits.load());
+        self.live_limits = Some(limits);
+        self
+    }
+
+    /// 可以热更新的微信被动回复超时时间，未设置时使用公众号配置中的 `reply_timeout_millis`
+    pub fn reply_timeouts(mut self, reply_timeouts: Live<ReplyTimeouts>) -> Self {
+        self.reply_timeouts = Some(reply_timeouts);
+        self
+    }
+
+    /// 上传接口，使用 [`LimitConfig::upload_body_limit_bytes`] 限制请求体大小
+    pub fn upload(mut self, upload: Router) -> Self {
+        self.upload = Some(upload);
+        self
+    }
+
+    /// 构造路由
+    pub fn build(self) -> Router {
+        let mut router = Router::new();
+        if let Some(path) = self.static_files_path {
+            router = router.nest_service("/", ServeDir::new(path));
+        }
+        if self.websocket {
+            router = router.route("/websocket", get(ws::websocket_on_connect));
+        }
+        if self.chat {
+            router = router.merge(chat::route()).merge(room::route());
+        }
+        if self.user {
+            router = router
+                .merge(user::route())
+                .merge(friend::route())
+                .merge(emoji::route())
+                .merge(shortlink::qr_route());
+        }
+        if self.wechat {
+            router = router.merge(wechat::route());
+            if self.work_client.is_some() {
+                router = router.merge(wechat::work_route());
+            }
+        }
+        if self.local_auth.is_some() {
+            router = router.merge(auth::local::route());
+        }
+        if self.captcha.is_some() {
+            router = router.merge(captcha::route());
+        }
+        if self.shortlink.is_some() {
+            router = router.merge(shortlink::route());
+        }
+        if self.admin {
+            router = router.merge(admin::route());
+        }
+        if self.open {
+            router = router.merge(open::route());
+        }
+        if self.oss {
+            router = router.merge(oss::route());
+        }
+        if self.swagger {
+            router = router.merge(
+                SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()),
+            );
+            router = router.route("/api-docs/ws.json", get(ws::push::asyncapi_json));
+        }
+        router = self.limits.limit_body(router);
+        if let Some(upload) = self.upload {
+            router = router.merge(self.limits.limit_upload_body(upload));
+        }
+
+        router = match &self.live_limits {
+            Some(limits) => router.layer(middleware::from_fn_with_state(
+                limits.clone(),
+                limit::live_timeout,
+            )),
+            None => router.layer(TimeoutLayer::new(self.limits.timeout())),
+        };
+        router = router.layer(middleware::map_response(limit::json_error_response));
+        router = router.layer(
             TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .make_span_with(context::RequestSpan)
                 .on_request(RequestTracer::from(Level::INFO))
                 .on_response(
                     DefaultOnResponse::new()
@@ -80,16 +735,63 @@ pub fn router<P: AsRef<std::path::Path>>(
                         .include_headers(true)
                         .latency_unit(LatencyUnit::Micros),
                 ),
-        )
-        .layer(Extension(storage))
-        .layer(Extension(cache))
-        .layer(Extension(key))
-        .layer(Extension(wx_client))
-        .layer(Extension(SessionManager::default()));
-    if with_swagger {
-        router.merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-    } else {
-        router
+        );
+        // 在 TraceLayer 之前分配请求 ID，以便记录到 span 中
+        router = router.layer(middleware::from_fn(context::request_context));
+        if let Some(repos) = self.repos {
+            router = router
+                .layer(Extension(repos.users))
+                .layer(Extension(repos.messages))
+                .layer(Extension(repos.rooms));
+        }
+        router = layer_option(router, self.storage);
+        router = layer_option(router, self.cache);
+        router = layer_option(router, self.jwt_keys);
+        router = layer_option(
+            router,
+            self.wx_clients
+                .as_ref()
+                .map(|wx_clients| wx_clients.default_client().clone()),
+        );
+        router = layer_option(router, self.wx_clients);
+        router = layer_option(router, self.work_client);
+        router = layer_option(router, self.local_auth);
+        router = layer_option(router, self.captcha);
+        router = layer_option(router, self.shortlink);
+        router = layer_option(router, self.session_manager);
+        router = layer_option(router, self.log_filter);
+        router = layer_option(router, self.trusted_proxies);
+        router = layer_option(router, self.ip_tracker);
+        router = layer_option(router, self.active_tracker);
+        router = layer_option(router, self.spam_guard);
+        router = layer_option(router, self.write_behind);
+        router = layer_option(router, self.member_count_metrics);
+        router = layer_option(router, self.webhooks);
+        router = layer_option(router, self.producer);
+        router = layer_option(router, self.object_store);
+        router = layer_option(router, self.oss_config);
+        router = layer_option(router, self.live_limits);
+        router = layer_option(router, self.reply_timeouts);
+        // 最外层处理预检请求
+        if let Some(cors) = self.cors {
+            router = router.layer(cors);
+        }
+
+        match self.prefix.as_deref() {
+            // axum 不支持嵌套到根路径
+            None | Some("") | Some("/") => router,
+            Some(prefix) => Router::new().nest(prefix, router),
+        }
+    }
+}
+
+fn layer_option<T>(router: Router, extension: Option<T>) -> Router
+where
+    T: Clone + Send + Sync + 'static,
+{
+    match extension {
+        Some(extension) => router.layer(Extension(extension)),
+        None => router,
     }
 }
 
@@ -129,3 +831,65 @@ impl<B> OnRequest<B> for RequestTracer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use utoipa::OpenApi;
+
+    use crate::handler::ApiDoc;
+
+    fn refs<'a>(value: &'a serde_json::Value, refs: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value.as_str()) {
+                        ("$ref", Some(reference)) => refs.push(reference),
+                        _ => self::refs(value, refs),
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| self::refs(value, refs))
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn openapi() -> anyhow::Result<()> {
+        let doc = serde_json::to_value(ApiDoc::openapi())?;
+
+        let params = &doc["paths"]["/capi/chat/public/room/page"]["get"]["parameters"];
+        let params = params.as_array().map(Vec::as_slice).unwrap_or_default();
+        assert_eq!(params.len(), 2);
+        assert!(
+            params.iter().all(|param| param["in"] == "query"),
+            "{params:?}"
+        );
+
+        let paths = doc["paths"]
+            .as_object()
+            .map(|paths| paths.values().collect::<Vec<_>>());
+        for operation in paths
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| path.as_object())
+            .flat_map(|path| path.values())
+        {
+            let schema = &operation["responses"]["200"]["content"]["application/json"]["schema"];
+            assert!(schema.get("$ref").is_some(), "{operation}");
+        }
+
+        let mut references = Vec::new();
+        refs(&doc["paths"], &mut references);
+        assert!(!references.is_empty());
+        for reference in references {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(
+                doc["components"]["schemas"].get(name).is_some(),
+                "{reference}"
+            );
+        }
+        Ok(())
+    }
+}
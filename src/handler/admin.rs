@@ -0,0 +1,1430 @@
+//! # 管理相关接口
+//!
+
+use crate::cache::{Cache, CacheStats};
+use crate::handler::valid::Valid;
+use axum::extract::Query;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::handler::api::{
+    ApiError, ApiResult, ApiValue, CursorPageReq, CursorPageResp, ErrorCode, ToApiData,
+};
+use crate::handler::auth::api_key::ApiScope;
+use crate::handler::auth::{Admin, JwtKeys};
+use crate::handler::ws::push::{Announcement, MsgRecall};
+use crate::handler::ws::{SessionInfo, SessionManager};
+use crate::jobs::member_count::{MemberCountMetrics, MemberCountStats};
+use crate::log::LogFilterHandle;
+use crate::mq::announcement::{push_announcement, ANNOUNCEMENT_TOPIC};
+use crate::mq::kick::{self, KickEvent};
+use crate::mq::recall::{push_recall, RecallEvent, RECALL_TOPIC};
+use crate::mq::{self, DynProducer, Producer};
+use crate::service::announcement::AnnouncementService;
+use crate::service::api_key::{split_scopes, ApiKeyService};
+use crate::service::black::BlackService;
+use crate::service::group::GroupService;
+use crate::service::item::{idempotent, IdempotentType, Item, ItemService};
+use crate::service::moderation::{ModerationService, ModerationStatus};
+use crate::service::room::RoomService;
+use crate::service::stats::{DailyStats, OnlineStats, StatsService};
+use crate::service::webhook::{split_events, WebhookService};
+use crate::service::welcome::WelcomeService;
+use crate::storage::model::{
+    api_key, archive_file, message_archive, moderation, webhook, wx_welcome,
+};
+use crate::storage::oss::{DynObjectStore, OssConfig};
+use crate::storage::repo::{DynMessageRepo, DynUserRepo, MessageRepo, UserRepo};
+use crate::storage::tx::with_txn;
+use crate::webhook::{UserBanned, WebhookEndpoint, WebhookEvent, WebhookEventType, Webhooks};
+use crate::weixin::{DynWxApi, WxClientRegistry};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use time::{Date, OffsetDateTime};
+
+/// 管理相关路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/admin",
+        Router::new()
+            .route("/log/level", get(get_log_level).put(set_log_level))
+            .route(
+                "/ws/sessions",
+                get(get_ws_sessions).delete(disconnect_ws_session),
+            )
+            .route("/user/kick", post(kick_user))
+            .route("/user/ban", post(ban_user))
+            .route("/user/delete", post(delete_user))
+            .route("/user/restore", post(restore_user))
+            .route("/room/restore", post(restore_group))
+            .route("/msg/delete", post(delete_message))
+            .route("/msg/restore", post(restore_message))
+            .route("/moderation/page", get(get_moderation_page))
+            .route("/moderation/approve", post(approve_moderation))
+            .route("/moderation/delete", post(delete_moderation))
+            .route("/item/grant", post(grant_item))
+            .route("/stats/daily", get(get_daily_stats))
+            .route("/stats/online", get(get_online_stats))
+            .route("/stats/member_count", get(get_member_count_stats))
+            .route("/cache/stats", get(get_cache_stats))
+            .route("/announcement", post(publish_announcement))
+            .route(
+                "/webhook",
+                get(list_webhooks)
+                    .post(create_webhook)
+                    .delete(delete_webhook),
+            )
+            .route("/archive/message", get(get_archived_messages))
+            .route("/archive/file", get(get_archive_files))
+            .route(
+                "/api_key",
+                get(list_api_keys)
+                    .post(create_api_key)
+                    .delete(delete_api_key),
+            )
+            .route("/wx/welcome", get(get_wx_welcome).put(set_wx_welcome)),
+    )
+}
+
+/// 日志级别
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelReq {
+    /// 日志级别（如 `debug`）或按模块过滤的规则（如 `info,mallchat=debug`）
+    #[validate(length(min = 1, max = 256))]
+    pub level: String,
+}
+
+/// 当前日志级别
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelResp {
+    /// 当前生效的过滤规则
+    pub level: String,
+}
+
+/// 获取当前日志级别
+#[utoipa::path(
+    get,
+    path = "/capi/admin/log/level",
+    responses(
+        (status = 200, description = "成功", body = LogLevelData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_log_level(
+    _admin: Admin,
+    Extension(handle): Extension<LogFilterHandle>,
+) -> ApiResult<LogLevelResp> {
+    LogLevelResp {
+        level: handle.current(),
+    }
+    .to_api_data()
+}
+
+/// 修改日志级别，无需重启立即生效
+#[utoipa::path(
+    put,
+    path = "/capi/admin/log/level",
+    request_body = LogLevelReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn set_log_level(
+    Admin(claims): Admin,
+    Extension(handle): Extension<LogFilterHandle>,
+    Valid(Json(req)): Valid<Json<LogLevelReq>>,
+) -> ApiResult<()> {
+    let previous = handle.current();
+    if let Err(e) = handle.reload(&req.level) {
+        return ApiError::business_err(ErrorCode::InvalidParam, e.to_string());
+    }
+    tracing::warn!(uid = claims.uid, %previous, current = %req.level, "Log level changed.");
+    ApiValue::success()
+}
+
+/// WebSocket 连接查询条件
+#[derive(Debug, Default, Validate, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsSessionQuery {
+    /// 用户 ID
+    pub uid: Option<i64>,
+    /// 客户端 IP
+    #[param(value_type = Option<String>)]
+    pub ip: Option<IpAddr>,
+}
+
+/// 查询当前的 WebSocket 连接
+#[utoipa::path(
+    get,
+    path = "/capi/admin/ws/sessions",
+    params(WsSessionQuery),
+    responses(
+        (status = 200, description = "成功", body = WsSessionListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_ws_sessions(
+    _admin: Admin,
+    Extension(session_manager): Extension<SessionManager>,
+    Valid(Query(query)): Valid<Query<WsSessionQuery>>,
+) -> ApiResult<Vec<SessionInfo>> {
+    session_manager.list(query.uid, query.ip).to_api_data()
+}
+
+/// 断开连接请求
+#[derive(Debug, Validate, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DisconnectReq {
+    /// 连接 ID
+    pub id: usize,
+}
+
+/// 强制断开 WebSocket 连接
+#[utoipa::path(
+    delete,
+    path = "/capi/admin/ws/sessions",
+    params(DisconnectReq),
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn disconnect_ws_session(
+    Admin(claims): Admin,
+    Extension(session_manager): Extension<SessionManager>,
+    Valid(Query(req)): Valid<Query<DisconnectReq>>,
+) -> ApiResult<()> {
+    if !session_manager.disconnect(req.id) {
+        return ApiError::business_err(ErrorCode::SessionNotFound, "连接不存在");
+    }
+    tracing::warn!(
+        uid = claims.uid,
+        id = req.id,
+        "Websocket session disconnected by admin."
+    );
+    ApiValue::success()
+}
+
+/// 踢出用户请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KickUserReq {
+    /// 用户 ID
+    pub uid: i64,
+    /// 原因，会在关闭连接时发送给客户端
+    #[validate(length(max = 100))]
+    pub reason: Option<String>,
+}
+
+/// 强制下线：吊销用户的 token 并关闭其在所有实例上的连接
+async fn force_logout(
+    jwt_keys: &JwtKeys,
+    session_manager: &SessionManager,
+    producer: Option<&dyn Producer>,
+    uid: i64,
+    reason: &str,
+) -> Result<(), ApiError> {
+    jwt_keys.revoke_user(uid).await?;
+    let event = KickEvent {
+        uid,
+        device: None,
+        reason: reason.to_string(),
+    };
+    kick::kick(session_manager, producer, event).await;
+    Ok(())
+}
+
+/// 踢出用户，强制下线
+#[utoipa::path(
+    post,
+    path = "/capi/admin/user/kick",
+    request_body = KickUserReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn kick_user(
+    Admin(claims): Admin,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<KickUserReq>>,
+) -> ApiResult<()> {
+    let reason = req.reason.as_deref().unwrap_or("Kicked by administrator");
+    let producer = producer
+        .as_ref()
+        .map(|Extension(producer)| producer.as_ref());
+    force_logout(&jwt_keys, &session_manager, producer, req.uid, reason).await?;
+    tracing::warn!(uid = claims.uid, target = req.uid, %reason, "User kicked by admin.");
+    ApiValue::success()
+}
+
+/// 拉黑用户，并强制下线
+#[utoipa::path(
+    post,
+    path = "/capi/admin/user/ban",
+    request_body = KickUserReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn ban_user(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    Extension(session_manager): Extension<SessionManager>,
+    cache: Option<Extension<Cache>>,
+    producer: Option<Extension<DynProducer>>,
+    webhooks: Option<Extension<Webhooks>>,
+    Valid(Json(req)): Valid<Json<KickUserReq>>,
+) -> ApiResult<()> {
+    if req.uid == claims.uid {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不能拉黑自己");
+    }
+    BlackService::new(&db).ban_user(req.uid).await?;
+    if let Some(Extension(cache)) = cache {
+        cache.local().invalidate_user(req.uid).await;
+    }
+    let reason = req.reason.as_deref().unwrap_or("Banned by administrator");
+    let producer = producer
+        .as_ref()
+        .map(|Extension(producer)| producer.as_ref());
+    force_logout(&jwt_keys, &session_manager, producer, req.uid, reason).await?;
+    tracing::warn!(uid = claims.uid, target = req.uid, %reason, "User banned by admin.");
+    if let Some(Extension(webhooks)) = webhooks {
+        webhooks
+            .emit(WebhookEvent::UserBanned(UserBanned {
+                uid: req.uid,
+                operator_uid: claims.uid,
+                reason: req.reason,
+            }))
+            .await;
+    }
+    ApiValue::success()
+}
+
+/// 恢复用户请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRestoreReq {
+    /// 用户 ID
+    pub uid: i64,
+}
+
+/// 注销用户：逻辑删除并强制下线，发送过的消息、好友关系等历史数据保留，可以恢复
+#[utoipa::path(
+    post,
+    path = "/capi/admin/user/delete",
+    request_body = KickUserReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_user(
+    Admin(claims): Admin,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<KickUserReq>>,
+) -> ApiResult<()> {
+    if req.uid == claims.uid {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不能注销自己");
+    }
+    if !users.soft_delete(req.uid).await? {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在或已注销");
+    }
+    let reason = req.reason.as_deref().unwrap_or("Deleted by administrator");
+    let producer = producer
+        .as_ref()
+        .map(|Extension(producer)| producer.as_ref());
+    force_logout(&jwt_keys, &session_manager, producer, req.uid, reason).await?;
+    tracing::warn!(uid = claims.uid, target = req.uid, %reason, "User deleted by admin.");
+    ApiValue::success()
+}
+
+/// 恢复已注销的用户
+#[utoipa::path(
+    post,
+    path = "/capi/admin/user/restore",
+    request_body = UserRestoreReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn restore_user(
+    Admin(claims): Admin,
+    Extension(users): Extension<DynUserRepo>,
+    Valid(Json(req)): Valid<Json<UserRestoreReq>>,
+) -> ApiResult<()> {
+    if !users.restore(req.uid).await? {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在或未注销");
+    }
+    tracing::warn!(
+        uid = claims.uid,
+        target = req.uid,
+        "User restored by admin."
+    );
+    ApiValue::success()
+}
+
+/// 恢复群聊请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRestoreReq {
+    /// 房间 ID
+    pub room_id: i64,
+}
+
+/// 恢复已解散的群聊，成员、群公告和历史消息都还在
+#[utoipa::path(
+    post,
+    path = "/capi/admin/room/restore",
+    request_body = GroupRestoreReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn restore_group(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    cache: Option<Extension<Cache>>,
+    Valid(Json(req)): Valid<Json<GroupRestoreReq>>,
+) -> ApiResult<()> {
+    if !GroupService::new(&db).restore(req.room_id).await? {
+        return ApiError::business_err(ErrorCode::RoomNotFound, "群聊不存在或未解散");
+    }
+    if let Some(Extension(cache)) = cache {
+        cache.local().invalidate_room(req.room_id).await;
+    }
+    tracing::warn!(
+        uid = claims.uid,
+        room_id = req.room_id,
+        "Group restored by admin."
+    );
+    ApiValue::success()
+}
+
+/// 删除、恢复消息请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageIdReq {
+    /// 消息 ID
+    pub msg_id: u64,
+}
+
+/// 删除消息，消息不再出现在列表和搜索结果中，引用它的回复仍然可以找到原消息
+#[utoipa::path(
+    post,
+    path = "/capi/admin/msg/delete",
+    request_body = MessageIdReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_message(
+    Admin(claims): Admin,
+    Extension(messages): Extension<DynMessageRepo>,
+    Valid(Json(req)): Valid<Json<MessageIdReq>>,
+) -> ApiResult<()> {
+    if !messages.soft_delete(req.msg_id).await? {
+        return ApiError::business_err(ErrorCode::MessageNotFound, "消息不存在或已删除");
+    }
+    tracing::warn!(
+        uid = claims.uid,
+        msg_id = req.msg_id,
+        "Message deleted by admin."
+    );
+    ApiValue::success()
+}
+
+/// 恢复已删除的消息
+#[utoipa::path(
+    post,
+    path = "/capi/admin/msg/restore",
+    request_body = MessageIdReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn restore_message(
+    Admin(claims): Admin,
+    Extension(messages): Extension<DynMessageRepo>,
+    Valid(Json(req)): Valid<Json<MessageIdReq>>,
+) -> ApiResult<()> {
+    if !messages.restore(req.msg_id).await? {
+        return ApiError::business_err(ErrorCode::MessageNotFound, "消息不存在或未删除");
+    }
+    tracing::warn!(
+        uid = claims.uid,
+        msg_id = req.msg_id,
+        "Message restored by admin."
+    );
+    ApiValue::success()
+}
+
+/// 发放物品请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GrantItemReq {
+    /// 用户 uid
+    pub uid: i64,
+    /// 物品 ID，见 `item_config` 表
+    pub item_id: i32,
+    /// 幂等号，相同的幂等号只发放一次
+    #[validate(length(min = 1, max = 32))]
+    pub idempotency_key: String,
+}
+
+/// 发放物品结果
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GrantItemResp {
+    /// 是否发放，幂等号已使用过时为 `false`
+    pub granted: bool,
+}
+
+/// 发放物品（改名卡、徽章）
+#[utoipa::path(
+    post,
+    path = "/capi/admin/item/grant",
+    request_body = GrantItemReq,
+    responses(
+        (status = 200, description = "成功", body = GrantItemData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn grant_item(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<GrantItemReq>>,
+) -> ApiResult<GrantItemResp> {
+    let Ok(item) = Item::try_from(req.item_id) else {
+        return ApiError::business_err(ErrorCode::InvalidParam, "物品不存在");
+    };
+    if UserRepo::find_by_id(&db, req.uid).await?.is_none() {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    }
+    let key = idempotent(item, IdempotentType::Admin, &req.idempotency_key);
+    let granted = ItemService::new(&db)
+        .grant_item(req.uid, item, &key)
+        .await?;
+    tracing::info!(
+        uid = claims.uid,
+        target = req.uid,
+        ?item,
+        %granted,
+        "Item granted by admin."
+    );
+    GrantItemResp { granted }.to_api_data()
+}
+
+/// 每日统计最多查询的天数
+pub const MAX_STATS_DAYS: i64 = 90;
+
+/// 每日统计查询条件
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyStatsQuery {
+    /// 开始日期（包含），如 `2023-08-01`
+    #[param(value_type = String, format = Date)]
+    pub from: Date,
+    /// 结束日期（包含）
+    #[param(value_type = String, format = Date)]
+    pub to: Date,
+}
+
+/// 查询每日新增用户、活跃用户、消息数和在线人数峰值，数据在次日凌晨统计
+#[utoipa::path(
+    get,
+    path = "/capi/admin/stats/daily",
+    params(DailyStatsQuery),
+    responses(
+        (status = 200, description = "成功", body = DailyStatsListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_daily_stats(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(cache): Extension<Cache>,
+    Valid(Query(query)): Valid<Query<DailyStatsQuery>>,
+) -> ApiResult<Vec<DailyStats>> {
+    daily_stats(&db, &cache, &query).await?.to_api_data()
+}
+
+/// 校验查询范围后查询每日统计，开放接口共用
+pub(crate) async fn daily_stats(
+    db: &DatabaseConnection,
+    cache: &Cache,
+    query: &DailyStatsQuery,
+) -> Result<Vec<DailyStats>, ApiError> {
+    let days = (query.to - query.from).whole_days();
+    if !(0..MAX_STATS_DAYS).contains(&days) {
+        return Err(ApiError::business(
+            ErrorCode::InvalidParam,
+            format!("查询范围需在{MAX_STATS_DAYS}天以内"),
+        ));
+    }
+    Ok(StatsService::new(db, cache)
+        .daily(query.from, query.to)
+        .await?)
+}
+
+/// 查询当前在线人数和最近 24 小时的在线人数峰值，在线人数每分钟采样一次
+#[utoipa::path(
+    get,
+    path = "/capi/admin/stats/online",
+    responses(
+        (status = 200, description = "成功", body = OnlineStatsData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_online_stats(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(cache): Extension<Cache>,
+) -> ApiResult<OnlineStats> {
+    online_stats(&db, &cache).await?.to_api_data()
+}
+
+/// 当前在线人数和最近 24 小时的峰值，开放接口共用
+pub(crate) async fn online_stats(
+    db: &DatabaseConnection,
+    cache: &Cache,
+) -> Result<OnlineStats, ApiError> {
+    let now = OffsetDateTime::now_utc();
+    Ok(StatsService::new(db, cache)
+        .online(now - time::Duration::DAY, now)
+        .await?)
+}
+
+/// 查询房间成员数校验的指标，没有启用定时任务时均为 0
+#[utoipa::path(
+    get,
+    path = "/capi/admin/stats/member_count",
+    responses(
+        (status = 200, description = "成功", body = MemberCountStatsData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_member_count_stats(
+    _admin: Admin,
+    metrics: Option<Extension<MemberCountMetrics>>,
+) -> ApiResult<MemberCountStats> {
+    metrics
+        .map(|Extension(metrics)| metrics)
+        .unwrap_or_default()
+        .stats()
+        .to_api_data()
+}
+
+/// 查询 Redis 连接指标
+#[utoipa::path(
+    get,
+    path = "/capi/admin/cache/stats",
+    responses(
+        (status = 200, description = "成功", body = CacheStatsData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_cache_stats(
+    _admin: Admin,
+    Extension(cache): Extension<Cache>,
+) -> ApiResult<CacheStats> {
+    cache.stats().await.to_api_data()
+}
+
+/// 发布公告请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnouncementReq {
+    /// 公告内容
+    #[validate(length(min = 1, max = 1024))]
+    pub content: String,
+}
+
+/// 发布系统公告，推送给所有在线用户，离线用户在下次登录时补发
+#[utoipa::path(
+    post,
+    path = "/capi/admin/announcement",
+    request_body = AnnouncementReq,
+    responses(
+        (status = 200, description = "成功", body = AnnouncementData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn publish_announcement(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<AnnouncementReq>>,
+) -> ApiResult<Announcement> {
+    let producer = producer.map(|Extension(producer)| producer);
+    let announcement = announce(&db, &session_manager, producer, claims.uid, &req.content).await?;
+    tracing::info!(uid = claims.uid, id = %announcement.id, "Announcement published by admin.");
+    announcement.to_api_data()
+}
+
+/// 保存并推送公告，开放接口共用
+pub(crate) async fn announce(
+    db: &DatabaseConnection,
+    session_manager: &SessionManager,
+    producer: Option<DynProducer>,
+    uid: i64,
+    content: &str,
+) -> Result<Announcement, ApiError> {
+    let announcement = Announcement::from(AnnouncementService::new(db).create(uid, content).await?);
+    // 多实例部署时通过消息队列推送给各实例的连接，已保存的公告会在登录时补发，推送失败时不影响接口返回
+    match producer {
+        Some(producer) => {
+            if let Err(error) =
+                mq::send_json(producer.as_ref(), ANNOUNCEMENT_TOPIC, &announcement).await
+            {
+                tracing::error!(id = %announcement.id, %error, "Failed to publish announcement.");
+            }
+        }
+        None => {
+            push_announcement(session_manager, db, announcement.clone()).await?;
+        }
+    }
+    Ok(announcement)
+}
+
+/// 注册 Webhook 请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookReq {
+    /// 回调地址
+    #[validate(url, length(max = 512))]
+    pub url: String,
+    /// 签名密钥，为空时不签名
+    #[validate(length(min = 1, max = 128))]
+    pub secret: Option<String>,
+    /// 订阅的事件，为空时订阅全部事件
+    #[serde(default)]
+    pub events: Vec<WebhookEventType>,
+}
+
+/// 注册的 Webhook，不返回密钥
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResp {
+    /// ID
+    pub id: u64,
+    /// 回调地址
+    pub url: String,
+    /// 订阅的事件，为空时订阅全部事件
+    pub events: Vec<WebhookEventType>,
+    /// 是否签名
+    pub signed: bool,
+    /// 注册时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+}
+
+impl From<webhook::Model> for WebhookResp {
+    fn from(model: webhook::Model) -> Self {
+        Self {
+            id: model.id,
+            events: split_events(&model.events),
+            signed: model.secret.is_some(),
+            url: model.url,
+            create_time: model.create_time,
+        }
+    }
+}
+
+/// 查询通过管理接口注册的 Webhook，不包括配置文件中的地址
+#[utoipa::path(
+    get,
+    path = "/capi/admin/webhook",
+    responses(
+        (status = 200, description = "成功", body = WebhookListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn list_webhooks(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ApiResult<Vec<WebhookResp>> {
+    let webhooks = WebhookService::new(&db).list().await?;
+    webhooks
+        .into_iter()
+        .map(WebhookResp::from)
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 注册 Webhook，需要在配置中启用 `webhook.enabled` 才会收到回调
+#[utoipa::path(
+    post,
+    path = "/capi/admin/webhook",
+    request_body = WebhookReq,
+    responses(
+        (status = 200, description = "成功", body = WebhookData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn create_webhook(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<WebhookReq>>,
+) -> ApiResult<WebhookResp> {
+    let endpoint = WebhookEndpoint {
+        url: req.url,
+        secret: req.secret,
+        events: req.events,
+    };
+    let webhook = WebhookService::new(&db)
+        .create(claims.uid, &endpoint)
+        .await?;
+    tracing::info!(uid = claims.uid, id = webhook.id, url = %webhook.url, "Webhook registered by admin.");
+    WebhookResp::from(webhook).to_api_data()
+}
+
+/// 删除 Webhook 请求
+#[derive(Debug, Validate, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookIdReq {
+    /// Webhook ID
+    pub id: u64,
+}
+
+/// 删除 Webhook，尚未投递的事件不再投递
+#[utoipa::path(
+    delete,
+    path = "/capi/admin/webhook",
+    params(WebhookIdReq),
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_webhook(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Query(req)): Valid<Query<WebhookIdReq>>,
+) -> ApiResult<()> {
+    if !WebhookService::new(&db).delete(req.id).await? {
+        return ApiError::business_err(ErrorCode::WebhookNotFound, "Webhook 不存在");
+    }
+    tracing::info!(uid = claims.uid, id = req.id, "Webhook deleted by admin.");
+    ApiValue::success()
+}
+
+/// 创建 API Key 请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyReq {
+    /// 名称，如调用方的服务名
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    /// 授权范围
+    #[validate(length(min = 1))]
+    pub scopes: Vec<ApiScope>,
+}
+
+/// API Key，不返回哈希
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyResp {
+    /// ID
+    pub id: u64,
+    /// 名称
+    pub name: String,
+    /// Key 的前缀，用于辨认
+    pub prefix: String,
+    /// 完整的 Key，只在创建时返回一次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// 授权范围
+    pub scopes: Vec<ApiScope>,
+    /// 创建的管理员 uid
+    pub uid: i64,
+    /// 创建时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+}
+
+impl From<api_key::Model> for ApiKeyResp {
+    fn from(model: api_key::Model) -> Self {
+        Self {
+            id: model.id,
+            scopes: split_scopes(&model.scopes),
+            name: model.name,
+            prefix: model.prefix,
+            key: None,
+            uid: model.uid,
+            create_time: model.create_time,
+        }
+    }
+}
+
+/// 查询 API Key
+#[utoipa::path(
+    get,
+    path = "/capi/admin/api_key",
+    responses(
+        (status = 200, description = "成功", body = ApiKeyListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn list_api_keys(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ApiResult<Vec<ApiKeyResp>> {
+    let keys = ApiKeyService::new(&db).list().await?;
+    keys.into_iter()
+        .map(ApiKeyResp::from)
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 创建 API Key，完整的 Key 只在响应中返回一次
+#[utoipa::path(
+    post,
+    path = "/capi/admin/api_key",
+    request_body = ApiKeyReq,
+    responses(
+        (status = 200, description = "成功", body = ApiKeyData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn create_api_key(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<ApiKeyReq>>,
+) -> ApiResult<ApiKeyResp> {
+    let (model, key) = ApiKeyService::new(&db)
+        .create(claims.uid, &req.name, &req.scopes)
+        .await?;
+    tracing::info!(uid = claims.uid, id = model.id, name = %model.name, scopes = %model.scopes, "API key created by admin.");
+    ApiKeyResp {
+        key: Some(key),
+        ..ApiKeyResp::from(model)
+    }
+    .to_api_data()
+}
+
+/// 吊销 API Key 请求
+#[derive(Debug, Validate, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiKeyIdReq {
+    /// Key ID
+    pub id: u64,
+}
+
+/// 吊销 API Key，立即生效
+#[utoipa::path(
+    delete,
+    path = "/capi/admin/api_key",
+    params(ApiKeyIdReq),
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_api_key(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Query(req)): Valid<Query<ApiKeyIdReq>>,
+) -> ApiResult<()> {
+    if !ApiKeyService::new(&db).delete(req.id).await? {
+        return ApiError::business_err(ErrorCode::ApiKeyNotFound, "API Key 不存在");
+    }
+    tracing::warn!(uid = claims.uid, id = req.id, "API key revoked by admin.");
+    ApiValue::success()
+}
+
+/// 查询归档的消息请求
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ArchiveReq {
+    /// 房间 ID
+    pub room_id: i64,
+}
+
+/// 归档的消息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedMessageResp {
+    /// 消息 ID
+    pub id: u64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 发送者 uid
+    pub from_uid: i64,
+    /// 消息内容
+    pub content: String,
+    /// 回复的消息 ID
+    pub reply_msg_id: Option<i64>,
+    /// 消息状态
+    pub status: i32,
+    /// 消息类型
+    pub r#type: Option<i32>,
+    /// 发送时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+    /// 归档时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub archive_time: time::PrimitiveDateTime,
+}
+
+impl From<message_archive::Model> for ArchivedMessageResp {
+    fn from(model: message_archive::Model) -> Self {
+        Self {
+            id: model.id,
+            room_id: model.room_id,
+            from_uid: model.from_uid,
+            content: model.content,
+            reply_msg_id: model.reply_msg_id,
+            status: model.status,
+            r#type: model.r#type,
+            create_time: model.create_time,
+            archive_time: model.archive_time,
+        }
+    }
+}
+
+/// 导出归档到 `message_archive` 表的消息，最新的在前
+#[utoipa::path(
+    get,
+    path = "/capi/admin/archive/message",
+    params(ArchiveReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = ArchivedMessagePageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_archived_messages(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Query(req)): Valid<Query<ArchiveReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<ArchivedMessageResp>> {
+    let select =
+        message_archive::Entity::find().filter(message_archive::Column::RoomId.eq(req.room_id));
+    page.fetch::<_, u64, _>(&db, select, message_archive::Column::Id, |message| {
+        message.id.to_string()
+    })
+    .await?
+    .map(ArchivedMessageResp::from)
+    .to_api_data()
+}
+
+/// 归档到对象存储的文件
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveFileResp {
+    /// ID
+    pub id: u64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 对象存储中的路径
+    pub key: String,
+    /// 预签名的下载地址，在 `oss.presign_expire_secs` 内有效，JSONL 格式，每行一条消息
+    pub url: String,
+    /// 第一条消息 ID
+    pub first_msg_id: u64,
+    /// 最后一条消息 ID
+    pub last_msg_id: u64,
+    /// 消息数
+    pub message_count: u32,
+    /// 归档时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+}
+
+/// 导出归档到对象存储的文件，最新的在前
+///
+/// 归档文件不公开访问，返回短期有效的预签名下载地址
+#[utoipa::path(
+    get,
+    path = "/capi/admin/archive/file",
+    params(ArchiveReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = ArchiveFilePageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_archive_files(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(store): Extension<DynObjectStore>,
+    Extension(config): Extension<OssConfig>,
+    Valid(Query(req)): Valid<Query<ArchiveReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<ArchiveFileResp>> {
+    let select = archive_file::Entity::find().filter(archive_file::Column::RoomId.eq(req.room_id));
+    page.fetch::<_, u64, _>(&db, select, archive_file::Column::Id, |file| {
+        file.id.to_string()
+    })
+    .await?
+    .try_map(|file| {
+        anyhow::Ok(ArchiveFileResp {
+            id: file.id,
+            room_id: file.room_id,
+            url: store.presign_get(&file.object_key, config.presign_expires())?,
+            key: file.object_key,
+            first_msg_id: file.first_msg_id,
+            last_msg_id: file.last_msg_id,
+            message_count: file.message_count,
+            create_time: file.create_time,
+        })
+    })?
+    .to_api_data()
+}
+
+/// 审核队列查询条件
+#[derive(Debug, Default, Validate, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ModerationReq {
+    /// 处理状态：0 待处理，1 保留，2 已删除，为空时查询全部
+    #[validate(range(min = 0, max = 2))]
+    pub status: Option<i32>,
+}
+
+/// 消息举报
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationResp {
+    /// ID
+    pub id: u64,
+    /// 被举报的消息 ID
+    pub msg_id: u64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 消息发送者 uid
+    pub from_uid: i64,
+    /// 消息内容，消息已归档时为空
+    pub content: Option<String>,
+    /// 举报人 uid
+    pub reporter_uid: i64,
+    /// 举报理由
+    pub reason: Option<String>,
+    /// 处理状态：0 待处理，1 保留，2 已删除
+    pub status: i32,
+    /// 处理的管理员 uid
+    pub operator_uid: Option<i64>,
+    /// 举报时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+}
+
+/// 审核队列，最新的在前
+#[utoipa::path(
+    get,
+    path = "/capi/admin/moderation/page",
+    params(ModerationReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = ModerationPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_moderation_page(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(messages): Extension<DynMessageRepo>,
+    Valid(Query(req)): Valid<Query<ModerationReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<ModerationResp>> {
+    let status = req
+        .status
+        .map(ModerationStatus::try_from)
+        .transpose()
+        .map_err(|_| ApiError::business(ErrorCode::InvalidParam, "处理状态错误"))?;
+    let page = page
+        .fetch::<_, u64, _>(
+            &db,
+            ModerationService::new(&db).queue(status),
+            moderation::Column::Id,
+            |report| report.id.to_string(),
+        )
+        .await?;
+    // 已删除的消息也需要展示内容
+    let msg_ids: Vec<u64> = page.list.iter().map(|report| report.msg_id).collect();
+    let contents: BTreeMap<u64, String> = messages
+        .find_by_ids(&msg_ids)
+        .await?
+        .into_iter()
+        .map(|message| (message.id, message.content))
+        .collect();
+    page.map(|report| ModerationResp {
+        id: report.id,
+        msg_id: report.msg_id,
+        room_id: report.room_id,
+        from_uid: report.from_uid,
+        content: contents.get(&report.msg_id).cloned(),
+        reporter_uid: report.reporter_uid,
+        reason: report.reason,
+        status: report.status,
+        operator_uid: report.operator_uid,
+        create_time: report.create_time,
+    })
+    .to_api_data()
+}
+
+/// 处理举报请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationIdReq {
+    /// 举报 ID
+    pub id: u64,
+}
+
+/// 审核通过，保留消息，同一消息的其他待处理举报一起处理
+#[utoipa::path(
+    post,
+    path = "/capi/admin/moderation/approve",
+    request_body = ModerationIdReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn approve_moderation(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<ModerationIdReq>>,
+) -> ApiResult<()> {
+    let report = find_pending_report(&db, req.id).await?;
+    let resolved = ModerationService::new(&db)
+        .resolve(report.msg_id, ModerationStatus::Kept, claims.uid)
+        .await?;
+    tracing::info!(uid = claims.uid, msg_id = report.msg_id, %resolved, "Reported message kept.");
+    ApiValue::success()
+}
+
+/// 删除被举报的消息，同一消息的其他待处理举报一起处理，并向房间推送撤回通知
+#[utoipa::path(
+    post,
+    path = "/capi/admin/moderation/delete",
+    request_body = ModerationIdReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_moderation(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<ModerationIdReq>>,
+) -> ApiResult<()> {
+    let report = find_pending_report(&db, req.id).await?;
+    let (msg_id, operator_uid) = (report.msg_id, claims.uid);
+    let resolved = with_txn(&db, |txn| {
+        Box::pin(async move {
+            MessageRepo::soft_delete(txn, msg_id).await?;
+            ModerationService::new(txn)
+                .resolve(msg_id, ModerationStatus::Deleted, operator_uid)
+                .await
+        })
+    })
+    .await?;
+    tracing::warn!(uid = claims.uid, %msg_id, %resolved, "Reported message deleted.");
+
+    // 单聊只推送给双方，群聊推送给成员，消息已删除，推送失败时不影响接口返回
+    let receivers = match RoomService::new(&db)
+        .find_single_by_room(report.room_id)
+        .await?
+    {
+        Some(room_friend) => Some(vec![room_friend.uid1, room_friend.uid2]),
+        None => GroupService::new(&db).receivers(report.room_id).await?,
+    };
+    let event = RecallEvent {
+        recall: MsgRecall {
+            msg_id: msg_id as i64,
+            room_id: report.room_id,
+            recall_uid: claims.uid,
+        },
+        receivers,
+    };
+    let result = match producer {
+        Some(Extension(producer)) => mq::send_json(producer.as_ref(), RECALL_TOPIC, &event)
+            .await
+            .map(|_| ()),
+        None => push_recall(&session_manager, event).map(|_| ()),
+    };
+    if let Err(error) = result {
+        tracing::error!(%msg_id, %error, "Failed to publish message recall.");
+    }
+    ApiValue::success()
+}
+
+/// 查询待处理的举报
+async fn find_pending_report(
+    db: &DatabaseConnection,
+    id: u64,
+) -> Result<moderation::Model, ApiError> {
+    match ModerationService::new(db).find(id).await? {
+        Some(report) if report.status == ModerationStatus::Pending as i32 => Ok(report),
+        _ => Err(ApiError::business(
+            ErrorCode::ModerationNotFound,
+            "举报不存在或已处理",
+        )),
+    }
+}
+
+/// 公众号查询条件
+#[derive(Debug, Default, Validate, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct WxAppQuery {
+    /// 公众号的开发者 ID，为空时使用默认公众号
+    #[validate(length(min = 1, max = 64))]
+    pub app_id: Option<String>,
+}
+
+/// 修改关注欢迎语请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WxWelcomeReq {
+    /// 公众号的开发者 ID，为空时使用默认公众号
+    #[validate(length(min = 1, max = 64))]
+    pub app_id: Option<String>,
+    /// 欢迎文本，为空时不发送
+    #[validate(length(max = 600))]
+    pub text: Option<String>,
+    /// 永久图文素材的 media_id，为空时不发送
+    #[validate(length(max = 128))]
+    pub news_media_id: Option<String>,
+}
+
+/// 关注欢迎语
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WxWelcomeResp {
+    /// 公众号的开发者 ID
+    pub app_id: String,
+    /// 欢迎文本
+    pub text: Option<String>,
+    /// 永久图文素材的 media_id
+    pub news_media_id: Option<String>,
+    /// 最后修改的管理员 uid，未配置时为空
+    pub uid: Option<i64>,
+    /// 修改时间，未配置时为空
+    #[schema(value_type = Option<String>, format = DateTime)]
+    #[serde(default, with = "crate::timestamp::iso8601::option")]
+    pub update_time: Option<time::PrimitiveDateTime>,
+}
+
+impl WxWelcomeResp {
+    fn new(app_id: &str, welcome: Option<wx_welcome::Model>) -> Self {
+        match welcome {
+            Some(welcome) => Self {
+                app_id: welcome.app_id,
+                text: welcome.text,
+                news_media_id: welcome.news_media_id,
+                uid: Some(welcome.uid),
+                update_time: Some(welcome.update_time),
+            },
+            None => Self {
+                app_id: app_id.to_string(),
+                text: None,
+                news_media_id: None,
+                uid: None,
+                update_time: None,
+            },
+        }
+    }
+}
+
+/// 按开发者 ID 选择公众号，为空时使用默认公众号
+fn find_wx_client<'a>(
+    wx_clients: &'a WxClientRegistry,
+    app_id: Option<&str>,
+) -> Result<&'a DynWxApi, ApiError> {
+    match app_id {
+        Some(app_id) => wx_clients
+            .get(app_id)
+            .ok_or_else(|| ApiError::business(ErrorCode::WxAppNotFound, "公众号不存在")),
+        None => Ok(wx_clients.default_client()),
+    }
+}
+
+/// 查询公众号的关注欢迎语
+#[utoipa::path(
+    get,
+    path = "/capi/admin/wx/welcome",
+    params(WxAppQuery),
+    responses(
+        (status = 200, description = "成功", body = WxWelcomeData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_wx_welcome(
+    _admin: Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(wx_clients): Extension<WxClientRegistry>,
+    Valid(Query(query)): Valid<Query<WxAppQuery>>,
+) -> ApiResult<WxWelcomeResp> {
+    let app_id = find_wx_client(&wx_clients, query.app_id.as_deref())?.app_id();
+    let welcome = WelcomeService::new(&db).find(app_id).await?;
+    WxWelcomeResp::new(app_id, welcome).to_api_data()
+}
+
+/// 修改公众号的关注欢迎语，配置了图文素材时先获取素材确认可以发送
+#[utoipa::path(
+    put,
+    path = "/capi/admin/wx/welcome",
+    request_body = WxWelcomeReq,
+    responses(
+        (status = 200, description = "成功", body = WxWelcomeData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn set_wx_welcome(
+    Admin(claims): Admin,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(wx_clients): Extension<WxClientRegistry>,
+    Valid(Json(req)): Valid<Json<WxWelcomeReq>>,
+) -> ApiResult<WxWelcomeResp> {
+    let wx_client = find_wx_client(&wx_clients, req.app_id.as_deref())?;
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+    let (text, news_media_id) = (non_empty(req.text), non_empty(req.news_media_id));
+    if let Some(media_id) = news_media_id.as_deref() {
+        match wx_client.get_news_material(media_id).await {
+            Ok(articles) if !articles.is_empty() => {}
+            Ok(_) => return ApiError::business_err(ErrorCode::InvalidParam, "图文素材没有文章"),
+            Err(error) => {
+                return ApiError::business_err(
+                    ErrorCode::InvalidParam,
+                    format!("获取图文素材失败：{error}"),
+                );
+            }
+        }
+    }
+    let app_id = wx_client.app_id();
+    let welcome = WelcomeService::new(&db)
+        .save(app_id, claims.uid, text, news_media_id)
+        .await?;
+    tracing::info!(uid = claims.uid, %app_id, "Weixin welcome message changed.");
+    WxWelcomeResp::new(app_id, welcome).to_api_data()
+}
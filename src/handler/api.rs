@@ -3,14 +3,21 @@
 use axum::http::StatusCode;
 use std::borrow::Cow;
 use std::mem::size_of;
+use std::str::FromStr;
 
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select,
+};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
-use utoipa::IntoParams;
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
+use crate::handler::valid;
+use crate::i18n;
+
 /// Api 错误的结果
 pub type Result<T> = std::result::Result<T, ApiError>;
 
@@ -32,11 +39,182 @@ pub enum ApiError {
     /// UTF-8
     #[error("UTF8 error: {0}")]
     Utf8(#[from] std::str::Utf8Error),
+    /// 参数校验错误
+    #[error("{}", valid::error_message(.0))]
+    Validation(#[from] validator::ValidationErrors),
+    /// 业务错误
+    #[error("{1}")]
+    Business(ErrorCode, Cow<'static, str>),
     /// 自定义错误
     #[error("Custom error ({0}) : {1}")]
     Custom(StatusCode, Cow<'static, str>),
 }
 
+/// # 错误码
+///
+/// 前端根据错误码区分错误类型，已发布的错误码不能修改含义：
+///
+/// | 范围 | 分类 |
+/// | --- | --- |
+/// | 1xxx | 认证、授权 |
+/// | 2xxx | 用户 |
+/// | 3xxx | 聊天、房间 |
+/// | 4xxx | 好友 |
+/// | 9xxx | 请求、系统 |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ErrorCode {
+    /// 未分类
+    Unknown = 0,
+    /// token 无效或缺失
+    InvalidToken = 1001,
+    /// 没有权限
+    PermissionDenied = 1002,
+    /// 邮箱或密码错误
+    InvalidCredentials = 1003,
+    /// 邮箱已注册
+    EmailTaken = 1004,
+    /// 登录失败次数过多
+    TooManyAttempts = 1005,
+    /// 需要验证码
+    CaptchaRequired = 1006,
+    /// 验证码错误或已过期
+    InvalidCaptcha = 1007,
+    /// 昵称已被占用
+    NameTaken = 2001,
+    /// 改名卡不足
+    NoRenameCard = 2002,
+    /// 用户不存在
+    UserNotFound = 2003,
+    /// 表情包数量已达上限
+    TooManyEmojis = 2004,
+    /// 表情包已存在
+    EmojiExists = 2005,
+    /// 表情包不存在
+    EmojiNotFound = 2006,
+    /// 登录设备不存在
+    DeviceNotFound = 2007,
+    /// 不是房间成员
+    NotRoomMember = 3001,
+    /// 房间不存在
+    RoomNotFound = 3002,
+    /// 已被禁言
+    Muted = 3003,
+    /// 邀请无效或已过期
+    InvalidInvite = 3004,
+    /// 群成员已满
+    GroupFull = 3005,
+    /// 相同客户端消息 ID 的消息正在发送
+    MessageSending = 3006,
+    /// 消息不存在或已删除
+    MessageNotFound = 3007,
+    /// 已经举报过该消息
+    AlreadyReported = 3008,
+    /// 不能添加自己为好友
+    AddSelfAsFriend = 4001,
+    /// 已经是好友
+    AlreadyFriends = 4002,
+    /// 不是好友
+    NotFriends = 4003,
+    /// 好友申请不存在
+    FriendApplyNotFound = 4004,
+    /// 好友申请已审批
+    FriendApplyHandled = 4005,
+    /// 请求参数错误
+    InvalidParam = 9001,
+    /// 请求体过大
+    PayloadTooLarge = 9002,
+    /// 请求超时
+    RequestTimeout = 9003,
+    /// WebSocket 连接数已满
+    TooManyConnections = 9004,
+    /// 单个 IP 的 WebSocket 连接数已满
+    TooManyConnectionsFromIp = 9005,
+    /// WebSocket 连接不存在
+    SessionNotFound = 9006,
+    /// 上传 URL 无效或已过期
+    InvalidUploadUrl = 9007,
+    /// 短链接不存在或已过期
+    ShortLinkNotFound = 9008,
+    /// 服务繁忙，稍后重试
+    ServerBusy = 9009,
+    /// Webhook 不存在
+    WebhookNotFound = 9010,
+    /// API Key 不存在
+    ApiKeyNotFound = 9011,
+    /// 举报不存在或已处理
+    ModerationNotFound = 9012,
+    /// 公众号不存在
+    WxAppNotFound = 9013,
+    /// 数据库错误
+    Database = 9101,
+    /// 缓存错误
+    Cache = 9102,
+    /// 服务内部错误
+    Internal = 9999,
+}
+
+impl ErrorCode {
+    /// 错误码对应的 HTTP 状态码
+    pub fn http_status_code(self) -> StatusCode {
+        match self {
+            Self::InvalidToken | Self::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            Self::PermissionDenied
+            | Self::NotRoomMember
+            | Self::Muted
+            | Self::NotFriends
+            | Self::InvalidUploadUrl => StatusCode::FORBIDDEN,
+            Self::NameTaken
+            | Self::EmailTaken
+            | Self::CaptchaRequired
+            | Self::InvalidCaptcha
+            | Self::NoRenameCard
+            | Self::UserNotFound
+            | Self::TooManyEmojis
+            | Self::EmojiExists
+            | Self::EmojiNotFound
+            | Self::DeviceNotFound
+            | Self::RoomNotFound
+            | Self::MessageNotFound
+            | Self::AlreadyReported
+            | Self::InvalidInvite
+            | Self::GroupFull
+            | Self::AddSelfAsFriend
+            | Self::AlreadyFriends
+            | Self::FriendApplyNotFound
+            | Self::FriendApplyHandled
+            | Self::SessionNotFound
+            | Self::WebhookNotFound
+            | Self::ApiKeyNotFound
+            | Self::ModerationNotFound
+            | Self::WxAppNotFound
+            | Self::InvalidParam => StatusCode::BAD_REQUEST,
+            Self::ShortLinkNotFound => StatusCode::NOT_FOUND,
+            Self::MessageSending => StatusCode::CONFLICT,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            Self::TooManyConnections | Self::ServerBusy => StatusCode::SERVICE_UNAVAILABLE,
+            Self::TooManyConnectionsFromIp | Self::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unknown | Self::Database | Self::Cache | Self::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl From<StatusCode> for ErrorCode {
+    fn from(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => Self::InvalidToken,
+            StatusCode::FORBIDDEN => Self::PermissionDenied,
+            StatusCode::BAD_REQUEST => Self::InvalidParam,
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge,
+            StatusCode::REQUEST_TIMEOUT => Self::RequestTimeout,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 impl From<anyhow::Error> for ApiError {
     fn from(value: anyhow::Error) -> Self {
         Self::Custom(StatusCode::INTERNAL_SERVER_ERROR, value.to_string().into())
@@ -44,6 +222,14 @@ impl From<anyhow::Error> for ApiError {
 }
 
 impl ApiError {
+    /// 构造一个业务错误，HTTP 状态码由错误码决定
+    pub fn business(code: ErrorCode, message: impl Into<Cow<'static, str>>) -> Self {
+        Self::Business(code, message.into())
+    }
+    /// 构造一个业务错误结果
+    pub fn business_err<T>(code: ErrorCode, message: impl Into<Cow<'static, str>>) -> ApiResult<T> {
+        Err(Self::business(code, message))
+    }
     /// 构造一个自定义错误
     pub fn custom(status: StatusCode, message: impl Into<Cow<'static, str>>) -> Self {
         Self::Custom(status, message.into())
@@ -60,20 +246,32 @@ impl ApiError {
         Err(self)
     }
     /// 错误码
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Database(_) => ErrorCode::Database,
+            Self::Redis(_) => ErrorCode::Cache,
+            Self::JWT(_) => ErrorCode::InvalidToken,
+            Self::Utf8(_) | Self::Validation(_) => ErrorCode::InvalidParam,
+            Self::Business(code, _) => *code,
+            Self::Custom(status, _) => ErrorCode::from(*status),
+        }
+    }
+    /// 错误码
     pub fn err_code(&self) -> i32 {
-        0
+        self.code() as i32
     }
-    /// 错误消息
+    /// 错误消息，按当前请求的语言翻译
     pub fn err_msg(&self) -> String {
-        format!("{}", self)
+        i18n::error_message(self.code(), &self.to_string())
     }
     /// 错误码
     pub fn http_status_code(&self) -> StatusCode {
-        if let Self::Custom(status, _) = self {
-            return *status;
+        match self {
+            Self::Custom(status, _) => *status,
+            Self::Business(code, _) => code.http_status_code(),
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
-
-        StatusCode::INTERNAL_SERVER_ERROR
     }
 }
 
@@ -86,6 +284,9 @@ impl Serialize for ApiError {
         map.serialize_entry("success", &false)?;
         map.serialize_entry("errCode", &self.err_code())?;
         map.serialize_entry("errMsg", &self.err_msg())?;
+        if let Self::Validation(errors) = self {
+            map.serialize_entry("details", &valid::field_errors(errors))?;
+        }
         map.end()
     }
 }
@@ -99,6 +300,7 @@ impl IntoResponse for ApiError {
 /// 基础分页器
 #[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct Pager {
     /// 页大小
     #[validate(range(min = 1, max = 100))]
@@ -108,6 +310,17 @@ pub struct Pager {
     pub page_no: usize,
 }
 
+impl Pager {
+    /// 偏移量
+    pub fn offset(&self) -> u64 {
+        (self.page_size * self.page_no.saturating_sub(1)) as u64
+    }
+    /// 条数限制
+    pub fn limit(&self) -> u64 {
+        self.page_size as u64
+    }
+}
+
 impl Default for Pager {
     fn default() -> Self {
         Self {
@@ -117,6 +330,142 @@ impl Default for Pager {
     }
 }
 
+/// 游标分页请求
+///
+/// 首页不传 `cursor`，之后使用上一页返回的 `cursor`
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct CursorPageReq {
+    /// 页大小
+    #[validate(range(min = 1, max = 100))]
+    pub page_size: usize,
+    /// 游标，上一页最后一条记录的位置
+    pub cursor: Option<String>,
+}
+
+impl Default for CursorPageReq {
+    fn default() -> Self {
+        Self {
+            page_size: 50,
+            cursor: None,
+        }
+    }
+}
+
+impl CursorPageReq {
+    /// 解析游标，首页返回 `None`
+    pub fn cursor<V: FromStr>(&self) -> Result<Option<V>> {
+        self.cursor
+            .as_deref()
+            .filter(|cursor| !cursor.is_empty())
+            .map(|cursor| {
+                cursor.parse::<V>().map_err(|_| {
+                    ApiError::business(ErrorCode::InvalidParam, format!("Invalid cursor: {cursor}"))
+                })
+            })
+            .transpose()
+    }
+
+    /// 多查一条用于判断是否为最后一页
+    pub fn fetch_limit(&self) -> u64 {
+        self.page_size as u64 + 1
+    }
+
+    /// 按 `column` 倒序查询游标之后的记录，多查一条用于判断是否为最后一页
+    pub fn select<E, V>(&self, select: Select<E>, column: E::Column) -> Result<Select<E>>
+    where
+        E: EntityTrait,
+        V: FromStr + Into<sea_orm::Value>,
+    {
+        let select = match self.cursor::<V>()? {
+            Some(cursor) => select.filter(column.lt(cursor)),
+            None => select,
+        };
+        Ok(select.order_by_desc(column).limit(self.fetch_limit()))
+    }
+
+    /// 使用 [`CursorPageReq::select`] 查询的结果构造分页响应，`cursor_of` 返回记录的游标
+    pub fn to_resp<T>(
+        &self,
+        mut list: Vec<T>,
+        cursor_of: impl Fn(&T) -> String,
+    ) -> CursorPageResp<T> {
+        let is_last = list.len() <= self.page_size;
+        list.truncate(self.page_size);
+        CursorPageResp {
+            cursor: list.last().map(cursor_of),
+            is_last,
+            list,
+        }
+    }
+
+    /// 按 `column` 倒序游标分页查询
+    pub async fn fetch<E, V, C>(
+        &self,
+        db: &C,
+        select: Select<E>,
+        column: E::Column,
+        cursor_of: impl Fn(&E::Model) -> String,
+    ) -> Result<CursorPageResp<E::Model>>
+    where
+        E: EntityTrait,
+        V: FromStr + Into<sea_orm::Value>,
+        C: ConnectionTrait,
+    {
+        let list = self.select::<E, V>(select, column)?.all(db).await?;
+        Ok(self.to_resp(list, cursor_of))
+    }
+}
+
+/// 游标分页响应
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorPageResp<T> {
+    /// 下一页的游标，没有数据时为空
+    pub cursor: Option<String>,
+    /// 是否为最后一页
+    pub is_last: bool,
+    /// 数据列表
+    pub list: Vec<T>,
+}
+
+impl<T> CursorPageResp<T> {
+    /// 空页
+    pub fn empty() -> Self {
+        Self {
+            cursor: None,
+            is_last: true,
+            list: Vec::new(),
+        }
+    }
+
+    /// 转换数据列表，游标不变
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> CursorPageResp<U> {
+        CursorPageResp {
+            cursor: self.cursor,
+            is_last: self.is_last,
+            list: self.list.into_iter().map(f).collect(),
+        }
+    }
+
+    /// 转换数据列表，游标不变，有一项转换失败时返回错误
+    pub fn try_map<U, E>(
+        self,
+        f: impl FnMut(T) -> std::result::Result<U, E>,
+    ) -> std::result::Result<CursorPageResp<U>, E> {
+        Ok(CursorPageResp {
+            cursor: self.cursor,
+            is_last: self.is_last,
+            list: self
+                .list
+                .into_iter()
+                .map(f)
+                .collect::<std::result::Result<_, _>>()?,
+        })
+    }
+}
+
 /// API 结果
 #[derive(Debug)]
 pub struct ApiValue<T>(T);
@@ -179,7 +528,10 @@ mod tests {
     use axum::http::StatusCode;
     use serde::Serialize;
 
-    use crate::handler::api::{ApiError, ApiValue};
+    use sea_orm::{DbBackend, EntityTrait, QueryTrait};
+
+    use crate::handler::api::{ApiError, ApiValue, CursorPageReq, ErrorCode};
+    use crate::storage::model::message;
 
     #[test]
     fn api_result_serialize() -> anyhow::Result<()> {
@@ -213,4 +565,54 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn error_code() -> anyhow::Result<()> {
+        let error = ApiError::business(ErrorCode::NotRoomMember, "您不是该房间的成员");
+        assert_eq!(error.http_status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            serde_json::to_value(&error)?,
+            serde_json::json!({
+                "success": false,
+                "errCode": 3001,
+                "errMsg": "您不是该房间的成员",
+            })
+        );
+
+        let error = ApiError::custom(StatusCode::UNAUTHORIZED, "Invalid token");
+        assert_eq!(error.err_code(), ErrorCode::InvalidToken as i32);
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_page() -> anyhow::Result<()> {
+        let req = CursorPageReq {
+            page_size: 2,
+            cursor: Some("100".to_string()),
+        };
+        let select =
+            req.select::<message::Entity, u64>(message::Entity::find(), message::Column::Id)?;
+        let sql = select.build(DbBackend::MySql).to_string();
+        assert!(
+            sql.ends_with("WHERE `message`.`id` < 100 ORDER BY `message`.`id` DESC LIMIT 3"),
+            "{sql}"
+        );
+
+        let resp = req.to_resp(vec![99, 98, 97], u64::to_string);
+        assert_eq!(resp.list, vec![99, 98]);
+        assert_eq!(resp.cursor.as_deref(), Some("98"));
+        assert!(!resp.is_last);
+
+        let resp = req.to_resp(vec![99], u64::to_string);
+        assert!(resp.is_last);
+
+        let req = CursorPageReq {
+            page_size: 2,
+            cursor: Some("abc".to_string()),
+        };
+        assert!(req
+            .select::<message::Entity, u64>(message::Entity::find(), message::Column::Id)
+            .is_err());
+        Ok(())
+    }
 }
@@ -1,22 +1,57 @@
 //! # 登录授权相关
 //!
 
-use crate::handler::api::ApiError;
+pub mod api_key;
+pub mod local;
+
+use crate::active::ActiveTracker;
+use crate::cache::Cache;
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::handler::context::RequestContext;
+use crate::handler::ws::push::LoginSuccess;
+use crate::service::black::UserStatus;
+use crate::service::device::{DeviceService, LoginDevice, DEVICE_ID_HEADER};
+use crate::service::role::{Role, RoleService};
+use crate::storage::model::user;
+use crate::storage::repo::{DynUserRepo, UserRepo};
 use axum::extract::FromRequestParts;
-use axum::headers::authorization::Bearer;
-use axum::headers::Authorization;
+use axum::http::header::USER_AGENT;
 use axum::http::request::Parts;
+use axum::http::HeaderMap;
 use axum::http::StatusCode;
-use axum::{async_trait, Extension, RequestPartsExt, TypedHeader};
+use axum::{async_trait, Extension, RequestPartsExt};
+use axum_extra::headers::authorization::Bearer;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use dashmap::DashMap;
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use redis::AsyncCommands;
+use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::net::IpAddr;
 use std::sync::Arc;
 
+/// 吊销记录的键前缀，后接 uid 或 `uid:设备 ID`，值为吊销时间（毫秒）
+pub const REVOKED_KEY: &str = "mallchat:jwt:revoked";
+
+/// 吊销记录在 Redis 中的保留时间（秒）
+///
+/// token 没有过期时间，过期后被踢出用户之前签发的 token 重新有效；被拉黑的用户在认证时检查，不依赖吊销记录
+pub const REVOKED_EXPIRE_SECONDS: u64 = 30 * 24 * 60 * 60;
+
 /// JWT 使用的加解密 KEY
+///
+/// 同时维护已吊销的 token：吊销某个用户后，该用户在吊销之前签发的 token 全部失效；
+/// 吊销某个设备后，该用户在该设备上吊销之前签发的 token 失效。
+/// 吊销记录保存在当前进程中，配置 Redis 后同时写入 Redis，由所有实例共享，重启后不丢失，
+/// 见 [`JwtKeys::with_cache`]、[`JwtKeys::authenticate`]
 #[derive(Clone)]
 pub struct JwtKeys {
     keys: Arc<(EncodingKey, DecodingKey)>,
+    revoked: Arc<DashMap<i64, i64>>,
+    revoked_devices: Arc<DashMap<(i64, String), i64>>,
+    cache: Option<Cache>,
 }
 
 impl TryFrom<&str> for JwtKeys {
@@ -28,11 +63,19 @@ impl TryFrom<&str> for JwtKeys {
                 EncodingKey::from_base64_secret(value)?,
                 DecodingKey::from_base64_secret(value)?,
             )),
+            revoked: Arc::default(),
+            revoked_devices: Arc::default(),
+            cache: None,
         })
     }
 }
 
 impl JwtKeys {
+    /// 吊销记录同时保存到 Redis
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
     /// 加密 Key
     pub fn encoding_key(&self) -> &EncodingKey {
         &self.keys.0
@@ -49,15 +92,96 @@ impl JwtKeys {
             self.encoding_key(),
         )?)
     }
-    /// 验证并获取 Claims
+    /// 验证并获取 Claims，只检查当前进程的吊销记录
     pub fn verify(&self, token: &str) -> Result<Claims, ApiError> {
         // 不对exp字段、过期时间做校验？？？
         // MallChat 为什么不使用标准的 Claims
         let mut validation = Validation::default();
         validation.required_spec_claims = HashSet::new();
         validation.validate_exp = false;
-        Ok(jsonwebtoken::decode(token, self.decoding_key(), &validation)?.claims)
+        let claims: Claims = jsonwebtoken::decode(token, self.decoding_key(), &validation)?.claims;
+        if self.is_revoked(&claims) {
+            return Err(ApiError::business(
+                ErrorCode::InvalidToken,
+                "Token has been revoked",
+            ));
+        }
+        Ok(claims)
+    }
+    /// 验证并获取 Claims，配置 Redis 时同时检查其他实例写入的吊销记录
+    ///
+    /// Redis 不可用时只检查当前进程的吊销记录
+    pub async fn authenticate(&self, token: &str) -> Result<Claims, ApiError> {
+        let claims = self.verify(token)?;
+        let Some(cache) = &self.cache else {
+            return Ok(claims);
+        };
+        match revoked_at(cache, &claims).await {
+            Ok(revoked_at) if revoked_at.is_some_and(|at| claims.create_time <= at) => Err(
+                ApiError::business(ErrorCode::InvalidToken, "Token has been revoked"),
+            ),
+            Ok(_) => Ok(claims),
+            Err(error) => {
+                tracing::warn!(uid = claims.uid, %error, "Failed to check revoked tokens.");
+                Ok(claims)
+            }
+        }
+    }
+    /// 吊销用户当前所有的 token
+    pub async fn revoke_user(&self, uid: i64) -> redis::RedisResult<()> {
+        let now = current_millisecond();
+        self.revoked.insert(uid, now);
+        self.save_revoked(revoked_key(uid, None), now).await
+    }
+    /// 吊销用户在某个设备上当前的 token
+    pub async fn revoke_device(&self, uid: i64, device: &str) -> redis::RedisResult<()> {
+        let now = current_millisecond();
+        self.revoked_devices.insert((uid, device.to_string()), now);
+        self.save_revoked(revoked_key(uid, Some(device)), now).await
+    }
+    async fn save_revoked(&self, key: String, revoked_at: i64) -> redis::RedisResult<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+        let mut connection = cache.connection().await?;
+        connection
+            .set_ex(key, revoked_at, REVOKED_EXPIRE_SECONDS as usize)
+            .await
+    }
+    /// token 是否已被吊销
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let revoked_before = |revoked_at: &i64| claims.create_time <= *revoked_at;
+        self.revoked
+            .get(&claims.uid)
+            .is_some_and(|r| revoked_before(&r))
+            || claims.device.as_ref().is_some_and(|device| {
+                self.revoked_devices
+                    .get(&(claims.uid, device.clone()))
+                    .is_some_and(|r| revoked_before(&r))
+            })
+    }
+}
+
+/// 吊销记录的键
+fn revoked_key(uid: i64, device: Option<&str>) -> String {
+    match device {
+        Some(device) => format!("{REVOKED_KEY}:{uid}:{device}"),
+        None => format!("{REVOKED_KEY}:{uid}"),
+    }
+}
+
+/// Redis 中 token 所属用户和设备最近的吊销时间
+async fn revoked_at(cache: &Cache, claims: &Claims) -> redis::RedisResult<Option<i64>> {
+    let mut keys = vec![revoked_key(claims.uid, None)];
+    if let Some(device) = &claims.device {
+        keys.push(revoked_key(claims.uid, Some(device)));
     }
+    let mut connection = cache.connection().await?;
+    let revoked: Vec<Option<i64>> = redis::cmd("MGET")
+        .arg(&keys)
+        .query_async(&mut connection)
+        .await?;
+    Ok(revoked.into_iter().flatten().max())
 }
 
 /// 存储到 JWT 中的数据
@@ -68,6 +192,9 @@ pub struct Claims {
     pub uid: i64,
     /// 创建时间
     pub create_time: i64,
+    /// 登录设备 ID，见 [`crate::service::device`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
 }
 
 impl From<i64> for Claims {
@@ -75,6 +202,17 @@ impl From<i64> for Claims {
         Self {
             uid,
             create_time: current_millisecond(),
+            device: None,
+        }
+    }
+}
+
+impl Claims {
+    /// 为用户在某个设备上的登录签发
+    pub fn for_device(uid: i64, device: Option<&str>) -> Self {
+        Self {
+            device: device.map(str::to_string),
+            ..Self::from(uid)
         }
     }
 }
@@ -97,10 +235,120 @@ where
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
-            .map_err(|_| ApiError::custom(StatusCode::UNAUTHORIZED, "Invalid token"))?;
-        jwt_keys
-            .verify(bearer.token())
-            .map_err(|_| ApiError::custom(StatusCode::UNAUTHORIZED, "Invalid token"))
+            .map_err(|_| ApiError::business(ErrorCode::InvalidToken, "Invalid token"))?;
+        let claims = jwt_keys
+            .authenticate(bearer.token())
+            .await
+            .map_err(|_| ApiError::business(ErrorCode::InvalidToken, "Invalid token"))?;
+        if let Some(users) = parts.extensions.get::<DynUserRepo>() {
+            ensure_user_not_banned(users.as_ref(), claims.uid).await?;
+        }
+        if let Some(active_tracker) = parts.extensions.get::<ActiveTracker>() {
+            active_tracker.record(claims.uid);
+        }
+        if let Some(context) = parts.extensions.get::<RequestContext>() {
+            context.set_uid(claims.uid);
+        }
+        Ok(claims)
+    }
+}
+
+/// 超级管理员身份
+///
+/// 在 [`Claims`] 的基础上校验用户拥有超级管理员角色
+#[derive(Debug)]
+pub struct Admin(pub Claims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        let claims = Claims::from_request_parts(parts, state).await?;
+        let Extension(db): Extension<DatabaseConnection> =
+            parts.extract_with_state(state).await.map_err(|_| {
+                ApiError::custom(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database not correctly initialized",
+                )
+            })?;
+        if RoleService::new(&db)
+            .has_role(claims.uid, Role::SuperAdmin)
+            .await?
+        {
+            Ok(Admin(claims))
+        } else {
+            Err(ApiError::business(
+                ErrorCode::PermissionDenied,
+                "Permission denied",
+            ))
+        }
+    }
+}
+
+/// 被拉黑的用户不能登录
+pub fn ensure_not_banned(user: &user::Model) -> Result<(), ApiError> {
+    if user.status == Some(UserStatus::Black as i32) {
+        return Err(ApiError::business(
+            ErrorCode::PermissionDenied,
+            "用户已被拉黑",
+        ));
+    }
+    Ok(())
+}
+
+/// 已登录的用户被拉黑后不能继续使用之前签发的 token，与吊销记录是否保留无关
+///
+/// 用户不存在时不检查
+pub async fn ensure_user_not_banned(users: &dyn UserRepo, uid: i64) -> Result<(), ApiError> {
+    match users.find_by_id(uid).await? {
+        Some(user) => ensure_not_banned(&user),
+        None => Ok(()),
+    }
+}
+
+/// 为用户在设备 `device` 上签发 token，返回与 WebSocket 推送的登录成功相同的数据
+///
+/// 被拉黑的用户返回错误，见 [`ensure_not_banned`]
+pub async fn login_success(
+    db: &DatabaseConnection,
+    jwt_keys: &JwtKeys,
+    user: user::Model,
+    device: Option<&str>,
+) -> Result<LoginSuccess, ApiError> {
+    ensure_not_banned(&user)?;
+    let uid = user.id as i64;
+    let token = jwt_keys.sign(&Claims::for_device(uid, device))?;
+    let power = RoleService::new(db).has_role(uid, Role::SuperAdmin).await?;
+    Ok(LoginSuccess {
+        uid,
+        avatar: user.avatar,
+        token,
+        name: user.name,
+        power: power as i32,
+    })
+}
+
+/// HTTP 登录请求的设备，设备 ID 来自请求头 [`DEVICE_ID_HEADER`]
+pub fn http_login_device(headers: &HeaderMap, ip: IpAddr) -> LoginDevice {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    LoginDevice::new(
+        header(DEVICE_ID_HEADER),
+        header(USER_AGENT.as_str()),
+        Some(ip),
+    )
+}
+
+/// 记录登录设备，未配置 Redis 时不记录，失败时只输出日志，不影响登录
+pub async fn record_device(cache: Option<&Cache>, uid: i64, device: &LoginDevice) {
+    let Some(cache) = cache else {
+        return;
+    };
+    if let Err(error) = DeviceService::new(cache).record(uid, device).await {
+        tracing::error!(%uid, device = %device.device_id, %error, "Failed to record login device.");
     }
 }
 
@@ -116,7 +364,13 @@ pub fn current_millisecond() -> i64 {
 
 #[cfg(test)]
 mod tests {
-    use crate::handler::auth::{current_millisecond, Claims, JwtKeys};
+    use sea_orm::DatabaseConnection;
+    use time::macros::datetime;
+
+    use crate::handler::api::{ApiError, ErrorCode};
+    use crate::handler::auth::{current_millisecond, login_success, Claims, JwtKeys};
+    use crate::service::black::UserStatus;
+    use crate::storage::model::user;
 
     #[test]
     fn jwt() -> anyhow::Result<()> {
@@ -124,10 +378,83 @@ mod tests {
         let claims = Claims {
             uid: 12,
             create_time: current_millisecond(),
+            device: Some("web".to_string()),
         };
         let token = keys.sign(&claims)?;
         let claims_verified = keys.verify(&token)?;
         assert_eq!(claims, claims_verified);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn revoke() -> anyhow::Result<()> {
+        let keys = JwtKeys::try_from("omOFP+Ejj/r+u4XeHr+KImZNtP0AlNqgvjLe3C5qics=")?;
+        let issued = Claims {
+            uid: 12,
+            create_time: current_millisecond() - 1000,
+            device: None,
+        };
+        let token = keys.sign(&issued)?;
+        let other = keys.sign(&Claims::from(13))?;
+        keys.revoke_user(12).await?;
+        assert!(keys.verify(&token).is_err());
+        assert!(keys.verify(&other).is_ok());
+
+        let reissued = Claims {
+            uid: 12,
+            create_time: current_millisecond() + 1000,
+            device: None,
+        };
+        assert!(keys.verify(&keys.sign(&reissued)?).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_device() -> anyhow::Result<()> {
+        let keys = JwtKeys::try_from("omOFP+Ejj/r+u4XeHr+KImZNtP0AlNqgvjLe3C5qics=")?;
+        let issued = |device: Option<&str>| Claims {
+            create_time: current_millisecond() - 1000,
+            ..Claims::for_device(12, device)
+        };
+        let phone = keys.sign(&issued(Some("phone")))?;
+        let web = keys.sign(&issued(Some("web")))?;
+        let legacy = keys.sign(&issued(None))?;
+        keys.revoke_device(12, "phone").await?;
+        assert!(keys.verify(&phone).is_err());
+        assert!(keys.verify(&web).is_ok());
+        assert!(keys.verify(&legacy).is_ok());
+        let reissued = Claims {
+            create_time: current_millisecond() + 1000,
+            ..Claims::for_device(12, Some("phone"))
+        };
+        assert!(keys.verify(&keys.sign(&reissued)?).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn banned_login() -> anyhow::Result<()> {
+        let keys = JwtKeys::try_from("omOFP+Ejj/r+u4XeHr+KImZNtP0AlNqgvjLe3C5qics=")?;
+        let now = datetime!(2023-06-01 00:00);
+        let user = user::Model {
+            id: 12,
+            name: None,
+            avatar: None,
+            sex: None,
+            open_id: "oUser".to_string(),
+            last_opt_time: now,
+            ip_info: None,
+            item_id: None,
+            status: Some(UserStatus::Black as i32),
+            create_time: now,
+            update_time: now,
+            deleted_at: None,
+        };
+        // 拉黑检查在查询数据库之前，不会签发 token
+        let result = login_success(&DatabaseConnection::Disconnected, &keys, user, None).await;
+        assert!(matches!(
+            result,
+            Err(ApiError::Business(ErrorCode::PermissionDenied, _))
+        ));
+        Ok(())
+    }
 }
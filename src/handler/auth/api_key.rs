@@ -0,0 +1,143 @@
+//! # API Key 认证
+//!
+//! 供外部服务调用开放接口（`/capi/open`），不需要以某个用户的身份登录。
+//! 超级管理员通过 `/capi/admin/api_key` 创建 Key 并指定授权范围 [`ApiScope`]，调用时在请求头
+//! [`API_KEY_HEADER`] 中携带，由 [`ApiKey`] 提取器校验，接口再通过 [`ApiKey::require`] 检查授权范围。
+
+use std::str::FromStr;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::{async_trait, Extension, RequestPartsExt};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::service::api_key::{split_scopes, ApiKeyService};
+
+/// 携带 API Key 的请求头
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// 授权范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    /// 发布系统公告
+    Announcement,
+    /// 查询统计数据
+    Stats,
+}
+
+impl ApiScope {
+    /// 授权范围名
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Announcement => "announcement",
+            Self::Stats => "stats",
+        }
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "announcement" => Ok(Self::Announcement),
+            "stats" => Ok(Self::Stats),
+            other => Err(format!("unknown api scope: {other}")),
+        }
+    }
+}
+
+/// 通过 API Key 认证的调用方
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    /// Key ID
+    pub id: u64,
+    /// 名称
+    pub name: String,
+    /// 创建 Key 的管理员 uid，调用方发布的公告等记录在该用户名下
+    pub uid: i64,
+    /// 授权范围
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiKey {
+    /// 检查授权范围，未授权时返回 [`ErrorCode::PermissionDenied`]
+    pub fn require(&self, scope: ApiScope) -> Result<(), ApiError> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(ApiError::business(
+                ErrorCode::PermissionDenied,
+                format!("API key not authorized for {}", scope.as_str()),
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiKey
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        let Some(key) = parts
+            .headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return Err(ApiError::business(
+                ErrorCode::InvalidToken,
+                "Invalid API key",
+            ));
+        };
+        let key = key.to_string();
+        let Extension(db): Extension<DatabaseConnection> =
+            parts.extract_with_state(state).await.map_err(|_| {
+                ApiError::custom(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database not correctly initialized",
+                )
+            })?;
+        let Some(model) = ApiKeyService::new(&db).find_by_key(&key).await? else {
+            return Err(ApiError::business(
+                ErrorCode::InvalidToken,
+                "Invalid API key",
+            ));
+        };
+        tracing::info!(id = model.id, name = %model.name, "Authenticated by API key.");
+        Ok(Self {
+            id: model.id,
+            name: model.name,
+            uid: model.uid,
+            scopes: split_scopes(&model.scopes),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::api::ErrorCode;
+    use crate::handler::auth::api_key::{ApiKey, ApiScope};
+
+    #[test]
+    fn require() {
+        let key = ApiKey {
+            id: 1,
+            name: "moderation".to_string(),
+            uid: 1,
+            scopes: vec![ApiScope::Stats],
+        };
+        assert!(key.require(ApiScope::Stats).is_ok());
+        assert!(matches!(
+            key.require(ApiScope::Announcement),
+            Err(error) if error.code() == ErrorCode::PermissionDenied
+        ));
+    }
+}
@@ -0,0 +1,385 @@
+//! # 邮箱密码登录
+//!
+//! 没有微信公众号时的登录方式，默认不启用。注册、登录成功后返回与 WebSocket 推送的
+//! [`LoginSuccess`] 相同的数据，客户端保存 token 后通过 WebSocket 的 `Authorize` 请求登录。
+//!
+//! 密码使用 argon2 哈希后保存在 `user_credential` 表中；同一邮箱连续登录失败
+//! [`LocalAuthConfig::max_failures`] 次后锁定 [`LocalAuthConfig::lock_secs`] 秒，计数保存在 Redis 中，
+//! 未配置 Redis 时不限制。启用验证码时，连续失败
+//! [`CaptchaConfig::login_after_failures`](crate::captcha::CaptchaConfig::login_after_failures)
+//! 次后登录需要携带验证码。
+//!
+//! argon2 哈希耗时较长，在阻塞线程池中执行；邮箱不存在时同样验证一次密码，响应时间不会暴露邮箱是否已注册。
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use std::sync::OnceLock;
+
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use redis::AsyncCommands;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set,
+};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::cache::Cache;
+use crate::captcha::Captcha;
+use crate::handler::api::{ApiError, ApiResult, ErrorCode, ToApiData};
+use crate::handler::auth::{
+    ensure_not_banned, http_login_device, login_success, record_device, JwtKeys,
+};
+use crate::handler::captcha::{self, CaptchaAnswer};
+use crate::handler::client_ip::ClientIp;
+use crate::handler::valid::Valid;
+use crate::handler::ws::push::LoginSuccess;
+use crate::service::item::ItemService;
+use crate::storage::model::{user, user_credential};
+use crate::storage::repo::UserRepo;
+use crate::storage::soft_delete::SoftDelete;
+use crate::storage::tx::with_txn;
+use crate::webhook::{UserRegistered, WebhookEvent, Webhooks};
+
+/// 登录失败计数的键前缀，后接邮箱
+const FAILURE_KEY: &str = "mallchat:auth:fail";
+
+/// 邮箱密码登录配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalAuthConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+    /// 锁定前允许连续登录失败的次数
+    #[serde(default = "default::max_failures")]
+    pub max_failures: u64,
+    /// 锁定时间（秒）
+    #[serde(default = "default::lock_secs")]
+    pub lock_secs: u64,
+}
+
+mod default {
+    pub fn max_failures() -> u64 {
+        5
+    }
+
+    pub fn lock_secs() -> u64 {
+        900
+    }
+}
+
+impl Default for LocalAuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_failures: default::max_failures(),
+            lock_secs: default::lock_secs(),
+        }
+    }
+}
+
+/// 邮箱密码登录相关路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/auth",
+        Router::new()
+            .route("/register", post(register))
+            .route("/login", post(login)),
+    )
+}
+
+/// 注册请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterReq {
+    /// 邮箱
+    #[validate(email, length(max = 128))]
+    pub email: String,
+    /// 密码
+    #[validate(length(min = 8, max = 64))]
+    pub password: String,
+}
+
+/// 登录请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginReq {
+    /// 邮箱
+    #[validate(email, length(max = 128))]
+    pub email: String,
+    /// 密码
+    #[validate(length(min = 1, max = 64))]
+    pub password: String,
+    /// 验证码，连续登录失败后需要
+    #[validate]
+    pub captcha: Option<CaptchaAnswer>,
+}
+
+/// 使用邮箱密码注册，并赠送改名卡、注册徽章
+#[utoipa::path(
+    post,
+    path = "/capi/auth/register",
+    request_body = RegisterReq,
+    responses(
+        (status = 200, description = "成功", body = LoginSuccessData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn register(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    cache: Option<Extension<Cache>>,
+    webhooks: Option<Extension<Webhooks>>,
+    Valid(Json(req)): Valid<Json<RegisterReq>>,
+) -> ApiResult<LoginSuccess> {
+    let email = normalize_email(&req.email);
+    let password_hash = hash_password(&req.password).await?;
+    let user = with_txn(&db, |txn| {
+        Box::pin(async move {
+            if find_credential(txn, &email).await?.is_some() {
+                return Err(ApiError::business(ErrorCode::EmailTaken, "邮箱已注册"));
+            }
+            let user = UserRepo::create(txn, &local_open_id(&email)).await?;
+            ItemService::new(txn)
+                .grant_register_items(user.id as i64)
+                .await?;
+            user_credential::ActiveModel {
+                uid: Set(user.id as i64),
+                email: Set(email),
+                password_hash: Set(password_hash),
+                ..Default::default()
+            }
+            .insert(txn)
+            .await?;
+            Ok(user)
+        })
+    })
+    .await?;
+    tracing::info!(uid = %user.id, "Local user registered");
+    if let Some(Extension(webhooks)) = webhooks {
+        webhooks
+            .emit(WebhookEvent::UserRegistered(UserRegistered {
+                uid: user.id as i64,
+            }))
+            .await;
+    }
+    let device = http_login_device(&headers, ip);
+    record_device(cache.as_deref(), user.id as i64, &device).await;
+    login_success(&db, &jwt_keys, user, Some(&device.device_id))
+        .await?
+        .to_api_data()
+}
+
+/// 使用邮箱密码登录
+#[utoipa::path(
+    post,
+    path = "/capi/auth/login",
+    request_body = LoginReq,
+    responses(
+        (status = 200, description = "成功", body = LoginSuccessData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn login(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    Extension(config): Extension<LocalAuthConfig>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    cache: Option<Extension<Cache>>,
+    captcha: Option<Extension<Captcha>>,
+    Valid(Json(req)): Valid<Json<LoginReq>>,
+) -> ApiResult<LoginSuccess> {
+    let email = normalize_email(&req.email);
+    let failures = cache.as_ref().map(|Extension(cache)| Failures {
+        cache: cache.clone(),
+        key: format!("{FAILURE_KEY}:{email}"),
+        config: &config,
+    });
+    if let Some(failures) = &failures {
+        let count = failures.count().await?;
+        if count >= config.max_failures {
+            return ApiError::business_err(
+                ErrorCode::TooManyAttempts,
+                "登录失败次数过多，请稍后再试",
+            );
+        }
+        if let Some(Extension(captcha)) = &captcha {
+            if captcha.login_required(count) {
+                captcha::check(captcha, req.captcha.as_ref()).await?;
+            }
+        }
+    }
+
+    let credential = find_credential(&db, &email).await?;
+    let password_hash = credential
+        .as_ref()
+        .map(|credential| credential.password_hash.as_str());
+    let verified = verify_password(&req.password, password_hash).await?;
+    let user = match credential {
+        Some(credential) if verified => {
+            user::Entity::find_alive()
+                .filter(user::Column::Id.eq(credential.uid as u64))
+                .one(&db)
+                .await?
+        }
+        _ => None,
+    };
+    let Some(user) = user else {
+        if let Some(failures) = &failures {
+            failures.increase().await?;
+        }
+        return ApiError::business_err(ErrorCode::InvalidCredentials, "邮箱或密码错误");
+    };
+    ensure_not_banned(&user)?;
+    if let Some(failures) = &failures {
+        failures.clear().await?;
+    }
+    tracing::info!(uid = %user.id, "Local user logged in");
+    let device = http_login_device(&headers, ip);
+    record_device(cache.as_deref(), user.id as i64, &device).await;
+    login_success(&db, &jwt_keys, user, Some(&device.device_id))
+        .await?
+        .to_api_data()
+}
+
+/// 同一邮箱的连续登录失败计数
+struct Failures<'a> {
+    cache: Cache,
+    key: String,
+    config: &'a LocalAuthConfig,
+}
+
+impl Failures<'_> {
+    async fn count(&self) -> anyhow::Result<u64> {
+        let mut connection = self.cache.connection().await?;
+        let count: Option<u64> = connection.get(&self.key).await?;
+        Ok(count.unwrap_or_default())
+    }
+
+    async fn increase(&self) -> anyhow::Result<()> {
+        let mut connection = self.cache.connection().await?;
+        let count: u64 = connection.incr(&self.key, 1).await?;
+        if count == 1 || count >= self.config.max_failures {
+            connection
+                .expire::<_, ()>(&self.key, self.config.lock_secs as usize)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn clear(&self) -> anyhow::Result<()> {
+        let mut connection = self.cache.connection().await?;
+        connection.del::<_, ()>(&self.key).await?;
+        Ok(())
+    }
+}
+
+async fn find_credential<C: ConnectionTrait>(
+    db: &C,
+    email: &str,
+) -> Result<Option<user_credential::Model>, DbErr> {
+    user_credential::Entity::find()
+        .filter(user_credential::Column::Email.eq(email))
+        .one(db)
+        .await
+}
+
+/// 去掉首尾空白并转为小写，同一邮箱只能注册一次
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// 邮箱注册的用户没有微信 openid，使用邮箱的哈希代替，保证 `user.open_id` 唯一
+pub fn local_open_id(email: &str) -> String {
+    let digest = Sha1::new()
+        .chain_update("local:")
+        .chain_update(email)
+        .finalize();
+    hex::encode(digest)[..32].to_string()
+}
+
+/// 使用 argon2 和随机盐哈希密码，返回 PHC 格式的字符串
+pub async fn hash_password(password: &str) -> anyhow::Result<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash(&password)).await?
+}
+
+/// 验证密码，哈希格式错误时视为不匹配
+///
+/// 没有哈希（邮箱未注册）时验证一个随机密码的哈希，耗时与正常验证相同，结果总是不匹配
+pub async fn verify_password(password: &str, password_hash: Option<&str>) -> anyhow::Result<bool> {
+    let password = password.to_string();
+    let password_hash = password_hash.map(str::to_string);
+    let verified = tokio::task::spawn_blocking(move || match password_hash {
+        Some(password_hash) => verify(&password, &password_hash),
+        None => {
+            verify(&password, dummy_hash());
+            false
+        }
+    })
+    .await?;
+    Ok(verified)
+}
+
+fn hash(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {e}"))
+}
+
+fn verify(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// 与真实密码使用相同参数的哈希，第一次使用时生成
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        let password = SaltString::generate(&mut OsRng);
+        hash(password.as_str()).unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::auth::local::{
+        dummy_hash, hash_password, local_open_id, normalize_email, verify_password,
+    };
+
+    #[tokio::test]
+    async fn password() -> anyhow::Result<()> {
+        let hash = hash_password("correct horse").await?;
+        assert!(hash.starts_with("$argon2"));
+        assert_ne!(hash, hash_password("correct horse").await?);
+        assert!(verify_password("correct horse", Some(&hash)).await?);
+        assert!(!verify_password("wrong horse", Some(&hash)).await?);
+        assert!(!verify_password("correct horse", Some("not a hash")).await?);
+        assert!(!verify_password("correct horse", None).await?);
+        assert!(dummy_hash().starts_with("$argon2"));
+        Ok(())
+    }
+
+    #[test]
+    fn email() {
+        let email = normalize_email(" Alice@Example.COM ");
+        assert_eq!(email, "alice@example.com");
+        assert_eq!(local_open_id(&email).len(), 32);
+        assert_eq!(local_open_id(&email), local_open_id("alice@example.com"));
+        assert_ne!(local_open_id(&email), local_open_id("bob@example.com"));
+    }
+}
@@ -0,0 +1,74 @@
+//! # 图形验证码相关接口
+//!
+
+use axum::routing::get;
+use axum::{Extension, Router};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::captcha::Captcha;
+use crate::handler::api::{ApiError, ApiResult, ErrorCode, ToApiData};
+
+/// 图形验证码相关路由
+pub fn route() -> Router {
+    Router::new().route("/capi/captcha", get(get_captcha))
+}
+
+/// 验证码
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptchaResp {
+    /// 提交答案时携带
+    pub nonce: String,
+    /// 图片，`data:image/svg+xml;base64,` 格式
+    pub image: String,
+}
+
+/// 验证码答案，需要验证码的请求中携带
+#[derive(Debug, Clone, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptchaAnswer {
+    /// 获取验证码时返回的 nonce
+    #[validate(length(min = 1, max = 64))]
+    pub nonce: String,
+    /// 答案
+    #[validate(length(min = 1, max = 16))]
+    pub answer: String,
+}
+
+/// 获取验证码
+#[utoipa::path(
+    get,
+    path = "/capi/captcha",
+    responses(
+        (status = 200, description = "成功", body = CaptchaData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_captcha(Extension(captcha): Extension<Captcha>) -> ApiResult<CaptchaResp> {
+    let (nonce, image) = captcha.issue().await?;
+    CaptchaResp {
+        nonce,
+        image: format!(
+            "data:image/svg+xml;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(image)
+        ),
+    }
+    .to_api_data()
+}
+
+/// 验证请求携带的验证码，未携带或答案错误时返回错误
+pub async fn check(captcha: &Captcha, answer: Option<&CaptchaAnswer>) -> Result<(), ApiError> {
+    let Some(answer) = answer else {
+        return Err(ApiError::business(
+            ErrorCode::CaptchaRequired,
+            "请输入验证码",
+        ));
+    };
+    if !captcha.verify(&answer.nonce, &answer.answer).await? {
+        return Err(ApiError::business(ErrorCode::InvalidCaptcha, "验证码错误"));
+    }
+    Ok(())
+}
@@ -1,16 +1,47 @@
 //! # 聊天相关
 //!
 
+use crate::cache::user_info::{UserInfo, UserInfoCache};
+use crate::cache::Cache;
+use crate::handler::valid::Valid;
 use axum::extract::Query;
-use axum::http::StatusCode;
 use axum::routing::{get, post, put};
 use axum::{Extension, Json, Router};
-use axum_valid::Valid;
-use redis::AsyncCommands;
-use sea_orm::{ConnectionTrait, DatabaseConnection};
+use sea_orm::{DatabaseConnection, DbErr, Set};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::handler::api::{ApiError, ApiResult, ApiValue, Pager, ToApiData};
+use crate::active::ActiveStatus;
+use crate::handler::api::{
+    ApiError, ApiResult, ApiValue, CursorPageReq, CursorPageResp, ErrorCode, Pager, ToApiData,
+};
 use crate::handler::auth::Claims;
+use crate::handler::client_ip::ClientIp;
+use crate::handler::ws::push::{SystemNotice, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::i18n;
+use crate::ip::{IpInfo, IpTracker};
+use crate::mq::message::{MessageEvent, MESSAGE_TOPIC};
+use crate::mq::{self, DynProducer};
+use crate::service::client_msg::{ClientMsgService, ClientMsgState};
+use crate::service::group::{is_muted, GroupService, GLOBAL_ROOM_ID};
+use crate::service::hot_room::{self, HotRoomService};
+use crate::service::message::MessagePageService;
+use crate::service::moderation::ModerationService;
+use crate::service::room::{RoomFriendStatus, RoomService, RoomType};
+use crate::spam::{SpamAction, SpamGuard, SpamVerdict, SYSTEM_REPORTER_UID};
+use crate::storage::model::{message, room, user};
+use crate::storage::repo::{
+    DynMessageRepo, DynRoomRepo, DynUserRepo, MarkWrite, MessageRepo, ReadCursor, RoomRepo,
+    UserRepo,
+};
+use crate::storage::tx::with_txn;
+use crate::storage::write_behind::WriteBehind;
+use crate::timestamp;
+use crate::url_discover::{UrlInfo, URL_CONTENT_MAP};
+use crate::webhook::{MessageFlagged, WebhookEvent, Webhooks};
 
 /// 聊天相关路由
 pub fn route() -> Router {
@@ -21,51 +52,1433 @@ pub fn route() -> Router {
             .route("/public/member/page", get(get_member_page))
             .route("/public/member/statistic", get(get_member_statistic))
             .route("/public/msg/page", get(get_msg_page))
+            .route("/msg/search", get(search_message))
             .route("/msg", post(send_message))
-            .route("/msg/mark", put(send_message_mark)),
+            .route("/msg/mark", put(send_message_mark))
+            .route("/msg/report", post(report_message))
+            .route("/msg/read", put(read_message))
+            .route("/msg/read/count", get(get_read_count))
+            .route("/msg/read/page", get(get_read_page)),
     )
 }
 
+/// 消息状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MessageStatus {
+    /// 正常
+    Normal = 0,
+    /// 删除
+    Deleted = 1,
+}
+
+/// 消息类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MessageType {
+    /// 普通文本
+    Text = 1,
+    /// 撤回消息
+    Recall = 2,
+    /// 图片
+    Image = 3,
+    /// 文件，额外信息中 `fileName` 为文件名
+    File = 4,
+    /// 语音，额外信息中 `second` 为时长（秒）
+    Sound = 5,
+    /// 视频
+    Video = 6,
+    /// 表情
+    Emoji = 7,
+    /// 系统消息
+    System = 8,
+}
+
+impl TryFrom<i32> for MessageType {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Text,
+            2 => Self::Recall,
+            3 => Self::Image,
+            4 => Self::File,
+            5 => Self::Sound,
+            6 => Self::Video,
+            7 => Self::Emoji,
+            8 => Self::System,
+            value => return Err(value),
+        })
+    }
+}
+
+/// 消息标记类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MarkType {
+    /// 点赞
+    Like = 1,
+    /// 点踩
+    Dislike = 2,
+}
+
+impl MarkType {
+    /// 另一种标记，点赞和点踩互斥
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Like => Self::Dislike,
+            Self::Dislike => Self::Like,
+        }
+    }
+}
+
+impl TryFrom<i32> for MarkType {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Like,
+            2 => Self::Dislike,
+            value => return Err(value),
+        })
+    }
+}
+
+/// 消息标记状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MarkStatus {
+    /// 正常
+    Normal = 0,
+    /// 已取消
+    Cancelled = 1,
+}
+
+/// 消息摘要的最大字符数
+const ABSTRACT_MAX_CHARS: usize = 32;
+
+/// 会话列表显示的消息摘要，如 `你好`、`[图片]`、`[语音] 5s`
+///
+/// 文本消息合并连续的空白字符，超过 [`ABSTRACT_MAX_CHARS`] 个字符时截断
+pub fn message_abstract(message: &message::Model) -> String {
+    let extra = |key: &str| message.extra.as_ref().and_then(|extra| extra.get(key));
+    let r#type = message
+        .r#type
+        .and_then(|r#type| MessageType::try_from(r#type).ok())
+        .unwrap_or(MessageType::Text);
+    let label = match r#type {
+        MessageType::Text | MessageType::System => None,
+        MessageType::Recall => Some("[消息已撤回]".to_string()),
+        MessageType::Image => Some("[图片]".to_string()),
+        MessageType::File => Some(match extra("fileName").and_then(|name| name.as_str()) {
+            Some(name) => format!("[文件] {name}"),
+            None => "[文件]".to_string(),
+        }),
+        MessageType::Sound => Some(match extra("second").and_then(|second| second.as_u64()) {
+            Some(second) => format!("[语音] {second}s"),
+            None => "[语音]".to_string(),
+        }),
+        MessageType::Video => Some("[视频]".to_string()),
+        MessageType::Emoji => Some("[表情]".to_string()),
+    };
+    let text = label.unwrap_or_else(|| {
+        message
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let mut chars = text.chars();
+    let mut summary: String = chars.by_ref().take(ABSTRACT_MAX_CHARS).collect();
+    if chars.next().is_some() {
+        summary.pop();
+        summary.push('…');
+    }
+    summary
+}
+
+/// 发送消息请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 消息内容
+    #[validate(length(min = 1, max = 1024))]
+    pub content: String,
+    /// 回复的消息 ID
+    pub reply_msg_id: Option<i64>,
+    /// 客户端生成的消息 ID，重试时携带相同的 ID 不会重复发送
+    #[validate(length(min = 1, max = 64))]
+    pub client_msg_id: Option<String>,
+}
+
+/// 消息信息
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageResp {
+    /// 消息 ID
+    pub id: u64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 发送者 uid
+    pub from_uid: i64,
+    /// 消息内容
+    pub content: String,
+    /// 回复的消息 ID
+    pub reply_msg_id: Option<i64>,
+    /// 发送时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub send_time: time::PrimitiveDateTime,
+    /// 发送者的 IP 归属地
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_region: Option<String>,
+    /// 消息中链接的预览，键为链接，异步生成后通过消息更新推送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_content_map: Option<BTreeMap<String, UrlInfo>>,
+    /// 发送时携带的客户端消息 ID，只在发送消息的响应和新消息推送中返回，客户端用于替换本地消息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_msg_id: Option<String>,
+}
+
+impl From<message::Model> for MessageResp {
+    fn from(message: message::Model) -> Self {
+        let url_content_map = message
+            .extra
+            .as_ref()
+            .and_then(|extra| extra.get(URL_CONTENT_MAP))
+            .and_then(|map| serde_json::from_value(map.clone()).ok());
+        Self {
+            id: message.id,
+            room_id: message.room_id,
+            from_uid: message.from_uid,
+            content: message.content,
+            reply_msg_id: message.reply_msg_id,
+            send_time: message.create_time,
+            from_region: None,
+            url_content_map,
+            client_msg_id: None,
+        }
+    }
+}
+
+/// 消息的标记统计
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMarkResp {
+    /// 点赞数
+    pub like_count: i64,
+    /// 当前用户是否点赞
+    pub user_like: bool,
+    /// 点踩数
+    pub dislike_count: i64,
+    /// 当前用户是否点踩
+    pub user_dislike: bool,
+}
+
+/// 消息列表中的消息
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessageResp {
+    /// 发送者，用户不存在时为空
+    pub from_user: Option<UserInfo>,
+    /// 消息
+    pub message: MessageResp,
+    /// 标记统计
+    pub mark: MessageMarkResp,
+}
+
+/// 会话信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomResp {
+    /// 房间 ID
+    pub room_id: u64,
+    /// 房间名
+    pub name: String,
+    /// 房间类型 1大群聊 2沸点 3单聊
+    pub r#type: i32,
+    /// 热度分数，随消息数增加、随时间衰减，不是热门房间时为 0
+    #[serde(default)]
+    pub hot_score: f64,
+    /// 最后一条消息的 ID
+    pub last_msg_id: Option<i64>,
+    /// 最后一条消息的摘要，只有群聊返回
+    pub last_msg_abstract: Option<String>,
+}
+
+impl RoomResp {
+    /// 使用房间信息和热度分数创建，单聊的消息摘要不返回
+    pub fn new(room: room::Model, hot_score: f64) -> Self {
+        let last_msg_abstract = if room.r#type == RoomType::Single as i32 {
+            None
+        } else {
+            room.last_msg_abstract
+                .map(|text| i18n::tr(&text).into_owned())
+        };
+        Self {
+            room_id: room.id,
+            name: room.name,
+            r#type: room.r#type,
+            hot_score,
+            last_msg_id: room.last_msg_id,
+            last_msg_abstract,
+        }
+    }
+}
+
+/// 群成员信息
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberResp {
+    /// 用户 ID
+    pub uid: u64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+    /// 是否在线，离开也算在线
+    pub online: bool,
+    /// 在线状态 1在线 2离开 3离线
+    pub active_status: ActiveStatus,
+    /// 最后活跃时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub last_opt_time: time::PrimitiveDateTime,
+}
+
+impl MemberResp {
+    /// 使用用户信息和本实例的在线状态创建
+    pub fn new(user: user::Model, active_status: ActiveStatus) -> Self {
+        Self {
+            uid: user.id,
+            name: user.name,
+            avatar: user.avatar,
+            online: active_status != ActiveStatus::Offline,
+            active_status,
+            last_opt_time: user.last_opt_time,
+        }
+    }
+}
+
 /// 会话列表
-#[utoipa::path(get, path = "/capi/chat/public/room/page", params(Pager))]
+#[utoipa::path(
+    get,
+    path = "/capi/chat/public/room/page",
+    params(Pager),
+    responses(
+        (status = 200, description = "成功", body = RoomPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
 pub async fn get_room_page(
-    Valid(Query(pager)): Valid<Query<Pager>>, // TODO: 这里使用了 Valid 就会导致 swagger 前端不生效
-    Extension(cache): Extension<redis::Client>,
-) -> ApiResult<Option<String>> {
-    tracing::info!(?pager, "get_room_page");
-    let mut connection = cache.get_async_connection().await?;
-    let value: Option<String> = connection.get("some-key").await?;
-    value.to_api_data()
+    Valid(Query(pager)): Valid<Query<Pager>>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    cache: Option<Extension<Cache>>,
+) -> ApiResult<Vec<RoomResp>> {
+    let ranking = match &cache {
+        Some(Extension(cache)) => {
+            HotRoomService::new(cache)
+                .ranking()
+                .await
+                .unwrap_or_else(|error| {
+                    // 热度只影响排序，Redis 不可用时按最后活跃时间排序
+                    tracing::warn!(%error, "Failed to get hot room ranking.");
+                    Vec::new()
+                })
+        }
+        None => Vec::new(),
+    };
+    room_page(rooms.as_ref(), &ranking, pager.offset(), pager.limit())
+        .await?
+        .to_api_data()
+}
+
+/// 热门房间按分数排在前面，其余房间按最后活跃时间排在后面，单聊房间即使有热度也不返回
+async fn room_page(
+    rooms: &dyn RoomRepo,
+    ranking: &[(i64, f64)],
+    offset: u64,
+    limit: u64,
+) -> Result<Vec<RoomResp>, DbErr> {
+    let room_ids: Vec<i64> = ranking.iter().map(|(room_id, _)| *room_id).collect();
+    let groups = rooms
+        .find_by_ids(&room_ids)
+        .await?
+        .into_iter()
+        .filter(|room| room.r#type != RoomType::Single as i32)
+        .collect();
+    let hot = hot_room::rank(groups, ranking);
+    let mut page: Vec<RoomResp> = hot
+        .iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .map(|(room, score)| RoomResp::new(room.clone(), *score))
+        .collect();
+    let remaining = limit.saturating_sub(page.len() as u64);
+    if remaining > 0 {
+        let exclude: Vec<i64> = hot.iter().map(|(room, _)| room.id as i64).collect();
+        let offset = offset.saturating_sub(hot.len() as u64);
+        page.extend(
+            rooms
+                .page(&exclude, offset, remaining)
+                .await?
+                .into_iter()
+                .map(|room| RoomResp::new(room, 0.0)),
+        );
+    }
+    Ok(page)
 }
 
-/// 群成员列表
-#[utoipa::path(get, path = "/capi/chat/public/member/page")]
-pub async fn get_member_page(Extension(db): Extension<DatabaseConnection>) -> ApiResult<u64> {
-    let r = db.execute_unprepared("select 1").await?;
-    r.rows_affected().to_api_data()
+/// 群成员列表，包含在线状态，只有登录用户可以查看
+#[utoipa::path(
+    get,
+    path = "/capi/chat/public/member/page",
+    params(Pager),
+    responses(
+        (status = 200, description = "成功", body = MemberPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_member_page(
+    _claims: Claims,
+    Valid(Query(pager)): Valid<Query<Pager>>,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(session_manager): Extension<SessionManager>,
+) -> ApiResult<Vec<MemberResp>> {
+    users
+        .page(pager.offset(), pager.limit())
+        .await?
+        .into_iter()
+        .map(|user| {
+            let active_status = session_manager.active_status(user.id as i64);
+            MemberResp::new(user, active_status)
+        })
+        .collect::<Vec<_>>()
+        .to_api_data()
 }
 
 /// 群成员人数统计
-#[utoipa::path(get, path = "/capi/chat/public/member/statistic")]
+#[utoipa::path(
+    get,
+    path = "/capi/chat/public/member/statistic",
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
 pub async fn get_member_statistic(_claims: Claims) -> ApiResult<()> {
     ApiValue::success()
 }
 
-/// 消息列表
-#[utoipa::path(get, path = "/capi/chat/public/msg/page")]
-pub async fn get_msg_page() -> ApiResult<()> {
+/// 消息列表请求
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct MessagePageReq {
+    /// 房间 ID
+    pub room_id: i64,
+}
+
+/// 消息列表，最新的在前，游标为上一页最后一条消息的 ID
+///
+/// 全员群聊的消息公开，其他群聊只有成员可以查看，单聊消息只有双方可以查看
+#[utoipa::path(
+    get,
+    path = "/capi/chat/public/msg/page",
+    params(MessagePageReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = ChatMessagePageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_msg_page(
+    claims: Option<Claims>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    cache: Option<Extension<Cache>>,
+    Valid(Query(req)): Valid<Query<MessagePageReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<ChatMessageResp>> {
+    let before = page.cursor::<u64>()?;
+    let uid = claims.map(|claims| claims.uid);
+    let Some(room) = rooms.find_by_id(req.room_id).await? else {
+        return ApiError::business_err(ErrorCode::RoomNotFound, "房间不存在");
+    };
+    let is_member = if room.r#type == RoomType::Single as i32 {
+        match uid {
+            Some(uid) => RoomService::new(&db)
+                .find_single_by_room(req.room_id)
+                .await?
+                .is_some_and(|room_friend| room_friend.uid1 == uid || room_friend.uid2 == uid),
+            None => false,
+        }
+    } else {
+        match uid {
+            Some(uid) => rooms.is_group_member(req.room_id, uid).await?,
+            None => req.room_id == GLOBAL_ROOM_ID,
+        }
+    };
+    if !is_member {
+        return ApiError::business_err(ErrorCode::NotRoomMember, "您不是该房间的成员");
+    }
+    let list = MessagePageService::new(
+        messages.as_ref(),
+        users.as_ref(),
+        cache.as_ref().map(|Extension(cache)| cache),
+    )
+    .page(req.room_id, uid, before, page.fetch_limit())
+    .await?;
+    page.to_resp(list, |message| message.message.id.to_string())
+        .to_api_data()
+}
+
+/// 搜索消息请求
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct MessageSearchReq {
+    /// 关键字，至少两个字符
+    #[validate(length(min = 2, max = 32))]
+    pub keyword: String,
+}
+
+/// 搜索到的消息
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSearchResp {
+    /// 消息
+    pub message: MessageResp,
+    /// 高亮片段，已转义 HTML，关键字使用 `<em>` 包裹
+    pub highlight: String,
+}
+
+/// 高亮片段中关键字前后保留的字符数
+const HIGHLIGHT_CONTEXT: usize = 20;
+
+fn escape_html(text: impl IntoIterator<Item = char>) -> String {
+    let mut escaped = String::new();
+    for c in text {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 生成高亮片段：关键字（不区分大小写）使用 `<em>` 包裹，
+/// 只保留第一个关键字前后 [`HIGHLIGHT_CONTEXT`] 个字符，省略的部分使用 `…` 代替
+pub fn highlight(content: &str, keyword: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let keyword: Vec<char> = keyword.chars().collect();
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while !keyword.is_empty() && i + keyword.len() <= chars.len() {
+        if chars[i..i + keyword.len()]
+            .iter()
+            .zip(&keyword)
+            .all(|(&a, &b)| same(a, b))
+        {
+            matches.push((i, i + keyword.len()));
+            i += keyword.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    let (start, mut end) = match matches.first() {
+        Some(&(first_start, first_end)) => (
+            first_start.saturating_sub(HIGHLIGHT_CONTEXT),
+            (first_end + HIGHLIGHT_CONTEXT).min(chars.len()),
+        ),
+        None => (0, (HIGHLIGHT_CONTEXT * 2).min(chars.len())),
+    };
+    // 不截断关键字
+    if let Some(&(_, e)) = matches.iter().find(|&&(s, e)| s < end && e > end) {
+        end = e;
+    }
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = start;
+    for &(s, e) in matches.iter().filter(|&&(s, _)| s >= start && s < end) {
+        snippet.push_str(&escape_html(chars[position..s].iter().copied()));
+        snippet.push_str("<em>");
+        snippet.push_str(&escape_html(chars[s..e].iter().copied()));
+        snippet.push_str("</em>");
+        position = e;
+    }
+    snippet.push_str(&escape_html(chars[position..end].iter().copied()));
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// 搜索消息
+///
+/// 只搜索当前用户可以访问的房间（全员群聊、加入的群聊和自己的单聊），最新的在前
+#[utoipa::path(
+    get,
+    path = "/capi/chat/msg/search",
+    params(MessageSearchReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = MessageSearchPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn search_message(
+    claims: Claims,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    Valid(Query(req)): Valid<Query<MessageSearchReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<MessageSearchResp>> {
+    let before = page.cursor::<u64>()?;
+    let room_ids = rooms.member_room_ids(claims.uid).await?;
+    let list = messages
+        .search(&room_ids, &req.keyword, before, page.fetch_limit())
+        .await?;
+    page.to_resp(list, |message| message.id.to_string())
+        .map(|message| MessageSearchResp {
+            highlight: highlight(&message.content, &req.keyword),
+            message: MessageResp::from(message),
+        })
+        .to_api_data()
+}
+
+/// 发送消息，携带客户端消息 ID 重试时返回已发送的消息
+///
+/// 启用反垃圾检查时，重复、连续发送或链接过多会依次受到警告、禁言、自动举报的处罚
+#[utoipa::path(
+    post,
+    path = "/capi/chat/msg",
+    request_body = SendMessageReq,
+    responses(
+        (status = 200, description = "成功", body = MessageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_message(
+    claims: Claims,
+    client_ip: Option<ClientIp>,
+    Extension(db): Extension<DatabaseConnection>,
+    ip_tracker: Option<Extension<IpTracker>>,
+    producer: Option<Extension<DynProducer>>,
+    cache: Option<Extension<Cache>>,
+    spam_guard: Option<Extension<SpamGuard>>,
+    session_manager: Option<Extension<SessionManager>>,
+    webhooks: Option<Extension<Webhooks>>,
+    Valid(Json(mut req)): Valid<Json<SendMessageReq>>,
+) -> ApiResult<MessageResp> {
+    if let (Some(ClientIp(ip)), Some(Extension(ip_tracker))) = (client_ip, ip_tracker) {
+        ip_tracker.record(claims.uid, ip);
+    }
+    let Some(room) = RoomRepo::find_by_id(&db, req.room_id).await? else {
+        return ApiError::business_err(ErrorCode::RoomNotFound, "房间不存在");
+    };
+    // 单聊只推送给双方，全员群聊推送给所有在线用户，其他群聊推送给成员
+    let receivers = if room.r#type == RoomType::Single as i32 {
+        let room_friend = RoomService::new(&db)
+            .find_single_by_room(req.room_id)
+            .await?
+            .filter(|room_friend| {
+                room_friend.status == RoomFriendStatus::Normal as i32
+                    && (room_friend.uid1 == claims.uid || room_friend.uid2 == claims.uid)
+            });
+        let Some(room_friend) = room_friend else {
+            return ApiError::business_err(ErrorCode::NotRoomMember, "您不是该房间的成员");
+        };
+        Some(vec![room_friend.uid1, room_friend.uid2])
+    } else {
+        let service = GroupService::new(&db);
+        let member = service.member(req.room_id, claims.uid).await?;
+        if member.is_none() && req.room_id != GLOBAL_ROOM_ID {
+            return ApiError::business_err(ErrorCode::NotRoomMember, "您不是该房间的成员");
+        }
+        let now = timestamp::now();
+        if member.is_some_and(|member| is_muted(&member, now)) {
+            return ApiError::business_err(ErrorCode::Muted, "您已被禁言");
+        }
+        service.receivers(req.room_id).await?
+    };
+    if let Some(remaining) = spam_guard
+        .as_ref()
+        .and_then(|Extension(guard)| guard.muted(claims.uid, req.room_id))
+    {
+        return ApiError::business_err(
+            ErrorCode::Muted,
+            format!("您已被禁言，{} 秒后解除", remaining.as_secs().max(1)),
+        );
+    }
+
+    // 携带客户端消息 ID 时去重，Redis 不可用时不去重
+    let client_msg_id = req.client_msg_id.take();
+    let dedup = match (client_msg_id.as_deref(), cache.as_ref()) {
+        (Some(client_msg_id), Some(Extension(cache))) => {
+            let service = ClientMsgService::new(cache);
+            match service.acquire(claims.uid, client_msg_id).await {
+                Ok(ClientMsgState::Acquired) => Some(service),
+                Ok(ClientMsgState::Pending) => {
+                    return ApiError::business_err(ErrorCode::MessageSending, "消息正在发送");
+                }
+                Ok(ClientMsgState::Sent(id)) => match MessageRepo::find_by_id(&db, id).await? {
+                    Some(message) => {
+                        tracing::info!(uid = claims.uid, %client_msg_id, %id, "Duplicated message ignored.");
+                        return MessageResp {
+                            client_msg_id: Some(client_msg_id.to_string()),
+                            ..MessageResp::from(message)
+                        }
+                        .to_api_data();
+                    }
+                    None => Some(service),
+                },
+                Err(error) => {
+                    tracing::error!(uid = claims.uid, %client_msg_id, %error, "Failed to deduplicate message.");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // 重试的消息已在上面返回，不会重复计入
+    let verdict =
+        spam_guard.and_then(|Extension(guard)| guard.check(claims.uid, req.room_id, &req.content));
+    if let Some(verdict) = verdict {
+        tracing::warn!(uid = claims.uid, room_id = %req.room_id, ?verdict, "Spam message detected.");
+        if verdict.action == Some(SpamAction::Mute) {
+            if let (Some(service), Some(client_msg_id)) = (dedup, client_msg_id.as_deref()) {
+                if let Err(error) = service.release(claims.uid, client_msg_id).await {
+                    tracing::error!(uid = claims.uid, %client_msg_id, %error, "Failed to release client message id.");
+                }
+            }
+            return ApiError::business_err(
+                ErrorCode::Muted,
+                format!("{}，已被禁言", verdict.kind.describe()),
+            );
+        }
+    }
+
+    // 保存消息的同时刷新房间活跃时间和最后一条消息，保证会话列表与消息一致
+    let result = with_txn(&db, |txn| {
+        Box::pin(async move {
+            let message = MessageRepo::create(
+                txn,
+                message::ActiveModel {
+                    room_id: Set(req.room_id),
+                    from_uid: Set(claims.uid),
+                    content: Set(req.content),
+                    reply_msg_id: Set(req.reply_msg_id),
+                    status: Set(MessageStatus::Normal as i32),
+                    r#type: Set(Some(MessageType::Text as i32)),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            RoomRepo::refresh_last_message(txn, &message).await?;
+            Ok::<_, ApiError>(message)
+        })
+    })
+    .await;
+    if let (Some(service), Some(client_msg_id)) = (dedup, client_msg_id.as_deref()) {
+        let recorded = match &result {
+            Ok(message) => {
+                service
+                    .complete(claims.uid, client_msg_id, message.id)
+                    .await
+            }
+            Err(_) => service.release(claims.uid, client_msg_id).await,
+        };
+        if let Err(error) = recorded {
+            tracing::error!(uid = claims.uid, %client_msg_id, %error, "Failed to record client message id.");
+        }
+    }
+    let message = result?;
+    if let Some(Extension(cache)) = &cache {
+        cache.local().invalidate_room(message.room_id).await;
+    }
+    if let Some(verdict) = verdict {
+        punish_spam(
+            &db,
+            session_manager.as_ref().map(|Extension(manager)| manager),
+            webhooks.as_ref().map(|Extension(webhooks)| webhooks),
+            &message,
+            verdict,
+        )
+        .await;
+    }
+
+    // 归属地随用户信息缓存，不需要每条消息都查询数据库
+    let from_region = match &cache {
+        Some(Extension(cache)) => UserInfoCache::new(cache, &db)
+            .get(claims.uid)
+            .await?
+            .and_then(|info| info.region),
+        None => UserRepo::find_by_id(&db, claims.uid)
+            .await?
+            .and_then(|user| IpInfo::from_json(user.ip_info.as_ref()).region()),
+    };
+    let message = MessageResp {
+        from_region,
+        client_msg_id,
+        ..MessageResp::from(message)
+    };
+    // 推送、热度统计等通过消息队列异步执行，消息已保存，发送失败时不影响接口返回
+    if let Some(Extension(producer)) = producer {
+        let event = MessageEvent {
+            message: message.clone(),
+            receivers,
+        };
+        if let Err(error) = mq::send_json(producer.as_ref(), MESSAGE_TOPIC, &event).await {
+            tracing::error!(id = %message.id, %error, "Failed to publish message event.");
+        }
+    }
+    message.to_api_data()
+}
+
+/// 对已发送的垃圾消息执行处罚，失败时只记录日志
+async fn punish_spam(
+    db: &DatabaseConnection,
+    session_manager: Option<&SessionManager>,
+    webhooks: Option<&Webhooks>,
+    message: &message::Model,
+    verdict: SpamVerdict,
+) {
+    match verdict.action {
+        Some(SpamAction::Warn) => {
+            let Some(session_manager) = session_manager else {
+                return;
+            };
+            let notice = WsPush::SystemNotice(SystemNotice {
+                content: i18n::tr(&format!(
+                    "{}，请勿刷屏，继续违规将被禁言",
+                    verdict.kind.describe()
+                ))
+                .into_owned(),
+            });
+            if let Err(error) = session_manager.send_to_user(message.from_uid, &notice) {
+                tracing::error!(uid = message.from_uid, %error, "Failed to send spam warning.");
+            }
+        }
+        Some(SpamAction::Report) => {
+            let reason = format!("自动举报：{}", verdict.kind.describe());
+            match ModerationService::new(db)
+                .report(message, SYSTEM_REPORTER_UID, Some(reason.clone()))
+                .await
+            {
+                Ok(Some(report)) => {
+                    tracing::info!(
+                        msg_id = message.id,
+                        id = report.id,
+                        "Spam message reported."
+                    );
+                    if let Some(webhooks) = webhooks {
+                        webhooks
+                            .emit(WebhookEvent::MessageFlagged(MessageFlagged {
+                                msg_id: message.id,
+                                room_id: message.room_id,
+                                from_uid: message.from_uid,
+                                reporter_uid: SYSTEM_REPORTER_UID,
+                                reason: Some(reason),
+                            }))
+                            .await;
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    tracing::error!(msg_id = message.id, %error, "Failed to report spam message.");
+                }
+            }
+        }
+        Some(SpamAction::Mute) | None => {}
+    }
+}
+
+/// 消息标记动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum MarkAction {
+    /// 确认
+    Confirm = 1,
+    /// 取消
+    Cancel = 2,
+}
+
+/// 消息标记请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMarkReq {
+    /// 消息 ID
+    pub msg_id: u64,
+    /// 标记类型：1 点赞，2 点踩
+    #[validate(range(min = 1, max = 2))]
+    pub mark_type: i32,
+    /// 动作：1 确认，2 取消
+    #[validate(range(min = 1, max = 2))]
+    pub act_type: i32,
+}
+
+/// 查询正常状态的消息，不存在或已删除时返回错误
+async fn find_message(messages: &dyn MessageRepo, id: u64) -> Result<message::Model, ApiError> {
+    match messages.find_by_id(id).await? {
+        Some(message) if message.status == MessageStatus::Normal as i32 => Ok(message),
+        _ => Err(ApiError::business(ErrorCode::MessageNotFound, "消息不存在")),
+    }
+}
+
+/// 校验用户是否可以访问房间：全员群聊、加入的群聊或自己的单聊
+async fn ensure_room_member(rooms: &dyn RoomRepo, uid: i64, room_id: i64) -> Result<(), ApiError> {
+    if rooms.member_room_ids(uid).await?.contains(&room_id) {
+        return Ok(());
+    }
+    Err(ApiError::business(
+        ErrorCode::NotRoomMember,
+        "您不是该房间的成员",
+    ))
+}
+
+/// 消息标记，点赞和点踩互斥，确认一种时取消另一种，只能标记所在房间的消息
+///
+/// 标记先在内存中合并，定时批量写入，消息列表中的标记数最多延迟一个写入周期
+#[utoipa::path(
+    put,
+    path = "/capi/chat/msg/mark",
+    request_body = MessageMarkReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn send_message_mark(
+    claims: Claims,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    write_behind: Option<Extension<WriteBehind>>,
+    Valid(Json(req)): Valid<Json<MessageMarkReq>>,
+) -> ApiResult<()> {
+    let message = find_message(messages.as_ref(), req.msg_id).await?;
+    ensure_room_member(rooms.as_ref(), claims.uid, message.room_id).await?;
+    let mark_type = MarkType::try_from(req.mark_type)
+        .map_err(|_| ApiError::business(ErrorCode::InvalidParam, "标记类型错误"))?;
+    let write = |mark_type: MarkType, status: MarkStatus| MarkWrite {
+        msg_id: message.id as i64,
+        uid: claims.uid,
+        mark_type: mark_type as i32,
+        status: status as i32,
+    };
+    let writes = if req.act_type == MarkAction::Confirm as i32 {
+        vec![
+            write(mark_type, MarkStatus::Normal),
+            write(mark_type.opposite(), MarkStatus::Cancelled),
+        ]
+    } else {
+        vec![write(mark_type, MarkStatus::Cancelled)]
+    };
+    match write_behind {
+        Some(Extension(write_behind)) => writes.into_iter().for_each(|w| write_behind.mark(w)),
+        None => messages.save_marks(&writes).await?,
+    }
+    ApiValue::success()
+}
+
+/// 举报消息请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReportReq {
+    /// 消息 ID
+    pub msg_id: u64,
+    /// 举报理由
+    #[validate(length(min = 1, max = 200))]
+    pub reason: String,
+}
+
+/// 举报消息，进入审核队列等待管理员处理，同一消息只能举报一次
+#[utoipa::path(
+    post,
+    path = "/capi/chat/msg/report",
+    request_body = MessageReportReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn report_message(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    webhooks: Option<Extension<Webhooks>>,
+    Valid(Json(req)): Valid<Json<MessageReportReq>>,
+) -> ApiResult<()> {
+    let message = find_message(messages.as_ref(), req.msg_id).await?;
+    if message.from_uid == claims.uid {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不能举报自己的消息");
+    }
+    ensure_room_member(rooms.as_ref(), claims.uid, message.room_id).await?;
+    let Some(report) = ModerationService::new(&db)
+        .report(&message, claims.uid, Some(req.reason.clone()))
+        .await?
+    else {
+        return ApiError::business_err(ErrorCode::AlreadyReported, "您已经举报过该消息");
+    };
+    tracing::info!(
+        uid = claims.uid,
+        msg_id = message.id,
+        id = report.id,
+        "Message reported."
+    );
+    if let Some(Extension(webhooks)) = webhooks {
+        webhooks
+            .emit(WebhookEvent::MessageFlagged(MessageFlagged {
+                msg_id: message.id,
+                room_id: message.room_id,
+                from_uid: message.from_uid,
+                reporter_uid: claims.uid,
+                reason: Some(req.reason),
+            }))
+            .await;
+    }
     ApiValue::success()
 }
 
-/// 发送消息
-#[utoipa::path(post, path = "/capi/chat/msg", request_body = Pager)]
-pub async fn send_message(Valid(Json(pager)): Valid<Json<Pager>>) -> ApiResult<()> {
-    tracing::info!(?pager, "send_message");
-    ApiError::custom_err(StatusCode::NOT_IMPLEMENTED, "TODO")
+/// 上报阅读进度请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReadReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 已读的最新消息 ID
+    pub msg_id: u64,
 }
 
-/// 消息标记
-#[utoipa::path(put, path = "/capi/chat/msg/mark")]
-pub async fn send_message_mark() -> ApiResult<()> {
+/// 上报所在房间的阅读进度，只会前进不会后退，与标记一样批量写入
+#[utoipa::path(
+    put,
+    path = "/capi/chat/msg/read",
+    request_body = MessageReadReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn read_message(
+    claims: Claims,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    write_behind: Option<Extension<WriteBehind>>,
+    Valid(Json(req)): Valid<Json<MessageReadReq>>,
+) -> ApiResult<()> {
+    let message = find_message(messages.as_ref(), req.msg_id).await?;
+    if message.room_id != req.room_id {
+        return ApiError::business_err(ErrorCode::MessageNotFound, "消息不存在");
+    }
+    ensure_room_member(rooms.as_ref(), claims.uid, req.room_id).await?;
+    let cursor = ReadCursor {
+        uid: claims.uid,
+        room_id: req.room_id,
+        msg_id: message.id,
+    };
+    match write_behind {
+        Some(Extension(write_behind)) => write_behind.read(cursor),
+        None => messages.save_read_cursors(&[cursor]).await?,
+    }
     ApiValue::success()
 }
+
+/// 消息已读信息请求
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct MessageReadInfoReq {
+    /// 消息 ID
+    pub msg_id: u64,
+}
+
+/// 消息已读数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReadCountResp {
+    /// 消息 ID
+    pub msg_id: u64,
+    /// 已读人数，不包括发送者
+    pub read_count: u64,
+    /// 未读人数，房间成员数减去发送者和已读人数
+    pub unread_count: u64,
+}
+
+/// 已读消息的用户
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReaderResp {
+    /// 用户 ID
+    pub uid: i64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+}
+
+/// 查询已读信息的消息，只有发送者可以查看
+async fn own_message(
+    messages: &dyn MessageRepo,
+    uid: i64,
+    msg_id: u64,
+) -> Result<message::Model, ApiError> {
+    let message = find_message(messages, msg_id).await?;
+    if message.from_uid != uid {
+        return Err(ApiError::business(
+            ErrorCode::PermissionDenied,
+            "只能查看自己发送的消息的已读信息",
+        ));
+    }
+    Ok(message)
+}
+
+/// 消息的已读、未读人数（n人已读），按当前房间成员的阅读进度统计，最多延迟一个阅读进度写入周期
+#[utoipa::path(
+    get,
+    path = "/capi/chat/msg/read/count",
+    params(MessageReadInfoReq),
+    responses(
+        (status = 200, description = "成功", body = MessageReadCountData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_read_count(
+    claims: Claims,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(rooms): Extension<DynRoomRepo>,
+    Valid(Query(req)): Valid<Query<MessageReadInfoReq>>,
+) -> ApiResult<MessageReadCountResp> {
+    let message = own_message(messages.as_ref(), claims.uid, req.msg_id).await?;
+    let Some(room) = rooms.find_by_id(message.room_id).await? else {
+        return ApiError::business_err(ErrorCode::RoomNotFound, "房间不存在");
+    };
+    let read_count = messages
+        .count_readers(message.room_id, message.id, message.from_uid)
+        .await?;
+    let unread_count = (room.member_count as u64)
+        .saturating_sub(1)
+        .saturating_sub(read_count);
+    ApiValue::data(MessageReadCountResp {
+        msg_id: message.id,
+        read_count,
+        unread_count,
+    })
+}
+
+/// 已读消息的用户列表，游标为上一页最后一个用户的 uid
+#[utoipa::path(
+    get,
+    path = "/capi/chat/msg/read/page",
+    params(MessageReadInfoReq, CursorPageReq),
+    responses(
+        (status = 200, description = "成功", body = MessageReaderPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_read_page(
+    claims: Claims,
+    Extension(messages): Extension<DynMessageRepo>,
+    Extension(users): Extension<DynUserRepo>,
+    Valid(Query(req)): Valid<Query<MessageReadInfoReq>>,
+    Valid(Query(page)): Valid<Query<CursorPageReq>>,
+) -> ApiResult<CursorPageResp<MessageReaderResp>> {
+    let message = own_message(messages.as_ref(), claims.uid, req.msg_id).await?;
+    let uids = messages
+        .page_readers(
+            message.room_id,
+            message.id,
+            message.from_uid,
+            page.cursor::<i64>()?,
+            page.fetch_limit(),
+        )
+        .await?;
+    let page = page.to_resp(uids, i64::to_string);
+    let users: BTreeMap<i64, user::Model> = users
+        .find_by_ids(&page.list)
+        .await?
+        .into_iter()
+        .map(|user| (user.id as i64, user))
+        .collect();
+    page.map(|uid| {
+        let user = users.get(&uid);
+        MessageReaderResp {
+            uid,
+            name: user.and_then(|user| user.name.clone()),
+            avatar: user.and_then(|user| user.avatar.clone()),
+        }
+    })
+    .to_api_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::handler::valid::Valid;
+    use async_trait::async_trait;
+    use axum::extract::Query;
+    use axum::Extension;
+    use sea_orm::DbErr;
+
+    use crate::handler::api::Pager;
+    use crate::handler::auth::Claims;
+    use crate::handler::chat::{
+        get_member_page, highlight, message_abstract, room_page, MessageType, RoomResp,
+    };
+    use crate::handler::ws::SessionManager;
+    use crate::ip::IpDetail;
+    use crate::storage::model::{message, user};
+    use crate::storage::repo::{RoomRepo, UserRepo};
+    use crate::testing::MemoryRepo;
+
+    struct MockUserRepo(Vec<user::Model>);
+
+    #[async_trait]
+    impl UserRepo for MockUserRepo {
+        async fn find_by_id(&self, uid: i64) -> Result<Option<user::Model>, DbErr> {
+            Ok(self.0.iter().find(|user| user.id as i64 == uid).cloned())
+        }
+
+        async fn find_by_ids(&self, uids: &[i64]) -> Result<Vec<user::Model>, DbErr> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|user| uids.contains(&(user.id as i64)))
+                .cloned()
+                .collect())
+        }
+
+        async fn find_by_open_id(&self, open_id: &str) -> Result<Option<user::Model>, DbErr> {
+            Ok(self.0.iter().find(|user| user.open_id == open_id).cloned())
+        }
+
+        async fn find_by_name(&self, name: &str) -> Result<Option<user::Model>, DbErr> {
+            Ok(self
+                .0
+                .iter()
+                .find(|user| user.name.as_deref() == Some(name))
+                .cloned())
+        }
+
+        async fn page(&self, offset: u64, limit: u64) -> Result<Vec<user::Model>, DbErr> {
+            Ok(self
+                .0
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn create(&self, _open_id: &str) -> Result<user::Model, DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn update_name(&self, _uid: i64, _name: &str) -> Result<(), DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn update_avatar(&self, _uid: i64, _avatar: &str) -> Result<(), DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn update_ip(&self, _uid: i64, _detail: &IpDetail) -> Result<bool, DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn refresh_active_time(&self, _uids: &[i64]) -> Result<(), DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn soft_delete(&self, _uid: i64) -> Result<bool, DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+
+        async fn restore(&self, _uid: i64) -> Result<bool, DbErr> {
+            Err(DbErr::Custom("read only".to_string()))
+        }
+    }
+
+    fn user(id: u64) -> user::Model {
+        let now = time::macros::datetime!(2023-06-01 00:00:00);
+        user::Model {
+            id,
+            name: Some(format!("user{id}")),
+            avatar: None,
+            sex: None,
+            open_id: format!("open_id_{id}"),
+            last_opt_time: now,
+            ip_info: None,
+            item_id: None,
+            status: None,
+            create_time: now,
+            update_time: now,
+            deleted_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn member_page() -> anyhow::Result<()> {
+        let users = MockUserRepo((1..=3).map(user).collect());
+        let pager = Pager {
+            page_size: 2,
+            page_no: 2,
+        };
+        let page = get_member_page(
+            Claims::from(1),
+            Valid(Query(pager)),
+            Extension(Arc::new(users)),
+            Extension(SessionManager::default()),
+        )
+        .await?;
+
+        let page = serde_json::to_value(page)?;
+        assert_eq!(page["data"][0]["uid"], 3);
+        assert_eq!(page["data"][0]["name"], "user3");
+        assert_eq!(page["data"][0]["online"], false);
+        assert_eq!(page["data"][0]["activeStatus"], 3);
+        assert_eq!(page["data"].as_array().map(Vec::len), Some(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hot_rooms_first() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        for name in ["a", "b", "c", "d"] {
+            repo.add_room(name, 1);
+        }
+        // 房间 9 不存在，跳过
+        let ranking = [(9, 8.0), (3, 2.5), (2, 1.0)];
+
+        let page = |offset, limit| {
+            let repo = &repo;
+            async move {
+                let page = room_page(repo, &ranking, offset, limit).await?;
+                anyhow::Ok(
+                    page.into_iter()
+                        .map(|room| (room.room_id, room.hot_score))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        assert_eq!(page(0, 3).await?, vec![(3, 2.5), (2, 1.0), (4, 0.0)]);
+        assert_eq!(page(1, 2).await?, vec![(2, 1.0), (4, 0.0)]);
+        assert_eq!(page(3, 2).await?, vec![(1, 0.0)]);
+        assert_eq!(room_page(&repo, &[], 0, 10).await?.len(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn single_room_abstract_hidden() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        let group = repo.add_room("a", 1);
+        let single = repo.add_room("", 3);
+        for (id, room_id) in [(1, group), (2, single)] {
+            repo.refresh_last_message(&message::Model {
+                id,
+                room_id,
+                from_uid: 1,
+                content: "悄悄话".to_string(),
+                reply_msg_id: None,
+                status: 0,
+                gap_count: None,
+                r#type: Some(MessageType::Text as i32),
+                extra: None,
+                create_time: time::PrimitiveDateTime::MIN,
+                update_time: time::PrimitiveDateTime::MIN,
+                deleted_at: None,
+            })
+            .await?;
+        }
+
+        // 单聊即使出现在热度排行中也不返回
+        let page = room_page(&repo, &[(single, 9.0), (group, 1.0)], 0, 10).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].room_id, group as u64);
+        assert_eq!(page[0].last_msg_abstract.as_deref(), Some("悄悄话"));
+
+        let rooms = RoomRepo::find_by_ids(&repo, &[single]).await?;
+        let resp = RoomResp::new(rooms[0].clone(), 0.0);
+        assert_eq!(resp.last_msg_abstract, None);
+        Ok(())
+    }
+
+    #[test]
+    fn abstracts() {
+        let message =
+            |r#type: MessageType, content: &str, extra: Option<serde_json::Value>| message::Model {
+                id: 1,
+                room_id: 1,
+                from_uid: 1,
+                content: content.to_string(),
+                reply_msg_id: None,
+                status: 0,
+                gap_count: None,
+                r#type: Some(r#type as i32),
+                extra,
+                create_time: time::PrimitiveDateTime::MIN,
+                update_time: time::PrimitiveDateTime::MIN,
+                deleted_at: None,
+            };
+        assert_eq!(
+            message_abstract(&message(MessageType::Text, "@张三  你好\n明天见", None)),
+            "@张三 你好 明天见"
+        );
+        let long = message(MessageType::Text, &"啊".repeat(40), None);
+        assert_eq!(message_abstract(&long), format!("{}…", "啊".repeat(31)));
+        assert_eq!(
+            message_abstract(&message(
+                MessageType::Image,
+                "https://example.com/a.png",
+                None
+            )),
+            "[图片]"
+        );
+        let sound = message(
+            MessageType::Sound,
+            "",
+            Some(serde_json::json!({ "second": 5 })),
+        );
+        assert_eq!(message_abstract(&sound), "[语音] 5s");
+        assert_eq!(
+            message_abstract(&message(MessageType::Recall, "你好", None)),
+            "[消息已撤回]"
+        );
+    }
+
+    #[test]
+    fn highlight_snippet() {
+        assert_eq!(
+            highlight("Hello <b>World</b> hello!", "hello"),
+            "<em>Hello</em> &lt;b&gt;World&lt;/b&gt; <em>hello</em>!"
+        );
+        let content = format!("{}抹茶聊天{}", "啊".repeat(30), "哦".repeat(30));
+        assert_eq!(
+            highlight(&content, "抹茶"),
+            format!("…{}<em>抹茶</em>聊天{}…", "啊".repeat(20), "哦".repeat(18))
+        );
+        // 关键字跨越片段末尾时不截断
+        let content = format!("ab{}ab", "-".repeat(19));
+        assert_eq!(
+            highlight(&content, "ab"),
+            format!("<em>ab</em>{}<em>ab</em>", "-".repeat(19))
+        );
+        assert_eq!(highlight("没有匹配", "抹茶"), "没有匹配");
+    }
+}
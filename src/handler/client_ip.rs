@@ -0,0 +1,151 @@
+//! # 客户端 IP
+//!
+//! 部署在 nginx 等反向代理之后时，TCP 连接的对端是代理服务器。
+//! 只有对端是可信代理时才从 `X-Forwarded-For`、`X-Real-IP` 中解析真实的客户端 IP，避免伪造。
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::{async_trait, Extension, RequestPartsExt};
+use ipnet::IpNet;
+
+use crate::handler::api::ApiError;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_REAL_IP: &str = "x-real-ip";
+
+/// 可信的反向代理
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Arc<Vec<IpNet>>,
+}
+
+impl TrustedProxies {
+    /// 使用 IP 或 CIDR（如 `10.0.0.0/8`）列表构造
+    pub fn new<S: AsRef<str>>(proxies: &[S]) -> anyhow::Result<Self> {
+        let networks = proxies
+            .iter()
+            .map(|proxy| {
+                let proxy = proxy.as_ref().trim();
+                match proxy.parse::<IpAddr>() {
+                    Ok(ip) => Ok(IpNet::from(ip)),
+                    Err(_) => proxy
+                        .parse::<IpNet>()
+                        .map_err(|e| anyhow::anyhow!("invalid trusted proxy {proxy}: {e}")),
+                }
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            networks: Arc::new(networks),
+        })
+    }
+
+    /// 是否为可信代理
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// 解析客户端 IP
+    ///
+    /// 从右向左遍历 `X-Forwarded-For`，跳过可信代理，第一个不可信的地址即为客户端；
+    /// 没有 `X-Forwarded-For` 时使用 `X-Real-IP`；对端不是可信代理时直接使用对端地址
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        let forwarded: Vec<IpAddr> = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect();
+        if !forwarded.is_empty() {
+            return forwarded
+                .iter()
+                .rev()
+                .find(|ip| !self.is_trusted(**ip))
+                // 全部是可信代理时使用最早的地址
+                .or(forwarded.first())
+                .copied()
+                .unwrap_or(peer);
+        }
+        headers
+            .get(X_REAL_IP)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+}
+
+/// 客户端 IP 提取器
+///
+/// 依赖 `into_make_service_with_connect_info::<SocketAddr>()` 提供的对端地址，
+/// 以及可选的 [`TrustedProxies`] Extension，未提供时不信任任何代理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, ApiError> {
+        let ConnectInfo(peer) = parts
+            .extract::<ConnectInfo<SocketAddr>>()
+            .await
+            .map_err(|_| {
+                ApiError::custom(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Connect info not correctly initialized",
+                )
+            })?;
+        let proxies = parts
+            .extract::<Extension<TrustedProxies>>()
+            .await
+            .map(|Extension(proxies)| proxies)
+            .unwrap_or_default();
+        Ok(ClientIp(proxies.resolve(peer.ip(), &parts.headers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use axum::http::HeaderMap;
+
+    use crate::handler::client_ip::TrustedProxies;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().expect("ip")
+    }
+
+    #[test]
+    fn resolve() -> anyhow::Result<()> {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8", "192.168.1.1"])?;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2, 10.0.0.2".parse()?);
+
+        // 对端不可信时忽略请求头
+        assert_eq!(proxies.resolve(ip("3.3.3.3"), &headers), ip("3.3.3.3"));
+        // 伪造的 1.1.1.1 被忽略，使用最后一个不可信的地址
+        assert_eq!(proxies.resolve(ip("192.168.1.1"), &headers), ip("2.2.2.2"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "4.4.4.4".parse()?);
+        assert_eq!(proxies.resolve(ip("10.1.2.3"), &headers), ip("4.4.4.4"));
+        assert_eq!(
+            proxies.resolve(ip("10.1.2.3"), &HeaderMap::new()),
+            ip("10.1.2.3")
+        );
+
+        assert!(TrustedProxies::new(&["not an ip"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! # 请求上下文
+//!
+//! 每个 HTTP 请求分配一个请求 ID，网关或客户端携带合法的 `X-Request-Id` 时沿用。
+//! 请求 ID 和认证后的 uid 记录到请求的 tracing span 中，并通过响应头 `X-Request-Id`、`X-Uid` 返回。
+//!
+//! 处理请求期间 [`RequestContext`] 保存在 task-local 中：
+//!
+//! - [`mq::send_json`](crate::mq::send_json) 发布事件时附加 `requestId` 字段，
+//!   [`mq::subscribe`](crate::mq::subscribe) 处理事件时恢复，后续发布的事件继续携带；
+//! - 需要在后台任务中继续处理的请求（如微信回调）通过 [`spawn`] 启动任务，保留 span 和上下文；
+//! - 按 `Accept-Language` 请求头选择的语言用于翻译错误消息和系统通知，见 [`i18n`](crate::i18n)。
+//!
+//! 这样同一次用户操作在各个实例、各个消费者中的日志都可以用同一个请求 ID 关联。
+
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::Request;
+use axum::http::{header, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use tokio::task::JoinHandle;
+use tower_http::trace::MakeSpan;
+use tracing::{Instrument, Span};
+
+use crate::i18n::Locale;
+
+/// 请求 ID 的请求头和响应头
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 认证后 uid 的响应头
+pub const UID: HeaderName = HeaderName::from_static("x-uid");
+
+/// 沿用的请求 ID 的最大长度
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// 请求上下文
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    request_id: String,
+    uid: OnceLock<i64>,
+    locale: OnceLock<Locale>,
+}
+
+impl RequestContext {
+    /// 使用指定的请求 ID 创建
+    pub fn new(request_id: impl Into<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                request_id: request_id.into(),
+                uid: OnceLock::new(),
+                locale: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// 生成新的请求 ID 并创建
+    pub fn generate() -> Self {
+        Self::new(format!("{:032x}", rand::random::<u128>()))
+    }
+
+    /// 请求 ID
+    pub fn request_id(&self) -> &str {
+        &self.inner.request_id
+    }
+
+    /// 认证后的 uid
+    pub fn uid(&self) -> Option<i64> {
+        self.inner.uid.get().copied()
+    }
+
+    /// 记录认证后的 uid，同时记录到当前 span，只保留第一次设置的值
+    pub fn set_uid(&self, uid: i64) {
+        if self.inner.uid.set(uid).is_ok() {
+            Span::current().record("uid", uid);
+        }
+    }
+
+    /// 请求指定的语言
+    pub fn locale(&self) -> Option<Locale> {
+        self.inner.locale.get().copied()
+    }
+
+    /// 记录请求指定的语言，只保留第一次设置的值
+    pub fn set_locale(&self, locale: Locale) {
+        let _ = self.inner.locale.set(locale);
+    }
+
+    /// 当前任务的请求上下文
+    pub fn current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    /// 当前任务的请求 ID
+    pub fn current_request_id() -> Option<String> {
+        CONTEXT
+            .try_with(|context| context.request_id().to_string())
+            .ok()
+    }
+
+    /// 在该上下文中执行
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+}
+
+/// 记录当前请求认证后的 uid，不在请求中时忽略
+pub fn record_uid(uid: i64) {
+    if let Some(context) = RequestContext::current() {
+        context.set_uid(uid);
+    }
+}
+
+/// 启动后台任务，继承当前的 span 和请求上下文
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.in_current_span();
+    match RequestContext::current() {
+        Some(context) => tokio::spawn(context.scope(future)),
+        None => tokio::spawn(future),
+    }
+}
+
+/// 沿用请求头中的请求 ID，只接受不超过 128 字节的可见 ASCII 字符
+fn valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LEN
+        && request_id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// 分配请求 ID、选择语言，处理完成后写入响应头
+pub async fn request_context(mut request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let context = headers
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|request_id| valid_request_id(request_id))
+        .map(RequestContext::new)
+        .unwrap_or_else(RequestContext::generate);
+    if let Some(locale) = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Locale::from_accept_language)
+    {
+        context.set_locale(locale);
+    }
+    request.extensions_mut().insert(context.clone());
+
+    let mut response = context.clone().scope(next.run(request)).await;
+    let headers = response.headers_mut();
+    if let Ok(request_id) = HeaderValue::from_str(context.request_id()) {
+        headers.insert(REQUEST_ID, request_id);
+    }
+    if let Some(uid) = context.uid() {
+        headers.insert(UID, HeaderValue::from(uid));
+    }
+    response
+}
+
+/// 创建请求的 span，包含请求 ID 和认证后的 uid
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &axum::http::Request<B>) -> Span {
+        let request_id = request
+            .extensions()
+            .get::<RequestContext>()
+            .map(RequestContext::request_id);
+        tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+            request_id,
+            uid = tracing::field::Empty,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    use crate::handler::context::{
+        record_uid, request_context, spawn, RequestContext, REQUEST_ID, UID,
+    };
+
+    fn router() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    record_uid(7);
+                    // 后台任务继承请求上下文
+                    spawn(async { RequestContext::current_request_id() })
+                        .await
+                        .ok()
+                        .flatten()
+                        .unwrap_or_default()
+                }),
+            )
+            .layer(middleware::from_fn(request_context))
+    }
+
+    #[tokio::test]
+    async fn request_id() -> anyhow::Result<()> {
+        let request = Request::get("/")
+            .header(REQUEST_ID, "abc-123")
+            .body(Body::empty())?;
+        let response = router().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[REQUEST_ID], "abc-123");
+        assert_eq!(response.headers()[UID], "7");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert_eq!(body.as_ref(), b"abc-123");
+
+        // 不合法时重新生成
+        let request = Request::get("/")
+            .header(REQUEST_ID, "a b")
+            .body(Body::empty())?;
+        let response = router().oneshot(request).await?;
+        let request_id = response.headers()[REQUEST_ID].to_str()?;
+        assert_eq!(request_id.len(), 32);
+
+        let response = router()
+            .oneshot(Request::get("/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.headers()[REQUEST_ID].len(), 32);
+        assert!(RequestContext::current().is_none());
+        Ok(())
+    }
+}
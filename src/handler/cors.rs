@@ -0,0 +1,272 @@
+//! # 跨域资源共享
+//!
+//! 未配置时，debug 构建允许任意来源访问，便于本地调试前端；release 构建不添加 CORS 响应头，
+//! 只允许同源访问。
+
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+use crate::handler::context;
+
+use crate::live::Live;
+
+/// 表示任意值的通配符
+const WILDCARD: &str = "*";
+
+/// CORS 配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// 允许的来源，例如 `https://mallchat.cn`，`*` 表示任意来源，为空时不启用 CORS
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// 允许的方法，`*` 表示任意方法
+    #[serde(default = "default::allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// 允许的请求头，`*` 表示任意请求头
+    #[serde(default = "default::allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// 是否允许携带凭证，不能与通配符同时使用
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// 预检请求结果的缓存时间（秒）
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+mod default {
+    pub fn allowed_methods() -> Vec<String> {
+        ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec()
+    }
+
+    pub fn allowed_headers() -> Vec<String> {
+        ["authorization", "content-type"].map(String::from).to_vec()
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Self::permissive()
+        } else {
+            Self::strict()
+        }
+    }
+}
+
+impl CorsConfig {
+    /// 允许任意来源、方法、请求头，仅用于开发环境
+    pub fn permissive() -> Self {
+        Self {
+            allowed_origins: vec![WILDCARD.to_string()],
+            allowed_methods: vec![WILDCARD.to_string()],
+            allowed_headers: vec![WILDCARD.to_string()],
+            allow_credentials: false,
+            max_age_secs: None,
+        }
+    }
+
+    /// 不允许跨域访问
+    pub fn strict() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: default::allowed_methods(),
+            allowed_headers: default::allowed_headers(),
+            allow_credentials: false,
+            max_age_secs: None,
+        }
+    }
+
+    /// 构造 CORS 中间件，未配置允许的来源时返回 `None`
+    pub fn layer(&self) -> anyhow::Result<Option<CorsLayer>> {
+        if self.allowed_origins.is_empty() {
+            return Ok(None);
+        }
+
+        let origin = if wildcard(&self.allowed_origins) {
+            AllowOrigin::from(Any)
+        } else {
+            let origins = self
+                .allowed_origins
+                .iter()
+                .map(|origin| {
+                    HeaderValue::from_str(origin)
+                        .with_context(|| format!("Invalid CORS origin: {origin}"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            AllowOrigin::list(origins)
+        };
+        self.build(origin).map(Some)
+    }
+
+    /// 构造允许的来源可以热更新的 CORS 中间件，方法、请求头等其他配置使用 `live` 的初始值
+    ///
+    /// 每个请求按 `live` 中最新的 `allowed_origins` 判断，来源不被允许时不添加 `Access-Control-Allow-Origin`
+    pub fn live_layer(live: Live<CorsConfig>) -> anyhow::Result<CorsLayer> {
+        let config = live.load();
+        config.layer()?;
+        config.build(AllowOrigin::predicate(move |origin, _| {
+            live.load().allows(origin)
+        }))
+    }
+
+    /// 是否允许来自 `origin` 的请求
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == WILDCARD || allowed.as_bytes() == origin.as_bytes())
+    }
+
+    fn build(&self, origin: AllowOrigin) -> anyhow::Result<CorsLayer> {
+        if self.allow_credentials
+            && (wildcard(&self.allowed_origins)
+                || wildcard(&self.allowed_methods)
+                || wildcard(&self.allowed_headers))
+        {
+            anyhow::bail!("CORS credentials can not be allowed with wildcard");
+        }
+
+        let methods = if wildcard(&self.allowed_methods) {
+            AllowMethods::from(Any)
+        } else {
+            let methods = self
+                .allowed_methods
+                .iter()
+                .map(|method| {
+                    Method::from_bytes(method.as_bytes())
+                        .with_context(|| format!("Invalid CORS method: {method}"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            AllowMethods::list(methods)
+        };
+        let headers = if wildcard(&self.allowed_headers) {
+            AllowHeaders::from(Any)
+        } else {
+            let headers = self
+                .allowed_headers
+                .iter()
+                .map(|header| {
+                    HeaderName::from_bytes(header.as_bytes())
+                        .with_context(|| format!("Invalid CORS header: {header}"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            AllowHeaders::list(headers)
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([context::REQUEST_ID, context::UID])
+            .allow_credentials(self.allow_credentials);
+        if let Some(max_age) = self.max_age_secs {
+            layer = layer.max_age(Duration::from_secs(max_age));
+        }
+        Ok(layer)
+    }
+}
+
+fn wildcard(values: &[String]) -> bool {
+    values.iter().any(|value| value == WILDCARD)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{header, Method, Request};
+    use tower::ServiceExt;
+
+    use crate::handler::cors::CorsConfig;
+    use crate::live::Live;
+    use crate::testing::TestApp;
+
+    #[test]
+    fn layer() -> anyhow::Result<()> {
+        assert!(CorsConfig::strict().layer()?.is_none());
+        assert!(CorsConfig::permissive().layer()?.is_some());
+
+        let mut config = CorsConfig {
+            allowed_origins: vec!["https://mallchat.cn".to_string()],
+            allow_credentials: true,
+            ..CorsConfig::strict()
+        };
+        assert!(config.layer()?.is_some());
+
+        config.allowed_headers = vec!["*".to_string()];
+        assert!(config.layer().is_err());
+
+        config.allowed_headers = vec!["bad header".to_string()];
+        assert!(config.layer().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn preflight() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let config = CorsConfig {
+            allowed_origins: vec!["https://mallchat.cn".to_string()],
+            ..CorsConfig::strict()
+        };
+        let cors = config
+            .layer()?
+            .ok_or_else(|| anyhow::anyhow!("cors disabled"))?;
+        let router = app.builder()?.cors(cors).build();
+
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/capi/chat/msg")
+            .header(header::ORIGIN, "https://mallchat.cn")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())?;
+        let response = router.oneshot(request).await?;
+        assert_eq!(
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&header::HeaderValue::from_static("https://mallchat.cn"))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn live_origins() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let live = Live::new(CorsConfig {
+            allowed_origins: vec!["https://mallchat.cn".to_string()],
+            ..CorsConfig::strict()
+        });
+        let router = app
+            .builder()?
+            .cors(CorsConfig::live_layer(live.clone())?)
+            .build();
+        let preflight = |origin: &'static str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/capi/chat/msg")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(Body::empty())
+        };
+
+        let response = router
+            .clone()
+            .oneshot(preflight("https://example.com")?)
+            .await?;
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+
+        live.store(CorsConfig {
+            allowed_origins: vec!["https://example.com".to_string()],
+            ..CorsConfig::strict()
+        });
+        let response = router.oneshot(preflight("https://example.com")?).await?;
+        assert_eq!(
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&header::HeaderValue::from_static("https://example.com"))
+        );
+        Ok(())
+    }
+}
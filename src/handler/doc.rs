@@ -0,0 +1,164 @@
+//! # OpenAPI 响应模型
+//!
+//! [`ApiValue`](crate::handler::api::ApiValue)、[`ApiError`](crate::handler::api::ApiError)
+//! 手动实现了序列化，这里的类型只用于生成文档，字段与实际响应保持一致。
+//! 新增返回数据的接口时，在 [`ApiData`] 的 `aliases` 中添加对应的别名。
+//!
+//! [`export`] 不启动服务，直接将 HTTP 和 WebSocket 协议文档写入文件，供前端在 CI 中生成代码。
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::cache::user_info::UserInfo;
+use crate::cache::CacheStats;
+use crate::handler::admin::{
+    ApiKeyResp, ArchiveFileResp, ArchivedMessageResp, GrantItemResp, LogLevelResp, ModerationResp,
+    WebhookResp, WxWelcomeResp,
+};
+use crate::handler::captcha::CaptchaResp;
+use crate::handler::chat::{
+    ChatMessageResp, MemberResp, MessageReadCountResp, MessageReaderResp, MessageResp,
+    MessageSearchResp, RoomResp,
+};
+use crate::handler::emoji::EmojiResp;
+use crate::handler::friend::{FriendApplyResp, FriendResp};
+use crate::handler::oss::OssResp;
+use crate::handler::room::{GroupResp, InviteResp, SingleRoomResp};
+use crate::handler::user::{AvatarResp, DeviceResp, UserInfoResp};
+use crate::handler::valid::FieldError;
+use crate::handler::ws::push::{Announcement, LoginSuccess};
+use crate::handler::ws::SessionInfo;
+use crate::handler::ApiDoc;
+use crate::jobs::member_count::MemberCountStats;
+use crate::service::stats::{DailyStats, OnlineStats};
+
+/// 成功响应
+#[derive(Serialize, ToSchema)]
+#[aliases(
+    RoomPageData = ApiData<Vec<RoomResp>>,
+    MemberPageData = ApiData<Vec<MemberResp>>,
+    MessageData = ApiData<MessageResp>,
+    MessageSearchPageData = ApiData<MessageSearchPage>,
+    ChatMessagePageData = ApiData<ChatMessagePage>,
+    MessageReadCountData = ApiData<MessageReadCountResp>,
+    MessageReaderPageData = ApiData<MessageReaderPage>,
+    FriendPageData = ApiData<Vec<FriendResp>>,
+    FriendApplyPageData = ApiData<Vec<FriendApplyResp>>,
+    SingleRoomData = ApiData<SingleRoomResp>,
+    GroupData = ApiData<GroupResp>,
+    InviteData = ApiData<InviteResp>,
+    LogLevelData = ApiData<LogLevelResp>,
+    WsSessionListData = ApiData<Vec<SessionInfo>>,
+    UserInfoData = ApiData<UserInfoResp>,
+    UserInfoListData = ApiData<Vec<UserInfo>>,
+    DeviceListData = ApiData<Vec<DeviceResp>>,
+    OssData = ApiData<OssResp>,
+    AvatarData = ApiData<AvatarResp>,
+    EmojiData = ApiData<EmojiResp>,
+    EmojiListData = ApiData<Vec<EmojiResp>>,
+    GrantItemData = ApiData<GrantItemResp>,
+    DailyStatsListData = ApiData<Vec<DailyStats>>,
+    OnlineStatsData = ApiData<OnlineStats>,
+    CacheStatsData = ApiData<CacheStats>,
+    MemberCountStatsData = ApiData<MemberCountStats>,
+    AnnouncementData = ApiData<Announcement>,
+    WebhookData = ApiData<WebhookResp>,
+    WebhookListData = ApiData<Vec<WebhookResp>>,
+    ApiKeyData = ApiData<ApiKeyResp>,
+    ApiKeyListData = ApiData<Vec<ApiKeyResp>>,
+    ArchivedMessagePageData = ApiData<ArchivedMessagePage>,
+    ArchiveFilePageData = ApiData<ArchiveFilePage>,
+    ModerationPageData = ApiData<ModerationPage>,
+    WxWelcomeData = ApiData<WxWelcomeResp>,
+    LoginSuccessData = ApiData<LoginSuccess>,
+    CaptchaData = ApiData<CaptchaResp>,
+)]
+pub struct ApiData<T> {
+    /// 固定为 `true`
+    pub success: bool,
+    /// 数据
+    pub data: T,
+}
+
+/// 游标分页数据，与 [`CursorPageResp`](crate::handler::api::CursorPageResp) 一致
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[aliases(
+    MessageSearchPage = CursorPage<MessageSearchResp>,
+    ChatMessagePage = CursorPage<ChatMessageResp>,
+    MessageReaderPage = CursorPage<MessageReaderResp>,
+    ArchivedMessagePage = CursorPage<ArchivedMessageResp>,
+    ArchiveFilePage = CursorPage<ArchiveFileResp>,
+    ModerationPage = CursorPage<ModerationResp>,
+)]
+pub struct CursorPage<T> {
+    /// 下一页的游标，没有数据时为空
+    pub cursor: Option<String>,
+    /// 是否为最后一页
+    pub is_last: bool,
+    /// 数据列表
+    pub list: Vec<T>,
+}
+
+/// 没有数据的成功响应
+#[derive(Serialize, ToSchema)]
+pub struct ApiSuccess {
+    /// 固定为 `true`
+    pub success: bool,
+}
+
+/// 错误响应
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiErrorResp {
+    /// 固定为 `false`
+    pub success: bool,
+    /// 错误码，见 [`ErrorCode`](crate::handler::api::ErrorCode)
+    pub err_code: i32,
+    /// 错误消息
+    pub err_msg: String,
+    /// 参数校验失败的字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<FieldError>>,
+}
+
+/// 将 OpenAPI 文档和 WebSocket 协议的 AsyncAPI 文档分别写入 `dir` 下的 `openapi.json`、`ws.json`，
+/// 与 `/api-docs/openapi.json`、`/api-docs/ws.json` 的内容相同，返回写入的文件
+pub fn export(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let docs = [
+        ("openapi.json", serde_json::to_value(ApiDoc::openapi())?),
+        ("ws.json", crate::handler::ws::push::asyncapi()),
+    ];
+    let mut files = Vec::with_capacity(docs.len());
+    for (name, doc) in docs {
+        let path = dir.join(name);
+        std::fs::write(&path, serde_json::to_vec_pretty(&doc)?)?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::doc::export;
+
+    #[test]
+    fn export_docs() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("mallchat-api-docs-{}", std::process::id()));
+        let files = export(&dir)?;
+        assert_eq!(files.len(), 2);
+
+        let openapi: serde_json::Value = serde_json::from_slice(&std::fs::read(&files[0])?)?;
+        assert!(openapi["paths"]
+            .get("/capi/chat/public/room/page")
+            .is_some());
+        let ws: serde_json::Value = serde_json::from_slice(&std::fs::read(&files[1])?)?;
+        assert_eq!(ws["asyncapi"], "2.6.0");
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
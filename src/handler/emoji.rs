@@ -0,0 +1,205 @@
+//! # 表情包相关接口
+//!
+
+use crate::handler::valid::Valid;
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::handler::api::{ApiError, ApiResult, ApiValue, ErrorCode, ToApiData};
+use crate::handler::auth::Claims;
+use crate::handler::friend::DeleteStatus;
+use crate::handler::oss;
+use crate::storage::model::user_emoji;
+use crate::storage::oss::{DynObjectStore, OssConfig, OssScene};
+use crate::storage::tx::with_txn;
+
+/// 每个用户最多收藏的表情包数量
+pub const MAX_EMOJIS: u64 = 30;
+
+/// 表情包相关路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/user/emoji",
+        Router::new()
+            .route("/", post(add_emoji).delete(delete_emoji))
+            .route("/list", get(list_emoji)),
+    )
+}
+
+/// 添加表情包请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiAddReq {
+    /// 表情地址
+    #[validate(url, length(max = 255))]
+    pub expression_url: String,
+}
+
+/// 删除表情包请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiDeleteReq {
+    /// 表情包 ID
+    pub id: u64,
+}
+
+/// 表情包
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiResp {
+    /// 表情包 ID
+    pub id: u64,
+    /// 表情地址
+    pub expression_url: String,
+}
+
+impl From<user_emoji::Model> for EmojiResp {
+    fn from(emoji: user_emoji::Model) -> Self {
+        Self {
+            id: emoji.id,
+            expression_url: emoji.expression_url,
+        }
+    }
+}
+
+/// 表情包列表
+#[utoipa::path(
+    get,
+    path = "/capi/user/emoji/list",
+    responses(
+        (status = 200, description = "成功", body = EmojiListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn list_emoji(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+) -> ApiResult<Vec<EmojiResp>> {
+    user_emoji::Entity::find()
+        .filter(user_emoji::Column::Uid.eq(claims.uid))
+        .filter(user_emoji::Column::DeleteStatus.eq(DeleteStatus::Normal as i32))
+        .order_by_asc(user_emoji::Column::Id)
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(EmojiResp::from)
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 添加表情包
+///
+/// `Content-Type` 为 `application/json` 时请求体为 [`EmojiAddReq`]，否则请求体为图片内容，
+/// 支持 PNG、JPEG、GIF、WebP。每个用户最多收藏 [`MAX_EMOJIS`] 个
+#[utoipa::path(
+    post,
+    path = "/capi/user/emoji",
+    request_body(
+        content = EmojiAddReq,
+        description = "表情地址，或者直接上传图片内容（Content-Type 为 image/*）"
+    ),
+    responses(
+        (status = 200, description = "成功", body = EmojiData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn add_emoji(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(store): Extension<DynObjectStore>,
+    Extension(config): Extension<OssConfig>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<EmojiResp> {
+    let count = user_emoji::Entity::find()
+        .filter(user_emoji::Column::Uid.eq(claims.uid))
+        .filter(user_emoji::Column::DeleteStatus.eq(DeleteStatus::Normal as i32))
+        .count(&db)
+        .await?;
+    if count >= MAX_EMOJIS {
+        return ApiError::business_err(
+            ErrorCode::TooManyEmojis,
+            format!("最多只能添加{MAX_EMOJIS}个表情哦~~"),
+        );
+    }
+
+    let expression_url = if oss::is_json(&headers) {
+        let req: EmojiAddReq = oss::json_body(&body)?;
+        req.expression_url
+    } else {
+        let max_bytes = config.emoji_max_bytes;
+        let key =
+            oss::put_image(store.as_ref(), OssScene::Emoji, claims.uid, body, max_bytes).await?;
+        store.public_url(&key)
+    };
+
+    let emoji = with_txn(&db, |txn| {
+        Box::pin(async move {
+            let exists = user_emoji::Entity::find()
+                .filter(user_emoji::Column::Uid.eq(claims.uid))
+                .filter(user_emoji::Column::ExpressionUrl.eq(expression_url.as_str()))
+                .filter(user_emoji::Column::DeleteStatus.eq(DeleteStatus::Normal as i32))
+                .one(txn)
+                .await?;
+            if exists.is_some() {
+                return Err(ApiError::business(
+                    ErrorCode::EmojiExists,
+                    "当前表情已存在哦~~",
+                ));
+            }
+            let emoji = user_emoji::ActiveModel {
+                uid: Set(claims.uid),
+                expression_url: Set(expression_url),
+                delete_status: Set(DeleteStatus::Normal as i32),
+                ..Default::default()
+            }
+            .insert(txn)
+            .await?;
+            Ok(emoji)
+        })
+    })
+    .await?;
+
+    EmojiResp::from(emoji).to_api_data()
+}
+
+/// 删除表情包
+#[utoipa::path(
+    delete,
+    path = "/capi/user/emoji",
+    request_body = EmojiDeleteReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_emoji(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<EmojiDeleteReq>>,
+) -> ApiResult<()> {
+    let result = user_emoji::Entity::update_many()
+        .col_expr(
+            user_emoji::Column::DeleteStatus,
+            Expr::value(DeleteStatus::Deleted as i32),
+        )
+        .filter(user_emoji::Column::Id.eq(req.id))
+        .filter(user_emoji::Column::Uid.eq(claims.uid))
+        .filter(user_emoji::Column::DeleteStatus.eq(DeleteStatus::Normal as i32))
+        .exec(&db)
+        .await?;
+    if result.rows_affected == 0 {
+        return ApiError::business_err(ErrorCode::EmojiNotFound, "表情不存在");
+    }
+    ApiValue::success()
+}
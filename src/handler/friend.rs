@@ -0,0 +1,551 @@
+//! # 好友相关接口
+//!
+
+use crate::handler::valid::Valid;
+use axum::extract::Query;
+use axum::routing::{delete, get, post, put};
+use axum::{Extension, Json, Router};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, Insert,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait, UpdateMany,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::handler::api::{ApiError, ApiResult, ApiValue, ErrorCode, Pager, ToApiData};
+use crate::handler::auth::Claims;
+use crate::handler::ws::push::{FriendApply, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::service::room::RoomService;
+use crate::storage::model::{user_apply, user_friend};
+use crate::storage::repo::DynUserRepo;
+use crate::storage::soft_delete::SoftDelete;
+use crate::storage::tx::with_txn;
+
+/// 好友相关路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/user/friend",
+        Router::new()
+            .route("/", delete(delete_friend))
+            .route("/page", get(friend_page))
+            .route("/apply", post(apply))
+            .route("/apply", put(approve))
+            .route("/apply/page", get(apply_page)),
+    )
+}
+
+/// 申请类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ApplyType {
+    /// 加好友
+    AddFriend = 1,
+}
+
+/// 申请状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[repr(i32)]
+pub enum ApplyStatus {
+    /// 待审批
+    Waiting = 1,
+    /// 同意
+    Agreed = 2,
+    /// 拒绝
+    Rejected = 3,
+}
+
+impl From<i32> for ApplyStatus {
+    fn from(value: i32) -> Self {
+        match value {
+            2 => ApplyStatus::Agreed,
+            3 => ApplyStatus::Rejected,
+            _ => ApplyStatus::Waiting,
+        }
+    }
+}
+
+/// 申请阅读状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ReadStatus {
+    /// 未读
+    Unread = 1,
+    /// 已读
+    Read = 2,
+}
+
+/// 逻辑删除状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum DeleteStatus {
+    /// 正常
+    Normal = 0,
+    /// 已删除
+    Deleted = 1,
+}
+
+/// 好友申请请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendApplyReq {
+    /// 好友 uid
+    #[validate(range(min = 1))]
+    pub target_uid: i64,
+    /// 申请信息
+    #[validate(length(min = 1, max = 64))]
+    pub msg: String,
+}
+
+/// 审批好友申请请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendApproveReq {
+    /// 申请 ID
+    pub apply_id: u64,
+    /// 是否同意
+    pub agree: bool,
+}
+
+/// 删除好友请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendDeleteReq {
+    /// 好友 uid
+    #[validate(range(min = 1))]
+    pub target_uid: i64,
+}
+
+/// 好友信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendResp {
+    /// 好友 uid
+    pub uid: i64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+    /// 是否在线
+    pub online: bool,
+}
+
+/// 好友申请信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendApplyResp {
+    /// 申请 ID
+    pub apply_id: u64,
+    /// 申请人 uid
+    pub uid: i64,
+    /// 申请信息
+    pub msg: String,
+    /// 申请状态
+    pub status: ApplyStatus,
+}
+
+/// 申请好友
+#[utoipa::path(
+    post,
+    path = "/capi/user/friend/apply",
+    request_body = FriendApplyReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn apply(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(session_manager): Extension<SessionManager>,
+    Valid(Json(req)): Valid<Json<FriendApplyReq>>,
+) -> ApiResult<()> {
+    if req.target_uid == claims.uid {
+        return ApiError::business_err(ErrorCode::AddSelfAsFriend, "不能添加自己为好友");
+    }
+
+    if users.find_by_id(req.target_uid).await?.is_none() {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    }
+
+    if is_friend(&db, claims.uid, req.target_uid).await? {
+        return ApiError::business_err(ErrorCode::AlreadyFriends, "你们已经是好友了");
+    }
+
+    // 已经申请过了，等待对方审批
+    if find_waiting_apply(&db, claims.uid, req.target_uid)
+        .await?
+        .is_some()
+    {
+        return ApiValue::success();
+    }
+
+    // 对方已经申请过了，直接同意
+    if let Some(reverse) = find_waiting_apply(&db, req.target_uid, claims.uid).await? {
+        // 并发审批时对方的申请可能已被处理，此时按普通申请继续
+        if with_txn(&db, |txn| Box::pin(agree(txn, reverse))).await? {
+            return ApiValue::success();
+        }
+    }
+
+    apply_upsert(claims.uid, req.target_uid, req.msg)
+        .exec_without_returning(&db)
+        .await?;
+
+    let unread_count = user_apply::Entity::find()
+        .filter(user_apply::Column::TargetId.eq(req.target_uid))
+        .filter(user_apply::Column::ReadStatus.eq(ReadStatus::Unread as i32))
+        .count(&db)
+        .await?;
+    let resp = WsPush::Apply(FriendApply {
+        uid: claims.uid,
+        unread_count,
+    });
+    if let Err(error) = session_manager.send_to_user(req.target_uid, &resp) {
+        tracing::error!(%error, target_uid = %req.target_uid, "Failed to push friend apply");
+    }
+
+    ApiValue::success()
+}
+
+/// 审批好友申请
+#[utoipa::path(
+    put,
+    path = "/capi/user/friend/apply",
+    request_body = FriendApproveReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn approve(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<FriendApproveReq>>,
+) -> ApiResult<()> {
+    let Some(apply) = user_apply::Entity::find_by_id(req.apply_id)
+        .one(&db)
+        .await?
+    else {
+        return ApiError::business_err(ErrorCode::FriendApplyNotFound, "申请不存在");
+    };
+    if apply.target_id != claims.uid {
+        return ApiError::business_err(ErrorCode::FriendApplyNotFound, "申请不存在");
+    }
+    if ApplyStatus::from(apply.status) != ApplyStatus::Waiting {
+        return ApiError::business_err(ErrorCode::FriendApplyHandled, "已审批过该申请");
+    }
+
+    let handled = if req.agree {
+        with_txn(&db, |txn| Box::pin(agree(txn, apply))).await?
+    } else {
+        handle_apply(&db, apply.id, ApplyStatus::Rejected).await?
+    };
+    // 上面的检查与更新之间可能被并发审批
+    if !handled {
+        return ApiError::business_err(ErrorCode::FriendApplyHandled, "已审批过该申请");
+    }
+
+    ApiValue::success()
+}
+
+/// 好友申请列表
+#[utoipa::path(
+    get,
+    path = "/capi/user/friend/apply/page",
+    params(Pager),
+    responses(
+        (status = 200, description = "成功", body = FriendApplyPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn apply_page(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Query(pager)): Valid<Query<Pager>>,
+) -> ApiResult<Vec<FriendApplyResp>> {
+    let applies = user_apply::Entity::find()
+        .filter(user_apply::Column::TargetId.eq(claims.uid))
+        .order_by_desc(user_apply::Column::Id)
+        .offset(pager.offset())
+        .limit(pager.limit())
+        .all(&db)
+        .await?;
+
+    // 拉取列表后将这些申请标记为已读
+    let ids: Vec<u64> = applies.iter().map(|apply| apply.id).collect();
+    if !ids.is_empty() {
+        user_apply::Entity::update_many()
+            .col_expr(
+                user_apply::Column::ReadStatus,
+                Expr::value(ReadStatus::Read as i32),
+            )
+            .filter(user_apply::Column::Id.is_in(ids))
+            .exec(&db)
+            .await?;
+    }
+
+    applies
+        .into_iter()
+        .map(|apply| FriendApplyResp {
+            apply_id: apply.id,
+            uid: apply.uid,
+            msg: apply.msg,
+            status: apply.status.into(),
+        })
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 好友列表
+#[utoipa::path(
+    get,
+    path = "/capi/user/friend/page",
+    params(Pager),
+    responses(
+        (status = 200, description = "成功", body = FriendPageData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn friend_page(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(session_manager): Extension<SessionManager>,
+    Valid(Query(pager)): Valid<Query<Pager>>,
+) -> ApiResult<Vec<FriendResp>> {
+    let friend_uids: Vec<i64> = user_friend::Entity::find_alive()
+        .filter(user_friend::Column::Uid.eq(claims.uid))
+        .order_by_desc(user_friend::Column::Id)
+        .offset(pager.offset())
+        .limit(pager.limit())
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|friend| friend.friend_uid)
+        .collect();
+    if friend_uids.is_empty() {
+        return Vec::new().to_api_data();
+    }
+
+    let users = users.find_by_ids(&friend_uids).await?;
+
+    friend_uids
+        .into_iter()
+        .map(|uid| {
+            let user = users.iter().find(|user| user.id as i64 == uid);
+            FriendResp {
+                uid,
+                name: user.and_then(|user| user.name.clone()),
+                avatar: user.and_then(|user| user.avatar.clone()),
+                online: session_manager.is_online(uid),
+            }
+        })
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 删除好友
+#[utoipa::path(
+    delete,
+    path = "/capi/user/friend",
+    request_body = FriendDeleteReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn delete_friend(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<FriendDeleteReq>>,
+) -> ApiResult<()> {
+    if !is_friend(&db, claims.uid, req.target_uid).await? {
+        return ApiError::business_err(ErrorCode::NotFriends, "你们不是好友");
+    }
+
+    with_txn(&db, |txn| {
+        Box::pin(async move {
+            user_friend::Entity::soft_delete_many()
+                .filter(
+                    user_friend::Column::Uid
+                        .eq(claims.uid)
+                        .and(user_friend::Column::FriendUid.eq(req.target_uid))
+                        .or(user_friend::Column::Uid
+                            .eq(req.target_uid)
+                            .and(user_friend::Column::FriendUid.eq(claims.uid))),
+                )
+                .exec(txn)
+                .await?;
+            RoomService::new(txn)
+                .disable_single(claims.uid, req.target_uid)
+                .await
+        })
+    })
+    .await?;
+
+    ApiValue::success()
+}
+
+pub(crate) async fn is_friend<C: ConnectionTrait>(
+    db: &C,
+    uid: i64,
+    friend_uid: i64,
+) -> Result<bool, sea_orm::DbErr> {
+    let count = user_friend::Entity::find_alive()
+        .filter(user_friend::Column::Uid.eq(uid))
+        .filter(user_friend::Column::FriendUid.eq(friend_uid))
+        .count(db)
+        .await?;
+    Ok(count > 0)
+}
+
+async fn find_waiting_apply<C: ConnectionTrait>(
+    db: &C,
+    uid: i64,
+    target_uid: i64,
+) -> Result<Option<user_apply::Model>, sea_orm::DbErr> {
+    user_apply::Entity::find()
+        .filter(user_apply::Column::Uid.eq(uid))
+        .filter(user_apply::Column::TargetId.eq(target_uid))
+        .filter(user_apply::Column::Status.eq(ApplyStatus::Waiting as i32))
+        .one(db)
+        .await
+}
+
+/// 按唯一索引 `uniq_uid_target_id_type` 写入申请，被拒绝或删除好友后再次申请时复用原来的记录，
+/// 并发申请时也只有一条
+fn apply_upsert(uid: i64, target_uid: i64, msg: String) -> Insert<user_apply::ActiveModel> {
+    user_apply::Entity::insert(user_apply::ActiveModel {
+        uid: Set(uid),
+        r#type: Set(ApplyType::AddFriend as i32),
+        target_id: Set(target_uid),
+        msg: Set(msg),
+        status: Set(ApplyStatus::Waiting as i32),
+        read_status: Set(ReadStatus::Unread as i32),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([
+            user_apply::Column::Uid,
+            user_apply::Column::TargetId,
+            user_apply::Column::Type,
+        ])
+        .update_columns([
+            user_apply::Column::Msg,
+            user_apply::Column::Status,
+            user_apply::Column::ReadStatus,
+        ])
+        .to_owned(),
+    )
+}
+
+/// 将等待审批的申请改为 `status`，返回是否更新；申请已被处理时不更新
+async fn handle_apply<C: ConnectionTrait>(
+    db: &C,
+    apply_id: u64,
+    status: ApplyStatus,
+) -> Result<bool, sea_orm::DbErr> {
+    let result = status_update(apply_id, status).exec(db).await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 只更新仍在等待审批的申请，并发审批时只有一个能更新成功
+fn status_update(apply_id: u64, status: ApplyStatus) -> UpdateMany<user_apply::Entity> {
+    user_apply::Entity::update_many()
+        .col_expr(user_apply::Column::Status, Expr::value(status as i32))
+        .filter(user_apply::Column::Id.eq(apply_id))
+        .filter(user_apply::Column::Status.eq(ApplyStatus::Waiting as i32))
+}
+
+/// 同意申请：双向建立好友关系并创建单聊房间，返回是否同意；申请已被处理时不做任何修改
+async fn agree<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    apply: user_apply::Model,
+) -> Result<bool, sea_orm::DbErr> {
+    let (uid, target_uid) = (apply.uid, apply.target_id);
+    if !handle_apply(db, apply.id, ApplyStatus::Agreed).await? {
+        return Ok(false);
+    }
+
+    for (uid, friend_uid) in [(uid, target_uid), (target_uid, uid)] {
+        let existed = user_friend::Entity::find()
+            .filter(user_friend::Column::Uid.eq(uid))
+            .filter(user_friend::Column::FriendUid.eq(friend_uid))
+            .one(db)
+            .await?;
+        match existed {
+            Some(friend) => {
+                // 删除过的好友关系恢复，保留原来的记录
+                user_friend::Entity::restore_many()
+                    .filter(user_friend::Column::Id.eq(friend.id))
+                    .exec(db)
+                    .await?;
+            }
+            None => {
+                user_friend::ActiveModel {
+                    uid: Set(uid),
+                    friend_uid: Set(friend_uid),
+                    delete_status: Set(DeleteStatus::Normal as i32),
+                    ..Default::default()
+                }
+                .insert(db)
+                .await?;
+            }
+        }
+    }
+
+    RoomService::new(db)
+        .get_or_create_single(uid, target_uid)
+        .await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DbBackend, QueryTrait};
+
+    use crate::handler::friend::{apply_upsert, status_update, ApplyStatus, FriendApplyReq};
+    use validator::Validate;
+
+    #[test]
+    fn apply_sql() {
+        let sql = apply_upsert(1, 2, "你好".to_string())
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "INSERT INTO `user_apply` (`uid`, `type`, `target_id`, `msg`, `status`, `read_status`) \
+            VALUES (1, 1, 2, '你好', 1, 1) \
+            ON DUPLICATE KEY UPDATE `msg` = VALUES(`msg`), `status` = VALUES(`status`), \
+            `read_status` = VALUES(`read_status`)"
+        );
+    }
+
+    #[test]
+    fn status_sql() {
+        let sql = status_update(1, ApplyStatus::Agreed)
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "UPDATE `user_apply` SET `status` = 2 WHERE `user_apply`.`id` = 1 AND `user_apply`.`status` = 1"
+        );
+    }
+
+    #[test]
+    fn validate_target() {
+        for (target_uid, valid) in [(1, true), (0, false), (-1, false)] {
+            let req = FriendApplyReq {
+                target_uid,
+                msg: "你好".to_string(),
+            };
+            assert_eq!(req.validate().is_ok(), valid, "{target_uid}");
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! # 请求限制
+//!
+//! 限制请求体大小和处理时间，超出限制时返回与其他接口一致的 JSON 错误
+
+use std::time::Duration;
+
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::live::Live;
+
+/// 请求限制配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitConfig {
+    /// 请求体大小上限（字节）
+    #[serde(default = "default::body_limit_bytes")]
+    pub body_limit_bytes: usize,
+    /// 上传接口的请求体大小上限（字节）
+    #[serde(default = "default::upload_body_limit_bytes")]
+    pub upload_body_limit_bytes: usize,
+    /// 请求处理超时时间（秒）
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+mod default {
+    pub fn body_limit_bytes() -> usize {
+        2 * 1024 * 1024
+    }
+
+    pub fn upload_body_limit_bytes() -> usize {
+        20 * 1024 * 1024
+    }
+
+    pub fn timeout_secs() -> u64 {
+        30
+    }
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        Self {
+            body_limit_bytes: default::body_limit_bytes(),
+            upload_body_limit_bytes: default::upload_body_limit_bytes(),
+            timeout_secs: default::timeout_secs(),
+        }
+    }
+}
+
+impl LimitConfig {
+    /// 请求处理超时时间
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// 为普通接口添加请求体大小限制
+    pub fn limit_body(&self, router: Router) -> Router {
+        limit_body(router, self.body_limit_bytes)
+    }
+
+    /// 为上传接口添加请求体大小限制
+    pub fn limit_upload_body(&self, router: Router) -> Router {
+        limit_body(router, self.upload_body_limit_bytes)
+    }
+}
+
+fn limit_body(router: Router, limit: usize) -> Router {
+    // RequestBodyLimitLayer 会改变请求体类型，无法用于已经确定请求体类型的 Router，
+    // DefaultBodyLimit 同样基于 `http_body::Limited`，在提取请求体时返回 413
+    router.layer(DefaultBodyLimit::max(limit))
+}
+
+/// 将请求体过大（413）和请求超时（408）的纯文本响应替换为 JSON 错误
+pub async fn json_error_response(response: Response) -> Response {
+    let (code, message) = match response.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => (ErrorCode::PayloadTooLarge, "Request body too large"),
+        StatusCode::REQUEST_TIMEOUT => (ErrorCode::RequestTimeout, "Request timeout"),
+        _ => return response,
+    };
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if is_json {
+        return response;
+    }
+    ApiError::business(code, message).into_response()
+}
+
+/// 按 [`Live`] 中最新的 `timeout_secs` 限制请求处理时间，超时返回 408
+pub async fn live_timeout(
+    State(limits): State<Live<LimitConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match tokio::time::timeout(limits.load().timeout(), next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => StatusCode::REQUEST_TIMEOUT.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use axum::routing::{get, post};
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+    use tower_http::timeout::TimeoutLayer;
+
+    use crate::handler::limit::{json_error_response, LimitConfig};
+    use crate::live::Live;
+
+    async fn error_body(router: Router, request: Request<Body>) -> anyhow::Result<String> {
+        let response = router.oneshot(request).await?;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok(String::from_utf8(body.to_vec())?)
+    }
+
+    #[tokio::test]
+    async fn body_limit() -> anyhow::Result<()> {
+        let config = LimitConfig {
+            body_limit_bytes: 4,
+            upload_body_limit_bytes: 8,
+            ..LimitConfig::default()
+        };
+        let router = config
+            .limit_body(Router::new().route("/", post(|body: String| async { body })))
+            .merge(config.limit_upload_body(
+                Router::new().route("/upload", post(|body: String| async { body })),
+            ))
+            .layer(middleware::map_response(json_error_response));
+
+        let request = |uri: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .body(Body::from("123456"))
+        };
+        let body = error_body(router.clone(), request("/")?).await?;
+        assert!(body.contains("Request body too large"), "{body}");
+        let response = router.oneshot(request("/upload")?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn timeout() -> anyhow::Result<()> {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async { tokio::time::sleep(Duration::from_secs(1)).await }),
+            )
+            .layer(TimeoutLayer::new(Duration::from_millis(10)))
+            .layer(middleware::map_response(json_error_response));
+        let body = error_body(router, Request::builder().uri("/").body(Body::empty())?).await?;
+        assert!(body.contains("Request timeout"), "{body}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn live_timeout() -> anyhow::Result<()> {
+        let limits = Live::new(LimitConfig {
+            timeout_secs: 0,
+            ..LimitConfig::default()
+        });
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async { tokio::time::sleep(Duration::from_millis(100)).await }),
+            )
+            .layer(middleware::from_fn_with_state(
+                limits.clone(),
+                super::live_timeout,
+            ));
+        let request = || Request::builder().uri("/").body(Body::empty());
+        let response = router.clone().oneshot(request()?).await?;
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+
+        limits.store(LimitConfig::default());
+        let response = router.oneshot(request()?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! # 监听与连接参数
+//!
+//! 监听队列长度、TCP keepalive、HTTP/2 等服务端参数。`/websocket` 长连接与静态文件共用同一个端口，
+//! 压测时默认参数下 HTTP/2 单连接的并发流数、监听队列长度容易成为瓶颈，可以在这里调整。
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum_server::accept::Accept;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// 监听与连接配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenerConfig {
+    /// 是否启用 HTTP/2（明文连接为 h2c，TLS 连接通过 ALPN 协商），关闭后只使用 HTTP/1.1
+    #[serde(default = "default::http2")]
+    pub http2: bool,
+    /// 单个 HTTP/2 连接的最大并发流数，不配置时使用 hyper 的默认值
+    #[serde(default)]
+    pub http2_max_concurrent_streams: Option<u32>,
+    /// HTTP/2 ping 间隔（秒），不配置时不发送
+    #[serde(default)]
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    /// 是否启用 HTTP/1.1 keep-alive
+    #[serde(default = "default::http1_keep_alive")]
+    pub http1_keep_alive: bool,
+    /// TCP keepalive 空闲时间（秒），不配置时不启用
+    #[serde(default)]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// 是否设置 TCP_NODELAY
+    #[serde(default = "default::tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// 监听队列长度
+    #[serde(default = "default::backlog")]
+    pub backlog: u32,
+}
+
+mod default {
+    pub fn http2() -> bool {
+        true
+    }
+
+    pub fn http1_keep_alive() -> bool {
+        true
+    }
+
+    pub fn tcp_nodelay() -> bool {
+        true
+    }
+
+    pub fn backlog() -> u32 {
+        1024
+    }
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            http2: default::http2(),
+            http2_max_concurrent_streams: None,
+            http2_keep_alive_interval_secs: None,
+            http1_keep_alive: default::http1_keep_alive(),
+            tcp_keepalive_secs: None,
+            tcp_nodelay: default::tcp_nodelay(),
+            backlog: default::backlog(),
+        }
+    }
+}
+
+impl ListenerConfig {
+    /// 按配置的监听队列长度监听地址
+    pub fn bind(&self, addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        #[cfg(unix)]
+        socket.set_reuseaddr(true)?;
+        socket.bind(addr)?;
+        socket.listen(self.backlog)?.into_std()
+    }
+
+    /// 设置 HTTP 协议参数
+    pub fn configure_http(&self, builder: &mut Builder<TokioExecutor>) {
+        builder.http1().keep_alive(self.http1_keep_alive);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(self.http2_max_concurrent_streams)
+            .keep_alive_interval(self.http2_keep_alive_interval_secs.map(Duration::from_secs));
+        if !self.http2 {
+            let http1_only = std::mem::replace(builder, Builder::new(TokioExecutor::new()));
+            *builder = http1_only.http1_only();
+        }
+    }
+
+    /// 设置 TCP 连接参数后交给 `inner` 处理，`inner` 为明文或 TLS 的 acceptor
+    pub fn acceptor<A>(&self, inner: A) -> TcpAcceptor<A> {
+        TcpAcceptor {
+            inner,
+            keepalive: self.tcp_keepalive_secs.map(Duration::from_secs),
+            nodelay: self.tcp_nodelay,
+        }
+    }
+}
+
+/// 设置 TCP keepalive、TCP_NODELAY 的 acceptor
+#[derive(Debug, Clone)]
+pub struct TcpAcceptor<A> {
+    inner: A,
+    keepalive: Option<Duration>,
+    nodelay: bool,
+}
+
+impl<A> TcpAcceptor<A> {
+    fn configure(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(time) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(())
+    }
+}
+
+impl<A, S> Accept<TcpStream, S> for TcpAcceptor<A>
+where
+    A: Accept<TcpStream, S>,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = A::Future;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        if let Err(error) = self.configure(&stream) {
+            tracing::warn!(%error, "Failed to set tcp options.");
+        }
+        self.inner.accept(stream, service)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper_util::rt::TokioExecutor;
+    use hyper_util::server::conn::auto::Builder;
+
+    use crate::handler::listener::ListenerConfig;
+
+    #[test]
+    fn deserialize_default() -> anyhow::Result<()> {
+        let config: ListenerConfig = serde_json::from_str("{}")?;
+        assert_eq!(config, ListenerConfig::default());
+
+        let config: ListenerConfig =
+            serde_json::from_str(r#"{"http2":false,"backlog":128,"tcp_keepalive_secs":60}"#)?;
+        assert!(!config.http2);
+        assert_eq!(config.backlog, 128);
+        assert_eq!(config.tcp_keepalive_secs, Some(60));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bind() -> anyhow::Result<()> {
+        let listener = ListenerConfig::default().bind(([127, 0, 0, 1], 0).into())?;
+        assert_ne!(listener.local_addr()?.port(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn http1_only() {
+        let mut builder = Builder::new(TokioExecutor::new());
+        ListenerConfig::default().configure_http(&mut builder);
+        assert!(builder.is_http2_available());
+
+        let config = ListenerConfig {
+            http2: false,
+            ..ListenerConfig::default()
+        };
+        config.configure_http(&mut builder);
+        assert!(!builder.is_http2_available());
+    }
+}
@@ -0,0 +1,97 @@
+//! # 开放接口
+//!
+//! 供外部服务通过 [`ApiKey`] 调用，不需要以用户身份登录，每个接口检查对应的授权范围 [`ApiScope`]
+
+use axum::extract::Query;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+
+use crate::cache::Cache;
+use crate::handler::admin::{self, AnnouncementReq, DailyStatsQuery};
+use crate::handler::api::{ApiResult, ToApiData};
+use crate::handler::auth::api_key::{ApiKey, ApiScope};
+use crate::handler::valid::Valid;
+use crate::handler::ws::push::Announcement;
+use crate::handler::ws::SessionManager;
+use crate::mq::DynProducer;
+use crate::service::stats::{DailyStats, OnlineStats};
+
+/// 开放接口路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/open",
+        Router::new()
+            .route("/announcement", post(publish_announcement))
+            .route("/stats/daily", get(get_daily_stats))
+            .route("/stats/online", get(get_online_stats)),
+    )
+}
+
+/// 发布系统公告，记录在创建 Key 的管理员名下，需要 `announcement` 授权
+#[utoipa::path(
+    post,
+    path = "/capi/open/announcement",
+    request_body = AnnouncementReq,
+    params(("X-Api-Key" = String, Header, description = "API Key")),
+    responses(
+        (status = 200, description = "成功", body = AnnouncementData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn publish_announcement(
+    api_key: ApiKey,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<AnnouncementReq>>,
+) -> ApiResult<Announcement> {
+    api_key.require(ApiScope::Announcement)?;
+    let producer = producer.map(|Extension(producer)| producer);
+    let announcement =
+        admin::announce(&db, &session_manager, producer, api_key.uid, &req.content).await?;
+    tracing::info!(key = api_key.id, name = %api_key.name, id = %announcement.id, "Announcement published by API key.");
+    announcement.to_api_data()
+}
+
+/// 查询每日统计，需要 `stats` 授权
+#[utoipa::path(
+    get,
+    path = "/capi/open/stats/daily",
+    params(
+        DailyStatsQuery,
+        ("X-Api-Key" = String, Header, description = "API Key"),
+    ),
+    responses(
+        (status = 200, description = "成功", body = DailyStatsListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_daily_stats(
+    api_key: ApiKey,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(cache): Extension<Cache>,
+    Valid(Query(query)): Valid<Query<DailyStatsQuery>>,
+) -> ApiResult<Vec<DailyStats>> {
+    api_key.require(ApiScope::Stats)?;
+    admin::daily_stats(&db, &cache, &query).await?.to_api_data()
+}
+
+/// 查询在线人数，需要 `stats` 授权
+#[utoipa::path(
+    get,
+    path = "/capi/open/stats/online",
+    params(("X-Api-Key" = String, Header, description = "API Key")),
+    responses(
+        (status = 200, description = "成功", body = OnlineStatsData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_online_stats(
+    api_key: ApiKey,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(cache): Extension<Cache>,
+) -> ApiResult<OnlineStats> {
+    api_key.require(ApiScope::Stats)?;
+    admin::online_stats(&db, &cache).await?.to_api_data()
+}
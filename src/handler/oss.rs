@@ -0,0 +1,389 @@
+//! # 对象存储相关接口
+//!
+
+use axum::body::Bytes;
+use axum::extract::{Path, Query};
+use axum::handler::Handler;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, put};
+use axum::{middleware, Extension, Router};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tower_http::services::ServeDir;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::handler::api::{ApiError, ApiResult, ApiValue, ErrorCode, ToApiData};
+use crate::handler::auth::Claims;
+use crate::handler::valid::Valid;
+use crate::storage::oss::local::{LocalStore, UPLOAD_PATH};
+use crate::storage::oss::{
+    object_key, DynObjectStore, ImageFormat, ObjectStore, OssConfig, OssScene,
+};
+
+/// 对象存储相关路由，依赖 [`DynObjectStore`] 和 [`OssConfig`]
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/oss",
+        Router::new().route("/upload/url", get(get_upload_url)),
+    )
+}
+
+/// 本地磁盘的上传、下载路由，通过 [`RouterBuilder::upload`](crate::handler::RouterBuilder::upload) 挂载
+///
+/// 只公开上传场景的目录，归档文件等其他对象需要签名下载。
+/// 下载的文件与接口同源，以附件形式返回并禁止执行脚本，见 [`download_headers`]
+pub fn local_route(store: LocalStore) -> Router {
+    let serve_path = store.serve_path().to_string();
+    let files = [OssScene::Chat, OssScene::Emoji, OssScene::Avatar]
+        .into_iter()
+        .fold(Router::new(), |files, scene| {
+            let prefix = scene.prefix();
+            files.nest_service(
+                &format!("/{prefix}"),
+                ServeDir::new(store.root().join(prefix)),
+            )
+        })
+        .layer(middleware::map_response(download_headers));
+    Router::new()
+        .route(
+            &format!("{UPLOAD_PATH}/*key"),
+            put(local_upload).get(local_download.layer(middleware::map_response(download_headers))),
+        )
+        .layer(Extension(store))
+        .nest(&serve_path, files)
+}
+
+/// 用户上传的文件不能被浏览器当作页面渲染：不猜测类型、作为附件下载、禁止脚本
+///
+/// `<img>`、`<video>` 等标签引用时不受 `Content-Disposition` 影响
+async fn download_headers(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment"),
+    );
+    headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("default-src 'none'; sandbox"),
+    );
+    response
+}
+
+/// 获取上传 URL 请求
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct UploadUrlReq {
+    /// 文件名，只用于确定扩展名
+    #[validate(length(min = 1, max = 128))]
+    pub file_name: String,
+    /// 上传场景：1 聊天消息，2 表情包，3 头像
+    #[param(value_type = i32)]
+    pub scene: OssScene,
+}
+
+/// 上传 URL
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OssResp {
+    /// 预签名的上传 URL，使用 `PUT` 上传文件内容
+    pub upload_url: String,
+    /// 上传成功后访问文件的 URL
+    pub download_url: String,
+    /// 上传时请求头 `Content-Type` 的值，使用 S3 时必须与此相同
+    pub content_type: String,
+}
+
+/// 获取预签名的上传 URL
+#[utoipa::path(
+    get,
+    path = "/capi/oss/upload/url",
+    params(UploadUrlReq),
+    responses(
+        (status = 200, description = "成功", body = OssData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_upload_url(
+    claims: Claims,
+    Extension(store): Extension<DynObjectStore>,
+    Extension(config): Extension<OssConfig>,
+    Valid(Query(req)): Valid<Query<UploadUrlReq>>,
+) -> ApiResult<OssResp> {
+    let Some((key, content_type)) = object_key(req.scene, claims.uid, &req.file_name) else {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不支持的文件类型");
+    };
+    OssResp {
+        upload_url: store.presign_put(&key, content_type, config.presign_expires())?,
+        download_url: store.public_url(&key),
+        content_type: content_type.to_string(),
+    }
+    .to_api_data()
+}
+
+/// 请求体是否为 JSON，图片等接口同时支持 JSON 参数和直接上传文件内容
+pub fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// 解析并校验 JSON 请求体
+pub fn json_body<T: DeserializeOwned + Validate>(body: &[u8]) -> Result<T, ApiError> {
+    let req: T = serde_json::from_slice(body)
+        .map_err(|error| ApiError::business(ErrorCode::InvalidParam, error.to_string()))?;
+    req.validate()?;
+    Ok(req)
+}
+
+/// 校验并保存用户直接上传的图片，返回对象路径
+pub async fn put_image(
+    store: &dyn ObjectStore,
+    scene: OssScene,
+    uid: i64,
+    data: Bytes,
+    max_bytes: usize,
+) -> Result<String, ApiError> {
+    if data.len() > max_bytes {
+        return Err(ApiError::business(
+            ErrorCode::PayloadTooLarge,
+            "图片文件过大",
+        ));
+    }
+    let Some(format) = ImageFormat::detect(&data) else {
+        return Err(ApiError::business(
+            ErrorCode::InvalidParam,
+            "不支持的图片格式",
+        ));
+    };
+    let Some((key, content_type)) =
+        object_key(scene, uid, &format!("image.{}", format.extension()))
+    else {
+        return Err(ApiError::business(
+            ErrorCode::InvalidParam,
+            "不支持的文件类型",
+        ));
+    };
+    store.put(&key, data.to_vec(), content_type).await?;
+    Ok(key)
+}
+
+/// 本地上传、下载 URL 的签名参数
+#[derive(Debug, Deserialize)]
+pub struct LocalUploadQuery {
+    /// 过期时间（Unix 时间戳）
+    pub expires: i64,
+    /// 签名
+    pub signature: String,
+}
+
+/// 上传文件到本地磁盘，URL 由 [`get_upload_url`] 生成
+pub async fn local_upload(
+    Path(key): Path<String>,
+    Query(query): Query<LocalUploadQuery>,
+    Extension(store): Extension<LocalStore>,
+    body: Bytes,
+) -> ApiResult<()> {
+    if let Err(error) = store.verify("PUT", &key, query.expires, &query.signature) {
+        return ApiError::business_err(ErrorCode::InvalidUploadUrl, error.to_string());
+    }
+    store.put(&key, body.to_vec(), "").await?;
+    ApiValue::success()
+}
+
+/// 从本地磁盘下载不公开的对象，URL 由 [`ObjectStore::presign_get`] 生成
+pub async fn local_download(
+    Path(key): Path<String>,
+    Query(query): Query<LocalUploadQuery>,
+    Extension(store): Extension<LocalStore>,
+) -> Result<Vec<u8>, StatusCode> {
+    if store
+        .verify("GET", &key, query.expires, &query.signature)
+        .is_err()
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match store.get(&key).await {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(error) => {
+            tracing::error!(%key, %error, "Failed to read local object.");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::body::{Body, Bytes};
+    use axum::extract::{Path, Query};
+    use axum::http::{Request, StatusCode};
+    use axum::Extension;
+    use tower::ServiceExt;
+
+    use crate::handler::api::ErrorCode;
+    use crate::handler::auth::Claims;
+    use crate::handler::oss::LocalUploadQuery;
+    use crate::handler::oss::{get_upload_url, local_route, local_upload, put_image};
+    use crate::handler::oss::{OssResp, UploadUrlReq};
+    use crate::handler::valid::Valid;
+    use crate::storage::oss::local::{LocalConfig, LocalStore};
+    use crate::storage::oss::{ObjectStore, OssConfig, OssScene};
+
+    #[tokio::test]
+    async fn local_upload_url() -> anyhow::Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("mallchat-oss-handler-{}", std::process::id()));
+        let store = LocalStore::new(LocalConfig {
+            path: root.clone(),
+            ..Default::default()
+        });
+        let req = UploadUrlReq {
+            file_name: "photo.png".to_string(),
+            scene: OssScene::Chat,
+        };
+        let resp = get_upload_url(
+            Claims::from(1),
+            Extension(Arc::new(store.clone())),
+            Extension(OssConfig::default()),
+            Valid(Query(req)),
+        )
+        .await?;
+        let resp: OssResp = serde_json::from_value(serde_json::to_value(resp)?["data"].clone())?;
+        assert!(resp.upload_url.starts_with("/capi/oss/local/chat/"));
+        assert!(resp.download_url.starts_with("/oss/chat/"));
+        assert!(resp.download_url.ends_with(".png"));
+
+        let router = local_route(store.clone());
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(&resp.upload_url)
+                    .body(Body::from("png"))
+                    .map_err(anyhow::Error::from)?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = router
+            .oneshot(
+                Request::get(&resp.download_url)
+                    .body(Body::empty())
+                    .map_err(anyhow::Error::from)?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(response.headers()["content-disposition"], "attachment");
+        assert!(response.headers().contains_key("content-security-policy"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert_eq!(body, "png");
+
+        let req = UploadUrlReq {
+            file_name: "index.html".to_string(),
+            scene: OssScene::Chat,
+        };
+        let error = get_upload_url(
+            Claims::from(1),
+            Extension(Arc::new(store.clone())),
+            Extension(OssConfig::default()),
+            Valid(Query(req)),
+        )
+        .await
+        .err();
+        assert_eq!(
+            error.map(|error| error.code()),
+            Some(ErrorCode::InvalidParam)
+        );
+
+        let error = local_upload(
+            Path("chat/other.png".to_string()),
+            Query(LocalUploadQuery {
+                expires: i64::MAX,
+                signature: "00".to_string(),
+            }),
+            Extension(store),
+            Bytes::from_static(b"png"),
+        )
+        .await
+        .err();
+        assert_eq!(
+            error.map(|error| error.code()),
+            Some(ErrorCode::InvalidUploadUrl)
+        );
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_private_object() -> anyhow::Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("mallchat-oss-private-{}", std::process::id()));
+        let store = LocalStore::new(LocalConfig {
+            path: root.clone(),
+            ..Default::default()
+        });
+        let key = "archive/message/1/1-2-0123456789abcdef.jsonl";
+        store.put(key, b"{}\n".to_vec(), "").await?;
+        let router = local_route(store.clone());
+        let get = |uri: &str| Request::get(uri).body(Body::empty());
+
+        // 上传场景以外的对象不公开
+        let response = router.clone().oneshot(get(&format!("/oss/{key}"))?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let upload_url = store.presign_put(key, "", Duration::from_secs(60))?;
+        let response = router.clone().oneshot(get(&upload_url)?).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let url = store.presign_get(key, Duration::from_secs(60))?;
+        let response = router.clone().oneshot(get(&url)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-disposition"], "attachment");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert_eq!(body, "{}\n");
+        let missing = store.presign_get("archive/missing.jsonl", Duration::from_secs(60))?;
+        let response = router.oneshot(get(&missing)?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn image() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("mallchat-oss-image-{}", std::process::id()));
+        let store = LocalStore::new(LocalConfig {
+            path: root.clone(),
+            ..Default::default()
+        });
+        let gif = Bytes::from_static(b"GIF89a....");
+        let key = put_image(&store, OssScene::Emoji, 1, gif.clone(), 1024).await?;
+        assert!(key.starts_with("emoji/") && key.ends_with(".gif"), "{key}");
+        assert_eq!(tokio::fs::read(root.join(&key)).await?, gif);
+
+        let error = put_image(&store, OssScene::Emoji, 1, gif, 4).await.err();
+        assert_eq!(
+            error.map(|error| error.code()),
+            Some(ErrorCode::PayloadTooLarge)
+        );
+        let svg = Bytes::from_static(b"<svg></svg>");
+        let error = put_image(&store, OssScene::Emoji, 1, svg, 1024).await.err();
+        assert_eq!(
+            error.map(|error| error.code()),
+            Some(ErrorCode::InvalidParam)
+        );
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,745 @@
+//! # 房间相关接口
+//!
+
+use crate::cache::Cache;
+use crate::handler::valid::Valid;
+use axum::extract::Query;
+use axum::routing::{delete, get, post, put};
+use axum::{Extension, Json, Router};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbErr, Set};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::handler::api::{ApiError, ApiResult, ApiValue, ErrorCode, ToApiData};
+use crate::handler::auth::{Claims, JwtKeys};
+use crate::handler::chat::{MessageResp, MessageStatus, MessageType};
+use crate::handler::friend::is_friend;
+use crate::handler::ws::push::{GroupChange, GroupChangeType};
+use crate::handler::ws::SessionManager;
+use crate::mq;
+use crate::mq::group::{push_group_change, GROUP_TOPIC};
+use crate::mq::message::{MessageEvent, MESSAGE_TOPIC};
+use crate::mq::DynProducer;
+use crate::service::group::{GroupRole, GroupService, MAX_GROUP_MEMBERS};
+use crate::service::role::{Role, RoleService};
+use crate::service::room::{RoomService, RoomType};
+use crate::shortlink::ShortLink;
+use crate::storage::model::{message, room};
+use crate::storage::repo::{MessageRepo, RoomRepo, UserRepo};
+use crate::storage::tx::with_txn;
+use crate::timestamp;
+
+/// 房间相关路由
+pub fn route() -> Router {
+    Router::new().nest(
+        "/capi/room",
+        Router::new()
+            .route("/single", post(get_or_create_single_room))
+            .route(
+                "/group",
+                get(get_group).post(create_group).delete(dissolve_group),
+            )
+            .route("/group/mute", put(mute_member))
+            .route("/group/announcement", put(set_group_announcement))
+            .route("/group/owner", put(transfer_group_owner))
+            .route("/group/member", delete(leave_group))
+            .route("/invite", post(create_invite))
+            .route("/join", post(join_group)),
+    )
+}
+
+/// 单聊房间请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleRoomReq {
+    /// 好友 uid
+    pub target_uid: i64,
+}
+
+/// 单聊房间信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleRoomResp {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 好友 uid
+    pub friend_uid: i64,
+}
+
+/// 获取（不存在则创建）与好友的单聊房间
+#[utoipa::path(
+    post,
+    path = "/capi/room/single",
+    request_body = SingleRoomReq,
+    responses(
+        (status = 200, description = "成功", body = SingleRoomData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_or_create_single_room(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<SingleRoomReq>>,
+) -> ApiResult<SingleRoomResp> {
+    if !is_friend(&db, claims.uid, req.target_uid).await? {
+        return ApiError::business_err(ErrorCode::NotFriends, "你们不是好友");
+    }
+
+    let room_friend = RoomService::new(&db)
+        .get_or_create_single(claims.uid, req.target_uid)
+        .await?;
+
+    SingleRoomResp {
+        room_id: room_friend.room_id,
+        friend_uid: req.target_uid,
+    }
+    .to_api_data()
+}
+
+/// 创建群聊请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateGroupReq {
+    /// 群名称
+    #[validate(length(min = 1, max = 32))]
+    pub name: String,
+}
+
+/// 群聊查询条件
+#[derive(Debug, Validate, Serialize, Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct GroupReq {
+    /// 房间 ID
+    pub room_id: i64,
+}
+
+/// 禁言请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MuteMemberReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 被禁言的成员 uid
+    pub uid: i64,
+    /// 禁言时间（分钟），最长 30 天，为 0 时解除禁言
+    #[validate(range(max = 43200))]
+    pub minutes: u32,
+}
+
+/// 修改群公告请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupAnnouncementReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 群公告，为空时清除公告
+    #[validate(length(max = 1024))]
+    pub announcement: String,
+}
+
+/// 转让群主请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferOwnerReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 新群主 uid
+    pub uid: i64,
+}
+
+/// 群聊信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResp {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 群名称
+    pub name: String,
+    /// 群主 uid，没有群主的群聊只能由聊天管理员管理
+    pub owner_uid: Option<i64>,
+    /// 群公告
+    pub announcement: Option<String>,
+    /// 有记录的成员数，包括群主、管理员和通过邀请加入的成员
+    pub member_count: u32,
+}
+
+/// 查找群聊房间，不存在或不是群聊时返回错误
+async fn find_group_room(db: &DatabaseConnection, room_id: i64) -> Result<room::Model, ApiError> {
+    match RoomRepo::find_by_id(db, room_id).await? {
+        Some(room) if room.r#type == RoomType::Group as i32 => Ok(room),
+        _ => Err(ApiError::business(ErrorCode::RoomNotFound, "群聊不存在")),
+    }
+}
+
+/// 是否可以禁言成员、修改群公告：群主、管理员或聊天管理员
+async fn can_manage<C: ConnectionTrait>(db: &C, room_id: i64, uid: i64) -> Result<bool, DbErr> {
+    if GroupService::new(db).role(room_id, uid).await?.can_manage() {
+        return Ok(true);
+    }
+    RoleService::new(db).has_role(uid, Role::ChatManager).await
+}
+
+/// 推送群聊变更，多实例部署时通过消息队列推送给各实例的连接，变更已保存，推送失败时不影响接口返回
+async fn publish_change(
+    session_manager: &SessionManager,
+    producer: Option<Extension<DynProducer>>,
+    change: GroupChange,
+) {
+    let room_id = change.room_id;
+    let result = match producer {
+        Some(Extension(producer)) => mq::send_json(producer.as_ref(), GROUP_TOPIC, &change)
+            .await
+            .map(|_| ()),
+        None => push_group_change(session_manager, change).map(|_| ()),
+    };
+    if let Err(error) = result {
+        tracing::error!(%room_id, %error, "Failed to publish group change.");
+    }
+}
+
+/// 查询群聊信息
+#[utoipa::path(
+    get,
+    path = "/capi/room/group",
+    params(GroupReq),
+    responses(
+        (status = 200, description = "成功", body = GroupData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_group(
+    _claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Query(req)): Valid<Query<GroupReq>>,
+) -> ApiResult<GroupResp> {
+    let room = find_group_room(&db, req.room_id).await?;
+    let service = GroupService::new(&db);
+    let group = service.find(req.room_id).await?;
+    GroupResp {
+        room_id: req.room_id,
+        name: room.name,
+        owner_uid: service.owner(req.room_id).await?,
+        announcement: group.and_then(|group| group.announcement),
+        member_count: room.member_count,
+    }
+    .to_api_data()
+}
+
+/// 创建群聊，创建者为群主
+#[utoipa::path(
+    post,
+    path = "/capi/room/group",
+    request_body = CreateGroupReq,
+    responses(
+        (status = 200, description = "成功", body = GroupData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn create_group(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Valid(Json(req)): Valid<Json<CreateGroupReq>>,
+) -> ApiResult<GroupResp> {
+    let room = with_txn(&db, |txn| {
+        Box::pin(async move { GroupService::new(txn).create(claims.uid, &req.name).await })
+    })
+    .await?;
+    tracing::info!(uid = claims.uid, room_id = %room.id, "Group created.");
+    GroupResp {
+        room_id: room.id as i64,
+        name: room.name,
+        owner_uid: Some(claims.uid),
+        announcement: None,
+        member_count: 1,
+    }
+    .to_api_data()
+}
+
+/// 禁言成员或解除禁言，需要群主、管理员或聊天管理员权限，群主不能被禁言，只能禁言群成员
+#[utoipa::path(
+    put,
+    path = "/capi/room/group/mute",
+    request_body = MuteMemberReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn mute_member(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<MuteMemberReq>>,
+) -> ApiResult<()> {
+    find_group_room(&db, req.room_id).await?;
+    if !can_manage(&db, req.room_id, claims.uid).await? {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "您没有管理该群的权限");
+    }
+    if req.uid == claims.uid {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不能禁言自己");
+    }
+    if UserRepo::find_by_id(&db, req.uid).await?.is_none() {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    }
+    // 禁言会写入成员记录，不能借此把非成员加入群聊
+    if !RoomRepo::is_group_member(&db, req.room_id, req.uid).await? {
+        return ApiError::business_err(ErrorCode::NotRoomMember, "该用户不是群成员");
+    }
+    let service = GroupService::new(&db);
+    if service.role(req.room_id, req.uid).await? == GroupRole::Owner {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "不能禁言群主");
+    }
+
+    let mute_until =
+        (req.minutes > 0).then(|| timestamp::now() + time::Duration::minutes(req.minutes as i64));
+    service.mute(req.room_id, req.uid, mute_until).await?;
+    tracing::info!(uid = claims.uid, room_id = %req.room_id, target = %req.uid, minutes = %req.minutes, "Group member muted.");
+    publish_change(
+        &session_manager,
+        producer,
+        GroupChange {
+            room_id: req.room_id,
+            change_type: GroupChangeType::Mute,
+            operator_uid: claims.uid,
+            uid: Some(req.uid),
+            mute_until,
+            announcement: None,
+        },
+    )
+    .await;
+    ApiValue::success()
+}
+
+/// 修改群公告并推送，需要群主、管理员或聊天管理员权限
+#[utoipa::path(
+    put,
+    path = "/capi/room/group/announcement",
+    request_body = GroupAnnouncementReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn set_group_announcement(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<GroupAnnouncementReq>>,
+) -> ApiResult<()> {
+    find_group_room(&db, req.room_id).await?;
+    if !can_manage(&db, req.room_id, claims.uid).await? {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "您没有管理该群的权限");
+    }
+
+    GroupService::new(&db)
+        .set_announcement(req.room_id, &req.announcement)
+        .await?;
+    tracing::info!(uid = claims.uid, room_id = %req.room_id, "Group announcement updated.");
+    publish_change(
+        &session_manager,
+        producer,
+        GroupChange {
+            room_id: req.room_id,
+            change_type: GroupChangeType::Announcement,
+            operator_uid: claims.uid,
+            uid: None,
+            mute_until: None,
+            announcement: Some(req.announcement),
+        },
+    )
+    .await;
+    ApiValue::success()
+}
+
+/// 转让群主，只有群主可以操作，只能转让给群成员，原群主成为普通成员
+#[utoipa::path(
+    put,
+    path = "/capi/room/group/owner",
+    request_body = TransferOwnerReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn transfer_group_owner(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Json(req)): Valid<Json<TransferOwnerReq>>,
+) -> ApiResult<()> {
+    find_group_room(&db, req.room_id).await?;
+    if GroupService::new(&db).role(req.room_id, claims.uid).await? != GroupRole::Owner {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "只有群主可以转让群聊");
+    }
+    if req.uid == claims.uid {
+        return ApiError::business_err(ErrorCode::InvalidParam, "不能转让给自己");
+    }
+    if UserRepo::find_by_id(&db, req.uid).await?.is_none() {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    }
+    if !RoomRepo::is_group_member(&db, req.room_id, req.uid).await? {
+        return ApiError::business_err(ErrorCode::NotRoomMember, "该用户不是群成员");
+    }
+
+    let room_id = req.room_id;
+    let uid = req.uid;
+    with_txn(&db, |txn| {
+        Box::pin(async move {
+            GroupService::new(txn)
+                .transfer_owner(room_id, claims.uid, uid)
+                .await
+        })
+    })
+    .await?;
+    tracing::info!(uid = claims.uid, %room_id, owner = %uid, "Group owner transferred.");
+    publish_change(
+        &session_manager,
+        producer,
+        GroupChange {
+            room_id,
+            change_type: GroupChangeType::TransferOwner,
+            operator_uid: claims.uid,
+            uid: Some(uid),
+            mute_until: None,
+            announcement: None,
+        },
+    )
+    .await;
+    ApiValue::success()
+}
+
+/// 解散群聊，只有群主可以操作，房间逻辑删除，成员记录和历史消息保留，管理员可以恢复
+#[utoipa::path(
+    delete,
+    path = "/capi/room/group",
+    request_body = GroupReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn dissolve_group(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    cache: Option<Extension<Cache>>,
+    Valid(Json(req)): Valid<Json<GroupReq>>,
+) -> ApiResult<()> {
+    find_group_room(&db, req.room_id).await?;
+    let group = GroupService::new(&db);
+    if group.role(req.room_id, claims.uid).await? != GroupRole::Owner {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "只有群主可以解散群聊");
+    }
+
+    let room_id = req.room_id;
+    group.dissolve(room_id).await?;
+    tracing::info!(uid = claims.uid, %room_id, "Group dissolved.");
+    if let Some(Extension(cache)) = cache {
+        cache.local().invalidate_room(room_id).await;
+    }
+    publish_change(
+        &session_manager,
+        producer,
+        GroupChange {
+            room_id,
+            change_type: GroupChangeType::Dissolve,
+            operator_uid: claims.uid,
+            uid: None,
+            mute_until: None,
+            announcement: None,
+        },
+    )
+    .await;
+    ApiValue::success()
+}
+
+/// 退出群聊，删除成员记录，群主需要先转让群聊
+#[utoipa::path(
+    delete,
+    path = "/capi/room/group/member",
+    request_body = GroupReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn leave_group(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    cache: Option<Extension<Cache>>,
+    Valid(Json(req)): Valid<Json<GroupReq>>,
+) -> ApiResult<()> {
+    find_group_room(&db, req.room_id).await?;
+    if GroupService::new(&db).role(req.room_id, claims.uid).await? == GroupRole::Owner {
+        return ApiError::business_err(ErrorCode::PermissionDenied, "群主需要先转让群聊");
+    }
+
+    let room_id = req.room_id;
+    let left = with_txn(&db, |txn| {
+        Box::pin(async move { GroupService::new(txn).leave(room_id, claims.uid).await })
+    })
+    .await?;
+    if !left {
+        return ApiValue::success();
+    }
+    tracing::info!(uid = claims.uid, %room_id, "Left group.");
+    if let Some(Extension(cache)) = cache {
+        cache.local().invalidate_room(room_id).await;
+    }
+    publish_change(
+        &session_manager,
+        producer,
+        GroupChange {
+            room_id,
+            change_type: GroupChangeType::Leave,
+            operator_uid: claims.uid,
+            uid: Some(claims.uid),
+            mute_until: None,
+            announcement: None,
+        },
+    )
+    .await;
+    ApiValue::success()
+}
+
+/// 邀请 token 中的数据，使用 JWT 的密钥签名
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct InviteClaims {
+    /// 房间 ID
+    room_id: i64,
+    /// 邀请人 uid
+    inviter: i64,
+    /// 过期时间，秒级时间戳
+    exp: i64,
+}
+
+/// 签发邀请 token
+fn sign_invite(jwt_keys: &JwtKeys, claims: &InviteClaims) -> Result<String, ApiError> {
+    Ok(jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        claims,
+        jwt_keys.encoding_key(),
+    )?)
+}
+
+/// 验证邀请 token，签名错误或已过期时返回错误
+fn verify_invite(jwt_keys: &JwtKeys, token: &str) -> Result<InviteClaims, ApiError> {
+    let mut validation = jsonwebtoken::Validation::default();
+    validation.leeway = 0;
+    jsonwebtoken::decode(token, jwt_keys.decoding_key(), &validation)
+        .map(|data| data.claims)
+        .map_err(|_| ApiError::business(ErrorCode::InvalidInvite, "邀请无效或已过期"))
+}
+
+/// 生成邀请请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteReq {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 有效期（小时），最长 7 天
+    #[validate(range(min = 1, max = 168))]
+    pub expire_hours: u32,
+}
+
+/// 邀请信息
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteResp {
+    /// 邀请 token，客户端放入邀请链接或生成二维码
+    pub token: String,
+    /// 过期时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub expire_time: PrimitiveDateTime,
+    /// 邀请页面的短链接，启用短链接并配置邀请页面时返回，有效期与邀请相同
+    pub short_url: Option<String>,
+}
+
+/// 加入群聊请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinGroupReq {
+    /// 邀请 token
+    #[validate(length(min = 1, max = 1024))]
+    pub token: String,
+}
+
+/// 生成群聊邀请，群成员或聊天管理员可以邀请
+#[utoipa::path(
+    post,
+    path = "/capi/room/invite",
+    request_body = InviteReq,
+    responses(
+        (status = 200, description = "成功", body = InviteData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn create_invite(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    shortlink: Option<Extension<ShortLink>>,
+    Valid(Json(req)): Valid<Json<InviteReq>>,
+) -> ApiResult<InviteResp> {
+    find_group_room(&db, req.room_id).await?;
+    let is_member = GroupService::new(&db)
+        .member(req.room_id, claims.uid)
+        .await?
+        .is_some();
+    if !is_member
+        && !RoleService::new(&db)
+            .has_role(claims.uid, Role::ChatManager)
+            .await?
+    {
+        return ApiError::business_err(ErrorCode::NotRoomMember, "您不是该群的成员");
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let expire = now + time::Duration::hours(req.expire_hours as i64);
+    let token = sign_invite(
+        &jwt_keys,
+        &InviteClaims {
+            room_id: req.room_id,
+            inviter: claims.uid,
+            exp: expire.unix_timestamp(),
+        },
+    )?;
+    let short_url = match shortlink {
+        Some(Extension(shortlink)) => {
+            shortlink
+                .create_invite(&token, req.expire_hours as u64 * 3600)
+                .await?
+        }
+        None => None,
+    };
+    InviteResp {
+        token,
+        expire_time: PrimitiveDateTime::new(expire.date(), expire.time()),
+        short_url,
+    }
+    .to_api_data()
+}
+
+/// 通过邀请加入群聊，群成员已满时返回错误，加入后在群中发送系统消息
+#[utoipa::path(
+    post,
+    path = "/capi/room/join",
+    request_body = JoinGroupReq,
+    responses(
+        (status = 200, description = "成功", body = GroupData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn join_group(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    producer: Option<Extension<DynProducer>>,
+    cache: Option<Extension<Cache>>,
+    Valid(Json(req)): Valid<Json<JoinGroupReq>>,
+) -> ApiResult<GroupResp> {
+    let invite = verify_invite(&jwt_keys, &req.token)?;
+    let room = find_group_room(&db, invite.room_id).await?;
+    let service = GroupService::new(&db);
+    let mut group = GroupResp {
+        room_id: invite.room_id,
+        name: room.name,
+        owner_uid: service.owner(invite.room_id).await?,
+        announcement: service
+            .find(invite.room_id)
+            .await?
+            .and_then(|group| group.announcement),
+        member_count: room.member_count,
+    };
+    if service.member(invite.room_id, claims.uid).await?.is_some() {
+        return group.to_api_data();
+    }
+    if u64::from(room.member_count) >= MAX_GROUP_MEMBERS {
+        return ApiError::business_err(ErrorCode::GroupFull, "群成员已满");
+    }
+    let Some(user) = UserRepo::find_by_id(&db, claims.uid).await? else {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    };
+
+    let room_id = invite.room_id;
+    let content = format!("{} 加入了群聊", user.name.as_deref().unwrap_or_default());
+    let message = with_txn(&db, |txn| {
+        Box::pin(async move {
+            GroupService::new(txn).join(room_id, claims.uid).await?;
+            let message = MessageRepo::create(
+                txn,
+                message::ActiveModel {
+                    room_id: Set(room_id),
+                    from_uid: Set(claims.uid),
+                    content: Set(content),
+                    status: Set(MessageStatus::Normal as i32),
+                    r#type: Set(Some(MessageType::System as i32)),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            RoomRepo::refresh_last_message(txn, &message).await?;
+            Ok::<_, DbErr>(message)
+        })
+    })
+    .await?;
+    tracing::info!(uid = claims.uid, %room_id, inviter = %invite.inviter, "Joined group by invite.");
+    group.member_count += 1;
+    if let Some(Extension(cache)) = cache {
+        cache.local().invalidate_room(room_id).await;
+    }
+
+    // 系统消息与普通消息一样通过消息队列推送给群成员，发送失败时不影响接口返回
+    if let Some(Extension(producer)) = producer {
+        let event = MessageEvent {
+            message: MessageResp::from(message),
+            receivers: service.receivers(room_id).await?,
+        };
+        if let Err(error) = mq::send_json(producer.as_ref(), MESSAGE_TOPIC, &event).await {
+            tracing::error!(id = %event.message.id, %error, "Failed to publish message event.");
+        }
+    }
+    group.to_api_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::auth::JwtKeys;
+    use crate::handler::room::{sign_invite, verify_invite, InviteClaims};
+
+    #[test]
+    fn invite_token() -> anyhow::Result<()> {
+        let jwt_keys = JwtKeys::try_from("c2VjcmV0")?;
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let claims = InviteClaims {
+            room_id: 2,
+            inviter: 1,
+            exp: now + 60,
+        };
+        let token = sign_invite(&jwt_keys, &claims)?;
+        assert_eq!(verify_invite(&jwt_keys, &token)?, claims);
+
+        let other = JwtKeys::try_from("b3RoZXI=")?;
+        assert!(verify_invite(&other, &token).is_err());
+
+        let expired = sign_invite(
+            &jwt_keys,
+            &InviteClaims {
+                exp: now - 1,
+                ..claims
+            },
+        )?;
+        assert!(verify_invite(&jwt_keys, &expired).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! # 短链接和二维码相关接口
+//!
+
+use axum::extract::{Path, Query};
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use axum::{Extension, Router};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::handler::valid::Valid;
+use crate::shortlink::{self, ShortLink};
+
+/// 短链接重定向路由
+pub fn route() -> Router {
+    Router::new().route("/s/:slug", get(redirect))
+}
+
+/// 二维码路由
+pub fn qr_route() -> Router {
+    Router::new().route("/capi/qr", get(get_qr))
+}
+
+/// 访问短链接，重定向到原 URL，不存在或已过期时返回 404
+pub async fn redirect(
+    Extension(shortlink): Extension<ShortLink>,
+    Path(slug): Path<String>,
+) -> Result<Redirect, ApiError> {
+    match shortlink.resolve(&slug).await? {
+        Some(url) => Ok(Redirect::temporary(&url)),
+        None => Err(ApiError::business(
+            ErrorCode::ShortLinkNotFound,
+            "链接不存在或已过期",
+        )),
+    }
+}
+
+/// 二维码请求
+#[derive(Debug, Validate, Serialize, Deserialize)]
+pub struct QrReq {
+    /// 二维码内容
+    #[validate(length(min = 1, max = 1024))]
+    pub data: String,
+    /// 图片边长（像素），默认 256
+    #[serde(default = "default_size")]
+    #[validate(range(min = 64, max = 1024))]
+    pub size: u32,
+}
+
+fn default_size() -> u32 {
+    256
+}
+
+/// 把 `data` 渲染为 PNG 二维码，相同参数的图片可以长期缓存
+pub async fn get_qr(Valid(Query(req)): Valid<Query<QrReq>>) -> Result<Response, ApiError> {
+    let png = shortlink::render_qr(req.data.as_bytes(), req.size)
+        .map_err(|error| ApiError::business(ErrorCode::InvalidParam, error.to_string()))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        png,
+    )
+        .into_response())
+}
@@ -0,0 +1,129 @@
+//! # HTTPS/WSS
+//!
+//! 没有部署在 nginx 等反向代理之后时，服务端可以直接使用 PEM 格式的证书和私钥提供 HTTPS、WSS。
+//! 启用 `reload` 后定期检查证书、私钥文件的修改时间，变化时重新加载，更新证书不需要重启服务。
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+/// TLS 配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// 证书文件路径，PEM 格式，包含完整的证书链
+    pub cert_path: PathBuf,
+    /// 私钥文件路径，PEM 格式
+    pub key_path: PathBuf,
+    /// 证书、私钥文件变化时是否自动重新加载
+    #[serde(default)]
+    pub reload: bool,
+    /// 检查文件变化的间隔（秒）
+    #[serde(default = "default::reload_interval_secs")]
+    pub reload_interval_secs: u64,
+}
+
+mod default {
+    pub fn reload_interval_secs() -> u64 {
+        60
+    }
+}
+
+impl TlsConfig {
+    /// 加载证书和私钥，`http2` 为 `false` 时 ALPN 只协商 HTTP/1.1
+    pub async fn load(&self, http2: bool) -> anyhow::Result<RustlsConfig> {
+        let config = RustlsConfig::from_pem_file(&self.cert_path, &self.key_path)
+            .await
+            .with_context(|| {
+                format!(
+                    "load tls certificate {} and key {}",
+                    self.cert_path.display(),
+                    self.key_path.display()
+                )
+            })?;
+        if !http2 {
+            http1_alpn(&config);
+        }
+        Ok(config)
+    }
+
+    /// 证书、私钥文件中较晚的修改时间
+    fn modified(&self) -> std::io::Result<SystemTime> {
+        let cert = std::fs::metadata(&self.cert_path)?.modified()?;
+        let key = std::fs::metadata(&self.key_path)?.modified()?;
+        Ok(cert.max(key))
+    }
+
+    /// 启用 `reload` 时启动检查文件变化的任务，文件变化后重新加载到 `config`
+    ///
+    /// 加载失败时继续使用原来的证书，等待下一次文件变化
+    pub fn watch(&self, config: RustlsConfig, http2: bool) -> Option<JoinHandle<()>> {
+        if !self.reload {
+            return None;
+        }
+        let tls = self.clone();
+        Some(tokio::spawn(async move {
+            let mut loaded = tls.modified().ok();
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(tls.reload_interval_secs.max(1)));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let modified = match tls.modified() {
+                    Ok(modified) => modified,
+                    Err(error) => {
+                        tracing::warn!(%error, "Failed to read tls certificate metadata.");
+                        continue;
+                    }
+                };
+                if loaded == Some(modified) {
+                    continue;
+                }
+                match config
+                    .reload_from_pem_file(&tls.cert_path, &tls.key_path)
+                    .await
+                {
+                    Ok(()) => {
+                        if !http2 {
+                            http1_alpn(&config);
+                        }
+                        tracing::info!(cert_path = ?tls.cert_path, "Tls certificate reloaded.")
+                    }
+                    Err(error) => {
+                        tracing::error!(%error, "Failed to reload tls certificate.");
+                    }
+                }
+                // 加载失败时同样记录，避免每次检查都重复加载同一份错误的文件
+                loaded = Some(modified);
+            }
+        }))
+    }
+}
+
+/// 重新加载时 ALPN 会恢复为 h2、http/1.1，需要在每次加载后设置
+fn http1_alpn(config: &RustlsConfig) {
+    let mut inner = (*config.get_inner()).clone();
+    inner.alpn_protocols = vec![b"http/1.1".to_vec()];
+    config.reload_from_config(Arc::new(inner));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::tls::TlsConfig;
+
+    #[tokio::test]
+    async fn missing_files() {
+        let config = TlsConfig {
+            cert_path: "not-exists/cert.pem".into(),
+            key_path: "not-exists/key.pem".into(),
+            reload: false,
+            reload_interval_secs: 60,
+        };
+        assert!(config.load(true).await.is_err());
+        assert!(config.modified().is_err());
+    }
+}
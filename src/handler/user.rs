@@ -1,11 +1,36 @@
 //! # 用户管理相关接口
 //!
 
-use axum::routing::{get, put};
-use axum::Router;
+use crate::cache::user_info::{UserInfo, UserInfoCache};
+use crate::cache::Cache;
+use crate::handler::valid::Valid;
+use axum::body::Bytes;
+use axum::extract::Query;
+use axum::http::HeaderMap;
+use axum::routing::{get, post, put};
+use axum::{Extension, Json, Router};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::handler::api::{ApiResult, ApiValue};
-use crate::handler::auth::Claims;
+use crate::captcha::Captcha;
+use crate::handler::api::{ApiError, ApiResult, ApiValue, ErrorCode, ToApiData};
+use crate::handler::auth::{Claims, JwtKeys};
+use crate::handler::captcha::{self, CaptchaAnswer};
+use crate::handler::oss;
+use crate::handler::ws::push::{UserInfoChange, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::ip::IpInfo;
+use crate::mq::kick::{kick, KickEvent};
+use crate::mq::DynProducer;
+use crate::service::device::{DeviceService, LoginDevice};
+use crate::service::item::{Item, ItemService};
+use crate::storage::is_duplicate_key;
+use crate::storage::oss::{is_owned_key, DynObjectStore, ImageFormat, OssConfig, OssScene};
+use crate::storage::repo::{DynUserRepo, UserRepo};
+use crate::storage::tx::with_txn;
 
 /// 用户管理相关路由
 pub fn route() -> Router {
@@ -13,33 +38,392 @@ pub fn route() -> Router {
         "/capi/user",
         Router::new()
             .route("/userInfo", get(get_user_info))
+            .route("/public/info/batch", post(batch_user_info))
             .route("/name", put(modify_name))
+            .route("/avatar", put(modify_avatar))
             .route("/badges", get(badges))
-            .route("/badge", put(wearing_badge)),
+            .route("/badge", put(wearing_badge))
+            .route("/devices", get(devices).delete(logout_device)),
     )
 }
 
 /// 用户详情
-#[utoipa::path(get, path = "/capi/user/userInfo")]
-pub async fn get_user_info(_claims: Claims) -> ApiResult<()> {
-    tracing::info!("get_user_info");
-    ApiValue::success()
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfoResp {
+    /// 用户 ID
+    pub id: u64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+    /// 性别 1为男性，2为女性
+    pub sex: Option<i32>,
+    /// 最近登录的 IP 归属地
+    pub region: Option<String>,
+}
+
+/// 用户详情
+#[utoipa::path(
+    get,
+    path = "/capi/user/userInfo",
+    responses(
+        (status = 200, description = "成功", body = UserInfoData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn get_user_info(
+    claims: Claims,
+    Extension(users): Extension<DynUserRepo>,
+) -> ApiResult<UserInfoResp> {
+    let Some(user) = users.find_by_id(claims.uid).await? else {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    };
+    UserInfoResp {
+        id: user.id,
+        region: IpInfo::from_json(user.ip_info.as_ref()).region(),
+        name: user.name,
+        avatar: user.avatar,
+        sex: user.sex,
+    }
+    .to_api_data()
+}
+
+/// 已缓存的用户信息版本
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfoVersion {
+    /// 用户 ID
+    pub uid: i64,
+    /// 客户端缓存的版本号，没有缓存时为空
+    pub version: Option<i64>,
+}
+
+/// 批量获取用户信息请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfoBatchReq {
+    /// 要获取的用户，最多 100 个
+    #[validate(length(min = 1, max = 100))]
+    pub req_list: Vec<UserInfoVersion>,
+}
+
+/// 批量获取用户信息，只返回版本号与客户端缓存的不同的用户，不存在的用户忽略
+#[utoipa::path(
+    post,
+    path = "/capi/user/public/info/batch",
+    request_body = UserInfoBatchReq,
+    responses(
+        (status = 200, description = "成功", body = UserInfoListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn batch_user_info(
+    Extension(cache): Extension<Cache>,
+    Extension(users): Extension<DynUserRepo>,
+    Valid(Json(req)): Valid<Json<UserInfoBatchReq>>,
+) -> ApiResult<Vec<UserInfo>> {
+    let cached: HashMap<i64, Option<i64>> = req
+        .req_list
+        .into_iter()
+        .map(|user| (user.uid, user.version))
+        .collect();
+    let uids: Vec<i64> = cached.keys().copied().collect();
+    UserInfoCache::new(&cache, users.as_ref())
+        .get_many(&uids)
+        .await?
+        .into_iter()
+        .filter(|info| cached.get(&info.uid) != Some(&Some(info.version)))
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 资料修改后更新用户信息的版本号，失败时客户端最多在缓存过期后看到修改
+async fn bump_user_info(cache: &Cache, users: &dyn UserRepo, uid: i64) {
+    if let Err(error) = UserInfoCache::new(cache, users).bump(uid).await {
+        tracing::warn!(%uid, %error, "Failed to bump user info version.");
+    }
 }
 
-/// 修改用户名
-#[utoipa::path(put, path = "/capi/user/name")]
-pub async fn modify_name() -> ApiResult<()> {
+/// 修改用户名请求
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifyNameReq {
+    /// 新的用户名
+    #[validate(length(min = 1, max = 6))]
+    pub name: String,
+    /// 验证码，短时间内多次改名后需要
+    #[validate]
+    pub captcha: Option<CaptchaAnswer>,
+}
+
+/// 修改用户名，消耗一张改名卡
+#[utoipa::path(
+    put,
+    path = "/capi/user/name",
+    request_body = ModifyNameReq,
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn modify_name(
+    claims: Claims,
+    Extension(db): Extension<DatabaseConnection>,
+    captcha: Option<Extension<Captcha>>,
+    cache: Option<Extension<Cache>>,
+    Valid(Json(req)): Valid<Json<ModifyNameReq>>,
+) -> ApiResult<()> {
+    if let Some(Extension(captcha)) = captcha {
+        if captcha.rename_attempt(claims.uid).await? {
+            captcha::check(&captcha, req.captcha.as_ref()).await?;
+        }
+    }
+    with_txn(&db, |txn| {
+        Box::pin(async move {
+            if let Some(user) = UserRepo::find_by_name(txn, &req.name).await? {
+                if user.id as i64 == claims.uid {
+                    return Ok(());
+                }
+                return Err(ApiError::business(ErrorCode::NameTaken, "名字已被抢占"));
+            }
+            if !ItemService::new(txn)
+                .consume(claims.uid, Item::ModifyNameCard)
+                .await?
+            {
+                return Err(ApiError::business(ErrorCode::NoRenameCard, "改名卡不足"));
+            }
+            // 并发改成同一个名字时由唯一索引兜底，回滚已消耗的改名卡
+            UserRepo::update_name(txn, claims.uid, &req.name)
+                .await
+                .map_err(|error| {
+                    if is_duplicate_key(&error) {
+                        ApiError::business(ErrorCode::NameTaken, "名字已被抢占")
+                    } else {
+                        error.into()
+                    }
+                })?;
+            Ok(())
+        })
+    })
+    .await?;
+    if let Some(Extension(cache)) = cache {
+        bump_user_info(&cache, &db, claims.uid).await;
+    }
+
     ApiValue::success()
 }
 
+/// 修改头像请求，使用已通过 `/capi/oss/upload/url` 上传的头像
+#[derive(Debug, Validate, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifyAvatarReq {
+    /// 对象路径，上传场景必须为头像
+    #[validate(length(min = 1, max = 256))]
+    pub key: String,
+}
+
+/// 修改头像结果
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AvatarResp {
+    /// 新的头像 URL
+    pub avatar: String,
+}
+
+/// 修改头像
+///
+/// `Content-Type` 为 `application/json` 时请求体为 [`ModifyAvatarReq`]，否则请求体为图片内容，
+/// 支持 PNG、JPEG、GIF、WebP。修改后通知所有在线用户
+#[utoipa::path(
+    put,
+    path = "/capi/user/avatar",
+    request_body(
+        content = ModifyAvatarReq,
+        description = "已上传的头像，或者直接上传图片内容（Content-Type 为 image/*）"
+    ),
+    responses(
+        (status = 200, description = "成功", body = AvatarData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn modify_avatar(
+    claims: Claims,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(store): Extension<DynObjectStore>,
+    Extension(config): Extension<OssConfig>,
+    session_manager: Option<Extension<SessionManager>>,
+    cache: Option<Extension<Cache>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<AvatarResp> {
+    let key = if oss::is_json(&headers) {
+        let req: ModifyAvatarReq = oss::json_body(&body)?;
+        let format = req
+            .key
+            .rsplit_once('.')
+            .and_then(|(_, extension)| ImageFormat::from_extension(extension));
+        if format.is_none() || !is_owned_key(&req.key, OssScene::Avatar, claims.uid) {
+            return ApiError::business_err(ErrorCode::InvalidParam, "头像路径无效");
+        }
+        req.key
+    } else {
+        let max_bytes = config.avatar_max_bytes;
+        oss::put_image(
+            store.as_ref(),
+            OssScene::Avatar,
+            claims.uid,
+            body,
+            max_bytes,
+        )
+        .await?
+    };
+
+    let Some(user) = users.find_by_id(claims.uid).await? else {
+        return ApiError::business_err(ErrorCode::UserNotFound, "用户不存在");
+    };
+    let avatar = store.public_url(&key);
+    users.update_avatar(claims.uid, &avatar).await?;
+    if let Some(Extension(cache)) = cache {
+        bump_user_info(&cache, users.as_ref(), claims.uid).await;
+    }
+
+    if let Some(Extension(session_manager)) = session_manager {
+        let push = WsPush::UserInfoChange(UserInfoChange {
+            uid: claims.uid,
+            name: user.name,
+            avatar: Some(avatar.clone()),
+        });
+        if let Err(error) = session_manager.broadcast_all(&push, true) {
+            tracing::warn!(uid = claims.uid, %error, "Failed to push user info change.");
+        }
+    }
+
+    AvatarResp { avatar }.to_api_data()
+}
+
 /// 可选徽章预览
-#[utoipa::path(get, path = "/capi/user/badges")]
+#[utoipa::path(
+    get,
+    path = "/capi/user/badges",
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
 pub async fn badges() -> ApiResult<()> {
     ApiValue::success()
 }
 
 /// 佩戴徽章
-#[utoipa::path(put, path = "/capi/user/badge")]
+#[utoipa::path(
+    put,
+    path = "/capi/user/badge",
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
 pub async fn wearing_badge() -> ApiResult<()> {
     ApiValue::success()
 }
+
+/// 登录设备
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceResp {
+    /// 设备 ID
+    pub device_id: String,
+    /// 登录时的 User-Agent
+    pub user_agent: Option<String>,
+    /// 登录时的 IP
+    pub ip: Option<String>,
+    /// 最近一次登录的时间（毫秒时间戳）
+    pub login_time: i64,
+    /// 是否为当前请求使用的设备
+    pub current: bool,
+}
+
+impl DeviceResp {
+    fn new(device: LoginDevice, current: Option<&str>) -> Self {
+        Self {
+            current: current == Some(device.device_id.as_str()),
+            device_id: device.device_id,
+            user_agent: device.user_agent,
+            ip: device.ip,
+            login_time: device.login_time,
+        }
+    }
+}
+
+/// 最近登录的设备，最近登录的在前
+#[utoipa::path(
+    get,
+    path = "/capi/user/devices",
+    responses(
+        (status = 200, description = "成功", body = DeviceListData),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn devices(
+    claims: Claims,
+    Extension(cache): Extension<Cache>,
+) -> ApiResult<Vec<DeviceResp>> {
+    DeviceService::new(&cache)
+        .list(claims.uid)
+        .await?
+        .into_iter()
+        .map(|device| DeviceResp::new(device, claims.device.as_deref()))
+        .collect::<Vec<_>>()
+        .to_api_data()
+}
+
+/// 下线设备请求
+#[derive(Debug, Validate, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct LogoutDeviceReq {
+    /// 设备 ID
+    #[validate(length(min = 1, max = 64))]
+    pub device_id: String,
+}
+
+/// 下线设备：吊销该设备上的 token 并关闭其连接，可以下线当前设备
+#[utoipa::path(
+    delete,
+    path = "/capi/user/devices",
+    params(LogoutDeviceReq),
+    responses(
+        (status = 200, description = "成功", body = ApiSuccess),
+        (status = "default", description = "失败", body = ApiErrorResp),
+    )
+)]
+pub async fn logout_device(
+    claims: Claims,
+    Extension(cache): Extension<Cache>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    Extension(session_manager): Extension<SessionManager>,
+    producer: Option<Extension<DynProducer>>,
+    Valid(Query(req)): Valid<Query<LogoutDeviceReq>>,
+) -> ApiResult<()> {
+    let uid = claims.uid;
+    if !DeviceService::new(&cache)
+        .remove(uid, &req.device_id)
+        .await?
+    {
+        return ApiError::business_err(ErrorCode::DeviceNotFound, "设备不存在");
+    }
+    jwt_keys.revoke_device(uid, &req.device_id).await?;
+    let event = KickEvent {
+        uid,
+        device: Some(req.device_id.clone()),
+        reason: "Logged out from another device".to_string(),
+    };
+    let producer = producer
+        .as_ref()
+        .map(|Extension(producer)| producer.as_ref());
+    kick(&session_manager, producer, event).await;
+    tracing::info!(%uid, device = %req.device_id, "Device logged out");
+    ApiValue::success()
+}
@@ -0,0 +1,236 @@
+//! # 参数校验
+//!
+//! 提取请求参数后使用 [`validator`] 校验，提取失败、校验失败都返回 [`ApiError`]：
+//!
+//! ```json
+//! {"success":false,"errCode":9001,"errMsg":"pageSize: must be in 1..=100","details":[...]}
+//! ```
+
+use axum::async_trait;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Query, Request};
+use axum::http::request::Parts;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationErrors};
+
+use crate::handler::api::{ApiError, ErrorCode};
+
+/// 提取并校验请求参数，用法与 `Json`、`Query` 相同：`Valid(Json(req)): Valid<Json<Req>>`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Valid<E>(pub E);
+
+/// 可以校验的提取器
+pub trait HasValidate {
+    /// 需要校验的参数
+    type Validate: Validate;
+    /// 获取需要校验的参数
+    fn get_validate(&self) -> &Self::Validate;
+}
+
+impl<T: Validate> HasValidate for Json<T> {
+    type Validate = T;
+    fn get_validate(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Validate> HasValidate for Query<T> {
+    type Validate = T;
+    fn get_validate(&self) -> &T {
+        &self.0
+    }
+}
+
+/// 提取失败的原因转换为 [`ApiError`]
+pub trait RejectionMessage {
+    /// 错误消息
+    fn message(&self) -> String;
+}
+
+impl RejectionMessage for JsonRejection {
+    fn message(&self) -> String {
+        self.body_text()
+    }
+}
+
+impl RejectionMessage for QueryRejection {
+    fn message(&self) -> String {
+        self.body_text()
+    }
+}
+
+#[async_trait]
+impl<S, E> FromRequest<S> for Valid<E>
+where
+    S: Send + Sync + 'static,
+    E: HasValidate + FromRequest<S>,
+    E::Rejection: RejectionMessage + IntoResponse,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, ApiError> {
+        let inner = E::from_request(req, state).await.map_err(reject)?;
+        inner.get_validate().validate()?;
+        Ok(Valid(inner))
+    }
+}
+
+#[async_trait]
+impl<S, E> FromRequestParts<S> for Valid<E>
+where
+    S: Send + Sync + 'static,
+    E: HasValidate + FromRequestParts<S>,
+    E::Rejection: RejectionMessage + IntoResponse,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        let inner = E::from_request_parts(parts, state).await.map_err(reject)?;
+        inner.get_validate().validate()?;
+        Ok(Valid(inner))
+    }
+}
+
+fn reject<R: RejectionMessage + IntoResponse>(rejection: R) -> ApiError {
+    let message = rejection.message();
+    let status = rejection.into_response().status();
+    if status.is_server_error() {
+        ApiError::custom(status, message)
+    } else {
+        ApiError::business(ErrorCode::InvalidParam, message)
+    }
+}
+
+/// 字段校验错误
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FieldError {
+    /// 字段名，与 JSON 中的字段名一致
+    pub field: String,
+    /// 校验规则，例如 `range`、`length`
+    pub code: String,
+    /// 错误消息
+    pub message: String,
+}
+
+/// 将校验错误按字段名排序后展开
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = errors.field_errors().into_iter().collect::<Vec<_>>();
+    fields.sort_by_key(|(field, _)| *field);
+    fields
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            let field = camel_case(field);
+            errors.iter().map(move |error| FieldError {
+                field: field.clone(),
+                code: error.code.to_string(),
+                message: match &error.message {
+                    Some(message) => message.to_string(),
+                    None => describe(&error.code, &error.params),
+                },
+            })
+        })
+        .collect()
+}
+
+/// 校验错误消息，例如 `pageNo: must be in 1..; pageSize: must be in 1..=100`
+pub fn error_message(errors: &ValidationErrors) -> String {
+    field_errors(errors)
+        .into_iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// 参数结构体统一使用 `#[serde(rename_all = "camelCase")]`
+fn camel_case(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut upper = false;
+    for c in field.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn describe(
+    code: &str,
+    params: &std::collections::HashMap<std::borrow::Cow<'static, str>, serde_json::Value>,
+) -> String {
+    // validator 以浮点数保存 range 的上下限
+    let bound = |value: &serde_json::Value| match value.as_f64() {
+        Some(number) if number.fract() == 0.0 => format!("{}", number as i64),
+        _ => value.to_string(),
+    };
+    let bounds = match (params.get("min").map(bound), params.get("max").map(bound)) {
+        (Some(min), Some(max)) => format!("{min}..={max}"),
+        (Some(min), None) => format!("{min}.."),
+        (None, Some(max)) => format!("..={max}"),
+        (None, None) => String::new(),
+    };
+    match code {
+        "range" if !bounds.is_empty() => format!("must be in {bounds}"),
+        "length" if !bounds.is_empty() => format!("length must be in {bounds}"),
+        _ => format!("failed {code} validation"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Query;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use crate::handler::api::Pager;
+    use crate::handler::valid::Valid;
+
+    async fn get_json(uri: &str) -> anyhow::Result<(StatusCode, serde_json::Value)> {
+        let router =
+            Router::new().route(
+                "/",
+                get(|Valid(Query(pager)): Valid<Query<Pager>>| async move {
+                    pager.page_size.to_string()
+                }),
+            );
+        let response = router
+            .oneshot(Request::builder().uri(uri).body(Body::empty())?)
+            .await?;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn validation_error() -> anyhow::Result<()> {
+        let (status, body) = get_json("/?pageSize=101&pageNo=0").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["success"], false);
+        assert_eq!(body["errCode"], 9001);
+        assert_eq!(
+            body["errMsg"],
+            "pageNo: must be in 1..; pageSize: must be in 1..=100"
+        );
+        assert_eq!(body["details"][1]["field"], "pageSize");
+        assert_eq!(body["details"][1]["code"], "range");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejection() -> anyhow::Result<()> {
+        let (status, body) = get_json("/?pageSize=abc").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["errCode"], 9001);
+        Ok(())
+    }
+}
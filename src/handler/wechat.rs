@@ -1,22 +1,46 @@
 //! # 微信 API 交互接口
 //!
+//! 配置多个公众号时，所有公众号使用同一个回调地址：接收消息时按 ToUserName 选择公众号，
+//! 网页授权回调按 `state` 参数中的 app_id 选择公众号。
+//!
+//! 配置了企业微信应用时，[`work_route`] 提供企业微信的回调和网页扫码登录回调，
+//! 解密后的消息与公众号的消息使用相同的去重流程，扫码登录的用户与公众号扫码注册的用户同样注册并发放奖励。
 
-use crate::handler::auth::current_millisecond;
-use crate::handler::ws::{Resp, RespType, SessionManager};
+use crate::cache::Cache;
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::handler::auth::{ensure_not_banned, login_success, record_device, JwtKeys};
+use crate::handler::context;
+use crate::handler::valid::Valid;
+use crate::handler::ws::push::{SystemNotice, WsPush};
+use crate::handler::ws::store::SessionStore;
+use crate::handler::ws::SessionManager;
+use crate::i18n;
+use crate::live::{Live, ReplyTimeouts};
+use crate::service::item::ItemService;
+use crate::service::welcome::{send_welcome, WelcomeService};
+use crate::storage::model::user;
+use crate::storage::repo::{DynUserRepo, UserRepo};
+use crate::storage::tx::with_txn;
+use crate::webhook::{UserRegistered, WebhookEvent, Webhooks};
 use axum::extract::Query;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::{get, post};
 use axum::{Extension, Router};
-use axum_valid::Valid;
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use redis::AsyncCommands;
+use sea_orm::{DatabaseConnection, DbErr};
 use serde::Deserialize;
+use std::borrow::Cow;
+use std::num::NonZeroUsize;
+use std::time::Duration;
 use validator::Validate;
 
+use crate::weixin::reply::WxReply;
+use crate::weixin::work::{work_open_id, WorkCallbackParam, WorkClient, WorkEncryptedXmlMessage};
 use crate::weixin::xml::Xml;
 use crate::weixin::{
-    WxClient, WxConfig, WxEncryptedRawXmlMessage, WxEvent, WxEventType, WxMessage, WxMessageData,
-    WxRawXmlMessage, WxServerParam,
+    WxApi, WxClientRegistry, WxConfig, WxEncryptedRawXmlMessage, WxEvent, WxEventType, WxMessage,
+    WxMessageData, WxRawXmlMessage, WxServerParam, WxXmlRecipient,
 };
 
 /// 微信 API 相关路由
@@ -30,6 +54,17 @@ pub fn route() -> Router {
     )
 }
 
+/// 企业微信相关路由
+pub fn work_route() -> Router {
+    Router::new().nest(
+        "/wx/work",
+        Router::new()
+            .route("/", get(work_echo_str))
+            .route("/", post(work_post))
+            .route("/callBack", get(work_call_back)),
+    )
+}
+
 /// 认证参数
 #[derive(Debug, Validate, Deserialize)]
 pub struct EchoStr {
@@ -38,13 +73,16 @@ pub struct EchoStr {
     pub echostr: String,
 }
 
-///认证
+///认证，任意一个公众号的令牌验证通过即可
 #[utoipa::path(get, path = "/wx/portal/public")]
 pub async fn echo_str(
-    Extension(wx): Extension<WxClient>,
+    Extension(wx_clients): Extension<WxClientRegistry>,
     Valid(Query(param)): Valid<Query<WxServerParam<EchoStr>>>,
 ) -> impl IntoResponse {
-    if param.is_signature_valid(wx.token()) {
+    if wx_clients
+        .iter()
+        .any(|wx| param.is_signature_valid(wx.token()))
+    {
         (StatusCode::OK, param.data.echostr)
     } else {
         (StatusCode::BAD_REQUEST, String::new())
@@ -57,18 +95,23 @@ pub struct CallBackParam {
     /// code
     #[validate(length(min = 1))]
     pub code: String,
+    /// 发起授权的公众号的 app_id，为空时使用默认公众号
+    pub state: Option<String>,
 }
 
 /// 认证回调
 #[utoipa::path(get, path = "/wx/portal/public/callBack")]
 pub async fn call_back(
-    Valid(Query(CallBackParam { code })): Valid<Query<CallBackParam>>,
-    Extension(wx_client): Extension<WxClient>,
+    Valid(Query(CallBackParam { code, state })): Valid<Query<CallBackParam>>,
+    Extension(wx_clients): Extension<WxClientRegistry>,
 ) -> super::api::Result<Redirect> {
+    let wx_client = state
+        .and_then(|app_id| wx_clients.get(&app_id))
+        .unwrap_or_else(|| wx_clients.default_client());
     let access_token = wx_client
         .get_webpage_authorization_access_token(&code)
         .await?;
-    let _user_info = wx_client.get_user_info(&access_token.access_token).await?;
+    wx_client.get_user_info(&access_token.access_token).await?;
     // WxOAuth2AccessToken accessToken = wxService.getOAuth2Service().getAccessToken(code);
     // WxOAuth2UserInfo userInfo = wxService.getOAuth2Service().getUserInfo(accessToken, "zh_CN");
     // wxMsgService.authorize(userInfo);
@@ -88,34 +131,64 @@ pub struct PostParam {
 
 /// post
 #[utoipa::path(post, path = "/wx/portal/public")]
+#[allow(clippy::too_many_arguments)]
 pub async fn wx_post(
     Valid(Query(param)): Valid<Query<WxServerParam<PostParam>>>,
-    Extension(wx_app): Extension<WxClient>,
+    Extension(wx_clients): Extension<WxClientRegistry>,
     Extension(connection): Extension<DatabaseConnection>,
+    Extension(users): Extension<DynUserRepo>,
     Extension(session_manager): Extension<SessionManager>,
+    Extension(cache): Extension<Cache>,
+    reply_timeouts: Option<Extension<Live<ReplyTimeouts>>>,
+    webhooks: Option<Extension<Webhooks>>,
     data: String,
 ) -> Response {
     tracing::info!(?param, %data, "wx_post");
 
+    let to_user_name = match quick_xml::de::from_str::<WxXmlRecipient>(&data) {
+        Ok(recipient) => recipient.to_user_name,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let Some(wx_app) = wx_clients.route(&to_user_name) else {
+        tracing::warn!(%to_user_name, "Received a message for unknown official account.");
+        return StatusCode::BAD_REQUEST.into_response();
+    };
     if !param.is_signature_valid(wx_app.token()) {
         return StatusCode::BAD_REQUEST.into_response();
     }
 
     let message = if let Some(encrypt_type) = param.data.encrypt_type {
         if encrypt_type.eq_ignore_ascii_case("aes") {
-            let encrypted_message = match serde_xml_rs::from_str::<WxEncryptedRawXmlMessage>(&data)
+            let encrypted_message = match quick_xml::de::from_str::<WxEncryptedRawXmlMessage>(&data)
             {
                 Ok(message) => message,
                 Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
             };
 
-            let (from_app_id, message) =
-                match encrypted_message.aes_decrypt(wx_app.encoding_aes_key()) {
-                    Ok(decrypted) => decrypted,
-                    Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
-                };
+            let crypto = wx_app.crypto();
+            let msg_signature_valid =
+                param
+                    .data
+                    .msg_signature
+                    .as_deref()
+                    .is_some_and(|signature| {
+                        crypto.verify_signature(
+                            signature,
+                            &param.timestamp,
+                            &param.nonce,
+                            Some(&encrypted_message.encrypt),
+                        )
+                    });
+            if !msg_signature_valid {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+
+            let message = match encrypted_message.decrypt(&crypto) {
+                Ok(message) => message,
+                Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+            };
 
-            tracing::info!(%from_app_id, ?message, "Received a encrypted message from weixin.");
+            tracing::info!(?message, "Received a encrypted message from weixin.");
             message
         } else {
             return (
@@ -125,7 +198,7 @@ pub async fn wx_post(
                 .into_response();
         }
     } else {
-        let raw = match serde_xml_rs::from_str::<WxRawXmlMessage>(&data) {
+        let raw = match quick_xml::de::from_str::<WxRawXmlMessage>(&data) {
             Ok(message) => message,
             Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
         };
@@ -139,22 +212,47 @@ pub async fn wx_post(
         message
     };
 
-    if let WxMessageData::Event { event } = &message.data {
-        if let WxEvent {
-            event: event @ WxEventType::Subscribe,
-            event_key: Some(event_key),
-            ticket: Some(ticket),
-        }
-        | WxEvent {
-            event: event @ WxEventType::Scan,
-            event_key: Some(event_key),
-            ticket: Some(ticket),
-        } = event
-        {
-            const EVENT_KEY_PREFIX: &str = "qrscene_";
-            let event_key: usize = match if let Some(stripped) =
-                event_key.strip_prefix(EVENT_KEY_PREFIX)
-            {
+    // 微信服务器在 5 秒内收不到响应会重试 3 次，同一条消息只处理一次
+    let dedup_key = message.dedup_key();
+    if !acquire_message(&cache, &dedup_key).await {
+        tracing::info!(%dedup_key, "Received a duplicated message from weixin, ignored.");
+        return success();
+    }
+
+    // 关注时通过客服消息接口发送欢迎语，不占用被动回复
+    if let WxMessageData::Event {
+        event: WxEvent {
+            event: WxEventType::Subscribe,
+            ..
+        },
+    } = &message.data
+    {
+        let connection = connection.clone();
+        let wx_client = wx_app.clone();
+        let open_id = message.from_user_name.clone();
+        let original_id = message.to_user_name.clone();
+        context::spawn(async move {
+            welcome(&connection, wx_client.as_ref(), &open_id, &original_id).await;
+        });
+    }
+
+    if let WxMessageData::Event {
+        event:
+            WxEvent {
+                event: event @ WxEventType::Subscribe,
+                event_key: Some(event_key),
+                ticket: Some(ticket),
+            }
+            | WxEvent {
+                event: event @ WxEventType::Scan,
+                event_key: Some(event_key),
+                ticket: Some(ticket),
+            },
+    } = &message.data
+    {
+        const EVENT_KEY_PREFIX: &str = "qrscene_";
+        let event_key: NonZeroUsize =
+            match if let Some(stripped) = event_key.strip_prefix(EVENT_KEY_PREFIX) {
                 stripped.parse()
             } else {
                 event_key.parse()
@@ -162,79 +260,352 @@ pub async fn wx_post(
                 Ok(event_key) => event_key,
                 Err(error) => return (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
             };
-            tracing::info!(?event, %event_key, %ticket, "Received event");
+        tracing::info!(?event, %event_key, %ticket, "Received event");
 
-            return match handle_scan(
-                &message.from_user_name,
-                &message.to_user_name,
+        // 注册、写库、推送等耗时操作放到单独的任务中执行，避免超过微信服务器 5 秒的等待时间；
+        // 任务继承请求 ID，超时后改用客服消息回复时仍能关联到这次回调
+        let (sender, mut receiver) = tokio::sync::oneshot::channel();
+        let from_user = message.from_user_name.clone();
+        let to_user = message.to_user_name.clone();
+        let wx_client = wx_app.clone();
+        let webhooks = webhooks.map(|Extension(webhooks)| webhooks);
+        context::spawn(async move {
+            let result = handle_scan(
+                &from_user,
+                &to_user,
                 event_key,
                 connection,
+                users,
                 session_manager,
-                wx_app.config(),
+                webhooks,
+                wx_client.config(),
             )
-            .await
-            {
-                Ok(Some(xml)) => (StatusCode::OK, xml).into_response(),
-                Ok(None) => StatusCode::OK.into_response(),
-                Err(error) => {
-                    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+            .await;
+            if let Err(error) = &result {
+                tracing::error!(%error, %from_user, "Failed to handle scan event");
+                // 处理失败，允许微信服务器重试
+                release_message(&cache, &dedup_key).await;
+            }
+            // 接收端已关闭说明被动回复已超时，改用客服消息接口回复
+            if let Err(Ok(Some(reply))) = sender.send(result) {
+                if let Err(error) = reply.send_custom(&*wx_client).await {
+                    tracing::error!(%error, %from_user, "Failed to send custom message");
                 }
-            };
+            }
+        });
+
+        let timeout = reply_timeouts
+            .and_then(|Extension(timeouts)| timeouts.load().get(&wx_app.config().app_id))
+            .unwrap_or_else(|| Duration::from_millis(wx_app.config().reply_timeout_millis));
+        let result = match tokio::time::timeout(timeout, &mut receiver).await {
+            Ok(result) => result.ok(),
+            Err(_) => {
+                receiver.close();
+                receiver.try_recv().ok()
+            }
+        };
+        return match result {
+            Some(Ok(Some(reply))) => (StatusCode::OK, Xml(reply)).into_response(),
+            Some(Err(error)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+            }
+            Some(Ok(None)) | None => success(),
+        };
+    }
+
+    success()
+}
+
+/// 发送公众号配置的欢迎语，失败时只记录日志
+async fn welcome(
+    connection: &DatabaseConnection,
+    wx_client: &dyn WxApi,
+    open_id: &str,
+    original_id: &str,
+) {
+    let result = async {
+        match WelcomeService::new(connection)
+            .find(wx_client.app_id())
+            .await?
+        {
+            Some(welcome) => send_welcome(&welcome, wx_client, open_id, original_id).await,
+            None => Ok(0),
         }
     }
+    .await;
+    match result {
+        Ok(sent) => tracing::info!(%open_id, %sent, "Welcome message sent."),
+        Err(error) => tracing::error!(%error, %open_id, "Failed to send welcome message"),
+    }
+}
 
-    StatusCode::OK.into_response()
+/// 回复微信服务器 `success`，表示已收到消息且不需要被动回复
+fn success() -> Response {
+    (StatusCode::OK, "success").into_response()
+}
+
+/// 消息去重标记的过期时间，覆盖微信服务器的重试周期
+const DEDUP_EXPIRE_SECONDS: usize = 60;
+
+/// 使用 `SET NX` 标记消息已在处理，返回是否为首次收到该消息
+///
+/// Redis 不可用时不去重，避免丢消息
+async fn acquire_message(cache: &Cache, key: &str) -> bool {
+    let result: redis::RedisResult<Option<String>> = async {
+        let mut connection = cache.connection().await?;
+        redis::cmd("SET")
+            .arg(key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(DEDUP_EXPIRE_SECONDS)
+            .query_async(&mut connection)
+            .await
+    }
+    .await;
+
+    match result {
+        Ok(set) => set.is_some(),
+        Err(error) => {
+            tracing::error!(%error, %key, "Failed to deduplicate weixin message");
+            true
+        }
+    }
+}
+
+/// 移除消息去重标记
+async fn release_message(cache: &Cache, key: &str) {
+    let result: redis::RedisResult<()> = async {
+        let mut connection = cache.connection().await?;
+        connection.del(key).await
+    }
+    .await;
+
+    if let Err(error) = result {
+        tracing::error!(%error, %key, "Failed to release weixin message deduplication key");
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_scan(
     from_user: &str,
     to_user: &str,
-    websocket_id: usize,
+    scene: NonZeroUsize,
     connection: DatabaseConnection,
+    users: DynUserRepo,
     session_manager: SessionManager,
+    webhooks: Option<Webhooks>,
     wx_config: &WxConfig,
-) -> anyhow::Result<Option<Xml<WxRawXmlMessage>>> {
-    use crate::storage::model::user::*;
-    if let Some(_user) = Entity::find()
-        .filter(Column::OpenId.eq(from_user))
-        .one(&connection)
-        .await?
-    {
+) -> anyhow::Result<Option<WxReply>> {
+    if let Some(user) = users.find_by_open_id(from_user).await? {
+        context::record_uid(user.id as i64);
         // TODO login
         return Ok(None);
     }
 
-    // register
-    let register = ActiveModel {
-        open_id: Set(from_user.to_string()),
-        ..Default::default()
-    };
-    let _inserted = Entity::insert(register).exec(&connection).await?;
+    let websocket_id = session_manager.scene_session(scene);
+    let user = register(
+        &connection,
+        &session_manager,
+        webhooks.as_ref(),
+        websocket_id,
+        from_user,
+    )
+    .await?;
+    context::record_uid(user.id as i64);
     // TODO save openid -> connection id to map
     // OPENID_EVENT_CODE_MAP.put(fromUser, eventKey);
     //授权流程,给用户发送授权消息，并且异步通知前端扫码成功
-    tokio::spawn(async move {
-        let resp = Resp {
-            r#type: RespType::LoginScanSuccess,
-            data: (),
-        };
-        if let Err(error) = session_manager.try_send(websocket_id, &resp).await {
-            tracing::error!(%error, %websocket_id, ?resp, "Failed to send response to websocket");
+    let resp = WsPush::LoginScanSuccess;
+    match websocket_id {
+        Some(websocket_id) => {
+            if let Err(error) = session_manager.try_send(websocket_id, &resp) {
+                tracing::error!(%error, %websocket_id, ?resp, "Failed to send response to websocket");
+            }
         }
-    });
+        None => tracing::info!(%scene, "Websocket session of scene not found"),
+    }
     let callback_url = format!("{}/wx/portal/public/callBack", wx_config.callback_url); // TODO use url
     let encoded_callback_url = urlencoding::encode(&callback_url);
-    let skip_url = format!("https://open.weixin.qq.com/connect/oauth2/authorize?appid={}&redirect_uri={}&response_type=code&scope=snsapi_userinfo&state=STATE#wechat_redirect", wx_config.app_id, encoded_callback_url);
-    let message = WxMessage {
-        to_user_name: from_user.to_string(),
-        from_user_name: to_user.to_string(),
-        create_time: (current_millisecond() / 1000) as i32,
-        data: WxMessageData::Text {
-            content: format!("请点击链接授权：<a href=\"{skip_url}\">登录</a>"),
-        },
-        msg_id: None,
-        msg_data_id: None,
-        idx: None,
+    let skip_url = format!("https://open.weixin.qq.com/connect/oauth2/authorize?appid={0}&redirect_uri={1}&response_type=code&scope=snsapi_userinfo&state={0}#wechat_redirect", wx_config.app_id, encoded_callback_url);
+    Ok(Some(WxReply::text(
+        to_user,
+        from_user,
+        i18n::tr(&format!("请点击链接授权：<a href=\"{skip_url}\">登录</a>")),
+    )))
+}
+
+/// 注册并赠送改名卡、注册徽章，获得徽章时通知 WebSocket 连接，并发布注册事件
+pub(crate) async fn register(
+    connection: &DatabaseConnection,
+    session_manager: &SessionManager,
+    webhooks: Option<&Webhooks>,
+    websocket_id: Option<usize>,
+    open_id: &str,
+) -> anyhow::Result<user::Model> {
+    let open_id = open_id.to_string();
+    let (registered, reward) = with_txn(connection, |txn| {
+        Box::pin(async move {
+            let user = UserRepo::create(txn, &open_id).await?;
+            let reward = ItemService::new(txn)
+                .grant_register_items(user.id as i64)
+                .await?;
+            Ok::<_, DbErr>((user, reward))
+        })
+    })
+    .await?;
+    if let (false, Some(websocket_id)) = (reward.badges.is_empty(), websocket_id) {
+        let notice = WsPush::SystemNotice(SystemNotice {
+            content: i18n::tr(&format!(
+                "恭喜你成为抹茶聊天第{}位用户，获得{}枚专属徽章",
+                reward.rank,
+                reward.badges.len()
+            ))
+            .into_owned(),
+        });
+        if let Err(error) = session_manager.try_send(websocket_id, &notice) {
+            tracing::error!(%error, %websocket_id, "Failed to send register notice to websocket");
+        }
+    }
+    if let Some(webhooks) = webhooks {
+        webhooks
+            .emit(WebhookEvent::UserRegistered(UserRegistered {
+                uid: registered.id as i64,
+            }))
+            .await;
+    }
+    Ok(registered)
+}
+
+/// 企业微信验证回调地址，返回解密后的 `echostr`
+#[utoipa::path(get, path = "/wx/work")]
+pub async fn work_echo_str(
+    Extension(work_client): Extension<WorkClient>,
+    Valid(Query(param)): Valid<Query<WorkCallbackParam>>,
+) -> Response {
+    let Some(echostr) = &param.echostr else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    if !param.is_signature_valid(&work_client.config().token, echostr) {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    match work_client.decrypt(echostr) {
+        Ok(echostr) => (StatusCode::OK, echostr).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// 接收企业微信的消息和事件
+#[utoipa::path(post, path = "/wx/work")]
+pub async fn work_post(
+    Extension(work_client): Extension<WorkClient>,
+    Extension(cache): Extension<Cache>,
+    Valid(Query(param)): Valid<Query<WorkCallbackParam>>,
+    data: String,
+) -> Response {
+    let encrypted = match quick_xml::de::from_str::<WorkEncryptedXmlMessage>(&data) {
+        Ok(encrypted) => encrypted,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if !param.is_signature_valid(&work_client.config().token, &encrypted.encrypt) {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let message = match work_client.decrypt_message(&encrypted) {
+        Ok(message) => message,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
-    Ok(Some(Xml(message.into())))
+    let dedup_key = message.dedup_key();
+    if !acquire_message(&cache, &dedup_key).await {
+        tracing::info!(%dedup_key, "Received a duplicated message from weixin work, ignored.");
+        return success();
+    }
+    tracing::info!(?message, "Received a message from weixin work.");
+    success()
+}
+
+/// 企业微信网页扫码登录回调参数
+#[derive(Debug, Validate, Deserialize)]
+pub struct WorkCallBackParam {
+    /// code
+    #[validate(length(min = 1))]
+    pub code: String,
+    /// 登录链接的场景值，见 [`SessionManager::bind_scene`]
+    pub state: NonZeroUsize,
+}
+
+/// 企业微信网页扫码登录回调，登录成功后通知发起登录的 WebSocket 连接
+#[utoipa::path(get, path = "/wx/work/callBack")]
+#[allow(clippy::too_many_arguments)]
+pub async fn work_call_back(
+    Valid(Query(WorkCallBackParam { code, state })): Valid<Query<WorkCallBackParam>>,
+    Extension(work_client): Extension<WorkClient>,
+    Extension(connection): Extension<DatabaseConnection>,
+    Extension(users): Extension<DynUserRepo>,
+    Extension(session_manager): Extension<SessionManager>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    cache: Option<Extension<Cache>>,
+    webhooks: Option<Extension<Webhooks>>,
+) -> super::api::Result<Cow<'static, str>> {
+    let user_id = work_client.get_user_id(&code).await?;
+    let open_id = work_open_id(&work_client.config().corp_id, &user_id);
+    let websocket_id = session_manager.scene_session(state);
+    let user = match users.find_by_open_id(&open_id).await? {
+        Some(user) if user.deleted_at.is_some() => {
+            return Err(ApiError::business(
+                ErrorCode::PermissionDenied,
+                "用户已注销",
+            ));
+        }
+        Some(user) => user,
+        None => {
+            register(
+                &connection,
+                &session_manager,
+                webhooks.as_deref(),
+                websocket_id,
+                &open_id,
+            )
+            .await?
+        }
+    };
+    ensure_not_banned(&user)?;
+    let uid = user.id as i64;
+    context::record_uid(uid);
+    let store = cache
+        .as_ref()
+        .map(|Extension(cache)| SessionStore::new(cache.clone()));
+    let Some(websocket_id) = websocket_id.filter(|id| session_manager.authenticate(*id, uid))
+    else {
+        // 连接已断开，如服务端重启，记录下来等待客户端携带登录码重连
+        match &store {
+            Some(store) => match store.complete(state, uid).await {
+                Ok(true) => {
+                    tracing::info!(%uid, %user_id, scene = %state, "Weixin work login pending")
+                }
+                Ok(false) => tracing::warn!(%uid, scene = %state, "Login scene expired"),
+                Err(error) => {
+                    tracing::error!(%error, scene = %state, "Failed to save pending login")
+                }
+            },
+            None => tracing::warn!(%uid, scene = %state, "Websocket session closed before login"),
+        }
+        return Ok(i18n::tr("登录成功，请返回聊天页面"));
+    };
+    let device = session_manager.login_device(websocket_id);
+    if let Some(device) = &device {
+        record_device(cache.as_deref(), uid, device).await;
+    }
+    let device = device.map(|device| device.device_id);
+    let resp =
+        WsPush::LoginSuccess(login_success(&connection, &jwt_keys, user, device.as_deref()).await?);
+    tracing::info!(%uid, %user_id, %websocket_id, "Weixin work user logged in");
+    if let Err(error) = session_manager.try_send(websocket_id, &resp) {
+        tracing::error!(%error, %websocket_id, "Failed to send login success to websocket");
+    }
+    if let Some(store) = store {
+        if let Err(error) = store.finish(state).await {
+            tracing::error!(%error, scene = %state, "Failed to remove finished login");
+        }
+    }
+    Ok(i18n::tr("登录成功，请返回聊天页面"))
 }
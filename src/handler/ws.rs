@@ -2,49 +2,695 @@
 
 use axum::Extension;
 use parking_lot::RwLock;
+use rand::Rng;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::net::SocketAddr;
+use std::io::Write;
+use std::net::IpAddr;
 use std::num::NonZeroUsize;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
 
-use crate::storage::model::prelude::User;
-use crate::weixin::WxClient;
-use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{ConnectInfo, WebSocketUpgrade};
+use crate::active::{ActiveStatus, ActiveTracker};
+use crate::cache::Cache;
+use crate::handler::api::{ApiError, ErrorCode};
+use crate::handler::auth::{
+    current_millisecond, ensure_not_banned, ensure_user_not_banned, record_device, Claims, JwtKeys,
+};
+use crate::handler::client_ip::ClientIp;
+use crate::handler::context::RequestContext;
+use crate::handler::ws::admission::{Admission, AdmissionConfig};
+use crate::handler::ws::outbox::{Outbox, OutboxReceiver, OverflowPolicy, PushOutcome, PushStats};
+use crate::handler::ws::proto::{PushFrame, ReqFrame, WsEncoding, PROTOBUF_PROTOCOL};
+use crate::handler::ws::push::{
+    LoginSuccess, LoginUrl, Reconnect, Typing, WsError, WsPush, WsReply,
+};
+use crate::handler::ws::resume::PushCursor;
+use crate::handler::ws::store::{IssuedLogin, PendingLogin, SessionStore};
+use crate::ip::IpTracker;
+use crate::mq::typing::{push_typing, TypingEvent, TYPING_TOPIC};
+use crate::mq::{self, DynProducer};
+use crate::service::announcement::AnnouncementService;
+use crate::service::device::{device_id_or_random, LoginDevice};
+use crate::service::group::GroupService;
+use crate::service::role::{self, RoleService};
+use crate::service::room::{RoomFriendStatus, RoomService, RoomType};
+use crate::storage::repo::{RoomRepo, UserRepo};
+use crate::timestamp;
+use crate::weixin::work::WorkClient;
+use crate::weixin::DynWxApi;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use axum::extract::{Query, WebSocketUpgrade};
+use axum::http::header::{SEC_WEBSOCKET_PROTOCOL, USER_AGENT};
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use dashmap::DashMap;
+use sea_orm::{DatabaseConnection, DbErr};
 use serde::{Deserialize, Serialize};
 use slab::Slab;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Instant;
+use tracing::Instrument;
+use utoipa::ToSchema;
 
-const EXPIRE_SECONDS: u64 = 60 * 60;
+pub mod admission;
+pub mod outbox;
+pub mod proto;
+pub mod push;
+pub mod resume;
+pub mod store;
+
+/// 使用企业微信登录的登录请求数据
+pub const WORK_LOGIN: &str = "work";
+
+/// 同一用户推送正在输入的最小间隔（毫秒）
+pub const TYPING_INTERVAL_MILLIS: i64 = 3000;
+
+/// 建立连接的参数
+#[derive(Debug, Default, Deserialize)]
+pub struct ConnectParam {
+    /// 登录后获得的 token，重连时携带即可直接登录
+    pub token: Option<String>,
+    /// 客户端支持的推送压缩方式
+    pub compress: Option<WsCompression>,
+    /// 登录二维码的登录码，重连时携带可以继续之前的扫码登录
+    pub login_code: Option<String>,
+    /// 客户端的设备 ID，扫码登录签发的 token 属于该设备，见 [`crate::service::device`]
+    pub device_id: Option<String>,
+}
+
+/// 推送压缩方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WsCompression {
+    /// 超过阈值的推送以 gzip 压缩后的二进制帧发送
+    Gzip,
+}
 
 /// 建立 WebSocket 连接
+///
+/// 已登录用户可以在查询参数 `token` 或 `Sec-WebSocket-Protocol` 中携带 token，
+/// 校验通过时直接标记为已登录，无效或缺失时为游客。
+/// 查询参数 `compress=gzip` 表示客户端可以解压二进制帧中的 gzip 数据；
+/// 子协议中包含 [`PROTOBUF_PROTOCOL`] 时使用 protobuf 二进制帧，此时不再压缩。
+/// 未登录时可以携带查询参数 `login_code` 继续之前的扫码登录，见 [`store`]
+#[allow(clippy::too_many_arguments)]
 pub async fn websocket_on_connect(
-    ws: WebSocketUpgrade,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut ws: WebSocketUpgrade,
+    ClientIp(addr): ClientIp,
+    Query(param): Query<ConnectParam>,
+    headers: HeaderMap,
     Extension(session_manager): Extension<SessionManager>,
-    Extension(wx_client): Extension<WxClient>,
-) -> impl IntoResponse {
-    let (id, receiver) = session_manager.accept(addr);
+    Extension(wx_client): Extension<DynWxApi>,
+    Extension(jwt_keys): Extension<JwtKeys>,
+    ip_tracker: Option<Extension<IpTracker>>,
+    db: Option<Extension<DatabaseConnection>>,
+    cache: Option<Extension<Cache>>,
+    work_client: Option<Extension<WorkClient>>,
+    active_tracker: Option<Extension<ActiveTracker>>,
+    producer: Option<Extension<DynProducer>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let ip_tracker = ip_tracker.map(|Extension(ip_tracker)| ip_tracker);
+    let producer = producer.map(|Extension(producer)| producer);
+    let active_tracker = active_tracker.map(|Extension(active_tracker)| active_tracker);
+    let work_client = work_client.map(|Extension(work_client)| work_client);
+    let db = db.map(|Extension(db)| db);
+    let store = cache
+        .as_ref()
+        .map(|Extension(cache)| SessionStore::new(cache.clone()));
+    let cache = cache.map(|Extension(cache)| cache);
+    let cursor = cache.clone().map(PushCursor::new);
+    let max_message_bytes = session_manager.config.max_message_bytes;
+    if max_message_bytes > 0 {
+        ws = ws
+            .max_message_size(max_message_bytes)
+            .max_frame_size(max_message_bytes);
+    }
+    let (id, receiver) = session_manager
+        .admit()
+        .and_then(|()| session_manager.accept(addr))
+        .inspect_err(|rejected| {
+            tracing::warn!(%addr, %rejected, "Websocket connection rejected.");
+        })?;
+    // 升级失败或请求在升级前被取消时 `on_upgrade` 不会执行，由 guard 释放占用的连接数
+    let guard = SessionGuard {
+        session_manager: session_manager.clone(),
+        id,
+    };
     tracing::info!(%addr, %id, "Websocket connection established.");
-    ws.on_upgrade(move |socket| handle_websocket(id, addr, socket, receiver, wx_client))
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    session_manager.set_user_agent(id, user_agent);
+    session_manager.set_device(id, device_id_or_random(param.device_id.as_deref()));
+    let encoding = if requested_protocols(&headers).any(|protocol| protocol == PROTOBUF_PROTOCOL) {
+        session_manager.set_encoding(id, WsEncoding::Protobuf);
+        // 浏览器要求服务端回应客户端提供的子协议之一，只能回应一个，优先回应编码
+        ws = ws.protocols([PROTOBUF_PROTOCOL]);
+        WsEncoding::Protobuf
+    } else {
+        WsEncoding::Json
+    };
+    if param.compress == Some(WsCompression::Gzip) {
+        session_manager.enable_compression(id);
+    }
+    let login = Login {
+        session_manager: &session_manager,
+        id,
+        addr,
+        ip_tracker: ip_tracker.as_ref(),
+        active_tracker: active_tracker.as_ref(),
+        db: db.as_ref(),
+        cache: cache.as_ref(),
+    };
+    let mut ticket = LoginTicket::default();
+    match authorize_upgrade(&jwt_keys, db.as_ref(), param.token.as_deref(), &headers).await {
+        Some((claims, protocol)) => {
+            tracing::info!(%id, uid = %claims.uid, "Websocket session authorized on upgrade");
+            login.authenticate(claims.uid, claims.device.as_deref());
+            if let (Some(protocol), WsEncoding::Json) = (protocol, encoding) {
+                ws = ws.protocols([protocol]);
+            }
+        }
+        None if param.token.is_some()
+            || requested_protocols(&headers).any(|protocol| protocol != PROTOBUF_PROTOCOL) =>
+        {
+            tracing::warn!(%id, "Websocket upgrade with invalid token, fallback to guest");
+        }
+        None => {}
+    }
+    if let (Some(code), Some(store), None) =
+        (&param.login_code, &store, session_manager.session_uid(id))
+    {
+        match store.claim(code).await {
+            Ok(Some(pending)) => {
+                resume_login(&login, &jwt_keys, &mut ticket, pending).await;
+            }
+            Ok(None) => tracing::info!(%id, "Login code expired, client should login again"),
+            Err(error) => tracing::error!(%id, %error, "Failed to claim login code."),
+        }
+    }
+    // 连接的处理任务沿用升级请求的 span 和请求 ID，连接中发布的事件可以关联到这次连接
+    let span = tracing::Span::current();
+    let context = RequestContext::current().unwrap_or_else(RequestContext::generate);
+    Ok(ws.on_upgrade(move |socket| {
+        context.scope(
+            async move {
+                handle_websocket(
+                    id,
+                    addr,
+                    socket,
+                    receiver,
+                    wx_client,
+                    work_client,
+                    jwt_keys,
+                    ip_tracker,
+                    active_tracker,
+                    db,
+                    cache,
+                    cursor,
+                    store,
+                    producer,
+                    ticket,
+                    &session_manager,
+                )
+                .await;
+                drop(guard);
+            }
+            .instrument(span),
+        )
+    }))
+}
+
+/// 连接占用的会话，drop 时移除
+struct SessionGuard {
+    session_manager: SessionManager,
+    id: usize,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.session_manager.remove(self.id);
+    }
+}
+
+/// 连接登录时需要更新的状态
+struct Login<'a> {
+    session_manager: &'a SessionManager,
+    id: usize,
+    addr: IpAddr,
+    ip_tracker: Option<&'a IpTracker>,
+    active_tracker: Option<&'a ActiveTracker>,
+    db: Option<&'a DatabaseConnection>,
+    cache: Option<&'a Cache>,
+}
+
+impl Login<'_> {
+    /// 标记连接已登录，记录 IP、活跃时间和登录设备，并投递未读的公告
+    ///
+    /// `device` 为 token 中的设备 ID，为空时使用连接的设备
+    fn authenticate(&self, uid: i64, device: Option<&str>) {
+        self.session_manager.authenticate(self.id, uid);
+        if let Some(device) = device {
+            self.session_manager.set_device(self.id, device.to_string());
+        }
+        if let (Some(cache), Some(device)) =
+            (self.cache, self.session_manager.login_device(self.id))
+        {
+            let cache = cache.clone();
+            tokio::spawn(async move { record_device(Some(&cache), uid, &device).await });
+        }
+        if let Some(ip_tracker) = self.ip_tracker {
+            ip_tracker.record(uid, self.addr);
+        }
+        if let Some(active_tracker) = self.active_tracker {
+            active_tracker.record(uid);
+        }
+        deliver_announcements(self.db.cloned(), self.session_manager.clone(), self.id, uid);
+    }
+}
+
+/// 重连后继续之前的扫码登录
+///
+/// 登录已完成时直接登录并推送 `LoginSuccess`，否则将二维码的场景值绑定到新的连接，过期时推送 `LoginUrlExpired`
+async fn resume_login(
+    login: &Login<'_>,
+    jwt_keys: &JwtKeys,
+    ticket: &mut LoginTicket,
+    pending: PendingLogin,
+) {
+    let id = login.id;
+    let Some(uid) = pending.uid else {
+        tracing::info!(%id, scene = %pending.scene, "Pending login resumed");
+        login.session_manager.bind_scene(id, pending.scene);
+        let expires_in = match pending.data.as_deref() {
+            Some(WORK_LOGIN) => None,
+            _ => pending.expires_in,
+        };
+        ticket.issue(pending.data, expires_in);
+        return;
+    };
+    // 扫码完成后、重连前被拉黑的用户不能继续登录
+    if let Some(db) = login.db {
+        match UserRepo::find_by_id(db, uid).await {
+            Ok(Some(user)) if ensure_not_banned(&user).is_err() => {
+                tracing::warn!(%id, %uid, "Pending login of banned user rejected");
+                let resp = WsPush::Error(WsError::new(ErrorCode::PermissionDenied, "用户已被拉黑"));
+                if let Err(error) = login.session_manager.try_send(id, &resp) {
+                    tracing::error!(%id, %uid, %error, "Failed to send login error.");
+                }
+                return;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                tracing::error!(%id, %uid, %error, "Failed to query user for pending login.");
+                return;
+            }
+        }
+    }
+    let device = login.session_manager.session_device(id);
+    let token = match jwt_keys.sign(&Claims::for_device(uid, device.as_deref())) {
+        Ok(token) => token,
+        Err(error) => {
+            tracing::error!(%id, %uid, %error, "Failed to sign token for pending login.");
+            return;
+        }
+    };
+    tracing::info!(%id, %uid, "Websocket session authorized by pending login");
+    login.authenticate(uid, None);
+    let resp = WsPush::LoginSuccess(authorized(login.db, uid, token).await);
+    if let Err(error) = login.session_manager.try_send(id, &resp) {
+        tracing::error!(%id, %uid, %error, "Failed to send login success.");
+    }
+}
+
+/// 验证 token，检查吊销记录和用户是否被拉黑，与 HTTP 接口的 [`Claims`] 相同
+async fn authenticate(
+    jwt_keys: &JwtKeys,
+    db: Option<&DatabaseConnection>,
+    token: &str,
+) -> Result<Claims, ApiError> {
+    let claims = jwt_keys.authenticate(token).await?;
+    if let Some(db) = db {
+        ensure_user_not_banned(db, claims.uid).await?;
+    }
+    Ok(claims)
+}
+
+/// 从查询参数或 `Sec-WebSocket-Protocol` 中查找有效的 token
+///
+/// 返回 token 中的用户信息，token 来自子协议时同时返回该子协议
+async fn authorize_upgrade(
+    jwt_keys: &JwtKeys,
+    db: Option<&DatabaseConnection>,
+    query_token: Option<&str>,
+    headers: &HeaderMap,
+) -> Option<(Claims, Option<String>)> {
+    let query = query_token.map(|token| (token, None));
+    let protocols = requested_protocols(headers).map(|protocol| (protocol, Some(protocol)));
+    for (token, protocol) in query.into_iter().chain(protocols) {
+        if let Ok(claims) = authenticate(jwt_keys, db, token).await {
+            return Some((claims, protocol.map(str::to_string)));
+        }
+    }
+    None
+}
+
+fn message_len(message: &Message) -> usize {
+    match message {
+        Message::Text(text) => text.len(),
+        Message::Binary(bytes) | Message::Ping(bytes) | Message::Pong(bytes) => bytes.len(),
+        Message::Close(frame) => frame.as_ref().map_or(0, |frame| frame.reason.len() + 2),
+    }
+}
+
+/// 客户端在 `Sec-WebSocket-Protocol` 中提供的子协议
+fn requested_protocols(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+/// 推送正在输入，单聊只推送给对方，全员群聊推送给所有在线用户，其他群聊推送给其他成员，不是房间成员时忽略
+///
+/// 多实例部署时通过消息队列推送给各实例的连接
+fn notify_typing(
+    db: DatabaseConnection,
+    producer: Option<DynProducer>,
+    session_manager: SessionManager,
+    uid: i64,
+    room_id: i64,
+) {
+    tokio::spawn(async move {
+        let receivers = match typing_receivers(&db, uid, room_id).await {
+            Ok(Some(receivers)) => receivers,
+            Ok(None) => {
+                tracing::warn!(%uid, %room_id, "Received typing request from non-member");
+                return;
+            }
+            Err(error) => {
+                tracing::error!(%uid, %room_id, %error, "Failed to query typing receivers.");
+                return;
+            }
+        };
+        let event = TypingEvent {
+            typing: Typing { room_id, uid },
+            receivers,
+        };
+        let result = match producer {
+            Some(producer) => mq::send_json(producer.as_ref(), TYPING_TOPIC, &event)
+                .await
+                .map(|_| ()),
+            None => push_typing(&session_manager, event).map(|_| ()),
+        };
+        if let Err(error) = result {
+            tracing::error!(%uid, %room_id, %error, "Failed to publish typing.");
+        }
+    });
+}
+
+/// 正在输入的接收者，不是房间成员时为 `None`，全员群聊时为 `Some(None)`
+async fn typing_receivers(
+    db: &DatabaseConnection,
+    uid: i64,
+    room_id: i64,
+) -> Result<Option<Option<Vec<i64>>>, DbErr> {
+    let Some(room) = RoomRepo::find_by_id(db, room_id).await? else {
+        return Ok(None);
+    };
+    if room.r#type != RoomType::Single as i32 {
+        if !RoomRepo::is_group_member(db, room_id, uid).await? {
+            return Ok(None);
+        }
+        let receivers = GroupService::new(db).receivers(room_id).await?;
+        return Ok(Some(receivers.map(|uids| {
+            uids.into_iter().filter(|&other| other != uid).collect()
+        })));
+    }
+    Ok(RoomService::new(db)
+        .find_single_by_room(room_id)
+        .await?
+        .filter(|room_friend| {
+            room_friend.status == RoomFriendStatus::Normal as i32
+                && (room_friend.uid1 == uid || room_friend.uid2 == uid)
+        })
+        .map(|room_friend| {
+            let other = if room_friend.uid1 == uid {
+                room_friend.uid2
+            } else {
+                room_friend.uid1
+            };
+            Some(vec![other])
+        }))
+}
+
+/// 登录后补发离线期间未送达的系统公告
+fn deliver_announcements(
+    db: Option<DatabaseConnection>,
+    session_manager: SessionManager,
+    id: usize,
+    uid: i64,
+) {
+    let Some(db) = db else {
+        return;
+    };
+    tokio::spawn(async move {
+        let service = AnnouncementService::new(&db);
+        let announcements = match service.undelivered(uid, timestamp::now()).await {
+            Ok(announcements) => announcements,
+            Err(error) => {
+                tracing::error!(%id, %uid, %error, "Failed to query undelivered announcements.");
+                return;
+            }
+        };
+        let Some(last_id) = announcements.last().map(|announcement| announcement.id) else {
+            return;
+        };
+        for announcement in announcements {
+            let push = WsPush::Announcement(announcement.into());
+            if let Err(error) = session_manager.try_send(id, &push) {
+                tracing::error!(%id, %uid, %error, "Failed to deliver announcement.");
+                return;
+            }
+        }
+        if let Err(error) = service.mark_delivered(&[uid], last_id).await {
+            tracing::error!(%id, %uid, %error, "Failed to mark announcements delivered.");
+        }
+    });
+}
+
+/// 当前连接最近一次签发的登录二维码
+#[derive(Debug, Default)]
+struct LoginTicket {
+    /// 登录请求的数据，刷新时使用相同的登录方式
+    data: Option<String>,
+    /// 过期时间，企业微信登录链接不会过期
+    expire_at: Option<Instant>,
+}
+
+impl LoginTicket {
+    /// 记录新签发的二维码，之前的二维码不再推送过期
+    fn issue(&mut self, data: Option<String>, expires_in: Option<Duration>) {
+        self.data = data;
+        self.expire_at = expires_in.map(|expires_in| Instant::now() + expires_in);
+    }
+}
+
+/// 记录用户确认的推送序号
+fn ack_push(cursor: PushCursor, id: usize, uid: i64, seq: u64) {
+    tokio::spawn(async move {
+        if let Err(error) = cursor.ack(uid, seq).await {
+            tracing::error!(%id, %uid, %seq, %error, "Failed to save push ack.");
+        }
+    });
+}
+
+/// 补发序号 `seq` 之后的消息，未指定时使用已确认的序号，都没有时不补发
+///
+/// 通过 [`Admission::spawn_resync`] 调度，实例刚启动时分散执行
+fn resume(
+    db: DatabaseConnection,
+    cursor: Option<PushCursor>,
+    session_manager: SessionManager,
+    id: usize,
+    uid: i64,
+    seq: Option<u64>,
+) {
+    let admission = session_manager.admission.clone();
+    admission.spawn_resync(async move {
+        // 延迟期间连接可能已经断开或切换了用户
+        if session_manager.session_uid(id) != Some(uid) {
+            return;
+        }
+        let seq = match (seq, cursor) {
+            (Some(seq), _) => seq,
+            (None, Some(cursor)) => match cursor.get(uid).await {
+                Ok(Some(seq)) => seq,
+                Ok(None) => return,
+                Err(error) => {
+                    tracing::error!(%id, %uid, %error, "Failed to get push ack.");
+                    return;
+                }
+            },
+            (None, None) => return,
+        };
+        let messages = match resume::missed_messages(&db, &db, uid, seq).await {
+            Ok(messages) => messages,
+            Err(error) => {
+                tracing::error!(%id, %uid, %seq, %error, "Failed to query missed messages.");
+                return;
+            }
+        };
+        tracing::info!(%id, %uid, %seq, count = messages.len(), "Resend missed messages.");
+        for message in messages {
+            if let Err(error) = session_manager.try_send(id, &WsPush::NewMessage(message)) {
+                tracing::error!(%id, %uid, %error, "Failed to resend message.");
+                return;
+            }
+        }
+    });
+}
+
+/// 解析 JSON 请求，失败时尽量取出请求序号用于回复错误
+fn parse_json_req(json: &str) -> Result<Req, (anyhow::Error, Option<u64>)> {
+    serde_json::from_str::<Req>(json).map_err(|error| {
+        let seq = serde_json::from_str::<serde_json::Value>(json)
+            .ok()
+            .and_then(|value| value.get("seq")?.as_u64());
+        (error.into(), seq)
+    })
+}
+
+/// 解析 protobuf 请求，失败时尽量取出请求序号用于回复错误
+fn parse_protobuf_req(bytes: &[u8]) -> Result<Req, (anyhow::Error, Option<u64>)> {
+    let frame =
+        <ReqFrame as prost::Message>::decode(bytes).map_err(|error| (error.into(), None))?;
+    let seq = frame.seq;
+    Req::try_from(frame).map_err(|error| (error, seq))
+}
+
+/// 直接回复请求，携带请求序号
+async fn reply(
+    socket: &mut WebSocket,
+    session_manager: &SessionManager,
+    encoding: WsEncoding,
+    resp: &WsPush,
+    seq: Option<u64>,
+) -> anyhow::Result<()> {
+    let payload = session_manager.reply_payload(resp, seq)?;
+    socket.send(payload.message(false, encoding)).await?;
+    Ok(())
+}
+
+/// 使用 token 登录成功的回复，查询用户信息失败时只返回 uid 和 token
+async fn authorized(db: Option<&DatabaseConnection>, uid: i64, token: String) -> LoginSuccess {
+    let mut resp = LoginSuccess {
+        uid,
+        avatar: None,
+        token,
+        name: None,
+        power: 0,
+    };
+    let Some(db) = db else {
+        return resp;
+    };
+    match UserRepo::find_by_id(db, uid).await {
+        Ok(Some(user)) => {
+            resp.avatar = user.avatar;
+            resp.name = user.name;
+        }
+        Ok(None) => {}
+        Err(error) => tracing::error!(%uid, %error, "Failed to query user."),
+    }
+    match RoleService::new(db)
+        .has_role(uid, role::Role::SuperAdmin)
+        .await
+    {
+        Ok(power) => resp.power = power as i32,
+        Err(error) => tracing::error!(%uid, %error, "Failed to query user role."),
+    }
+    resp
 }
 
 // 处理 WebSocket 连接
+#[allow(clippy::too_many_arguments)]
 async fn handle_websocket(
     id: usize,
-    addr: SocketAddr,
+    addr: IpAddr,
     mut socket: WebSocket,
-    mut receiver: Receiver<Message>,
-    wx_client: WxClient,
+    mut receiver: OutboxReceiver,
+    wx_client: DynWxApi,
+    work_client: Option<WorkClient>,
+    jwt_keys: JwtKeys,
+    ip_tracker: Option<IpTracker>,
+    active_tracker: Option<ActiveTracker>,
+    db: Option<DatabaseConnection>,
+    cache: Option<Cache>,
+    cursor: Option<PushCursor>,
+    store: Option<SessionStore>,
+    producer: Option<DynProducer>,
+    mut ticket: LoginTicket,
+    session_manager: &SessionManager,
 ) {
     let Some(id) = NonZeroUsize::new(id) else {
         tracing::error!(%id, %addr, "WebSocket id must be a nonzero usize");
         return;
     };
+    let encoding = session_manager
+        .sessions
+        .get(&id.get())
+        .map(|session| session.encoding)
+        .unwrap_or_default();
+    let config = &session_manager.config;
+    let heartbeat_timeout = Duration::from_secs(config.heartbeat_timeout_secs);
+    let mut heartbeat =
+        tokio::time::interval(Duration::from_secs(config.heartbeat_interval_secs.max(1)));
+    heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // 第一次立即触发，跳过
+    heartbeat.reset();
+    let mut last_received = Instant::now();
     loop {
         tokio::select! {
+            _ = heartbeat.tick(), if config.heartbeat_interval_secs > 0 => {
+                if let Err(error) = socket.send(Message::Ping(Vec::new())).await {
+                    tracing::error!(%id, %addr, %error, "Failed to send ping");
+                    break;
+                }
+            }
+            _ = tokio::time::sleep_until(last_received + heartbeat_timeout), if config.heartbeat_timeout_secs > 0 => {
+                tracing::info!(%id, %addr, "Heartbeat timeout, disconnect client.");
+                let close = Message::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "Heartbeat timeout".into(),
+                }));
+                if let Err(error) = socket.send(close).await {
+                    tracing::error!(%id, %addr, %error, "Failed to send close frame");
+                }
+                break;
+            }
+            _ = tokio::time::sleep_until(ticket.expire_at.unwrap_or_else(Instant::now)), if ticket.expire_at.is_some() => {
+                ticket.expire_at = None;
+                if session_manager.session_uid(id.get()).is_some() {
+                    continue;
+                }
+                tracing::info!(%id, %addr, "Login QRCode expired");
+                match session_manager.payload(&WsPush::LoginUrlExpired) {
+                    Ok(payload) => {
+                        if let Err(error) = socket.send(payload.message(false, encoding)).await {
+                            tracing::error!(%id, %addr, %error, "Failed to send login url expired");
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        tracing::error!(%id, %addr, %error, "Failed to serialize login url expired");
+                        break;
+                    }
+                }
+            }
             recv = socket.recv() => {
                 let Some(result) = recv else {
                     tracing::info!(%id, %addr, "WebSocket closed by client.");
@@ -60,15 +706,60 @@ async fn handle_websocket(
                 };
 
                 tracing::info!(%id, ?message, "Received message from websocket.");
-                match message {
-                    Message::Text(json) => {
-                        let req = match serde_json::from_str::<Req>(&json) {
-                            Ok(req) => req,
-                            Err(error) => {
-                                tracing::error!(%id, %error, %json, "Failed to deserialize json request from client.");
-                                break;
+                last_received = Instant::now();
+                session_manager.touch(id.get());
+                let parsed = match message {
+                    Message::Text(json) => parse_json_req(&json).inspect_err(|(error, _)| {
+                        tracing::warn!(%id, %error, %json, "Failed to deserialize json request from client.");
+                    }),
+                    Message::Binary(bytes) if encoding == WsEncoding::Protobuf => {
+                        parse_protobuf_req(&bytes).inspect_err(|(error, _)| {
+                            tracing::warn!(%id, %error, "Failed to decode protobuf request from client.");
+                        })
+                    }
+                    Message::Ping(bytes) => {
+                        if let Err(error) = socket.send(Message::Pong(bytes)).await {
+                            tracing::error!(%id, %error, "Failed to send pong back.");
+                            break;
+                        }
+                        continue;
+                    }
+                    // 服务端 Ping 的回应，只用于判断连接是否存活
+                    Message::Pong(_) => continue,
+                    unexpected_message => {
+                        tracing::warn!(%id, ?unexpected_message, "Received unexpected message from websocket");
+                        continue;
+                    }
+                };
+                let req = match parsed {
+                    Ok(req) => req,
+                    Err((error, seq)) => {
+                        let resp = WsPush::Error(WsError::new(ErrorCode::InvalidParam, format!("请求格式错误: {error}")));
+                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                            break;
+                        }
+                        if session_manager.record_violation(id.get()) {
+                            tracing::warn!(%id, %addr, "Too many protocol violations, disconnect client.");
+                            let close = Message::Close(Some(CloseFrame {
+                                code: close_code::PROTOCOL,
+                                reason: "Too many protocol violations".into(),
+                            }));
+                            if let Err(error) = socket.send(close).await {
+                                tracing::error!(%id, %addr, %error, "Failed to send close frame");
                             }
-                        };
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                let seq = req.seq;
+                // 心跳只说明连接还在，不算用户操作
+                if !matches!(req.r#type, ReqType::Heartbeat) {
+                    if let (Some(active_tracker), Some(uid)) = (&active_tracker, session_manager.session_uid(id.get())) {
+                        active_tracker.record(uid);
+                    }
+                }
 
                         match req  {
                             Req {
@@ -78,56 +769,188 @@ async fn handle_websocket(
                                 // do nothing
                             }
                             Req {
-                                r#type: ReqType::Login,
+                                r#type: r#type @ (ReqType::Login | ReqType::RefreshLogin),
+                                data,
                                 ..
                             } => {
-                                match wx_client.get_qrcode_tick_by_id(EXPIRE_SECONDS, false, id).await {
-                                    Ok(ticket) => {
-                                        let resp = Resp {
-                                            r#type: RespType::LoginUrl,
-                                            data: LoginUrl {
-                                                login_url: ticket.url
-                                            }
-                                        };
-                                        match serde_json::to_string(&resp) {
-                                            Ok(json) => {
-                                                if let Err(error) = socket.send(Message::Text(json)).await {
-                                                    tracing::error!(%id, %addr, %error, ?resp, "Failed to send response");
-                                                    break;
-                                                }
-                                            }
-                                            Err(error) => {
-                                                tracing::error!(%id, %addr, %error, ?resp, "Failed to serialize response");
-                                                break;
-                                            }
+                                if matches!(r#type, ReqType::RefreshLogin) && session_manager.session_uid(id.get()).is_some() {
+                                    tracing::warn!(%id, "Received refresh login request after authorized");
+                                    continue;
+                                }
+                                let data = match r#type {
+                                    ReqType::RefreshLogin => data.or_else(|| ticket.data.clone()),
+                                    _ => data,
+                                };
+                                let ttl = Duration::from_secs(config.login_qrcode_ttl_secs);
+                                // 配置了 Redis 时场景值由 Redis 分配，重启后仍然可以继续登录，否则使用连接 ID
+                                let issued = match &store {
+                                    Some(store) => store.issue(ttl, data.as_deref()).await.map(|IssuedLogin { scene, code }| (scene, Some(code))),
+                                    None => Ok((id, None)),
+                                };
+                                let login_url = match issued {
+                                    Ok((scene, login_code)) => {
+                                        session_manager.bind_scene(id.get(), scene);
+                                        match (data.as_deref(), &work_client) {
+                                            (Some(WORK_LOGIN), Some(work_client)) => Ok((work_client.login_url(&scene.to_string()), None)),
+                                            _ => wx_client
+                                                .get_qrcode_tick_by_id(Some(config.login_qrcode_ttl_secs), false, scene)
+                                                .await
+                                                .map(|ticket| (ticket.url, Some(ttl))),
+                                        }
+                                        .map(|(login_url, expires_in)| (login_url, login_code, expires_in))
+                                    }
+                                    Err(error) => Err(error),
+                                };
+                                match login_url {
+                                    Ok((login_url, login_code, expires_in)) => {
+                                        ticket.issue(data, expires_in);
+                                        let resp = WsPush::LoginUrl(LoginUrl {
+                                            login_url,
+                                            login_code,
+                                        });
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, ?resp, "Failed to send response");
+                                            break;
                                         }
                                     }
                                     Err(error) => {
                                         tracing::error!(%id, %error, "Failed to get QRCode tick by id");
+                                        let resp = WsPush::Error(WsError::new(ErrorCode::Unknown, "获取登录二维码失败"));
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                            break;
+                                        }
                                     }
                                 }
                             }
                             Req {
                                 r#type: ReqType::Authorize,
-                                data: Some(data),
+                                data: Some(token),
+                                ..
+                            } => {
+                                let resp = match authenticate(&jwt_keys, db.as_ref(), &token).await {
+                                    Ok(claims) => {
+                                        tracing::info!(%id, uid = %claims.uid, "Websocket session authorized");
+                                        Login {
+                                            session_manager,
+                                            id: id.get(),
+                                            addr,
+                                            ip_tracker: ip_tracker.as_ref(),
+                                            active_tracker: active_tracker.as_ref(),
+                                            db: db.as_ref(),
+                                            cache: cache.as_ref(),
+                                        }
+                                        .authenticate(claims.uid, claims.device.as_deref());
+                                        WsPush::LoginSuccess(authorized(db.as_ref(), claims.uid, token).await)
+                                    }
+                                    Err(error @ ApiError::Business(ErrorCode::PermissionDenied, _)) => {
+                                        tracing::warn!(%id, %error, "Received authorize request from banned user");
+                                        WsPush::Error(WsError::new(ErrorCode::PermissionDenied, "用户已被拉黑"))
+                                    }
+                                    Err(error) => {
+                                        tracing::warn!(%id, %error, "Received authorize request with invalid token");
+                                        WsPush::TokenExpired
+                                    }
+                                };
+                                if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                    tracing::error!(%id, %addr, %error, "Failed to reply authorize result");
+                                    break;
+                                }
+                            }
+                            Req {
+                                r#type: ReqType::Ack,
+                                data: Some(ack),
+                                ..
+                            } => {
+                                match (session_manager.session_uid(id.get()), &cursor, ack.parse::<u64>()) {
+                                    (Some(uid), Some(cursor), Ok(ack)) => ack_push(cursor.clone(), id.get(), uid, ack),
+                                    (_, _, Err(error)) => {
+                                        tracing::warn!(%id, %error, %ack, "Received ack request with invalid seq");
+                                        let resp = WsPush::Error(WsError::new(ErrorCode::InvalidParam, "确认的推送序号无效"));
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                            break;
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            Req {
+                                r#type: ReqType::Resume,
+                                data,
+                                ..
+                            } => {
+                                let resume_from = match data.as_deref().map(str::parse::<u64>).transpose() {
+                                    Ok(resume_from) => resume_from,
+                                    Err(error) => {
+                                        tracing::warn!(%id, %error, "Received resume request with invalid seq");
+                                        let resp = WsPush::Error(WsError::new(ErrorCode::InvalidParam, "补发的推送序号无效"));
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                            break;
+                                        }
+                                        continue;
+                                    }
+                                };
+                                match (session_manager.session_uid(id.get()), &db) {
+                                    (Some(uid), Some(db)) => resume(
+                                        db.clone(),
+                                        cursor.clone(),
+                                        session_manager.clone(),
+                                        id.get(),
+                                        uid,
+                                        resume_from,
+                                    ),
+                                    (None, _) => {
+                                        tracing::warn!(%id, "Received resume request before authorized");
+                                        let resp = WsPush::Error(WsError::new(ErrorCode::InvalidToken, "请先登录"));
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                            break;
+                                        }
+                                    }
+                                    (_, None) => {}
+                                }
+                            }
+                            Req {
+                                r#type: ReqType::Typing,
+                                data: Some(room_id),
+                                ..
                             } => {
-                                tracing::info!(%id, %data, "Received authorize request");
+                                let Some(uid) = session_manager.session_uid(id.get()) else {
+                                    tracing::warn!(%id, "Received typing request before authorized");
+                                    let resp = WsPush::Error(WsError::new(ErrorCode::InvalidToken, "请先登录"));
+                                    if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                        tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                        break;
+                                    }
+                                    continue;
+                                };
+                                let room_id = match room_id.parse::<i64>() {
+                                    Ok(room_id) => room_id,
+                                    Err(error) => {
+                                        tracing::warn!(%id, %error, %room_id, "Received typing request with invalid room id");
+                                        let resp = WsPush::Error(WsError::new(ErrorCode::InvalidParam, "房间 ID 无效"));
+                                        if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                            tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                            break;
+                                        }
+                                        continue;
+                                    }
+                                };
+                                if let (Some(db), true) = (&db, session_manager.throttle_typing(uid)) {
+                                    notify_typing(db.clone(), producer.clone(), session_manager.clone(), uid, room_id);
+                                }
                             }
                             unexpected_req => {
                                 tracing::warn!(%id, ?unexpected_req, "Received unexpected request from websocket");
+                                let resp = WsPush::Error(WsError::new(ErrorCode::InvalidParam, "缺少请求数据"));
+                                if let Err(error) = reply(&mut socket, session_manager, encoding, &resp, seq).await {
+                                    tracing::error!(%id, %addr, %error, "Failed to reply error");
+                                    break;
+                                }
                             }
                         }
-                    }
-                    Message::Ping(bytes) => {
-                        if let Err(error) = socket.send(Message::Pong(bytes)).await {
-                            tracing::error!(%id, %error, "Failed to send pong back.");
-                            break;
-                        }
-                    }
-                    unexpected_message => {
-                        tracing::warn!(%id, ?unexpected_message, "Received unexpected message from websocket");
-                    }
-                }
             }
             to_send = receiver.recv() => {
                 let Some(message) = to_send else {
@@ -135,10 +958,17 @@ async fn handle_websocket(
                     break;
                 };
 
+                let close = matches!(message, Message::Close(_));
+                let bytes = message_len(&message);
                 if let Err(error) = socket.send(message).await {
                     tracing::error!(%id, %error, "Failed to send message to client");
                     break;
                 }
+                session_manager.record_sent(id.get(), bytes);
+                if close {
+                    tracing::info!(%id, %addr, "WebSocket closed by server.");
+                    break;
+                }
             }
         }
     }
@@ -153,46 +983,29 @@ pub struct Req {
     pub r#type: ReqType,
     /// 请求数据
     pub data: Option<String>,
+    /// 请求序号，登录二维码、登录结果和错误等直接回复中原样返回
+    #[serde(default)]
+    pub seq: Option<u64>,
 }
 
 /// WebSocket 请求类型
 #[derive(Debug, serde_repr::Deserialize_repr)]
 #[repr(u8)]
 pub enum ReqType {
-    /// 登录
+    /// 登录，数据为 [`WORK_LOGIN`] 时使用企业微信登录，否则使用公众号二维码登录
     Login = 1,
     /// 心跳
     Heartbeat = 2,
     /// 登录
     Authorize = 3,
-}
-
-/// WebSocket 响应类型
-#[derive(Debug, serde_repr::Serialize_repr)]
-#[repr(u8)]
-pub enum RespType {
-    /// 登录二维码返回
-    LoginUrl = 1,
-    /// 用户扫描成功等待授权
-    LoginScanSuccess = 2,
-    /// 用户登录成功返回用户信息
-    LoginSuccess = 3,
-}
-
-/// WebSocket 响应
-#[derive(Debug, Serialize)]
-pub struct Resp<T> {
-    /// 响应类型
-    pub r#type: RespType,
-    /// 响应数据
-    pub data: T,
-}
-
-/// 登录 Url
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct LoginUrl {
-    login_url: String,
+    /// 确认推送，数据为推送的序号
+    Ack = 4,
+    /// 重连后补发消息，数据为已收到的序号，为空时使用服务端记录的序号
+    Resume = 5,
+    /// 刷新登录二维码，数据与登录请求相同，为空时使用上一次登录请求的登录方式
+    RefreshLogin = 6,
+    /// 正在输入，数据为房间 ID
+    Typing = 7,
 }
 
 /// 登录认证
@@ -209,28 +1022,232 @@ pub enum Role {
     Guest,
     /// 已登录用户
     Authenticated {
-        /// 用户信息
-        user: User,
+        /// 用户 ID
+        uid: i64,
     },
 }
 
+impl Role {
+    /// 已登录用户的 ID
+    pub fn uid(&self) -> Option<i64> {
+        match self {
+            Role::Guest => None,
+            Role::Authenticated { uid } => Some(*uid),
+        }
+    }
+}
+
 /// # WebSocket session
 #[derive(Debug)]
 pub struct Session {
     /// ID
     pub id: Id,
-    /// IP 地址
-    pub ip_addr: SocketAddr,
+    /// 客户端 IP，经过可信代理时为代理转发的客户端地址
+    pub ip_addr: IpAddr,
     /// 角色
     pub role: Role,
-    /// 消息发送器
-    pub sender: Sender<Message>,
+    /// 发送队列
+    pub outbox: Outbox,
+    /// 是否压缩较大的推送
+    pub compress: bool,
+    /// 推送的编码
+    pub encoding: WsEncoding,
+    /// 建立连接的时间（毫秒时间戳）
+    pub connect_time: i64,
+    /// 客户端 User-Agent
+    pub user_agent: Option<String>,
+    /// 最后一次收到客户端消息的时间（毫秒时间戳）
+    pub last_active_time: i64,
+    /// 用户最后一次操作的时间（毫秒时间戳），不包括心跳
+    pub last_opt_time: i64,
+    /// 已发送给客户端的字节数
+    pub bytes_sent: u64,
+    /// 违反协议的次数，如无法解析的请求
+    pub violations: usize,
+    /// 最近一次签发的登录二维码的场景值
+    pub scene: Option<NonZeroUsize>,
+    /// 设备 ID，使用 token 登录时为 token 中的设备
+    pub device: Option<String>,
+}
+
+/// 连接信息
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    /// 连接 ID
+    pub id: usize,
+    /// 已登录用户的 ID，游客为空
+    pub uid: Option<i64>,
+    /// 客户端地址
+    pub ip_addr: String,
+    /// 客户端 User-Agent
+    pub user_agent: Option<String>,
+    /// 建立连接的时间（毫秒时间戳）
+    pub connect_time: i64,
+    /// 最后一次收到客户端消息的时间（毫秒时间戳）
+    pub last_active_time: i64,
+    /// 已发送给客户端的字节数
+    pub bytes_sent: u64,
+    /// 发送队列中等待发送的消息数
+    pub queued: usize,
+}
+
+impl From<&Session> for SessionInfo {
+    fn from(session: &Session) -> Self {
+        Self {
+            id: session.id.id(),
+            uid: session.role.uid(),
+            ip_addr: session.ip_addr.to_string(),
+            user_agent: session.user_agent.clone(),
+            connect_time: session.connect_time,
+            last_active_time: session.last_active_time,
+            bytes_sent: session.bytes_sent,
+            queued: session.outbox.len(),
+        }
+    }
 }
 
 impl Session {
-    /// 发送消息
-    pub async fn send(&self, msg: Message) -> anyhow::Result<()> {
-        Ok(self.sender.send(msg).await?)
+    /// 推送消息，不等待
+    pub fn send(&self, msg: Message) -> PushOutcome {
+        self.outbox.push(msg)
+    }
+}
+
+/// WebSocket 配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WsConfig {
+    /// 最大连接数，0 表示不限制
+    #[serde(default = "default::max_connections")]
+    pub max_connections: usize,
+    /// 单个 IP 的最大连接数，0 表示不限制
+    ///
+    /// 部署在反向代理之后时需要配置 `trusted_proxies`，否则所有连接都来自代理的 IP
+    #[serde(default = "default::max_connections_per_ip")]
+    pub max_connections_per_ip: usize,
+    /// 每个连接的发送队列长度
+    #[serde(default = "default::queue_capacity")]
+    pub queue_capacity: usize,
+    /// 发送队列满时的处理策略
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    /// 压缩阈值，客户端支持压缩时超过该字节数的推送以 gzip 压缩，0 表示不压缩
+    #[serde(default = "default::compression_threshold_bytes")]
+    pub compression_threshold_bytes: usize,
+    /// 已登录用户超过该秒数没有操作（心跳不算）时视为离开
+    #[serde(default = "default::away_secs")]
+    pub away_secs: u64,
+    /// 连接违反协议（如发送无法解析的请求）超过该次数时断开，0 表示不断开
+    #[serde(default = "default::max_protocol_violations")]
+    pub max_protocol_violations: usize,
+    /// 公众号登录二维码的有效期（秒），过期后推送 `LoginUrlExpired`
+    #[serde(default = "default::login_qrcode_ttl_secs")]
+    pub login_qrcode_ttl_secs: u64,
+    /// 服务端发送 Ping 的间隔（秒），用于保持经过代理的连接，0 表示不发送
+    #[serde(default = "default::heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// 超过该秒数没有收到客户端的任何消息（包括心跳和 Pong）时断开，0 表示不断开
+    #[serde(default = "default::heartbeat_timeout_secs")]
+    pub heartbeat_timeout_secs: u64,
+    /// 客户端消息（及单个帧）的最大字节数，超过时断开，0 表示使用默认的限制（消息 64 MiB，帧 16 MiB）
+    #[serde(default = "default::max_message_bytes")]
+    pub max_message_bytes: usize,
+    /// 停机时通知客户端重连的最大随机延迟（毫秒），分散重连到其他实例的时间
+    #[serde(default = "default::reconnect_spread_millis")]
+    pub reconnect_spread_millis: u64,
+    /// 新连接的接受速率和断线补发的调度，见 [`admission`]
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+}
+
+mod default {
+    pub fn max_connections() -> usize {
+        10000
+    }
+
+    pub fn max_connections_per_ip() -> usize {
+        16
+    }
+
+    pub fn queue_capacity() -> usize {
+        32
+    }
+
+    pub fn compression_threshold_bytes() -> usize {
+        1024
+    }
+
+    pub fn away_secs() -> u64 {
+        300
+    }
+
+    pub fn max_protocol_violations() -> usize {
+        5
+    }
+
+    pub fn login_qrcode_ttl_secs() -> u64 {
+        60 * 60
+    }
+
+    pub fn heartbeat_interval_secs() -> u64 {
+        30
+    }
+
+    pub fn heartbeat_timeout_secs() -> u64 {
+        90
+    }
+
+    pub fn max_message_bytes() -> usize {
+        64 * 1024
+    }
+
+    pub fn reconnect_spread_millis() -> u64 {
+        5000
+    }
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: default::max_connections(),
+            max_connections_per_ip: default::max_connections_per_ip(),
+            queue_capacity: default::queue_capacity(),
+            overflow_policy: OverflowPolicy::default(),
+            compression_threshold_bytes: default::compression_threshold_bytes(),
+            away_secs: default::away_secs(),
+            max_protocol_violations: default::max_protocol_violations(),
+            login_qrcode_ttl_secs: default::login_qrcode_ttl_secs(),
+            heartbeat_interval_secs: default::heartbeat_interval_secs(),
+            heartbeat_timeout_secs: default::heartbeat_timeout_secs(),
+            max_message_bytes: default::max_message_bytes(),
+            reconnect_spread_millis: default::reconnect_spread_millis(),
+            admission: AdmissionConfig::default(),
+        }
+    }
+}
+
+/// 拒绝连接的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ConnectionRejected {
+    /// 超过最大连接数
+    #[error("Too many websocket connections")]
+    TooManyConnections,
+    /// 超过单个 IP 的最大连接数
+    #[error("Too many websocket connections from this IP")]
+    TooManyConnectionsFromIp,
+    /// 超过新连接的接受速率，客户端应退避后重试
+    #[error("Too many websocket connections are being established, retry later")]
+    AcceptRateLimited,
+}
+
+impl From<ConnectionRejected> for ApiError {
+    fn from(rejected: ConnectionRejected) -> Self {
+        let code = match rejected {
+            ConnectionRejected::TooManyConnections => ErrorCode::TooManyConnections,
+            ConnectionRejected::TooManyConnectionsFromIp => ErrorCode::TooManyConnectionsFromIp,
+            ConnectionRejected::AcceptRateLimited => ErrorCode::ServerBusy,
+        };
+        ApiError::business(code, rejected.to_string())
     }
 }
 
@@ -239,35 +1256,566 @@ impl Session {
 pub struct SessionManager {
     id_gen: IdGenerator,
     sessions: Arc<DashMap<usize, Session>>,
+    config: WsConfig,
+    connections: Arc<AtomicUsize>,
+    connections_per_ip: Arc<DashMap<IpAddr, usize>>,
+    push_stats: Arc<PushStats>,
+    typing: Arc<DashMap<i64, i64>>,
+    scenes: Arc<DashMap<NonZeroUsize, usize>>,
+    admission: Arc<Admission>,
+}
+
+/// 序列化后的推送，压缩和 protobuf 编码结果在第一个需要的连接发送时生成，之后复用
+struct Payload<'a> {
+    push: &'a WsPush,
+    seq: Option<u64>,
+    json: String,
+    threshold: usize,
+    gzip: OnceLock<Option<Vec<u8>>>,
+    protobuf: OnceLock<Vec<u8>>,
+}
+
+impl Payload<'_> {
+    fn message(&self, compress: bool, encoding: WsEncoding) -> Message {
+        if encoding == WsEncoding::Protobuf {
+            let bytes = self.protobuf.get_or_init(|| {
+                let mut frame = PushFrame::from(self.push);
+                frame.seq = self.seq;
+                prost::Message::encode_to_vec(&frame)
+            });
+            return Message::Binary(bytes.clone());
+        }
+        if compress && self.threshold > 0 && self.json.len() >= self.threshold {
+            let gzip = self.gzip.get_or_init(|| {
+                gzip(self.json.as_bytes())
+                    .inspect_err(|error| tracing::error!(%error, "Failed to compress push."))
+                    .ok()
+            });
+            if let Some(bytes) = gzip {
+                return Message::Binary(bytes.clone());
+            }
+        }
+        Message::Text(self.json.clone())
+    }
 }
 
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// 不限制连接数时预分配的连接 ID 数
+const DEFAULT_ID_CAPACITY: usize = 1024;
+
 impl SessionManager {
-    /// 接收一个 WebSocket 连接
-    pub fn accept(&self, ip_addr: SocketAddr) -> (usize, Receiver<Message>) {
+    /// 使用指定的配置创建
+    pub fn new(config: WsConfig) -> Self {
+        let capacity = match config.max_connections {
+            0 => DEFAULT_ID_CAPACITY,
+            max_connections => max_connections,
+        };
+        Self {
+            id_gen: IdGenerator::with_capacity(capacity),
+            admission: Arc::new(Admission::new(config.admission.clone())),
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// 连接准入，超过新连接的接受速率时拒绝，见 [`admission`]
+    pub fn admit(&self) -> Result<(), ConnectionRejected> {
+        if self.admission.try_admit() {
+            Ok(())
+        } else {
+            Err(ConnectionRejected::AcceptRateLimited)
+        }
+    }
+
+    /// 接收一个 WebSocket 连接，超过连接数限制时拒绝
+    pub fn accept(&self, ip_addr: IpAddr) -> Result<(usize, OutboxReceiver), ConnectionRejected> {
+        let max_connections = self.config.max_connections;
+        if self.connections.fetch_add(1, Ordering::SeqCst) >= max_connections && max_connections > 0
+        {
+            self.connections.fetch_sub(1, Ordering::SeqCst);
+            return Err(ConnectionRejected::TooManyConnections);
+        }
+        {
+            let mut count = self.connections_per_ip.entry(ip_addr).or_insert(0);
+            let max_connections_per_ip = self.config.max_connections_per_ip;
+            if *count >= max_connections_per_ip && max_connections_per_ip > 0 {
+                drop(count);
+                self.connections.fetch_sub(1, Ordering::SeqCst);
+                return Err(ConnectionRejected::TooManyConnectionsFromIp);
+            }
+            *count += 1;
+        }
+
         let id = self.id_gen.generate();
-        let (sender, receiver) = tokio::sync::mpsc::channel(32);
+        let (outbox, receiver) =
+            outbox::outbox(self.config.queue_capacity, self.config.overflow_policy);
         let ws_id = id.id();
+        let now = current_millisecond();
         self.sessions.insert(
             id.id(),
             Session {
                 id,
                 ip_addr,
                 role: Role::Guest,
-                sender,
+                outbox,
+                compress: false,
+                encoding: WsEncoding::Json,
+                connect_time: now,
+                user_agent: None,
+                last_active_time: now,
+                last_opt_time: now,
+                bytes_sent: 0,
+                violations: 0,
+                scene: None,
+                device: None,
             },
         );
-        (ws_id, receiver)
+        Ok((ws_id, receiver))
+    }
+
+    /// 模拟一个已登录用户的连接，返回连接 ID 和推送给该连接的消息
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn connect(&self, uid: i64) -> (usize, OutboxReceiver) {
+        let (id, receiver) = self
+            .accept(IpAddr::from([127, 0, 0, 1]))
+            .expect("connection limit exceeded in tests");
+        self.authenticate(id, uid);
+        (id, receiver)
+    }
+
+    /// 移除一个 WebSocket 连接
+    pub fn remove(&self, id: usize) {
+        let Some((_, session)) = self.sessions.remove(&id) else {
+            return;
+        };
+        if let Some(scene) = session.scene {
+            self.scenes.remove_if(&scene, |_, bound| *bound == id);
+        }
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+        let ip = session.ip_addr;
+        if let Some(mut count) = self.connections_per_ip.get_mut(&ip) {
+            *count = count.saturating_sub(1);
+        }
+        self.connections_per_ip
+            .remove_if(&ip, |_, count| *count == 0);
+    }
+
+    fn update(&self, id: usize, f: impl FnOnce(&mut Session)) -> bool {
+        match self.sessions.get_mut(&id) {
+            Some(mut session) => {
+                f(&mut session);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 将连接标记为已登录用户
+    pub fn authenticate(&self, id: usize, uid: i64) -> bool {
+        self.update(id, |session| session.role = Role::Authenticated { uid })
+    }
+
+    /// 对连接启用推送压缩，未配置压缩阈值时不启用
+    pub fn enable_compression(&self, id: usize) -> bool {
+        self.config.compression_threshold_bytes > 0
+            && self.update(id, |session| session.compress = true)
+    }
+
+    /// 设置连接的推送编码
+    pub fn set_encoding(&self, id: usize, encoding: WsEncoding) -> bool {
+        self.update(id, |session| session.encoding = encoding)
+    }
+
+    /// 记录客户端的 User-Agent
+    pub fn set_user_agent(&self, id: usize, user_agent: Option<String>) -> bool {
+        self.update(id, |session| session.user_agent = user_agent)
+    }
+
+    /// 设置连接的设备 ID
+    pub fn set_device(&self, id: usize, device: String) -> bool {
+        self.update(id, |session| session.device = Some(device))
+    }
+
+    /// 连接的设备 ID
+    pub fn session_device(&self, id: usize) -> Option<String> {
+        self.sessions
+            .get(&id)
+            .and_then(|session| session.device.clone())
+    }
+
+    /// 连接的登录设备，登录时间为当前时间
+    pub fn login_device(&self, id: usize) -> Option<LoginDevice> {
+        let session = self.sessions.get(&id)?;
+        Some(LoginDevice::new(
+            session.device.as_deref(),
+            session.user_agent.as_deref(),
+            Some(session.ip_addr),
+        ))
+    }
+
+    /// 记录收到客户端消息
+    fn touch(&self, id: usize) {
+        self.update(id, |session| {
+            session.last_active_time = current_millisecond()
+        });
+    }
+
+    /// 记录用户操作，刷新该用户所有连接的最后操作时间
+    pub fn touch_user(&self, uid: i64) {
+        let now = current_millisecond();
+        for mut session in self.sessions.iter_mut() {
+            if session.role.uid() == Some(uid) {
+                session.last_opt_time = now;
+            }
+        }
+    }
+
+    /// 用户是否可以推送正在输入，同一用户每 [`TYPING_INTERVAL_MILLIS`] 最多推送一次
+    pub fn throttle_typing(&self, uid: i64) -> bool {
+        let now = current_millisecond();
+        // 记录数超过连接数时清理过期的记录，避免无限增长
+        if self.typing.len() > self.sessions.len() {
+            self.typing
+                .retain(|_, last| now - *last < TYPING_INTERVAL_MILLIS);
+        }
+        let mut last = self.typing.entry(uid).or_insert(i64::MIN);
+        if now.saturating_sub(*last) < TYPING_INTERVAL_MILLIS {
+            return false;
+        }
+        *last = now;
+        true
+    }
+
+    /// 记录连接违反协议一次，超过 [`WsConfig::max_protocol_violations`] 时返回 `true`，应断开连接
+    pub fn record_violation(&self, id: usize) -> bool {
+        let max = self.config.max_protocol_violations;
+        let mut exceeded = false;
+        self.update(id, |session| {
+            session.violations += 1;
+            exceeded = max > 0 && session.violations > max;
+        });
+        exceeded
+    }
+
+    /// 记录发送给客户端的字节数
+    fn record_sent(&self, id: usize, bytes: usize) {
+        self.update(id, |session| session.bytes_sent += bytes as u64);
+    }
+
+    /// 查询连接，可以按用户 ID 和 IP 过滤，按连接 ID 排序
+    pub fn list(&self, uid: Option<i64>, ip: Option<IpAddr>) -> Vec<SessionInfo> {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|session| uid.is_none() || session.role.uid() == uid)
+            .filter(|session| ip.is_none() || Some(session.ip_addr) == ip)
+            .map(|session| SessionInfo::from(session.value()))
+            .collect();
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
+
+    /// 踢出用户：推送 token 失效并关闭该用户的所有连接，返回关闭的连接数
+    ///
+    /// 只处理连接，吊销 token 见 [`JwtKeys::revoke_user`]
+    pub fn kick_user(&self, uid: i64, reason: &str) -> anyhow::Result<usize> {
+        self.kick(|session| session.role.uid() == Some(uid), reason)
+    }
+
+    /// 下线用户的某个设备：推送 token 失效并关闭该设备的连接，返回关闭的连接数
+    ///
+    /// 只处理连接，吊销 token 见 [`JwtKeys::revoke_device`]
+    pub fn kick_device(&self, uid: i64, device: &str, reason: &str) -> anyhow::Result<usize> {
+        self.kick(
+            |session| session.role.uid() == Some(uid) && session.device.as_deref() == Some(device),
+            reason,
+        )
     }
 
-    /// 获取某个连接的引用
-    pub async fn try_send<T: Serialize>(&self, id: usize, resp: &Resp<T>) -> anyhow::Result<bool> {
-        if let Some(pair) = self.sessions.get_mut(&id) {
-            pair.sender
-                .send(Message::Text(serde_json::to_string(resp)?))
-                .await?;
+    fn kick(&self, predicate: impl Fn(&Session) -> bool, reason: &str) -> anyhow::Result<usize> {
+        let payload = self.payload(&WsPush::TokenExpired)?;
+        let mut kicked = 0;
+        for mut session in self.sessions.iter_mut() {
+            if !predicate(&session) {
+                continue;
+            }
+            let _ = session.send(payload.message(session.compress, session.encoding));
+            let close = Message::Close(Some(CloseFrame {
+                code: close_code::POLICY,
+                reason: reason.to_string().into(),
+            }));
+            let _ = session.outbox.push_control(close);
+            // 关闭前不再接收该用户的推送
+            session.role = Role::Guest;
+            kicked += 1;
         }
+        Ok(kicked)
+    }
 
-        Ok(false)
+    /// 强制断开某个连接，返回连接是否存在
+    pub fn disconnect(&self, id: usize) -> bool {
+        let Some(session) = self.sessions.get(&id) else {
+            return false;
+        };
+        let close = Message::Close(Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: "Disconnected by administrator".into(),
+        }));
+        let _ = session.outbox.push_control(close);
+        true
+    }
+
+    /// 断开超过 `max_idle` 没有收到客户端消息（包括心跳）的连接，返回断开的连接数
+    pub fn disconnect_idle(&self, max_idle: Duration) -> usize {
+        let deadline = current_millisecond() - max_idle.as_millis() as i64;
+        let mut closed = 0;
+        for session in self.sessions.iter() {
+            if session.last_active_time >= deadline {
+                continue;
+            }
+            let close = Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "Idle timeout".into(),
+            }));
+            let _ = session.outbox.push_control(close);
+            closed += 1;
+        }
+        closed
+    }
+
+    /// 用户是否在线（至少有一个已登录的连接）
+    pub fn is_online(&self, uid: i64) -> bool {
+        self.sessions
+            .iter()
+            .any(|session| session.role.uid() == Some(uid))
+    }
+
+    /// 用户在本实例的在线状态
+    ///
+    /// 有连接在 `away_secs` 内操作过为在线，有连接但都超时为离开，没有连接为离线
+    pub fn active_status(&self, uid: i64) -> ActiveStatus {
+        let deadline = self.away_deadline();
+        self.sessions
+            .iter()
+            .filter(|session| session.role.uid() == Some(uid))
+            .map(|session| Self::status(session.last_opt_time, deadline))
+            .min_by_key(|status| *status as u8)
+            .unwrap_or(ActiveStatus::Offline)
+    }
+
+    /// 本实例所有已登录用户的在线状态，不包括离线用户
+    pub fn presence(&self) -> HashMap<i64, ActiveStatus> {
+        let deadline = self.away_deadline();
+        let mut presence = HashMap::new();
+        for session in self.sessions.iter() {
+            let Some(uid) = session.role.uid() else {
+                continue;
+            };
+            let status = Self::status(session.last_opt_time, deadline);
+            presence
+                .entry(uid)
+                .and_modify(|current: &mut ActiveStatus| {
+                    if status == ActiveStatus::Online {
+                        *current = status;
+                    }
+                })
+                .or_insert(status);
+        }
+        presence
+    }
+
+    fn away_deadline(&self) -> i64 {
+        current_millisecond() - (self.config.away_secs * 1000) as i64
+    }
+
+    fn status(last_opt_time: i64, deadline: i64) -> ActiveStatus {
+        if last_opt_time >= deadline {
+            ActiveStatus::Online
+        } else {
+            ActiveStatus::Away
+        }
+    }
+
+    /// 在线用户数（已登录的用户去重）
+    pub fn online_users(&self) -> usize {
+        self.online_uids().len()
+    }
+
+    /// 将登录二维码的场景值绑定到连接，扫码事件通过场景值找到连接，之前签发的场景值不再有效
+    pub fn bind_scene(&self, id: usize, scene: NonZeroUsize) -> bool {
+        let mut previous = None;
+        if !self.update(id, |session| previous = session.scene.replace(scene)) {
+            return false;
+        }
+        if let Some(previous) = previous.filter(|previous| *previous != scene) {
+            self.scenes.remove_if(&previous, |_, bound| *bound == id);
+        }
+        self.scenes.insert(scene, id);
+        true
+    }
+
+    /// 场景值绑定的连接
+    pub fn scene_session(&self, scene: NonZeroUsize) -> Option<usize> {
+        self.scenes.get(&scene).map(|id| *id)
+    }
+
+    /// 连接的用户，未登录时为 `None`
+    fn session_uid(&self, id: usize) -> Option<i64> {
+        self.sessions
+            .get(&id)
+            .and_then(|session| session.role.uid())
+    }
+
+    /// 本实例在线的用户，不包括游客
+    pub fn online_uids(&self) -> Vec<i64> {
+        self.sessions
+            .iter()
+            .filter_map(|session| session.role.uid())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// 向某个用户的所有连接推送消息，不等待，返回加入发送队列的连接数
+    pub fn send_to_user(&self, uid: i64, resp: &WsPush) -> anyhow::Result<usize> {
+        let payload = self.payload(resp)?;
+        let mut sent = 0;
+        for session in self
+            .sessions
+            .iter()
+            .filter(|session| session.role.uid() == Some(uid))
+        {
+            if self.record(
+                session.id.id(),
+                session.send(payload.message(session.compress, session.encoding)),
+            ) {
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// 向所有在线连接推送消息，不等待，返回加入发送队列的连接数
+    ///
+    /// 消息只序列化、压缩一次；`authenticated_only` 为 `true` 时跳过未登录的连接。
+    /// 加入队列不会阻塞，在当前线程逐个推送即可，不占用异步运行时之外的线程。
+    pub fn broadcast_all(&self, resp: &WsPush, authenticated_only: bool) -> anyhow::Result<usize> {
+        let payload = self.payload(resp)?;
+        // 先取出发送队列再推送，避免推送期间持有 DashMap 的锁
+        let outboxes: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|session| !authenticated_only || session.role.uid().is_some())
+            .map(|session| {
+                let format = (session.compress, session.encoding);
+                (session.id.id(), session.outbox.clone(), format)
+            })
+            .collect();
+
+        let sent = outboxes
+            .iter()
+            .filter(|(id, outbox, (compress, encoding))| {
+                self.record(*id, outbox.push(payload.message(*compress, *encoding)))
+            })
+            .count();
+        Ok(sent)
+    }
+
+    fn payload<'a>(&self, resp: &'a WsPush) -> anyhow::Result<Payload<'a>> {
+        self.reply_payload(resp, resp.seq())
+    }
+
+    /// 对请求的直接回复，`seq` 为请求序号
+    fn reply_payload<'a>(&self, resp: &'a WsPush, seq: Option<u64>) -> anyhow::Result<Payload<'a>> {
+        Ok(Payload {
+            push: resp,
+            seq,
+            protobuf: OnceLock::new(),
+            json: serde_json::to_string(&WsReply { push: resp, seq })?,
+            threshold: self.config.compression_threshold_bytes,
+            gzip: OnceLock::new(),
+        })
+    }
+
+    /// 推送统计
+    pub fn push_stats(&self) -> &PushStats {
+        &self.push_stats
+    }
+
+    fn record(&self, id: usize, outcome: PushOutcome) -> bool {
+        self.push_stats.record(outcome);
+        match outcome {
+            PushOutcome::DroppedOldest | PushOutcome::DroppedNewest => {
+                tracing::warn!(%id, ?outcome, "Websocket outbox is full, push dropped.");
+            }
+            PushOutcome::Disconnected => {
+                tracing::warn!(%id, "Websocket outbox is full, disconnect slow client.");
+            }
+            PushOutcome::Queued | PushOutcome::Closed => {}
+        }
+        outcome.is_queued()
+    }
+
+    /// 当前连接数
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// 是否没有任何连接
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// 向所有连接发送 [`WsPush::Reconnect`] 和 Close 帧，并等待连接全部关闭
+    ///
+    /// 每个连接的重连延迟在 `reconnect_spread_millis` 内随机选取，避免客户端同时重连。
+    /// 返回超时后仍未关闭的连接数
+    pub async fn close_all(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let spread = self.config.reconnect_spread_millis;
+        for session in self.sessions.iter() {
+            let reconnect = WsPush::Reconnect(Reconnect {
+                after_ms: rand::thread_rng().gen_range(0..=spread),
+            });
+            match self.payload(&reconnect) {
+                // 不受队列容量限制，保证在 Close 帧之前送达
+                Ok(payload) => {
+                    let _ = session
+                        .outbox
+                        .push_control(payload.message(session.compress, session.encoding));
+                }
+                Err(error) => tracing::error!(%error, "Failed to serialize reconnect push."),
+            }
+            let close = Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "Server is shutting down".into(),
+            }));
+            // 发送失败说明连接已经关闭
+            let _ = session.outbox.push_control(close);
+        }
+
+        while !self.sessions.is_empty() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        self.sessions.len()
+    }
+
+    /// 向某个连接推送消息，不等待，返回是否加入了发送队列
+    pub fn try_send(&self, id: usize, resp: &WsPush) -> anyhow::Result<bool> {
+        let payload = self.payload(resp)?;
+        Ok(match self.sessions.get(&id) {
+            Some(session) => self.record(
+                id,
+                session.send(payload.message(session.compress, session.encoding)),
+            ),
+            None => false,
+        })
     }
 }
 
@@ -281,7 +1829,15 @@ pub struct IdGenerator {
 
 impl Default for IdGenerator {
     fn default() -> Self {
-        let mut slab = Slab::with_capacity(1024);
+        Self::with_capacity(DEFAULT_ID_CAPACITY)
+    }
+}
+
+impl IdGenerator {
+    /// 预分配 `capacity` 个 ID
+    pub fn with_capacity(capacity: usize) -> Self {
+        // 0 被保留，多分配一个
+        let mut slab = Slab::with_capacity(capacity + 1);
         // preserve zero
         let _zero = slab.insert(());
 
@@ -289,9 +1845,7 @@ impl Default for IdGenerator {
             slab: Arc::new(RwLock::new(slab)),
         }
     }
-}
 
-impl IdGenerator {
     /// 生成一个新的 ID，会在离开作用域时自动释放
     pub fn generate(&self) -> Id {
         let id = {
@@ -356,7 +1910,21 @@ impl Drop for Id {
 
 #[cfg(test)]
 mod tests {
-    use crate::handler::ws::IdGenerator;
+    use crate::active::ActiveStatus;
+    use crate::handler::auth::{Claims, JwtKeys};
+    use crate::handler::ws::outbox::OverflowPolicy;
+    use crate::handler::ws::proto::{PushFrame, WsEncoding};
+    use crate::handler::ws::push::{FriendApply, WsPush};
+    use crate::handler::ws::{
+        authorize_upgrade, ConnectionRejected, IdGenerator, SessionGuard, SessionManager, WsConfig,
+    };
+    use axum::extract::ws::Message;
+    use axum::http::header::SEC_WEBSOCKET_PROTOCOL;
+    use axum::http::HeaderMap;
+    use std::io::Read;
+    use std::net::IpAddr;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
 
     #[test]
     fn id_manager() {
@@ -380,4 +1948,387 @@ mod tests {
         let id6 = id_manager.generate();
         assert_eq!(id6.id(), 1);
     }
+
+    #[test]
+    fn session_authenticate() {
+        let session_manager = SessionManager::default();
+        let addr = IpAddr::from([127, 0, 0, 1]);
+        let (id, _receiver) = session_manager.accept(addr).expect("accept");
+        assert!(!session_manager.is_online(12));
+        assert!(session_manager.authenticate(id, 12));
+        assert!(session_manager.is_online(12));
+        let (other, _other_receiver) = session_manager.accept(addr).expect("accept");
+        assert!(session_manager.authenticate(other, 12));
+        assert_eq!(session_manager.online_users(), 1);
+        assert_eq!(session_manager.online_uids(), vec![12]);
+        session_manager.remove(other);
+        session_manager.remove(id);
+        assert_eq!(session_manager.online_users(), 0);
+        assert!(!session_manager.is_online(12));
+        assert!(!session_manager.authenticate(id, 12));
+    }
+
+    #[tokio::test]
+    async fn session_close_all() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let addr = IpAddr::from([127, 0, 0, 1]);
+        let (id, mut receiver) = session_manager.accept(addr).expect("accept");
+        let (_, _unresponsive) = session_manager.accept(addr).expect("accept");
+
+        let manager = session_manager.clone();
+        let client = tokio::spawn(async move {
+            let reconnect = receiver.recv().await;
+            if let Some(Message::Close(_)) = receiver.recv().await {
+                manager.remove(id);
+            }
+            reconnect
+        });
+
+        let remaining = session_manager.close_all(Duration::from_millis(300)).await;
+        assert_eq!(remaining, 1);
+        let Some(Message::Text(reconnect)) = client.await? else {
+            anyhow::bail!("expected a reconnect frame");
+        };
+        let reconnect: serde_json::Value = serde_json::from_str(&reconnect)?;
+        assert_eq!(reconnect["type"], 20);
+        assert!(reconnect["data"]["afterMs"].as_u64() <= Some(5000));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorize_upgrade_token() -> anyhow::Result<()> {
+        let jwt_keys = JwtKeys::try_from("omOFP+Ejj/r+u4XeHr+KImZNtP0AlNqgvjLe3C5qics=")?;
+        let token = jwt_keys.sign(&Claims::from(7))?;
+        let mut headers = HeaderMap::new();
+
+        let authorized = authorize_upgrade(&jwt_keys, None, Some(&token), &headers).await;
+        assert!(matches!(authorized, Some((Claims { uid: 7, .. }, None))));
+        assert!(
+            authorize_upgrade(&jwt_keys, None, Some("invalid"), &headers)
+                .await
+                .is_none()
+        );
+
+        headers.insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            format!("mallchat, {token}").parse()?,
+        );
+        let authorized = authorize_upgrade(&jwt_keys, None, Some("invalid"), &headers).await;
+        assert!(
+            matches!(authorized, Some((Claims { uid: 7, .. }, Some(protocol))) if protocol == token)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn connection_limits() {
+        let session_manager = SessionManager::new(WsConfig {
+            max_connections: 3,
+            max_connections_per_ip: 2,
+            ..WsConfig::default()
+        });
+        let first = IpAddr::from([127, 0, 0, 1]);
+        let second = IpAddr::from([127, 0, 0, 2]);
+
+        let accepted = session_manager.accept(first).map(|(id, _)| id);
+        assert!(session_manager.accept(first).is_ok());
+        assert_eq!(
+            session_manager.accept(first).err(),
+            Some(ConnectionRejected::TooManyConnectionsFromIp)
+        );
+        assert!(session_manager.accept(second).is_ok());
+        assert_eq!(
+            session_manager.accept(second).err(),
+            Some(ConnectionRejected::TooManyConnections)
+        );
+
+        if let Ok(id) = accepted {
+            session_manager.remove(id);
+        }
+        assert!(session_manager.accept(first).is_ok());
+    }
+
+    #[test]
+    fn session_guard() {
+        let session_manager = SessionManager::new(WsConfig {
+            max_connections: 1,
+            ..WsConfig::default()
+        });
+        let addr = IpAddr::from([127, 0, 0, 1]);
+        let (id, _receiver) = session_manager.accept(addr).expect("accept");
+        let guard = SessionGuard {
+            session_manager: session_manager.clone(),
+            id,
+        };
+        assert!(session_manager.accept(addr).is_err());
+
+        // 未升级就被丢弃的连接释放占用的连接数
+        drop(guard);
+        assert!(session_manager.accept(addr).is_ok());
+    }
+
+    #[tokio::test]
+    async fn slow_client() -> anyhow::Result<()> {
+        let session_manager = SessionManager::new(WsConfig {
+            queue_capacity: 1,
+            overflow_policy: OverflowPolicy::DropNewest,
+            ..WsConfig::default()
+        });
+        let (_id, mut receiver) = session_manager.connect(1);
+        assert_eq!(session_manager.send_to_user(1, &WsPush::TokenExpired)?, 1);
+        assert_eq!(session_manager.send_to_user(1, &WsPush::TokenExpired)?, 0);
+        assert_eq!(session_manager.push_stats().dropped(), 1);
+        assert!(matches!(receiver.recv().await, Some(Message::Text(_))));
+
+        let (id, mut receiver) = session_manager.connect(2);
+        assert!(session_manager.try_send(id, &WsPush::TokenExpired)?);
+        assert!(matches!(receiver.recv().await, Some(Message::Text(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn broadcast_all() -> anyhow::Result<()> {
+        let session_manager = SessionManager::new(WsConfig {
+            max_connections: 0,
+            max_connections_per_ip: 0,
+            ..WsConfig::default()
+        });
+        let (_id, mut user) = session_manager.connect(1);
+        let (_id, mut guest) = session_manager
+            .accept(IpAddr::from([127, 0, 0, 1]))
+            .expect("accept");
+
+        assert_eq!(
+            session_manager.broadcast_all(&WsPush::TokenExpired, true)?,
+            1
+        );
+        assert_eq!(
+            session_manager.broadcast_all(&WsPush::TokenExpired, false)?,
+            2
+        );
+        assert!(matches!(user.recv().await, Some(Message::Text(_))));
+        assert!(matches!(guest.recv().await, Some(Message::Text(_))));
+
+        let receivers: Vec<_> = (0..3000).map(|uid| session_manager.connect(uid)).collect();
+        assert_eq!(
+            session_manager.broadcast_all(&WsPush::TokenExpired, true)?,
+            receivers.len() + 1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn protocol_violations() {
+        let session_manager = SessionManager::new(WsConfig {
+            max_protocol_violations: 2,
+            ..WsConfig::default()
+        });
+        let (id, _receiver) = session_manager.connect(1);
+        assert!(!session_manager.record_violation(id));
+        assert!(!session_manager.record_violation(id));
+        assert!(session_manager.record_violation(id));
+
+        let session_manager = SessionManager::new(WsConfig {
+            max_protocol_violations: 0,
+            ..WsConfig::default()
+        });
+        let (id, _receiver) = session_manager.connect(1);
+        assert!((0..100).all(|_| !session_manager.record_violation(id)));
+    }
+
+    #[test]
+    fn scenes() {
+        let session_manager = SessionManager::default();
+        let (first, _first) = session_manager.connect(1);
+        let (second, _second) = session_manager.connect(2);
+        let scene = |scene| NonZeroUsize::new(scene).expect("nonzero");
+
+        assert!(session_manager.bind_scene(first, scene(100)));
+        assert_eq!(session_manager.scene_session(scene(100)), Some(first));
+        // 重新签发后之前的场景值失效
+        assert!(session_manager.bind_scene(first, scene(101)));
+        assert_eq!(session_manager.scene_session(scene(100)), None);
+        // 重连的连接继续之前的登录
+        assert!(session_manager.bind_scene(second, scene(101)));
+        assert_eq!(session_manager.scene_session(scene(101)), Some(second));
+        session_manager.remove(first);
+        assert_eq!(session_manager.scene_session(scene(101)), Some(second));
+        session_manager.remove(second);
+        assert_eq!(session_manager.scene_session(scene(101)), None);
+        assert!(!session_manager.bind_scene(second, scene(102)));
+    }
+
+    #[test]
+    fn config() -> anyhow::Result<()> {
+        let config: WsConfig = config::Config::builder()
+            .add_source(config::File::from_str(
+                "heartbeat_timeout_secs = 0\nmax_message_bytes = 1024",
+                config::FileFormat::Toml,
+            ))
+            .build()?
+            .try_deserialize()?;
+        assert_eq!(
+            config,
+            WsConfig {
+                heartbeat_timeout_secs: 0,
+                max_message_bytes: 1024,
+                ..WsConfig::default()
+            }
+        );
+        assert_eq!(config.login_qrcode_ttl_secs, 3600);
+        assert_eq!(config.heartbeat_interval_secs, 30);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compression() -> anyhow::Result<()> {
+        let session_manager = SessionManager::new(WsConfig {
+            compression_threshold_bytes: 16,
+            ..WsConfig::default()
+        });
+        let (id, mut receiver) = session_manager.connect(1);
+        assert!(session_manager.enable_compression(id));
+
+        session_manager.send_to_user(1, &WsPush::TokenExpired)?;
+        assert_eq!(
+            receiver.recv().await,
+            Some(Message::Text(r#"{"type":6}"#.into()))
+        );
+
+        let push = WsPush::Apply(FriendApply {
+            uid: 2,
+            unread_count: 3,
+        });
+        session_manager.send_to_user(1, &push)?;
+        let Some(Message::Binary(bytes)) = receiver.recv().await else {
+            anyhow::bail!("expect a binary frame");
+        };
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(bytes.as_slice()).read_to_string(&mut json)?;
+        assert_eq!(json, serde_json::to_string(&push)?);
+
+        // protobuf 编码的连接不压缩
+        assert!(session_manager.set_encoding(id, WsEncoding::Protobuf));
+        session_manager.send_to_user(1, &push)?;
+        let Some(Message::Binary(bytes)) = receiver.recv().await else {
+            anyhow::bail!("expect a binary frame");
+        };
+        assert_eq!(
+            <PushFrame as prost::Message>::decode(bytes.as_slice())?,
+            PushFrame::from(&push)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_and_disconnect() {
+        let session_manager = SessionManager::default();
+        let (first, _receiver) = session_manager.connect(1);
+        let (second, mut receiver) = session_manager
+            .accept(IpAddr::from([10, 0, 0, 1]))
+            .expect("accept");
+        session_manager.set_user_agent(second, Some("test".to_string()));
+
+        assert_eq!(session_manager.list(None, None).len(), 2);
+        let sessions = session_manager.list(Some(1), None);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, first);
+        let sessions = session_manager.list(None, "10.0.0.1".parse().ok());
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].uid, None);
+        assert_eq!(sessions[0].user_agent.as_deref(), Some("test"));
+
+        assert!(session_manager.disconnect(second));
+        assert!(matches!(receiver.recv().await, Some(Message::Close(_))));
+        assert!(!session_manager.disconnect(0));
+    }
+
+    #[tokio::test]
+    async fn disconnect_idle() {
+        let session_manager = SessionManager::default();
+        let (idle, mut receiver) = session_manager.connect(1);
+        let (_active, _receiver) = session_manager.connect(2);
+        session_manager.update(idle, |session| session.last_active_time -= 60_000);
+
+        assert_eq!(session_manager.disconnect_idle(Duration::from_secs(30)), 1);
+        assert!(matches!(receiver.recv().await, Some(Message::Close(_))));
+        assert_eq!(session_manager.disconnect_idle(Duration::from_secs(120)), 0);
+    }
+
+    #[test]
+    fn active_status() {
+        let session_manager = SessionManager::default();
+        let (idle, _receiver) = session_manager.connect(1);
+        let (_active, _receiver) = session_manager.connect(1);
+        let (away, _receiver) = session_manager.connect(2);
+        for id in [idle, away] {
+            session_manager.update(id, |session| session.last_opt_time -= 600_000);
+        }
+
+        // 任意一个连接最近有操作即为在线
+        assert_eq!(session_manager.active_status(1), ActiveStatus::Online);
+        assert_eq!(session_manager.active_status(2), ActiveStatus::Away);
+        assert_eq!(session_manager.active_status(3), ActiveStatus::Offline);
+        let presence = session_manager.presence();
+        assert_eq!(presence.len(), 2);
+        assert_eq!(presence.get(&2), Some(&ActiveStatus::Away));
+
+        session_manager.touch_user(2);
+        assert_eq!(session_manager.active_status(2), ActiveStatus::Online);
+    }
+
+    #[test]
+    fn throttle_typing() {
+        let session_manager = SessionManager::default();
+        assert!(session_manager.throttle_typing(1));
+        assert!(!session_manager.throttle_typing(1));
+        assert!(session_manager.throttle_typing(2));
+
+        session_manager
+            .typing
+            .alter(&1, |_, last| last - super::TYPING_INTERVAL_MILLIS);
+        assert!(session_manager.throttle_typing(1));
+    }
+
+    #[tokio::test]
+    async fn kick_user() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (_id, mut kicked) = session_manager.connect(1);
+        let (_id, _other) = session_manager.connect(2);
+
+        assert_eq!(session_manager.kick_user(1, "banned")?, 1);
+        assert!(!session_manager.is_online(1));
+        assert!(session_manager.is_online(2));
+        assert_eq!(
+            kicked.recv().await,
+            Some(Message::Text(r#"{"type":6}"#.into()))
+        );
+        assert!(matches!(kicked.recv().await, Some(Message::Close(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn kick_device() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (phone, mut kicked) = session_manager.connect(1);
+        let (web, _web) = session_manager.connect(1);
+        session_manager.set_device(phone, "phone".to_string());
+        session_manager.set_device(web, "web".to_string());
+        assert_eq!(
+            session_manager
+                .login_device(web)
+                .map(|device| device.device_id),
+            Some("web".to_string())
+        );
+
+        assert_eq!(session_manager.kick_device(2, "phone", "logout")?, 0);
+        assert_eq!(session_manager.kick_device(1, "phone", "logout")?, 1);
+        assert_eq!(session_manager.session_uid(phone), None);
+        assert_eq!(session_manager.session_uid(web), Some(1));
+        assert_eq!(
+            kicked.recv().await,
+            Some(Message::Text(r#"{"type":6}"#.into()))
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,277 @@
+//! # 连接准入与补发调度
+//!
+//! 实例启动或其他实例下线时，大量客户端几乎同时重连，每个连接登录后还会请求断线补发，
+//! 瞬间的数据库查询可能压垮刚启动的实例。[`Admission`] 用令牌桶限制每秒接受的新连接数，
+//! 启动后的 `ramp_up_secs` 秒内上限从 `ramp_up_initial_percent` 线性增加到 `accept_rate`，
+//! 超出时返回 503，客户端退避后重试。
+//!
+//! 补发通过 [`Admission::spawn_resync`] 执行：预热期间先随机等待一段时间，越接近启动时刻等待越久，
+//! 同时执行的补发数不超过 `resync_concurrency`。
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+/// 连接准入配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionConfig {
+    /// 每秒接受的新连接数，0 表示不限制
+    #[serde(default)]
+    pub accept_rate: u32,
+    /// 允许突发的连接数
+    #[serde(default = "default::accept_burst")]
+    pub accept_burst: u32,
+    /// 启动后的预热时间（秒），期间接受速率和突发数线性增加
+    #[serde(default = "default::ramp_up_secs")]
+    pub ramp_up_secs: u64,
+    /// 预热开始时的速率占 `accept_rate` 的百分比
+    #[serde(default = "default::ramp_up_initial_percent")]
+    pub ramp_up_initial_percent: u8,
+    /// 同时执行的断线补发数，0 表示不限制
+    #[serde(default = "default::resync_concurrency")]
+    pub resync_concurrency: usize,
+    /// 预热期间断线补发的最大随机延迟（毫秒）
+    #[serde(default = "default::resync_spread_millis")]
+    pub resync_spread_millis: u64,
+}
+
+mod default {
+    pub fn accept_burst() -> u32 {
+        100
+    }
+
+    pub fn ramp_up_secs() -> u64 {
+        60
+    }
+
+    pub fn ramp_up_initial_percent() -> u8 {
+        10
+    }
+
+    pub fn resync_concurrency() -> usize {
+        32
+    }
+
+    pub fn resync_spread_millis() -> u64 {
+        10_000
+    }
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            accept_rate: 0,
+            accept_burst: default::accept_burst(),
+            ramp_up_secs: default::ramp_up_secs(),
+            ramp_up_initial_percent: default::ramp_up_initial_percent(),
+            resync_concurrency: default::resync_concurrency(),
+            resync_spread_millis: default::resync_spread_millis(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// 连接准入
+#[derive(Debug)]
+pub struct Admission {
+    config: AdmissionConfig,
+    started: Instant,
+    bucket: Mutex<Bucket>,
+    resync: Option<Arc<Semaphore>>,
+    rejected: AtomicU64,
+}
+
+impl Default for Admission {
+    fn default() -> Self {
+        Self::new(AdmissionConfig::default())
+    }
+}
+
+impl Admission {
+    /// 创建，预热从此时开始
+    pub fn new(config: AdmissionConfig) -> Self {
+        Self::started_at(config, Instant::now())
+    }
+
+    fn started_at(config: AdmissionConfig, started: Instant) -> Self {
+        let resync = (config.resync_concurrency > 0)
+            .then(|| Arc::new(Semaphore::new(config.resync_concurrency)));
+        let admission = Self {
+            config,
+            started,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last: started,
+            }),
+            resync,
+            rejected: AtomicU64::new(0),
+        };
+        admission.bucket.lock().tokens = admission.capacity(admission.ratio(started));
+        admission
+    }
+
+    /// 预热进度，0 到 1
+    fn progress(&self, now: Instant) -> f64 {
+        match self.config.ramp_up_secs {
+            0 => 1.0,
+            secs => {
+                let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+                (elapsed / secs as f64).min(1.0)
+            }
+        }
+    }
+
+    /// 当前速率占 `accept_rate` 的比例
+    fn ratio(&self, now: Instant) -> f64 {
+        let initial = f64::from(self.config.ramp_up_initial_percent.min(100)) / 100.0;
+        initial + (1.0 - initial) * self.progress(now)
+    }
+
+    fn capacity(&self, ratio: f64) -> f64 {
+        (f64::from(self.config.accept_burst) * ratio).max(1.0)
+    }
+
+    /// 尝试接受一个新连接
+    pub fn try_admit(&self) -> bool {
+        self.try_admit_at(Instant::now())
+    }
+
+    fn try_admit_at(&self, now: Instant) -> bool {
+        if self.config.accept_rate == 0 {
+            return true;
+        }
+        let ratio = self.ratio(now);
+        let rate = f64::from(self.config.accept_rate) * ratio;
+        let capacity = self.capacity(ratio);
+        let mut bucket = self.bucket.lock();
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last = bucket.last.max(now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            drop(bucket);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// 启动以来拒绝的连接数
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// 预热期间补发前的随机延迟，预热结束后不延迟
+    fn resync_delay(&self, now: Instant, rng: &mut impl Rng) -> Duration {
+        let remaining = 1.0 - self.progress(now);
+        let max = (self.config.resync_spread_millis as f64 * remaining) as u64;
+        match max {
+            0 => Duration::ZERO,
+            max => Duration::from_millis(rng.gen_range(0..=max)),
+        }
+    }
+
+    /// 在后台执行补发，预热期间随机延迟，并限制同时执行的数量
+    pub fn spawn_resync<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let delay = self.resync_delay(Instant::now(), &mut rand::thread_rng());
+        let semaphore = self.resync.clone();
+        tokio::spawn(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let _permit = match semaphore {
+                Some(semaphore) => semaphore.acquire_owned().await.ok(),
+                None => None,
+            };
+            task.await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::handler::ws::admission::{Admission, AdmissionConfig};
+
+    fn admitted(admission: &Admission, now: Instant) -> usize {
+        (0..1000)
+            .take_while(|_| admission.try_admit_at(now))
+            .count()
+    }
+
+    #[test]
+    fn ramp_up() {
+        let start = Instant::now();
+        let config = AdmissionConfig {
+            accept_rate: 100,
+            accept_burst: 50,
+            ramp_up_secs: 10,
+            ramp_up_initial_percent: 10,
+            ..Default::default()
+        };
+        let admission = Admission::started_at(config, start);
+        // 刚启动时只允许 10% 的突发
+        assert_eq!(admitted(&admission, start), 5);
+        // 1 秒后速率约为 19/s，突发上限约为 9
+        assert_eq!(admitted(&admission, start + Duration::from_secs(1)), 9);
+        assert_eq!(admission.rejected(), 2);
+
+        // 预热结束后按完整的速率和突发数接受
+        let end = start + Duration::from_secs(10);
+        assert_eq!(admitted(&admission, end), 50);
+        assert_eq!(admitted(&admission, end + Duration::from_millis(100)), 10);
+    }
+
+    #[test]
+    fn unlimited() {
+        let admission = Admission::default();
+        assert_eq!(admitted(&admission, Instant::now()), 1000);
+        assert_eq!(admission.rejected(), 0);
+    }
+
+    #[test]
+    fn resync_delay() {
+        let start = Instant::now();
+        let config = AdmissionConfig {
+            ramp_up_secs: 10,
+            resync_spread_millis: 1000,
+            ..Default::default()
+        };
+        let admission = Admission::started_at(config, start);
+        let mut rng = StdRng::seed_from_u64(0);
+        let delays: Vec<_> = (0..100)
+            .map(|_| admission.resync_delay(start, &mut rng))
+            .collect();
+        assert!(delays.iter().all(|delay| *delay <= Duration::from_secs(1)));
+        assert!(delays
+            .iter()
+            .any(|delay| *delay > Duration::from_millis(500)));
+
+        // 越接近预热结束，延迟越短
+        let late = start + Duration::from_secs(9);
+        assert!(
+            (0..100).all(|_| admission.resync_delay(late, &mut rng) <= Duration::from_millis(100))
+        );
+        let end = start + Duration::from_secs(10);
+        assert_eq!(admission.resync_delay(end, &mut rng), Duration::ZERO);
+    }
+}
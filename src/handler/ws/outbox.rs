@@ -0,0 +1,265 @@
+//! # 连接的发送队列
+//!
+//! 每个 WebSocket 连接一个有界队列，推送不等待：队列满时按 [`OverflowPolicy`] 处理，
+//! 避免一个慢客户端拖慢向所有连接的推送。
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::extract::ws::{close_code, CloseFrame, Message};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// 队列满时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// 丢弃最早的消息
+    DropOldest,
+    /// 丢弃新消息
+    DropNewest,
+    /// 断开连接，客户端重连后重新拉取
+    #[default]
+    Disconnect,
+}
+
+/// 推送结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    /// 已加入队列
+    Queued,
+    /// 已加入队列，丢弃了最早的一条消息
+    DroppedOldest,
+    /// 队列已满，丢弃了这条消息
+    DroppedNewest,
+    /// 队列已满，断开连接
+    Disconnected,
+    /// 连接已关闭
+    Closed,
+}
+
+impl PushOutcome {
+    /// 这条消息是否会发送给客户端
+    pub fn is_queued(self) -> bool {
+        matches!(self, PushOutcome::Queued | PushOutcome::DroppedOldest)
+    }
+}
+
+/// 推送统计
+#[derive(Debug, Default)]
+pub struct PushStats {
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl PushStats {
+    /// 记录一次推送结果
+    pub fn record(&self, outcome: PushOutcome) {
+        match outcome {
+            PushOutcome::DroppedOldest | PushOutcome::DroppedNewest => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            PushOutcome::Disconnected => {
+                self.disconnected.fetch_add(1, Ordering::Relaxed);
+            }
+            PushOutcome::Queued | PushOutcome::Closed => {}
+        }
+    }
+
+    /// 因队列满丢弃的消息数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 因队列满断开的连接数
+    pub fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<VecDeque<Message>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    notify: Notify,
+    closed: AtomicBool,
+    senders: AtomicUsize,
+}
+
+impl Shared {
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+}
+
+/// 创建发送队列
+pub fn outbox(capacity: usize, policy: OverflowPolicy) -> (Outbox, OutboxReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity: capacity.max(1),
+        policy,
+        notify: Notify::new(),
+        closed: AtomicBool::new(false),
+        senders: AtomicUsize::new(1),
+    });
+    (
+        Outbox {
+            shared: shared.clone(),
+        },
+        OutboxReceiver { shared },
+    )
+}
+
+/// 发送端
+#[derive(Debug)]
+pub struct Outbox {
+    shared: Arc<Shared>,
+}
+
+impl Clone for Outbox {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl Outbox {
+    /// 推送一条消息，不等待
+    pub fn push(&self, message: Message) -> PushOutcome {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return PushOutcome::Closed;
+        }
+        let mut queue = self.shared.queue.lock();
+        let outcome = if queue.len() < self.shared.capacity {
+            queue.push_back(message);
+            PushOutcome::Queued
+        } else {
+            match self.shared.policy {
+                OverflowPolicy::DropOldest => {
+                    queue.pop_front();
+                    queue.push_back(message);
+                    PushOutcome::DroppedOldest
+                }
+                OverflowPolicy::DropNewest => PushOutcome::DroppedNewest,
+                OverflowPolicy::Disconnect => {
+                    queue.clear();
+                    queue.push_back(Message::Close(Some(CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: "Client is too slow".into(),
+                    })));
+                    self.shared.closed.store(true, Ordering::SeqCst);
+                    PushOutcome::Disconnected
+                }
+            }
+        };
+        drop(queue);
+        self.shared.notify.notify_one();
+        outcome
+    }
+
+    /// 推送控制消息（如 Close 帧），不受队列容量限制
+    pub fn push_control(&self, message: Message) -> PushOutcome {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return PushOutcome::Closed;
+        }
+        self.shared.queue.lock().push_back(message);
+        self.shared.notify.notify_one();
+        PushOutcome::Queued
+    }
+
+    /// 队列中等待发送的消息数
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().len()
+    }
+
+    /// 队列是否为空
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.lock().is_empty()
+    }
+}
+
+/// 接收端，由连接的处理任务持有
+#[derive(Debug)]
+pub struct OutboxReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutboxReceiver {
+    /// 接收下一条消息，队列为空且连接关闭或发送端全部释放时返回 `None`
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let notified = self.shared.notify.notified();
+            if let Some(message) = self.shared.queue.lock().pop_front() {
+                return Some(message);
+            }
+            if self.shared.closed.load(Ordering::SeqCst)
+                || self.shared.senders.load(Ordering::SeqCst) == 0
+            {
+                return None;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for OutboxReceiver {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::handler::ws::outbox::{outbox, OverflowPolicy, PushOutcome};
+
+    fn text(text: &str) -> Message {
+        Message::Text(text.to_string())
+    }
+
+    #[tokio::test]
+    async fn overflow_policy() {
+        let (sender, mut receiver) = outbox(2, OverflowPolicy::DropOldest);
+        sender.push(text("1"));
+        sender.push(text("2"));
+        assert_eq!(sender.push(text("3")), PushOutcome::DroppedOldest);
+        assert_eq!(receiver.recv().await, Some(text("2")));
+        assert_eq!(receiver.recv().await, Some(text("3")));
+
+        let (sender, mut receiver) = outbox(1, OverflowPolicy::DropNewest);
+        sender.push(text("1"));
+        assert_eq!(sender.push(text("2")), PushOutcome::DroppedNewest);
+        assert_eq!(receiver.recv().await, Some(text("1")));
+
+        let (sender, mut receiver) = outbox(1, OverflowPolicy::Disconnect);
+        sender.push(text("1"));
+        assert_eq!(sender.push(text("2")), PushOutcome::Disconnected);
+        assert!(matches!(receiver.recv().await, Some(Message::Close(_))));
+        assert_eq!(receiver.recv().await, None);
+        assert_eq!(sender.push(text("3")), PushOutcome::Closed);
+    }
+
+    #[tokio::test]
+    async fn sender_dropped() {
+        let (sender, mut receiver) = outbox(1, OverflowPolicy::Disconnect);
+        let task = tokio::spawn(async move { receiver.recv().await });
+        tokio::task::yield_now().await;
+        drop(sender);
+        assert!(matches!(task.await, Ok(None)));
+    }
+}
@@ -0,0 +1,765 @@
+//! # WebSocket protobuf 编码
+//!
+//! 客户端在 `Sec-WebSocket-Protocol` 中携带 [`PROTOBUF_PROTOCOL`] 时，请求与推送都使用二进制帧，
+//! 消息定义见 `doc/ws.proto`，这里的类型与其一一对应（等同于 prost-build 的生成结果），
+//! 测试中逐个字段对照 `doc/ws.proto` 检查。
+
+use std::collections::BTreeMap;
+
+use crate::handler::chat::{MemberResp, MessageResp};
+use crate::handler::ws::push::{self, WsPush};
+use crate::handler::ws::{Req, ReqType};
+use crate::timestamp;
+
+/// protobuf 编码的子协议名
+pub const PROTOBUF_PROTOCOL: &str = "mallchat.protobuf";
+
+/// 连接使用的编码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WsEncoding {
+    /// JSON 文本帧
+    #[default]
+    Json,
+    /// protobuf 二进制帧
+    Protobuf,
+}
+
+/// 客户端请求
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReqFrame {
+    /// 请求类型
+    #[prost(uint32, tag = "1")]
+    pub r#type: u32,
+    /// 请求数据
+    #[prost(string, optional, tag = "2")]
+    pub data: Option<String>,
+    /// 请求序号，直接回复中原样返回
+    #[prost(uint64, optional, tag = "3")]
+    pub seq: Option<u64>,
+}
+
+impl TryFrom<ReqFrame> for Req {
+    type Error = anyhow::Error;
+
+    fn try_from(frame: ReqFrame) -> Result<Self, Self::Error> {
+        let r#type = match frame.r#type {
+            1 => ReqType::Login,
+            2 => ReqType::Heartbeat,
+            3 => ReqType::Authorize,
+            4 => ReqType::Ack,
+            5 => ReqType::Resume,
+            6 => ReqType::RefreshLogin,
+            7 => ReqType::Typing,
+            other => anyhow::bail!("unknown request type: {other}"),
+        };
+        Ok(Req {
+            r#type,
+            data: frame.data,
+            seq: frame.seq,
+        })
+    }
+}
+
+/// 服务端推送
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PushFrame {
+    /// 推送类型
+    #[prost(uint32, tag = "1")]
+    pub r#type: u32,
+    /// 推送数据
+    #[prost(
+        oneof = "PushData",
+        tags = "2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 14, 15, 16, 17"
+    )]
+    pub data: Option<PushData>,
+    /// 推送序号，新消息携带，见 [`WsPush::seq`]；直接回复请求时为请求序号
+    #[prost(uint64, optional, tag = "13")]
+    pub seq: Option<u64>,
+}
+
+/// 推送数据
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum PushData {
+    /// 登录二维码
+    #[prost(message, tag = "2")]
+    LoginUrl(LoginUrl),
+    /// 登录成功
+    #[prost(message, tag = "3")]
+    LoginSuccess(LoginSuccess),
+    /// 新消息
+    #[prost(message, tag = "4")]
+    NewMessage(Message),
+    /// 上下线通知
+    #[prost(message, tag = "5")]
+    OnlineOfflineNotify(OnlineOfflineNotify),
+    /// 消息标记
+    #[prost(message, tag = "6")]
+    MsgMark(MsgMark),
+    /// 消息撤回
+    #[prost(message, tag = "7")]
+    MsgRecall(MsgRecall),
+    /// 好友申请
+    #[prost(message, tag = "8")]
+    Apply(FriendApply),
+    /// 用户资料变更
+    #[prost(message, tag = "9")]
+    UserInfoChange(UserInfoChange),
+    /// 系统通知
+    #[prost(message, tag = "10")]
+    SystemNotice(SystemNotice),
+    /// 消息更新
+    #[prost(message, tag = "11")]
+    MsgUpdate(Message),
+    /// 系统公告
+    #[prost(message, tag = "12")]
+    Announcement(Announcement),
+    /// 群聊变更
+    #[prost(message, tag = "14")]
+    GroupChange(GroupChange),
+    /// 正在输入
+    #[prost(message, tag = "15")]
+    Typing(Typing),
+    /// 请求错误
+    #[prost(message, tag = "16")]
+    Error(WsError),
+    /// 服务端即将停机
+    #[prost(message, tag = "17")]
+    Reconnect(Reconnect),
+}
+
+/// 登录二维码
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LoginUrl {
+    /// 二维码链接
+    #[prost(string, tag = "1")]
+    pub login_url: String,
+    /// 登录码，重连时携带可以继续登录
+    #[prost(string, optional, tag = "2")]
+    pub login_code: Option<String>,
+}
+
+/// 登录成功
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LoginSuccess {
+    /// 用户 ID
+    #[prost(int64, tag = "1")]
+    pub uid: i64,
+    /// 头像
+    #[prost(string, optional, tag = "2")]
+    pub avatar: Option<String>,
+    /// token
+    #[prost(string, tag = "3")]
+    pub token: String,
+    /// 昵称
+    #[prost(string, optional, tag = "4")]
+    pub name: Option<String>,
+    /// 权限
+    #[prost(int32, tag = "5")]
+    pub power: i32,
+}
+
+/// 消息
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Message {
+    /// 消息 ID
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// 房间 ID
+    #[prost(int64, tag = "2")]
+    pub room_id: i64,
+    /// 发送者 uid
+    #[prost(int64, tag = "3")]
+    pub from_uid: i64,
+    /// 消息内容
+    #[prost(string, tag = "4")]
+    pub content: String,
+    /// 回复的消息 ID
+    #[prost(int64, optional, tag = "5")]
+    pub reply_msg_id: Option<i64>,
+    /// 发送时间，与 JSON 协议的格式相同
+    #[prost(string, tag = "6")]
+    pub send_time: String,
+    /// 发送者的 IP 归属地
+    #[prost(string, optional, tag = "7")]
+    pub from_region: Option<String>,
+    /// 链接预览，键为链接
+    #[prost(btree_map = "string, message", tag = "8")]
+    pub url_content_map: BTreeMap<String, UrlInfo>,
+    /// 发送时携带的客户端消息 ID
+    #[prost(string, optional, tag = "9")]
+    pub client_msg_id: Option<String>,
+}
+
+/// 链接预览
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UrlInfo {
+    /// 标题
+    #[prost(string, optional, tag = "1")]
+    pub title: Option<String>,
+    /// 描述
+    #[prost(string, optional, tag = "2")]
+    pub description: Option<String>,
+    /// 网站图标
+    #[prost(string, optional, tag = "3")]
+    pub image: Option<String>,
+}
+
+/// 群成员
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Member {
+    /// 用户 ID
+    #[prost(uint64, tag = "1")]
+    pub uid: u64,
+    /// 昵称
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    /// 头像
+    #[prost(string, optional, tag = "3")]
+    pub avatar: Option<String>,
+    /// 是否在线
+    #[prost(bool, tag = "4")]
+    pub online: bool,
+    /// 在线状态 1在线 2离开 3离线
+    #[prost(uint32, tag = "5")]
+    pub active_status: u32,
+    /// 最后活跃时间
+    #[prost(string, tag = "6")]
+    pub last_opt_time: String,
+}
+
+/// 上下线通知
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OnlineOfflineNotify {
+    /// 上下线的用户
+    #[prost(message, repeated, tag = "1")]
+    pub change_list: Vec<Member>,
+    /// 在线人数
+    #[prost(uint64, tag = "2")]
+    pub online_num: u64,
+}
+
+/// 单条消息的标记变化
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgMarkItem {
+    /// 操作者 uid
+    #[prost(int64, tag = "1")]
+    pub uid: i64,
+    /// 消息 ID
+    #[prost(int64, tag = "2")]
+    pub msg_id: i64,
+    /// 标记类型
+    #[prost(int32, tag = "3")]
+    pub mark_type: i32,
+    /// 标记数量
+    #[prost(int32, tag = "4")]
+    pub mark_count: i32,
+    /// 动作类型
+    #[prost(int32, tag = "5")]
+    pub act_type: i32,
+}
+
+/// 消息标记
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgMark {
+    /// 标记变化
+    #[prost(message, repeated, tag = "1")]
+    pub mark_list: Vec<MsgMarkItem>,
+}
+
+/// 消息撤回
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgRecall {
+    /// 消息 ID
+    #[prost(int64, tag = "1")]
+    pub msg_id: i64,
+    /// 房间 ID
+    #[prost(int64, tag = "2")]
+    pub room_id: i64,
+    /// 撤回者 uid
+    #[prost(int64, tag = "3")]
+    pub recall_uid: i64,
+}
+
+/// 好友申请通知
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FriendApply {
+    /// 申请人 uid
+    #[prost(int64, tag = "1")]
+    pub uid: i64,
+    /// 未读申请数
+    #[prost(uint64, tag = "2")]
+    pub unread_count: u64,
+}
+
+/// 用户资料变更通知
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UserInfoChange {
+    /// 用户 ID
+    #[prost(int64, tag = "1")]
+    pub uid: i64,
+    /// 昵称
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    /// 头像
+    #[prost(string, optional, tag = "3")]
+    pub avatar: Option<String>,
+}
+
+/// 系统通知
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SystemNotice {
+    /// 通知内容
+    #[prost(string, tag = "1")]
+    pub content: String,
+}
+
+/// 系统公告
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Announcement {
+    /// 公告 ID
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// 公告内容
+    #[prost(string, tag = "2")]
+    pub content: String,
+    /// 发布时间
+    #[prost(string, tag = "3")]
+    pub create_time: String,
+}
+
+/// 群聊变更
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GroupChange {
+    /// 房间 ID
+    #[prost(int64, tag = "1")]
+    pub room_id: i64,
+    /// 变更类型
+    #[prost(uint32, tag = "2")]
+    pub change_type: u32,
+    /// 操作者 uid
+    #[prost(int64, tag = "3")]
+    pub operator_uid: i64,
+    /// 被禁言的成员、新群主或退出的成员 uid
+    #[prost(int64, optional, tag = "4")]
+    pub uid: Option<i64>,
+    /// 禁言截止时间
+    #[prost(string, optional, tag = "5")]
+    pub mute_until: Option<String>,
+    /// 新的群公告
+    #[prost(string, optional, tag = "6")]
+    pub announcement: Option<String>,
+}
+
+/// 正在输入
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Typing {
+    /// 房间 ID
+    #[prost(int64, tag = "1")]
+    pub room_id: i64,
+    /// 正在输入的用户 uid
+    #[prost(int64, tag = "2")]
+    pub uid: i64,
+}
+
+/// 请求错误
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WsError {
+    /// 错误码
+    #[prost(int32, tag = "1")]
+    pub err_code: i32,
+    /// 错误信息
+    #[prost(string, tag = "2")]
+    pub err_msg: String,
+}
+
+/// 服务端即将停机
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Reconnect {
+    /// 收到 Close 帧后等待的毫秒数，之后重连
+    #[prost(uint64, tag = "1")]
+    pub after_ms: u64,
+}
+
+/// 与 JSON 协议使用相同的时间格式
+fn format_time(time: time::PrimitiveDateTime) -> String {
+    timestamp::format(time)
+}
+
+impl From<&push::Announcement> for Announcement {
+    fn from(announcement: &push::Announcement) -> Self {
+        Self {
+            id: announcement.id,
+            content: announcement.content.clone(),
+            create_time: format_time(announcement.create_time),
+        }
+    }
+}
+
+impl From<&MessageResp> for Message {
+    fn from(message: &MessageResp) -> Self {
+        let send_time = format_time(message.send_time);
+        Self {
+            id: message.id,
+            room_id: message.room_id,
+            from_uid: message.from_uid,
+            content: message.content.clone(),
+            reply_msg_id: message.reply_msg_id,
+            send_time,
+            from_region: message.from_region.clone(),
+            url_content_map: message
+                .url_content_map
+                .iter()
+                .flatten()
+                .map(|(url, info)| {
+                    let info = UrlInfo {
+                        title: info.title.clone(),
+                        description: info.description.clone(),
+                        image: info.image.clone(),
+                    };
+                    (url.clone(), info)
+                })
+                .collect(),
+            client_msg_id: message.client_msg_id.clone(),
+        }
+    }
+}
+
+impl From<&MemberResp> for Member {
+    fn from(member: &MemberResp) -> Self {
+        Self {
+            uid: member.uid,
+            name: member.name.clone(),
+            avatar: member.avatar.clone(),
+            online: member.online,
+            active_status: member.active_status as u32,
+            last_opt_time: format_time(member.last_opt_time),
+        }
+    }
+}
+
+impl From<&WsPush> for PushFrame {
+    fn from(push: &WsPush) -> Self {
+        let data = match push {
+            WsPush::LoginUrl(push::LoginUrl {
+                login_url,
+                login_code,
+            }) => Some(PushData::LoginUrl(LoginUrl {
+                login_url: login_url.clone(),
+                login_code: login_code.clone(),
+            })),
+            WsPush::LoginSuccess(data) => Some(PushData::LoginSuccess(LoginSuccess {
+                uid: data.uid,
+                avatar: data.avatar.clone(),
+                token: data.token.clone(),
+                name: data.name.clone(),
+                power: data.power,
+            })),
+            WsPush::NewMessage(message) => Some(PushData::NewMessage(message.into())),
+            WsPush::MsgUpdate(message) => Some(PushData::MsgUpdate(message.into())),
+            WsPush::OnlineOfflineNotify(data) => {
+                Some(PushData::OnlineOfflineNotify(OnlineOfflineNotify {
+                    change_list: data.change_list.iter().map(Member::from).collect(),
+                    online_num: data.online_num,
+                }))
+            }
+            WsPush::MsgMark(data) => Some(PushData::MsgMark(MsgMark {
+                mark_list: data
+                    .mark_list
+                    .iter()
+                    .map(|item| MsgMarkItem {
+                        uid: item.uid,
+                        msg_id: item.msg_id,
+                        mark_type: item.mark_type,
+                        mark_count: item.mark_count,
+                        act_type: item.act_type,
+                    })
+                    .collect(),
+            })),
+            WsPush::MsgRecall(data) => Some(PushData::MsgRecall(MsgRecall {
+                msg_id: data.msg_id,
+                room_id: data.room_id,
+                recall_uid: data.recall_uid,
+            })),
+            WsPush::Apply(data) => Some(PushData::Apply(FriendApply {
+                uid: data.uid,
+                unread_count: data.unread_count,
+            })),
+            WsPush::UserInfoChange(data) => Some(PushData::UserInfoChange(UserInfoChange {
+                uid: data.uid,
+                name: data.name.clone(),
+                avatar: data.avatar.clone(),
+            })),
+            WsPush::SystemNotice(data) => Some(PushData::SystemNotice(SystemNotice {
+                content: data.content.clone(),
+            })),
+            WsPush::Announcement(data) => Some(PushData::Announcement(data.into())),
+            WsPush::GroupChange(data) => Some(PushData::GroupChange(GroupChange {
+                room_id: data.room_id,
+                change_type: data.change_type as u32,
+                operator_uid: data.operator_uid,
+                uid: data.uid,
+                mute_until: data.mute_until.map(format_time),
+                announcement: data.announcement.clone(),
+            })),
+            WsPush::Typing(data) => Some(PushData::Typing(Typing {
+                room_id: data.room_id,
+                uid: data.uid,
+            })),
+            WsPush::Error(data) => Some(PushData::Error(WsError {
+                err_code: data.err_code,
+                err_msg: data.err_msg.clone(),
+            })),
+            WsPush::Reconnect(data) => Some(PushData::Reconnect(Reconnect {
+                after_ms: data.after_ms,
+            })),
+            WsPush::LoginScanSuccess | WsPush::TokenExpired | WsPush::LoginUrlExpired => None,
+        };
+        Self {
+            r#type: push.push_type() as u32,
+            data,
+            seq: push.seq(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use prost::Message;
+
+    use crate::handler::api::ErrorCode;
+    use crate::handler::ws::proto::{PushData, PushFrame, ReqFrame};
+    use crate::handler::ws::push::{self, FriendApply, WsPush};
+    use crate::handler::ws::{Req, ReqType};
+
+    #[test]
+    fn encode_decode() -> anyhow::Result<()> {
+        let push = WsPush::Apply(FriendApply {
+            uid: 1,
+            unread_count: 2,
+        });
+        let bytes = PushFrame::from(&push).encode_to_vec();
+        let frame = PushFrame::decode(bytes.as_slice())?;
+        assert_eq!(frame.r#type, 10);
+        assert!(matches!(
+            frame.data,
+            Some(PushData::Apply(apply)) if apply.uid == 1 && apply.unread_count == 2
+        ));
+
+        let push = WsPush::Announcement(push::Announcement {
+            id: 3,
+            content: "系统维护".to_string(),
+            create_time: time::macros::datetime!(2023-06-01 00:00),
+        });
+        let frame = PushFrame::decode(PushFrame::from(&push).encode_to_vec().as_slice())?;
+        assert_eq!(frame.r#type, 15);
+        assert!(matches!(
+            frame.data,
+            Some(PushData::Announcement(announcement))
+                if announcement.id == 3 && announcement.content == "系统维护"
+        ));
+
+        let frame = PushFrame::from(&WsPush::TokenExpired);
+        assert_eq!(frame.r#type, 6);
+        assert!(frame.data.is_none());
+        let frame = PushFrame::from(&WsPush::LoginUrlExpired);
+        assert_eq!(frame.r#type, 16);
+        assert!(frame.data.is_none());
+        let push = WsPush::Error(push::WsError::new(ErrorCode::InvalidParam, "请求格式错误"));
+        let frame = PushFrame::decode(PushFrame::from(&push).encode_to_vec().as_slice())?;
+        assert_eq!(frame.r#type, 19);
+        assert!(matches!(
+            frame.data,
+            Some(PushData::Error(error)) if error.err_code == 9001
+        ));
+        let push = WsPush::Reconnect(push::Reconnect { after_ms: 1500 });
+        let frame = PushFrame::decode(PushFrame::from(&push).encode_to_vec().as_slice())?;
+        assert_eq!(frame.r#type, 20);
+        assert!(matches!(
+            frame.data,
+            Some(PushData::Reconnect(reconnect)) if reconnect.after_ms == 1500
+        ));
+
+        let bytes = ReqFrame {
+            r#type: 3,
+            data: Some("token".to_string()),
+            seq: Some(7),
+        }
+        .encode_to_vec();
+        let req = Req::try_from(ReqFrame::decode(bytes.as_slice())?)?;
+        assert!(matches!(req.r#type, ReqType::Authorize));
+        assert_eq!(req.data.as_deref(), Some("token"));
+        assert_eq!(req.seq, Some(7));
+        let req = Req::try_from(ReqFrame {
+            r#type: 6,
+            data: None,
+            seq: None,
+        })?;
+        assert!(matches!(req.r#type, ReqType::RefreshLogin));
+        assert!(Req::try_from(ReqFrame {
+            r#type: 9,
+            data: None,
+            seq: None,
+        })
+        .is_err());
+        Ok(())
+    }
+
+    /// 字段描述，`消息.字段` -> `标签 类型 = 编号`
+    type Fields = BTreeMap<String, String>;
+
+    const SCALARS: [&str; 15] = [
+        "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+        "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+    ];
+
+    fn quoted<'a>(attr: &'a str, key: &str) -> Option<&'a str> {
+        let (_, value) = attr.split_once(&format!("{key} = \""))?;
+        value.split_once('"').map(|(value, _)| value)
+    }
+
+    fn proto_kind(ty: &str) -> String {
+        if let Some((key, value)) = ty
+            .strip_prefix("map<")
+            .and_then(|ty| ty.strip_suffix('>'))
+            .and_then(|ty| ty.split_once(", "))
+        {
+            format!("map<{}, {}>", proto_kind(key), proto_kind(value))
+        } else if SCALARS.contains(&ty) {
+            ty.to_string()
+        } else {
+            format!("message {ty}")
+        }
+    }
+
+    /// 解析 `.proto` 文件中的字段
+    fn proto_fields(proto: &str) -> Fields {
+        let mut fields = Fields::new();
+        let mut message = "";
+        let mut oneof: Option<(&str, Vec<&str>)> = None;
+        for line in proto.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("message ") {
+                message = name.trim_end_matches(" {");
+            } else if let Some(name) = line.strip_prefix("oneof ") {
+                oneof = Some((name.trim_end_matches(" {"), Vec::new()));
+            } else if line == "}" {
+                if let Some((name, tags)) = oneof.take() {
+                    fields.insert(
+                        format!("{message}.{name}"),
+                        format!("oneof {}", tags.join(", ")),
+                    );
+                }
+            } else if let Some((decl, tag)) = line
+                .strip_suffix(';')
+                .and_then(|field| field.split_once(" = "))
+                .filter(|_| !message.is_empty())
+            {
+                let Some((ty, name)) = decl.rsplit_once(' ') else {
+                    continue;
+                };
+                let (label, ty) = match ty.split_once(' ') {
+                    Some((label @ ("optional" | "repeated"), ty)) => (format!("{label} "), ty),
+                    _ => (String::new(), ty),
+                };
+                let label = match &mut oneof {
+                    Some((_, tags)) => {
+                        tags.push(tag);
+                        "oneof ".to_string()
+                    }
+                    None => label,
+                };
+                fields.insert(
+                    format!("{message}.{name}"),
+                    format!("{label}{} = {tag}", proto_kind(ty)),
+                );
+            }
+        }
+        fields
+    }
+
+    /// 解析 Rust 源码中带 `#[prost(...)]` 的字段，`oneof` 的成员归到所在的消息
+    fn rust_fields(source: &str) -> Fields {
+        let source = source.split("#[cfg(test)]").next().unwrap_or_default();
+        let mut fields = Fields::new();
+        let mut oneofs = BTreeMap::new();
+        let mut message = String::new();
+        let mut in_oneof = false;
+        let mut attr = String::new();
+        for line in source.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("pub struct ") {
+                message = name.trim_end_matches(" {").to_string();
+                in_oneof = false;
+            } else if let Some(name) = line.strip_prefix("pub enum ") {
+                let parent = oneofs.get(name.trim_end_matches(" {"));
+                message = parent.cloned().unwrap_or_default();
+                in_oneof = parent.is_some();
+            } else if line.starts_with("#[prost(") || !attr.ends_with(")]") && !attr.is_empty() {
+                attr.push_str(line);
+            } else if !attr.is_empty() && !message.is_empty() {
+                let (name, ty) = match line.strip_prefix("pub ") {
+                    Some(field) => field.split_once(": ").unwrap_or_default(),
+                    None => line.split_once('(').unwrap_or_default(),
+                };
+                let name = if in_oneof {
+                    name.chars()
+                        .enumerate()
+                        .fold(String::new(), |mut name, (i, c)| {
+                            if c.is_uppercase() && i > 0 {
+                                name.push('_');
+                            }
+                            name.push(c.to_ascii_lowercase());
+                            name
+                        })
+                } else {
+                    name.trim_start_matches("r#").to_string()
+                };
+                let ty = ty
+                    .trim_end_matches([',', '>', ')'])
+                    .rsplit(['<', ' ', '('])
+                    .next()
+                    .unwrap_or_default();
+                let value = if let Some(oneof) = quoted(&attr, "oneof") {
+                    oneofs.insert(oneof.to_string(), message.clone());
+                    format!("oneof {}", quoted(&attr, "tags").unwrap_or_default())
+                } else {
+                    let label = if in_oneof {
+                        "oneof "
+                    } else if attr.contains("optional") {
+                        "optional "
+                    } else if attr.contains("repeated") {
+                        "repeated "
+                    } else {
+                        ""
+                    };
+                    let kind = attr
+                        .trim_start_matches("#[prost(")
+                        .split(',')
+                        .next()
+                        .unwrap_or_default();
+                    let kind = match quoted(&attr, "btree_map").and_then(|map| map.split_once(", "))
+                    {
+                        Some((key, "message")) => format!("map<{key}, message {ty}>"),
+                        Some((key, value)) => format!("map<{key}, {value}>"),
+                        None if kind == "message" => format!("message {ty}"),
+                        None => kind.to_string(),
+                    };
+                    format!(
+                        "{label}{kind} = {}",
+                        quoted(&attr, "tag").unwrap_or_default()
+                    )
+                };
+                fields.insert(format!("{message}.{name}"), value);
+                attr.clear();
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn matches_proto_file() {
+        let proto = proto_fields(include_str!("../../../doc/ws.proto"));
+        assert!(proto.contains_key("Message.url_content_map"));
+        assert_eq!(rust_fields(include_str!("proto.rs")), proto);
+    }
+}
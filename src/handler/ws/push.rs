@@ -0,0 +1,713 @@
+//! # WebSocket 推送
+//!
+//! 服务端推送给客户端的消息统一为 `{"type": 类型, "data": 数据}`，类型见 [`WsPushType`]。
+//! 协议文档（AsyncAPI）由 [`asyncapi`] 生成，在 `/api-docs/ws.json` 提供。
+
+use axum::Json;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::ToSchema;
+
+use crate::active::ActiveStatus;
+use crate::handler::api::ErrorCode;
+use crate::handler::chat::{MemberResp, MessageResp};
+use crate::i18n;
+use crate::storage::model::announcement;
+use crate::url_discover::UrlInfo;
+
+/// 推送类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum WsPushType {
+    /// 登录二维码返回
+    LoginUrl = 1,
+    /// 用户扫描成功等待授权
+    LoginScanSuccess = 2,
+    /// 用户登录成功返回用户信息
+    LoginSuccess = 3,
+    /// 新消息
+    NewMessage = 4,
+    /// 上下线通知
+    OnlineOfflineNotify = 5,
+    /// token 失效，需要重新登录
+    TokenExpired = 6,
+    /// 消息标记
+    MsgMark = 8,
+    /// 消息撤回
+    MsgRecall = 9,
+    /// 好友申请
+    Apply = 10,
+    /// 用户资料变更
+    UserInfoChange = 12,
+    /// 系统通知
+    SystemNotice = 13,
+    /// 消息更新，如生成了链接预览，客户端按消息 ID 替换
+    MsgUpdate = 14,
+    /// 系统公告
+    Announcement = 15,
+    /// 登录二维码已过期，需要刷新
+    LoginUrlExpired = 16,
+    /// 群聊变更，如禁言、修改公告、转让群主、解散
+    GroupChange = 17,
+    /// 正在输入
+    Typing = 18,
+    /// 请求错误，如请求格式错误、未登录
+    Error = 19,
+    /// 服务端即将停机，客户端等待一段时间后重连
+    Reconnect = 20,
+}
+
+/// 服务端推送
+#[derive(Debug, Clone)]
+pub enum WsPush {
+    /// 登录二维码
+    LoginUrl(LoginUrl),
+    /// 用户扫描成功等待授权，没有数据
+    LoginScanSuccess,
+    /// 用户登录成功
+    LoginSuccess(LoginSuccess),
+    /// 新消息
+    NewMessage(MessageResp),
+    /// 上下线通知
+    OnlineOfflineNotify(OnlineOfflineNotify),
+    /// token 失效，没有数据
+    TokenExpired,
+    /// 消息标记
+    MsgMark(MsgMark),
+    /// 消息撤回
+    MsgRecall(MsgRecall),
+    /// 好友申请
+    Apply(FriendApply),
+    /// 用户资料变更
+    UserInfoChange(UserInfoChange),
+    /// 系统通知
+    SystemNotice(SystemNotice),
+    /// 消息更新
+    MsgUpdate(MessageResp),
+    /// 系统公告
+    Announcement(Announcement),
+    /// 登录二维码已过期，没有数据
+    LoginUrlExpired,
+    /// 群聊变更
+    GroupChange(GroupChange),
+    /// 正在输入
+    Typing(Typing),
+    /// 请求错误
+    Error(WsError),
+    /// 服务端即将停机
+    Reconnect(Reconnect),
+}
+
+impl WsPush {
+    /// 推送序号，只有新消息携带，为消息 ID，客户端确认后用于断线补发，见 [`resume`](super::resume)
+    pub fn seq(&self) -> Option<u64> {
+        match self {
+            WsPush::NewMessage(message) => Some(message.id),
+            _ => None,
+        }
+    }
+
+    /// 推送类型
+    pub fn push_type(&self) -> WsPushType {
+        match self {
+            WsPush::LoginUrl(_) => WsPushType::LoginUrl,
+            WsPush::LoginScanSuccess => WsPushType::LoginScanSuccess,
+            WsPush::LoginSuccess(_) => WsPushType::LoginSuccess,
+            WsPush::NewMessage(_) => WsPushType::NewMessage,
+            WsPush::OnlineOfflineNotify(_) => WsPushType::OnlineOfflineNotify,
+            WsPush::TokenExpired => WsPushType::TokenExpired,
+            WsPush::MsgMark(_) => WsPushType::MsgMark,
+            WsPush::MsgRecall(_) => WsPushType::MsgRecall,
+            WsPush::Apply(_) => WsPushType::Apply,
+            WsPush::UserInfoChange(_) => WsPushType::UserInfoChange,
+            WsPush::SystemNotice(_) => WsPushType::SystemNotice,
+            WsPush::MsgUpdate(_) => WsPushType::MsgUpdate,
+            WsPush::Announcement(_) => WsPushType::Announcement,
+            WsPush::LoginUrlExpired => WsPushType::LoginUrlExpired,
+            WsPush::GroupChange(_) => WsPushType::GroupChange,
+            WsPush::Typing(_) => WsPushType::Typing,
+            WsPush::Error(_) => WsPushType::Error,
+            WsPush::Reconnect(_) => WsPushType::Reconnect,
+        }
+    }
+}
+
+/// 对请求的直接回复，`seq` 为请求中的序号，客户端据此匹配请求与回复
+#[derive(Debug, Clone, Copy)]
+pub struct WsReply<'a> {
+    /// 回复内容
+    pub push: &'a WsPush,
+    /// 请求序号
+    pub seq: Option<u64>,
+}
+
+impl Serialize for WsPush {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        WsReply {
+            push: self,
+            seq: self.seq(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for WsReply<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut push = serializer.serialize_struct("WsPush", 3)?;
+        push.serialize_field("type", &self.push.push_type())?;
+        match self.seq {
+            Some(seq) => push.serialize_field("seq", &seq)?,
+            None => push.skip_field("seq")?,
+        }
+        match self.push {
+            WsPush::LoginUrl(data) => push.serialize_field("data", data)?,
+            WsPush::LoginSuccess(data) => push.serialize_field("data", data)?,
+            WsPush::NewMessage(data) => push.serialize_field("data", data)?,
+            WsPush::OnlineOfflineNotify(data) => push.serialize_field("data", data)?,
+            WsPush::MsgMark(data) => push.serialize_field("data", data)?,
+            WsPush::MsgRecall(data) => push.serialize_field("data", data)?,
+            WsPush::Apply(data) => push.serialize_field("data", data)?,
+            WsPush::UserInfoChange(data) => push.serialize_field("data", data)?,
+            WsPush::SystemNotice(data) => push.serialize_field("data", data)?,
+            WsPush::MsgUpdate(data) => push.serialize_field("data", data)?,
+            WsPush::Announcement(data) => push.serialize_field("data", data)?,
+            WsPush::GroupChange(data) => push.serialize_field("data", data)?,
+            WsPush::Typing(data) => push.serialize_field("data", data)?,
+            WsPush::Error(data) => push.serialize_field("data", data)?,
+            WsPush::Reconnect(data) => push.serialize_field("data", data)?,
+            WsPush::LoginScanSuccess | WsPush::TokenExpired | WsPush::LoginUrlExpired => {
+                push.skip_field("data")?
+            }
+        }
+        push.end()
+    }
+}
+
+/// 登录二维码
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginUrl {
+    /// 二维码链接，使用企业微信登录时为网页扫码登录的链接
+    ///
+    /// 无法自行生成二维码的客户端可以使用 `/capi/qr?data=` 加上编码后的链接作为图片地址
+    pub login_url: String,
+    /// 登录码，服务端重启等原因断开后，重连时在查询参数 `login_code` 中携带可以继续登录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_code: Option<String>,
+}
+
+/// 登录成功
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginSuccess {
+    /// 用户 ID
+    pub uid: i64,
+    /// 头像
+    pub avatar: Option<String>,
+    /// token
+    pub token: String,
+    /// 昵称
+    pub name: Option<String>,
+    /// 权限，1 为管理员
+    pub power: i32,
+}
+
+/// 上下线通知
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OnlineOfflineNotify {
+    /// 上下线的用户
+    pub change_list: Vec<MemberResp>,
+    /// 在线人数
+    pub online_num: u64,
+}
+
+/// 消息标记
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgMark {
+    /// 标记变化
+    pub mark_list: Vec<MsgMarkItem>,
+}
+
+/// 单条消息的标记变化
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgMarkItem {
+    /// 操作者 uid
+    pub uid: i64,
+    /// 消息 ID
+    pub msg_id: i64,
+    /// 标记类型 1点赞 2举报
+    pub mark_type: i32,
+    /// 标记数量
+    pub mark_count: i32,
+    /// 动作类型 1确认 2取消
+    pub act_type: i32,
+}
+
+/// 消息撤回
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgRecall {
+    /// 消息 ID
+    pub msg_id: i64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 撤回者 uid
+    pub recall_uid: i64,
+}
+
+/// 好友申请通知
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendApply {
+    /// 申请人 uid
+    pub uid: i64,
+    /// 未读申请数
+    pub unread_count: u64,
+}
+
+/// 用户资料变更通知
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfoChange {
+    /// 用户 ID
+    pub uid: i64,
+    /// 昵称
+    pub name: Option<String>,
+    /// 头像
+    pub avatar: Option<String>,
+}
+
+/// 系统通知
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemNotice {
+    /// 通知内容
+    pub content: String,
+}
+
+/// 系统公告
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Announcement {
+    /// 公告 ID
+    pub id: u64,
+    /// 公告内容
+    pub content: String,
+    /// 发布时间
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(with = "crate::timestamp::iso8601")]
+    pub create_time: time::PrimitiveDateTime,
+}
+
+impl From<announcement::Model> for Announcement {
+    fn from(model: announcement::Model) -> Self {
+        Self {
+            id: model.id,
+            content: model.content,
+            create_time: model.create_time,
+        }
+    }
+}
+
+/// 群聊变更类型
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde_repr::Serialize_repr,
+    serde_repr::Deserialize_repr,
+    ToSchema,
+)]
+#[repr(u8)]
+pub enum GroupChangeType {
+    /// 禁言或解除禁言
+    Mute = 1,
+    /// 修改群公告
+    Announcement = 2,
+    /// 转让群主
+    TransferOwner = 3,
+    /// 解散群聊
+    Dissolve = 4,
+    /// 成员退出群聊
+    Leave = 5,
+}
+
+/// 群聊变更
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupChange {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 变更类型
+    pub change_type: GroupChangeType,
+    /// 操作者 uid
+    pub operator_uid: i64,
+    /// 被禁言的成员、新群主或退出的成员 uid
+    pub uid: Option<i64>,
+    /// 禁言截止时间，为空时解除禁言
+    #[schema(value_type = Option<String>, format = DateTime)]
+    #[serde(default, with = "crate::timestamp::iso8601::option")]
+    pub mute_until: Option<time::PrimitiveDateTime>,
+    /// 新的群公告
+    pub announcement: Option<String>,
+}
+
+/// 正在输入
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Typing {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 正在输入的用户 uid
+    pub uid: i64,
+}
+
+/// 请求错误，错误码与 HTTP 接口相同
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WsError {
+    /// 错误码，见 [`ErrorCode`]
+    pub err_code: i32,
+    /// 错误信息
+    pub err_msg: String,
+}
+
+impl WsError {
+    /// 创建，错误信息按当前连接的语言翻译
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            err_code: code as i32,
+            err_msg: i18n::error_message(code, &message.into()),
+        }
+    }
+}
+
+/// 服务端即将停机
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconnect {
+    /// 收到 Close 帧后等待的毫秒数，之后重连，由负载均衡分配到其他实例
+    pub after_ms: u64,
+}
+
+fn schema<'s, T: ToSchema<'s>>() -> (&'s str, RefOr<Schema>) {
+    T::schema()
+}
+
+fn message(r#type: WsPushType, summary: &str, data: Option<&str>) -> serde_json::Value {
+    let mut properties = serde_json::json!({
+        "type": { "type": "integer", "enum": [r#type as u8] },
+    });
+    let mut required = vec!["type"];
+    if let Some(data) = data {
+        properties["data"] = serde_json::json!({ "$ref": format!("#/components/schemas/{data}") });
+        required.push("data");
+    }
+    serde_json::json!({
+        "name": format!("{type:?}"),
+        "summary": summary,
+        "payload": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    })
+}
+
+/// 新消息额外携带推送序号
+fn new_message() -> serde_json::Value {
+    let mut message = message(WsPushType::NewMessage, "新消息", Some("MessageResp"));
+    message["payload"]["properties"]["seq"] = serde_json::json!({
+        "type": "integer",
+        "description": "推送序号，处理后发送确认请求",
+    });
+    message["payload"]["required"] = serde_json::json!(["type", "data", "seq"]);
+    message
+}
+
+/// 携带请求序号的直接回复
+const REPLIES: [&str; 4] = ["LoginUrl", "LoginSuccess", "TokenExpired", "Error"];
+
+/// 生成 WebSocket 协议的 AsyncAPI 文档
+pub fn asyncapi() -> serde_json::Value {
+    let schemas = [
+        schema::<LoginUrl>(),
+        schema::<LoginSuccess>(),
+        schema::<MessageResp>(),
+        schema::<UrlInfo>(),
+        schema::<MemberResp>(),
+        schema::<ActiveStatus>(),
+        schema::<OnlineOfflineNotify>(),
+        schema::<MsgMark>(),
+        schema::<MsgMarkItem>(),
+        schema::<MsgRecall>(),
+        schema::<FriendApply>(),
+        schema::<UserInfoChange>(),
+        schema::<SystemNotice>(),
+        schema::<Announcement>(),
+        schema::<GroupChangeType>(),
+        schema::<GroupChange>(),
+        schema::<Typing>(),
+        schema::<WsError>(),
+        schema::<Reconnect>(),
+    ]
+    .into_iter()
+    .map(|(name, schema)| serde_json::to_value(schema).map(|schema| (name.to_string(), schema)))
+    .collect::<Result<serde_json::Map<_, _>, _>>()
+    .unwrap_or_default();
+
+    let pushes = [
+        message(WsPushType::LoginUrl, "登录二维码", Some("LoginUrl")),
+        message(WsPushType::LoginScanSuccess, "用户扫描成功等待授权", None),
+        message(
+            WsPushType::LoginSuccess,
+            "用户登录成功",
+            Some("LoginSuccess"),
+        ),
+        new_message(),
+        message(
+            WsPushType::OnlineOfflineNotify,
+            "上下线通知",
+            Some("OnlineOfflineNotify"),
+        ),
+        message(WsPushType::TokenExpired, "token 失效，需要重新登录", None),
+        message(WsPushType::MsgMark, "消息标记", Some("MsgMark")),
+        message(WsPushType::MsgRecall, "消息撤回", Some("MsgRecall")),
+        message(WsPushType::Apply, "好友申请", Some("FriendApply")),
+        message(
+            WsPushType::UserInfoChange,
+            "用户资料变更",
+            Some("UserInfoChange"),
+        ),
+        message(WsPushType::SystemNotice, "系统通知", Some("SystemNotice")),
+        message(
+            WsPushType::MsgUpdate,
+            "消息更新，如生成了链接预览",
+            Some("MessageResp"),
+        ),
+        message(WsPushType::Announcement, "系统公告", Some("Announcement")),
+        message(
+            WsPushType::LoginUrlExpired,
+            "登录二维码已过期，发送 RefreshLogin 请求获取新的二维码",
+            None,
+        ),
+        message(WsPushType::GroupChange, "群聊变更", Some("GroupChange")),
+        message(
+            WsPushType::Typing,
+            "正在输入，群聊中也会推送给输入者自己的连接，客户端忽略自己的输入状态",
+            Some("Typing"),
+        ),
+        message(
+            WsPushType::Error,
+            "请求错误，如请求格式错误、未登录",
+            Some("WsError"),
+        ),
+        message(
+            WsPushType::Reconnect,
+            "服务端即将停机，随后发送 Close 帧，客户端等待 afterMs 毫秒后重连",
+            Some("Reconnect"),
+        ),
+    ]
+    .map(|mut push| {
+        if REPLIES.contains(&push["name"].as_str().unwrap_or_default()) {
+            push["payload"]["properties"]["seq"] = serde_json::json!({
+                "type": "integer",
+                "description": "回复请求时为请求中的序号",
+            });
+        }
+        push
+    });
+    let requests = [
+        serde_json::json!({
+            "name": "Login",
+            "summary": "请求登录二维码",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [1] },
+                    "data": { "type": "string", "enum": ["work"], "description": "为 work 时返回企业微信登录链接" },
+                },
+            },
+        }),
+        serde_json::json!({
+            "name": "Heartbeat",
+            "summary": "心跳",
+            "payload": { "type": "object", "properties": { "type": { "type": "integer", "enum": [2] } } },
+        }),
+        serde_json::json!({
+            "name": "Authorize",
+            "summary": "使用 token 登录",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [3] },
+                    "data": { "type": "string", "description": "token" },
+                },
+            },
+        }),
+        serde_json::json!({
+            "name": "Ack",
+            "summary": "确认推送",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [4] },
+                    "data": { "type": "string", "description": "推送序号" },
+                },
+            },
+        }),
+        serde_json::json!({
+            "name": "Resume",
+            "summary": "重连后补发序号之后的消息，每次最多 100 条",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [5] },
+                    "data": { "type": "string", "description": "已收到的推送序号，为空时使用服务端记录的确认序号" },
+                },
+            },
+        }),
+        serde_json::json!({
+            "name": "RefreshLogin",
+            "summary": "二维码过期后刷新，返回绑定当前连接的新二维码",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [6] },
+                    "data": { "type": "string", "enum": ["work"], "description": "登录方式，为空时与上一次登录请求相同" },
+                },
+            },
+        }),
+        serde_json::json!({
+            "name": "Typing",
+            "summary": "正在输入，推送给房间的其他在线成员，同一用户每 3 秒最多推送一次",
+            "payload": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "integer", "enum": [7] },
+                    "data": { "type": "string", "description": "房间 ID" },
+                },
+            },
+        }),
+    ]
+    .map(|mut request| {
+        request["payload"]["properties"]["seq"] = serde_json::json!({
+            "type": "integer",
+            "description": "请求序号，可选，登录二维码、登录结果和错误等直接回复中原样返回",
+        });
+        request
+    });
+
+    serde_json::json!({
+        "asyncapi": "2.6.0",
+        "info": {
+            "title": "MallChat WebSocket",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "channels": {
+            "/websocket": {
+                "description": "默认使用 JSON 文本帧；子协议中包含 mallchat.protobuf 时使用 protobuf 二进制帧，定义见 doc/ws.proto",
+                "bindings": {
+                    "ws": {
+                        "query": {
+                            "type": "object",
+                            "properties": {
+                                "token": {
+                                    "type": "string",
+                                    "description": "登录后获得的 token，也可以放在 Sec-WebSocket-Protocol 中",
+                                },
+                                "compress": {
+                                    "type": "string",
+                                    "enum": ["gzip"],
+                                    "description": "超过阈值的推送以 gzip 压缩后的二进制帧发送",
+                                },
+                            },
+                        },
+                    },
+                },
+                "publish": { "message": { "oneOf": requests } },
+                "subscribe": { "message": { "oneOf": pushes } },
+            },
+        },
+        "components": { "schemas": schemas },
+    })
+}
+
+/// WebSocket 协议文档
+pub async fn asyncapi_json() -> Json<serde_json::Value> {
+    Json(asyncapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::api::ErrorCode;
+    use crate::handler::chat::MessageResp;
+    use crate::handler::ws::push::{asyncapi, FriendApply, WsError, WsPush, WsReply};
+
+    #[test]
+    fn serialize() -> anyhow::Result<()> {
+        let push = WsPush::Apply(FriendApply {
+            uid: 1,
+            unread_count: 2,
+        });
+        assert_eq!(
+            serde_json::to_string(&push)?,
+            r#"{"type":10,"data":{"uid":1,"unreadCount":2}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&WsPush::TokenExpired)?,
+            r#"{"type":6}"#
+        );
+        let push = WsPush::NewMessage(MessageResp {
+            id: 5,
+            room_id: 1,
+            from_uid: 1,
+            content: "hello".to_string(),
+            reply_msg_id: None,
+            send_time: time::macros::datetime!(2023-06-01 00:00),
+            from_region: None,
+            url_content_map: None,
+            client_msg_id: None,
+        });
+        assert!(serde_json::to_string(&push)?.starts_with(r#"{"type":4,"seq":5,"data":{"id":5,"#));
+        let push = WsPush::Error(WsError::new(ErrorCode::InvalidParam, "请求格式错误"));
+        assert_eq!(
+            serde_json::to_string(&WsReply {
+                push: &push,
+                seq: Some(3)
+            })?,
+            r#"{"type":19,"seq":3,"data":{"errCode":9001,"errMsg":"请求格式错误"}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn asyncapi_refs() {
+        let doc = asyncapi();
+        let pushes = doc["channels"]["/websocket"]["subscribe"]["message"]["oneOf"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        assert_eq!(pushes.len(), 18);
+        let schemas = &doc["components"]["schemas"];
+        for push in pushes {
+            if let Some(reference) = push["payload"]["properties"]["data"]["$ref"].as_str() {
+                let name = reference.trim_start_matches("#/components/schemas/");
+                assert!(schemas.get(name).is_some(), "{reference}");
+            }
+        }
+        assert!(schemas["OnlineOfflineNotify"].is_object());
+    }
+}
@@ -0,0 +1,144 @@
+//! # 推送确认与断线补发
+//!
+//! 新消息的推送携带序号 `seq`，即单调递增的消息 ID。客户端处理后发送 [`ReqType::Ack`](super::ReqType::Ack)
+//! 确认，服务端在 Redis 中记录每个用户确认过的最大序号；重连并登录后客户端发送
+//! [`ReqType::Resume`](super::ReqType::Resume)，可以携带本地的序号，未携带时使用服务端记录的序号，
+//! 服务端补发之后的房间消息。每次最多补发 [`MAX_REPLAY`] 条，客户端可以使用补发后的序号再次请求。
+
+use sea_orm::DbErr;
+
+use crate::cache::Cache;
+use crate::handler::chat::MessageResp;
+use crate::storage::repo::{MessageRepo, RoomRepo};
+
+/// 用户已确认的推送序号，字段为 uid
+pub const PUSH_ACK_KEY: &str = "mallchat:ws:ack";
+
+/// 每次最多补发的消息数
+pub const MAX_REPLAY: u64 = 100;
+
+/// 只在序号更大时更新，避免乱序的确认使游标回退
+const ACK_SCRIPT: &str = r#"
+local current = tonumber(redis.call('HGET', KEYS[1], ARGV[1]) or '0')
+if tonumber(ARGV[2]) > current then
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+end
+return 0
+"#;
+
+/// 用户已确认的推送序号
+#[derive(Debug, Clone)]
+pub struct PushCursor {
+    cache: Cache,
+}
+
+impl PushCursor {
+    /// 创建
+    pub fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+
+    /// 确认推送序号
+    pub async fn ack(&self, uid: i64, seq: u64) -> anyhow::Result<()> {
+        let mut connection = self.cache.connection().await?;
+        redis::Script::new(ACK_SCRIPT)
+            .key(PUSH_ACK_KEY)
+            .arg(uid)
+            .arg(seq)
+            .invoke_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    /// 已确认的最大推送序号
+    pub async fn get(&self, uid: i64) -> anyhow::Result<Option<u64>> {
+        let mut connection = self.cache.connection().await?;
+        let seq: Option<u64> = redis::cmd("HGET")
+            .arg(PUSH_ACK_KEY)
+            .arg(uid)
+            .query_async(&mut connection)
+            .await?;
+        Ok(seq)
+    }
+}
+
+/// 用户可以访问的房间中序号 `after` 之后的消息，按序号排列
+pub async fn missed_messages(
+    messages: &dyn MessageRepo,
+    rooms: &dyn RoomRepo,
+    uid: i64,
+    after: u64,
+) -> Result<Vec<MessageResp>, DbErr> {
+    let room_ids = rooms.member_room_ids(uid).await?;
+    Ok(messages
+        .list_after(&room_ids, after, MAX_REPLAY)
+        .await?
+        .into_iter()
+        .map(MessageResp::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::Set;
+
+    use crate::handler::chat::MessageStatus;
+    use crate::handler::ws::resume::missed_messages;
+    use crate::service::room::RoomType;
+    use crate::storage::model::message;
+    use crate::storage::repo::MessageRepo;
+    use crate::testing::MemoryRepo;
+
+    #[tokio::test]
+    async fn missed() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        let group = repo.add_room("group", RoomType::Group as i32);
+        let single = repo.add_room("single", RoomType::Single as i32);
+        for (room_id, status) in [
+            (group, MessageStatus::Normal),
+            (single, MessageStatus::Normal),
+            (group, MessageStatus::Normal),
+            (group, MessageStatus::Deleted),
+            (group, MessageStatus::Normal),
+        ] {
+            MessageRepo::create(
+                &repo,
+                message::ActiveModel {
+                    room_id: Set(room_id),
+                    from_uid: Set(1),
+                    content: Set("hello".to_string()),
+                    status: Set(status as i32),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+
+        let missed = missed_messages(&repo, &repo, 1, 1).await?;
+        let ids: Vec<_> = missed.iter().map(|message| message.id).collect();
+        assert_eq!(ids, vec![3, 5]);
+        assert!(missed_messages(&repo, &repo, 1, 5).await?.is_empty());
+
+        // 创建的群聊只补发给成员
+        let other = repo.add_room("other", RoomType::Group as i32);
+        MessageRepo::create(
+            &repo,
+            message::ActiveModel {
+                room_id: Set(other),
+                from_uid: Set(2),
+                content: Set("hello".to_string()),
+                status: Set(MessageStatus::Normal as i32),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert!(missed_messages(&repo, &repo, 1, 5).await?.is_empty());
+        repo.add_group_member(other, 1);
+        let missed = missed_messages(&repo, &repo, 1, 5).await?;
+        assert_eq!(
+            missed.iter().map(|message| message.id).collect::<Vec<_>>(),
+            vec![6]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! # 登录状态持久化
+//!
+//! 连接只保存在实例的内存中，重启后客户端需要重连。为了不让扫码登录中断，
+//! 签发登录二维码时在 Redis 中记录场景值和登录码，登录码随 `LoginUrl` 推送给客户端：
+//!
+//! - 场景值使用 Redis 自增分配，重启后不会与之前签发的二维码重复，扫码事件不会通知到其他连接；
+//! - 扫码登录完成时发起登录的连接不在本实例（如正在重连），记录场景值对应的用户，
+//!   客户端携带登录码重连后直接登录；
+//! - 客户端携带登录码重连时登录尚未完成，将场景值绑定到新的连接，之后的扫码事件通知新的连接。
+//!
+//! 推送确认的序号已经保存在 Redis 中（见 [`PushCursor`](super::resume::PushCursor)），重连后可以补发。
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::cache::{Cache, CacheConnection};
+
+/// 分配场景值的计数器
+pub const SCENE_SEQ_KEY: &str = "mallchat:ws:scene_seq";
+
+/// 登录码对应的待登录状态，哈希表，字段为 `scene`、登录请求的 `data` 和登录完成后的 `uid`
+const LOGIN_KEY_PREFIX: &str = "mallchat:ws:login:";
+
+/// 场景值对应的登录码
+const SCENE_KEY_PREFIX: &str = "mallchat:ws:scene:";
+
+/// 临时二维码的场景值为 32 位非 0 整数
+const MAX_SCENE: u64 = u32::MAX as u64;
+
+/// 新签发的登录二维码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuedLogin {
+    /// 场景值
+    pub scene: NonZeroUsize,
+    /// 登录码，客户端重连时携带
+    pub code: String,
+}
+
+/// 待登录状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingLogin {
+    /// 场景值
+    pub scene: NonZeroUsize,
+    /// 登录请求的数据，即登录方式
+    pub data: Option<String>,
+    /// 已完成登录的用户，未扫码或未授权时为空
+    pub uid: Option<i64>,
+    /// 二维码的剩余有效期
+    pub expires_in: Option<Duration>,
+}
+
+/// 登录状态存储
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    cache: Cache,
+}
+
+impl SessionStore {
+    /// 创建
+    pub fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+
+    /// 分配场景值并生成登录码，在二维码过期后一起过期，`data` 为登录请求的数据
+    pub async fn issue(&self, ttl: Duration, data: Option<&str>) -> anyhow::Result<IssuedLogin> {
+        let mut connection = self.cache.connection().await?;
+        let seq: u64 = redis::cmd("INCR")
+            .arg(SCENE_SEQ_KEY)
+            .query_async(&mut connection)
+            .await?;
+        let scene = scene_of(seq);
+        let code = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        let ttl = ttl.as_secs().max(1);
+        let mut fields = vec![("scene", scene.to_string())];
+        if let Some(data) = data {
+            fields.push(("data", data.to_string()));
+        }
+        redis::pipe()
+            .atomic()
+            .cmd("HSET")
+            .arg(login_key(&code))
+            .arg(fields)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(login_key(&code))
+            .arg(ttl)
+            .ignore()
+            .cmd("SET")
+            .arg(scene_key(scene))
+            .arg(&code)
+            .arg("EX")
+            .arg(ttl)
+            .ignore()
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(IssuedLogin { scene, code })
+    }
+
+    /// 记录场景值对应的登录已完成，返回场景值是否仍然有效
+    pub async fn complete(&self, scene: NonZeroUsize, uid: i64) -> anyhow::Result<bool> {
+        let mut connection = self.cache.connection().await?;
+        let code: Option<String> = redis::cmd("GET")
+            .arg(scene_key(scene))
+            .query_async(&mut connection)
+            .await?;
+        let Some(code) = code else {
+            return Ok(false);
+        };
+        // 只更新仍然存在的登录码，不改变过期时间
+        let updated: i64 = redis::Script::new(
+            "if redis.call('EXISTS', KEYS[1]) == 1 then return redis.call('HSET', KEYS[1], 'uid', ARGV[1]) + 1 end return 0",
+        )
+        .key(login_key(&code))
+        .arg(uid)
+        .invoke_async(&mut connection)
+        .await?;
+        Ok(updated > 0)
+    }
+
+    /// 客户端携带登录码重连，登录已完成时移除登录码，只能使用一次
+    pub async fn claim(&self, code: &str) -> anyhow::Result<Option<PendingLogin>> {
+        let mut connection = self.cache.connection().await?;
+        let (fields, ttl): (Vec<(String, String)>, i64) = redis::pipe()
+            .cmd("HGETALL")
+            .arg(login_key(code))
+            .cmd("TTL")
+            .arg(login_key(code))
+            .query_async(&mut connection)
+            .await?;
+        let Some(pending) = pending_login(&fields, ttl) else {
+            return Ok(None);
+        };
+        if pending.uid.is_some() {
+            self.remove(&mut connection, pending.scene, code).await?;
+        }
+        Ok(Some(pending))
+    }
+
+    /// 登录已通知到连接，移除场景值和登录码
+    pub async fn finish(&self, scene: NonZeroUsize) -> anyhow::Result<()> {
+        let mut connection = self.cache.connection().await?;
+        let code: Option<String> = redis::cmd("GET")
+            .arg(scene_key(scene))
+            .query_async(&mut connection)
+            .await?;
+        if let Some(code) = code {
+            self.remove(&mut connection, scene, &code).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove(
+        &self,
+        connection: &mut CacheConnection,
+        scene: NonZeroUsize,
+        code: &str,
+    ) -> redis::RedisResult<()> {
+        redis::cmd("DEL")
+            .arg(login_key(code))
+            .arg(scene_key(scene))
+            .query_async(connection)
+            .await
+    }
+}
+
+fn login_key(code: &str) -> String {
+    format!("{LOGIN_KEY_PREFIX}{code}")
+}
+
+fn scene_key(scene: NonZeroUsize) -> String {
+    format!("{SCENE_KEY_PREFIX}{scene}")
+}
+
+/// 将自增序号映射到 `1..=u32::MAX`
+fn scene_of(seq: u64) -> NonZeroUsize {
+    let scene = seq.saturating_sub(1) % MAX_SCENE + 1;
+    NonZeroUsize::new(scene as usize).expect("scene is nonzero")
+}
+
+/// 解析 `HGETALL` 和 `TTL` 的结果，登录码不存在时返回 `None`
+fn pending_login(fields: &[(String, String)], ttl: i64) -> Option<PendingLogin> {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+    };
+    Some(PendingLogin {
+        scene: field("scene")?.parse().ok()?,
+        data: field("data").map(str::to_string),
+        uid: field("uid").and_then(|uid| uid.parse().ok()),
+        expires_in: u64::try_from(ttl).ok().map(Duration::from_secs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use crate::handler::ws::store::{pending_login, scene_of, PendingLogin};
+
+    #[test]
+    fn scene() {
+        assert_eq!(scene_of(1).get(), 1);
+        assert_eq!(scene_of(u32::MAX as u64).get(), u32::MAX as usize);
+        assert_eq!(scene_of(u32::MAX as u64 + 1).get(), 1);
+    }
+
+    #[test]
+    fn pending() {
+        let scene = NonZeroUsize::new(42).expect("nonzero");
+        assert_eq!(pending_login(&[], -2), None);
+        let field = |key: &str, value: &str| (key.to_string(), value.to_string());
+        assert_eq!(
+            pending_login(&[field("scene", "42")], 60),
+            Some(PendingLogin {
+                scene,
+                data: None,
+                uid: None,
+                expires_in: Some(Duration::from_secs(60)),
+            })
+        );
+        assert_eq!(
+            pending_login(
+                &[
+                    field("scene", "42"),
+                    field("data", "work"),
+                    field("uid", "7")
+                ],
+                -1
+            ),
+            Some(PendingLogin {
+                scene,
+                data: Some("work".to_string()),
+                uid: Some(7),
+                expires_in: None,
+            })
+        );
+        assert_eq!(pending_login(&[field("scene", "0")], 60), None);
+    }
+}
@@ -0,0 +1,482 @@
+//! # 本地化
+//!
+//! 面向用户的文本（错误消息、系统通知、微信回复）在代码中使用中文书写，输出时按语言翻译：
+//!
+//! - HTTP 请求和 WebSocket 连接按 `Accept-Language` 请求头选择语言，记录在 [`RequestContext`] 中；
+//! - 没有请求头或不支持时（如微信回调、消息队列消费者）使用配置的默认语言 [`I18nConfig::default_locale`]。
+//!
+//! 翻译表以中文原文为键，原文中的参数用 `{}` 占位，如 `您已被禁言，{} 秒后解除`，
+//! 格式化后的文本也能匹配并翻译，参数本身有译文时一起翻译。
+//! 错误消息没有译文时使用错误码对应的通用消息。
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::handler::api::ErrorCode;
+use crate::handler::context::RequestContext;
+
+/// 本地化配置
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct I18nConfig {
+    /// 没有 `Accept-Language` 请求头或不支持时使用的语言
+    pub default_locale: Locale,
+}
+
+/// 支持的语言
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// 简体中文，代码中的原文
+    #[default]
+    Zh,
+    /// 英文
+    En,
+}
+
+static DEFAULT_LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// 设置默认语言，只在启动时设置一次
+pub fn set_default_locale(locale: Locale) {
+    if DEFAULT_LOCALE.set(locale).is_err() {
+        tracing::warn!(?locale, "Default locale already set, ignored");
+    }
+}
+
+impl Locale {
+    /// 默认语言
+    pub fn default_locale() -> Self {
+        DEFAULT_LOCALE.get().copied().unwrap_or_default()
+    }
+
+    /// 当前请求的语言，不在请求中或请求未指定时为默认语言
+    pub fn current() -> Self {
+        RequestContext::current()
+            .and_then(|context| context.locale())
+            .unwrap_or_else(Self::default_locale)
+    }
+
+    /// 语言标签对应的语言，如 `zh-CN`、`en`
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        if primary.eq_ignore_ascii_case("zh") {
+            Some(Self::Zh)
+        } else if primary.eq_ignore_ascii_case("en") {
+            Some(Self::En)
+        } else {
+            None
+        }
+    }
+
+    /// 按 `Accept-Language` 请求头选择权重最高的支持的语言，权重相同时取靠前的
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut best: Option<(Self, f32)> = None;
+        for item in header.split(',') {
+            let mut parts = item.split(';');
+            let Some(locale) = parts.next().and_then(Self::from_tag) else {
+                continue;
+            };
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+
+    /// 翻译文本，没有译文时返回 `None`
+    pub fn translate(self, text: &str) -> Option<Cow<'static, str>> {
+        let index = match self {
+            Self::Zh => return None,
+            Self::En => 0,
+        };
+        let catalog = catalog();
+        if let Some(translations) = catalog.exact.get(text) {
+            return Some(Cow::Borrowed(translations[index]));
+        }
+        catalog
+            .templates
+            .iter()
+            .find_map(|(template, translations)| {
+                let args = match_template(template, text)?;
+                let mut args = args.into_iter().map(|arg| tr_in(self, arg));
+                let translated = translations[index].split("{}").enumerate().fold(
+                    String::new(),
+                    |mut translated, (i, piece)| {
+                        if i > 0 {
+                            translated.push_str(&args.next().unwrap_or_default());
+                        }
+                        translated.push_str(piece);
+                        translated
+                    },
+                );
+                Some(Cow::Owned(translated))
+            })
+    }
+}
+
+/// 使用指定的语言翻译，没有译文时返回原文
+pub fn tr_in(locale: Locale, text: &str) -> Cow<'_, str> {
+    match locale.translate(text) {
+        Some(translated) => translated,
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// 使用当前请求的语言翻译，没有译文时返回原文
+pub fn tr(text: &str) -> Cow<'_, str> {
+    tr_in(Locale::current(), text)
+}
+
+/// 使用当前请求的语言翻译错误消息，没有译文时：
+/// 原文不含非 ASCII 字符（如提取参数失败的英文消息）则保留，否则使用错误码对应的通用消息
+pub fn error_message(code: ErrorCode, message: &str) -> String {
+    let locale = Locale::current();
+    match locale.translate(message) {
+        Some(translated) => translated.into_owned(),
+        None if locale == Locale::Zh || message.is_ascii() => message.to_string(),
+        None => tr_in(locale, code_message(code)).into_owned(),
+    }
+}
+
+/// 错误码对应的通用消息
+fn code_message(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::Unknown => "未知错误",
+        ErrorCode::InvalidToken => "登录已失效，请重新登录",
+        ErrorCode::PermissionDenied => "没有权限",
+        ErrorCode::InvalidCredentials => "邮箱或密码错误",
+        ErrorCode::EmailTaken => "邮箱已注册",
+        ErrorCode::TooManyAttempts => "登录失败次数过多，请稍后再试",
+        ErrorCode::CaptchaRequired => "请输入验证码",
+        ErrorCode::InvalidCaptcha => "验证码错误",
+        ErrorCode::NameTaken => "名字已被抢占",
+        ErrorCode::NoRenameCard => "改名卡不足",
+        ErrorCode::UserNotFound => "用户不存在",
+        ErrorCode::TooManyEmojis => "表情数量已达上限",
+        ErrorCode::EmojiExists => "当前表情已存在哦~~",
+        ErrorCode::EmojiNotFound => "表情不存在",
+        ErrorCode::DeviceNotFound => "设备不存在",
+        ErrorCode::NotRoomMember => "您不是该房间的成员",
+        ErrorCode::RoomNotFound => "房间不存在",
+        ErrorCode::Muted => "您已被禁言",
+        ErrorCode::InvalidInvite => "邀请无效或已过期",
+        ErrorCode::GroupFull => "群成员已满",
+        ErrorCode::MessageSending => "消息正在发送",
+        ErrorCode::MessageNotFound => "消息不存在或已删除",
+        ErrorCode::AlreadyReported => "您已经举报过该消息",
+        ErrorCode::AddSelfAsFriend => "不能添加自己为好友",
+        ErrorCode::AlreadyFriends => "你们已经是好友了",
+        ErrorCode::NotFriends => "你们不是好友",
+        ErrorCode::FriendApplyNotFound => "申请不存在",
+        ErrorCode::FriendApplyHandled => "已审批过该申请",
+        ErrorCode::InvalidParam => "请求参数错误",
+        ErrorCode::PayloadTooLarge => "请求体过大",
+        ErrorCode::RequestTimeout => "请求超时",
+        ErrorCode::TooManyConnections | ErrorCode::TooManyConnectionsFromIp => "连接数已满",
+        ErrorCode::SessionNotFound => "连接不存在",
+        ErrorCode::InvalidUploadUrl => "上传链接无效或已过期",
+        ErrorCode::ShortLinkNotFound => "链接不存在或已过期",
+        ErrorCode::ServerBusy => "服务繁忙，请稍后再试",
+        ErrorCode::WebhookNotFound => "Webhook 不存在",
+        ErrorCode::ApiKeyNotFound => "API Key 不存在",
+        ErrorCode::ModerationNotFound => "举报不存在或已处理",
+        ErrorCode::WxAppNotFound => "公众号不存在",
+        ErrorCode::Database | ErrorCode::Cache | ErrorCode::Internal => "服务内部错误",
+    }
+}
+
+/// 匹配含 `{}` 占位符的原文，返回各个参数
+fn match_template<'t>(template: &str, text: &'t str) -> Option<Vec<&'t str>> {
+    let mut pieces = template.split("{}");
+    let first = pieces.next()?;
+    let mut rest = text.strip_prefix(first)?;
+    let mut pieces = pieces.peekable();
+    let mut args = Vec::new();
+    while let Some(piece) = pieces.next() {
+        if pieces.peek().is_none() {
+            args.push(rest.strip_suffix(piece)?);
+            return Some(args);
+        }
+        let end = rest.find(piece)?;
+        args.push(&rest[..end]);
+        rest = &rest[end + piece.len()..];
+    }
+    None
+}
+
+/// 翻译表，分为完整匹配的文本和含 `{}` 占位符的模板
+struct Catalog {
+    exact: HashMap<&'static str, &'static [&'static str]>,
+    templates: Vec<(&'static str, &'static [&'static str])>,
+}
+
+fn catalog() -> &'static Catalog {
+    static CATALOG: OnceLock<Catalog> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let (templates, exact) = MESSAGES
+            .iter()
+            .map(|(text, translations)| (*text, *translations))
+            .partition::<Vec<_>, _>(|(text, _)| text.contains("{}"));
+        Catalog {
+            exact: exact.into_iter().collect(),
+            templates,
+        }
+    })
+}
+
+/// 中文原文和各语言的译文，译文的顺序与 [`Locale`] 中除中文外的语言相同
+const MESSAGES: &[(&str, &[&str])] = &[
+    // 通用
+    ("未知错误", &["Unknown error"]),
+    ("没有权限", &["Permission denied"]),
+    ("请求参数错误", &["Invalid parameters"]),
+    ("请求体过大", &["Request body too large"]),
+    ("请求超时", &["Request timeout"]),
+    ("连接数已满", &["Too many connections"]),
+    (
+        "服务繁忙，请稍后再试",
+        &["Server busy, please try again later"],
+    ),
+    ("服务内部错误", &["Internal server error"]),
+    ("请求格式错误: {}", &["Invalid request format: {}"]),
+    ("缺少请求数据", &["Missing request data"]),
+    ("确认的推送序号无效", &["Invalid acknowledged sequence"]),
+    ("补发的推送序号无效", &["Invalid resend sequence"]),
+    ("连接不存在", &["Connection not found"]),
+    (
+        "上传链接无效或已过期",
+        &["Upload URL is invalid or expired"],
+    ),
+    ("链接不存在或已过期", &["Link not found or expired"]),
+    ("图片文件过大", &["Image file too large"]),
+    ("不支持的图片格式", &["Unsupported image format"]),
+    ("不支持的文件类型", &["Unsupported file type"]),
+    // 认证、用户
+    (
+        "登录已失效，请重新登录",
+        &["Login expired, please login again"],
+    ),
+    ("请先登录", &["Please login first"]),
+    ("获取登录二维码失败", &["Failed to get login QR code"]),
+    ("邮箱或密码错误", &["Incorrect email or password"]),
+    ("邮箱已注册", &["Email already registered"]),
+    (
+        "登录失败次数过多，请稍后再试",
+        &["Too many failed attempts, please try again later"],
+    ),
+    ("请输入验证码", &["Please enter the captcha"]),
+    ("验证码错误", &["Incorrect captcha"]),
+    ("用户已被拉黑", &["User has been blocked"]),
+    ("用户已注销", &["User has been deactivated"]),
+    ("用户不存在", &["User not found"]),
+    ("用户不存在或已注销", &["User not found or deactivated"]),
+    ("用户不存在或未注销", &["User not found or not deactivated"]),
+    ("名字已被抢占", &["Name already taken"]),
+    ("改名卡不足", &["Not enough rename cards"]),
+    ("头像路径无效", &["Invalid avatar path"]),
+    ("设备不存在", &["Device not found"]),
+    ("物品不存在", &["Item not found"]),
+    ("表情数量已达上限", &["Too many emojis"]),
+    (
+        "最多只能添加{}个表情哦~~",
+        &["You can add at most {} emojis"],
+    ),
+    ("当前表情已存在哦~~", &["Emoji already exists"]),
+    ("表情不存在", &["Emoji not found"]),
+    ("不能拉黑自己", &["You cannot block yourself"]),
+    ("不能注销自己", &["You cannot deactivate yourself"]),
+    // 聊天、房间
+    ("房间不存在", &["Room not found"]),
+    ("房间 ID 无效", &["Invalid room ID"]),
+    ("群聊不存在", &["Group not found"]),
+    ("群聊不存在或未解散", &["Group not found or not disbanded"]),
+    ("您不是该房间的成员", &["You are not a member of this room"]),
+    ("您不是该群的成员", &["You are not a member of this group"]),
+    (
+        "该用户不是群成员",
+        &["The user is not a member of this group"],
+    ),
+    (
+        "您没有管理该群的权限",
+        &["You are not allowed to manage this group"],
+    ),
+    ("您已被禁言", &["You have been muted"]),
+    (
+        "您已被禁言，{} 秒后解除",
+        &["You have been muted for {} more seconds"],
+    ),
+    ("不能禁言自己", &["You cannot mute yourself"]),
+    ("不能禁言群主", &["You cannot mute the group owner"]),
+    (
+        "只有群主可以转让群聊",
+        &["Only the group owner can transfer the group"],
+    ),
+    (
+        "不能转让给自己",
+        &["You cannot transfer the group to yourself"],
+    ),
+    (
+        "只有群主可以解散群聊",
+        &["Only the group owner can disband the group"],
+    ),
+    (
+        "群主需要先转让群聊",
+        &["The group owner must transfer the group first"],
+    ),
+    ("邀请无效或已过期", &["Invitation is invalid or expired"]),
+    ("群成员已满", &["The group is full"]),
+    ("消息正在发送", &["The message is being sent"]),
+    ("消息不存在", &["Message not found"]),
+    ("消息不存在或已删除", &["Message not found or deleted"]),
+    ("消息不存在或未删除", &["Message not found or not deleted"]),
+    ("标记类型错误", &["Invalid mark type"]),
+    (
+        "不能举报自己的消息",
+        &["You cannot report your own message"],
+    ),
+    (
+        "您已经举报过该消息",
+        &["You have already reported this message"],
+    ),
+    (
+        "只能查看自己发送的消息的已读信息",
+        &["You can only view read receipts of your own messages"],
+    ),
+    ("处理状态错误", &["Invalid moderation status"]),
+    (
+        "举报不存在或已处理",
+        &["Report not found or already handled"],
+    ),
+    (
+        "查询范围需在{}天以内",
+        &["The query range must be within {} days"],
+    ),
+    ("重复发送相同内容", &["Sending the same content repeatedly"]),
+    ("发送消息过于频繁", &["Sending messages too frequently"]),
+    ("消息中的链接过多", &["Too many links in the message"]),
+    ("{}，已被禁言", &["{}, you have been muted"]),
+    (
+        "{}，请勿刷屏，继续违规将被禁言",
+        &["{}, please stop spamming or you will be muted"],
+    ),
+    // 消息摘要
+    ("[消息已撤回]", &["[Message recalled]"]),
+    ("[图片]", &["[Image]"]),
+    ("[文件]", &["[File]"]),
+    ("[文件] {}", &["[File] {}"]),
+    ("[语音]", &["[Voice]"]),
+    ("[语音] {}s", &["[Voice] {}s"]),
+    ("[视频]", &["[Video]"]),
+    ("[表情]", &["[Emoji]"]),
+    // 好友
+    (
+        "不能添加自己为好友",
+        &["You cannot add yourself as a friend"],
+    ),
+    ("你们已经是好友了", &["You are already friends"]),
+    ("你们不是好友", &["You are not friends"]),
+    ("申请不存在", &["Friend request not found"]),
+    (
+        "已审批过该申请",
+        &["The friend request has already been handled"],
+    ),
+    // 微信、推送
+    ("公众号不存在", &["Official account not found"]),
+    ("图文素材没有文章", &["The news material has no articles"]),
+    (
+        "获取图文素材失败：{}",
+        &["Failed to get the news material: {}"],
+    ),
+    (
+        "请点击链接授权：<a href=\"{}\">登录</a>",
+        &["Please click the link to authorize: <a href=\"{}\">Login</a>"],
+    ),
+    (
+        "恭喜你成为抹茶聊天第{}位用户，获得{}枚专属徽章",
+        &["Congratulations on becoming user No.{} of MallChat, you got {} exclusive badges"],
+    ),
+    (
+        "登录成功，请返回聊天页面",
+        &["Login succeeded, please return to the chat page"],
+    ),
+    ("有人在群聊中@了你", &["Someone mentioned you in a group"]),
+    ("你收到了一条私聊消息", &["You received a private message"]),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::api::ErrorCode;
+    use crate::handler::context::RequestContext;
+    use crate::i18n::{error_message, tr, tr_in, Locale};
+
+    #[test]
+    fn accept_language() {
+        assert_eq!(
+            Locale::from_accept_language("zh-CN,zh;q=0.9"),
+            Some(Locale::Zh)
+        );
+        assert_eq!(
+            Locale::from_accept_language("fr-FR, en-US;q=0.8, zh;q=0.5"),
+            Some(Locale::En)
+        );
+        assert_eq!(
+            Locale::from_accept_language("en;q=0.5, zh-Hans;q=0.7"),
+            Some(Locale::Zh)
+        );
+        assert_eq!(Locale::from_accept_language("en;q=0, ja"), None);
+        assert_eq!(Locale::from_accept_language("*"), None);
+        assert_eq!(Locale::from_accept_language(""), None);
+    }
+
+    #[test]
+    fn translate() {
+        assert_eq!(tr_in(Locale::Zh, "房间不存在"), "房间不存在");
+        assert_eq!(tr_in(Locale::En, "房间不存在"), "Room not found");
+        assert_eq!(tr_in(Locale::En, "没有译文"), "没有译文");
+        assert_eq!(
+            tr_in(Locale::En, "您已被禁言，42 秒后解除"),
+            "You have been muted for 42 more seconds"
+        );
+        assert_eq!(
+            tr_in(Locale::En, "恭喜你成为抹茶聊天第7位用户，获得2枚专属徽章"),
+            "Congratulations on becoming user No.7 of MallChat, you got 2 exclusive badges"
+        );
+        // 参数也会翻译
+        assert_eq!(
+            tr_in(Locale::En, "发送消息过于频繁，已被禁言"),
+            "Sending messages too frequently, you have been muted"
+        );
+        assert_eq!(tr_in(Locale::En, "[文件] 抹茶.txt"), "[File] 抹茶.txt");
+    }
+
+    #[tokio::test]
+    async fn current() {
+        assert_eq!(tr("房间不存在"), "房间不存在");
+        let context = RequestContext::generate();
+        context.set_locale(Locale::En);
+        context
+            .scope(async {
+                assert_eq!(tr("房间不存在"), "Room not found");
+                assert_eq!(
+                    error_message(ErrorCode::RoomNotFound, "房间不存在"),
+                    "Room not found"
+                );
+                assert_eq!(
+                    error_message(ErrorCode::InvalidParam, "missing field `roomId`"),
+                    "missing field `roomId`"
+                );
+                assert_eq!(
+                    error_message(ErrorCode::GroupFull, "群成员已满（上限 500 人）"),
+                    "The group is full"
+                );
+            })
+            .await;
+    }
+}
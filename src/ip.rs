@@ -0,0 +1,360 @@
+//! # IP 归属地
+//!
+//! 记录用户注册、最近登录的 IP，并使用离线的 [ip2region](https://github.com/lionsoul2014/ip2region)
+//! xdb 数据库解析归属地。解析在后台任务中进行，不阻塞登录、发消息等请求。
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::cache::user_info::UserInfoCache;
+use crate::cache::Cache;
+use crate::storage::repo::DynUserRepo;
+
+/// IP 归属地配置
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IpConfig {
+    /// ip2region xdb 文件路径，不配置时只记录 IP，不解析归属地
+    #[serde(default)]
+    pub ip2region_path: Option<PathBuf>,
+}
+
+impl IpConfig {
+    /// 加载 ip2region 数据库
+    pub fn load(&self) -> anyhow::Result<Option<Ip2Region>> {
+        self.ip2region_path
+            .as_ref()
+            .map(Ip2Region::from_file)
+            .transpose()
+    }
+}
+
+/// IP 详情
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IpDetail {
+    /// IP
+    pub ip: String,
+    /// 国家
+    pub country: Option<String>,
+    /// 省份
+    pub region: Option<String>,
+    /// 城市
+    pub city: Option<String>,
+    /// 运营商
+    pub isp: Option<String>,
+}
+
+impl IpDetail {
+    /// 解析 ip2region 的结果，格式为 `国家|区域|省份|城市|运营商`，未知的字段为 `0`
+    pub fn parse(ip: IpAddr, region: &str) -> Self {
+        let mut fields = region
+            .split('|')
+            .map(|field| (!field.is_empty() && field != "0").then(|| field.to_string()));
+        let country = fields.next().flatten();
+        let _area = fields.next();
+        Self {
+            ip: ip.to_string(),
+            country,
+            region: fields.next().flatten(),
+            city: fields.next().flatten(),
+            isp: fields.next().flatten(),
+        }
+    }
+
+    /// 展示给其他用户的归属地：国内为省份，国外为国家
+    pub fn display(&self) -> Option<&str> {
+        match self.country.as_deref() {
+            Some("中国") => self.region.as_deref().or(Some("中国")),
+            country => country,
+        }
+    }
+}
+
+/// 用户的 IP 信息，保存在 `user` 表的 `ip_info` 字段中
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpInfo {
+    /// 注册时的 IP
+    pub create_ip: Option<String>,
+    /// 注册时的 IP 详情
+    pub create_ip_detail: Option<IpDetail>,
+    /// 最近登录的 IP
+    pub update_ip: Option<String>,
+    /// 最近登录的 IP 详情
+    pub update_ip_detail: Option<IpDetail>,
+}
+
+impl IpInfo {
+    /// 从 `user.ip_info` 解析，格式不正确时视为空
+    pub fn from_json(json: Option<&serde_json::Value>) -> Self {
+        json.and_then(|json| serde_json::from_value(json.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// 最近登录的归属地
+    pub fn region(&self) -> Option<String> {
+        self.update_ip_detail
+            .as_ref()
+            .and_then(IpDetail::display)
+            .map(str::to_string)
+    }
+
+    /// 记录新的 IP 及其详情，首次记录时同时作为注册 IP，IP 未变化时返回 `false`
+    ///
+    /// 与 [`UserRepo::update_ip`](crate::storage::repo::UserRepo::update_ip) 的条件更新相同
+    pub fn refresh(&mut self, detail: IpDetail) -> bool {
+        if self.update_ip.as_deref() == Some(&detail.ip) {
+            return false;
+        }
+        if self.create_ip.is_none() {
+            self.create_ip = Some(detail.ip.clone());
+            self.create_ip_detail = Some(detail.clone());
+        }
+        self.update_ip = Some(detail.ip.clone());
+        self.update_ip_detail = Some(detail);
+        true
+    }
+}
+
+/// ip2region xdb 格式的离线 IP 数据库，只支持 IPv4
+///
+/// 文件整体加载到内存中，查询不需要读文件
+#[derive(Debug, Clone)]
+pub struct Ip2Region {
+    buffer: Arc<Vec<u8>>,
+}
+
+impl Ip2Region {
+    const HEADER_LEN: usize = 256;
+    const VECTOR_INDEX_COLS: usize = 256;
+    const VECTOR_INDEX_SIZE: usize = 8;
+    const SEGMENT_INDEX_SIZE: usize = 14;
+
+    /// 从文件加载
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Self::new(std::fs::read(path)?)
+    }
+
+    /// 使用 xdb 文件内容构造
+    pub fn new(buffer: Vec<u8>) -> anyhow::Result<Self> {
+        let min_len = Self::HEADER_LEN
+            + Self::VECTOR_INDEX_COLS * Self::VECTOR_INDEX_COLS * Self::VECTOR_INDEX_SIZE;
+        anyhow::ensure!(buffer.len() >= min_len, "invalid ip2region xdb file");
+        Ok(Self {
+            buffer: Arc::new(buffer),
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.buffer.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.buffer.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// 查询 IP 的归属地，格式为 `国家|区域|省份|城市|运营商`
+    pub fn search(&self, ip: IpAddr) -> Option<String> {
+        let IpAddr::V4(ip) = ip else {
+            return None;
+        };
+        let octets = ip.octets();
+        let ip = u32::from(ip);
+        let index = Self::HEADER_LEN
+            + (octets[0] as usize * Self::VECTOR_INDEX_COLS + octets[1] as usize)
+                * Self::VECTOR_INDEX_SIZE;
+        let start = self.u32_at(index)? as usize;
+        let end = self.u32_at(index + 4)? as usize;
+        if end < start {
+            return None;
+        }
+
+        let (mut low, mut high) = (0, (end - start) / Self::SEGMENT_INDEX_SIZE);
+        while low <= high {
+            let middle = (low + high) / 2;
+            let offset = start + middle * Self::SEGMENT_INDEX_SIZE;
+            let start_ip = self.u32_at(offset)?;
+            let end_ip = self.u32_at(offset + 4)?;
+            if ip < start_ip {
+                if middle == 0 {
+                    return None;
+                }
+                high = middle - 1;
+            } else if ip > end_ip {
+                low = middle + 1;
+            } else {
+                let len = self.u16_at(offset + 8)? as usize;
+                let ptr = self.u32_at(offset + 10)? as usize;
+                let data = self.buffer.get(ptr..ptr + len)?;
+                return String::from_utf8(data.to_vec()).ok();
+            }
+        }
+        None
+    }
+}
+
+/// 记录用户 IP 并在后台解析归属地
+#[derive(Clone)]
+pub struct IpTracker {
+    users: DynUserRepo,
+    searcher: Option<Ip2Region>,
+    cache: Option<Cache>,
+    /// 本进程最近记录的 IP，相同时不再访问数据库
+    recorded: Arc<DashMap<i64, IpAddr>>,
+}
+
+impl IpTracker {
+    /// 创建，`searcher` 为空时只记录 IP
+    pub fn new(users: DynUserRepo, searcher: Option<Ip2Region>) -> Self {
+        Self {
+            users,
+            searcher,
+            cache: None,
+            recorded: Arc::default(),
+        }
+    }
+
+    /// 归属地变化时更新用户信息缓存的版本号，见 [`UserInfoCache::bump`]
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// 解析 IP 详情
+    pub fn resolve(&self, ip: IpAddr) -> IpDetail {
+        let region = self
+            .searcher
+            .as_ref()
+            .and_then(|searcher| searcher.search(ip));
+        match region {
+            Some(region) => IpDetail::parse(ip, &region),
+            None => IpDetail {
+                ip: ip.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// 记录用户的 IP，只在 IP 变化时更新，返回是否更新
+    pub async fn refresh(&self, uid: i64, ip: IpAddr) -> anyhow::Result<bool> {
+        let updated = self.users.update_ip(uid, &self.resolve(ip)).await?;
+        if let (true, Some(cache)) = (updated, &self.cache) {
+            UserInfoCache::new(cache, self.users.as_ref())
+                .bump(uid)
+                .await?;
+        }
+        Ok(updated)
+    }
+
+    /// 在后台记录用户的 IP，与本进程上次记录的 IP 相同时跳过
+    pub fn record(&self, uid: i64, ip: IpAddr) {
+        if self.recorded.insert(uid, ip) == Some(ip) {
+            return;
+        }
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = tracker.refresh(uid, ip).await {
+                tracing::warn!(%uid, %ip, %error, "Failed to refresh user ip info.");
+                tracker
+                    .recorded
+                    .remove_if(&uid, |_, recorded| *recorded == ip);
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for IpTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IpTracker")
+            .field("searcher", &self.searcher.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    use crate::ip::{Ip2Region, IpDetail, IpInfo, IpTracker};
+    use crate::storage::repo::UserRepo;
+    use crate::testing::MemoryRepo;
+
+    /// 构造只包含一个 IP 段的 xdb 数据
+    fn xdb(start: Ipv4Addr, end: Ipv4Addr, region: &str) -> Vec<u8> {
+        let header = Ip2Region::HEADER_LEN;
+        let vector = 256 * 256 * Ip2Region::VECTOR_INDEX_SIZE;
+        let data_ptr = header + vector;
+        let segment_ptr = data_ptr + region.len();
+
+        let mut buffer = vec![0; segment_ptr + Ip2Region::SEGMENT_INDEX_SIZE];
+        let octets = start.octets();
+        let index = header + (octets[0] as usize * 256 + octets[1] as usize) * 8;
+        buffer[index..index + 4].copy_from_slice(&(segment_ptr as u32).to_le_bytes());
+        buffer[index + 4..index + 8].copy_from_slice(&(segment_ptr as u32).to_le_bytes());
+        buffer[data_ptr..segment_ptr].copy_from_slice(region.as_bytes());
+        let segment = &mut buffer[segment_ptr..];
+        segment[0..4].copy_from_slice(&u32::from(start).to_le_bytes());
+        segment[4..8].copy_from_slice(&u32::from(end).to_le_bytes());
+        segment[8..10].copy_from_slice(&(region.len() as u16).to_le_bytes());
+        segment[10..14].copy_from_slice(&(data_ptr as u32).to_le_bytes());
+        buffer
+    }
+
+    #[test]
+    fn ip2region() -> anyhow::Result<()> {
+        let searcher = Ip2Region::new(xdb(
+            Ipv4Addr::new(1, 2, 0, 0),
+            Ipv4Addr::new(1, 2, 255, 255),
+            "中国|0|广东省|深圳市|电信",
+        ))?;
+        let ip = IpAddr::from([1, 2, 3, 4]);
+        let region = searcher.search(ip);
+        assert_eq!(region.as_deref(), Some("中国|0|广东省|深圳市|电信"));
+        assert_eq!(searcher.search(IpAddr::from([1, 3, 0, 1])), None);
+
+        let detail = IpDetail::parse(ip, region.as_deref().unwrap_or_default());
+        assert_eq!(detail.region.as_deref(), Some("广东省"));
+        assert_eq!(detail.display(), Some("广东省"));
+        assert!(Ip2Region::new(Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn refresh() {
+        let detail = |ip: [u8; 4]| IpDetail {
+            ip: IpAddr::from(ip).to_string(),
+            ..Default::default()
+        };
+        let mut ip_info = IpInfo::default();
+        assert!(ip_info.refresh(detail([1, 1, 1, 1])));
+        assert!(!ip_info.refresh(detail([1, 1, 1, 1])));
+        assert!(ip_info.refresh(detail([2, 2, 2, 2])));
+        assert_eq!(ip_info.create_ip.as_deref(), Some("1.1.1.1"));
+        assert_eq!(ip_info.create_ip_detail, Some(detail([1, 1, 1, 1])));
+        assert_eq!(ip_info.update_ip.as_deref(), Some("2.2.2.2"));
+    }
+
+    #[tokio::test]
+    async fn tracker() -> anyhow::Result<()> {
+        let users = Arc::new(MemoryRepo::default());
+        let uid = users.add_user("open_id", None);
+        let tracker = IpTracker::new(users.clone(), None);
+        assert!(tracker.refresh(uid, IpAddr::from([1, 1, 1, 1])).await?);
+        assert!(!tracker.refresh(uid, IpAddr::from([1, 1, 1, 1])).await?);
+        assert!(tracker.refresh(uid, IpAddr::from([2, 2, 2, 2])).await?);
+
+        let user = users.find_by_id(uid).await?;
+        let ip_info = IpInfo::from_json(user.and_then(|user| user.ip_info).as_ref());
+        assert_eq!(ip_info.create_ip.as_deref(), Some("1.1.1.1"));
+        assert_eq!(ip_info.update_ip.as_deref(), Some("2.2.2.2"));
+        Ok(())
+    }
+}
@@ -0,0 +1,524 @@
+//! # 定时任务
+//!
+//! 进程内的轻量 cron 调度器：每个任务在独立的 tokio 任务中按 cron 表达式执行。
+//! 多实例部署时，需要全局只执行一次的任务在每个触发时刻通过 Redis `SET NX` 抢占锁，
+//! 只作用于本实例状态的任务（如清理本实例的 WebSocket 连接）不加锁。
+
+pub mod archive;
+pub mod hot_room;
+pub mod member_count;
+pub mod presence;
+pub mod session;
+pub mod stats;
+pub mod wx;
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, UtcOffset};
+use tokio::task::JoinHandle;
+
+use crate::cache::Cache;
+
+/// 定时任务配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobsConfig {
+    /// 是否启用定时任务
+    #[serde(default = "default::enabled")]
+    pub enabled: bool,
+    /// 分布式锁的过期时间（秒），需要大于各实例间的时钟偏差
+    #[serde(default = "default::lock_ttl_secs")]
+    pub lock_ttl_secs: u64,
+    /// 热门房间分数衰减
+    #[serde(default = "default::hot_room_decay")]
+    pub hot_room_decay: Cron,
+    /// 每次衰减保留的分数比例
+    #[serde(default = "default::hot_room_decay_factor")]
+    pub hot_room_decay_factor: f64,
+    /// 清理长时间没有心跳的 WebSocket 连接
+    #[serde(default = "default::session_cleanup")]
+    pub session_cleanup: Cron,
+    /// 没有心跳超过该时间（秒）的连接会被断开
+    #[serde(default = "default::session_idle_secs")]
+    pub session_idle_secs: u64,
+    /// 推送在线、离开、离线状态的变化
+    #[serde(default = "default::presence_refresh")]
+    pub presence_refresh: Cron,
+    /// 统计前一天的新增用户、活跃用户、消息数和在线人数峰值
+    #[serde(default = "default::daily_statistics", alias = "daily_active_users")]
+    pub daily_statistics: Cron,
+    /// 采样在线人数，用于统计在线人数峰值
+    #[serde(default = "default::online_sampling")]
+    pub online_sampling: Cron,
+    /// 提前刷新微信 access_token
+    #[serde(default = "default::access_token_refresh")]
+    pub access_token_refresh: Cron,
+    /// 在 access_token 过期前该时间（秒）内刷新
+    #[serde(default = "default::access_token_refresh_ahead_secs")]
+    pub access_token_refresh_ahead_secs: u64,
+    /// 修正群聊冗余的成员数
+    #[serde(default = "default::member_count_reconcile")]
+    pub member_count_reconcile: Cron,
+    /// 归档超过保留期的消息，需要启用 `retention.enabled`
+    #[serde(default = "default::message_archive")]
+    pub message_archive: Cron,
+}
+
+mod default {
+    use crate::jobs::Cron;
+
+    pub fn enabled() -> bool {
+        true
+    }
+
+    pub fn lock_ttl_secs() -> u64 {
+        600
+    }
+
+    pub fn hot_room_decay() -> Cron {
+        Cron::every_minutes(10)
+    }
+
+    pub fn hot_room_decay_factor() -> f64 {
+        0.9
+    }
+
+    pub fn session_cleanup() -> Cron {
+        Cron::every_minutes(1)
+    }
+
+    pub fn session_idle_secs() -> u64 {
+        120
+    }
+
+    pub fn presence_refresh() -> Cron {
+        Cron::every_minutes(1)
+    }
+
+    pub fn daily_statistics() -> Cron {
+        "5 0 * * *".parse().expect("valid cron expression")
+    }
+
+    pub fn online_sampling() -> Cron {
+        Cron::every_minutes(1)
+    }
+
+    pub fn access_token_refresh() -> Cron {
+        Cron::every_minutes(5)
+    }
+
+    pub fn access_token_refresh_ahead_secs() -> u64 {
+        600
+    }
+
+    pub fn member_count_reconcile() -> Cron {
+        "30 3 * * *".parse().expect("valid cron expression")
+    }
+
+    pub fn message_archive() -> Cron {
+        "0 4 * * *".parse().expect("valid cron expression")
+    }
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default::enabled(),
+            lock_ttl_secs: default::lock_ttl_secs(),
+            hot_room_decay: default::hot_room_decay(),
+            hot_room_decay_factor: default::hot_room_decay_factor(),
+            session_cleanup: default::session_cleanup(),
+            session_idle_secs: default::session_idle_secs(),
+            presence_refresh: default::presence_refresh(),
+            daily_statistics: default::daily_statistics(),
+            online_sampling: default::online_sampling(),
+            access_token_refresh: default::access_token_refresh(),
+            access_token_refresh_ahead_secs: default::access_token_refresh_ahead_secs(),
+            member_count_reconcile: default::member_count_reconcile(),
+            message_archive: default::message_archive(),
+        }
+    }
+}
+
+/// cron 表达式错误
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid cron expression `{0}`")]
+pub struct CronError(String);
+
+/// cron 表达式：`分 时 日 月 周`
+///
+/// 每个字段支持 `*`、`*/n`、`a`、`a-b`、`a-b/n` 以及逗号分隔的列表，周日为 0 或 7。
+/// 与标准 cron 相同，日和周都不是 `*` 时满足其一即可
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// 向后查找的最长时间，超过后认为表达式不会触发（如 2 月 30 日）
+    const MAX_LOOKAHEAD_MINUTES: i64 = 366 * 24 * 60;
+
+    /// 每 `n` 分钟执行一次
+    pub fn every_minutes(n: u8) -> Self {
+        format!("*/{} * * * *", n.max(1))
+            .parse()
+            .expect("valid cron expression")
+    }
+
+    fn parse_field(field: &str, min: u8, max: u8) -> Option<u64> {
+        let mut mask = 0;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u8>().ok().filter(|s| *s > 0)?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                    None => {
+                        let value = range.parse().ok()?;
+                        // `a/n` 表示从 a 开始到最大值
+                        (value, if part.contains('/') { max } else { value })
+                    }
+                },
+            };
+            if start < min || end > max || start > end {
+                return None;
+            }
+            for value in (start..=end).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+        Some(mask)
+    }
+
+    fn matches(&self, time: OffsetDateTime) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().number_days_from_sunday()) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+            && self.months & (1 << time.month() as u8) != 0
+            && day_matches
+    }
+
+    /// `time` 之后（不含）的下一个触发时刻，精确到分钟
+    pub fn next_after(&self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        let mut next = time.replace_second(0).ok()?.replace_nanosecond(0).ok()?;
+        for _ in 0..Self::MAX_LOOKAHEAD_MINUTES {
+            next += time::Duration::MINUTE;
+            if self.matches(next) {
+                return Some(next);
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = CronError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let error = || CronError(source.to_string());
+        let fields: Vec<_> = source.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(error());
+        };
+        let mut weekday_mask = Self::parse_field(weekdays, 0, 7).ok_or_else(error)?;
+        // 7 也表示周日
+        if weekday_mask & (1 << 7) != 0 {
+            weekday_mask = (weekday_mask & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            source: source.to_string(),
+            minutes: Self::parse_field(minutes, 0, 59).ok_or_else(error)?,
+            hours: Self::parse_field(hours, 0, 23).ok_or_else(error)?,
+            days: Self::parse_field(days, 1, 31).ok_or_else(error)?,
+            months: Self::parse_field(months, 1, 12).ok_or_else(error)?,
+            weekdays: weekday_mask,
+            any_day: *days == "*",
+            any_weekday: *weekdays == "*",
+        })
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = CronError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        source.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.source
+    }
+}
+
+impl Display for Cron {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// 定时任务
+#[async_trait]
+pub trait Job: Send + Sync + 'static {
+    /// 任务名，同时用作分布式锁的键
+    fn name(&self) -> &str;
+    /// 多实例部署时是否只在一个实例上执行，默认为 `true`
+    fn exclusive(&self) -> bool {
+        true
+    }
+    /// 执行任务
+    async fn run(&self) -> anyhow::Result<()>;
+}
+
+/// 定时任务的分布式锁
+///
+/// 锁的键包含触发时刻，每个时刻只有一个实例能获得锁，锁不主动释放，过期后自动删除
+#[derive(Debug, Clone)]
+pub struct JobLock {
+    cache: Option<Cache>,
+    ttl_secs: u64,
+}
+
+impl JobLock {
+    /// 创建，未配置 Redis 时视为单实例部署，总能获得锁
+    pub fn new(cache: Option<Cache>, ttl_secs: u64) -> Self {
+        Self { cache, ttl_secs }
+    }
+
+    fn key(name: &str, tick: i64) -> String {
+        format!("mallchat:job:{name}:{tick}")
+    }
+
+    /// 抢占任务在 `tick` 时刻的执行权
+    ///
+    /// Redis 不可用时放弃本次执行，避免多个实例重复执行
+    pub async fn acquire(&self, name: &str, tick: i64) -> bool {
+        let Some(cache) = &self.cache else {
+            return true;
+        };
+        let key = Self::key(name, tick);
+        let result: redis::RedisResult<Option<String>> = async {
+            let mut connection = cache.connection().await?;
+            redis::cmd("SET")
+                .arg(&key)
+                .arg(std::process::id())
+                .arg("NX")
+                .arg("EX")
+                .arg(self.ttl_secs.max(1))
+                .query_async(&mut connection)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(set) => set.is_some(),
+            Err(error) => {
+                tracing::error!(%error, %key, "Failed to acquire job lock.");
+                false
+            }
+        }
+    }
+}
+
+/// 定时任务调度器，注册任务后调用 [`Scheduler::start`] 启动
+pub struct Scheduler {
+    offset: UtcOffset,
+    lock: JobLock,
+    jobs: Vec<(Cron, Arc<dyn Job>)>,
+}
+
+impl Scheduler {
+    /// 创建，cron 表达式按 `offset` 时区解析
+    pub fn new(offset: UtcOffset, lock: JobLock) -> Self {
+        Self {
+            offset,
+            lock,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// 注册任务
+    pub fn register(mut self, cron: Cron, job: impl Job) -> Self {
+        self.jobs.push((cron, Arc::new(job)));
+        self
+    }
+
+    /// 已注册的任务数
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// 是否没有注册任务
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// 启动所有任务
+    pub fn start(self) -> SchedulerHandle {
+        let tasks = self
+            .jobs
+            .into_iter()
+            .map(|(cron, job)| {
+                tokio::spawn(Self::run_job(self.offset, self.lock.clone(), cron, job))
+            })
+            .collect();
+        SchedulerHandle { tasks }
+    }
+
+    async fn run_job(offset: UtcOffset, lock: JobLock, cron: Cron, job: Arc<dyn Job>) {
+        let name = job.name().to_string();
+        tracing::info!(job = %name, %cron, "Job scheduled.");
+        loop {
+            let now = OffsetDateTime::now_utc().to_offset(offset);
+            let Some(next) = cron.next_after(now) else {
+                tracing::warn!(job = %name, %cron, "Job will never run again.");
+                return;
+            };
+            let wait = std::time::Duration::try_from(next - now).unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            if job.exclusive() && !lock.acquire(&name, next.unix_timestamp()).await {
+                tracing::debug!(job = %name, "Job is running on another instance.");
+                continue;
+            }
+            let start = std::time::Instant::now();
+            match job.run().await {
+                Ok(()) => tracing::info!(job = %name, elapsed = ?start.elapsed(), "Job finished."),
+                Err(error) => tracing::error!(job = %name, %error, "Job failed."),
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let jobs: Vec<_> = self
+            .jobs
+            .iter()
+            .map(|(cron, job)| format!("{}: {cron}", job.name()))
+            .collect();
+        f.debug_struct("Scheduler")
+            .field("offset", &self.offset)
+            .field("lock", &self.lock)
+            .field("jobs", &jobs)
+            .finish()
+    }
+}
+
+/// 运行中的调度器
+#[derive(Debug)]
+pub struct SchedulerHandle {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SchedulerHandle {
+    /// 停止所有任务，正在执行的任务会被取消
+    pub fn shutdown(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+    use crate::jobs::{Cron, JobsConfig};
+
+    fn at(day: u8, hour: u8, minute: u8) -> anyhow::Result<OffsetDateTime> {
+        // 2023-06-01 是周四
+        let date = Date::from_calendar_date(2023, Month::June, day)?;
+        let time = Time::from_hms(hour, minute, 30)?;
+        Ok(PrimitiveDateTime::new(date, time).assume_utc())
+    }
+
+    #[test]
+    fn cron() -> anyhow::Result<()> {
+        let cron: Cron = "*/15 * * * *".parse()?;
+        assert_eq!(
+            cron.next_after(at(1, 10, 0)?),
+            Some(at(1, 10, 15)?.replace_second(0)?)
+        );
+        assert_eq!(
+            cron.next_after(at(1, 10, 59)?),
+            Some(at(1, 11, 0)?.replace_second(0)?)
+        );
+
+        let cron: Cron = "5 0 * * *".parse()?;
+        assert_eq!(
+            cron.next_after(at(1, 10, 0)?),
+            Some(at(2, 0, 5)?.replace_second(0)?)
+        );
+
+        // 工作日 9 点，6 月 3 日为周六
+        let cron: Cron = "0 9 * * 1-5".parse()?;
+        assert_eq!(
+            cron.next_after(at(2, 10, 0)?),
+            Some(at(5, 9, 0)?.replace_second(0)?)
+        );
+
+        // 周日可以写作 7
+        let cron: Cron = "0 0 * * 7".parse()?;
+        assert_eq!(
+            cron.next_after(at(1, 0, 0)?),
+            Some(at(4, 0, 0)?.replace_second(0)?)
+        );
+
+        // 日和周都指定时满足其一即可
+        let cron: Cron = "0 0 10 * 5".parse()?;
+        assert_eq!(
+            cron.next_after(at(1, 0, 0)?),
+            Some(at(2, 0, 0)?.replace_second(0)?)
+        );
+
+        assert_eq!(Cron::every_minutes(10).to_string(), "*/10 * * * *");
+        assert!("0 0 30 2 *"
+            .parse::<Cron>()?
+            .next_after(at(1, 0, 0)?)
+            .is_none());
+        for invalid in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(invalid.parse::<Cron>().is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn config() -> anyhow::Result<()> {
+        let config: JobsConfig = serde_json::from_str(r#"{"session_cleanup":"*/2 * * * *"}"#)?;
+        assert_eq!(config.session_cleanup.to_string(), "*/2 * * * *");
+        assert_eq!(config.hot_room_decay, JobsConfig::default().hot_room_decay);
+        assert!(serde_json::from_str::<JobsConfig>(r#"{"session_cleanup":"bad"}"#).is_err());
+        // 兼容旧的配置项名
+        let config: JobsConfig = serde_json::from_str(r#"{"daily_active_users":"0 1 * * *"}"#)?;
+        assert_eq!(config.daily_statistics.to_string(), "0 1 * * *");
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+//! # 消息归档
+//!
+//! [`MessageArchive`] 定时将超过保留期（默认 90 天）的消息移出 `message` 表，保持热数据表较小：
+//!
+//! - [`ArchiveTarget::Table`]：移动到 `message_archive` 表；
+//! - [`ArchiveTarget::ObjectStore`]：按房间写成 JSONL 文件（每行一条消息）上传到对象存储，
+//!   路径为 `archive/message/{room_id}/{first_id}-{last_id}-{随机数}.jsonl`，记录在 `archive_file` 表中。
+//!
+//! 每个房间可以单独配置保留天数，为 0 时不归档。归档的消息可以通过管理接口
+//! `/capi/admin/archive/message`、`/capi/admin/archive/file` 导出。
+//! 归档文件包含单聊消息，`archive/` 不是上传场景的前缀，不公开访问，只能通过预签名的下载地址获取。
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+use crate::jobs::Job;
+use crate::service::archive::{ArchiveScope, ArchiveService};
+use crate::storage::model::message;
+use crate::storage::oss::DynObjectStore;
+use crate::storage::tx::with_txn;
+use crate::timestamp;
+
+/// 归档文件的内容类型
+pub const JSONL_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// 消息保留配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// 是否启用归档，还需要启用定时任务
+    #[serde(default)]
+    pub enabled: bool,
+    /// 消息在 `message` 表中保留的天数，为 0 时只归档单独配置的房间
+    #[serde(default = "default::hot_days")]
+    pub hot_days: u32,
+    /// 归档的位置
+    #[serde(default)]
+    pub target: ArchiveTarget,
+    /// 每批归档的消息数
+    #[serde(default = "default::batch_size")]
+    pub batch_size: u64,
+    /// 每次执行每个范围最多归档的批数，剩余的消息在下次执行时归档
+    #[serde(default = "default::max_batches")]
+    pub max_batches: u32,
+    /// 单独配置保留天数的房间
+    #[serde(default)]
+    pub rooms: Vec<RoomRetention>,
+}
+
+mod default {
+    pub fn hot_days() -> u32 {
+        90
+    }
+
+    pub fn batch_size() -> u64 {
+        1000
+    }
+
+    pub fn max_batches() -> u32 {
+        100
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hot_days: default::hot_days(),
+            target: ArchiveTarget::default(),
+            batch_size: default::batch_size(),
+            max_batches: default::max_batches(),
+            rooms: Vec::new(),
+        }
+    }
+}
+
+/// 房间的保留天数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomRetention {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 保留天数，为 0 时不归档
+    pub hot_days: u32,
+}
+
+/// 归档的位置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveTarget {
+    /// `message_archive` 表
+    #[default]
+    Table,
+    /// 对象存储中的 JSONL 文件
+    ObjectStore,
+}
+
+impl RetentionConfig {
+    /// 各个归档范围及其截止时间，截止时间之前发送的消息需要归档
+    pub fn scopes(&self, now: PrimitiveDateTime) -> Vec<(ArchiveScope, PrimitiveDateTime)> {
+        let before = |days: u32| now - time::Duration::days(i64::from(days));
+        let mut scopes: Vec<_> = self
+            .rooms
+            .iter()
+            .filter(|room| room.hot_days > 0)
+            .map(|room| (ArchiveScope::Room(room.room_id), before(room.hot_days)))
+            .collect();
+        if self.hot_days > 0 {
+            let except = self.rooms.iter().map(|room| room.room_id).collect();
+            scopes.push((ArchiveScope::Except(except), before(self.hot_days)));
+        }
+        scopes
+    }
+}
+
+/// 归档文件在对象存储中的路径，带随机数避免被猜到
+pub fn object_key(room_id: i64, messages: &[message::Model]) -> String {
+    let first = messages.first().map_or(0, |message| message.id);
+    let last = messages.last().map_or(0, |message| message.id);
+    format!(
+        "archive/message/{room_id}/{first}-{last}-{:016x}.jsonl",
+        rand::random::<u64>()
+    )
+}
+
+/// 编码为 JSONL，每行一条消息
+pub fn to_jsonl(messages: &[message::Model]) -> serde_json::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for message in messages {
+        serde_json::to_writer(&mut data, message)?;
+        data.push(b'\n');
+    }
+    Ok(data)
+}
+
+/// 归档超过保留期的消息
+#[derive(Debug, Clone)]
+pub struct MessageArchive {
+    db: DatabaseConnection,
+    store: DynObjectStore,
+    config: RetentionConfig,
+}
+
+impl MessageArchive {
+    /// 创建
+    pub fn new(db: DatabaseConnection, store: DynObjectStore, config: RetentionConfig) -> Self {
+        Self { db, store, config }
+    }
+
+    /// 归档一批消息
+    async fn archive(&self, messages: Vec<message::Model>) -> anyhow::Result<()> {
+        match self.config.target {
+            ArchiveTarget::Table => {
+                with_txn(&self.db, |txn| {
+                    Box::pin(async move { ArchiveService::new(txn).move_to_table(&messages).await })
+                })
+                .await?;
+            }
+            ArchiveTarget::ObjectStore => {
+                let mut rooms: BTreeMap<i64, Vec<message::Model>> = BTreeMap::new();
+                for message in messages {
+                    rooms.entry(message.room_id).or_default().push(message);
+                }
+                for (room_id, messages) in rooms {
+                    let key = object_key(room_id, &messages);
+                    // 先上传再删除，删除失败时下次重新上传，只会留下多余的文件
+                    self.store
+                        .put(&key, to_jsonl(&messages)?, JSONL_CONTENT_TYPE)
+                        .await?;
+                    with_txn(&self.db, |txn| {
+                        Box::pin(async move {
+                            ArchiveService::new(txn)
+                                .move_to_file(room_id, &key, &messages)
+                                .await
+                        })
+                    })
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 归档一个范围内的消息，返回归档的消息数
+    async fn archive_scope(
+        &self,
+        scope: &ArchiveScope,
+        before: PrimitiveDateTime,
+    ) -> anyhow::Result<usize> {
+        let batch_size = self.config.batch_size.max(1);
+        let mut archived = 0;
+        for _ in 0..self.config.max_batches {
+            let messages = ArchiveService::new(&self.db)
+                .expired(scope, before, batch_size)
+                .await?;
+            let count = messages.len();
+            if count == 0 {
+                break;
+            }
+            self.archive(messages).await?;
+            archived += count;
+            if (count as u64) < batch_size {
+                break;
+            }
+        }
+        Ok(archived)
+    }
+}
+
+#[async_trait]
+impl Job for MessageArchive {
+    fn name(&self) -> &str {
+        "message_archive"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let now = timestamp::now();
+        for (scope, before) in self.config.scopes(now) {
+            let archived = self.archive_scope(&scope, before).await?;
+            tracing::info!(?scope, %before, archived, target = ?self.config.target, "Messages archived.");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::jobs::archive::{object_key, to_jsonl, RetentionConfig, RoomRetention};
+    use crate::service::archive::ArchiveScope;
+    use crate::storage::model::message;
+
+    fn message(id: u64) -> message::Model {
+        message::Model {
+            id,
+            room_id: 1,
+            from_uid: 2,
+            content: format!("消息{id}"),
+            reply_msg_id: None,
+            status: 0,
+            gap_count: None,
+            r#type: Some(1),
+            extra: None,
+            create_time: datetime!(2023-08-01 08:00),
+            update_time: datetime!(2023-08-01 08:00),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        let now = datetime!(2023-08-31 00:00);
+        let config = RetentionConfig {
+            hot_days: 30,
+            rooms: vec![
+                RoomRetention {
+                    room_id: 1,
+                    hot_days: 7,
+                },
+                RoomRetention {
+                    room_id: 2,
+                    hot_days: 0,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.scopes(now),
+            [
+                (ArchiveScope::Room(1), datetime!(2023-08-24 00:00)),
+                (
+                    ArchiveScope::Except(vec![1, 2]),
+                    datetime!(2023-08-01 00:00)
+                ),
+            ]
+        );
+
+        // 全局不归档时只归档单独配置的房间
+        let config = RetentionConfig {
+            hot_days: 0,
+            ..config
+        };
+        assert_eq!(
+            config.scopes(now),
+            [(ArchiveScope::Room(1), datetime!(2023-08-24 00:00))]
+        );
+    }
+
+    #[test]
+    fn jsonl() -> anyhow::Result<()> {
+        let messages = [message(1), message(2)];
+        let data = to_jsonl(&messages)?;
+        let lines: Vec<message::Model> = data
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines, messages);
+
+        let key = object_key(1, &messages);
+        assert!(key.starts_with("archive/message/1/1-2-"), "{key}");
+        assert!(key.ends_with(".jsonl"));
+        assert_ne!(key, object_key(1, &messages));
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+//! # 热门房间
+//!
+//! 定时衰减房间热度分数，分数的维护见 [`HotRoomService`]
+
+use async_trait::async_trait;
+
+use crate::cache::Cache;
+use crate::jobs::Job;
+use crate::service::hot_room::HotRoomService;
+
+/// 热门房间分数衰减
+#[derive(Debug, Clone)]
+pub struct HotRoomDecay {
+    cache: Cache,
+    factor: f64,
+}
+
+impl HotRoomDecay {
+    /// 创建，每次衰减后分数乘以 `factor`
+    pub fn new(cache: Cache, factor: f64) -> Self {
+        Self {
+            cache,
+            factor: factor.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[async_trait]
+impl Job for HotRoomDecay {
+    fn name(&self) -> &str {
+        "hot_room_decay"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        HotRoomService::new(&self.cache).decay(self.factor).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! # 房间成员数校验
+//!
+//! `room.member_count` 在增删成员记录的事务中更新，直接修改数据库、无符号列减到 0 等情况会产生偏差。
+//! [`MemberCountReconcile`] 定时重新统计群聊的成员记录数，修正偏差并记录到 [`MemberCountMetrics`]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::jobs::Job;
+use crate::service::group::{GroupService, MemberCountDrift};
+
+/// 成员数校验指标，计数均为启动以来的累计值
+#[derive(Debug, Clone, Default)]
+pub struct MemberCountMetrics {
+    inner: Arc<Metrics>,
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    runs: AtomicU64,
+    failures: AtomicU64,
+    drifted_rooms: AtomicU64,
+    total_drift: AtomicU64,
+    last_drifted_rooms: AtomicU64,
+}
+
+/// 成员数校验统计
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberCountStats {
+    /// 执行次数
+    pub runs: u64,
+    /// 执行失败的次数
+    pub failures: u64,
+    /// 修正过的房间数，同一房间多次修正时重复计数
+    pub drifted_rooms: u64,
+    /// 修正的成员数偏差之和（绝对值）
+    pub total_drift: u64,
+    /// 最近一次执行修正的房间数
+    pub last_drifted_rooms: u64,
+}
+
+impl MemberCountMetrics {
+    fn record(&self, drifts: &[MemberCountDrift]) {
+        let metrics = &self.inner;
+        let drift: u64 = drifts
+            .iter()
+            .map(|drift| drift.actual.abs_diff(i64::from(drift.recorded)))
+            .sum();
+        metrics.runs.fetch_add(1, Ordering::Relaxed);
+        metrics
+            .drifted_rooms
+            .fetch_add(drifts.len() as u64, Ordering::Relaxed);
+        metrics.total_drift.fetch_add(drift, Ordering::Relaxed);
+        metrics
+            .last_drifted_rooms
+            .store(drifts.len() as u64, Ordering::Relaxed);
+    }
+
+    fn fail(&self) {
+        self.inner.runs.fetch_add(1, Ordering::Relaxed);
+        self.inner.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 当前统计
+    pub fn stats(&self) -> MemberCountStats {
+        let metrics = &self.inner;
+        MemberCountStats {
+            runs: metrics.runs.load(Ordering::Relaxed),
+            failures: metrics.failures.load(Ordering::Relaxed),
+            drifted_rooms: metrics.drifted_rooms.load(Ordering::Relaxed),
+            total_drift: metrics.total_drift.load(Ordering::Relaxed),
+            last_drifted_rooms: metrics.last_drifted_rooms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 修正群聊冗余的成员数
+#[derive(Debug, Clone)]
+pub struct MemberCountReconcile {
+    db: DatabaseConnection,
+    metrics: MemberCountMetrics,
+}
+
+impl MemberCountReconcile {
+    /// 创建
+    pub fn new(db: DatabaseConnection, metrics: MemberCountMetrics) -> Self {
+        Self { db, metrics }
+    }
+
+    async fn reconcile(&self) -> anyhow::Result<Vec<MemberCountDrift>> {
+        let service = GroupService::new(&self.db);
+        let drifts = service.member_count_drifts().await?;
+        for drift in &drifts {
+            tracing::warn!(
+                room_id = %drift.room_id,
+                recorded = %drift.recorded,
+                actual = %drift.actual,
+                "Room member count drifted."
+            );
+            service.reset_member_count(drift.room_id).await?;
+        }
+        Ok(drifts)
+    }
+}
+
+#[async_trait]
+impl Job for MemberCountReconcile {
+    fn name(&self) -> &str {
+        "member_count_reconcile"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        match self.reconcile().await {
+            Ok(drifts) => {
+                self.metrics.record(&drifts);
+                tracing::info!(drifted = drifts.len(), "Room member counts reconciled.");
+                Ok(())
+            }
+            Err(error) => {
+                self.metrics.fail();
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jobs::member_count::{MemberCountMetrics, MemberCountStats};
+    use crate::service::group::MemberCountDrift;
+
+    #[test]
+    fn metrics() {
+        let metrics = MemberCountMetrics::default();
+        metrics.record(&[
+            MemberCountDrift {
+                room_id: 1,
+                recorded: 3,
+                actual: 5,
+            },
+            MemberCountDrift {
+                room_id: 2,
+                recorded: 4,
+                actual: 1,
+            },
+        ]);
+        metrics.fail();
+        metrics.record(&[]);
+        assert_eq!(
+            metrics.stats(),
+            MemberCountStats {
+                runs: 3,
+                failures: 1,
+                drifted_rooms: 2,
+                total_drift: 5,
+                last_drifted_rooms: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! # 在线状态推送
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use crate::active::ActiveStatus;
+use crate::handler::chat::MemberResp;
+use crate::handler::ws::push::{OnlineOfflineNotify, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::jobs::Job;
+use crate::storage::repo::DynUserRepo;
+
+/// 定时检查本实例用户的在线状态，向所有已登录连接推送发生变化的用户
+///
+/// 长时间没有操作的用户由在线变为离开，再次操作后恢复在线；第一次执行只记录状态，不推送
+#[derive(Clone)]
+pub struct PresenceRefresh {
+    session_manager: SessionManager,
+    users: DynUserRepo,
+    last: Arc<Mutex<Option<HashMap<i64, ActiveStatus>>>>,
+}
+
+impl PresenceRefresh {
+    /// 创建
+    pub fn new(session_manager: SessionManager, users: DynUserRepo) -> Self {
+        Self {
+            session_manager,
+            users,
+            last: Arc::default(),
+        }
+    }
+}
+
+/// 相比上一次状态发生变化的用户，不再出现的用户为离线
+fn changes(
+    last: &HashMap<i64, ActiveStatus>,
+    current: &HashMap<i64, ActiveStatus>,
+) -> HashMap<i64, ActiveStatus> {
+    let mut changes: HashMap<_, _> = current
+        .iter()
+        .filter(|(uid, status)| last.get(uid) != Some(status))
+        .map(|(uid, status)| (*uid, *status))
+        .collect();
+    changes.extend(
+        last.keys()
+            .filter(|uid| !current.contains_key(uid))
+            .map(|uid| (*uid, ActiveStatus::Offline)),
+    );
+    changes
+}
+
+#[async_trait]
+impl Job for PresenceRefresh {
+    fn name(&self) -> &str {
+        "presence_refresh"
+    }
+
+    /// 连接只保存在本实例中，每个实例都需要执行
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let current = self.session_manager.presence();
+        let changes = match self.last.lock().replace(current.clone()) {
+            Some(last) => changes(&last, &current),
+            None => return Ok(()),
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let uids: Vec<i64> = changes.keys().copied().collect();
+        let change_list: Vec<_> = self
+            .users
+            .find_by_ids(&uids)
+            .await?
+            .into_iter()
+            .filter_map(|user| {
+                let status = changes.get(&(user.id as i64)).copied()?;
+                Some(MemberResp::new(user, status))
+            })
+            .collect();
+        if change_list.is_empty() {
+            return Ok(());
+        }
+        let changed = change_list.len();
+        let push = WsPush::OnlineOfflineNotify(OnlineOfflineNotify {
+            change_list,
+            online_num: self.session_manager.online_users() as u64,
+        });
+        let sent = self.session_manager.broadcast_all(&push, true)?;
+        tracing::info!(%changed, %sent, "Presence changes pushed.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use axum::extract::ws::Message;
+
+    use crate::active::ActiveStatus;
+    use crate::handler::ws::SessionManager;
+    use crate::jobs::presence::{changes, PresenceRefresh};
+    use crate::jobs::Job;
+    use crate::testing::MemoryRepo;
+
+    #[test]
+    fn diff() {
+        let last = HashMap::from([(1, ActiveStatus::Online), (2, ActiveStatus::Online)]);
+        let current = HashMap::from([(1, ActiveStatus::Away), (3, ActiveStatus::Online)]);
+        let changes = changes(&last, &current);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[&1], ActiveStatus::Away);
+        assert_eq!(changes[&2], ActiveStatus::Offline);
+        assert_eq!(changes[&3], ActiveStatus::Online);
+    }
+
+    #[tokio::test]
+    async fn push_changes() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        let first = repo.add_user("first", Some("first"));
+        let second = repo.add_user("second", Some("second"));
+        let session_manager = SessionManager::default();
+        let (_id, mut receiver) = session_manager.connect(first);
+        let job = PresenceRefresh::new(session_manager.clone(), Arc::new(repo));
+
+        job.run().await?;
+        job.run().await?;
+        let (_id, _receiver) = session_manager.connect(second);
+        job.run().await?;
+
+        let Some(Message::Text(json)) = receiver.recv().await else {
+            anyhow::bail!("expect a text frame");
+        };
+        let push: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(push["type"], 5);
+        assert_eq!(push["data"]["onlineNum"], 2);
+        assert_eq!(push["data"]["changeList"][0]["uid"], second);
+        assert_eq!(push["data"]["changeList"][0]["activeStatus"], 1);
+        // 前两次执行没有推送
+        assert_eq!(session_manager.list(Some(first), None)[0].queued, 0);
+        Ok(())
+    }
+}
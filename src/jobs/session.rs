@@ -0,0 +1,45 @@
+//! # 清理 WebSocket 连接
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::handler::ws::SessionManager;
+use crate::jobs::Job;
+
+/// 断开长时间没有心跳的连接，如客户端异常退出、网络中断后残留的连接
+#[derive(Debug, Clone)]
+pub struct SessionCleanup {
+    session_manager: SessionManager,
+    max_idle: Duration,
+}
+
+impl SessionCleanup {
+    /// 创建
+    pub fn new(session_manager: SessionManager, max_idle: Duration) -> Self {
+        Self {
+            session_manager,
+            max_idle,
+        }
+    }
+}
+
+#[async_trait]
+impl Job for SessionCleanup {
+    fn name(&self) -> &str {
+        "session_cleanup"
+    }
+
+    /// 连接只保存在本实例中，每个实例都需要执行
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let closed = self.session_manager.disconnect_idle(self.max_idle);
+        if closed > 0 {
+            tracing::info!(%closed, "Idle websocket sessions closed.");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! # 统计
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::cache::Cache;
+use crate::handler::ws::SessionManager;
+use crate::jobs::Job;
+use crate::service::stats::StatsService;
+
+/// 统计前一天的新增用户、活跃用户、消息数和在线人数峰值，见 [`StatsService`]
+#[derive(Debug, Clone)]
+pub struct DailyStatistics {
+    db: DatabaseConnection,
+    cache: Cache,
+    offset: UtcOffset,
+}
+
+impl DailyStatistics {
+    /// 创建，按 `offset` 时区划分日期
+    pub fn new(db: DatabaseConnection, cache: Cache, offset: UtcOffset) -> Self {
+        Self { db, cache, offset }
+    }
+}
+
+#[async_trait]
+impl Job for DailyStatistics {
+    fn name(&self) -> &str {
+        "daily_statistics"
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let Some(date) = now.to_offset(self.offset).date().previous_day() else {
+            return Ok(());
+        };
+        let service = StatsService::new(&self.db, &self.cache);
+        let stats = service.compute(date, self.offset).await?;
+        service.save(&stats).await?;
+        let pruned = service.prune_online(now).await?;
+        tracing::info!(?stats, %pruned, "Daily statistics computed.");
+        Ok(())
+    }
+}
+
+/// 采样本实例的在线人数，见 [`StatsService::sample_online`]
+#[derive(Debug, Clone)]
+pub struct OnlineSampling {
+    db: DatabaseConnection,
+    cache: Cache,
+    session_manager: SessionManager,
+}
+
+impl OnlineSampling {
+    /// 创建
+    pub fn new(db: DatabaseConnection, cache: Cache, session_manager: SessionManager) -> Self {
+        Self {
+            db,
+            cache,
+            session_manager,
+        }
+    }
+}
+
+#[async_trait]
+impl Job for OnlineSampling {
+    fn name(&self) -> &str {
+        "online_sampling"
+    }
+
+    /// 各实例分别累加本实例的在线人数
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        let online = self.session_manager.online_users() as u64;
+        StatsService::new(&self.db, &self.cache)
+            .sample_online(OffsetDateTime::now_utc(), online)
+            .await
+    }
+}
@@ -0,0 +1,55 @@
+//! # 微信 access_token
+
+use async_trait::async_trait;
+
+use crate::jobs::Job;
+use crate::weixin::WxClient;
+
+/// 在 access_token 过期前提前刷新，避免请求时才发现过期
+#[derive(Debug, Clone)]
+pub struct AccessTokenRefresh {
+    wx_clients: Vec<WxClient>,
+    ahead_secs: u64,
+}
+
+impl AccessTokenRefresh {
+    /// 创建，在过期前 `ahead_secs` 秒内刷新所有公众号的 access_token
+    pub fn new(wx_clients: Vec<WxClient>, ahead_secs: u64) -> Self {
+        Self {
+            wx_clients,
+            ahead_secs,
+        }
+    }
+}
+
+#[async_trait]
+impl Job for AccessTokenRefresh {
+    fn name(&self) -> &str {
+        "access_token_refresh"
+    }
+
+    /// access_token 保存在各实例的内存中，每个实例都需要执行
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    /// 一个公众号刷新失败时继续刷新其他公众号，最后返回第一个错误
+    async fn run(&self) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for wx_client in &self.wx_clients {
+            match wx_client.refresh_access_token(self.ahead_secs).await {
+                Ok(true) => {
+                    tracing::info!(app_id = %wx_client.app_id(), "Weixin access token refreshed.");
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    tracing::error!(app_id = %wx_client.app_id(), %error, "Failed to refresh weixin access token.");
+                    if result.is_ok() {
+                        result = Err(error);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
@@ -1,10 +1,28 @@
 #![doc = include_str!("../README.md")]
 #![deny(unsafe_code, missing_docs, clippy::unwrap_used)]
 
+pub mod active;
+pub mod bot;
 pub mod cache;
+pub mod captcha;
 pub mod handler;
+pub mod i18n;
+pub mod ip;
+pub mod jobs;
+pub mod live;
 pub mod log;
+pub mod mq;
+pub mod push;
+pub mod secret;
+pub mod service;
+pub mod shortlink;
+pub mod spam;
 pub mod storage;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod timestamp;
+pub mod url_discover;
+pub mod webhook;
 pub mod weixin;
 
 #[cfg(test)]
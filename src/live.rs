@@ -0,0 +1,225 @@
+//! # 配置热更新
+//!
+//! 日志级别、请求超时、跨域来源、微信被动回复超时等可以在运行时修改的配置通过 [`Live`] 共享，
+//! 作为 Extension 注入路由，每次使用时读取最新的值。
+//!
+//! [`ConfigWatcher`] 定期检查配置文件的修改时间，Unix 下同时响应 SIGHUP，
+//! 变化时调用回调重新读取配置并发布到各个 [`Live`]。
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+/// 配置热更新
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReloadConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+    /// 检查配置文件修改时间的间隔（秒）
+    #[serde(default = "default::interval_secs")]
+    pub interval_secs: u64,
+}
+
+mod default {
+    pub fn interval_secs() -> u64 {
+        5
+    }
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default::interval_secs(),
+        }
+    }
+}
+
+/// 可以在运行时替换的值
+pub struct Live<T>(Arc<ArcSwap<T>>);
+
+impl<T> Clone for Live<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Debug> Debug for Live<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Live").field(&self.0.load()).finish()
+    }
+}
+
+impl<T> Live<T> {
+    /// 创建
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(value)))
+    }
+
+    /// 当前的值
+    pub fn load(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+
+    /// 替换为新的值
+    pub fn store(&self, value: T) {
+        self.0.store(Arc::new(value));
+    }
+}
+
+/// 各公众号被动回复的最长等待时间，按 app_id 保存
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplyTimeouts(HashMap<String, Duration>);
+
+impl ReplyTimeouts {
+    /// 公众号被动回复的最长等待时间，未配置时返回 `None`
+    pub fn get(&self, app_id: &str) -> Option<Duration> {
+        self.0.get(app_id).copied()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, Duration)> for ReplyTimeouts {
+    fn from_iter<I: IntoIterator<Item = (S, Duration)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(app_id, timeout)| (app_id.into(), timeout))
+                .collect(),
+        )
+    }
+}
+
+/// 配置文件监视
+#[derive(Debug, Clone)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    interval: Duration,
+}
+
+impl ConfigWatcher {
+    /// 按 `config` 中的间隔检查 `path` 的修改时间
+    pub fn new(path: impl Into<PathBuf>, config: &ReloadConfig) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(config.interval_secs.max(1)),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// 启动监视任务，配置文件变化或收到 SIGHUP 时调用 `reload`
+    ///
+    /// `reload` 失败时只记录日志，继续使用原来的配置
+    pub fn spawn<F>(self, mut reload: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> anyhow::Result<()> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup =
+                match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(signal) => Some(signal),
+                    Err(error) => {
+                        tracing::error!(%error, "Failed to listen for SIGHUP.");
+                        None
+                    }
+                };
+
+            let mut loaded = self.modified();
+            let mut interval = tokio::time::interval(self.interval);
+            interval.tick().await;
+            loop {
+                #[cfg(unix)]
+                let hangup = async {
+                    match hangup.as_mut() {
+                        Some(signal) => signal.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let hangup = std::future::pending::<Option<()>>();
+
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let modified = self.modified();
+                        if modified == loaded {
+                            continue;
+                        }
+                        loaded = modified;
+                        tracing::info!(path = ?self.path, "Config file changed, reloading.");
+                    }
+                    _ = hangup => {
+                        loaded = self.modified();
+                        tracing::info!(path = ?self.path, "Received SIGHUP, reloading config.");
+                    }
+                }
+                match reload() {
+                    Ok(()) => tracing::info!("Config reloaded."),
+                    Err(error) => tracing::error!(%error, "Failed to reload config."),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::live::{ConfigWatcher, Live, ReloadConfig, ReplyTimeouts};
+
+    #[test]
+    fn live() {
+        let live = Live::new(1);
+        let cloned = live.clone();
+        cloned.store(2);
+        assert_eq!(*live.load(), 2);
+
+        let timeouts = ReplyTimeouts::from_iter([("wx1", Duration::from_secs(1))]);
+        assert_eq!(timeouts.get("wx1"), Some(Duration::from_secs(1)));
+        assert_eq!(timeouts.get("wx2"), None);
+    }
+
+    #[tokio::test]
+    async fn watch() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("mallchat-live-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("server.toml");
+        std::fs::write(&path, "a = 1")?;
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let config = ReloadConfig {
+            enabled: true,
+            interval_secs: 1,
+        };
+        let task = ConfigWatcher::new(&path, &config).spawn({
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        std::fs::remove_file(&path)?;
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        task.abort();
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,17 +1,25 @@
-#![cfg(not(feature = "shuttle"))]
 //! # 日志
 //!
 
+use std::fmt::{Debug, Formatter};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use byte_unit::Byte;
+use parking_lot::RwLock;
 use rolling_file::RollingConditionBasic;
 use serde::{Deserialize, Serialize};
 use time::format_description::FormatItem;
 use time::UtcOffset;
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::fmt::time::OffsetTime;
+use tracing_subscriber::filter::{EnvFilter, Targets};
+use tracing_subscriber::fmt::time::{FormatTime, OffsetTime};
+use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::reload;
+use tracing_subscriber::Layer;
 
 /// # 日志时间格式
 ///
@@ -26,6 +34,14 @@ pub struct LogConfig {
     /// 日志级别
     #[serde(with = "serde_level", default = "default::level")]
     pub level: tracing::Level,
+    /// 按模块过滤日志的规则，如 `info,mallchat::weixin=debug,sea_orm=warn`，配置后 `level` 不再生效
+    ///
+    /// 语法见 [`EnvFilter`]
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// 是否写入日志文件，关闭后只输出到 stdout，适用于容器、Shuttle 等没有持久化磁盘的环境
+    #[serde(default = "default::file")]
+    pub file: bool,
     /// 文件保存地址
     #[serde(default = "default::path")]
     pub path: PathBuf,
@@ -35,15 +51,81 @@ pub struct LogConfig {
     /// 文件个数
     #[serde(default = "default::archived_count")]
     pub archived_count: usize,
+    /// 文件日志格式
+    #[serde(default)]
+    pub format: LogFormat,
+    /// stdout 日志格式
+    #[serde(default)]
+    pub stdout_format: LogFormat,
+    /// OTLP 导出配置，不配置则不导出
+    #[serde(default)]
+    pub otlp: Option<OtlpConfig>,
+}
+
+/// # 日志格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 文本格式
+    #[default]
+    Text,
+    /// JSON 格式，每行一个对象，事件字段展开到顶层，便于 ELK/Loki 采集
+    Json,
+}
+
+impl LogFormat {
+    /// 按格式构造 fmt 层
+    fn layer<S, W, T>(self, writer: W, ansi: bool, timer: T) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+        T: FormatTime + Send + Sync + 'static,
+    {
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi)
+            .with_file(true)
+            .with_line_number(true)
+            .with_target(false)
+            .with_timer(timer);
+        match self {
+            LogFormat::Text => layer.boxed(),
+            LogFormat::Json => layer.json().flatten_event(true).boxed(),
+        }
+    }
+}
+
+/// # OTLP 导出配置
+///
+/// 将 span 通过 OTLP (gRPC) 导出到 Jaeger、Tempo 等后端
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OtlpConfig {
+    /// 导出地址
+    #[serde(default = "default::otlp_endpoint")]
+    pub endpoint: String,
+    /// 导出级别
+    #[serde(with = "serde_level", default = "default::level")]
+    pub level: tracing::Level,
+    /// 采样率，取值 `[0.0, 1.0]`
+    #[serde(default = "default::otlp_sample_ratio")]
+    pub sample_ratio: f64,
+    /// 导出超时时间（秒）
+    #[serde(default = "default::otlp_timeout_secs")]
+    pub timeout_secs: u64,
 }
 
 impl Default for LogConfig {
     fn default() -> Self {
         Self {
             level: default::level(),
+            filter: None,
+            format: LogFormat::default(),
+            stdout_format: LogFormat::default(),
+            file: default::file(),
             path: default::path(),
             trigger_size: default::trigger_size(),
             archived_count: default::archived_count(),
+            otlp: None,
         }
     }
 }
@@ -57,6 +139,10 @@ mod default {
         tracing::Level::INFO
     }
 
+    pub fn file() -> bool {
+        true
+    }
+
     pub fn path() -> PathBuf {
         PathBuf::from("log")
     }
@@ -68,6 +154,18 @@ mod default {
     pub fn archived_count() -> usize {
         32
     }
+
+    pub fn otlp_endpoint() -> String {
+        "http://localhost:4317".to_string()
+    }
+
+    pub fn otlp_sample_ratio() -> f64 {
+        1.0
+    }
+
+    pub fn otlp_timeout_secs() -> u64 {
+        3
+    }
 }
 
 mod serde_level {
@@ -109,10 +207,142 @@ mod serde_level {
 /// 在需要使用 tracing 期间需要保证其存活
 #[must_use]
 pub struct Logger {
-    _guard: WorkerGuard,
+    _guard: Option<WorkerGuard>,
+    otlp: bool,
+    filter_handle: LogFilterHandle,
+}
+
+impl Logger {
+    /// 用于运行时修改日志过滤规则的句柄
+    pub fn filter_handle(&self) -> LogFilterHandle {
+        self.filter_handle.clone()
+    }
+}
+
+type Reloader = Box<dyn Fn(EnvFilter) -> anyhow::Result<()> + Send + Sync>;
+
+/// # 日志过滤规则热更新句柄
+///
+/// 同时修改 stdout 和文件日志的过滤规则
+#[derive(Clone)]
+pub struct LogFilterHandle {
+    current: Arc<RwLock<String>>,
+    reloaders: Arc<Vec<Reloader>>,
+}
+
+impl Debug for LogFilterHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LogFilterHandle {{ current: {:?} }}", self.current())
+    }
+}
+
+impl LogFilterHandle {
+    /// 当前的过滤规则
+    pub fn current(&self) -> String {
+        self.current.read().clone()
+    }
+
+    /// 修改过滤规则，如 `debug` 或 `info,mallchat=debug`
+    pub fn reload(&self, filter: &str) -> anyhow::Result<()> {
+        let mut current = self.current.write();
+        for reloader in self.reloaders.iter() {
+            let env_filter = EnvFilter::try_new(filter)
+                .map_err(|e| anyhow::anyhow!("Invalid log filter `{filter}`: {e}"))?;
+            reloader(env_filter)?;
+        }
+        *current = filter.to_string();
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for LogFilterHandle {
+    /// 未接入日志系统的句柄，仅用于测试
+    fn default() -> Self {
+        Self {
+            current: Arc::new(RwLock::new(default::level().to_string())),
+            reloaders: Arc::new(Vec::new()),
+        }
+    }
+}
+
+/// 将过滤器包装为可热更新的过滤器
+fn reloadable<S>(filter: EnvFilter, reloaders: &mut Vec<Reloader>) -> reload::Layer<EnvFilter, S>
+where
+    S: tracing::Subscriber + 'static,
+{
+    let (filter, handle) = reload::Layer::new(filter);
+    reloaders.push(Box::new(move |filter| Ok(handle.reload(filter)?)));
+    filter
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        if self.otlp {
+            // 导出剩余的 span
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+impl OtlpConfig {
+    /// 构造 OTLP 导出层
+    ///
+    /// sea-orm 的查询 span 是 TRACE 级别，单独放开以便导出
+    fn layer<S>(&self, service: &str) -> anyhow::Result<impl Layer<S>>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        use opentelemetry::KeyValue;
+        use opentelemetry_otlp::WithExportConfig;
+        use opentelemetry_sdk::trace::Sampler;
+        use opentelemetry_sdk::Resource;
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&self.endpoint)
+                    .with_timeout(Duration::from_secs(self.timeout_secs)),
+            )
+            .with_trace_config(
+                opentelemetry_sdk::trace::config()
+                    .with_sampler(Sampler::TraceIdRatioBased(self.sample_ratio))
+                    .with_resource(Resource::new([KeyValue::new(
+                        "service.name",
+                        service.to_string(),
+                    )])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+        let targets = Targets::new()
+            .with_default(self.level)
+            .with_target("sea_orm::database", tracing::Level::TRACE);
+
+        Ok(tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(targets))
+    }
 }
 
 impl LogConfig {
+    /// 过滤规则，未配置 `filter` 时为 `level`
+    pub fn filter_directives(&self) -> String {
+        self.filter
+            .clone()
+            .unwrap_or_else(|| self.level.to_string())
+    }
+
+    /// 构造 stdout 和文件日志使用的过滤器
+    fn env_filter(&self) -> anyhow::Result<EnvFilter> {
+        match &self.filter {
+            Some(filter) => EnvFilter::try_new(filter)
+                .map_err(|e| anyhow::anyhow!("Invalid log filter `{filter}`: {e}")),
+            None => Ok(EnvFilter::new(self.level.as_str())),
+        }
+    }
+
     /// 初始化，确保全局执行一次
     pub async fn init<P: AsRef<Path>>(
         self,
@@ -123,54 +353,111 @@ impl LogConfig {
     ) -> anyhow::Result<Logger> {
         let local_time = OffsetTime::new(offset, LOG_FORMAT);
 
-        let log_path = root_path.as_ref().join(&self.path);
-        tokio::fs::create_dir_all(&log_path).await?;
+        let otlp = self.otlp.is_some();
+        let mut reloaders = Vec::new();
 
-        let file_appender = rolling_file::BasicRollingFileAppender::new(
-            log_path.join(format!("{}.log", service)),
-            RollingConditionBasic::new().max_size(self.trigger_size.get_bytes()),
-            self.archived_count,
-        )?;
-        let (nonblocking, _guard) = tracing_appender::non_blocking(file_appender);
+        let otlp_layer = self
+            .otlp
+            .as_ref()
+            .map(|otlp| otlp.layer(service))
+            .transpose()?;
 
-        if stdout {
-            let registry = tracing_subscriber::Registry::default();
+        let (file_layer, _guard) = if self.file {
+            let log_path = root_path.as_ref().join(&self.path);
+            tokio::fs::create_dir_all(&log_path).await?;
 
-            let file_layer = tracing_subscriber::fmt::layer()
-                .with_writer(nonblocking.with_max_level(self.level))
-                .with_file(true)
-                .with_line_number(true)
-                .with_target(false)
-                .with_timer(local_time.clone());
+            let file_appender = rolling_file::BasicRollingFileAppender::new(
+                log_path.join(format!("{}.log", service)),
+                RollingConditionBasic::new().max_size(self.trigger_size.get_bytes()),
+                self.archived_count,
+            )?;
+            let (nonblocking, guard) = tracing_appender::non_blocking(file_appender);
+            let layer = self
+                .format
+                .layer(nonblocking, false, local_time.clone())
+                .with_filter(reloadable(self.env_filter()?, &mut reloaders));
+            (Some(layer), Some(guard))
+        } else {
+            (None, None)
+        };
 
-            let stdout_layer = tracing_subscriber::fmt::layer()
-                .with_writer(std::io::stdout.with_max_level(self.level))
-                .with_file(true)
-                .with_line_number(true)
-                .with_target(false)
-                .with_timer(local_time);
+        let stdout_layer = if stdout {
+            Some(
+                self.stdout_format
+                    .layer(std::io::stdout, true, local_time)
+                    .with_filter(reloadable(self.env_filter()?, &mut reloaders)),
+            )
+        } else {
+            None
+        };
 
-            let registry = registry.with(stdout_layer).with(file_layer);
+        let registry = tracing_subscriber::Registry::default()
+            .with(stdout_layer)
+            .with(file_layer)
+            .with(otlp_layer);
 
-            tracing::subscriber::set_global_default(registry)?;
-        } else {
-            let registry = tracing_subscriber::Registry::default();
+        tracing::subscriber::set_global_default(registry)?;
 
-            let file_layer = tracing_subscriber::fmt::layer()
-                .with_writer(nonblocking.with_max_level(self.level))
-                .with_ansi(false)
-                .with_file(true)
-                .with_line_number(true)
-                .with_target(false)
-                .with_timer(local_time.clone());
+        tracing::info!(log = ?self, "Global logger initialized.");
 
-            let registry = registry.with(file_layer);
+        let filter_handle = LogFilterHandle {
+            current: Arc::new(RwLock::new(self.filter_directives())),
+            reloaders: Arc::new(reloaders),
+        };
 
-            tracing::subscriber::set_global_default(registry)?;
-        }
+        Ok(Logger {
+            _guard,
+            otlp,
+            filter_handle,
+        })
+    }
+}
 
-        tracing::info!(log = ?self, "Global logger initialized.");
+#[cfg(test)]
+mod tests {
+    use crate::log::{reloadable, LogConfig, LogFilterHandle, LogFormat};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn env_filter() {
+        let mut config = LogConfig::default();
+        assert_eq!(config.env_filter().expect("level").to_string(), "info");
+
+        config.filter = Some("info,mallchat::weixin=debug,sea_orm=warn".to_string());
+        assert!(config.env_filter().is_ok());
+
+        config.filter = Some("mallchat=loud".to_string());
+        assert!(config.env_filter().is_err());
+    }
+
+    #[test]
+    fn reload_filter() {
+        let mut reloaders = Vec::new();
+        let _layer = reloadable::<tracing_subscriber::Registry>(
+            LogConfig::default().env_filter().expect("level"),
+            &mut reloaders,
+        );
+        let handle = LogFilterHandle {
+            current: Arc::new(RwLock::new("INFO".to_string())),
+            reloaders: Arc::new(reloaders),
+        };
+
+        handle.reload("debug").expect("reload");
+        assert_eq!(handle.current(), "debug");
+
+        assert!(handle.reload("mallchat=loud").is_err());
+        assert_eq!(handle.current(), "debug");
+    }
 
-        Ok(Logger { _guard })
+    #[test]
+    fn log_format() -> anyhow::Result<()> {
+        assert_eq!(LogConfig::default().format, LogFormat::Text);
+        assert_eq!(
+            serde_json::from_str::<LogFormat>(r#""json""#)?,
+            LogFormat::Json
+        );
+        assert!(serde_json::from_str::<LogFormat>(r#""xml""#).is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,326 @@
+//! # 消息队列
+//!
+//! 发送消息后的推送、热度统计等操作通过消息队列异步执行，与发送接口解耦。
+//! 生产者、消费者只依赖 [`Producer`]、[`Consumer`] trait，默认使用 [`stream::RedisStreams`]，
+//! 启用 `kafka` feature 后可以通过配置改用 Kafka，测试时可以使用 [`memory::MemoryMq`]。
+//!
+//! 同一消费组内每条消息只投递给一个消费者，处理成功后确认；未确认的消息超时后重新投递，
+//! 超过最大投递次数后转入死信队列。
+//!
+//! 在请求中发布的事件携带请求 ID，见 [`RequestContext`]。
+
+pub mod active;
+pub mod announcement;
+pub mod bot;
+pub mod group;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod kick;
+pub mod memory;
+pub mod message;
+pub mod recall;
+pub mod stream;
+pub mod typing;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::cache::Cache;
+use crate::handler::context::RequestContext;
+
+/// 消息队列的实现
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MqBackend {
+    /// Redis Streams，使用 `[cache]` 配置的 Redis
+    #[default]
+    Redis,
+    /// Kafka，需要启用 `kafka` feature
+    Kafka,
+}
+
+/// 消息队列配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqConfig {
+    /// 使用的实现
+    #[serde(default)]
+    pub backend: MqBackend,
+    /// Kafka 配置，`backend` 为 `kafka` 时必须配置
+    #[cfg(feature = "kafka")]
+    #[serde(default)]
+    pub kafka: Option<kafka::KafkaConfig>,
+    /// 每个主题保留的最大消息数（近似值）
+    #[serde(default = "default::max_len")]
+    pub max_len: usize,
+    /// 每次拉取的最大消息数
+    #[serde(default = "default::batch_size")]
+    pub batch_size: usize,
+    /// 没有消息时拉取的最长等待时间（毫秒）
+    #[serde(default = "default::block_millis")]
+    pub block_millis: u64,
+    /// 消息投递后超过该时间（秒）未确认则重新投递
+    #[serde(default = "default::retry_after_secs")]
+    pub retry_after_secs: u64,
+    /// 最大投递次数，超过后转入死信队列
+    #[serde(default = "default::max_attempts")]
+    pub max_attempts: usize,
+    /// 实例 ID，每个实例都需要收到的消息（如 WebSocket 推送）按实例创建消费组，
+    /// 未配置时使用 `HOSTNAME` 环境变量或进程 ID
+    #[serde(default)]
+    pub instance_id: Option<String>,
+}
+
+mod default {
+    pub fn max_len() -> usize {
+        100_000
+    }
+
+    pub fn batch_size() -> usize {
+        32
+    }
+
+    pub fn block_millis() -> u64 {
+        5000
+    }
+
+    pub fn retry_after_secs() -> u64 {
+        30
+    }
+
+    pub fn max_attempts() -> usize {
+        5
+    }
+}
+
+impl Default for MqConfig {
+    fn default() -> Self {
+        Self {
+            backend: MqBackend::default(),
+            #[cfg(feature = "kafka")]
+            kafka: None,
+            max_len: default::max_len(),
+            batch_size: default::batch_size(),
+            block_millis: default::block_millis(),
+            retry_after_secs: default::retry_after_secs(),
+            max_attempts: default::max_attempts(),
+            instance_id: None,
+        }
+    }
+}
+
+impl MqConfig {
+    /// 实例 ID
+    pub fn instance_id(&self) -> String {
+        self.instance_id
+            .clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| std::process::id().to_string())
+    }
+}
+
+/// 按配置创建的消息队列
+#[derive(Debug, Clone)]
+pub enum MessageQueue {
+    /// Redis Streams
+    Redis(stream::RedisStreams),
+    /// Kafka
+    #[cfg(feature = "kafka")]
+    Kafka(kafka::KafkaMq),
+}
+
+impl MessageQueue {
+    /// 按配置创建
+    pub fn new(config: MqConfig, cache: Cache) -> anyhow::Result<Self> {
+        match config.backend {
+            MqBackend::Redis => Ok(Self::Redis(stream::RedisStreams::new(cache, config))),
+            #[cfg(feature = "kafka")]
+            MqBackend::Kafka => {
+                let Some(kafka) = config.kafka.clone() else {
+                    anyhow::bail!("mq.kafka must be configured for the kafka backend");
+                };
+                Ok(Self::Kafka(kafka::KafkaMq::new(kafka, config)?))
+            }
+            #[cfg(not(feature = "kafka"))]
+            MqBackend::Kafka => anyhow::bail!("kafka backend requires the `kafka` feature"),
+        }
+    }
+
+    /// 配置
+    pub fn config(&self) -> &MqConfig {
+        match self {
+            Self::Redis(mq) => mq.config(),
+            #[cfg(feature = "kafka")]
+            Self::Kafka(mq) => mq.config(),
+        }
+    }
+
+    /// 生产者
+    pub fn producer(&self) -> DynProducer {
+        match self {
+            Self::Redis(mq) => Arc::new(mq.clone()),
+            #[cfg(feature = "kafka")]
+            Self::Kafka(mq) => Arc::new(mq.clone()),
+        }
+    }
+
+    /// 创建消费者
+    pub async fn consumer(
+        &self,
+        topic: &str,
+        group: &str,
+        name: &str,
+    ) -> anyhow::Result<Box<dyn Consumer>> {
+        Ok(match self {
+            Self::Redis(mq) => Box::new(mq.consumer(topic, group, name).await?),
+            #[cfg(feature = "kafka")]
+            Self::Kafka(mq) => Box::new(mq.consumer(topic, group, name)?),
+        })
+    }
+}
+
+/// 死信队列的主题
+pub fn dead_letter_topic(topic: &str) -> String {
+    format!("{topic}:dead")
+}
+
+/// 投递给消费者的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// 消息 ID
+    pub id: String,
+    /// 消息内容
+    pub payload: Vec<u8>,
+    /// 第几次投递，从 1 开始
+    pub attempts: usize,
+}
+
+/// 以 Extension 注入的生产者
+pub type DynProducer = Arc<dyn Producer>;
+
+/// 生产者
+#[async_trait]
+pub trait Producer: Debug + Send + Sync {
+    /// 发送消息，返回消息 ID
+    async fn send(&self, topic: &str, payload: &[u8]) -> anyhow::Result<String>;
+}
+
+/// 附加了请求 ID 的事件
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Traced<'a, T> {
+    #[serde(flatten)]
+    event: &'a T,
+    request_id: String,
+}
+
+/// 事件中携带的请求 ID，其他字段忽略
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceId {
+    request_id: Option<String>,
+}
+
+/// 将事件序列化为 JSON 后发送，在请求上下文中时附加 `requestId` 字段，事件必须序列化为 JSON 对象
+pub async fn send_json<T: Serialize + Sync>(
+    producer: &dyn Producer,
+    topic: &str,
+    event: &T,
+) -> anyhow::Result<String> {
+    let payload = match RequestContext::current_request_id() {
+        Some(request_id) => serde_json::to_vec(&Traced { event, request_id })?,
+        None => serde_json::to_vec(event)?,
+    };
+    producer.send(topic, &payload).await
+}
+
+/// 处理一条消息，恢复发布时的请求上下文
+async fn handle(handler: &impl Handler, delivery: &Delivery) -> anyhow::Result<()> {
+    let request_id = serde_json::from_slice::<TraceId>(&delivery.payload)
+        .ok()
+        .and_then(|trace| trace.request_id);
+    let span = tracing::info_span!(
+        "mq",
+        handler = handler.name(),
+        id = %delivery.id,
+        request_id = request_id.as_deref(),
+    );
+    let handled = handler.handle(&delivery.payload).instrument(span);
+    match request_id {
+        Some(request_id) => RequestContext::new(request_id).scope(handled).await,
+        None => handled.await,
+    }
+}
+
+/// 消费者，创建时绑定主题和消费组
+#[async_trait]
+pub trait Consumer: Send + Sync {
+    /// 拉取一批消息，包括超时未确认需要重新投递的消息，没有消息时等待一段时间后返回空
+    async fn poll(&self) -> anyhow::Result<Vec<Delivery>>;
+    /// 确认消息已处理
+    async fn ack(&self, delivery: &Delivery) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl Consumer for Box<dyn Consumer> {
+    async fn poll(&self) -> anyhow::Result<Vec<Delivery>> {
+        self.as_ref().poll().await
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> anyhow::Result<()> {
+        self.as_ref().ack(delivery).await
+    }
+}
+
+/// 消息处理器
+#[async_trait]
+pub trait Handler: Send + Sync + 'static {
+    /// 处理器名，用于日志
+    fn name(&self) -> &str;
+    /// 处理消息，返回错误时消息不会被确认，稍后重新投递
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()>;
+}
+
+/// 拉取消息失败后的等待时间
+const POLL_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// 启动消费任务，停止时取消返回的任务即可
+pub fn subscribe(consumer: impl Consumer + 'static, handler: impl Handler) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let deliveries = match consumer.poll().await {
+                Ok(deliveries) => deliveries,
+                Err(error) => {
+                    tracing::error!(handler = handler.name(), %error, "Failed to poll messages.");
+                    tokio::time::sleep(POLL_ERROR_BACKOFF).await;
+                    continue;
+                }
+            };
+            for delivery in deliveries {
+                if let Err(error) = handle(&handler, &delivery).await {
+                    tracing::warn!(
+                        handler = handler.name(),
+                        id = %delivery.id,
+                        attempts = delivery.attempts,
+                        %error,
+                        "Failed to handle message."
+                    );
+                    continue;
+                }
+                if let Err(error) = consumer.ack(&delivery).await {
+                    tracing::error!(
+                        handler = handler.name(),
+                        id = %delivery.id,
+                        %error,
+                        "Failed to ack message."
+                    );
+                }
+            }
+        }
+    })
+}
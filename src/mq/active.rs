@@ -0,0 +1,50 @@
+//! # 用户活跃事件
+//!
+//! [`ActiveTracker`](crate::active::ActiveTracker) 定时发布 [`UserActiveEvent`] 到 [`USER_ACTIVE_TOPIC`]，
+//! 由 [`RecordActive`] 批量更新最后活跃时间，所有实例共用一个消费组。
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::mq::Handler;
+use crate::storage::repo::DynUserRepo;
+
+/// 用户活跃事件的主题
+pub const USER_ACTIVE_TOPIC: &str = "mallchat:mq:user_active";
+
+/// 记录最后活跃时间的消费组
+pub const USER_ACTIVE_GROUP: &str = "user_active";
+
+/// 用户活跃事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserActiveEvent {
+    /// 一个发布间隔内活跃的用户
+    pub uids: Vec<i64>,
+}
+
+/// 更新用户的最后活跃时间
+#[derive(Clone)]
+pub struct RecordActive {
+    users: DynUserRepo,
+}
+
+impl RecordActive {
+    /// 创建
+    pub fn new(users: DynUserRepo) -> Self {
+        Self { users }
+    }
+}
+
+#[async_trait]
+impl Handler for RecordActive {
+    fn name(&self) -> &str {
+        "record_active"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let UserActiveEvent { uids } = serde_json::from_slice(payload)?;
+        self.users.refresh_active_time(&uids).await?;
+        Ok(())
+    }
+}
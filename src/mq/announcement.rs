@@ -0,0 +1,62 @@
+//! # 系统公告事件
+//!
+//! 管理员发布公告后发布 [`Announcement`] 到 [`ANNOUNCEMENT_TOPIC`]，
+//! 由 [`PushAnnouncement`] 在各实例上推送给在线用户，消费组与新消息的推送相同，按实例创建。
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+
+use crate::handler::ws::push::{Announcement, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::mq::Handler;
+use crate::service::announcement::AnnouncementService;
+
+/// 系统公告的主题
+pub const ANNOUNCEMENT_TOPIC: &str = "mallchat:mq:announcement";
+
+/// 推送公告给本实例的在线用户，并记录为已送达，返回推送的连接数
+///
+/// 记录失败时只影响登录时是否重复补发，不返回错误
+pub async fn push_announcement(
+    session_manager: &SessionManager,
+    db: &DatabaseConnection,
+    announcement: Announcement,
+) -> anyhow::Result<usize> {
+    let id = announcement.id;
+    let uids = session_manager.online_uids();
+    let pushed = session_manager.broadcast_all(&WsPush::Announcement(announcement), true)?;
+    if let Err(error) = AnnouncementService::new(db).mark_delivered(&uids, id).await {
+        tracing::error!(%id, %error, "Failed to mark announcement delivered.");
+    }
+    Ok(pushed)
+}
+
+/// 推送系统公告
+#[derive(Debug, Clone)]
+pub struct PushAnnouncement {
+    session_manager: SessionManager,
+    db: DatabaseConnection,
+}
+
+impl PushAnnouncement {
+    /// 创建
+    pub fn new(session_manager: SessionManager, db: DatabaseConnection) -> Self {
+        Self {
+            session_manager,
+            db,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for PushAnnouncement {
+    fn name(&self) -> &str {
+        "push_announcement"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let announcement: Announcement = serde_json::from_slice(payload)?;
+        push_announcement(&self.session_manager, &self.db, announcement).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! # 聊天机器人的回复
+//!
+//! [`BotMention`] 消费新消息事件，消息 @ 了机器人时发布 [`BotRequest`] 到 [`BOT_TOPIC`]，
+//! 由 [`BotReply`] 生成回复。回复先以占位内容发送为新消息，生成过程中按
+//! [`BotConfig::stream_interval_millis`] 的间隔发布到 [`MESSAGE_UPDATE_TOPIC`]，由推送消息更新的消费者推送。
+//!
+//! 超出频率限制的 @ 直接丢弃，不在房间中回复，避免刷屏。
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::bot::{BotConfig, ChatMessage, DynChatBot, Role};
+use crate::cache::Cache;
+use crate::handler::chat::{MessageResp, MessageStatus, MessageType};
+use crate::mq::message::{MessageEvent, MESSAGE_TOPIC, MESSAGE_UPDATE_TOPIC};
+use crate::mq::{send_json, DynProducer, Handler};
+use crate::storage::model::message;
+use crate::storage::repo::Repos;
+
+/// 机器人回复请求的主题
+pub const BOT_TOPIC: &str = "mallchat:mq:bot";
+
+/// 检查是否 @ 了机器人的消费组
+pub const BOT_MENTION_GROUP: &str = "bot_mention";
+
+/// 生成机器人回复的消费组
+pub const BOT_REPLY_GROUP: &str = "bot_reply";
+
+/// 频率限制计数的键前缀，后接 uid
+const RATE_LIMIT_KEY: &str = "mallchat:bot:rate";
+
+/// 计数，第一次计数时设置过期时间；在同一个脚本中执行，不会留下没有过期时间的计数
+const RATE_LIMIT_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+/// 生成回复前的占位内容
+const PLACEHOLDER: &str = "…";
+/// 生成失败时的回复
+const FAILED_REPLY: &str = "抱歉，暂时无法回答，请稍后再试";
+/// 回复的最大长度（字符），与消息内容的列长度一致
+const MAX_REPLY_CHARS: usize = 1024;
+
+/// 机器人回复请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotRequest {
+    /// @ 机器人的消息
+    pub message: MessageResp,
+    /// 去掉 `@昵称` 后的问题
+    pub prompt: String,
+    /// 接收者，与原消息一致
+    pub receivers: Option<Vec<i64>>,
+}
+
+/// 检查消息是否 @ 了机器人
+#[derive(Debug, Clone)]
+pub struct BotMention {
+    config: BotConfig,
+    cache: Cache,
+    producer: DynProducer,
+}
+
+impl BotMention {
+    /// 创建
+    pub fn new(config: BotConfig, cache: Cache, producer: DynProducer) -> Self {
+        Self {
+            config,
+            cache,
+            producer,
+        }
+    }
+
+    /// 计数并检查用户是否超出频率限制
+    async fn rate_limited(&self, uid: i64) -> anyhow::Result<bool> {
+        let mut connection = self.cache.connection().await?;
+        let count: u64 = redis::Script::new(RATE_LIMIT_SCRIPT)
+            .key(format!("{RATE_LIMIT_KEY}:{uid}"))
+            .arg(self.config.rate_limit_secs.max(1))
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(count > self.config.rate_limit)
+    }
+}
+
+#[async_trait]
+impl Handler for BotMention {
+    fn name(&self) -> &str {
+        "bot_mention"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let MessageEvent { message, receivers } = serde_json::from_slice(payload)?;
+        if message.from_uid == self.config.uid {
+            return Ok(());
+        }
+        let Some(prompt) = self.config.mention(&message.content) else {
+            return Ok(());
+        };
+        if self.rate_limited(message.from_uid).await? {
+            tracing::info!(
+                uid = message.from_uid,
+                id = message.id,
+                "Bot mention dropped by rate limit."
+            );
+            return Ok(());
+        }
+        let request = BotRequest {
+            message,
+            prompt,
+            receivers,
+        };
+        send_json(self.producer.as_ref(), BOT_TOPIC, &request).await?;
+        Ok(())
+    }
+}
+
+/// 生成机器人回复
+#[derive(Clone)]
+pub struct BotReply {
+    config: BotConfig,
+    bot: DynChatBot,
+    repos: Repos,
+    producer: DynProducer,
+}
+
+impl BotReply {
+    /// 创建
+    pub fn new(config: BotConfig, bot: DynChatBot, repos: Repos, producer: DynProducer) -> Self {
+        Self {
+            config,
+            bot,
+            repos,
+            producer,
+        }
+    }
+
+    /// 发布消息更新
+    async fn update(
+        &self,
+        message: &MessageResp,
+        content: &str,
+        receivers: &Option<Vec<i64>>,
+    ) -> anyhow::Result<()> {
+        let event = MessageEvent {
+            message: MessageResp {
+                content: content.to_string(),
+                ..message.clone()
+            },
+            receivers: receivers.clone(),
+        };
+        send_json(self.producer.as_ref(), MESSAGE_UPDATE_TOPIC, &event).await?;
+        Ok(())
+    }
+
+    /// 生成回复，生成过程中按间隔发布已生成的内容
+    async fn complete(
+        &self,
+        reply: &MessageResp,
+        prompt: String,
+        receivers: &Option<Vec<i64>>,
+    ) -> anyhow::Result<String> {
+        let messages = [
+            ChatMessage::new(Role::System, self.config.system_prompt.as_str()),
+            ChatMessage::new(Role::User, prompt),
+        ];
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let completion = self.bot.complete(&messages, sender);
+        tokio::pin!(completion);
+
+        let interval = Duration::from_millis(self.config.stream_interval_millis);
+        let mut content = String::new();
+        let mut pushed = Instant::now();
+        loop {
+            tokio::select! {
+                // 先处理已收到的增量内容
+                biased;
+                Some(delta) = receiver.recv() => {
+                    content.push_str(&delta);
+                    if pushed.elapsed() >= interval {
+                        pushed = Instant::now();
+                        // 中间结果推送失败时不影响生成
+                        if let Err(error) = self.update(reply, &content, receivers).await {
+                            tracing::warn!(id = %reply.id, %error, "Failed to publish bot reply.");
+                        }
+                    }
+                }
+                result = &mut completion => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for BotReply {
+    fn name(&self) -> &str {
+        "bot_reply"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let BotRequest {
+            message,
+            prompt,
+            receivers,
+        } = serde_json::from_slice(payload)?;
+        let model = self
+            .repos
+            .messages
+            .create(message::ActiveModel {
+                room_id: Set(message.room_id),
+                from_uid: Set(self.config.uid),
+                content: Set(PLACEHOLDER.to_string()),
+                reply_msg_id: Set(Some(message.id as i64)),
+                status: Set(MessageStatus::Normal as i32),
+                r#type: Set(Some(MessageType::Text as i32)),
+                ..Default::default()
+            })
+            .await?;
+        self.repos.rooms.refresh_last_message(&model).await?;
+        let reply = MessageResp::from(model.clone());
+        let event = MessageEvent {
+            message: reply.clone(),
+            receivers: receivers.clone(),
+        };
+        send_json(self.producer.as_ref(), MESSAGE_TOPIC, &event).await?;
+
+        // 回复已经发送，之后的错误不再重新投递，避免重复回复
+        let content = match self.complete(&reply, prompt, &receivers).await {
+            Ok(content) if !content.trim().is_empty() => content,
+            Ok(_) => FAILED_REPLY.to_string(),
+            Err(error) => {
+                tracing::warn!(id = %message.id, %error, "Failed to complete bot reply.");
+                FAILED_REPLY.to_string()
+            }
+        };
+        let content: String = content.chars().take(MAX_REPLY_CHARS).collect();
+        if let Err(error) = self.repos.messages.update_content(reply.id, &content).await {
+            tracing::error!(id = %reply.id, %error, "Failed to save bot reply.");
+        } else {
+            // 会话列表显示回复内容，而不是占位文字
+            let model = message::Model {
+                content: content.clone(),
+                ..model
+            };
+            if let Err(error) = self.repos.rooms.refresh_last_message(&model).await {
+                tracing::error!(id = %reply.id, %error, "Failed to refresh room last message.");
+            }
+        }
+        if let Err(error) = self.update(&reply, &content, &receivers).await {
+            tracing::error!(id = %reply.id, %error, "Failed to publish bot reply.");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    use crate::bot::{BotConfig, ChatBot, ChatMessage, Role};
+    use crate::handler::chat::MessageResp;
+    use crate::mq::bot::{BotReply, BotRequest};
+    use crate::mq::memory::MemoryMq;
+    use crate::mq::message::{MessageEvent, MESSAGE_TOPIC, MESSAGE_UPDATE_TOPIC};
+    use crate::mq::{Consumer, Handler};
+    use crate::storage::repo::{MessageRepo, Repos, RoomRepo};
+    use crate::testing::MemoryRepo;
+
+    #[derive(Debug)]
+    struct EchoBot;
+
+    #[async_trait]
+    impl ChatBot for EchoBot {
+        async fn complete(
+            &self,
+            messages: &[ChatMessage],
+            delta: mpsc::UnboundedSender<String>,
+        ) -> anyhow::Result<String> {
+            let Some(prompt) = messages.iter().find(|message| message.role == Role::User) else {
+                anyhow::bail!("no prompt");
+            };
+            for c in prompt.content.chars() {
+                delta.send(c.to_string())?;
+                tokio::task::yield_now().await;
+            }
+            Ok(prompt.content.clone())
+        }
+    }
+
+    fn request() -> BotRequest {
+        BotRequest {
+            message: MessageResp {
+                id: 7,
+                room_id: 2,
+                from_uid: 1,
+                content: "@MallChatBot hello".to_string(),
+                reply_msg_id: None,
+                send_time: time::macros::datetime!(2023-06-01 00:00),
+                from_region: None,
+                url_content_map: None,
+                client_msg_id: None,
+            },
+            prompt: "hello".to_string(),
+            receivers: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn bot_reply() -> anyhow::Result<()> {
+        let repo = Arc::new(MemoryRepo::default());
+        repo.add_room("抹茶群聊", 1);
+        repo.add_room("机器人", 1);
+        let repos = Repos {
+            users: repo.clone(),
+            messages: repo.clone(),
+            rooms: repo.clone(),
+        };
+        let mq = MemoryMq::default();
+        let messages = mq.consumer(MESSAGE_TOPIC, "push", 3);
+        let updates = mq.consumer(MESSAGE_UPDATE_TOPIC, "push", 3);
+        let config = BotConfig {
+            uid: 100,
+            stream_interval_millis: 0,
+            ..Default::default()
+        };
+        let handler = BotReply::new(config, Arc::new(EchoBot), repos, Arc::new(mq.clone()));
+        handler.handle(&serde_json::to_vec(&request())?).await?;
+
+        let deliveries = messages.poll().await?;
+        assert_eq!(deliveries.len(), 1);
+        let event: MessageEvent = serde_json::from_slice(&deliveries[0].payload)?;
+        assert_eq!(event.message.from_uid, 100);
+        assert_eq!(event.message.reply_msg_id, Some(7));
+        messages.ack(&deliveries[0]).await?;
+        let id = event.message.id;
+
+        let updates: Vec<MessageEvent> = updates
+            .poll()
+            .await?
+            .iter()
+            .map(|delivery| serde_json::from_slice(&delivery.payload))
+            .collect::<Result<_, _>>()?;
+        assert!(updates.len() > 1, "{updates:?}");
+        assert!(updates.iter().all(|update| update.message.id == id));
+        assert_eq!(updates[0].message.content, "h");
+        assert_eq!(
+            updates.last().map(|update| update.message.content.as_str()),
+            Some("hello")
+        );
+        let saved = MessageRepo::find_by_id(repo.as_ref(), id).await?;
+        assert_eq!(saved.map(|saved| saved.content).as_deref(), Some("hello"));
+        let room = RoomRepo::find_by_id(repo.as_ref(), 2).await?;
+        assert_eq!(
+            room.and_then(|room| room.last_msg_abstract).as_deref(),
+            Some("hello")
+        );
+        Ok(())
+    }
+}
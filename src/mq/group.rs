@@ -0,0 +1,86 @@
+//! # 群聊变更事件
+//!
+//! 禁言、修改群公告、转让群主、解散群聊后发布 [`GroupChange`] 到 [`GROUP_TOPIC`]，
+//! 由 [`PushGroupChange`] 在各实例上推送，消费组与新消息的推送相同，按实例创建。
+
+use async_trait::async_trait;
+
+use crate::handler::ws::push::{GroupChange, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::mq::Handler;
+
+/// 群聊变更的主题
+pub const GROUP_TOPIC: &str = "mallchat:mq:group";
+
+/// 推送群聊变更给本实例的已登录连接，返回推送的连接数
+///
+/// 群聊消息推送给所有在线用户，变更也同样广播
+pub fn push_group_change(
+    session_manager: &SessionManager,
+    change: GroupChange,
+) -> anyhow::Result<usize> {
+    session_manager.broadcast_all(&WsPush::GroupChange(change), true)
+}
+
+/// 推送群聊变更
+#[derive(Debug, Clone)]
+pub struct PushGroupChange {
+    session_manager: SessionManager,
+}
+
+impl PushGroupChange {
+    /// 创建
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self { session_manager }
+    }
+}
+
+#[async_trait]
+impl Handler for PushGroupChange {
+    fn name(&self) -> &str {
+        "push_group_change"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let change: GroupChange = serde_json::from_slice(payload)?;
+        push_group_change(&self.session_manager, change)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::handler::ws::push::{GroupChange, GroupChangeType};
+    use crate::handler::ws::SessionManager;
+    use crate::mq::group::PushGroupChange;
+    use crate::mq::Handler;
+
+    #[tokio::test]
+    async fn push_change() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (_id, mut receiver) = session_manager.connect(1);
+        let change = GroupChange {
+            room_id: 2,
+            change_type: GroupChangeType::Announcement,
+            operator_uid: 1,
+            uid: None,
+            mute_until: None,
+            announcement: Some("群公告".to_string()),
+        };
+        PushGroupChange::new(session_manager)
+            .handle(&serde_json::to_vec(&change)?)
+            .await?;
+
+        let Some(Message::Text(json)) = receiver.recv().await else {
+            anyhow::bail!("expect a text frame");
+        };
+        let push: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(push["type"], 17);
+        assert_eq!(push["data"]["roomId"], 2);
+        assert_eq!(push["data"]["changeType"], 2);
+        assert_eq!(push["data"]["announcement"], "群公告");
+        Ok(())
+    }
+}
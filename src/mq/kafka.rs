@@ -0,0 +1,314 @@
+//! # Kafka 实现
+//!
+//! 需要启用 `kafka` feature。主题名中的 `:` 替换为 `.`（Kafka 主题名不支持 `:`），
+//! 消费组对应 Kafka 的 consumer group，可以使用 Kafka 自带的工具重置位点重放消息。
+//!
+//! Kafka 只能按分区提交位点，消费者在内存中记录未确认的消息：
+//! 超时未确认的消息重新投递，提交的位点不超过最早的未确认消息。
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rdkafka::consumer::{CommitMode, Consumer as _, StreamConsumer};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+
+use crate::mq::{dead_letter_topic, Consumer, Delivery, MqConfig, Producer};
+
+/// Kafka 配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KafkaConfig {
+    /// broker 地址，逗号分隔
+    pub brokers: String,
+    /// 新的消费组从最早（`earliest`）还是最新（`latest`）的消息开始消费
+    #[serde(default = "default::offset_reset")]
+    pub offset_reset: String,
+    /// 其他 librdkafka 配置，如 `security.protocol`
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+mod default {
+    pub fn offset_reset() -> String {
+        "latest".to_string()
+    }
+}
+
+impl KafkaConfig {
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", &self.brokers);
+        for (key, value) in &self.properties {
+            config.set(key, value);
+        }
+        config
+    }
+}
+
+/// Kafka 主题名
+pub fn topic_name(topic: &str) -> String {
+    topic.replace(':', ".")
+}
+
+/// 发送消息的最长排队时间
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 基于 Kafka 的消息队列
+#[derive(Clone)]
+pub struct KafkaMq {
+    producer: FutureProducer,
+    kafka: Arc<KafkaConfig>,
+    config: Arc<MqConfig>,
+}
+
+impl std::fmt::Debug for KafkaMq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaMq")
+            .field("brokers", &self.kafka.brokers)
+            .finish()
+    }
+}
+
+impl KafkaMq {
+    /// 创建
+    pub fn new(kafka: KafkaConfig, config: MqConfig) -> anyhow::Result<Self> {
+        let producer = kafka.client_config().create()?;
+        Ok(Self {
+            producer,
+            kafka: Arc::new(kafka),
+            config: Arc::new(config),
+        })
+    }
+
+    /// 配置
+    pub fn config(&self) -> &MqConfig {
+        &self.config
+    }
+
+    /// 创建消费者
+    pub fn consumer(&self, topic: &str, group: &str, name: &str) -> anyhow::Result<KafkaConsumer> {
+        let consumer: StreamConsumer = self
+            .kafka
+            .client_config()
+            .set("group.id", group)
+            .set("client.id", name)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", &self.kafka.offset_reset)
+            .create()?;
+        let topic = topic_name(topic);
+        consumer.subscribe(&[&topic])?;
+        Ok(KafkaConsumer {
+            consumer,
+            mq: self.clone(),
+            topic,
+            pending: Mutex::new(Pending::default()),
+        })
+    }
+}
+
+#[async_trait]
+impl Producer for KafkaMq {
+    async fn send(&self, topic: &str, payload: &[u8]) -> anyhow::Result<String> {
+        let topic = topic_name(topic);
+        let record = FutureRecord::<(), [u8]>::to(&topic).payload(payload);
+        let (partition, offset) = self
+            .producer
+            .send(record, QUEUE_TIMEOUT)
+            .await
+            .map_err(|(error, _)| error)?;
+        Ok(format!("{partition}:{offset}"))
+    }
+}
+
+/// 未确认的消息
+#[derive(Debug, Default)]
+struct Pending {
+    deliveries: BTreeMap<(i32, i64), (Delivery, Instant)>,
+    received: HashMap<i32, i64>,
+    committed: HashMap<i32, i64>,
+}
+
+impl Pending {
+    fn insert(&mut self, partition: i32, offset: i64, delivery: Delivery) {
+        self.deliveries
+            .insert((partition, offset), (delivery, Instant::now()));
+        let received = self.received.entry(partition).or_insert(offset);
+        *received = offset.max(*received);
+    }
+
+    /// 取出超时需要重新投递的消息
+    fn expired(&mut self, retry_after: Duration) -> Vec<(i32, i64, Delivery)> {
+        self.deliveries
+            .iter_mut()
+            .filter(|(_, (_, delivered_at))| delivered_at.elapsed() >= retry_after)
+            .map(|(&(partition, offset), (delivery, delivered_at))| {
+                *delivered_at = Instant::now();
+                delivery.attempts += 1;
+                (partition, offset, delivery.clone())
+            })
+            .collect()
+    }
+
+    /// 确认消息，返回分区可以提交的位点：最早的未确认消息，或者已收到的最新消息之后
+    fn ack(&mut self, partition: i32, offset: i64) -> Option<i64> {
+        self.deliveries.remove(&(partition, offset));
+        let received = *self.received.get(&partition)?;
+        let commit = self
+            .deliveries
+            .range((partition, i64::MIN)..=(partition, i64::MAX))
+            .next()
+            .map_or(received + 1, |(&(_, pending), _)| pending);
+        let committed = self.committed.entry(partition).or_default();
+        if commit <= *committed {
+            return None;
+        }
+        *committed = commit;
+        Some(commit)
+    }
+}
+
+fn parse_id(id: &str) -> Option<(i32, i64)> {
+    let (partition, offset) = id.split_once(':')?;
+    Some((partition.parse().ok()?, offset.parse().ok()?))
+}
+
+/// Kafka 消费者
+pub struct KafkaConsumer {
+    consumer: StreamConsumer,
+    mq: KafkaMq,
+    topic: String,
+    pending: Mutex<Pending>,
+}
+
+impl std::fmt::Debug for KafkaConsumer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaConsumer")
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+impl KafkaConsumer {
+    fn commit(&self, partition: i32, offset: i64) -> anyhow::Result<()> {
+        let mut list = TopicPartitionList::new();
+        list.add_partition_offset(&self.topic, partition, Offset::Offset(offset))?;
+        self.consumer.commit(&list, CommitMode::Async)?;
+        Ok(())
+    }
+
+    /// 重新投递超时的消息，超过最大投递次数的转入死信队列
+    async fn retry(&self) -> anyhow::Result<Vec<Delivery>> {
+        let config = self.mq.config();
+        let expired = self
+            .pending
+            .lock()
+            .expired(Duration::from_secs(config.retry_after_secs));
+        let mut deliveries = Vec::with_capacity(expired.len());
+        for (partition, offset, delivery) in expired {
+            if delivery.attempts <= config.max_attempts {
+                deliveries.push(delivery);
+                continue;
+            }
+            tracing::warn!(
+                topic = %self.topic,
+                id = %delivery.id,
+                "Message moved to dead letter queue."
+            );
+            self.mq
+                .send(&dead_letter_topic(&self.topic), &delivery.payload)
+                .await?;
+            let commit = self.pending.lock().ack(partition, offset);
+            if let Some(commit) = commit {
+                self.commit(partition, commit)?;
+            }
+        }
+        Ok(deliveries)
+    }
+
+    fn receive(&self, message: &impl Message) -> Delivery {
+        let delivery = Delivery {
+            id: format!("{}:{}", message.partition(), message.offset()),
+            payload: message.payload().unwrap_or_default().to_vec(),
+            attempts: 1,
+        };
+        self.pending
+            .lock()
+            .insert(message.partition(), message.offset(), delivery.clone());
+        delivery
+    }
+}
+
+#[async_trait]
+impl Consumer for KafkaConsumer {
+    async fn poll(&self) -> anyhow::Result<Vec<Delivery>> {
+        let config = self.mq.config();
+        let mut deliveries = self.retry().await?;
+
+        // 有需要重试的消息时不等待新消息
+        let mut wait = match deliveries.is_empty() {
+            true => Duration::from_millis(config.block_millis),
+            false => Duration::ZERO,
+        };
+        while deliveries.len() < config.batch_size {
+            let Ok(message) = tokio::time::timeout(wait, self.consumer.recv()).await else {
+                break;
+            };
+            deliveries.push(self.receive(&message?));
+            wait = Duration::ZERO;
+        }
+        Ok(deliveries)
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> anyhow::Result<()> {
+        let Some((partition, offset)) = parse_id(&delivery.id) else {
+            anyhow::bail!("invalid kafka message id: {}", delivery.id);
+        };
+        let commit = self.pending.lock().ack(partition, offset);
+        if let Some(commit) = commit {
+            self.commit(partition, commit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::mq::kafka::{parse_id, topic_name, Pending};
+    use crate::mq::Delivery;
+
+    fn delivery(offset: i64) -> Delivery {
+        Delivery {
+            id: format!("0:{offset}"),
+            payload: Vec::new(),
+            attempts: 1,
+        }
+    }
+
+    #[test]
+    fn pending() {
+        let mut pending = Pending::default();
+        for offset in 0..3 {
+            pending.insert(0, offset, delivery(offset));
+        }
+        // 之前的消息未确认时不提交
+        assert_eq!(pending.ack(0, 1), None);
+        assert_eq!(pending.ack(0, 0), Some(2));
+        assert_eq!(pending.ack(0, 2), Some(3));
+        assert_eq!(pending.ack(0, 2), None);
+
+        pending.insert(1, 5, delivery(5));
+        let expired = pending.expired(Duration::ZERO);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].2.attempts, 2);
+
+        assert_eq!(parse_id("1:5"), Some((1, 5)));
+        assert_eq!(parse_id("bad"), None);
+        assert_eq!(topic_name("mallchat:mq:message"), "mallchat.mq.message");
+    }
+}
@@ -0,0 +1,109 @@
+//! # 踢出用户事件
+//!
+//! 管理员踢出、拉黑用户或用户下线设备后发布 [`KickEvent`] 到 [`KICK_TOPIC`]，
+//! 由 [`KickSessions`] 在各实例上关闭对应的连接，消费组与新消息的推送相同，按实例创建。
+//! token 的吊销记录保存在 Redis 中，不通过消息队列同步。
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::handler::ws::SessionManager;
+use crate::mq::{send_json, Handler, Producer};
+
+/// 踢出用户的主题
+pub const KICK_TOPIC: &str = "mallchat:mq:kick";
+
+/// 踢出用户事件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KickEvent {
+    /// 用户 ID
+    pub uid: i64,
+    /// 设备 ID，为空时关闭用户的所有连接
+    pub device: Option<String>,
+    /// 原因，会在关闭连接时发送给客户端
+    pub reason: String,
+}
+
+/// 关闭本实例上对应的连接，返回关闭的连接数
+pub fn kick_sessions(session_manager: &SessionManager, event: &KickEvent) -> anyhow::Result<usize> {
+    match &event.device {
+        Some(device) => session_manager.kick_device(event.uid, device, &event.reason),
+        None => session_manager.kick_user(event.uid, &event.reason),
+    }
+}
+
+/// 踢出用户：有消息队列时发布给所有实例，否则只关闭本实例的连接；失败时只输出日志
+pub async fn kick(
+    session_manager: &SessionManager,
+    producer: Option<&dyn Producer>,
+    event: KickEvent,
+) {
+    let result = match producer {
+        Some(producer) => send_json(producer, KICK_TOPIC, &event).await.map(|_| ()),
+        None => kick_sessions(session_manager, &event).map(|_| ()),
+    };
+    if let Err(error) = result {
+        tracing::error!(uid = event.uid, device = event.device, %error, "Failed to kick sessions.");
+    }
+}
+
+/// 关闭被踢出用户的连接
+#[derive(Debug, Clone)]
+pub struct KickSessions {
+    session_manager: SessionManager,
+}
+
+impl KickSessions {
+    /// 创建
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self { session_manager }
+    }
+}
+
+#[async_trait]
+impl Handler for KickSessions {
+    fn name(&self) -> &str {
+        "kick_sessions"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let event: KickEvent = serde_json::from_slice(payload)?;
+        let kicked = kick_sessions(&self.session_manager, &event)?;
+        tracing::info!(uid = event.uid, device = event.device, %kicked, "Sessions kicked.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::handler::ws::SessionManager;
+    use crate::mq::kick::{kick_sessions, KickEvent};
+
+    #[tokio::test]
+    async fn kick_device() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (phone, mut receiver) = session_manager.connect(1);
+        session_manager.set_device(phone, "phone".to_string());
+        let (web, _web_receiver) = session_manager.connect(1);
+        session_manager.set_device(web, "web".to_string());
+
+        let event = KickEvent {
+            uid: 1,
+            device: Some("phone".to_string()),
+            reason: "Logged out".to_string(),
+        };
+        assert_eq!(kick_sessions(&session_manager, &event)?, 1);
+        assert!(matches!(receiver.recv().await, Some(Message::Text(_))));
+        assert!(matches!(receiver.recv().await, Some(Message::Close(_))));
+
+        let event = KickEvent {
+            device: None,
+            ..event
+        };
+        assert_eq!(kick_sessions(&session_manager, &event)?, 1);
+        Ok(())
+    }
+}
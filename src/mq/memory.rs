@@ -0,0 +1,221 @@
+//! # 内存实现
+//!
+//! 用于测试和单实例部署，进程退出后消息丢失。未确认的消息在下一次拉取时立即重新投递
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::mq::{dead_letter_topic, Consumer, Delivery, Producer};
+
+#[derive(Debug, Default)]
+struct Group {
+    queue: Mutex<VecDeque<Delivery>>,
+    pending: Mutex<Vec<Delivery>>,
+    notify: Notify,
+}
+
+#[derive(Debug, Default)]
+struct Topics {
+    next_id: u64,
+    groups: HashMap<String, HashMap<String, Arc<Group>>>,
+    dead: HashMap<String, Vec<Vec<u8>>>,
+}
+
+/// 内存消息队列
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMq {
+    topics: Arc<Mutex<Topics>>,
+}
+
+impl MemoryMq {
+    /// 创建消费者，消费组不存在时只能收到之后发送的消息
+    pub fn consumer(&self, topic: &str, group: &str, max_attempts: usize) -> MemoryConsumer {
+        let group = self
+            .topics
+            .lock()
+            .groups
+            .entry(topic.to_string())
+            .or_default()
+            .entry(group.to_string())
+            .or_default()
+            .clone();
+        MemoryConsumer {
+            mq: self.clone(),
+            topic: topic.to_string(),
+            group,
+            max_attempts,
+            block: Duration::from_millis(100),
+        }
+    }
+
+    /// 死信队列中的消息
+    pub fn dead_letters(&self, topic: &str) -> Vec<Vec<u8>> {
+        self.topics
+            .lock()
+            .dead
+            .get(&dead_letter_topic(topic))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Producer for MemoryMq {
+    async fn send(&self, topic: &str, payload: &[u8]) -> anyhow::Result<String> {
+        let (id, groups) = {
+            let mut topics = self.topics.lock();
+            topics.next_id += 1;
+            let groups: Vec<_> = topics
+                .groups
+                .get(topic)
+                .map(|groups| groups.values().cloned().collect())
+                .unwrap_or_default();
+            (topics.next_id.to_string(), groups)
+        };
+        for group in groups {
+            group.queue.lock().push_back(Delivery {
+                id: id.clone(),
+                payload: payload.to_vec(),
+                attempts: 0,
+            });
+            group.notify.notify_one();
+        }
+        Ok(id)
+    }
+}
+
+/// 内存消息队列的消费者
+#[derive(Debug)]
+pub struct MemoryConsumer {
+    mq: MemoryMq,
+    topic: String,
+    group: Arc<Group>,
+    max_attempts: usize,
+    block: Duration,
+}
+
+impl MemoryConsumer {
+    fn take(&self) -> Vec<Delivery> {
+        let mut pending = self.group.pending.lock();
+        let mut deliveries: Vec<_> = pending.drain(..).collect();
+        deliveries.extend(self.group.queue.lock().drain(..));
+
+        let mut dead = Vec::new();
+        deliveries.retain_mut(|delivery| {
+            delivery.attempts += 1;
+            let retain = delivery.attempts <= self.max_attempts;
+            if !retain {
+                dead.push(delivery.payload.clone());
+            }
+            retain
+        });
+        if !dead.is_empty() {
+            self.mq
+                .topics
+                .lock()
+                .dead
+                .entry(dead_letter_topic(&self.topic))
+                .or_default()
+                .extend(dead);
+        }
+        pending.extend(deliveries.iter().cloned());
+        deliveries
+    }
+}
+
+#[async_trait]
+impl Consumer for MemoryConsumer {
+    async fn poll(&self) -> anyhow::Result<Vec<Delivery>> {
+        let deliveries = self.take();
+        if !deliveries.is_empty() {
+            return Ok(deliveries);
+        }
+        let _ = tokio::time::timeout(self.block, self.group.notify.notified()).await;
+        Ok(self.take())
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> anyhow::Result<()> {
+        self.group
+            .pending
+            .lock()
+            .retain(|pending| pending.id != delivery.id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    use crate::handler::context::RequestContext;
+    use crate::mq::memory::MemoryMq;
+    use crate::mq::{send_json, subscribe, Consumer, Handler, Producer};
+
+    #[tokio::test]
+    async fn retry_and_dead_letter() -> anyhow::Result<()> {
+        let mq = MemoryMq::default();
+        let first = mq.consumer("topic", "first", 2);
+        let second = mq.consumer("topic", "second", 2);
+        mq.send("topic", b"hello").await?;
+
+        // 每个消费组都能收到
+        let deliveries = first.poll().await?;
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].payload, b"hello");
+        assert_eq!(deliveries[0].attempts, 1);
+        first.ack(&deliveries[0]).await?;
+        assert!(first.poll().await?.is_empty());
+
+        // 未确认时重新投递，超过最大次数后转入死信队列
+        assert_eq!(second.poll().await?[0].attempts, 1);
+        assert_eq!(second.poll().await?[0].attempts, 2);
+        assert!(second.poll().await?.is_empty());
+        assert_eq!(mq.dead_letters("topic"), vec![b"hello".to_vec()]);
+        Ok(())
+    }
+
+    struct Capture(mpsc::UnboundedSender<Option<String>>);
+
+    #[async_trait]
+    impl Handler for Capture {
+        fn name(&self) -> &str {
+            "capture"
+        }
+
+        async fn handle(&self, _payload: &[u8]) -> anyhow::Result<()> {
+            let _ = self.0.send(RequestContext::current_request_id());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn request_id() -> anyhow::Result<()> {
+        let mq = MemoryMq::default();
+        let consumer = mq.consumer("topic", "group", 2);
+        let capture = mq.consumer("topic", "capture", 2);
+        let event = serde_json::json!({ "uid": 1 });
+        RequestContext::new("abc")
+            .scope(send_json(&mq, "topic", &event))
+            .await?;
+        send_json(&mq, "topic", &event).await?;
+
+        let deliveries = consumer.poll().await?;
+        let payload: serde_json::Value = serde_json::from_slice(&deliveries[0].payload)?;
+        assert_eq!(payload, serde_json::json!({ "uid": 1, "requestId": "abc" }));
+        assert_eq!(deliveries[1].payload, br#"{"uid":1}"#);
+
+        // 处理时恢复请求上下文
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = subscribe(capture, Capture(sender));
+        assert_eq!(receiver.recv().await, Some(Some("abc".to_string())));
+        assert_eq!(receiver.recv().await, Some(None));
+        task.abort();
+        Ok(())
+    }
+}
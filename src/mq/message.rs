@@ -0,0 +1,489 @@
+//! # 新消息事件
+//!
+//! 发送消息后发布 [`MessageEvent`]，由以下消费者处理：
+//!
+//! - [`PushMessage`]：推送给在线用户，每个实例只推送给自己的连接，因此按实例创建消费组
+//! - [`HotRoom`]：更新房间热度，所有实例共用一个消费组
+//! - [`DiscoverUrl`]：生成消息中链接的预览，保存后发布到 [`MESSAGE_UPDATE_TOPIC`]，
+//!   再由 [`PushMessage::update`] 推送给在线用户
+//! - [`BotMention`](crate::mq::bot::BotMention)：消息 @ 了聊天机器人时发布回复请求
+//! - [`OfflinePush`]：通知被 @ 或收到私聊消息的离线用户
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::handler::chat::MessageResp;
+use crate::handler::ws::push::WsPush;
+use crate::handler::ws::SessionManager;
+use crate::mq::{send_json, DynProducer, Handler};
+use crate::push::{mentions, DynPushProvider, Notification, NotificationKind, PushConfig};
+use crate::service::hot_room::HotRoomService;
+use crate::storage::repo::{DynMessageRepo, DynUserRepo};
+use crate::url_discover::{extract_urls, UrlDiscover, URL_CONTENT_MAP};
+
+/// 新消息事件的主题
+pub const MESSAGE_TOPIC: &str = "mallchat:mq:message";
+
+/// 消息更新事件的主题，事件内容同样为 [`MessageEvent`]
+pub const MESSAGE_UPDATE_TOPIC: &str = "mallchat:mq:message_update";
+
+/// 房间热度的消费组
+pub const HOT_ROOM_GROUP: &str = "hot_room";
+
+/// 链接预览的消费组
+pub const URL_DISCOVER_GROUP: &str = "url_discover";
+
+/// 离线推送的消费组
+pub const OFFLINE_PUSH_GROUP: &str = "offline_push";
+
+/// 推送的消费组，每个实例一个
+pub fn push_group(instance_id: &str) -> String {
+    format!("push:{instance_id}")
+}
+
+/// 新消息事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEvent {
+    /// 消息
+    pub message: MessageResp,
+    /// 接收者，为空时推送给所有已登录的用户
+    pub receivers: Option<Vec<i64>>,
+}
+
+/// 推送新消息或消息更新
+#[derive(Debug, Clone)]
+pub struct PushMessage {
+    session_manager: SessionManager,
+    update: bool,
+}
+
+impl PushMessage {
+    /// 推送新消息，消费 [`MESSAGE_TOPIC`]
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self {
+            session_manager,
+            update: false,
+        }
+    }
+
+    /// 推送消息更新，消费 [`MESSAGE_UPDATE_TOPIC`]
+    pub fn update(session_manager: SessionManager) -> Self {
+        Self {
+            session_manager,
+            update: true,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for PushMessage {
+    fn name(&self) -> &str {
+        if self.update {
+            "push_message_update"
+        } else {
+            "push_message"
+        }
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let MessageEvent { message, receivers } = serde_json::from_slice(payload)?;
+        let push = if self.update {
+            WsPush::MsgUpdate(message)
+        } else {
+            WsPush::NewMessage(message)
+        };
+        match receivers {
+            Some(receivers) => {
+                for uid in receivers {
+                    self.session_manager.send_to_user(uid, &push)?;
+                }
+            }
+            None => {
+                self.session_manager.broadcast_all(&push, true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 更新房间热度
+#[derive(Debug, Clone)]
+pub struct HotRoom {
+    cache: Cache,
+}
+
+impl HotRoom {
+    /// 创建
+    pub fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+}
+
+#[async_trait]
+impl Handler for HotRoom {
+    fn name(&self) -> &str {
+        "hot_room"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let event: MessageEvent = serde_json::from_slice(payload)?;
+        HotRoomService::new(&self.cache)
+            .record_message(event.message.room_id)
+            .await?;
+        Ok(())
+    }
+}
+
+/// 生成链接预览
+#[derive(Clone)]
+pub struct DiscoverUrl {
+    messages: DynMessageRepo,
+    producer: DynProducer,
+    discover: UrlDiscover,
+}
+
+impl DiscoverUrl {
+    /// 创建
+    pub fn new(messages: DynMessageRepo, producer: DynProducer, discover: UrlDiscover) -> Self {
+        Self {
+            messages,
+            producer,
+            discover,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for DiscoverUrl {
+    fn name(&self) -> &str {
+        "discover_url"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let MessageEvent {
+            mut message,
+            receivers,
+        } = serde_json::from_slice(payload)?;
+        // 重新投递时已经生成过的不再抓取
+        if message.url_content_map.is_some()
+            || extract_urls(&message.content, self.discover.config().max_urls).is_empty()
+        {
+            return Ok(());
+        }
+        let url_content_map = self.discover.discover(&message.content).await;
+        if url_content_map.is_empty() {
+            return Ok(());
+        }
+
+        let Some(model) = self.messages.find_by_id(message.id).await? else {
+            return Ok(());
+        };
+        let mut extra = match model.extra {
+            Some(serde_json::Value::Object(extra)) => extra,
+            _ => serde_json::Map::new(),
+        };
+        extra.insert(
+            URL_CONTENT_MAP.to_string(),
+            serde_json::to_value(&url_content_map)?,
+        );
+        self.messages
+            .update_extra(message.id, serde_json::Value::Object(extra))
+            .await?;
+
+        message.url_content_map = Some(url_content_map);
+        let event = MessageEvent { message, receivers };
+        send_json(self.producer.as_ref(), MESSAGE_UPDATE_TOPIC, &event).await?;
+        Ok(())
+    }
+}
+
+/// 通知被 @ 或收到私聊消息的离线用户
+///
+/// 是否在线只检查当前实例的连接，多实例部署时连接在其他实例上的用户也会收到通知
+#[derive(Clone)]
+pub struct OfflinePush {
+    config: PushConfig,
+    users: DynUserRepo,
+    session_manager: SessionManager,
+    providers: Vec<DynPushProvider>,
+}
+
+impl OfflinePush {
+    /// 创建
+    pub fn new(
+        config: PushConfig,
+        users: DynUserRepo,
+        session_manager: SessionManager,
+        providers: Vec<DynPushProvider>,
+    ) -> Self {
+        Self {
+            config,
+            users,
+            session_manager,
+            providers,
+        }
+    }
+
+    /// 需要通知的用户，不包括发送者
+    async fn targets(
+        &self,
+        message: &MessageResp,
+        receivers: Option<Vec<i64>>,
+    ) -> anyhow::Result<Vec<(i64, NotificationKind)>> {
+        let mut targets = Vec::new();
+        match receivers {
+            Some(receivers) => {
+                targets.extend(
+                    receivers
+                        .into_iter()
+                        .map(|uid| (uid, NotificationKind::Direct)),
+                );
+            }
+            None => {
+                for name in mentions(&message.content, self.config.max_mentions) {
+                    if let Some(user) = self.users.find_by_name(name).await? {
+                        targets.push((user.id as i64, NotificationKind::Mention));
+                    }
+                }
+            }
+        }
+        targets.retain(|(uid, _)| *uid != message.from_uid);
+        Ok(targets)
+    }
+}
+
+#[async_trait]
+impl Handler for OfflinePush {
+    fn name(&self) -> &str {
+        "offline_push"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let MessageEvent { message, receivers } = serde_json::from_slice(payload)?;
+        let targets: Vec<_> = self
+            .targets(&message, receivers)
+            .await?
+            .into_iter()
+            .filter(|(uid, _)| !self.session_manager.is_online(*uid))
+            .collect();
+        if targets.is_empty() {
+            return Ok(());
+        }
+
+        let sender_name = self
+            .users
+            .find_by_id(message.from_uid)
+            .await?
+            .and_then(|user| user.name)
+            .unwrap_or_default();
+        let uids: Vec<_> = targets.iter().map(|(uid, _)| *uid).collect();
+        let users = self.users.find_by_ids(&uids).await?;
+        let content = self.config.summary(&message.content);
+        // 查询完成后不再返回错误，避免重新投递时重复通知
+        for (uid, kind) in targets {
+            let Some(user) = users.iter().find(|user| user.id as i64 == uid) else {
+                continue;
+            };
+            let notification = Notification {
+                kind,
+                uid,
+                open_id: user.open_id.clone(),
+                room_id: message.room_id,
+                message_id: message.id,
+                sender_name: sender_name.clone(),
+                content: content.clone(),
+            };
+            for provider in &self.providers {
+                if let Err(error) = provider.push(&notification).await {
+                    tracing::warn!(%uid, provider = provider.name(), %error, "Failed to push offline notification.");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::extract::ws::Message;
+
+    use std::sync::Arc;
+
+    use axum::response::Html;
+    use axum::routing::get;
+    use axum::Router;
+    use sea_orm::Set;
+
+    use crate::handler::chat::MessageResp;
+    use crate::handler::ws::SessionManager;
+    use crate::mq::memory::MemoryMq;
+    use crate::mq::message::{DiscoverUrl, MessageEvent, OfflinePush, PushMessage};
+    use crate::mq::message::{MESSAGE_TOPIC, MESSAGE_UPDATE_TOPIC};
+    use crate::mq::{send_json, subscribe, Consumer, Handler};
+    use crate::push::wechat::{WxTemplateConfig, WxTemplatePush};
+    use crate::push::PushConfig;
+    use crate::storage::model::message;
+    use crate::storage::repo::MessageRepo;
+    use crate::testing::{MemoryRepo, MockWxClient};
+    use crate::url_discover::{UrlDiscover, UrlDiscoverConfig, URL_CONTENT_MAP};
+
+    #[tokio::test]
+    async fn push_message() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (_id, mut receiver) = session_manager.connect(1);
+        let (_id, mut other) = session_manager.connect(3);
+
+        let mq = MemoryMq::default();
+        let task = subscribe(
+            mq.consumer(MESSAGE_TOPIC, "push", 3),
+            PushMessage::new(session_manager),
+        );
+        let event = MessageEvent {
+            message: MessageResp {
+                id: 1,
+                room_id: 2,
+                from_uid: 1,
+                content: "hello".to_string(),
+                reply_msg_id: None,
+                send_time: time::macros::datetime!(2023-06-01 00:00),
+                from_region: None,
+                url_content_map: None,
+                client_msg_id: None,
+            },
+            receivers: Some(vec![1, 2]),
+        };
+        send_json(&mq, MESSAGE_TOPIC, &event).await?;
+
+        let Some(Message::Text(text)) = receiver.recv().await else {
+            anyhow::bail!("message not pushed");
+        };
+        assert!(text.contains(r#""content":"hello""#));
+        let pushed = tokio::time::timeout(Duration::from_millis(50), other.recv()).await;
+        assert!(pushed.is_err());
+        task.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discover_url() -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let page = Router::new().route(
+            "/",
+            get(|| async { Html("<html><head><title>MallChat</title></head></html>") }),
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, page).await });
+
+        let repo = Arc::new(MemoryRepo::default());
+        let model = MessageRepo::create(
+            repo.as_ref(),
+            message::ActiveModel {
+                room_id: Set(1),
+                from_uid: Set(1),
+                content: Set(format!("see http://{addr}/")),
+                status: Set(0),
+                extra: Set(Some(serde_json::json!({ "other": 1 }))),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let mq = MemoryMq::default();
+        let updates = mq.consumer(MESSAGE_UPDATE_TOPIC, "push", 3);
+        let handler = DiscoverUrl::new(
+            repo.clone(),
+            Arc::new(mq.clone()),
+            UrlDiscover::new(UrlDiscoverConfig {
+                allow_private_network: true,
+                ..Default::default()
+            }),
+        );
+        let event = MessageEvent {
+            message: MessageResp::from(model),
+            receivers: None,
+        };
+        handler.handle(&serde_json::to_vec(&event)?).await?;
+
+        let Some(extra) = MessageRepo::find_by_id(repo.as_ref(), 1)
+            .await?
+            .and_then(|model| model.extra)
+        else {
+            anyhow::bail!("extra not saved");
+        };
+        assert_eq!(extra["other"], 1);
+        let url = format!("http://{addr}/");
+        assert_eq!(extra[URL_CONTENT_MAP][&url]["title"], "MallChat");
+
+        let deliveries = updates.poll().await?;
+        assert_eq!(deliveries.len(), 1);
+        let updated: MessageEvent = serde_json::from_slice(&deliveries[0].payload)?;
+        let title = updated
+            .message
+            .url_content_map
+            .and_then(|map| map.get(&url).and_then(|info| info.title.clone()));
+        assert_eq!(title.as_deref(), Some("MallChat"));
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offline_push() -> anyhow::Result<()> {
+        let repo = Arc::new(MemoryRepo::default());
+        let sender = repo.add_user("sender", Some("张三"));
+        let online = repo.add_user("online", Some("李四"));
+        let offline = repo.add_user("offline", Some("王五"));
+        let session_manager = SessionManager::default();
+        let (_id, _receiver) = session_manager.connect(online);
+
+        let wx_client = Arc::new(MockWxClient::default());
+        let provider = WxTemplatePush::new(
+            WxTemplateConfig {
+                app_id: None,
+                template_id: "template".to_string(),
+                url: None,
+            },
+            wx_client.clone(),
+        );
+        let handler = OfflinePush::new(
+            PushConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            repo.clone(),
+            session_manager,
+            vec![Arc::new(provider)],
+        );
+        let event = |content: &str, receivers: Option<Vec<i64>>| MessageEvent {
+            message: MessageResp {
+                id: 1,
+                room_id: 2,
+                from_uid: sender,
+                content: content.to_string(),
+                reply_msg_id: None,
+                send_time: time::macros::datetime!(2023-06-01 00:00),
+                from_region: None,
+                url_content_map: None,
+                client_msg_id: None,
+            },
+            receivers,
+        };
+
+        let mention = event("@张三 @李四 @王五 @赵六 你好", None);
+        handler.handle(&serde_json::to_vec(&mention)?).await?;
+        let direct = event("你好", Some(vec![sender, offline]));
+        handler.handle(&serde_json::to_vec(&direct)?).await?;
+        let direct = event("你好", Some(vec![sender, online]));
+        handler.handle(&serde_json::to_vec(&direct)?).await?;
+
+        let sent = wx_client.sent_templates();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|message| message.touser == "offline"));
+        assert_eq!(sent[0].data["title"].value, "有人在群聊中@了你");
+        assert_eq!(sent[0].data["sender"].value, "张三");
+        assert_eq!(sent[1].data["title"].value, "你收到了一条私聊消息");
+        Ok(())
+    }
+}
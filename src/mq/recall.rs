@@ -0,0 +1,104 @@
+//! # 消息撤回事件
+//!
+//! 管理员处理举报删除消息后发布 [`RecallEvent`] 到 [`RECALL_TOPIC`]，
+//! 由 [`PushRecall`] 在各实例上推送，消费组与新消息的推送相同，按实例创建。
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::handler::ws::push::{MsgRecall, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::mq::Handler;
+
+/// 消息撤回的主题
+pub const RECALL_TOPIC: &str = "mallchat:mq:recall";
+
+/// 消息撤回事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecallEvent {
+    /// 撤回的消息
+    pub recall: MsgRecall,
+    /// 接收者，与新消息相同，为空时推送给所有已登录的用户
+    pub receivers: Option<Vec<i64>>,
+}
+
+/// 推送消息撤回给本实例的连接，返回推送的连接数
+pub fn push_recall(session_manager: &SessionManager, event: RecallEvent) -> anyhow::Result<usize> {
+    let push = WsPush::MsgRecall(event.recall);
+    match event.receivers {
+        Some(receivers) => {
+            let mut pushed = 0;
+            for uid in receivers {
+                pushed += session_manager.send_to_user(uid, &push)?;
+            }
+            Ok(pushed)
+        }
+        None => session_manager.broadcast_all(&push, true),
+    }
+}
+
+/// 推送消息撤回
+#[derive(Debug, Clone)]
+pub struct PushRecall {
+    session_manager: SessionManager,
+}
+
+impl PushRecall {
+    /// 创建
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self { session_manager }
+    }
+}
+
+#[async_trait]
+impl Handler for PushRecall {
+    fn name(&self) -> &str {
+        "push_recall"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let event: RecallEvent = serde_json::from_slice(payload)?;
+        push_recall(&self.session_manager, event)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::handler::ws::push::MsgRecall;
+    use crate::handler::ws::SessionManager;
+    use crate::mq::recall::{push_recall, PushRecall, RecallEvent};
+    use crate::mq::Handler;
+
+    #[tokio::test]
+    async fn push() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (_id, mut receiver) = session_manager.connect(1);
+        let (_other, _) = session_manager.connect(3);
+        let event = RecallEvent {
+            recall: MsgRecall {
+                msg_id: 10,
+                room_id: 2,
+                recall_uid: 9,
+            },
+            receivers: Some(vec![1, 2]),
+        };
+        // 单聊只推送给双方
+        assert_eq!(push_recall(&session_manager, event.clone())?, 1);
+        PushRecall::new(session_manager)
+            .handle(&serde_json::to_vec(&event)?)
+            .await?;
+
+        let Some(Message::Text(json)) = receiver.recv().await else {
+            anyhow::bail!("expect a text frame");
+        };
+        let push: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(push["type"], 9);
+        assert_eq!(push["data"]["msgId"], 10);
+        assert_eq!(push["data"]["recallUid"], 9);
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! # Redis Streams 实现
+//!
+//! 主题对应一个 stream，消息内容保存在 `payload` 字段中。
+//! 消费者通过 `XREADGROUP` 拉取新消息，通过 `XPENDING`、`XCLAIM` 接管超时未确认的消息，
+//! 兼容 Redis 5.0 及以上版本。
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamMaxlen, StreamPendingCountReply, StreamReadOptions,
+    StreamReadReply,
+};
+use redis::AsyncCommands;
+use tokio::sync::Mutex;
+
+use crate::cache::{Cache, CacheConnection};
+use crate::mq::{dead_letter_topic, Consumer, Delivery, MqConfig, Producer};
+
+const PAYLOAD: &str = "payload";
+
+/// 基于 Redis Streams 的消息队列
+#[derive(Debug, Clone)]
+pub struct RedisStreams {
+    client: Cache,
+    config: Arc<MqConfig>,
+}
+
+impl RedisStreams {
+    /// 创建
+    pub fn new(client: Cache, config: MqConfig) -> Self {
+        Self {
+            client,
+            config: Arc::new(config),
+        }
+    }
+
+    /// 配置
+    pub fn config(&self) -> &MqConfig {
+        &self.config
+    }
+
+    /// 创建消费者，消费组不存在时从最新的消息开始消费
+    pub async fn consumer(
+        &self,
+        topic: &str,
+        group: &str,
+        name: &str,
+    ) -> anyhow::Result<StreamConsumer> {
+        // 拉取消息时会阻塞连接，每个消费者使用单独的连接
+        let mut connection = self.client.dedicated_connection().await?;
+        let created: redis::RedisResult<()> =
+            connection.xgroup_create_mkstream(topic, group, "$").await;
+        match created {
+            Ok(()) => tracing::info!(%topic, %group, "Consumer group created."),
+            Err(error) if error.code() == Some("BUSYGROUP") => {}
+            Err(error) => return Err(error.into()),
+        }
+        Ok(StreamConsumer {
+            connection: Mutex::new(connection),
+            topic: topic.to_string(),
+            group: group.to_string(),
+            name: name.to_string(),
+            config: self.config.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Producer for RedisStreams {
+    async fn send(&self, topic: &str, payload: &[u8]) -> anyhow::Result<String> {
+        let mut connection = self.client.connection().await?;
+        let id = connection
+            .xadd_maxlen(
+                topic,
+                StreamMaxlen::Approx(self.config.max_len),
+                "*",
+                &[(PAYLOAD, payload)],
+            )
+            .await?;
+        Ok(id)
+    }
+}
+
+/// Redis Streams 消费者
+pub struct StreamConsumer {
+    connection: Mutex<CacheConnection>,
+    topic: String,
+    group: String,
+    name: String,
+    config: Arc<MqConfig>,
+}
+
+impl std::fmt::Debug for StreamConsumer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamConsumer")
+            .field("topic", &self.topic)
+            .field("group", &self.group)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl StreamConsumer {
+    fn delivery(entry: &StreamId, attempts: usize) -> Delivery {
+        Delivery {
+            id: entry.id.clone(),
+            payload: entry.get(PAYLOAD).unwrap_or_default(),
+            attempts,
+        }
+    }
+
+    /// 接管超时未确认的消息，超过最大投递次数的转入死信队列
+    async fn claim(&self, connection: &mut CacheConnection) -> anyhow::Result<Vec<Delivery>> {
+        let retry_after = self.config.retry_after_secs as usize * 1000;
+        let pending: StreamPendingCountReply = connection
+            .xpending_count(&self.topic, &self.group, "-", "+", self.config.batch_size)
+            .await?;
+        let idle: Vec<_> = pending
+            .ids
+            .into_iter()
+            .filter(|pending| pending.last_delivered_ms >= retry_after)
+            .collect();
+        if idle.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<_> = idle.iter().map(|pending| pending.id.as_str()).collect();
+        let claimed: StreamClaimReply = connection
+            .xclaim(&self.topic, &self.group, &self.name, retry_after, &ids)
+            .await?;
+        let mut deliveries = Vec::with_capacity(claimed.ids.len());
+        for entry in &claimed.ids {
+            let attempts = idle
+                .iter()
+                .find(|pending| pending.id == entry.id)
+                .map_or(1, |pending| pending.times_delivered + 1);
+            let delivery = Self::delivery(entry, attempts);
+            if attempts <= self.config.max_attempts {
+                deliveries.push(delivery);
+                continue;
+            }
+            tracing::warn!(
+                topic = %self.topic,
+                group = %self.group,
+                id = %delivery.id,
+                "Message moved to dead letter queue."
+            );
+            let _: String = connection
+                .xadd_maxlen(
+                    dead_letter_topic(&self.topic),
+                    StreamMaxlen::Approx(self.config.max_len),
+                    "*",
+                    &[(PAYLOAD, delivery.payload.as_slice())],
+                )
+                .await?;
+            let _: usize = connection
+                .xack(&self.topic, &self.group, &[&delivery.id])
+                .await?;
+        }
+        Ok(deliveries)
+    }
+}
+
+#[async_trait]
+impl Consumer for StreamConsumer {
+    async fn poll(&self) -> anyhow::Result<Vec<Delivery>> {
+        let mut connection = self.connection.lock().await;
+        let mut deliveries = self.claim(&mut connection).await?;
+
+        let mut options = StreamReadOptions::default()
+            .group(&self.group, &self.name)
+            .count(self.config.batch_size);
+        // 有需要重试的消息时不等待新消息
+        if deliveries.is_empty() {
+            options = options.block(self.config.block_millis as usize);
+        }
+        let reply: Option<StreamReadReply> = connection
+            .xread_options(&[&self.topic], &[">"], &options)
+            .await?;
+        deliveries.extend(
+            reply
+                .into_iter()
+                .flat_map(|reply| reply.keys)
+                .flat_map(|key| key.ids)
+                .map(|entry| Self::delivery(&entry, 1)),
+        );
+        Ok(deliveries)
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().await;
+        let _: usize = connection
+            .xack(&self.topic, &self.group, &[&delivery.id])
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! # 正在输入事件
+//!
+//! 客户端发送正在输入请求后发布 [`TypingEvent`] 到 [`TYPING_TOPIC`]，
+//! 由 [`PushTyping`] 在各实例上推送，消费组与新消息的推送相同，按实例创建。输入状态不保存。
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::handler::ws::push::{Typing, WsPush};
+use crate::handler::ws::SessionManager;
+use crate::mq::Handler;
+
+/// 正在输入的主题
+pub const TYPING_TOPIC: &str = "mallchat:mq:typing";
+
+/// 正在输入事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypingEvent {
+    /// 输入状态
+    pub typing: Typing,
+    /// 接收者，单聊时为对方，其他群聊时为其他成员，全员群聊时为空，推送给所有在线用户
+    pub receivers: Option<Vec<i64>>,
+}
+
+/// 推送正在输入给本实例的连接，返回推送的连接数
+pub fn push_typing(session_manager: &SessionManager, event: TypingEvent) -> anyhow::Result<usize> {
+    let push = WsPush::Typing(event.typing);
+    match event.receivers {
+        Some(receivers) => receivers.into_iter().try_fold(0, |sent, uid| {
+            Ok(sent + session_manager.send_to_user(uid, &push)?)
+        }),
+        None => session_manager.broadcast_all(&push, true),
+    }
+}
+
+/// 推送正在输入
+#[derive(Debug, Clone)]
+pub struct PushTyping {
+    session_manager: SessionManager,
+}
+
+impl PushTyping {
+    /// 创建
+    pub fn new(session_manager: SessionManager) -> Self {
+        Self { session_manager }
+    }
+}
+
+#[async_trait]
+impl Handler for PushTyping {
+    fn name(&self) -> &str {
+        "push_typing"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let event: TypingEvent = serde_json::from_slice(payload)?;
+        push_typing(&self.session_manager, event)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+
+    use crate::handler::ws::push::Typing;
+    use crate::handler::ws::SessionManager;
+    use crate::mq::typing::{push_typing, TypingEvent};
+
+    #[tokio::test]
+    async fn push_to_receivers() -> anyhow::Result<()> {
+        let session_manager = SessionManager::default();
+        let (sender, _sender_receiver) = session_manager.connect(1);
+        let (_id, mut receiver) = session_manager.connect(2);
+        let event = TypingEvent {
+            typing: Typing { room_id: 3, uid: 1 },
+            receivers: Some(vec![2]),
+        };
+        assert_eq!(push_typing(&session_manager, event)?, 1);
+
+        let Some(Message::Text(json)) = receiver.recv().await else {
+            anyhow::bail!("expect a text frame");
+        };
+        let push: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(push["type"], 18);
+        assert_eq!(push["data"]["roomId"], 3);
+        assert_eq!(push["data"]["uid"], 1);
+        assert_eq!(session_manager.list(Some(1), None)[0].id, sender);
+        assert_eq!(session_manager.list(Some(1), None)[0].queued, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! # 离线推送
+//!
+//! 用户被 @ 或收到私聊消息，但在当前实例没有已登录的 WebSocket 连接时，
+//! [`OfflinePush`](crate::mq::message::OfflinePush) 通过已配置的推送渠道通知用户。
+//!
+//! 推送渠道只依赖 [`PushProvider`] trait，默认提供微信模板消息 [`wechat::WxTemplatePush`]
+//! 和通用的 [`webhook::WebhookPush`]，可以同时启用。
+
+pub mod webhook;
+pub mod wechat;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::push::webhook::{WebhookConfig, WebhookPush};
+use crate::push::wechat::{WxTemplateConfig, WxTemplatePush};
+use crate::weixin::WxClientRegistry;
+
+/// 共享的推送渠道
+pub type DynPushProvider = Arc<dyn PushProvider>;
+
+/// 推送渠道
+#[async_trait]
+pub trait PushProvider: Debug + Send + Sync {
+    /// 名称，用于日志
+    fn name(&self) -> &str;
+    /// 推送通知
+    async fn push(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+/// 通知的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    /// 群聊中被 @
+    Mention,
+    /// 私聊消息
+    Direct,
+}
+
+impl NotificationKind {
+    /// 通知的标题，推送时使用 [`i18n::tr`](crate::i18n::tr) 翻译
+    pub fn title(&self) -> &'static str {
+        match self {
+            NotificationKind::Mention => "有人在群聊中@了你",
+            NotificationKind::Direct => "你收到了一条私聊消息",
+        }
+    }
+}
+
+/// 离线通知
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    /// 原因
+    pub kind: NotificationKind,
+    /// 接收者
+    pub uid: i64,
+    /// 接收者的微信 openid
+    pub open_id: String,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 消息 ID
+    pub message_id: u64,
+    /// 发送者的昵称
+    pub sender_name: String,
+    /// 消息摘要
+    pub content: String,
+}
+
+/// 离线推送配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+    /// 消息摘要的最大长度（字符）
+    #[serde(default = "default::max_content_chars")]
+    pub max_content_chars: usize,
+    /// 一条消息中最多通知的被 @ 用户数
+    #[serde(default = "default::max_mentions")]
+    pub max_mentions: usize,
+    /// 微信模板消息，未配置时不启用
+    #[serde(default)]
+    pub wechat: Option<WxTemplateConfig>,
+    /// Webhook，未配置时不启用
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+}
+
+mod default {
+    pub fn max_content_chars() -> usize {
+        50
+    }
+
+    pub fn max_mentions() -> usize {
+        10
+    }
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_content_chars: default::max_content_chars(),
+            max_mentions: default::max_mentions(),
+            wechat: None,
+            webhook: None,
+        }
+    }
+}
+
+impl PushConfig {
+    /// 创建已配置的推送渠道
+    pub fn build(&self, wx_clients: &WxClientRegistry) -> anyhow::Result<Vec<DynPushProvider>> {
+        let mut providers: Vec<DynPushProvider> = Vec::new();
+        if let Some(wechat) = &self.wechat {
+            let wx_client = match &wechat.app_id {
+                Some(app_id) => wx_clients
+                    .get(app_id)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown weixin app_id: {app_id}"))?,
+                None => wx_clients.default_client(),
+            };
+            providers.push(Arc::new(WxTemplatePush::new(
+                wechat.clone(),
+                wx_client.clone(),
+            )));
+        }
+        if let Some(webhook) = &self.webhook {
+            providers.push(Arc::new(WebhookPush::new(webhook.clone())?));
+        }
+        Ok(providers)
+    }
+
+    /// 消息摘要，超出长度时截断并以省略号结尾
+    pub fn summary(&self, content: &str) -> String {
+        let mut chars = content.chars();
+        let summary: String = chars.by_ref().take(self.max_content_chars).collect();
+        if chars.next().is_some() {
+            format!("{summary}…")
+        } else {
+            summary
+        }
+    }
+}
+
+/// 消息中 `@昵称` 的昵称，昵称到空白或下一个 `@` 为止，去重后最多返回 `limit` 个
+pub fn mentions(content: &str, limit: usize) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for segment in content.split('@').skip(1) {
+        let name = segment
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+        if name.is_empty() || names.contains(&name) {
+            continue;
+        }
+        if names.len() == limit {
+            break;
+        }
+        names.push(name);
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::push::{mentions, PushConfig};
+
+    #[test]
+    fn mention_names() {
+        assert_eq!(mentions("@张三 @李四 你好 @张三", 10), vec!["张三", "李四"]);
+        assert_eq!(mentions("@张三@李四\n@王五", 2), vec!["张三", "李四"]);
+        assert!(mentions("a@ b @ c", 10).is_empty());
+    }
+
+    #[test]
+    fn summary() {
+        let config = PushConfig {
+            max_content_chars: 3,
+            ..Default::default()
+        };
+        assert_eq!(config.summary("你好"), "你好");
+        assert_eq!(config.summary("你好啊"), "你好啊");
+        assert_eq!(config.summary("你好啊！"), "你好啊…");
+    }
+}
@@ -0,0 +1,159 @@
+//! # Webhook
+//!
+//! 以 JSON 格式将 [`Notification`] POST 到配置的地址，由接收方转发到 APNs、FCM 等推送服务。
+//! 配置了密钥时，请求头 [`SIGNATURE_HEADER`] 携带请求体的 HMAC-SHA256 签名（十六进制）。
+
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::push::{Notification, PushProvider};
+
+/// 签名的请求头
+pub const SIGNATURE_HEADER: &str = "X-MallChat-Signature";
+
+/// Webhook 配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// 地址
+    pub url: String,
+    /// 签名密钥，为空时不签名
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// 超时时间（秒）
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+mod default {
+    pub fn timeout_secs() -> u64 {
+        5
+    }
+}
+
+/// 通过 Webhook 推送
+pub struct WebhookPush {
+    config: WebhookConfig,
+    client: reqwest::Client,
+}
+
+impl Debug for WebhookPush {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookPush")
+            .field("url", &self.config.url)
+            .finish()
+    }
+}
+
+impl WebhookPush {
+    /// 创建
+    pub fn new(config: WebhookConfig) -> anyhow::Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        Ok(Self { config, client })
+    }
+}
+
+/// 请求体的签名
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[async_trait]
+impl PushProvider for WebhookPush {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn push(&self, notification: &Notification) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(notification)?;
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.config.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("webhook responded with {status}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::sync::mpsc;
+
+    use crate::push::webhook::{sign, WebhookConfig, WebhookPush, SIGNATURE_HEADER};
+    use crate::push::{Notification, NotificationKind, PushProvider};
+
+    #[tokio::test]
+    async fn webhook() -> anyhow::Result<()> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let api = Router::new().route(
+            "/push",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let signature = headers
+                    .get(SIGNATURE_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                let _ = sender.send((signature, body));
+                StatusCode::NO_CONTENT
+            }),
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, api).await });
+
+        let push = WebhookPush::new(WebhookConfig {
+            url: format!("http://{addr}/push"),
+            secret: Some("secret".to_string()),
+            timeout_secs: 1,
+        })?;
+        let notification = Notification {
+            kind: NotificationKind::Mention,
+            uid: 2,
+            open_id: "open_id".to_string(),
+            room_id: 1,
+            message_id: 3,
+            sender_name: "张三".to_string(),
+            content: "@李四 你好".to_string(),
+        };
+        push.push(&notification).await?;
+
+        let Some((signature, body)) = receiver.recv().await else {
+            anyhow::bail!("webhook not called");
+        };
+        assert_eq!(signature, Some(sign("secret", &body)));
+        let received: Notification = serde_json::from_slice(&body)?;
+        assert_eq!(received, notification);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body)?["kind"],
+            "mention"
+        );
+
+        let push = WebhookPush::new(WebhookConfig {
+            url: format!("http://{addr}/missing"),
+            secret: None,
+            timeout_secs: 1,
+        })?;
+        assert!(push.push(&notification).await.is_err());
+        server.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! # 微信模板消息
+//!
+//! 通过公众号的模板消息通知用户，模板中需要包含 `{{title.DATA}}`、`{{sender.DATA}}` 和
+//! `{{content.DATA}}` 三个变量，分别为通知的标题、发送者的昵称和消息摘要。
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n;
+use crate::push::{Notification, PushProvider};
+use crate::weixin::{DynWxApi, WxTemplateMessage};
+
+/// 微信模板消息配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WxTemplateConfig {
+    /// 发送模板消息的公众号的 app_id，为空时使用默认公众号
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// 模板 ID
+    pub template_id: String,
+    /// 点击模板消息后跳转的链接
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// 通过微信模板消息推送
+#[derive(Debug, Clone)]
+pub struct WxTemplatePush {
+    config: WxTemplateConfig,
+    wx_client: DynWxApi,
+}
+
+impl WxTemplatePush {
+    /// 创建
+    pub fn new(config: WxTemplateConfig, wx_client: DynWxApi) -> Self {
+        Self { config, wx_client }
+    }
+
+    /// 通知对应的模板消息
+    fn message(&self, notification: &Notification) -> WxTemplateMessage {
+        let data = BTreeMap::from([
+            (
+                "title".to_string(),
+                i18n::tr(notification.kind.title()).into_owned(),
+            ),
+            ("sender".to_string(), notification.sender_name.clone()),
+            ("content".to_string(), notification.content.clone()),
+        ]);
+        WxTemplateMessage {
+            touser: notification.open_id.clone(),
+            template_id: self.config.template_id.clone(),
+            url: self.config.url.clone(),
+            data: data
+                .into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl PushProvider for WxTemplatePush {
+    fn name(&self) -> &str {
+        "wechat"
+    }
+
+    async fn push(&self, notification: &Notification) -> anyhow::Result<()> {
+        self.wx_client
+            .send_template_message(&self.message(notification))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::push::wechat::{WxTemplateConfig, WxTemplatePush};
+    use crate::push::{Notification, NotificationKind, PushProvider};
+    use crate::testing::MockWxClient;
+
+    #[tokio::test]
+    async fn template_message() -> anyhow::Result<()> {
+        let wx_client = Arc::new(MockWxClient::default());
+        let push = WxTemplatePush::new(
+            WxTemplateConfig {
+                app_id: None,
+                template_id: "template".to_string(),
+                url: Some("https://mallchat.cn".to_string()),
+            },
+            wx_client.clone(),
+        );
+        push.push(&Notification {
+            kind: NotificationKind::Direct,
+            uid: 2,
+            open_id: "open_id".to_string(),
+            room_id: 1,
+            message_id: 3,
+            sender_name: "张三".to_string(),
+            content: "你好".to_string(),
+        })
+        .await?;
+
+        let sent = wx_client.sent_templates();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&sent[0])?,
+            serde_json::json!({
+                "touser": "open_id",
+                "template_id": "template",
+                "url": "https://mallchat.cn",
+                "data": {
+                    "content": { "value": "你好" },
+                    "sender": { "value": "张三" },
+                    "title": { "value": "你收到了一条私聊消息" },
+                },
+            })
+        );
+        Ok(())
+    }
+}
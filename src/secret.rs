@@ -0,0 +1,100 @@
+//! # 密钥
+//!
+//! 配置中的密钥不必以明文写在 `server.toml` 中：
+//!
+//! - 值中的 `${NAME}` 替换为环境变量 `NAME` 的值，`$$` 表示 `$` 本身
+//! - `jwt_secret_file`、`app_secret_file` 等 `*_file` 配置从文件读取，适用于 Docker/Kubernetes secrets
+//!
+//! 启动时解析所有密钥，缺少环境变量、文件无法读取或没有配置时返回指明具体配置项的错误。
+
+use std::path::Path;
+
+use anyhow::Context;
+
+/// 替换 `value` 中的 `${NAME}` 为环境变量的值
+///
+/// `name` 为配置项名称，用于错误信息
+pub fn interpolate(name: &str, value: &str) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(stripped) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('{') {
+            let end = stripped
+                .find('}')
+                .with_context(|| format!("Secret `{name}`: unclosed `${{` in value"))?;
+            let var = &stripped[..end];
+            let env = std::env::var(var).with_context(|| {
+                format!("Secret `{name}`: environment variable `{var}` is not set")
+            })?;
+            result.push_str(&env);
+            rest = &stripped[end + 1..];
+        } else {
+            result.push('$');
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// 解析密钥，配置了 `file` 时从文件读取（去掉末尾的换行），否则对 `value` 进行环境变量替换
+///
+/// `name` 为配置项名称，如 `http.jwt_secret`，两者都没有配置时返回错误
+pub fn resolve(name: &str, value: &str, file: Option<&Path>) -> anyhow::Result<String> {
+    let secret = match file {
+        Some(file) => std::fs::read_to_string(file)
+            .with_context(|| format!("Secret `{name}`: failed to read {}", file.display()))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string(),
+        None => interpolate(name, value)?,
+    };
+    if secret.is_empty() {
+        anyhow::bail!("Secret `{name}` is not configured, set `{name}` or `{name}_file`");
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secret::{interpolate, resolve};
+
+    #[test]
+    fn interpolate_env() -> anyhow::Result<()> {
+        std::env::set_var("MALLCHAT_TEST_SECRET", "s3cret");
+        assert_eq!(interpolate("a", "${MALLCHAT_TEST_SECRET}")?, "s3cret");
+        assert_eq!(
+            interpolate("a", "x-${MALLCHAT_TEST_SECRET}-$$-$")?,
+            "x-s3cret-$-$"
+        );
+        assert_eq!(interpolate("a", "plain")?, "plain");
+
+        let error = interpolate("wx.app_secret", "${MALLCHAT_TEST_MISSING}")
+            .err()
+            .map(|error| error.to_string())
+            .unwrap_or_default();
+        assert!(error.contains("wx.app_secret"), "{error}");
+        assert!(error.contains("MALLCHAT_TEST_MISSING"), "{error}");
+        assert!(interpolate("a", "${UNCLOSED").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_file() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("mallchat-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n")?;
+        assert_eq!(resolve("a", "ignored", Some(&path))?, "from-file");
+        std::fs::remove_file(&path)?;
+        assert!(resolve("a", "", Some(&path)).is_err());
+
+        let error = resolve("http.jwt_secret", "", None)
+            .err()
+            .map(|error| error.to_string())
+            .unwrap_or_default();
+        assert!(error.contains("http.jwt_secret_file"), "{error}");
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+//! # 业务服务
+//!
+//! 供多个处理器复用的业务逻辑，方法对 `ConnectionTrait` 泛型，既可以使用数据库连接，也可以在事务中使用
+
+pub mod announcement;
+pub mod api_key;
+pub mod archive;
+pub mod black;
+pub mod client_msg;
+pub mod device;
+pub mod group;
+pub mod hot_room;
+pub mod item;
+pub mod message;
+pub mod moderation;
+pub mod role;
+pub mod room;
+pub mod stats;
+pub mod webhook;
+pub mod welcome;
@@ -0,0 +1,88 @@
+//! # 系统公告服务
+//!
+//! 公告发布后推送给在线用户，同时在 `announcement_read` 表中记录每个用户已送达的最新公告；
+//! 离线用户在下次登录时补发最近 [`DELIVER_DAYS`] 天内未送达的公告。
+
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use time::PrimitiveDateTime;
+
+use crate::storage::model::{announcement, announcement_read};
+
+/// 登录时补发的公告的最长时间（天）
+pub const DELIVER_DAYS: i64 = 7;
+/// 登录时最多补发的公告数
+pub const MAX_DELIVER: u64 = 10;
+
+/// 系统公告服务
+#[derive(Debug, Clone, Copy)]
+pub struct AnnouncementService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> AnnouncementService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 保存公告
+    pub async fn create(&self, uid: i64, content: &str) -> Result<announcement::Model, DbErr> {
+        announcement::ActiveModel {
+            uid: Set(uid),
+            content: Set(content.to_string()),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await
+    }
+
+    /// 用户尚未送达的公告，只包括 `now` 之前 [`DELIVER_DAYS`] 天内最新的 [`MAX_DELIVER`] 条，按发布顺序排列
+    pub async fn undelivered(
+        &self,
+        uid: i64,
+        now: PrimitiveDateTime,
+    ) -> Result<Vec<announcement::Model>, DbErr> {
+        let last_id = announcement_read::Entity::find_by_id(uid)
+            .one(self.db)
+            .await?
+            .map_or(0, |read| read.last_id);
+        let mut announcements = announcement::Entity::find()
+            .filter(announcement::Column::Id.gt(last_id))
+            .filter(announcement::Column::CreateTime.gte(now - time::Duration::days(DELIVER_DAYS)))
+            .order_by_desc(announcement::Column::Id)
+            .limit(MAX_DELIVER)
+            .all(self.db)
+            .await?;
+        announcements.reverse();
+        Ok(announcements)
+    }
+
+    /// 记录公告已送达这些用户，不会回退已记录的更新的公告
+    pub async fn mark_delivered(&self, uids: &[i64], id: u64) -> Result<(), DbErr> {
+        if uids.is_empty() {
+            return Ok(());
+        }
+        announcement_read::Entity::insert_many(uids.iter().map(|uid| {
+            announcement_read::ActiveModel {
+                uid: Set(*uid),
+                last_id: Set(id),
+                ..Default::default()
+            }
+        }))
+        .on_conflict(
+            OnConflict::column(announcement_read::Column::Uid)
+                .value(
+                    announcement_read::Column::LastId,
+                    Expr::cust("GREATEST(`last_id`, VALUES(`last_id`))"),
+                )
+                .to_owned(),
+        )
+        .exec(self.db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! # API Key 服务
+//!
+//! 只保存 Key 的 SHA-256 哈希和前缀，明文只在创建时返回一次；授权范围以逗号分隔保存在 `scopes` 中
+
+use rand::RngCore;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+use sha2::{Digest, Sha256};
+
+use crate::handler::auth::api_key::ApiScope;
+use crate::storage::model::api_key;
+
+/// Key 的固定前缀，便于在日志、代码仓库中识别泄露的 Key
+pub const KEY_PREFIX: &str = "mc_";
+
+/// 保存用于辨认的前缀长度，包括 [`KEY_PREFIX`]
+const DISPLAY_PREFIX_LEN: usize = 11;
+
+/// API Key 服务
+#[derive(Debug, Clone, Copy)]
+pub struct ApiKeyService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> ApiKeyService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 所有 Key，按创建顺序排列
+    pub async fn list(&self) -> Result<Vec<api_key::Model>, DbErr> {
+        api_key::Entity::find()
+            .order_by_asc(api_key::Column::Id)
+            .all(self.db)
+            .await
+    }
+
+    /// 创建 Key，返回保存的记录和明文
+    pub async fn create(
+        &self,
+        uid: i64,
+        name: &str,
+        scopes: &[ApiScope],
+    ) -> Result<(api_key::Model, String), DbErr> {
+        let key = generate_key();
+        let model = api_key::ActiveModel {
+            name: Set(name.to_string()),
+            prefix: Set(key[..DISPLAY_PREFIX_LEN].to_string()),
+            key_hash: Set(hash_key(&key)),
+            scopes: Set(join_scopes(scopes)),
+            uid: Set(uid),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        Ok((model, key))
+    }
+
+    /// 按明文查找 Key
+    pub async fn find_by_key(&self, key: &str) -> Result<Option<api_key::Model>, DbErr> {
+        api_key::Entity::find()
+            .filter(api_key::Column::KeyHash.eq(hash_key(key)))
+            .one(self.db)
+            .await
+    }
+
+    /// 吊销（删除）Key，返回是否存在
+    pub async fn delete(&self, id: u64) -> Result<bool, DbErr> {
+        let result = api_key::Entity::delete_by_id(id).exec(self.db).await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+/// 生成 Key：[`KEY_PREFIX`] 加 32 字节随机数的十六进制
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{KEY_PREFIX}{}", hex::encode(bytes))
+}
+
+/// Key 的 SHA-256 哈希（十六进制）
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// 保存到 `scopes` 列的格式
+pub fn join_scopes(scopes: &[ApiScope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 解析 `scopes` 列，忽略无法识别的授权范围
+pub fn split_scopes(scopes: &str) -> Vec<ApiScope> {
+    scopes
+        .split(',')
+        .filter_map(|scope| scope.trim().parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::auth::api_key::ApiScope;
+    use crate::service::api_key::{generate_key, hash_key, join_scopes, split_scopes, KEY_PREFIX};
+
+    #[test]
+    fn key() {
+        let key = generate_key();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(key.len(), KEY_PREFIX.len() + 64);
+        assert_ne!(key, generate_key());
+        assert_eq!(hash_key(&key).len(), 64);
+        assert_eq!(hash_key(&key), hash_key(&key));
+        assert_ne!(hash_key(&key), hash_key(&generate_key()));
+    }
+
+    #[test]
+    fn scopes() {
+        let scopes = [ApiScope::Announcement, ApiScope::Stats];
+        let joined = join_scopes(&scopes);
+        assert_eq!(joined, "announcement,stats");
+        assert_eq!(split_scopes(&joined), scopes);
+        assert_eq!(split_scopes("stats, unknown,"), [ApiScope::Stats]);
+        assert!(split_scopes("").is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! # 消息归档服务
+//!
+//! 超过保留期的消息按批移出 `message` 表：写入 `message_archive` 表，或上传到对象存储后记录在 `archive_file` 表中。
+//! 房间的最后一条消息（`room.last_msg_id`）会话列表仍需要，不会归档。
+
+use sea_orm::sea_query::Query;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use time::PrimitiveDateTime;
+
+use crate::storage::model::{archive_file, message, message_archive, room};
+
+/// 归档的房间范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveScope {
+    /// 单个房间
+    Room(i64),
+    /// 除指定房间外的所有房间
+    Except(Vec<i64>),
+}
+
+/// 消息归档服务
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> ArchiveService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 查询 `before` 之前发送、可以归档的消息，按 ID 升序
+    pub async fn expired(
+        &self,
+        scope: &ArchiveScope,
+        before: PrimitiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        let last_messages = Query::select()
+            .column(room::Column::LastMsgId)
+            .from(room::Entity)
+            .and_where(room::Column::LastMsgId.is_not_null())
+            .to_owned();
+        let select = message::Entity::find()
+            .filter(message::Column::CreateTime.lt(before))
+            .filter(message::Column::Id.not_in_subquery(last_messages));
+        let select = match scope {
+            ArchiveScope::Room(room_id) => select.filter(message::Column::RoomId.eq(*room_id)),
+            ArchiveScope::Except(room_ids) if room_ids.is_empty() => select,
+            ArchiveScope::Except(room_ids) => {
+                select.filter(message::Column::RoomId.is_not_in(room_ids.iter().copied()))
+            }
+        };
+        select
+            .order_by_asc(message::Column::Id)
+            .limit(limit)
+            .all(self.db)
+            .await
+    }
+
+    /// 将消息移动到 `message_archive` 表，需要在事务中执行
+    pub async fn move_to_table(&self, messages: &[message::Model]) -> Result<(), DbErr> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        message_archive::Entity::insert_many(messages.iter().cloned().map(|message| {
+            message_archive::ActiveModel {
+                id: Set(message.id),
+                room_id: Set(message.room_id),
+                from_uid: Set(message.from_uid),
+                content: Set(message.content),
+                reply_msg_id: Set(message.reply_msg_id),
+                status: Set(message.status),
+                gap_count: Set(message.gap_count),
+                r#type: Set(message.r#type),
+                extra: Set(message.extra),
+                create_time: Set(message.create_time),
+                update_time: Set(message.update_time),
+                ..Default::default()
+            }
+        }))
+        .exec(self.db)
+        .await?;
+        self.delete(messages).await
+    }
+
+    /// 记录已上传到对象存储的同一房间的消息，并从 `message` 表删除，需要在事务中执行
+    pub async fn move_to_file(
+        &self,
+        room_id: i64,
+        object_key: &str,
+        messages: &[message::Model],
+    ) -> Result<(), DbErr> {
+        let (Some(first), Some(last)) = (messages.first(), messages.last()) else {
+            return Ok(());
+        };
+        archive_file::ActiveModel {
+            room_id: Set(room_id),
+            object_key: Set(object_key.to_string()),
+            first_msg_id: Set(first.id),
+            last_msg_id: Set(last.id),
+            message_count: Set(messages.len() as u32),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        self.delete(messages).await
+    }
+
+    async fn delete(&self, messages: &[message::Model]) -> Result<(), DbErr> {
+        message::Entity::delete_many()
+            .filter(message::Column::Id.is_in(messages.iter().map(|message| message.id)))
+            .exec(self.db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! # 黑名单服务
+
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+
+use crate::storage::model::{black, user};
+use crate::storage::tx::with_txn;
+
+/// 拉黑目标类型，与 `black` 表的 `type` 字段对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum BlackType {
+    /// IP
+    Ip = 1,
+    /// 用户
+    Uid = 2,
+}
+
+/// 用户状态，与 `user` 表的 `status` 字段对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum UserStatus {
+    /// 正常
+    Normal = 0,
+    /// 拉黑
+    Black = 1,
+}
+
+/// 黑名单服务
+#[derive(Debug, Clone, Copy)]
+pub struct BlackService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> BlackService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 加入黑名单，已在黑名单中时返回 `false`
+    pub async fn add(&self, r#type: BlackType, target: &str) -> Result<bool, DbErr> {
+        let exists = black::Entity::find()
+            .filter(black::Column::Type.eq(r#type as i32))
+            .filter(black::Column::Target.eq(target))
+            .one(self.db)
+            .await?
+            .is_some();
+        if exists {
+            return Ok(false);
+        }
+        black::ActiveModel {
+            r#type: Set(r#type as i32),
+            target: Set(target.to_string()),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        Ok(true)
+    }
+
+    /// 拉黑用户：在事务中加入黑名单并将用户状态标记为拉黑
+    pub async fn ban_user(&self, uid: i64) -> Result<bool, DbErr>
+    where
+        C: TransactionTrait,
+    {
+        with_txn(self.db, |txn| {
+            Box::pin(async move {
+                user::Entity::update_many()
+                    .col_expr(user::Column::Status, Expr::value(UserStatus::Black as i32))
+                    .filter(user::Column::Id.eq(uid))
+                    .exec(txn)
+                    .await?;
+                BlackService::new(txn)
+                    .add(BlackType::Uid, &uid.to_string())
+                    .await
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,101 @@
+//! # 客户端消息 ID 去重
+//!
+//! 移动网络不稳定时客户端可能重试发送消息，携带相同的客户端消息 ID 时只保存一次：
+//! 发送前使用 `SET NX` 占用 ID，保存后记录对应的消息 ID，重试时直接返回已保存的消息。
+//! 占位只保留 [`PENDING_EXPIRE_SECONDS`] 秒，发送过程中进程退出时客户端不必等待一天才能重试。
+
+use redis::AsyncCommands;
+
+use crate::cache::Cache;
+
+/// 客户端消息 ID 与消息 ID 的对应关系
+pub const CLIENT_MSG_KEY: &str = "mallchat:msg:client";
+
+/// 客户端消息 ID 的保留时间（秒），覆盖客户端的重试周期
+pub const CLIENT_MSG_EXPIRE_SECONDS: usize = 24 * 60 * 60;
+
+/// 发送中占位的保留时间（秒），覆盖一次发送的耗时
+pub const PENDING_EXPIRE_SECONDS: usize = 30;
+
+/// 发送中的占位值
+const PENDING: u64 = 0;
+
+/// 占用客户端消息 ID 的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientMsgState {
+    /// 首次发送
+    Acquired,
+    /// 正在发送
+    Pending,
+    /// 已发送，为消息 ID
+    Sent(u64),
+}
+
+/// 客户端消息 ID 去重服务
+#[derive(Debug, Clone, Copy)]
+pub struct ClientMsgService<'a> {
+    cache: &'a Cache,
+}
+
+impl<'a> ClientMsgService<'a> {
+    /// 使用 Redis 客户端构造
+    pub fn new(cache: &'a Cache) -> Self {
+        Self { cache }
+    }
+
+    /// 占用用户的客户端消息 ID，已被占用时返回发送状态
+    pub async fn acquire(
+        &self,
+        uid: i64,
+        client_msg_id: &str,
+    ) -> redis::RedisResult<ClientMsgState> {
+        let key = key(uid, client_msg_id);
+        let mut connection = self.cache.connection().await?;
+        let set: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(PENDING)
+            .arg("NX")
+            .arg("EX")
+            .arg(PENDING_EXPIRE_SECONDS)
+            .query_async(&mut connection)
+            .await?;
+        if set.is_some() {
+            return Ok(ClientMsgState::Acquired);
+        }
+        let id: Option<u64> = connection.get(&key).await?;
+        Ok(match id {
+            Some(id) if id != PENDING => ClientMsgState::Sent(id),
+            _ => ClientMsgState::Pending,
+        })
+    }
+
+    /// 消息已保存，记录对应的消息 ID，保留时间延长到 [`CLIENT_MSG_EXPIRE_SECONDS`]
+    pub async fn complete(&self, uid: i64, client_msg_id: &str, id: u64) -> redis::RedisResult<()> {
+        let mut connection = self.cache.connection().await?;
+        connection
+            .set_ex(key(uid, client_msg_id), id, CLIENT_MSG_EXPIRE_SECONDS)
+            .await
+    }
+
+    /// 发送失败，释放客户端消息 ID，允许重试
+    pub async fn release(&self, uid: i64, client_msg_id: &str) -> redis::RedisResult<()> {
+        let mut connection = self.cache.connection().await?;
+        connection.del(key(uid, client_msg_id)).await
+    }
+}
+
+/// 客户端消息 ID 只在同一用户内唯一
+fn key(uid: i64, client_msg_id: &str) -> String {
+    format!("{CLIENT_MSG_KEY}:{uid}:{client_msg_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::service::client_msg::key;
+
+    #[test]
+    fn key_per_user() {
+        assert_eq!(key(1, "abc"), "mallchat:msg:client:1:abc");
+        assert_ne!(key(1, "abc"), key(2, "abc"));
+    }
+}
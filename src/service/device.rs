@@ -0,0 +1,228 @@
+//! # 登录设备服务
+//!
+//! 用户通过 HTTP 或 WebSocket 登录时记录设备 ID、User-Agent、IP 和登录时间，保存在 Redis 哈希表
+//! [`DEVICES_KEY`]`:{uid}` 中，字段为设备 ID，每个用户只保留最近登录的 [`MAX_DEVICES`] 个设备。
+//!
+//! 设备 ID 由客户端提供（HTTP 请求头 [`DEVICE_ID_HEADER`]、WebSocket 查询参数 `device_id`），
+//! 未提供或格式不正确时随机生成。token 中携带设备 ID，下线某个设备时只吊销该设备的 token，
+//! 见 [`JwtKeys::revoke_device`](crate::handler::auth::JwtKeys::revoke_device)。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::cache::Cache;
+
+/// 用户登录设备的哈希表，后接 uid
+pub const DEVICES_KEY: &str = "mallchat:user:devices";
+
+/// 客户端提供设备 ID 的 HTTP 请求头
+pub const DEVICE_ID_HEADER: &str = "x-device-id";
+
+/// 每个用户保留的设备数
+pub const MAX_DEVICES: usize = 10;
+
+/// 设备 ID 的最大长度
+const MAX_DEVICE_ID_LEN: usize = 64;
+
+/// 保存的 User-Agent 的最大长度，超过时截断
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// 登录设备
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginDevice {
+    /// 设备 ID
+    pub device_id: String,
+    /// 登录时的 User-Agent
+    pub user_agent: Option<String>,
+    /// 登录时的 IP
+    pub ip: Option<String>,
+    /// 最近一次登录的时间（毫秒时间戳）
+    pub login_time: i64,
+}
+
+impl LoginDevice {
+    /// 当前登录的设备，`device_id` 无效时随机生成
+    pub fn new(device_id: Option<&str>, user_agent: Option<&str>, ip: Option<IpAddr>) -> Self {
+        Self {
+            device_id: device_id_or_random(device_id),
+            user_agent: user_agent.map(|user_agent| truncate(user_agent, MAX_USER_AGENT_LEN)),
+            ip: ip.map(|ip| ip.to_string()),
+            login_time: (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64,
+        }
+    }
+}
+
+/// 客户端提供的设备 ID 只能包含字母、数字和 `-`、`_`、`.`，长度不超过 64
+pub fn is_valid_device_id(device_id: &str) -> bool {
+    (1..=MAX_DEVICE_ID_LEN).contains(&device_id.len())
+        && device_id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+}
+
+/// 客户端提供的设备 ID 无效时随机生成
+pub fn device_id_or_random(device_id: Option<&str>) -> String {
+    match device_id.filter(|device_id| is_valid_device_id(device_id)) {
+        Some(device_id) => device_id.to_string(),
+        None => hex::encode(rand::thread_rng().gen::<[u8; 8]>()),
+    }
+}
+
+fn truncate(s: &str, max_len: usize) -> String {
+    let mut end = s.len().min(max_len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s[..end].to_string()
+}
+
+/// 登录设备服务
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceService<'a> {
+    cache: &'a Cache,
+}
+
+impl<'a> DeviceService<'a> {
+    /// 使用 Redis 客户端构造
+    pub fn new(cache: &'a Cache) -> Self {
+        Self { cache }
+    }
+
+    /// 记录用户在设备上登录，超过 [`MAX_DEVICES`] 时移除最早登录的设备
+    pub async fn record(&self, uid: i64, device: &LoginDevice) -> anyhow::Result<()> {
+        let key = devices_key(uid);
+        let mut connection = self.cache.connection().await?;
+        let count: usize = redis::pipe()
+            .cmd("HSET")
+            .arg(&key)
+            .arg(&device.device_id)
+            .arg(serde_json::to_string(device)?)
+            .ignore()
+            .cmd("HLEN")
+            .arg(&key)
+            .query_async::<_, (usize,)>(&mut connection)
+            .await?
+            .0;
+        if count > MAX_DEVICES {
+            let devices: HashMap<String, String> = redis::cmd("HGETALL")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await?;
+            let evicted = evicted(parse(devices), MAX_DEVICES);
+            if !evicted.is_empty() {
+                redis::cmd("HDEL")
+                    .arg(&key)
+                    .arg(evicted)
+                    .query_async::<_, ()>(&mut connection)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 用户的登录设备，最近登录的在前
+    pub async fn list(&self, uid: i64) -> anyhow::Result<Vec<LoginDevice>> {
+        let mut connection = self.cache.connection().await?;
+        let devices: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(devices_key(uid))
+            .query_async(&mut connection)
+            .await?;
+        Ok(parse(devices))
+    }
+
+    /// 移除登录设备，返回设备是否存在
+    pub async fn remove(&self, uid: i64, device_id: &str) -> anyhow::Result<bool> {
+        let mut connection = self.cache.connection().await?;
+        let removed: usize = redis::cmd("HDEL")
+            .arg(devices_key(uid))
+            .arg(device_id)
+            .query_async(&mut connection)
+            .await?;
+        Ok(removed > 0)
+    }
+}
+
+fn devices_key(uid: i64) -> String {
+    format!("{DEVICES_KEY}:{uid}")
+}
+
+/// 解析保存的设备，忽略无法解析的字段，最近登录的在前
+fn parse(devices: HashMap<String, String>) -> Vec<LoginDevice> {
+    let mut devices: Vec<LoginDevice> = devices
+        .into_values()
+        .filter_map(|device| serde_json::from_str(&device).ok())
+        .collect();
+    devices.sort_by(|a, b| {
+        b.login_time
+            .cmp(&a.login_time)
+            .then_with(|| a.device_id.cmp(&b.device_id))
+    });
+    devices
+}
+
+/// 超过 `max` 个设备时需要移除的设备 ID，`devices` 按登录时间倒序排列
+fn evicted(devices: Vec<LoginDevice>, max: usize) -> Vec<String> {
+    devices
+        .into_iter()
+        .skip(max)
+        .map(|device| device.device_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::service::device::{
+        device_id_or_random, evicted, is_valid_device_id, parse, LoginDevice,
+    };
+
+    #[test]
+    fn device_id() {
+        assert!(is_valid_device_id("iPhone-15_pro.1"));
+        assert!(!is_valid_device_id(""));
+        assert!(!is_valid_device_id("a b"));
+        assert!(!is_valid_device_id(&"a".repeat(65)));
+        assert_eq!(device_id_or_random(Some("web-1")), "web-1");
+        let random = device_id_or_random(Some("设备"));
+        assert_eq!(random.len(), 16);
+        assert!(is_valid_device_id(&random));
+        assert_ne!(random, device_id_or_random(None));
+
+        let device = LoginDevice::new(None, Some(&"浏览器".repeat(100)), None);
+        assert!(device
+            .user_agent
+            .is_some_and(|user_agent| user_agent.len() <= 256));
+    }
+
+    #[test]
+    fn recent_devices() -> anyhow::Result<()> {
+        let device = |device_id: &str, login_time| LoginDevice {
+            device_id: device_id.to_string(),
+            user_agent: None,
+            ip: Some("127.0.0.1".to_string()),
+            login_time,
+        };
+        let mut stored = HashMap::new();
+        for device in [device("a", 3), device("b", 1), device("c", 2)] {
+            stored.insert(device.device_id.clone(), serde_json::to_string(&device)?);
+        }
+        stored.insert("broken".to_string(), "{".to_string());
+
+        let devices = parse(stored);
+        let ids: Vec<_> = devices
+            .iter()
+            .map(|device| device.device_id.as_str())
+            .collect();
+        assert_eq!(ids, ["a", "c", "b"]);
+        assert_eq!(evicted(devices.clone(), 2), ["b"]);
+        assert!(evicted(devices, 3).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+//! # 群聊服务
+//!
+//! 群聊房间的公告保存在 `room_group` 表中，群主、管理员、被禁言及通过邀请加入的成员保存在 `group_member` 表中。
+//! 全员群聊 [`GLOBAL_ROOM_ID`] 对所有用户开放，消息推送给所有在线用户，没有记录的用户视为普通成员；
+//! 其他群聊只有有成员记录的用户可以收发、查看消息。
+//!
+//! `room.member_count` 冗余保存成员记录数，增删成员记录时在同一事务中更新，
+//! 偏差由 [`MemberCountReconcile`](crate::jobs::member_count::MemberCountReconcile) 定时修正。
+
+use sea_orm::sea_query::{
+    Alias, Expr, OnConflict, Query, QueryStatementBuilder, SelectStatement, SimpleExpr,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, FromQueryResult,
+    PaginatorTrait, QueryFilter, QuerySelect, Set,
+};
+use time::PrimitiveDateTime;
+
+use crate::service::room::RoomType;
+use crate::storage::model::{group_member, room, room_group};
+use crate::storage::soft_delete::SoftDelete;
+
+/// 全员群聊的房间 ID，由初始迁移创建，所有用户都是成员
+pub const GLOBAL_ROOM_ID: i64 = 1;
+
+/// 通过邀请加入时群成员数的上限
+pub const MAX_GROUP_MEMBERS: u64 = 500;
+
+/// 群成员角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum GroupRole {
+    /// 群主
+    Owner = 1,
+    /// 管理员
+    Admin = 2,
+    /// 普通成员
+    Member = 3,
+}
+
+impl GroupRole {
+    /// 是否可以禁言成员、修改群公告
+    pub fn can_manage(self) -> bool {
+        matches!(self, GroupRole::Owner | GroupRole::Admin)
+    }
+}
+
+impl TryFrom<i32> for GroupRole {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(GroupRole::Owner),
+            2 => Ok(GroupRole::Admin),
+            3 => Ok(GroupRole::Member),
+            other => Err(other),
+        }
+    }
+}
+
+/// 冗余的成员数与成员记录数不一致的群聊
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct MemberCountDrift {
+    /// 房间 ID
+    pub room_id: u64,
+    /// `room.member_count` 中的成员数
+    pub recorded: u32,
+    /// 成员记录数
+    pub actual: i64,
+}
+
+/// 成员在 `now` 时是否处于禁言中
+pub fn is_muted(member: &group_member::Model, now: PrimitiveDateTime) -> bool {
+    member.mute_until.is_some_and(|until| until > now)
+}
+
+/// 群聊服务
+#[derive(Debug, Clone, Copy)]
+pub struct GroupService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> GroupService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 创建群聊房间，`owner` 为群主，需要在事务中调用
+    pub async fn create(&self, owner: i64, name: &str) -> Result<room::Model, DbErr> {
+        let room = room::ActiveModel {
+            name: Set(name.to_string()),
+            r#type: Set(RoomType::Group as i32),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        room_group::ActiveModel {
+            room_id: Set(room.id as i64),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        self.set_role(room.id as i64, owner, GroupRole::Owner)
+            .await?;
+        Ok(room)
+    }
+
+    /// 查找群聊信息
+    pub async fn find(&self, room_id: i64) -> Result<Option<room_group::Model>, DbErr> {
+        room_group::Entity::find()
+            .filter(room_group::Column::RoomId.eq(room_id))
+            .one(self.db)
+            .await
+    }
+
+    /// 查找群成员记录
+    pub async fn member(
+        &self,
+        room_id: i64,
+        uid: i64,
+    ) -> Result<Option<group_member::Model>, DbErr> {
+        group_member::Entity::find()
+            .filter(group_member::Column::RoomId.eq(room_id))
+            .filter(group_member::Column::Uid.eq(uid))
+            .one(self.db)
+            .await
+    }
+
+    /// 群聊消息的接收者，全员群聊为 `None`，推送给所有在线用户，其他群聊为有成员记录的用户
+    pub async fn receivers(&self, room_id: i64) -> Result<Option<Vec<i64>>, DbErr> {
+        if room_id == GLOBAL_ROOM_ID {
+            return Ok(None);
+        }
+        let uids = group_member::Entity::find()
+            .select_only()
+            .column(group_member::Column::Uid)
+            .filter(group_member::Column::RoomId.eq(room_id))
+            .into_tuple()
+            .all(self.db)
+            .await?;
+        Ok(Some(uids))
+    }
+
+    /// 成员角色，没有记录时为普通成员
+    pub async fn role(&self, room_id: i64, uid: i64) -> Result<GroupRole, DbErr> {
+        Ok(self
+            .member(room_id, uid)
+            .await?
+            .and_then(|member| GroupRole::try_from(member.role).ok())
+            .unwrap_or(GroupRole::Member))
+    }
+
+    /// 群主 uid
+    pub async fn owner(&self, room_id: i64) -> Result<Option<i64>, DbErr> {
+        Ok(group_member::Entity::find()
+            .filter(group_member::Column::RoomId.eq(room_id))
+            .filter(group_member::Column::Role.eq(GroupRole::Owner as i32))
+            .one(self.db)
+            .await?
+            .map(|member| member.uid))
+    }
+
+    /// 统计有记录的群成员数，包括群主、管理员和通过邀请加入的成员
+    ///
+    /// 需要扫描成员记录，一般读取 `room.member_count` 即可
+    pub async fn count_members(&self, room_id: i64) -> Result<u64, DbErr> {
+        group_member::Entity::find()
+            .filter(group_member::Column::RoomId.eq(room_id))
+            .count(self.db)
+            .await
+    }
+
+    /// 以普通成员身份加入群聊，成员数加一，需要在事务中调用
+    pub async fn join(&self, room_id: i64, uid: i64) -> Result<group_member::Model, DbErr> {
+        let member = group_member::ActiveModel {
+            room_id: Set(room_id),
+            uid: Set(uid),
+            role: Set(GroupRole::Member as i32),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await?;
+        self.incr_member_count(room_id).await?;
+        Ok(member)
+    }
+
+    /// 退出群聊，删除成员记录，成员数减一，返回是否有记录被删除，需要在事务中调用
+    pub async fn leave(&self, room_id: i64, uid: i64) -> Result<bool, DbErr> {
+        let deleted = group_member::Entity::delete_many()
+            .filter(group_member::Column::RoomId.eq(room_id))
+            .filter(group_member::Column::Uid.eq(uid))
+            .exec(self.db)
+            .await?
+            .rows_affected;
+        if deleted > 0 {
+            room::Entity::update_many()
+                .col_expr(
+                    room::Column::MemberCount,
+                    Expr::col(room::Column::MemberCount).sub(1),
+                )
+                .filter(room::Column::Id.eq(room_id as u64))
+                // 无符号列减到负数会报错，偏差由定时任务修正
+                .filter(room::Column::MemberCount.gt(0))
+                .exec(self.db)
+                .await?;
+        }
+        Ok(deleted > 0)
+    }
+
+    /// 禁言成员到 `until`，为 `None` 时解除禁言
+    pub async fn mute(
+        &self,
+        room_id: i64,
+        uid: i64,
+        until: Option<PrimitiveDateTime>,
+    ) -> Result<(), DbErr> {
+        self.upsert_member(
+            room_id,
+            group_member::ActiveModel {
+                uid: Set(uid),
+                role: Set(GroupRole::Member as i32),
+                mute_until: Set(until),
+                ..Default::default()
+            },
+            group_member::Column::MuteUntil,
+        )
+        .await
+    }
+
+    /// 修改群公告
+    pub async fn set_announcement(&self, room_id: i64, announcement: &str) -> Result<(), DbErr> {
+        room_group::Entity::insert(room_group::ActiveModel {
+            room_id: Set(room_id),
+            announcement: Set(Some(announcement.to_string())),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(room_group::Column::RoomId)
+                .update_column(room_group::Column::Announcement)
+                .to_owned(),
+        )
+        .exec(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// 将群主转让给 `to`，原群主成为普通成员，需要在事务中调用
+    pub async fn transfer_owner(&self, room_id: i64, from: i64, to: i64) -> Result<(), DbErr> {
+        self.set_role(room_id, from, GroupRole::Member).await?;
+        self.set_role(room_id, to, GroupRole::Owner).await
+    }
+
+    /// 解散群聊，逻辑删除房间，成员记录、群聊信息和历史消息保留，可以通过 [`restore`](Self::restore) 恢复
+    pub async fn dissolve(&self, room_id: i64) -> Result<bool, DbErr> {
+        let result = room::Entity::soft_delete_many()
+            .filter(room::Column::Id.eq(room_id as u64))
+            .exec(self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// 恢复已解散的群聊，返回是否有群聊被恢复
+    pub async fn restore(&self, room_id: i64) -> Result<bool, DbErr> {
+        let result = room::Entity::restore_many()
+            .filter(room::Column::Id.eq(room_id as u64))
+            .filter(room::Column::Type.eq(RoomType::Group as i32))
+            .exec(self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// 查找冗余的成员数与成员记录数不一致的群聊
+    pub async fn member_count_drifts(&self) -> Result<Vec<MemberCountDrift>, DbErr> {
+        let query = member_count_drifts_query();
+        let backend = self.db.get_database_backend();
+        MemberCountDrift::find_by_statement(backend.build(&query))
+            .all(self.db)
+            .await
+    }
+
+    /// 把房间的成员数修正为当前的成员记录数
+    pub async fn reset_member_count(&self, room_id: u64) -> Result<(), DbErr> {
+        room::Entity::update_many()
+            .col_expr(
+                room::Column::MemberCount,
+                member_count_query(room_id as i64),
+            )
+            .filter(room::Column::Id.eq(room_id))
+            .exec(self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn incr_member_count(&self, room_id: i64) -> Result<(), DbErr> {
+        room::Entity::update_many()
+            .col_expr(
+                room::Column::MemberCount,
+                Expr::col(room::Column::MemberCount).add(1),
+            )
+            .filter(room::Column::Id.eq(room_id as u64))
+            .exec(self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_role(&self, room_id: i64, uid: i64, role: GroupRole) -> Result<(), DbErr> {
+        self.upsert_member(
+            room_id,
+            group_member::ActiveModel {
+                uid: Set(uid),
+                role: Set(role as i32),
+                ..Default::default()
+            },
+            group_member::Column::Role,
+        )
+        .await
+    }
+
+    /// 插入 `room_id` 的成员记录，已存在时只更新 `column`，插入时成员数加一
+    ///
+    /// MySQL 的 `INSERT ... ON DUPLICATE KEY UPDATE` 插入时影响 1 行，更新时影响 2 行
+    /// （sqlx 连接时设置了 `CLIENT_FOUND_ROWS`，值不变也计为更新）
+    async fn upsert_member(
+        &self,
+        room_id: i64,
+        mut member: group_member::ActiveModel,
+        column: group_member::Column,
+    ) -> Result<(), DbErr> {
+        member.room_id = Set(room_id);
+        let affected = group_member::Entity::insert(member)
+            .on_conflict(
+                OnConflict::columns([group_member::Column::RoomId, group_member::Column::Uid])
+                    .update_column(column)
+                    .to_owned(),
+            )
+            .exec_without_returning(self.db)
+            .await?;
+        if affected == 1 {
+            self.incr_member_count(room_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 冗余的成员数与成员记录数不一致的群聊查询
+fn member_count_drifts_query() -> SelectStatement {
+    Query::select()
+        .expr_as(
+            Expr::col((room::Entity, room::Column::Id)),
+            Alias::new("room_id"),
+        )
+        .expr_as(
+            Expr::col((room::Entity, room::Column::MemberCount)),
+            Alias::new("recorded"),
+        )
+        .expr_as(
+            Expr::col((group_member::Entity, group_member::Column::Id)).count(),
+            Alias::new("actual"),
+        )
+        .from(room::Entity)
+        .left_join(
+            group_member::Entity,
+            Expr::col((group_member::Entity, group_member::Column::RoomId))
+                .equals((room::Entity, room::Column::Id)),
+        )
+        .and_where(Expr::col((room::Entity, room::Column::Type)).eq(RoomType::Group as i32))
+        .group_by_columns([
+            (room::Entity, room::Column::Id),
+            (room::Entity, room::Column::MemberCount),
+        ])
+        .and_having(Expr::col(Alias::new("recorded")).ne(Expr::col(Alias::new("actual"))))
+        .to_owned()
+}
+
+/// 房间的成员记录数子查询
+fn member_count_query(room_id: i64) -> SimpleExpr {
+    let query = Query::select()
+        .expr(Expr::col(group_member::Column::Id).count())
+        .from(group_member::Entity)
+        .and_where(group_member::Column::RoomId.eq(room_id))
+        .to_owned();
+    SimpleExpr::SubQuery(None, Box::new(query.into_sub_query_statement()))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::sea_query::MysqlQueryBuilder;
+    use sea_orm::{ColumnTrait, DbBackend, EntityTrait, QueryFilter, QueryTrait};
+
+    use crate::service::group::{
+        is_muted, member_count_drifts_query, member_count_query, GroupRole,
+    };
+    use crate::storage::model::{group_member, room};
+
+    #[test]
+    fn roles() {
+        assert_eq!(GroupRole::try_from(1), Ok(GroupRole::Owner));
+        assert_eq!(GroupRole::try_from(4), Err(4));
+        assert!(GroupRole::Owner.can_manage());
+        assert!(GroupRole::Admin.can_manage());
+        assert!(!GroupRole::Member.can_manage());
+    }
+
+    #[test]
+    fn muted() {
+        let now = time::macros::datetime!(2023-08-08 12:00:00);
+        let mut member = group_member::Model {
+            id: 1,
+            room_id: 1,
+            uid: 1,
+            role: GroupRole::Member as i32,
+            mute_until: None,
+            create_time: now,
+            update_time: now,
+        };
+        assert!(!is_muted(&member, now));
+        member.mute_until = Some(now + time::Duration::minutes(10));
+        assert!(is_muted(&member, now));
+        assert!(!is_muted(&member, now + time::Duration::minutes(10)));
+    }
+
+    #[test]
+    fn member_count_sql() {
+        assert_eq!(
+            member_count_drifts_query().to_string(MysqlQueryBuilder),
+            "SELECT `room`.`id` AS `room_id`, `room`.`member_count` AS `recorded`, \
+            COUNT(`group_member`.`id`) AS `actual` FROM `room` \
+            LEFT JOIN `group_member` ON `group_member`.`room_id` = `room`.`id` \
+            WHERE `room`.`type` = 1 GROUP BY `room`.`id`, `room`.`member_count` \
+            HAVING `recorded` <> `actual`"
+        );
+        // 在一条语句中重新统计，避免覆盖统计之后加入的成员
+        let sql = room::Entity::update_many()
+            .col_expr(room::Column::MemberCount, member_count_query(2))
+            .filter(room::Column::Id.eq(2u64))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "UPDATE `room` SET `member_count` = \
+            (SELECT COUNT(`id`) FROM `group_member` WHERE `group_member`.`room_id` = 2) \
+            WHERE `room`.`id` = 2"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! # 房间热度服务
+//!
+//! 房间每收到一条消息分数加 1（见 [`crate::mq::message::HotRoom`]），
+//! 由定时任务 [`HotRoomDecay`](crate::jobs::hot_room::HotRoomDecay) 按比例衰减，
+//! 即分数随时间指数衰减，最近消息多的房间分数高。分数保存在 Redis 有序集合 [`HOT_ROOM_KEY`] 中。
+
+use std::collections::HashMap;
+
+use crate::cache::Cache;
+use crate::storage::model::room;
+
+/// 热门房间分数的有序集合
+pub const HOT_ROOM_KEY: &str = "mallchat:room:hot";
+
+/// 衰减后低于该分数的房间移出有序集合
+const MIN_SCORE: f64 = 0.01;
+
+/// 参与热度排序的房间数上限，其余房间按最后活跃时间排在后面
+pub const MAX_HOT_ROOMS: usize = 100;
+
+/// 房间热度服务
+#[derive(Debug, Clone, Copy)]
+pub struct HotRoomService<'a> {
+    cache: &'a Cache,
+}
+
+impl<'a> HotRoomService<'a> {
+    /// 使用 Redis 客户端构造
+    pub fn new(cache: &'a Cache) -> Self {
+        Self { cache }
+    }
+
+    /// 记录房间收到新消息
+    pub async fn record_message(&self, room_id: i64) -> redis::RedisResult<()> {
+        let mut connection = self.cache.connection().await?;
+        redis::cmd("ZINCRBY")
+            .arg(HOT_ROOM_KEY)
+            .arg(1)
+            .arg(room_id)
+            .query_async(&mut connection)
+            .await
+    }
+
+    /// 所有房间的分数乘以 `factor`，并移除分数过低的房间
+    pub async fn decay(&self, factor: f64) -> redis::RedisResult<()> {
+        let mut connection = self.cache.connection().await?;
+        redis::pipe()
+            .atomic()
+            .cmd("ZUNIONSTORE")
+            .arg(HOT_ROOM_KEY)
+            .arg(1)
+            .arg(HOT_ROOM_KEY)
+            .arg("WEIGHTS")
+            .arg(factor.clamp(0.0, 1.0))
+            .ignore()
+            .cmd("ZREMRANGEBYSCORE")
+            .arg(HOT_ROOM_KEY)
+            .arg("-inf")
+            .arg(format!("({MIN_SCORE}"))
+            .ignore()
+            .query_async(&mut connection)
+            .await
+    }
+
+    /// 分数最高的 [`MAX_HOT_ROOMS`] 个房间及其分数，分数高的在前
+    pub async fn ranking(&self) -> redis::RedisResult<Vec<(i64, f64)>> {
+        let mut connection = self.cache.connection().await?;
+        redis::cmd("ZREVRANGE")
+            .arg(HOT_ROOM_KEY)
+            .arg(0)
+            .arg(MAX_HOT_ROOMS - 1)
+            .arg("WITHSCORES")
+            .query_async(&mut connection)
+            .await
+    }
+}
+
+/// 按热度排列房间，分数相同时最近活跃的在前，不在 `ranking` 中的房间被忽略
+pub fn rank(rooms: Vec<room::Model>, ranking: &[(i64, f64)]) -> Vec<(room::Model, f64)> {
+    let scores: HashMap<i64, f64> = ranking.iter().copied().collect();
+    let mut ranked: Vec<_> = rooms
+        .into_iter()
+        .filter_map(|room| {
+            let score = scores.get(&(room.id as i64)).copied()?;
+            Some((room, score))
+        })
+        .collect();
+    ranked.sort_by(|(a, a_score), (b, b_score)| {
+        b_score
+            .total_cmp(a_score)
+            .then(b.active_time.cmp(&a.active_time))
+            .then(b.id.cmp(&a.id))
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::service::hot_room::rank;
+    use crate::storage::model::room;
+
+    fn room(id: u64, active_time: time::PrimitiveDateTime) -> room::Model {
+        room::Model {
+            id,
+            name: format!("room{id}"),
+            r#type: 1,
+            member_count: 0,
+            active_time,
+            last_msg_id: None,
+            last_msg_abstract: None,
+            create_time: active_time,
+            update_time: active_time,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn rank_by_score() {
+        let earlier = time::macros::datetime!(2023-06-01 00:00:00);
+        let later = time::macros::datetime!(2023-06-02 00:00:00);
+        let rooms = vec![
+            room(1, later),
+            room(2, earlier),
+            room(3, later),
+            room(4, later),
+        ];
+        let ranking = [(2, 5.0), (3, 1.5), (1, 1.5), (5, 9.0)];
+
+        let ranked: Vec<_> = rank(rooms, &ranking)
+            .into_iter()
+            .map(|(room, score)| (room.id, score))
+            .collect();
+        assert_eq!(ranked, vec![(2, 5.0), (3, 1.5), (1, 1.5)]);
+    }
+}
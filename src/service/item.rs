@@ -0,0 +1,246 @@
+//! # 物品服务
+
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, Set,
+};
+
+use crate::storage::model::{user, user_backpack};
+
+/// 物品，与 `item_config` 表对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Item {
+    /// 改名卡
+    ModifyNameCard = 1,
+    /// 爆赞徽章
+    LikeBadge = 2,
+    /// 前 10 名注册徽章
+    Register10Badge = 3,
+    /// 前 100 名注册徽章
+    Register100Badge = 4,
+    /// 知识星球徽章
+    PlanetBadge = 5,
+    /// 前 1000 名注册徽章
+    Register1000Badge = 6,
+}
+
+impl TryFrom<i32> for Item {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::ModifyNameCard),
+            2 => Ok(Self::LikeBadge),
+            3 => Ok(Self::Register10Badge),
+            4 => Ok(Self::Register100Badge),
+            5 => Ok(Self::PlanetBadge),
+            6 => Ok(Self::Register1000Badge),
+            other => Err(other),
+        }
+    }
+}
+
+/// 背包物品状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum BackpackStatus {
+    /// 待使用
+    Unused = 0,
+    /// 已使用
+    Used = 1,
+}
+
+/// 发放物品的业务类型，用于构造幂等号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum IdempotentType {
+    /// 按用户发放
+    Uid = 1,
+    /// 按消息发放
+    MsgId = 2,
+    /// 管理员发放
+    Admin = 3,
+}
+
+/// 注册徽章及其名额，注册名次在名额内的用户获得
+const REGISTER_BADGES: [(u64, Item); 3] = [
+    (10, Item::Register10Badge),
+    (100, Item::Register100Badge),
+    (1000, Item::Register1000Badge),
+];
+
+/// 注册名次对应的徽章
+pub fn register_badges(rank: u64) -> impl Iterator<Item = Item> {
+    REGISTER_BADGES
+        .into_iter()
+        .filter(move |&(limit, _)| rank <= limit)
+        .map(|(_, badge)| badge)
+}
+
+/// 注册奖励
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterReward {
+    /// 注册名次，从 1 开始
+    pub rank: u64,
+    /// 获得的徽章
+    pub badges: Vec<Item>,
+}
+
+/// 物品服务
+#[derive(Debug, Clone, Copy)]
+pub struct ItemService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> ItemService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 发放物品，同一业务重复发放时忽略，返回是否发放
+    pub async fn acquire(
+        &self,
+        uid: i64,
+        item: Item,
+        idempotent_type: IdempotentType,
+        business_id: &str,
+    ) -> Result<bool, DbErr> {
+        let idempotent = idempotent(item, idempotent_type, business_id);
+        self.grant_item(uid, item, &idempotent).await
+    }
+
+    /// 按幂等号发放物品，幂等号已存在时不重复发放，返回是否发放
+    pub async fn grant_item(
+        &self,
+        uid: i64,
+        item: Item,
+        idempotency_key: &str,
+    ) -> Result<bool, DbErr> {
+        if self.is_granted(idempotency_key).await? {
+            return Ok(false);
+        }
+        let inserted = user_backpack::ActiveModel {
+            uid: Set(uid),
+            item_id: Set(item as i32),
+            status: Set(BackpackStatus::Unused as i32),
+            idempotent: Set(idempotency_key.to_string()),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await;
+        match inserted {
+            Ok(_) => Ok(true),
+            // 并发发放时由唯一索引保证只有一个成功
+            Err(_) if self.is_granted(idempotency_key).await? => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn is_granted(&self, idempotency_key: &str) -> Result<bool, DbErr> {
+        let existed = user_backpack::Entity::find()
+            .filter(user_backpack::Column::Idempotent.eq(idempotency_key))
+            .one(self.db)
+            .await?;
+        Ok(existed.is_some())
+    }
+
+    /// 发放注册奖励：一张改名卡，注册名次在前 10 / 100 / 1000 名时发放对应的徽章
+    ///
+    /// 需要在创建用户的事务中调用，用户和奖励一起提交
+    pub async fn grant_register_items(&self, uid: i64) -> Result<RegisterReward, DbErr> {
+        let business_id = uid.to_string();
+        self.acquire(uid, Item::ModifyNameCard, IdempotentType::Uid, &business_id)
+            .await?;
+
+        let rank = user::Entity::find()
+            .filter(user::Column::Id.lte(uid as u64))
+            .count(self.db)
+            .await?;
+        let mut badges = Vec::new();
+        for badge in register_badges(rank) {
+            if self
+                .acquire(uid, badge, IdempotentType::Uid, &business_id)
+                .await?
+            {
+                badges.push(badge);
+            }
+        }
+        Ok(RegisterReward { rank, badges })
+    }
+
+    /// 使用一个物品，没有可用的物品时返回 `false`
+    pub async fn consume(&self, uid: i64, item: Item) -> Result<bool, DbErr> {
+        let Some(backpack) = user_backpack::Entity::find()
+            .filter(user_backpack::Column::Uid.eq(uid))
+            .filter(user_backpack::Column::ItemId.eq(item as i32))
+            .filter(user_backpack::Column::Status.eq(BackpackStatus::Unused as i32))
+            .order_by_asc(user_backpack::Column::Id)
+            .one(self.db)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        // 带上状态条件，并发使用同一个物品时只有一个能成功
+        let result = user_backpack::Entity::update_many()
+            .col_expr(
+                user_backpack::Column::Status,
+                Expr::value(BackpackStatus::Used as i32),
+            )
+            .filter(user_backpack::Column::Id.eq(backpack.id))
+            .filter(user_backpack::Column::Status.eq(BackpackStatus::Unused as i32))
+            .exec(self.db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+}
+
+/// 幂等号，格式为 `物品ID_业务类型_业务ID`
+pub fn idempotent(item: Item, idempotent_type: IdempotentType, business_id: &str) -> String {
+    format!("{}_{}_{}", item as i32, idempotent_type as i32, business_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::service::item::{idempotent, register_badges, IdempotentType, Item};
+
+    #[test]
+    fn register_badge() {
+        assert_eq!(
+            register_badges(10).collect::<Vec<_>>(),
+            [
+                Item::Register10Badge,
+                Item::Register100Badge,
+                Item::Register1000Badge
+            ]
+        );
+        assert_eq!(
+            register_badges(101).collect::<Vec<_>>(),
+            [Item::Register1000Badge]
+        );
+        assert_eq!(register_badges(1001).count(), 0);
+    }
+
+    #[test]
+    fn item_id() {
+        for item in [
+            Item::ModifyNameCard,
+            Item::Register100Badge,
+            Item::PlanetBadge,
+        ] {
+            assert_eq!(Item::try_from(item as i32), Ok(item));
+        }
+        assert_eq!(Item::try_from(0), Err(0));
+    }
+
+    #[test]
+    fn idempotent_key() {
+        assert_eq!(
+            idempotent(Item::ModifyNameCard, IdempotentType::Uid, "42"),
+            "1_1_42"
+        );
+    }
+}
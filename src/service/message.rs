@@ -0,0 +1,189 @@
+//! # 消息列表服务
+//!
+//! 消息表有上千万行时，`OFFSET` 分页和 `SELECT *` 排序都会随翻页深度变慢。消息列表分两步读取：
+//!
+//! 1. [`MessageRepo::page_ids`] 在 `(room_id, status, id)` 索引上按游标取出一页消息 ID，只扫描索引；
+//! 2. 按 ID 用 `IN` 查询批量读取消息内容、标记数和当前用户的标记，发送者信息通过
+//!    [`UserInfoCache`] 读取，大部分命中进程内缓存或 Redis。
+//!
+//! 每页的查询次数固定，不随页大小和翻页深度增长。
+
+use std::collections::HashMap;
+
+use sea_orm::DbErr;
+
+use crate::cache::user_info::{UserInfo, UserInfoCache};
+use crate::cache::Cache;
+use crate::handler::chat::{ChatMessageResp, MarkType, MessageMarkResp, MessageResp};
+use crate::storage::repo::{MessageRepo, UserRepo};
+
+/// 消息列表服务
+#[derive(Clone, Copy)]
+pub struct MessagePageService<'a> {
+    messages: &'a dyn MessageRepo,
+    users: &'a dyn UserRepo,
+    cache: Option<&'a Cache>,
+}
+
+impl<'a> MessagePageService<'a> {
+    /// 使用数据访问对象构造，没有 Redis 时发送者信息直接从数据库读取
+    pub fn new(
+        messages: &'a dyn MessageRepo,
+        users: &'a dyn UserRepo,
+        cache: Option<&'a Cache>,
+    ) -> Self {
+        Self {
+            messages,
+            users,
+            cache,
+        }
+    }
+
+    /// 查询房间内 ID 小于 `before` 的最多 `limit` 条正常消息，最新的在前
+    ///
+    /// `uid` 为当前用户，用于返回是否已点赞、点踩
+    pub async fn page(
+        &self,
+        room_id: i64,
+        uid: Option<i64>,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<ChatMessageResp>, DbErr> {
+        let ids = self.messages.page_ids(room_id, before, limit).await?;
+        self.hydrate(&ids, uid).await
+    }
+
+    /// 按消息 ID 批量补全消息内容、标记和发送者，按 `ids` 的顺序返回，不存在的消息忽略
+    pub async fn hydrate(
+        &self,
+        ids: &[u64],
+        uid: Option<i64>,
+    ) -> Result<Vec<ChatMessageResp>, DbErr> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (messages, counts, user_marks) = tokio::try_join!(
+            self.messages.find_by_ids(ids),
+            self.messages.count_marks(ids),
+            async {
+                match uid {
+                    Some(uid) => self.messages.user_marks(uid, ids).await,
+                    None => Ok(Vec::new()),
+                }
+            },
+        )?;
+
+        let mut marks: HashMap<i64, MessageMarkResp> = HashMap::new();
+        for count in counts {
+            let mark = marks.entry(count.msg_id).or_default();
+            match MarkType::try_from(count.mark_type) {
+                Ok(MarkType::Like) => mark.like_count = count.count,
+                Ok(MarkType::Dislike) => mark.dislike_count = count.count,
+                Err(_) => {}
+            }
+        }
+        for (msg_id, mark_type) in user_marks {
+            let mark = marks.entry(msg_id).or_default();
+            match MarkType::try_from(mark_type) {
+                Ok(MarkType::Like) => mark.user_like = true,
+                Ok(MarkType::Dislike) => mark.user_dislike = true,
+                Err(_) => {}
+            }
+        }
+
+        let mut from_uids: Vec<i64> = messages.iter().map(|message| message.from_uid).collect();
+        from_uids.sort_unstable();
+        from_uids.dedup();
+        let users: HashMap<i64, UserInfo> = self
+            .user_infos(&from_uids)
+            .await?
+            .into_iter()
+            .map(|info| (info.uid, info))
+            .collect();
+
+        let mut messages: HashMap<u64, _> = messages
+            .into_iter()
+            .map(|message| (message.id, message))
+            .collect();
+        Ok(ids
+            .iter()
+            .filter_map(|id| messages.remove(id))
+            .map(|message| ChatMessageResp {
+                from_user: users.get(&message.from_uid).cloned(),
+                mark: marks.remove(&(message.id as i64)).unwrap_or_default(),
+                message: MessageResp::from(message),
+            })
+            .collect())
+    }
+
+    async fn user_infos(&self, uids: &[i64]) -> Result<Vec<UserInfo>, DbErr> {
+        match self.cache {
+            Some(cache) => UserInfoCache::new(cache, self.users).get_many(uids).await,
+            None => Ok(self
+                .users
+                .find_by_ids(uids)
+                .await?
+                .into_iter()
+                .map(UserInfo::from)
+                .collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::Set;
+
+    use crate::handler::chat::MarkType;
+    use crate::service::message::MessagePageService;
+    use crate::storage::model::message;
+    use crate::storage::repo::MessageRepo;
+    use crate::testing::MemoryRepo;
+
+    #[tokio::test]
+    async fn page() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        let alice = repo.add_user("open_id_1", Some("alice"));
+        let bob = repo.add_user("open_id_2", Some("bob"));
+        for (room_id, from_uid) in [(1, alice), (1, bob), (2, alice), (1, alice + 10)] {
+            repo.create(message::ActiveModel {
+                room_id: Set(room_id),
+                from_uid: Set(from_uid),
+                content: Set(format!("from {from_uid}")),
+                status: Set(0),
+                ..Default::default()
+            })
+            .await?;
+        }
+        repo.add_mark(2, alice, MarkType::Like as i32);
+        repo.add_mark(2, bob, MarkType::Like as i32);
+        repo.add_mark(2, bob, MarkType::Dislike as i32);
+
+        let service = MessagePageService::new(&repo, &repo, None);
+        let page = service.page(1, Some(bob), None, 2).await?;
+        let ids: Vec<_> = page.iter().map(|message| message.message.id).collect();
+        assert_eq!(ids, vec![4, 2]);
+        assert_eq!(page[0].from_user, None);
+        assert_eq!(
+            page[1]
+                .from_user
+                .as_ref()
+                .and_then(|user| user.name.as_deref()),
+            Some("bob")
+        );
+        assert_eq!(page[1].mark.like_count, 2);
+        assert_eq!(page[1].mark.dislike_count, 1);
+        assert!(page[1].mark.user_like && page[1].mark.user_dislike);
+
+        let page = service.page(1, None, Some(2), 2).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].message.id, 1);
+        assert!(!page[0].mark.user_like);
+
+        assert!(service.hydrate(&[], None).await?.is_empty());
+        let hydrated = service.hydrate(&[3, 100, 1], None).await?;
+        let ids: Vec<_> = hydrated.iter().map(|message| message.message.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+        Ok(())
+    }
+}
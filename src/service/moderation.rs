@@ -0,0 +1,135 @@
+//! # 消息举报服务
+//!
+//! 用户举报的消息保存在 `moderation` 表中等待管理员审核，同一用户对同一消息只保留一条举报。
+//! 审核时同一消息的所有待处理举报一起处理：保留消息，或逻辑删除消息并推送撤回通知。
+
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, Select, Set,
+};
+
+use crate::storage::model::{message, moderation};
+
+/// 举报处理状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ModerationStatus {
+    /// 待处理
+    Pending = 0,
+    /// 审核通过，消息保留
+    Kept = 1,
+    /// 消息已删除
+    Deleted = 2,
+}
+
+impl TryFrom<i32> for ModerationStatus {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Pending,
+            1 => Self::Kept,
+            2 => Self::Deleted,
+            value => return Err(value),
+        })
+    }
+}
+
+/// 消息举报服务
+#[derive(Debug, Clone, Copy)]
+pub struct ModerationService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> ModerationService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 举报消息，同一用户已经举报过时返回 `None`
+    pub async fn report(
+        &self,
+        message: &message::Model,
+        reporter_uid: i64,
+        reason: Option<String>,
+    ) -> Result<Option<moderation::Model>, DbErr> {
+        let existed = moderation::Entity::find()
+            .filter(moderation::Column::MsgId.eq(message.id))
+            .filter(moderation::Column::ReporterUid.eq(reporter_uid))
+            .one(self.db)
+            .await?;
+        if existed.is_some() {
+            return Ok(None);
+        }
+        moderation::ActiveModel {
+            msg_id: Set(message.id),
+            room_id: Set(message.room_id),
+            from_uid: Set(message.from_uid),
+            reporter_uid: Set(reporter_uid),
+            reason: Set(reason),
+            status: Set(ModerationStatus::Pending as i32),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await
+        .map(Some)
+    }
+
+    /// 按 ID 查询举报
+    pub async fn find(&self, id: u64) -> Result<Option<moderation::Model>, DbErr> {
+        moderation::Entity::find_by_id(id).one(self.db).await
+    }
+
+    /// 处理消息的所有待处理举报，返回处理的举报数
+    pub async fn resolve(
+        &self,
+        msg_id: u64,
+        status: ModerationStatus,
+        operator_uid: i64,
+    ) -> Result<u64, DbErr> {
+        let result = moderation::Entity::update_many()
+            .col_expr(moderation::Column::Status, Expr::value(status as i32))
+            .col_expr(moderation::Column::OperatorUid, Expr::value(operator_uid))
+            .filter(moderation::Column::MsgId.eq(msg_id))
+            .filter(moderation::Column::Status.eq(ModerationStatus::Pending as i32))
+            .exec(self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// 审核队列，可以按状态过滤，配合游标分页按 ID 倒序查询
+    pub fn queue(&self, status: Option<ModerationStatus>) -> Select<moderation::Entity> {
+        let select = moderation::Entity::find();
+        match status {
+            Some(status) => select.filter(moderation::Column::Status.eq(status as i32)),
+            None => select,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseConnection, DbBackend, QueryTrait};
+
+    use crate::service::moderation::{ModerationService, ModerationStatus};
+
+    #[test]
+    fn status() {
+        for status in [
+            ModerationStatus::Pending,
+            ModerationStatus::Kept,
+            ModerationStatus::Deleted,
+        ] {
+            assert_eq!(ModerationStatus::try_from(status as i32), Ok(status));
+        }
+        assert_eq!(ModerationStatus::try_from(3), Err(3));
+
+        let db = DatabaseConnection::Disconnected;
+        let sql = ModerationService::new(&db)
+            .queue(Some(ModerationStatus::Pending))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert!(sql.ends_with("WHERE `moderation`.`status` = 0"), "{sql}");
+    }
+}
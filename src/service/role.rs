@@ -0,0 +1,38 @@
+//! # 角色服务
+
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter};
+
+use crate::storage::model::user_role;
+
+/// 角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub enum Role {
+    /// 超级管理员
+    SuperAdmin = 1,
+    /// 抹茶群聊管理员
+    ChatManager = 2,
+}
+
+/// 角色服务
+#[derive(Debug, Clone, Copy)]
+pub struct RoleService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> RoleService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 用户是否拥有某个角色，超级管理员拥有所有角色
+    pub async fn has_role(&self, uid: i64, role: Role) -> Result<bool, DbErr> {
+        let count = user_role::Entity::find()
+            .filter(user_role::Column::Uid.eq(uid))
+            .filter(user_role::Column::RoleId.is_in([role as i64, Role::SuperAdmin as i64]))
+            .count(self.db)
+            .await?;
+        Ok(count > 0)
+    }
+}
@@ -0,0 +1,207 @@
+//! # 房间服务
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QuerySelect,
+    Set, TransactionTrait,
+};
+
+use crate::storage::is_duplicate_key;
+use crate::storage::model::{room, room_friend};
+use crate::storage::tx::with_txn;
+
+/// 会话类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum RoomType {
+    /// 大群聊
+    Group = 1,
+    /// 单聊
+    Single = 3,
+}
+
+/// 单聊房间状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum RoomFriendStatus {
+    /// 正常
+    Normal = 0,
+    /// 禁用（删除好友后禁用）
+    Disabled = 1,
+}
+
+/// 房间服务
+#[derive(Debug, Clone, Copy)]
+pub struct RoomService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> RoomService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 查找两个用户间的单聊房间
+    pub async fn find_single(
+        &self,
+        uid: i64,
+        other_uid: i64,
+    ) -> Result<Option<room_friend::Model>, DbErr> {
+        room_friend::Entity::find()
+            .filter(room_friend::Column::RoomKey.eq(room_key(uid, other_uid)))
+            .one(self.db)
+            .await
+    }
+
+    /// 按房间 ID 查找单聊房间
+    pub async fn find_single_by_room(
+        &self,
+        room_id: i64,
+    ) -> Result<Option<room_friend::Model>, DbErr> {
+        room_friend::Entity::find()
+            .filter(room_friend::Column::RoomId.eq(room_id))
+            .one(self.db)
+            .await
+    }
+
+    /// 获取两个用户间的单聊房间，不存在则在事务中创建，已禁用则恢复
+    pub async fn get_or_create_single(
+        &self,
+        uid: i64,
+        other_uid: i64,
+    ) -> Result<room_friend::Model, DbErr>
+    where
+        C: TransactionTrait,
+    {
+        match self.find_single(uid, other_uid).await? {
+            Some(room_friend) if room_friend.status == RoomFriendStatus::Normal as i32 => {
+                Ok(room_friend)
+            }
+            Some(room_friend) => {
+                let mut room_friend: room_friend::ActiveModel = room_friend.into();
+                room_friend.status = Set(RoomFriendStatus::Normal as i32);
+                room_friend.update(self.db).await
+            }
+            None => {
+                let created =
+                    with_txn(self.db, |txn| Box::pin(create_single(txn, uid, other_uid))).await;
+                match created {
+                    // 双方同时创建时后提交的一方违反 `uniq_room_key`，事务回滚，使用先创建的房间；
+                    // 调用方可能在可重复读的事务中，普通查询读到的是事务开始时的快照，
+                    // 看不到对方刚提交的房间，加共享锁读取最新提交的记录
+                    Err(error) if is_duplicate_key(&error) => room_friend::Entity::find()
+                        .filter(room_friend::Column::RoomKey.eq(room_key(uid, other_uid)))
+                        .lock_shared()
+                        .one(self.db)
+                        .await?
+                        .ok_or(error),
+                    created => created,
+                }
+            }
+        }
+    }
+
+    /// 禁用两个用户间的单聊房间
+    pub async fn disable_single(&self, uid: i64, other_uid: i64) -> Result<(), DbErr> {
+        if let Some(room_friend) = self.find_single(uid, other_uid).await? {
+            let mut room_friend: room_friend::ActiveModel = room_friend.into();
+            room_friend.status = Set(RoomFriendStatus::Disabled as i32);
+            room_friend.update(self.db).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 创建单聊房间及两个用户的关联
+async fn create_single<C: ConnectionTrait>(
+    db: &C,
+    uid: i64,
+    other_uid: i64,
+) -> Result<room_friend::Model, DbErr> {
+    let room = room::ActiveModel {
+        name: Set(String::new()),
+        r#type: Set(RoomType::Single as i32),
+        member_count: Set(2),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    room_friend::ActiveModel {
+        room_id: Set(room.id as i64),
+        uid1: Set(uid.min(other_uid)),
+        uid2: Set(uid.max(other_uid)),
+        room_key: Set(room_key(uid, other_uid)),
+        status: Set(RoomFriendStatus::Normal as i32),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// 单聊房间 key，由排序后的两个 uid 拼接而成
+pub fn room_key(uid: i64, other_uid: i64) -> String {
+    format!("{}_{}", uid.min(other_uid), uid.max(other_uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sea_orm::{
+        ColumnTrait, ConnectionTrait, Database, DbBackend, EntityTrait, QueryFilter, Statement,
+        TransactionTrait,
+    };
+    use sea_orm_migration::MigratorTrait;
+
+    use crate::service::room::{room_key, RoomService};
+    use crate::storage::migration::Migrator;
+    use crate::storage::model::room_friend;
+
+    #[test]
+    fn room_key_is_symmetric() {
+        assert_eq!(room_key(1, 2), "1_2");
+        assert_eq!(room_key(2, 1), "1_2");
+    }
+
+    /// 两个事务同时创建同一个单聊房间，后提交的一方在已有快照的事务中也能拿到先创建的房间
+    #[tokio::test]
+    #[ignore = "需要 MySQL，设置 MALLCHAT_TEST_DATABASE_URL 后运行"]
+    async fn concurrent_create_single() -> anyhow::Result<()> {
+        let url = std::env::var("MALLCHAT_TEST_DATABASE_URL")?;
+        let db = Database::connect(url).await?;
+        Migrator::up(&db, None).await?;
+        let (uid, other_uid) = (900_000_001, 900_000_002);
+        room_friend::Entity::delete_many()
+            .filter(room_friend::Column::RoomKey.eq(room_key(uid, other_uid)))
+            .exec(&db)
+            .await?;
+
+        let first = db.begin().await?;
+        let created = RoomService::new(&first)
+            .get_or_create_single(uid, other_uid)
+            .await?;
+        let second = tokio::spawn({
+            let db = db.clone();
+            async move {
+                let txn = db.begin().await?;
+                // 先读一次，建立事务的快照
+                txn.execute(Statement::from_string(
+                    DbBackend::MySql,
+                    "SELECT COUNT(*) FROM `room_friend`".to_string(),
+                ))
+                .await?;
+                // 插入时等待第一个事务提交后违反唯一索引
+                let room_friend = RoomService::new(&txn)
+                    .get_or_create_single(other_uid, uid)
+                    .await?;
+                txn.commit().await?;
+                anyhow::Ok(room_friend)
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        first.commit().await?;
+        let found = second.await??;
+        assert_eq!(found.room_id, created.room_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! # 运营统计服务
+//!
+//! 每日统计由定时任务在次日计算后写入 `statistics` 表，同时缓存在 Redis 哈希表
+//! [`DAILY_STATS_KEY`] 中；在线人数由各实例每分钟采样累加到 [`ONLINE_SAMPLES_KEY`]，
+//! 同一分钟内所有实例的采样之和即为全局在线人数。
+
+use std::collections::BTreeMap;
+
+use redis::AsyncCommands;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use utoipa::ToSchema;
+
+use crate::cache::Cache;
+use crate::storage::model::{message, statistics, user};
+
+/// 每日统计的缓存，字段为日期（`YYYY-MM-DD`），值为 [`DailyStats`] 的 JSON
+pub const DAILY_STATS_KEY: &str = "mallchat:stats:daily";
+/// 在线人数采样，字段为 Unix 时间戳（分钟），值为该分钟各实例在线人数之和
+pub const ONLINE_SAMPLES_KEY: &str = "mallchat:stats:online";
+/// 在线人数采样的保留时间（分钟）
+const ONLINE_SAMPLES_RETENTION_MINUTES: i64 = 2 * 24 * 60;
+
+/// 某一天的统计数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyStats {
+    /// 日期
+    #[schema(value_type = String, format = Date)]
+    pub date: Date,
+    /// 新增用户数
+    pub new_users: u64,
+    /// 活跃用户数（发送过消息的用户）
+    pub active_users: u64,
+    /// 消息数
+    pub messages: u64,
+    /// 在线人数峰值
+    pub online_peak: u64,
+}
+
+impl From<statistics::Model> for DailyStats {
+    fn from(model: statistics::Model) -> Self {
+        Self {
+            date: model.date,
+            new_users: model.new_users as u64,
+            active_users: model.active_users as u64,
+            messages: model.messages as u64,
+            online_peak: model.online_peak as u64,
+        }
+    }
+}
+
+/// 在线人数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OnlineStats {
+    /// 最近一次采样的在线人数
+    pub current: u64,
+    /// 统计区间内的在线人数峰值
+    pub peak: u64,
+}
+
+/// 从采样中统计 `[from, to)` 分钟内的在线人数
+fn online_stats(samples: &BTreeMap<i64, u64>, from: i64, to: i64) -> OnlineStats {
+    let range = samples.range(from..to);
+    OnlineStats {
+        current: range.clone().next_back().map_or(0, |(_, count)| *count),
+        peak: range.map(|(_, count)| *count).max().unwrap_or_default(),
+    }
+}
+
+/// 某个时刻所在的分钟
+fn unix_minute(time: OffsetDateTime) -> i64 {
+    time.unix_timestamp().div_euclid(60)
+}
+
+/// 统计服务
+#[derive(Debug, Clone, Copy)]
+pub struct StatsService<'a, C> {
+    db: &'a C,
+    cache: &'a Cache,
+}
+
+impl<'a, C: ConnectionTrait> StatsService<'a, C> {
+    /// 使用数据库连接和 Redis 客户端构造
+    pub fn new(db: &'a C, cache: &'a Cache) -> Self {
+        Self { db, cache }
+    }
+
+    /// 记录本实例在 `now` 所在分钟的在线人数
+    pub async fn sample_online(&self, now: OffsetDateTime, online: u64) -> anyhow::Result<()> {
+        let mut connection = self.cache.connection().await?;
+        connection
+            .hincr::<_, _, _, ()>(ONLINE_SAMPLES_KEY, unix_minute(now), online)
+            .await?;
+        Ok(())
+    }
+
+    async fn online_samples(&self) -> anyhow::Result<BTreeMap<i64, u64>> {
+        let mut connection = self.cache.connection().await?;
+        let samples: BTreeMap<i64, u64> = connection.hgetall(ONLINE_SAMPLES_KEY).await?;
+        Ok(samples)
+    }
+
+    /// `[from, to)` 内的在线人数，不包括采样尚未完成的当前分钟
+    pub async fn online(
+        &self,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> anyhow::Result<OnlineStats> {
+        let samples = self.online_samples().await?;
+        Ok(online_stats(&samples, unix_minute(from), unix_minute(to)))
+    }
+
+    /// 删除过期的在线人数采样
+    pub async fn prune_online(&self, now: OffsetDateTime) -> anyhow::Result<usize> {
+        let expired = unix_minute(now) - ONLINE_SAMPLES_RETENTION_MINUTES;
+        let mut connection = self.cache.connection().await?;
+        let minutes: Vec<i64> = connection.hkeys(ONLINE_SAMPLES_KEY).await?;
+        let expired: Vec<_> = minutes
+            .into_iter()
+            .filter(|&minute| minute < expired)
+            .collect();
+        if !expired.is_empty() {
+            connection
+                .hdel::<_, _, ()>(ONLINE_SAMPLES_KEY, &expired)
+                .await?;
+        }
+        Ok(expired.len())
+    }
+
+    /// 计算 `offset` 时区下某一天的统计数据
+    pub async fn compute(&self, date: Date, offset: UtcOffset) -> anyhow::Result<DailyStats> {
+        let start = PrimitiveDateTime::new(date, Time::MIDNIGHT);
+        let end = start + time::Duration::DAY;
+        let new_users = user::Entity::find()
+            .filter(user::Column::CreateTime.gte(start))
+            .filter(user::Column::CreateTime.lt(end))
+            .count(self.db)
+            .await?;
+        let active_users = message::Entity::find()
+            .select_only()
+            .column(message::Column::FromUid)
+            .distinct()
+            .filter(message::Column::CreateTime.gte(start))
+            .filter(message::Column::CreateTime.lt(end))
+            .count(self.db)
+            .await?;
+        let messages = message::Entity::find()
+            .filter(message::Column::CreateTime.gte(start))
+            .filter(message::Column::CreateTime.lt(end))
+            .count(self.db)
+            .await?;
+        let online = self
+            .online(start.assume_offset(offset), end.assume_offset(offset))
+            .await?;
+        Ok(DailyStats {
+            date,
+            new_users,
+            active_users,
+            messages,
+            online_peak: online.peak,
+        })
+    }
+
+    /// 保存统计数据，同一天重复保存时覆盖
+    pub async fn save(&self, stats: &DailyStats) -> anyhow::Result<()> {
+        statistics::Entity::insert(statistics::ActiveModel {
+            date: Set(stats.date),
+            new_users: Set(stats.new_users as i64),
+            active_users: Set(stats.active_users as i64),
+            messages: Set(stats.messages as i64),
+            online_peak: Set(stats.online_peak as i64),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(statistics::Column::Date)
+                .update_columns([
+                    statistics::Column::NewUsers,
+                    statistics::Column::ActiveUsers,
+                    statistics::Column::Messages,
+                    statistics::Column::OnlinePeak,
+                ])
+                .to_owned(),
+        )
+        .exec(self.db)
+        .await?;
+        self.cache(std::slice::from_ref(stats)).await
+    }
+
+    async fn cache(&self, stats: &[DailyStats]) -> anyhow::Result<()> {
+        if stats.is_empty() {
+            return Ok(());
+        }
+        let items = stats
+            .iter()
+            .map(|stats| Ok((stats.date.to_string(), serde_json::to_string(stats)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut connection = self.cache.connection().await?;
+        connection
+            .hset_multiple::<_, _, _, ()>(DAILY_STATS_KEY, &items)
+            .await?;
+        Ok(())
+    }
+
+    /// 查询 `[from, to]` 内每天的统计数据，按日期排序，尚未统计的日期不返回
+    ///
+    /// 优先读取缓存，缓存中没有的从数据库读取后写回缓存
+    pub async fn daily(&self, from: Date, to: Date) -> anyhow::Result<Vec<DailyStats>> {
+        let dates: Vec<_> = std::iter::successors(Some(from), |date| date.next_day())
+            .take_while(|date| *date <= to)
+            .collect();
+        if dates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fields: Vec<_> = dates.iter().map(Date::to_string).collect();
+        let mut connection = self.cache.connection().await?;
+        let cached: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(DAILY_STATS_KEY)
+            .arg(&fields)
+            .query_async(&mut connection)
+            .await?;
+
+        let mut result = Vec::with_capacity(dates.len());
+        let mut missing = Vec::new();
+        for (date, cached) in dates.into_iter().zip(cached) {
+            match cached.and_then(|json| serde_json::from_str::<DailyStats>(&json).ok()) {
+                Some(stats) => result.push(stats),
+                None => missing.push(date),
+            }
+        }
+        if !missing.is_empty() {
+            let loaded: Vec<DailyStats> = statistics::Entity::find()
+                .filter(statistics::Column::Date.is_in(missing))
+                .order_by_asc(statistics::Column::Date)
+                .all(self.db)
+                .await?
+                .into_iter()
+                .map(DailyStats::from)
+                .collect();
+            self.cache(&loaded).await?;
+            result.extend(loaded);
+            result.sort_by_key(|stats| stats.date);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::service::stats::{online_stats, OnlineStats};
+
+    #[test]
+    fn online() {
+        let samples = BTreeMap::from([(1, 5), (2, 9), (3, 7), (5, 2)]);
+        assert_eq!(
+            online_stats(&samples, 1, 4),
+            OnlineStats {
+                current: 7,
+                peak: 9
+            }
+        );
+        assert_eq!(
+            online_stats(&samples, 3, 10),
+            OnlineStats {
+                current: 2,
+                peak: 7
+            }
+        );
+        assert_eq!(online_stats(&samples, 6, 10), OnlineStats::default());
+    }
+}
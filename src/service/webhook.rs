@@ -0,0 +1,101 @@
+//! # 事件 Webhook 服务
+//!
+//! 管理通过管理接口注册的 Webhook 地址，订阅的事件以逗号分隔保存在 `events` 中，为空时订阅全部事件
+
+use sea_orm::{ActiveModelTrait, ConnectionTrait, DbErr, EntityTrait, QueryOrder, Set};
+
+use crate::storage::model::webhook;
+use crate::webhook::{WebhookEndpoint, WebhookEventType};
+
+/// 事件 Webhook 服务
+#[derive(Debug, Clone, Copy)]
+pub struct WebhookService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> WebhookService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 所有注册的地址，按注册顺序排列
+    pub async fn list(&self) -> Result<Vec<webhook::Model>, DbErr> {
+        webhook::Entity::find()
+            .order_by_asc(webhook::Column::Id)
+            .all(self.db)
+            .await
+    }
+
+    /// 注册地址
+    pub async fn create(
+        &self,
+        uid: i64,
+        endpoint: &WebhookEndpoint,
+    ) -> Result<webhook::Model, DbErr> {
+        webhook::ActiveModel {
+            url: Set(endpoint.url.clone()),
+            secret: Set(endpoint.secret.clone()),
+            events: Set(join_events(&endpoint.events)),
+            uid: Set(uid),
+            ..Default::default()
+        }
+        .insert(self.db)
+        .await
+    }
+
+    /// 删除地址，返回是否存在
+    pub async fn delete(&self, id: u64) -> Result<bool, DbErr> {
+        let result = webhook::Entity::delete_by_id(id).exec(self.db).await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+/// 保存到 `events` 列的格式
+pub fn join_events(events: &[WebhookEventType]) -> String {
+    events
+        .iter()
+        .map(|event| event.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 解析 `events` 列，忽略无法识别的事件
+pub fn split_events(events: &str) -> Vec<WebhookEventType> {
+    events
+        .split(',')
+        .filter_map(|event| event.trim().parse().ok())
+        .collect()
+}
+
+impl From<webhook::Model> for WebhookEndpoint {
+    fn from(model: webhook::Model) -> Self {
+        Self {
+            events: split_events(&model.events),
+            url: model.url,
+            secret: model.secret,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::service::webhook::{join_events, split_events};
+    use crate::webhook::WebhookEventType;
+
+    #[test]
+    fn events() {
+        let events = [
+            WebhookEventType::UserRegistered,
+            WebhookEventType::UserBanned,
+        ];
+        let joined = join_events(&events);
+        assert_eq!(joined, "user_registered,user_banned");
+        assert_eq!(split_events(&joined), events);
+        assert_eq!(
+            split_events("message_flagged, unknown,"),
+            [WebhookEventType::MessageFlagged]
+        );
+        assert!(split_events("").is_empty());
+    }
+}
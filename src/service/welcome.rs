@@ -0,0 +1,156 @@
+//! # 公众号关注欢迎语
+//!
+//! 每个公众号在 `wx_welcome` 表中保存一条配置，用户关注时依次通过客服消息接口发送：
+//! 欢迎文本，以及从永久图文素材获取的图文卡片。未配置或都为空时不发送。
+
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, Set};
+use time::OffsetDateTime;
+
+use crate::storage::model::wx_welcome;
+use crate::weixin::{WxApi, WxMessage, WxMessageData};
+
+/// 关注欢迎语服务
+#[derive(Debug, Clone, Copy)]
+pub struct WelcomeService<'a, C> {
+    db: &'a C,
+}
+
+impl<'a, C: ConnectionTrait> WelcomeService<'a, C> {
+    /// 使用数据库连接或事务构造
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    /// 查询公众号的欢迎语
+    pub async fn find(&self, app_id: &str) -> Result<Option<wx_welcome::Model>, DbErr> {
+        wx_welcome::Entity::find()
+            .filter(wx_welcome::Column::AppId.eq(app_id))
+            .one(self.db)
+            .await
+    }
+
+    /// 保存公众号的欢迎语，已存在时覆盖
+    pub async fn save(
+        &self,
+        app_id: &str,
+        uid: i64,
+        text: Option<String>,
+        news_media_id: Option<String>,
+    ) -> Result<Option<wx_welcome::Model>, DbErr> {
+        wx_welcome::Entity::insert(wx_welcome::ActiveModel {
+            app_id: Set(app_id.to_string()),
+            text: Set(text),
+            news_media_id: Set(news_media_id),
+            uid: Set(uid),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(wx_welcome::Column::AppId)
+                .update_columns([
+                    wx_welcome::Column::Text,
+                    wx_welcome::Column::NewsMediaId,
+                    wx_welcome::Column::Uid,
+                ])
+                .to_owned(),
+        )
+        .exec(self.db)
+        .await?;
+        self.find(app_id).await
+    }
+}
+
+/// 向关注的用户发送欢迎语，返回发送的消息数
+///
+/// `to_user` 为用户的 OpenID，`from_user` 为公众号的原始 ID
+pub async fn send_welcome(
+    welcome: &wx_welcome::Model,
+    wx_client: &dyn WxApi,
+    to_user: &str,
+    from_user: &str,
+) -> anyhow::Result<usize> {
+    let mut sent = 0;
+    if let Some(text) = welcome.text.as_deref().filter(|text| !text.is_empty()) {
+        let message = WxMessage {
+            to_user_name: to_user.to_string(),
+            from_user_name: from_user.to_string(),
+            create_time: OffsetDateTime::now_utc(),
+            data: WxMessageData::Text {
+                content: text.to_string(),
+            },
+            msg_id: None,
+            msg_data_id: None,
+            idx: None,
+        };
+        wx_client.send_custom_message(&message).await?;
+        sent += 1;
+    }
+    if let Some(media_id) = welcome.news_media_id.as_deref().filter(|id| !id.is_empty()) {
+        let articles = wx_client.get_news_material(media_id).await?;
+        if !articles.is_empty() {
+            wx_client.send_custom_news(to_user, &articles).await?;
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::service::welcome::send_welcome;
+    use crate::storage::model::wx_welcome;
+    use crate::testing::MockWxClient;
+    use crate::weixin::WxMessageData;
+
+    fn welcome(text: Option<&str>, news_media_id: Option<&str>) -> wx_welcome::Model {
+        wx_welcome::Model {
+            id: 1,
+            app_id: "mock_app_id".to_string(),
+            text: text.map(str::to_string),
+            news_media_id: news_media_id.map(str::to_string),
+            uid: 1,
+            create_time: datetime!(2023-08-17 08:00),
+            update_time: datetime!(2023-08-17 08:00),
+        }
+    }
+
+    #[tokio::test]
+    async fn send() -> anyhow::Result<()> {
+        let wx_client = MockWxClient::default();
+        let sent = send_welcome(
+            &welcome(Some("欢迎关注"), Some("MEDIA")),
+            &wx_client,
+            "open_id",
+            "gh_mock",
+        )
+        .await?;
+        assert_eq!(sent, 2);
+        let messages = wx_client.sent_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].to_user_name, "open_id");
+        assert!(
+            matches!(&messages[0].data, WxMessageData::Text { content } if content == "欢迎关注")
+        );
+        let news = wx_client.sent_news();
+        assert_eq!(news.len(), 1);
+        assert_eq!(news[0].0, "open_id");
+        assert_eq!(news[0].1[0].title, "MEDIA");
+
+        // 都为空时不发送
+        let sent = send_welcome(&welcome(Some(""), None), &wx_client, "open_id", "gh_mock").await?;
+        assert_eq!(sent, 0);
+
+        // 素材无效时返回错误
+        assert!(send_welcome(
+            &welcome(None, Some("invalid")),
+            &wx_client,
+            "open_id",
+            "gh_mock"
+        )
+        .await
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! # 短链接和二维码
+//!
+//! 群聊邀请等链接较长，生成的二维码密度高、不易识别。[`ShortLink`] 把 URL 保存在 Redis 中，
+//! 返回 `{base_url}/s/{slug}` 形式的短链接，访问时重定向到原 URL，过期后自动删除。
+//!
+//! [`render_qr`] 在服务端把文本渲染为 PNG 二维码，不方便自行生成二维码的客户端（例如登录页的降级方案）
+//! 可以直接使用 `/capi/qr?data=...` 作为图片地址。
+
+use std::io::Cursor;
+
+use image::{ImageFormat, Luma};
+use qrcode::QrCode;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+
+/// 短链接的键前缀，后接 slug
+const SHORT_LINK_KEY: &str = "mallchat:shortlink";
+
+/// slug 长度，62 个字符中随机选取
+pub const SLUG_LEN: usize = 8;
+
+/// slug 冲突时的最大重试次数
+const MAX_ATTEMPTS: usize = 3;
+
+/// 短链接配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortLinkConfig {
+    /// 是否启用，需要 Redis
+    #[serde(default)]
+    pub enabled: bool,
+    /// 短链接的前缀，包含协议、域名和路由前缀，例如 `https://chat.example.com`，为空时返回相对路径
+    #[serde(default)]
+    pub base_url: String,
+    /// 群聊邀请页面的地址，`{token}` 替换为邀请 token，配置后生成邀请时同时返回短链接
+    #[serde(default)]
+    pub invite_url: Option<String>,
+    /// 短链接的最长有效期（秒）
+    #[serde(default = "default::max_ttl_secs")]
+    pub max_ttl_secs: u64,
+}
+
+mod default {
+    pub fn max_ttl_secs() -> u64 {
+        7 * 24 * 60 * 60
+    }
+}
+
+impl Default for ShortLinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            base_url: String::new(),
+            invite_url: None,
+            max_ttl_secs: default::max_ttl_secs(),
+        }
+    }
+}
+
+/// 短链接的生成和解析
+#[derive(Debug, Clone)]
+pub struct ShortLink {
+    config: ShortLinkConfig,
+    cache: Cache,
+}
+
+impl ShortLink {
+    /// 创建
+    pub fn new(config: ShortLinkConfig, cache: Cache) -> Self {
+        Self { config, cache }
+    }
+
+    /// 配置
+    pub fn config(&self) -> &ShortLinkConfig {
+        &self.config
+    }
+
+    /// 保存 URL，返回短链接，有效期不超过 `max_ttl_secs`
+    pub async fn create(&self, url: &str, ttl_secs: u64) -> anyhow::Result<String> {
+        let ttl_secs = ttl_secs.clamp(1, self.config.max_ttl_secs.max(1));
+        let mut connection = self.cache.connection().await?;
+        for _ in 0..MAX_ATTEMPTS {
+            let slug = slug(&mut rand::thread_rng());
+            let created: bool = redis::cmd("SET")
+                .arg(key(&slug))
+                .arg(url)
+                .arg("NX")
+                .arg("EX")
+                .arg(ttl_secs)
+                .query_async::<_, Option<String>>(&mut connection)
+                .await?
+                .is_some();
+            if created {
+                return Ok(self.url(&slug));
+            }
+        }
+        anyhow::bail!("failed to allocate short link slug after {MAX_ATTEMPTS} attempts")
+    }
+
+    /// 生成群聊邀请的短链接，未配置 `invite_url` 时返回 `None`
+    pub async fn create_invite(
+        &self,
+        token: &str,
+        ttl_secs: u64,
+    ) -> anyhow::Result<Option<String>> {
+        match &self.config.invite_url {
+            Some(template) => {
+                let url = template.replace("{token}", token);
+                Ok(Some(self.create(&url, ttl_secs).await?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 查找短链接对应的 URL，不存在或已过期时返回 `None`
+    pub async fn resolve(&self, slug: &str) -> anyhow::Result<Option<String>> {
+        if !is_slug(slug) {
+            return Ok(None);
+        }
+        let mut connection = self.cache.connection().await?;
+        Ok(connection.get(key(slug)).await?)
+    }
+
+    fn url(&self, slug: &str) -> String {
+        format!("{}/s/{slug}", self.config.base_url.trim_end_matches('/'))
+    }
+}
+
+fn key(slug: &str) -> String {
+    format!("{SHORT_LINK_KEY}:{slug}")
+}
+
+/// 随机生成 slug
+fn slug(rng: &mut impl Rng) -> String {
+    rng.sample_iter(&Alphanumeric)
+        .take(SLUG_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// 是否可能是生成的 slug，避免任意字符串拼入 Redis 键
+fn is_slug(slug: &str) -> bool {
+    slug.len() == SLUG_LEN && slug.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// 把 `data` 渲染为 PNG 二维码，图片边长不小于 `size` 像素，数据过长时返回错误
+pub fn render_qr(data: &[u8], size: u32) -> anyhow::Result<Vec<u8>> {
+    let image = QrCode::new(data)?
+        .render::<Luma<u8>>()
+        .min_dimensions(size, size)
+        .build();
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::cache::Cache;
+    use crate::shortlink::{is_slug, render_qr, slug, ShortLink, ShortLinkConfig, SLUG_LEN};
+
+    #[test]
+    fn slugs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let generated = slug(&mut rng);
+        assert_eq!(generated.len(), SLUG_LEN);
+        assert!(is_slug(&generated));
+        assert_ne!(generated, slug(&mut rng));
+
+        assert!(!is_slug("short"));
+        assert!(!is_slug("abc:defg"));
+        assert!(!is_slug("abcdefgh1"));
+    }
+
+    #[test]
+    fn qr_png() -> anyhow::Result<()> {
+        let png = render_qr(b"https://example.com/s/abcdefgh", 256)?;
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let image = image::load_from_memory(&png)?;
+        assert!(image.width() >= 256);
+        assert_eq!(image.width(), image.height());
+
+        assert!(render_qr(&[b'a'; 4096], 256).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn urls() -> anyhow::Result<()> {
+        let config = ShortLinkConfig {
+            enabled: true,
+            base_url: "https://chat.example.com/".to_string(),
+            ..Default::default()
+        };
+        let shortlink = ShortLink::new(config, Cache::open("redis://127.0.0.1:1/")?);
+        assert_eq!(
+            shortlink.url("abcdefgh"),
+            "https://chat.example.com/s/abcdefgh"
+        );
+        assert_eq!(shortlink.create_invite("token", 60).await?, None);
+        assert_eq!(shortlink.resolve("../etc").await?, None);
+        Ok(())
+    }
+}
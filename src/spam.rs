@@ -0,0 +1,461 @@
+//! # 反垃圾消息
+//!
+//! 发送消息时由 [`SpamGuard`] 检查三类行为：时间窗口内重复发送相同内容、短时间内连续发送、
+//! 单条消息中的链接过多。违规次数在 `strike_window_secs` 内累计，依次触发 `actions` 中的处罚，
+//! 超过后重复最后一项：
+//!
+//! - [`SpamAction::Warn`]：消息正常发送，向发送者推送系统通知；
+//! - [`SpamAction::Mute`]：拒绝这条消息，并在该房间禁言 `mute_minutes` 分钟；
+//! - [`SpamAction::Report`]：消息正常发送，同时以系统（uid 为 [`SYSTEM_REPORTER_UID`]）的名义举报到审核队列。
+//!
+//! 规则可以按房间单独配置。发送记录保存在各实例的内存中，多实例部署时按实例分别统计。
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::url_discover::extract_urls;
+
+/// 自动举报时使用的举报人 uid
+pub const SYSTEM_REPORTER_UID: i64 = 0;
+
+/// 反垃圾配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpamConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+    /// 默认规则
+    #[serde(flatten)]
+    pub rules: SpamRules,
+    /// 单独配置规则的房间
+    #[serde(default)]
+    pub rooms: Vec<RoomSpam>,
+}
+
+impl SpamConfig {
+    /// 房间使用的规则
+    pub fn rules(&self, room_id: i64) -> &SpamRules {
+        self.rooms
+            .iter()
+            .find(|room| room.room_id == room_id)
+            .map_or(&self.rules, |room| &room.rules)
+    }
+}
+
+/// 房间的反垃圾规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomSpam {
+    /// 房间 ID
+    pub room_id: i64,
+    /// 规则，未配置的项使用默认值
+    #[serde(flatten)]
+    pub rules: SpamRules,
+}
+
+/// 反垃圾规则，数量限制为 0 时不检查该项
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpamRules {
+    /// 重复内容的检测窗口（秒）
+    #[serde(default = "default::duplicate_window_secs")]
+    pub duplicate_window_secs: u64,
+    /// 窗口内相同内容最多发送的次数
+    #[serde(default = "default::duplicate_limit")]
+    pub duplicate_limit: usize,
+    /// 连续发送的检测窗口（秒）
+    #[serde(default = "default::burst_window_secs")]
+    pub burst_window_secs: u64,
+    /// 窗口内最多发送的消息数
+    #[serde(default = "default::burst_limit")]
+    pub burst_limit: usize,
+    /// 每条消息最多包含的链接数
+    #[serde(default = "default::max_links")]
+    pub max_links: usize,
+    /// 违规次数的累计窗口（秒）
+    #[serde(default = "default::strike_window_secs")]
+    pub strike_window_secs: u64,
+    /// 第 n 次违规触发第 n 项处罚，超过后重复最后一项，为空时只记录日志
+    #[serde(default = "default::actions")]
+    pub actions: Vec<SpamAction>,
+    /// 禁言时长（分钟）
+    #[serde(default = "default::mute_minutes")]
+    pub mute_minutes: u64,
+}
+
+mod default {
+    use super::SpamAction;
+
+    pub fn duplicate_window_secs() -> u64 {
+        60
+    }
+
+    pub fn duplicate_limit() -> usize {
+        3
+    }
+
+    pub fn burst_window_secs() -> u64 {
+        10
+    }
+
+    pub fn burst_limit() -> usize {
+        10
+    }
+
+    pub fn max_links() -> usize {
+        3
+    }
+
+    pub fn strike_window_secs() -> u64 {
+        3600
+    }
+
+    pub fn actions() -> Vec<SpamAction> {
+        vec![SpamAction::Warn, SpamAction::Mute, SpamAction::Report]
+    }
+
+    pub fn mute_minutes() -> u64 {
+        10
+    }
+}
+
+impl Default for SpamRules {
+    fn default() -> Self {
+        Self {
+            duplicate_window_secs: default::duplicate_window_secs(),
+            duplicate_limit: default::duplicate_limit(),
+            burst_window_secs: default::burst_window_secs(),
+            burst_limit: default::burst_limit(),
+            max_links: default::max_links(),
+            strike_window_secs: default::strike_window_secs(),
+            actions: default::actions(),
+            mute_minutes: default::mute_minutes(),
+        }
+    }
+}
+
+/// 违规类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamKind {
+    /// 重复发送相同内容
+    Duplicate,
+    /// 短时间内连续发送
+    Burst,
+    /// 链接过多
+    TooManyLinks,
+}
+
+impl SpamKind {
+    /// 提示文本
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Duplicate => "重复发送相同内容",
+            Self::Burst => "发送消息过于频繁",
+            Self::TooManyLinks => "消息中的链接过多",
+        }
+    }
+}
+
+/// 处罚
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamAction {
+    /// 推送警告
+    Warn,
+    /// 临时禁言
+    Mute,
+    /// 自动举报
+    Report,
+}
+
+/// 检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpamVerdict {
+    /// 违规类型
+    pub kind: SpamKind,
+    /// 累计窗口内的违规次数
+    pub strikes: usize,
+    /// 处罚，未配置处罚时为 `None`
+    pub action: Option<SpamAction>,
+}
+
+/// 用户在房间内的发送记录
+#[derive(Debug, Default)]
+struct SenderState {
+    /// 最近发送的消息：发送时间、内容哈希
+    sent: VecDeque<(Instant, u64)>,
+    /// 最近的违规时间
+    strikes: VecDeque<Instant>,
+    /// 禁言截止时间
+    mute_until: Option<Instant>,
+}
+
+impl SenderState {
+    /// 清理过期的记录，全部过期时返回 `true`
+    fn expire(&mut self, rules: &SpamRules, now: Instant) -> bool {
+        let window = Duration::from_secs(rules.duplicate_window_secs.max(rules.burst_window_secs));
+        while self
+            .sent
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) >= window)
+        {
+            self.sent.pop_front();
+        }
+        let window = Duration::from_secs(rules.strike_window_secs);
+        while self
+            .strikes
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= window)
+        {
+            self.strikes.pop_front();
+        }
+        if self.mute_until.is_some_and(|until| until <= now) {
+            self.mute_until = None;
+        }
+        self.sent.is_empty() && self.strikes.is_empty() && self.mute_until.is_none()
+    }
+}
+
+/// # 反垃圾检查
+///
+/// 按用户和房间记录最近发送的消息
+#[derive(Debug, Clone)]
+pub struct SpamGuard {
+    config: Arc<SpamConfig>,
+    senders: Arc<DashMap<(i64, i64), SenderState>>,
+}
+
+impl SpamGuard {
+    /// 创建
+    pub fn new(config: SpamConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            senders: Arc::default(),
+        }
+    }
+
+    /// 用户在房间内剩余的禁言时间，未禁言时返回 `None`
+    pub fn muted(&self, uid: i64, room_id: i64) -> Option<Duration> {
+        self.muted_at(uid, room_id, Instant::now())
+    }
+
+    fn muted_at(&self, uid: i64, room_id: i64, now: Instant) -> Option<Duration> {
+        let until = self.senders.get(&(uid, room_id))?.mute_until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// 检查并记录用户在房间内发送的消息，违规时返回处罚，处罚为禁言时同时开始禁言
+    pub fn check(&self, uid: i64, room_id: i64, content: &str) -> Option<SpamVerdict> {
+        self.check_at(uid, room_id, content, Instant::now())
+    }
+
+    fn check_at(&self, uid: i64, room_id: i64, content: &str, now: Instant) -> Option<SpamVerdict> {
+        let rules = self.config.rules(room_id);
+        let digest = digest(content);
+        let mut state = self.senders.entry((uid, room_id)).or_default();
+        state.expire(rules, now);
+
+        let within = |secs: u64| {
+            let window = Duration::from_secs(secs);
+            state
+                .sent
+                .iter()
+                .filter(move |(time, _)| now.duration_since(*time) < window)
+        };
+        let duplicates = within(rules.duplicate_window_secs)
+            .filter(|(_, sent)| *sent == digest)
+            .count();
+        let recent = within(rules.burst_window_secs).count();
+        let kind = if rules.duplicate_limit > 0 && duplicates >= rules.duplicate_limit {
+            Some(SpamKind::Duplicate)
+        } else if rules.burst_limit > 0 && recent >= rules.burst_limit {
+            Some(SpamKind::Burst)
+        } else if rules.max_links > 0
+            && extract_urls(content, rules.max_links + 1).len() > rules.max_links
+        {
+            Some(SpamKind::TooManyLinks)
+        } else {
+            None
+        };
+        state.sent.push_back((now, digest));
+
+        let kind = kind?;
+        state.strikes.push_back(now);
+        let strikes = state.strikes.len();
+        let action = rules
+            .actions
+            .get(strikes - 1)
+            .or(rules.actions.last())
+            .copied();
+        if action == Some(SpamAction::Mute) {
+            state.mute_until = Some(now + Duration::from_secs(rules.mute_minutes * 60));
+        }
+        Some(SpamVerdict {
+            kind,
+            strikes,
+            action,
+        })
+    }
+
+    /// 清理过期的发送记录，返回剩余的记录数
+    pub fn purge(&self) -> usize {
+        let now = Instant::now();
+        self.senders
+            .retain(|(_, room_id), state| !state.expire(self.config.rules(*room_id), now));
+        self.senders.len()
+    }
+
+    /// 启动定时清理的任务，停止时取消返回的任务即可
+    pub fn spawn(self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.purge();
+            }
+        })
+    }
+}
+
+/// 内容摘要，忽略首尾空白
+fn digest(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.trim().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use config::{Config, File, FileFormat};
+
+    use crate::spam::{
+        RoomSpam, SpamAction, SpamConfig, SpamGuard, SpamKind, SpamRules, SpamVerdict,
+    };
+
+    #[test]
+    fn config() -> anyhow::Result<()> {
+        let config: SpamConfig = Config::builder()
+            .add_source(File::from_str(
+                r#"
+                enabled = true
+                burst_limit = 5
+                actions = ["warn", "report"]
+
+                [[rooms]]
+                room_id = 1
+                max_links = 0
+                "#,
+                FileFormat::Toml,
+            ))
+            .build()?
+            .try_deserialize()?;
+        assert!(config.enabled);
+        assert_eq!(config.rules.burst_limit, 5);
+        assert_eq!(config.rules.actions, [SpamAction::Warn, SpamAction::Report]);
+        assert_eq!(config.rules(1).max_links, 0);
+        assert_eq!(
+            config.rules(1).burst_limit,
+            SpamRules::default().burst_limit
+        );
+        assert_eq!(config.rules(2), &config.rules);
+        Ok(())
+    }
+
+    #[test]
+    fn escalate() {
+        let guard = SpamGuard::new(SpamConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        let second = |secs: u64| now + Duration::from_secs(secs);
+        for secs in 0..3 {
+            assert_eq!(guard.check_at(1, 1, "抹茶", second(secs)), None);
+        }
+        // 其他用户和其他房间分别统计
+        assert_eq!(guard.check_at(2, 1, "抹茶", second(3)), None);
+        assert_eq!(guard.check_at(1, 2, "抹茶", second(3)), None);
+
+        let verdict = |strikes, action| {
+            Some(SpamVerdict {
+                kind: SpamKind::Duplicate,
+                strikes,
+                action: Some(action),
+            })
+        };
+        assert_eq!(
+            guard.check_at(1, 1, " 抹茶 ", second(3)),
+            verdict(1, SpamAction::Warn)
+        );
+        assert_eq!(guard.muted_at(1, 1, second(3)), None);
+        assert_eq!(
+            guard.check_at(1, 1, "抹茶", second(4)),
+            verdict(2, SpamAction::Mute)
+        );
+        assert_eq!(
+            guard.muted_at(1, 1, second(5)),
+            Some(Duration::from_secs(599))
+        );
+        assert_eq!(guard.muted_at(1, 2, second(5)), None);
+        assert_eq!(guard.muted_at(1, 1, second(604)), None);
+        assert_eq!(
+            guard.check_at(1, 1, "抹茶", second(40)),
+            verdict(3, SpamAction::Report)
+        );
+        assert_eq!(
+            guard.check_at(1, 1, "抹茶", second(41)),
+            verdict(4, SpamAction::Report)
+        );
+        // 重复窗口过后不再违规
+        assert_eq!(guard.check_at(1, 1, "抹茶", second(200)), None);
+    }
+
+    #[test]
+    fn burst_and_links() {
+        let guard = SpamGuard::new(SpamConfig {
+            enabled: true,
+            rules: SpamRules {
+                burst_limit: 2,
+                actions: Vec::new(),
+                ..Default::default()
+            },
+            rooms: vec![RoomSpam {
+                room_id: 2,
+                rules: SpamRules {
+                    max_links: 1,
+                    ..Default::default()
+                },
+            }],
+        });
+        let now = Instant::now();
+        assert_eq!(guard.check_at(1, 1, "1", now), None);
+        assert_eq!(guard.check_at(1, 1, "2", now), None);
+        assert_eq!(
+            guard.check_at(1, 1, "3", now),
+            Some(SpamVerdict {
+                kind: SpamKind::Burst,
+                strikes: 1,
+                action: None,
+            })
+        );
+        assert_eq!(
+            guard.check_at(1, 1, "4", now + Duration::from_secs(10)),
+            None
+        );
+
+        let links = "https://a.com https://b.com";
+        assert_eq!(
+            guard.check_at(1, 2, links, now).map(|verdict| verdict.kind),
+            Some(SpamKind::TooManyLinks)
+        );
+        assert_eq!(guard.check_at(1, 3, links, now), None);
+    }
+}
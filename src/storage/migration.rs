@@ -0,0 +1,75 @@
+//! # 数据库迁移
+//!
+//! 新增或修改表结构时，在此目录下添加新的迁移文件并注册到 [`Migrator`]，
+//! 同时同步修改 `script/init.sql`
+
+use sea_orm_migration::prelude::*;
+
+mod m20230601_000001_create_tables;
+mod m20230801_000001_create_user_emoji;
+mod m20230802_000001_register_1000_badge;
+mod m20230803_000001_create_statistics;
+mod m20230804_000001_message_fulltext;
+mod m20230805_000001_create_announcement;
+mod m20230806_000001_create_user_credential;
+mod m20230807_000001_room_last_message;
+mod m20230808_000001_create_room_group;
+mod m20230809_000001_message_page_index;
+mod m20230810_000001_write_behind;
+mod m20230811_000001_room_member_count;
+mod m20230812_000001_create_webhook;
+mod m20230813_000001_create_api_key;
+mod m20230814_000001_create_message_archive;
+mod m20230815_000001_soft_delete;
+mod m20230816_000001_create_moderation;
+mod m20230817_000001_create_wx_welcome;
+mod m20230818_000001_utc_datetime;
+mod m20230819_000001_room_read_index;
+mod m20230820_000001_user_apply_unique;
+
+/// 迁移执行器
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20230601_000001_create_tables::Migration),
+            Box::new(m20230801_000001_create_user_emoji::Migration),
+            Box::new(m20230802_000001_register_1000_badge::Migration),
+            Box::new(m20230803_000001_create_statistics::Migration),
+            Box::new(m20230804_000001_message_fulltext::Migration),
+            Box::new(m20230805_000001_create_announcement::Migration),
+            Box::new(m20230806_000001_create_user_credential::Migration),
+            Box::new(m20230807_000001_room_last_message::Migration),
+            Box::new(m20230808_000001_create_room_group::Migration),
+            Box::new(m20230809_000001_message_page_index::Migration),
+            Box::new(m20230810_000001_write_behind::Migration),
+            Box::new(m20230811_000001_room_member_count::Migration),
+            Box::new(m20230812_000001_create_webhook::Migration),
+            Box::new(m20230813_000001_create_api_key::Migration),
+            Box::new(m20230814_000001_create_message_archive::Migration),
+            Box::new(m20230815_000001_soft_delete::Migration),
+            Box::new(m20230816_000001_create_moderation::Migration),
+            Box::new(m20230817_000001_create_wx_welcome::Migration),
+            Box::new(m20230818_000001_utc_datetime::Migration),
+            Box::new(m20230819_000001_room_read_index::Migration),
+            Box::new(m20230820_000001_user_apply_unique::Migration),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::migration::Migrator;
+    use sea_orm_migration::MigratorTrait;
+
+    #[test]
+    fn migrations_in_order() {
+        let names = Migrator::migrations()
+            .iter()
+            .map(|m| m.name().to_string())
+            .collect::<Vec<_>>();
+        assert!(names.windows(2).all(|w| w[0] < w[1]), "{names:?}");
+    }
+}
@@ -0,0 +1,262 @@
+//! # 初始化表结构
+//!
+//! 与 `script/init.sql` 保持一致，已存在的表和数据会被跳过，便于从手动建表的环境切换过来
+
+use sea_orm_migration::prelude::*;
+
+/// 建表语句，按创建顺序排列
+const TABLES: &[(&str, &str)] = &[
+    (
+        "item_config",
+        r#"CREATE TABLE IF NOT EXISTS `item_config`  (
+    `id` bigint(20) UNSIGNED NOT NULL COMMENT 'id',
+    `type` int(11) NOT NULL COMMENT '物品类型 1改名卡 2徽章',
+    `img` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '物品图片',
+    `describe` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '物品功能描述',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '功能物品配置表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "message",
+        r#"CREATE TABLE IF NOT EXISTS `message`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+    `from_uid` bigint(20) NOT NULL COMMENT '消息发送者uid',
+    `content` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci DEFAULT NULL COMMENT '消息内容',
+    `reply_msg_id` bigint(20) NULL DEFAULT NULL COMMENT '回复的消息内容',
+    `status` int(11) NOT NULL COMMENT '消息状态 0正常 1删除',
+    `gap_count` int(11) NULL DEFAULT NULL COMMENT '与回复的消息间隔多少条',
+    `type` int(11) NULL DEFAULT 1 COMMENT '消息类型 1普通消息 2.撤回消息',
+    `extra` json DEFAULT NULL COMMENT '扩展信息',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_room_id`(`room_id`) USING BTREE,
+    INDEX `idx_from_uid`(`from_uid`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '消息表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "message_mark",
+        r#"CREATE TABLE IF NOT EXISTS `message_mark`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `msg_id` bigint(20) NOT NULL COMMENT '消息表id',
+    `uid` bigint(20) NOT NULL COMMENT '标记人uid',
+    `type` int(11) NOT NULL COMMENT '标记类型 1点赞 2举报',
+    `status` int(11) NOT NULL COMMENT '消息状态 0正常 1取消',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_msg_id`(`msg_id`) USING BTREE,
+    INDEX `idx_uid`(`uid`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '消息标记表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "room",
+        r#"CREATE TABLE IF NOT EXISTS `room`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '会话名',
+    `type` int(11) NOT NULL COMMENT '会话类型 1大群聊 2沸点 3单聊',
+    `active_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '最后活跃时间-排序',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_active_time`(`active_time`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '会话表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "user",
+        r#"CREATE TABLE IF NOT EXISTS `user`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT '用户id',
+    `name` varchar(20) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '用户昵称',
+    `avatar` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '用户头像',
+    `sex` int(11) NULL DEFAULT NULL COMMENT '性别 1为男性，2为女性',
+    `open_id` char(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '微信openid用户标识',
+    `last_opt_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '最后上下线时间',
+    `ip_info` json NULL COMMENT 'ip信息',
+    `item_id` bigint(20) NULL DEFAULT NULL COMMENT '佩戴的徽章id',
+    `status` int(11) DEFAULT "0" COMMENT '使用状态 0.正常 1拉黑',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_open_id`(`open_id`) USING BTREE,
+    UNIQUE INDEX `uniq_name`(`name`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "user_backpack",
+        r#"CREATE TABLE IF NOT EXISTS `user_backpack`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT 'uid',
+    `item_id` int(11) NOT NULL COMMENT '物品id',
+    `status` int(11) NOT NULL COMMENT '使用状态 0.待使用 1已使用',
+    `idempotent` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '幂等号',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_idempotent`(`idempotent`) USING BTREE,
+    INDEX `idx_uid`(`uid`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户背包表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "user_friend",
+        r#"CREATE TABLE IF NOT EXISTS `user_friend`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT 'uid',
+    `friend_uid` bigint(20) NOT NULL COMMENT '好友uid',
+    `delete_status` int(11) NOT NULL DEFAULT 0 COMMENT '逻辑删除 0正常 1删除',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_uid_friend_uid`(`uid`, `friend_uid`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户联系人表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "user_apply",
+        r#"CREATE TABLE IF NOT EXISTS `user_apply`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT '申请人uid',
+    `type` int(11) NOT NULL COMMENT '申请类型 1加好友',
+    `target_id` bigint(20) NOT NULL COMMENT '接收人uid',
+    `msg` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '申请信息',
+    `status` int(11) NOT NULL COMMENT '申请状态 1待审批 2同意 3拒绝',
+    `read_status` int(11) NOT NULL COMMENT '阅读状态 1未读 2已读',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_uid_target_id`(`uid`, `target_id`) USING BTREE,
+    INDEX `idx_target_id_read_status`(`target_id`, `read_status`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户申请表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "room_friend",
+        r#"CREATE TABLE IF NOT EXISTS `room_friend`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `room_id` bigint(20) NOT NULL COMMENT '房间id',
+    `uid1` bigint(20) NOT NULL COMMENT 'uid1（更小的uid）',
+    `uid2` bigint(20) NOT NULL COMMENT 'uid2（更大的uid）',
+    `room_key` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '房间key由两个uid拼接，先做排序uid1_uid2',
+    `status` int(11) NOT NULL COMMENT '房间状态 0正常 1禁用(删好友了禁用)',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_room_key`(`room_key`) USING BTREE,
+    INDEX `idx_room_id`(`room_id`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '单聊房间表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "wx_msg",
+        r#"CREATE TABLE IF NOT EXISTS `wx_msg`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `open_id` char(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '微信openid用户标识',
+    `msg` text CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NOT NULL COMMENT '用户消息',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_open_id`(`open_id`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE,
+    INDEX `idx_update_time`(`update_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci COMMENT = '微信消息表' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "black",
+        r#"CREATE TABLE IF NOT EXISTS `black`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `type` int(11) NOT NULL COMMENT '拉黑目标类型 1.ip 2uid',
+    `target` varchar(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '拉黑目标',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `idx_type_target`(`type`, `target`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '黑名单' ROW_FORMAT = Dynamic;"#,
+    ),
+    (
+        "role",
+        r#"CREATE TABLE IF NOT EXISTS `role` (
+    `id` bigint(20) unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(64) COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '角色名称',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    KEY `idx_create_time` (`create_time`) USING BTREE,
+    KEY `idx_update_time` (`update_time`) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci COMMENT='角色表';"#,
+    ),
+    (
+        "user_role",
+        r#"CREATE TABLE IF NOT EXISTS `user_role` (
+    `id` bigint(20) unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT 'uid',
+    `role_id` bigint(20) NOT NULL COMMENT '角色id',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    KEY `idx_uid` (`uid`) USING BTREE,
+    KEY `idx_role_id` (`role_id`) USING BTREE,
+    KEY `idx_create_time` (`create_time`) USING BTREE,
+    KEY `idx_update_time` (`update_time`) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci COMMENT='用户角色关系表';"#,
+    ),
+];
+
+/// 初始数据
+const SEEDS: &[&str] = &[
+    r#"INSERT IGNORE INTO `item_config` VALUES (1, 1, NULL, '用户可以使用改名卡，更改自己的名字。mallchat名称全局唯一，快抢订你的专属昵称吧', '2023-03-25 22:27:30.511', '2023-03-25 22:27:30.511');"#,
+    r#"INSERT IGNORE INTO `item_config` VALUES (2, 2, 'https://cdn-icons-png.flaticon.com/128/1533/1533913.png', '爆赞徽章，单条消息被点赞超过10次，即可获得', '2023-05-07 17:50:31.090', '2023-05-07 18:12:05.824');"#,
+    r#"INSERT IGNORE INTO `item_config` VALUES (3, 2, 'https://cdn-icons-png.flaticon.com/512/6198/6198527.png ', '抹茶聊天前10名注册的用户才能获得的专属徽章', '2023-05-07 17:50:31.100', '2023-05-07 18:12:01.448');"#,
+    r#"INSERT IGNORE INTO `item_config` VALUES (4, 2, 'https://cdn-icons-png.flaticon.com/512/10232/10232583.png', '抹茶聊天前100名注册的用户才能获得的专属徽章', '2023-05-07 17:50:31.109', '2023-05-07 17:56:36.059');"#,
+    r#"INSERT IGNORE INTO `item_config` VALUES (5, 2, 'https://cdn-icons-png.flaticon.com/128/2909/2909937.png', '抹茶知识星球成员的专属徽章', '2023-05-07 17:50:31.109', '2023-05-07 17:56:36.059');"#,
+    r#"INSERT IGNORE INTO `room` VALUES (1, '抹茶群聊', 1, '2023-03-25 22:30:07.328', '2023-03-25 22:30:07.328', '2023-03-25 22:30:07.328');"#,
+    r#"INSERT IGNORE INTO role(id,`name`) values(1,'超级管理员');"#,
+    r#"INSERT IGNORE INTO role(id,`name`) values(2,'抹茶群聊管理员');"#,
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for (_, sql) in TABLES {
+            db.execute_unprepared(sql).await?;
+        }
+        for sql in SEEDS {
+            db.execute_unprepared(sql).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, _) in TABLES.iter().rev() {
+            manager
+                .drop_table(
+                    Table::drop()
+                        .table(Alias::new(table))
+                        .if_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
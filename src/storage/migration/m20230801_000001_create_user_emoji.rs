@@ -0,0 +1,39 @@
+//! # 用户表情包
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS `user_emoji`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT '用户表ID',
+    `expression_url` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '表情地址',
+    `delete_status` int(1) NOT NULL DEFAULT 0 COMMENT '逻辑删除(0-正常,1-删除)',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_uid`(`uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户表情包' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_TABLE)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new("user_emoji"))
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,27 @@
+//! # 前 1000 名注册徽章
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"INSERT IGNORE INTO `item_config` VALUES (6, 2, NULL, '抹茶聊天前1000名注册的用户才能获得的专属徽章', '2023-08-02 00:00:00.000', '2023-08-02 00:00:00.000');"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DELETE FROM `item_config` WHERE `id` = 6;")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+//! # 每日统计
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS `statistics`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `date` date NOT NULL COMMENT '统计日期',
+    `new_users` bigint(20) NOT NULL DEFAULT 0 COMMENT '新增用户数',
+    `active_users` bigint(20) NOT NULL DEFAULT 0 COMMENT '活跃用户数（发送过消息）',
+    `messages` bigint(20) NOT NULL DEFAULT 0 COMMENT '消息数',
+    `online_peak` bigint(20) NOT NULL DEFAULT 0 COMMENT '在线人数峰值',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_date`(`date`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '每日统计' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_TABLE)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new("statistics"))
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
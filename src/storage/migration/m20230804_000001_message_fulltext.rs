@@ -0,0 +1,29 @@
+//! # 消息全文索引
+//!
+//! 使用 ngram 分词，支持中文搜索，分词长度由 MySQL 的 `ngram_token_size` 决定（默认为 2）
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE `message` ADD FULLTEXT INDEX `ft_content`(`content`) WITH PARSER ngram",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE `message` DROP INDEX `ft_content`")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+//! # 系统公告
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_ANNOUNCEMENT: &str = r#"CREATE TABLE IF NOT EXISTS `announcement`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT '发布公告的管理员uid',
+    `content` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '公告内容',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_create_time`(`create_time`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '系统公告' ROW_FORMAT = Dynamic;"#;
+
+const CREATE_ANNOUNCEMENT_READ: &str = r#"CREATE TABLE IF NOT EXISTS `announcement_read`  (
+    `uid` bigint(20) NOT NULL COMMENT '用户uid',
+    `last_id` bigint(20) UNSIGNED NOT NULL COMMENT '已送达的最新公告id',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '用户已送达的公告' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection.execute_unprepared(CREATE_ANNOUNCEMENT).await?;
+        connection
+            .execute_unprepared(CREATE_ANNOUNCEMENT_READ)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in ["announcement_read", "announcement"] {
+            manager
+                .drop_table(
+                    Table::drop()
+                        .table(Alias::new(table))
+                        .if_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
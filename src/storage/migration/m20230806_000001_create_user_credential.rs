@@ -0,0 +1,38 @@
+//! # 本地账号
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_USER_CREDENTIAL: &str = r#"CREATE TABLE IF NOT EXISTS `user_credential`  (
+    `uid` bigint(20) NOT NULL COMMENT '用户uid',
+    `email` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '登录邮箱',
+    `password_hash` varchar(128) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT 'argon2 密码哈希',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`uid`) USING BTREE,
+    UNIQUE INDEX `uniq_email`(`email`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '本地账号的登录凭据' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_USER_CREDENTIAL)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new("user_credential"))
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,33 @@
+//! # 房间最后一条消息
+//!
+//! 发送消息时保存消息摘要，会话列表不需要再查询、截取消息内容
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE `room` \
+                ADD COLUMN `last_msg_id` bigint(20) NULL DEFAULT NULL COMMENT '会话中的最后一条消息id' AFTER `active_time`, \
+                ADD COLUMN `last_msg_abstract` varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '最后一条消息的摘要' AFTER `last_msg_id`",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE `room` DROP COLUMN `last_msg_abstract`, DROP COLUMN `last_msg_id`",
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! # 群聊
+//!
+//! 群聊房间的公告及群成员的角色、禁言时间
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_ROOM_GROUP: &str = r#"CREATE TABLE IF NOT EXISTS `room_group`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `room_id` bigint(20) NOT NULL COMMENT '房间id',
+    `announcement` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '群公告',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_room_id`(`room_id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '群聊房间' ROW_FORMAT = Dynamic;"#;
+
+const CREATE_GROUP_MEMBER: &str = r#"CREATE TABLE IF NOT EXISTS `group_member`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `room_id` bigint(20) NOT NULL COMMENT '房间id',
+    `uid` bigint(20) NOT NULL COMMENT '成员uid',
+    `role` int(11) NOT NULL COMMENT '成员角色 1群主 2管理员 3普通成员',
+    `mute_until` datetime(3) NULL DEFAULT NULL COMMENT '禁言截止时间',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_room_id_uid`(`room_id`, `uid`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '群成员' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection.execute_unprepared(CREATE_ROOM_GROUP).await?;
+        connection.execute_unprepared(CREATE_GROUP_MEMBER).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in ["group_member", "room_group"] {
+            manager
+                .drop_table(
+                    Table::drop()
+                        .table(Alias::new(table))
+                        .if_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
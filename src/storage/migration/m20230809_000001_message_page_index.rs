@@ -0,0 +1,51 @@
+//! # 消息分页索引
+//!
+//! 消息列表先在 `(room_id, status, id)` 上只扫描索引取出一页消息 ID，再按主键批量读取消息内容，
+//! 翻页深度不影响回表次数；消息标记按 `(msg_id, status, type)` 统计，不需要回表。
+//! 新索引以 `room_id`、`msg_id` 开头，原来的单列索引不再需要。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `message` \
+                ADD INDEX `idx_room_status_id`(`room_id`, `status`, `id`) USING BTREE, \
+                DROP INDEX `idx_room_id`",
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `message_mark` \
+                ADD INDEX `idx_msg_status_type`(`msg_id`, `status`, `type`) USING BTREE, \
+                DROP INDEX `idx_msg_id`",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `message_mark` \
+                ADD INDEX `idx_msg_id`(`msg_id`) USING BTREE, \
+                DROP INDEX `idx_msg_status_type`",
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `message` \
+                ADD INDEX `idx_room_id`(`room_id`) USING BTREE, \
+                DROP INDEX `idx_room_status_id`",
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! # 消息标记和阅读进度的批量写入
+//!
+//! 消息标记和阅读进度由 [`WriteBehind`](crate::storage::write_behind::WriteBehind) 合并后批量 upsert，
+//! 需要唯一索引：每个用户对每条消息的每种标记一行，每个用户在每个房间的阅读进度一行。
+//! 原来的 `message_mark` 没有唯一索引，添加前删除重复的标记，只保留最新的一条
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_ROOM_READ: &str = r#"CREATE TABLE IF NOT EXISTS `room_read`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `uid` bigint(20) NOT NULL COMMENT '用户uid',
+    `room_id` bigint(20) NOT NULL COMMENT '房间id',
+    `read_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '已读的最新消息id',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_uid_room_id`(`uid`, `room_id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '房间阅读进度' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                "DELETE `older` FROM `message_mark` `older` \
+                JOIN `message_mark` `newer` ON `older`.`msg_id` = `newer`.`msg_id` \
+                AND `older`.`uid` = `newer`.`uid` \
+                AND `older`.`type` = `newer`.`type` AND `older`.`id` < `newer`.`id`",
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `message_mark` \
+                ADD UNIQUE INDEX `uniq_msg_uid_type`(`msg_id`, `uid`, `type`) USING BTREE",
+            )
+            .await?;
+        connection.execute_unprepared(CREATE_ROOM_READ).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new("room_read"))
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE `message_mark` DROP INDEX `uniq_msg_uid_type`")
+            .await?;
+        Ok(())
+    }
+}
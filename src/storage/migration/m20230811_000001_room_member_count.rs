@@ -0,0 +1,38 @@
+//! # 房间成员数
+//!
+//! 在 `room` 中冗余保存成员数，加入、退出群聊时在同一事务中更新，查询群聊信息时不再统计 `group_member`。
+//! 冗余值与实际值的偏差由 [`MemberCountReconcile`](crate::jobs::member_count::MemberCountReconcile) 定时修正
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "ALTER TABLE `room` \
+            ADD COLUMN `member_count` int(11) UNSIGNED NOT NULL DEFAULT 0 COMMENT '成员数，群聊为群成员记录数，单聊为 2' AFTER `type`",
+        )
+        .await?;
+        db.execute_unprepared(
+            "UPDATE `room` SET `member_count` = \
+            (SELECT COUNT(*) FROM `group_member` WHERE `group_member`.`room_id` = `room`.`id`) \
+            WHERE `type` = 1",
+        )
+        .await?;
+        db.execute_unprepared("UPDATE `room` SET `member_count` = 2 WHERE `type` = 3")
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE `room` DROP COLUMN `member_count`")
+            .await?;
+        Ok(())
+    }
+}
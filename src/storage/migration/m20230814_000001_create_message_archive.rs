@@ -0,0 +1,62 @@
+//! # 消息归档
+//!
+//! 超过保留期的消息移动到 `message_archive` 表或对象存储，见 [`archive`](crate::jobs::archive)
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_MESSAGE_ARCHIVE: &str = r#"CREATE TABLE IF NOT EXISTS `message_archive`  (
+    `id` bigint(20) UNSIGNED NOT NULL COMMENT '原消息id',
+    `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+    `from_uid` bigint(20) NOT NULL COMMENT '消息发送者uid',
+    `content` varchar(1024) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci DEFAULT NULL COMMENT '消息内容',
+    `reply_msg_id` bigint(20) NULL DEFAULT NULL COMMENT '回复的消息内容',
+    `status` int(11) NOT NULL COMMENT '消息状态 0正常 1删除',
+    `gap_count` int(11) NULL DEFAULT NULL COMMENT '与回复的消息间隔多少条',
+    `type` int(11) NULL DEFAULT 1 COMMENT '消息类型 1正常文本 2.撤回消息',
+    `extra` json DEFAULT NULL COMMENT '扩展信息',
+    `create_time` datetime(3) NOT NULL COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL COMMENT '修改时间',
+    `archive_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '归档时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_room_id_id`(`room_id`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '归档的消息' ROW_FORMAT = Dynamic;"#;
+
+const CREATE_ARCHIVE_FILE: &str = r#"CREATE TABLE IF NOT EXISTS `archive_file`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+    `object_key` varchar(512) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL COMMENT '对象存储中的路径',
+    `first_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '第一条消息id',
+    `last_msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '最后一条消息id',
+    `message_count` int(11) UNSIGNED NOT NULL COMMENT '消息数',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    INDEX `idx_room_id_id`(`room_id`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '归档到对象存储的消息文件' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(CREATE_MESSAGE_ARCHIVE).await?;
+        db.execute_unprepared(CREATE_ARCHIVE_FILE).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in ["archive_file", "message_archive"] {
+            manager
+                .drop_table(
+                    Table::drop()
+                        .table(Alias::new(table))
+                        .if_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! # 逻辑删除
+//!
+//! 用户、消息、房间和好友关系增加删除时间，删除时只写入墓碑标记，见 [`SoftDelete`](crate::storage::soft_delete::SoftDelete)
+
+use sea_orm_migration::prelude::*;
+
+const TABLES: [&str; 4] = ["user", "message", "room", "user_friend"];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for table in TABLES {
+            db.execute_unprepared(&format!(
+                "ALTER TABLE `{table}` \
+                ADD COLUMN `deleted_at` datetime(3) NULL DEFAULT NULL COMMENT '删除时间，为空时未删除' AFTER `update_time`"
+            ))
+            .await?;
+        }
+        // 已有的逻辑删除记录以最后修改时间作为删除时间
+        db.execute_unprepared(
+            "UPDATE `message` SET `deleted_at` = `update_time` WHERE `status` = 1",
+        )
+        .await?;
+        db.execute_unprepared(
+            "UPDATE `user_friend` SET `deleted_at` = `update_time` WHERE `delete_status` = 1",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for table in TABLES {
+            db.execute_unprepared(&format!("ALTER TABLE `{table}` DROP COLUMN `deleted_at`"))
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+//! # 消息举报
+//!
+//! 用户举报的消息进入审核队列，见 [`moderation`](crate::service::moderation)
+
+use sea_orm_migration::prelude::*;
+
+const CREATE_MODERATION: &str = r#"CREATE TABLE IF NOT EXISTS `moderation`  (
+    `id` bigint(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `msg_id` bigint(20) UNSIGNED NOT NULL COMMENT '被举报的消息id',
+    `room_id` bigint(20) NOT NULL COMMENT '会话表id',
+    `from_uid` bigint(20) NOT NULL COMMENT '消息发送者uid',
+    `reporter_uid` bigint(20) NOT NULL COMMENT '举报人uid',
+    `reason` varchar(200) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NULL DEFAULT NULL COMMENT '举报理由',
+    `status` int(11) NOT NULL DEFAULT 0 COMMENT '处理状态 0待处理 1保留 2已删除',
+    `operator_uid` bigint(20) NULL DEFAULT NULL COMMENT '处理的管理员uid',
+    `create_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT '创建时间',
+    `update_time` datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间',
+    PRIMARY KEY (`id`) USING BTREE,
+    UNIQUE INDEX `uniq_msg_id_reporter_uid`(`msg_id`, `reporter_uid`) USING BTREE,
+    INDEX `idx_status_id`(`status`, `id`) USING BTREE
+) ENGINE = InnoDB CHARACTER SET = utf8mb4 COLLATE = utf8mb4_unicode_ci COMMENT = '消息举报' ROW_FORMAT = Dynamic;"#;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(CREATE_MODERATION)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new("moderation"))
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
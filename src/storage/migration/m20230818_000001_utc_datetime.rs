@@ -0,0 +1,116 @@
+//! # 统一时间列
+//!
+//! 时间列统一为保存 UTC 时间的 `datetime(3)`，见 [`timestamp`](crate::timestamp)。
+//! 从其他版本升级的数据库中可能存在精度不足毫秒的 `datetime` 列或按会话时区转换的 `timestamp` 列，
+//! 修改为 `datetime(3)`，保留可空、默认值、自动更新和注释。
+//! 迁移使用的连接会话时区为 `+00:00`，`timestamp` 列的值转换后为 UTC 时间。
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 需要修改的列
+const COLUMNS: &str = "SELECT `TABLE_NAME` AS `table_name`, `COLUMN_NAME` AS `column_name`, \
+    `IS_NULLABLE` AS `nullable`, `COLUMN_DEFAULT` AS `default_value`, `EXTRA` AS `extra`, \
+    `COLUMN_COMMENT` AS `comment` \
+    FROM `information_schema`.`COLUMNS` \
+    WHERE `TABLE_SCHEMA` = DATABASE() \
+    AND (`DATA_TYPE` = 'timestamp' OR (`DATA_TYPE` = 'datetime' AND `DATETIME_PRECISION` < 3))";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let rows = db
+            .query_all(Statement::from_string(
+                manager.get_database_backend(),
+                COLUMNS.to_string(),
+            ))
+            .await?;
+        for row in rows {
+            let table: String = row.try_get("", "table_name")?;
+            let column: String = row.try_get("", "column_name")?;
+            let nullable: String = row.try_get("", "nullable")?;
+            let default: Option<String> = row.try_get("", "default_value")?;
+            let extra: String = row.try_get("", "extra")?;
+            let comment: String = row.try_get("", "comment")?;
+            let definition = column_definition(nullable == "YES", default, &extra, &comment);
+            db.execute_unprepared(&format!(
+                "ALTER TABLE `{table}` MODIFY COLUMN `{column}` {definition}"
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // 原来的列类型没有保存，`datetime(3)` 兼容原来的数据，不需要恢复
+        Ok(())
+    }
+}
+
+/// 修改后的列定义
+fn column_definition(
+    nullable: bool,
+    default: Option<String>,
+    extra: &str,
+    comment: &str,
+) -> String {
+    let mut definition = String::from("datetime(3)");
+    definition.push_str(if nullable { " NULL" } else { " NOT NULL" });
+    match default {
+        Some(default)
+            if default
+                .to_ascii_uppercase()
+                .starts_with("CURRENT_TIMESTAMP") =>
+        {
+            definition.push_str(" DEFAULT CURRENT_TIMESTAMP(3)")
+        }
+        Some(default) => definition.push_str(&format!(" DEFAULT '{}'", escape(&default))),
+        None if nullable => definition.push_str(" DEFAULT NULL"),
+        None => {}
+    }
+    if extra
+        .to_ascii_lowercase()
+        .contains("on update current_timestamp")
+    {
+        definition.push_str(" ON UPDATE CURRENT_TIMESTAMP(3)");
+    }
+    if !comment.is_empty() {
+        definition.push_str(&format!(" COMMENT '{}'", escape(comment)));
+    }
+    definition
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "''")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::migration::m20230818_000001_utc_datetime::column_definition;
+
+    #[test]
+    fn definition() {
+        assert_eq!(
+            column_definition(
+                false,
+                Some("CURRENT_TIMESTAMP".to_string()),
+                "DEFAULT_GENERATED on update CURRENT_TIMESTAMP",
+                "修改时间",
+            ),
+            "datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) \
+            ON UPDATE CURRENT_TIMESTAMP(3) COMMENT '修改时间'"
+        );
+        assert_eq!(
+            column_definition(true, None, "", "用户's 时间"),
+            "datetime(3) NULL DEFAULT NULL COMMENT '用户''s 时间'"
+        );
+        assert_eq!(
+            column_definition(false, Some("2023-06-01 00:00:00".to_string()), "", ""),
+            "datetime(3) NOT NULL DEFAULT '2023-06-01 00:00:00'"
+        );
+    }
+}
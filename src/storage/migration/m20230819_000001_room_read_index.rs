@@ -0,0 +1,31 @@
+//! # 消息已读数索引
+//!
+//! 群聊消息的已读数按阅读进度统计：`room_read` 中同一房间 `read_msg_id` 不小于消息 ID 的用户已读该消息。
+//! 在 `(room_id, read_msg_id, uid)` 上只扫描索引即可统计已读数和列出已读用户。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE `room_read` \
+                ADD INDEX `idx_room_read_uid`(`room_id`, `read_msg_id`, `uid`) USING BTREE",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE `room_read` DROP INDEX `idx_room_read_uid`")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+//! # 好友申请唯一索引
+//!
+//! 同一用户对同一用户的同类申请只保留一条，并发申请不会写入重复记录，再次申请时更新原来的记录。
+//! 添加索引前删除重复的申请，只保留最新的一条；新索引以 `(uid, target_id)` 开头，原来的索引不再需要。
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                "DELETE `older` FROM `user_apply` `older` \
+                JOIN `user_apply` `newer` ON `older`.`uid` = `newer`.`uid` \
+                AND `older`.`target_id` = `newer`.`target_id` \
+                AND `older`.`type` = `newer`.`type` AND `older`.`id` < `newer`.`id`",
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                "ALTER TABLE `user_apply` \
+                ADD UNIQUE INDEX `uniq_uid_target_id_type`(`uid`, `target_id`, `type`) USING BTREE, \
+                DROP INDEX `idx_uid_target_id`",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE `user_apply` \
+                ADD INDEX `idx_uid_target_id`(`uid`, `target_id`) USING BTREE, \
+                DROP INDEX `uniq_uid_target_id_type`",
+            )
+            .await?;
+        Ok(())
+    }
+}
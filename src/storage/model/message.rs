@@ -18,6 +18,7 @@ pub struct Model {
     pub extra: Option<Json>,
     pub create_time: TimeDateTime,
     pub update_time: TimeDateTime,
+    pub deleted_at: Option<TimeDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,11 +2,30 @@
 
 pub mod prelude;
 
+pub mod announcement;
+pub mod announcement_read;
+pub mod api_key;
+pub mod archive_file;
 pub mod black;
+pub mod group_member;
 pub mod item_config;
 pub mod message;
+pub mod message_archive;
 pub mod message_mark;
+pub mod moderation;
+pub mod role;
 pub mod room;
+pub mod room_friend;
+pub mod room_group;
+pub mod room_read;
+pub mod statistics;
 pub mod user;
+pub mod user_apply;
 pub mod user_backpack;
+pub mod user_credential;
+pub mod user_emoji;
+pub mod user_friend;
+pub mod user_role;
+pub mod webhook;
 pub mod wx_msg;
+pub mod wx_welcome;
@@ -1,10 +1,29 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
+pub use super::announcement::Entity as Announcement;
+pub use super::announcement_read::Entity as AnnouncementRead;
+pub use super::api_key::Entity as ApiKey;
+pub use super::archive_file::Entity as ArchiveFile;
 pub use super::black::Entity as Black;
+pub use super::group_member::Entity as GroupMember;
 pub use super::item_config::Entity as ItemConfig;
 pub use super::message::Entity as Message;
+pub use super::message_archive::Entity as MessageArchive;
 pub use super::message_mark::Entity as MessageMark;
+pub use super::moderation::Entity as Moderation;
+pub use super::role::Entity as Role;
 pub use super::room::Entity as Room;
+pub use super::room_friend::Entity as RoomFriend;
+pub use super::room_group::Entity as RoomGroup;
+pub use super::room_read::Entity as RoomRead;
+pub use super::statistics::Entity as Statistics;
 pub use super::user::Entity as User;
+pub use super::user_apply::Entity as UserApply;
 pub use super::user_backpack::Entity as UserBackpack;
+pub use super::user_credential::Entity as UserCredential;
+pub use super::user_emoji::Entity as UserEmoji;
+pub use super::user_friend::Entity as UserFriend;
+pub use super::user_role::Entity as UserRole;
+pub use super::webhook::Entity as Webhook;
 pub use super::wx_msg::Entity as WxMsg;
+pub use super::wx_welcome::Entity as WxWelcome;
@@ -10,9 +10,13 @@ pub struct Model {
     pub id: u64,
     pub name: String,
     pub r#type: i32,
+    pub member_count: u32,
     pub active_time: TimeDateTime,
+    pub last_msg_id: Option<i64>,
+    pub last_msg_abstract: Option<String>,
     pub create_time: TimeDateTime,
     pub update_time: TimeDateTime,
+    pub deleted_at: Option<TimeDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
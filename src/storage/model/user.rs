@@ -20,6 +20,7 @@ pub struct Model {
     pub status: Option<i32>,
     pub create_time: TimeDateTime,
     pub update_time: TimeDateTime,
+    pub deleted_at: Option<TimeDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
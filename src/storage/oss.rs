@@ -0,0 +1,312 @@
+//! # 对象存储
+//!
+//! 图片、文件消息和头像保存在对象存储中。前端先通过 `/capi/oss/upload/url` 获取预签名的上传 URL，
+//! 再直接 `PUT` 文件内容，文件不经过业务接口。
+//!
+//! 支持本地磁盘（[`local::LocalStore`]）和 S3 兼容的对象存储（[`s3::S3Store`]，如 AWS S3、阿里云 OSS、MinIO）。
+//!
+//! 只有上传场景（[`OssScene`]）的前缀公开访问，消息归档等其他对象通过预签名的下载 URL 获取。
+//! 使用 S3 时存储桶的公开读权限也只应授予这些前缀，不能整个存储桶公开。
+
+pub mod local;
+pub mod s3;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::storage::oss::local::{LocalConfig, LocalStore};
+use crate::storage::oss::s3::{S3Config, S3Store};
+
+/// 以 Extension 注入的对象存储
+pub type DynObjectStore = Arc<dyn ObjectStore>;
+
+/// 对象存储
+#[async_trait]
+pub trait ObjectStore: Debug + Send + Sync {
+    /// 生成预签名的上传 URL，在 `expires` 内可以使用 `PUT` 上传，上传时的 `Content-Type` 必须为 `content_type`
+    fn presign_put(
+        &self,
+        key: &str,
+        content_type: &str,
+        expires: Duration,
+    ) -> anyhow::Result<String>;
+    /// 由服务端直接上传
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> anyhow::Result<()>;
+    /// 生成预签名的下载 URL，在 `expires` 内可以使用 `GET` 下载，用于不公开的对象
+    fn presign_get(&self, key: &str, expires: Duration) -> anyhow::Result<String>;
+    /// 公开访问的 URL
+    fn public_url(&self, key: &str) -> String;
+    /// 删除对象，对象不存在时不返回错误
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// 对象存储配置，配置了 `s3` 时使用 S3，否则使用本地磁盘
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OssConfig {
+    /// 预签名上传、下载 URL 的有效期（秒）
+    #[serde(default = "default::presign_expire_secs")]
+    pub presign_expire_secs: u64,
+    /// 头像的大小上限（字节）
+    #[serde(default = "default::avatar_max_bytes")]
+    pub avatar_max_bytes: usize,
+    /// 表情包的大小上限（字节）
+    #[serde(default = "default::emoji_max_bytes")]
+    pub emoji_max_bytes: usize,
+    /// 本地磁盘
+    #[serde(default)]
+    pub local: LocalConfig,
+    /// S3 兼容的对象存储
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+}
+
+mod default {
+    pub fn presign_expire_secs() -> u64 {
+        300
+    }
+
+    pub fn avatar_max_bytes() -> usize {
+        1024 * 1024
+    }
+
+    pub fn emoji_max_bytes() -> usize {
+        1024 * 1024
+    }
+}
+
+impl Default for OssConfig {
+    fn default() -> Self {
+        Self {
+            presign_expire_secs: default::presign_expire_secs(),
+            avatar_max_bytes: default::avatar_max_bytes(),
+            emoji_max_bytes: default::emoji_max_bytes(),
+            local: LocalConfig::default(),
+            s3: None,
+        }
+    }
+}
+
+impl OssConfig {
+    /// 预签名上传、下载 URL 的有效期
+    pub fn presign_expires(&self) -> Duration {
+        Duration::from_secs(self.presign_expire_secs)
+    }
+
+    /// 创建对象存储，使用本地磁盘时同时返回 [`LocalStore`]，用于提供上传和下载接口
+    pub fn build(&self) -> anyhow::Result<(DynObjectStore, Option<LocalStore>)> {
+        match &self.s3 {
+            Some(s3) => Ok((Arc::new(S3Store::new(s3.clone())?), None)),
+            None => {
+                let local = LocalStore::new(self.local.clone());
+                Ok((Arc::new(local.clone()), Some(local)))
+            }
+        }
+    }
+}
+
+/// 上传场景，决定对象的路径前缀
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde_repr::Serialize_repr, serde_repr::Deserialize_repr,
+)]
+#[repr(i32)]
+pub enum OssScene {
+    /// 聊天消息中的图片、文件
+    Chat = 1,
+    /// 表情包
+    Emoji = 2,
+    /// 头像
+    Avatar = 3,
+}
+
+impl OssScene {
+    /// 路径前缀
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Emoji => "emoji",
+            Self::Avatar => "avatar",
+        }
+    }
+
+    /// 允许上传的扩展名对应的 MIME 类型，头像、表情包只允许图片；不允许 HTML、SVG 等可以执行脚本的类型
+    pub fn content_type(self, extension: &str) -> Option<&'static str> {
+        if let Some(format) = ImageFormat::from_extension(extension) {
+            return Some(format.mime());
+        }
+        match self {
+            Self::Chat => FILE_TYPES
+                .iter()
+                .find(|(allowed, _)| allowed.eq_ignore_ascii_case(extension))
+                .map(|(_, content_type)| *content_type),
+            Self::Emoji | Self::Avatar => None,
+        }
+    }
+}
+
+/// 聊天消息中允许发送的文件类型（图片以外）
+const FILE_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+];
+
+/// 生成对象的路径 `场景/年月/uid/随机串.扩展名` 及其 MIME 类型，扩展名不在场景的允许范围内时返回 `None`
+///
+/// 不使用原文件名，避免重名覆盖和路径注入
+pub fn object_key(scene: OssScene, uid: i64, file_name: &str) -> Option<(String, &'static str)> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    let content_type = scene.content_type(&extension)?;
+    let now = OffsetDateTime::now_utc();
+    let random: u128 = rand::thread_rng().gen();
+    let key = format!(
+        "{}/{:04}{:02}/{uid}/{random:032x}.{extension}",
+        scene.prefix(),
+        now.year(),
+        now.month() as u8
+    );
+    Some((key, content_type))
+}
+
+/// 对象是否由 [`object_key`] 为该用户、场景生成
+pub fn is_owned_key(key: &str, scene: OssScene, uid: i64) -> bool {
+    let mut parts = key.split('/');
+    parts.next() == Some(scene.prefix())
+        && parts.next().is_some()
+        && parts.next() == Some(uid.to_string().as_str())
+        && parts
+            .next()
+            .is_some_and(|name| !name.is_empty() && !name.starts_with('.'))
+        && parts.next().is_none()
+}
+
+/// 支持的图片格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// PNG
+    Png,
+    /// JPEG
+    Jpeg,
+    /// GIF
+    Gif,
+    /// WebP
+    Webp,
+}
+
+impl ImageFormat {
+    /// 根据文件头识别格式
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(Self::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(Self::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    /// 根据扩展名识别格式
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    /// 扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+        }
+    }
+
+    /// MIME 类型
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::oss::{is_owned_key, object_key, ImageFormat, OssScene};
+
+    #[test]
+    fn key() {
+        let key = |scene, file_name| object_key(scene, 1, file_name).map(|(key, _)| key);
+        let (chat, content_type) = object_key(OssScene::Chat, 1, "photo.PNG").unwrap_or_default();
+        assert_eq!(content_type, "image/png");
+        let parts: Vec<_> = chat.split('/').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "chat");
+        assert_eq!(parts[2], "1");
+        assert!(parts[3].ends_with(".png"));
+        assert_ne!(Some(chat), key(OssScene::Chat, "photo.PNG"));
+
+        assert_eq!(key(OssScene::Avatar, "../../etc/passwd"), None);
+        assert_eq!(key(OssScene::Emoji, "noext"), None);
+        // 可以执行脚本的类型不能上传
+        assert_eq!(key(OssScene::Chat, "index.html"), None);
+        assert_eq!(key(OssScene::Chat, "image.svg"), None);
+        assert!(key(OssScene::Chat, "report.pdf").is_some());
+        assert_eq!(key(OssScene::Avatar, "report.pdf"), None);
+
+        let key = key(OssScene::Avatar, "a.png").unwrap_or_default();
+        assert!(is_owned_key(&key, OssScene::Avatar, 1));
+        assert!(!is_owned_key(&key, OssScene::Avatar, 2));
+        assert!(!is_owned_key(&key, OssScene::Chat, 1));
+        assert!(!is_owned_key(
+            "avatar/202306/1/../2/a.png",
+            OssScene::Avatar,
+            1
+        ));
+    }
+
+    #[test]
+    fn image_format() {
+        assert_eq!(
+            ImageFormat::detect(b"\x89PNG\r\n\x1a\n...."),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(ImageFormat::detect(b"<svg></svg>"), None);
+        assert_eq!(ImageFormat::from_extension("JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_extension("svg"), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! # 本地磁盘
+//!
+//! 上传 URL 指向本服务的 `PUT /capi/oss/local/{key}` 接口，使用 HMAC-SHA256 签名防止伪造，
+//! 上传的文件通过 `GET {serve_path}/{key}` 访问，只公开上传场景的前缀；归档文件等不公开的对象
+//! 通过同样签名的 `GET /capi/oss/local/{key}` 下载。适合开发环境和单实例部署
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::OffsetDateTime;
+
+use crate::storage::oss::ObjectStore;
+
+/// 本地上传、签名下载接口的路径前缀
+pub const UPLOAD_PATH: &str = "/capi/oss/local";
+
+/// 本地磁盘配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalConfig {
+    /// 保存文件的目录
+    #[serde(default = "default::path")]
+    pub path: PathBuf,
+    /// 访问文件的路径前缀
+    #[serde(default = "default::serve_path")]
+    pub serve_path: String,
+    /// 签名密钥，未配置时启动时随机生成，重启后之前的上传 URL 失效
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+mod default {
+    use std::path::PathBuf;
+
+    pub fn path() -> PathBuf {
+        PathBuf::from("upload")
+    }
+
+    pub fn serve_path() -> String {
+        "/oss".to_string()
+    }
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            path: default::path(),
+            serve_path: default::serve_path(),
+            secret: None,
+        }
+    }
+}
+
+/// 签名 URL 的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum SignatureError {
+    /// 签名不正确
+    #[error("Invalid upload signature")]
+    Invalid,
+    /// 已过期
+    #[error("Upload url expired")]
+    Expired,
+}
+
+/// 本地磁盘存储
+#[derive(Clone)]
+pub struct LocalStore {
+    root: Arc<PathBuf>,
+    serve_path: Arc<str>,
+    secret: Arc<[u8]>,
+}
+
+impl std::fmt::Debug for LocalStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalStore")
+            .field("root", &self.root)
+            .field("serve_path", &self.serve_path)
+            .finish()
+    }
+}
+
+impl LocalStore {
+    /// 创建
+    pub fn new(config: LocalConfig) -> Self {
+        let secret = match config.secret {
+            Some(secret) => secret.into_bytes(),
+            None => rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+        };
+        Self {
+            root: Arc::new(config.path),
+            serve_path: config.serve_path.trim_end_matches('/').into(),
+            secret: secret.into(),
+        }
+    }
+
+    /// 保存文件的目录
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 访问文件的路径前缀
+    pub fn serve_path(&self) -> &str {
+        &self.serve_path
+    }
+
+    fn mac(&self, method: &str, key: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC can take key of any size");
+        mac.update(method.as_bytes());
+        mac.update(b"\n");
+        mac.update(key.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    fn sign(&self, method: &str, key: &str, expires: i64) -> String {
+        hex::encode(self.mac(method, key, expires).finalize().into_bytes())
+    }
+
+    /// 生成 `method` 请求的签名 URL
+    fn presign(&self, method: &str, key: &str, expires: Duration) -> anyhow::Result<String> {
+        self.path(key)?;
+        let expires = OffsetDateTime::now_utc().unix_timestamp() + expires.as_secs() as i64;
+        Ok(format!(
+            "{UPLOAD_PATH}/{key}?expires={expires}&signature={}",
+            self.sign(method, key, expires)
+        ))
+    }
+
+    /// 校验上传、下载 URL 的签名，`method` 为 `PUT` 或 `GET`
+    pub fn verify(
+        &self,
+        method: &str,
+        key: &str,
+        expires: i64,
+        signature: &str,
+    ) -> Result<(), SignatureError> {
+        let mac = self.mac(method, key, expires);
+        let signature = hex::decode(signature).map_err(|_| SignatureError::Invalid)?;
+        mac.verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)?;
+        if expires < OffsetDateTime::now_utc().unix_timestamp() {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+
+    /// 读取对象，对象不存在时返回 `None`
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// 对象在磁盘上的路径，不允许跳出保存目录
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(key);
+        let valid = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        anyhow::ensure!(valid && !key.is_empty(), "invalid object key: {key}");
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    /// MIME 类型由下载时的扩展名决定，不需要签名
+    fn presign_put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        expires: Duration,
+    ) -> anyhow::Result<String> {
+        self.presign("PUT", key, expires)
+    }
+
+    fn presign_get(&self, key: &str, expires: Duration) -> anyhow::Result<String> {
+        self.presign("GET", key, expires)
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, _content_type: &str) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{key}", self.serve_path)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::storage::oss::local::{LocalConfig, LocalStore, SignatureError};
+    use crate::storage::oss::ObjectStore;
+
+    #[tokio::test]
+    async fn local_store() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("mallchat-oss-{}", std::process::id()));
+        let store = LocalStore::new(LocalConfig {
+            path: root.clone(),
+            ..Default::default()
+        });
+
+        let url = store.presign_put("chat/1.png", "image/png", Duration::from_secs(60))?;
+        let query = url
+            .strip_prefix("/capi/oss/local/chat/1.png?expires=")
+            .unwrap_or_default();
+        let Some((expires, signature)) = query.split_once("&signature=") else {
+            anyhow::bail!("unexpected url: {url}");
+        };
+        let expires: i64 = expires.parse()?;
+        store.verify("PUT", "chat/1.png", expires, signature)?;
+        assert_eq!(
+            store.verify("PUT", "chat/2.png", expires, signature),
+            Err(SignatureError::Invalid)
+        );
+        // 上传 URL 不能用于下载
+        assert_eq!(
+            store.verify("GET", "chat/1.png", expires, signature),
+            Err(SignatureError::Invalid)
+        );
+        let expired = store.sign("PUT", "chat/1.png", 1);
+        assert_eq!(
+            store.verify("PUT", "chat/1.png", 1, &expired),
+            Err(SignatureError::Expired)
+        );
+
+        store
+            .put("chat/1.png", b"png".to_vec(), "image/png")
+            .await?;
+        assert_eq!(tokio::fs::read(root.join("chat/1.png")).await?, b"png");
+        assert_eq!(store.get("chat/1.png").await?.as_deref(), Some(&b"png"[..]));
+        assert_eq!(store.get("chat/2.png").await?, None);
+        assert_eq!(store.public_url("chat/1.png"), "/oss/chat/1.png");
+        store.delete("chat/1.png").await?;
+        store.delete("chat/1.png").await?;
+        assert!(store.put("../escape", Vec::new(), "").await.is_err());
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,941 @@
+//! # 数据访问
+//!
+//! 处理器通过这里的 trait 读写数据，不直接拼装 sea-orm 查询。
+//! 所有实现了 [`ConnectionTrait`] 的类型（数据库连接、事务）都实现了这些 trait，
+//! 单元测试中可以替换为 mock 实现。
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sea_orm::sea_query::{Expr, OnConflict, Query};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, FromQueryResult, Insert, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+    Select, Set, UpdateMany,
+};
+
+use crate::handler::chat::{message_abstract, MarkStatus, MessageStatus};
+use crate::ip::IpDetail;
+use crate::service::group::{GroupService, GLOBAL_ROOM_ID};
+use crate::service::room::{RoomFriendStatus, RoomType};
+use crate::storage::model::{
+    group_member, message, message_mark, room, room_friend, room_read, user,
+};
+use crate::storage::soft_delete::SoftDelete;
+
+/// 以 Extension 注入的用户数据访问对象
+pub type DynUserRepo = Arc<dyn UserRepo>;
+/// 以 Extension 注入的消息数据访问对象
+pub type DynMessageRepo = Arc<dyn MessageRepo>;
+/// 以 Extension 注入的房间数据访问对象
+pub type DynRoomRepo = Arc<dyn RoomRepo>;
+
+/// 处理器使用的全部数据访问对象
+#[derive(Clone)]
+pub struct Repos {
+    /// 用户
+    pub users: DynUserRepo,
+    /// 消息
+    pub messages: DynMessageRepo,
+    /// 房间
+    pub rooms: DynRoomRepo,
+}
+
+impl Repos {
+    /// 全部使用数据库实现
+    pub fn new(db: &DatabaseConnection) -> Self {
+        Self {
+            users: Arc::new(db.clone()),
+            messages: Arc::new(db.clone()),
+            rooms: Arc::new(db.clone()),
+        }
+    }
+}
+
+/// 消息的有效标记数
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct MarkCount {
+    /// 消息 ID
+    pub msg_id: i64,
+    /// 标记类型
+    pub mark_type: i32,
+    /// 标记人数
+    pub count: i64,
+}
+
+/// 批量写入的消息标记
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkWrite {
+    /// 消息 ID
+    pub msg_id: i64,
+    /// 标记人 uid
+    pub uid: i64,
+    /// 标记类型
+    pub mark_type: i32,
+    /// 标记状态
+    pub status: i32,
+}
+
+/// 用户在房间中的阅读进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadCursor {
+    /// 用户 uid
+    pub uid: i64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 已读的最新消息 ID
+    pub msg_id: u64,
+}
+
+/// 一条 upsert 语句最多写入的行数
+const UPSERT_CHUNK: usize = 500;
+
+/// 用户数据访问
+#[async_trait]
+pub trait UserRepo: Send + Sync {
+    /// 按 uid 查询未删除的用户
+    async fn find_by_id(&self, uid: i64) -> Result<Option<user::Model>, DbErr>;
+    /// 批量查询用户，包括已删除的用户，用于展示历史消息的发送者等，不保证返回顺序
+    async fn find_by_ids(&self, uids: &[i64]) -> Result<Vec<user::Model>, DbErr>;
+    /// 按微信 openid 查询用户，包括已删除的用户，openid 唯一，已删除的用户不能重新注册
+    async fn find_by_open_id(&self, open_id: &str) -> Result<Option<user::Model>, DbErr>;
+    /// 按昵称查询用户，包括已删除的用户，昵称唯一
+    async fn find_by_name(&self, name: &str) -> Result<Option<user::Model>, DbErr>;
+    /// 分页查询未删除的用户，最近上下线的在前
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<user::Model>, DbErr>;
+    /// 使用微信 openid 注册用户
+    async fn create(&self, open_id: &str) -> Result<user::Model, DbErr>;
+    /// 修改昵称
+    async fn update_name(&self, uid: i64, name: &str) -> Result<(), DbErr>;
+    /// 修改头像
+    async fn update_avatar(&self, uid: i64, avatar: &str) -> Result<(), DbErr>;
+    /// 记录用户的 IP，IP 未变化时不更新，返回是否更新，见 [`IpInfo::refresh`](crate::ip::IpInfo::refresh)
+    async fn update_ip(&self, uid: i64, detail: &IpDetail) -> Result<bool, DbErr>;
+    /// 将用户最后活跃时间更新为当前时间
+    async fn refresh_active_time(&self, uids: &[i64]) -> Result<(), DbErr>;
+    /// 逻辑删除用户，返回是否有用户被删除
+    async fn soft_delete(&self, uid: i64) -> Result<bool, DbErr>;
+    /// 恢复已删除的用户，返回是否有用户被恢复
+    async fn restore(&self, uid: i64) -> Result<bool, DbErr>;
+}
+
+/// 消息数据访问
+#[async_trait]
+pub trait MessageRepo: Send + Sync {
+    /// 分页查询房间内的消息，最新的在前
+    async fn page(
+        &self,
+        room_id: i64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr>;
+    /// 查询房间内 ID 小于 `before` 的正常消息的 ID，最新的在前，只扫描 `idx_room_status_id` 索引
+    async fn page_ids(
+        &self,
+        room_id: i64,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<u64>, DbErr>;
+    /// 保存消息
+    async fn create(&self, message: message::ActiveModel) -> Result<message::Model, DbErr>;
+    /// 按消息 ID 查询消息
+    async fn find_by_id(&self, id: u64) -> Result<Option<message::Model>, DbErr>;
+    /// 按消息 ID 批量查询消息，不存在的忽略，不保证返回顺序
+    async fn find_by_ids(&self, ids: &[u64]) -> Result<Vec<message::Model>, DbErr>;
+    /// 统计消息的有效标记数，没有标记的消息不返回
+    async fn count_marks(&self, msg_ids: &[u64]) -> Result<Vec<MarkCount>, DbErr>;
+    /// 用户对消息的有效标记，返回消息 ID 和标记类型
+    async fn user_marks(&self, uid: i64, msg_ids: &[u64]) -> Result<Vec<(i64, i32)>, DbErr>;
+    /// 批量保存消息标记，已存在的覆盖状态
+    async fn save_marks(&self, marks: &[MarkWrite]) -> Result<(), DbErr>;
+    /// 批量保存阅读进度，只会前进不会后退
+    async fn save_read_cursors(&self, cursors: &[ReadCursor]) -> Result<(), DbErr>;
+    /// 统计房间内已读到 `msg_id` 的当前成员数，不包括 `exclude_uid`（消息的发送者）
+    async fn count_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+    ) -> Result<u64, DbErr>;
+    /// 房间内已读到 `msg_id` 的当前成员，uid 大的在前，`before` 为上一页最后一个 uid
+    async fn page_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+        before: Option<i64>,
+        limit: u64,
+    ) -> Result<Vec<i64>, DbErr>;
+    /// 更新消息内容
+    async fn update_content(&self, id: u64, content: &str) -> Result<(), DbErr>;
+    /// 更新消息的额外信息
+    async fn update_extra(&self, id: u64, extra: serde_json::Value) -> Result<(), DbErr>;
+    /// 逻辑删除消息，返回是否有消息被删除
+    async fn soft_delete(&self, id: u64) -> Result<bool, DbErr>;
+    /// 恢复已删除的消息，返回是否有消息被恢复
+    async fn restore(&self, id: u64) -> Result<bool, DbErr>;
+    /// 查询 `room_ids` 中 ID 大于 `after` 的正常消息，按 ID 顺序返回最多 `limit` 条
+    async fn list_after(
+        &self,
+        room_ids: &[i64],
+        after: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr>;
+    /// 在 `room_ids` 中搜索包含 `keyword` 的正常消息，按 ID 倒序返回 ID 小于 `before` 的最多 `limit` 条
+    async fn search(
+        &self,
+        room_ids: &[i64],
+        keyword: &str,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr>;
+}
+
+/// 房间数据访问
+#[async_trait]
+pub trait RoomRepo: Send + Sync {
+    /// 按房间 ID 查询未删除的房间
+    async fn find_by_id(&self, room_id: i64) -> Result<Option<room::Model>, DbErr>;
+    /// 批量查询未删除的房间，不保证返回顺序
+    async fn find_by_ids(&self, room_ids: &[i64]) -> Result<Vec<room::Model>, DbErr>;
+    /// 分页查询 `exclude` 以外未删除的公开房间（不包括单聊），最近活跃的在前
+    async fn page(
+        &self,
+        exclude: &[i64],
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<room::Model>, DbErr>;
+    /// 将房间的最后一条消息更新为 `message`，同时更新活跃时间和消息摘要，不会回退到更早的消息
+    async fn refresh_last_message(&self, message: &message::Model) -> Result<(), DbErr>;
+    /// 用户是否是群聊的成员：全员群聊所有用户都是成员，其他群聊需要有成员记录
+    async fn is_group_member(&self, room_id: i64, uid: i64) -> Result<bool, DbErr>;
+    /// 用户可以访问的房间：全员群聊、有成员记录且未解散的群聊，以及用户参与的、未禁用的单聊
+    async fn member_room_ids(&self, uid: i64) -> Result<Vec<i64>, DbErr>;
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send + Sync> UserRepo for C {
+    async fn find_by_id(&self, uid: i64) -> Result<Option<user::Model>, DbErr> {
+        user::Entity::find_alive()
+            .filter(user::Column::Id.eq(uid as u64))
+            .one(self)
+            .await
+    }
+
+    async fn find_by_ids(&self, uids: &[i64]) -> Result<Vec<user::Model>, DbErr> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+        user::Entity::find()
+            .filter(user::Column::Id.is_in(uids.iter().map(|uid| *uid as u64)))
+            .all(self)
+            .await
+    }
+
+    async fn find_by_open_id(&self, open_id: &str) -> Result<Option<user::Model>, DbErr> {
+        user::Entity::find()
+            .filter(user::Column::OpenId.eq(open_id))
+            .one(self)
+            .await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<user::Model>, DbErr> {
+        user::Entity::find()
+            .filter(user::Column::Name.eq(name))
+            .one(self)
+            .await
+    }
+
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<user::Model>, DbErr> {
+        user::Entity::find_alive()
+            .order_by_desc(user::Column::LastOptTime)
+            .order_by_desc(user::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(self)
+            .await
+    }
+
+    async fn create(&self, open_id: &str) -> Result<user::Model, DbErr> {
+        user::ActiveModel {
+            open_id: Set(open_id.to_string()),
+            ..Default::default()
+        }
+        .insert(self)
+        .await
+    }
+
+    async fn update_name(&self, uid: i64, name: &str) -> Result<(), DbErr> {
+        user::Entity::update_many()
+            .col_expr(user::Column::Name, Expr::value(name))
+            .filter(user::Column::Id.eq(uid as u64))
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_avatar(&self, uid: i64, avatar: &str) -> Result<(), DbErr> {
+        user::Entity::update_many()
+            .col_expr(user::Column::Avatar, Expr::value(avatar))
+            .filter(user::Column::Id.eq(uid as u64))
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_ip(&self, uid: i64, detail: &IpDetail) -> Result<bool, DbErr> {
+        let result = ip_update(uid, detail)?.exec(self).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn refresh_active_time(&self, uids: &[i64]) -> Result<(), DbErr> {
+        if uids.is_empty() {
+            return Ok(());
+        }
+        user::Entity::update_many()
+            .col_expr(
+                user::Column::LastOptTime,
+                Expr::cust("CURRENT_TIMESTAMP(3)"),
+            )
+            .filter(user::Column::Id.is_in(uids.iter().map(|uid| *uid as u64)))
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn soft_delete(&self, uid: i64) -> Result<bool, DbErr> {
+        let result = user::Entity::soft_delete_many()
+            .filter(user::Column::Id.eq(uid as u64))
+            .exec(self)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn restore(&self, uid: i64) -> Result<bool, DbErr> {
+        let result = user::Entity::restore_many()
+            .filter(user::Column::Id.eq(uid as u64))
+            .exec(self)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send + Sync> MessageRepo for C {
+    async fn page(
+        &self,
+        room_id: i64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        message::Entity::find()
+            .filter(message::Column::RoomId.eq(room_id))
+            .order_by_desc(message::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(self)
+            .await
+    }
+
+    async fn page_ids(
+        &self,
+        room_id: i64,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<u64>, DbErr> {
+        page_ids_select(room_id, before, limit)
+            .into_tuple()
+            .all(self)
+            .await
+    }
+
+    async fn create(&self, message: message::ActiveModel) -> Result<message::Model, DbErr> {
+        message.insert(self).await
+    }
+
+    async fn find_by_id(&self, id: u64) -> Result<Option<message::Model>, DbErr> {
+        message::Entity::find_by_id(id).one(self).await
+    }
+
+    async fn find_by_ids(&self, ids: &[u64]) -> Result<Vec<message::Model>, DbErr> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        message::Entity::find()
+            .filter(message::Column::Id.is_in(ids.iter().copied()))
+            .all(self)
+            .await
+    }
+
+    async fn count_marks(&self, msg_ids: &[u64]) -> Result<Vec<MarkCount>, DbErr> {
+        if msg_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        message_mark::Entity::find()
+            .select_only()
+            .column(message_mark::Column::MsgId)
+            .column_as(message_mark::Column::Type, "mark_type")
+            .column_as(message_mark::Column::Id.count(), "count")
+            .filter(message_mark::Column::MsgId.is_in(msg_ids.iter().map(|id| *id as i64)))
+            .filter(message_mark::Column::Status.eq(MarkStatus::Normal as i32))
+            .group_by(message_mark::Column::MsgId)
+            .group_by(message_mark::Column::Type)
+            .into_model()
+            .all(self)
+            .await
+    }
+
+    async fn user_marks(&self, uid: i64, msg_ids: &[u64]) -> Result<Vec<(i64, i32)>, DbErr> {
+        if msg_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        message_mark::Entity::find()
+            .select_only()
+            .column(message_mark::Column::MsgId)
+            .column(message_mark::Column::Type)
+            .filter(message_mark::Column::Uid.eq(uid))
+            .filter(message_mark::Column::MsgId.is_in(msg_ids.iter().map(|id| *id as i64)))
+            .filter(message_mark::Column::Status.eq(MarkStatus::Normal as i32))
+            .into_tuple()
+            .all(self)
+            .await
+    }
+
+    async fn save_marks(&self, marks: &[MarkWrite]) -> Result<(), DbErr> {
+        for chunk in marks.chunks(UPSERT_CHUNK) {
+            marks_upsert(chunk).exec_without_returning(self).await?;
+        }
+        Ok(())
+    }
+
+    async fn save_read_cursors(&self, cursors: &[ReadCursor]) -> Result<(), DbErr> {
+        for chunk in cursors.chunks(UPSERT_CHUNK) {
+            read_cursors_upsert(chunk)
+                .exec_without_returning(self)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn count_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+    ) -> Result<u64, DbErr> {
+        readers_select(room_id, msg_id, exclude_uid)
+            .count(self)
+            .await
+    }
+
+    async fn page_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+        before: Option<i64>,
+        limit: u64,
+    ) -> Result<Vec<i64>, DbErr> {
+        let mut select = readers_select(room_id, msg_id, exclude_uid)
+            .select_only()
+            .column(room_read::Column::Uid);
+        if let Some(before) = before {
+            select = select.filter(room_read::Column::Uid.lt(before));
+        }
+        select
+            .order_by_desc(room_read::Column::Uid)
+            .limit(limit)
+            .into_tuple()
+            .all(self)
+            .await
+    }
+
+    async fn update_content(&self, id: u64, content: &str) -> Result<(), DbErr> {
+        message::Entity::update_many()
+            .col_expr(message::Column::Content, Expr::value(content))
+            .filter(message::Column::Id.eq(id))
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_extra(&self, id: u64, extra: serde_json::Value) -> Result<(), DbErr> {
+        message::Entity::update_many()
+            .col_expr(message::Column::Extra, Expr::value(extra))
+            .filter(message::Column::Id.eq(id))
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn soft_delete(&self, id: u64) -> Result<bool, DbErr> {
+        let result = message::Entity::soft_delete_many()
+            .filter(message::Column::Id.eq(id))
+            .exec(self)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn restore(&self, id: u64) -> Result<bool, DbErr> {
+        let result = message::Entity::restore_many()
+            .filter(message::Column::Id.eq(id))
+            .exec(self)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn list_after(
+        &self,
+        room_ids: &[i64],
+        after: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        if room_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        message::Entity::find()
+            .filter(message::Column::Id.gt(after))
+            .filter(message::Column::RoomId.is_in(room_ids.iter().copied()))
+            .filter(message::Column::Status.eq(MessageStatus::Normal as i32))
+            .order_by_asc(message::Column::Id)
+            .limit(limit)
+            .all(self)
+            .await
+    }
+
+    async fn search(
+        &self,
+        room_ids: &[i64],
+        keyword: &str,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        if room_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        // 作为短语搜索，去掉双引号避免被解析为布尔模式的运算符
+        let phrase = format!("\"{}\"", keyword.replace('"', " "));
+        let mut select = message::Entity::find()
+            .filter(Expr::cust_with_values(
+                "MATCH(`content`) AGAINST (? IN BOOLEAN MODE)",
+                [phrase],
+            ))
+            .filter(message::Column::RoomId.is_in(room_ids.iter().copied()))
+            .filter(message::Column::Status.eq(MessageStatus::Normal as i32));
+        if let Some(before) = before {
+            select = select.filter(message::Column::Id.lt(before));
+        }
+        select
+            .order_by_desc(message::Column::Id)
+            .limit(limit)
+            .all(self)
+            .await
+    }
+}
+
+/// 按唯一索引 `uniq_msg_uid_type` 覆盖标记状态
+fn marks_upsert(marks: &[MarkWrite]) -> Insert<message_mark::ActiveModel> {
+    message_mark::Entity::insert_many(marks.iter().map(|mark| message_mark::ActiveModel {
+        msg_id: Set(mark.msg_id),
+        uid: Set(mark.uid),
+        r#type: Set(mark.mark_type),
+        status: Set(mark.status),
+        ..Default::default()
+    }))
+    .on_conflict(
+        OnConflict::columns([
+            message_mark::Column::MsgId,
+            message_mark::Column::Uid,
+            message_mark::Column::Type,
+        ])
+        .update_column(message_mark::Column::Status)
+        .to_owned(),
+    )
+}
+
+/// 只在 IP 变化时更新 `ip_info`，不需要先读出原来的值；首次记录时同时写入注册 IP
+fn ip_update(uid: i64, detail: &IpDetail) -> Result<UpdateMany<user::Entity>, DbErr> {
+    let json = serde_json::to_string(detail).map_err(|error| DbErr::Custom(error.to_string()))?;
+    let ip = detail.ip.as_str();
+    Ok(user::Entity::update_many()
+        .col_expr(
+            user::Column::IpInfo,
+            Expr::cust_with_values(
+                "JSON_SET(\
+                CASE WHEN IFNULL(JSON_UNQUOTE(JSON_EXTRACT(`ip_info`, '$.createIp')), 'null') = 'null' \
+                THEN JSON_OBJECT('createIp', ?, 'createIpDetail', CAST(? AS JSON)) \
+                ELSE `ip_info` END, \
+                '$.updateIp', ?, '$.updateIpDetail', CAST(? AS JSON))",
+                [ip, json.as_str(), ip, json.as_str()],
+            ),
+        )
+        .filter(user::Column::Id.eq(uid as u64))
+        .filter(Expr::cust_with_values(
+            "NOT (JSON_UNQUOTE(JSON_EXTRACT(`ip_info`, '$.updateIp')) <=> ?)",
+            [ip],
+        )))
+}
+
+/// 按唯一索引 `uniq_uid_room_id` 保留较大的消息 ID
+fn read_cursors_upsert(cursors: &[ReadCursor]) -> Insert<room_read::ActiveModel> {
+    room_read::Entity::insert_many(cursors.iter().map(|cursor| room_read::ActiveModel {
+        uid: Set(cursor.uid),
+        room_id: Set(cursor.room_id),
+        read_msg_id: Set(cursor.msg_id),
+        ..Default::default()
+    }))
+    .on_conflict(
+        OnConflict::columns([room_read::Column::Uid, room_read::Column::RoomId])
+            .value(
+                room_read::Column::ReadMsgId,
+                Expr::cust("GREATEST(`read_msg_id`, VALUES(`read_msg_id`))"),
+            )
+            .to_owned(),
+    )
+}
+
+/// 已读到 `msg_id` 的阅读进度，条件在 `idx_room_read_uid` 上，不需要回表
+///
+/// 全员群聊所有用户都是成员；其他房间只统计当前的群成员或单聊双方，退群的成员不计入
+fn readers_select(room_id: i64, msg_id: u64, exclude_uid: i64) -> Select<room_read::Entity> {
+    let select = room_read::Entity::find()
+        .filter(room_read::Column::RoomId.eq(room_id))
+        .filter(room_read::Column::ReadMsgId.gte(msg_id))
+        .filter(room_read::Column::Uid.ne(exclude_uid));
+    if room_id == GLOBAL_ROOM_ID {
+        return select;
+    }
+    let single = |column: room_friend::Column| {
+        room_read::Column::Uid.in_subquery(
+            Query::select()
+                .column(column)
+                .from(room_friend::Entity)
+                .and_where(room_friend::Column::RoomId.eq(room_id))
+                .to_owned(),
+        )
+    };
+    select.filter(
+        Condition::any()
+            .add(
+                room_read::Column::Uid.in_subquery(
+                    Query::select()
+                        .column(group_member::Column::Uid)
+                        .from(group_member::Entity)
+                        .and_where(group_member::Column::RoomId.eq(room_id))
+                        .to_owned(),
+                ),
+            )
+            .add(single(room_friend::Column::Uid1))
+            .add(single(room_friend::Column::Uid2)),
+    )
+}
+
+/// 只选择 `id` 列，条件和排序都在 `idx_room_status_id` 上，不需要回表
+fn page_ids_select(room_id: i64, before: Option<u64>, limit: u64) -> Select<message::Entity> {
+    let mut select = message::Entity::find()
+        .select_only()
+        .column(message::Column::Id)
+        .filter(message::Column::RoomId.eq(room_id))
+        .filter(message::Column::Status.eq(MessageStatus::Normal as i32));
+    if let Some(before) = before {
+        select = select.filter(message::Column::Id.lt(before));
+    }
+    select.order_by_desc(message::Column::Id).limit(limit)
+}
+
+#[async_trait]
+impl<C: ConnectionTrait + Send + Sync> RoomRepo for C {
+    async fn find_by_id(&self, room_id: i64) -> Result<Option<room::Model>, DbErr> {
+        room::Entity::find_alive()
+            .filter(room::Column::Id.eq(room_id as u64))
+            .one(self)
+            .await
+    }
+
+    async fn find_by_ids(&self, room_ids: &[i64]) -> Result<Vec<room::Model>, DbErr> {
+        if room_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        room::Entity::find_alive()
+            .filter(room::Column::Id.is_in(room_ids.iter().map(|room_id| *room_id as u64)))
+            .all(self)
+            .await
+    }
+
+    async fn page(
+        &self,
+        exclude: &[i64],
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<room::Model>, DbErr> {
+        let mut query =
+            room::Entity::find_alive().filter(room::Column::Type.ne(RoomType::Single as i32));
+        if !exclude.is_empty() {
+            query = query
+                .filter(room::Column::Id.is_not_in(exclude.iter().map(|room_id| *room_id as u64)));
+        }
+        query
+            .order_by_desc(room::Column::ActiveTime)
+            .order_by_desc(room::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(self)
+            .await
+    }
+
+    async fn refresh_last_message(&self, message: &message::Model) -> Result<(), DbErr> {
+        let id = message.id as i64;
+        room::Entity::update_many()
+            .col_expr(room::Column::ActiveTime, Expr::value(message.create_time))
+            .col_expr(room::Column::LastMsgId, Expr::value(id))
+            .col_expr(
+                room::Column::LastMsgAbstract,
+                Expr::value(message_abstract(message)),
+            )
+            .filter(room::Column::Id.eq(message.room_id as u64))
+            // 并发发送消息时不回退到更早的消息，同一条消息可以重复更新（如修改内容）
+            .filter(
+                Condition::any()
+                    .add(room::Column::LastMsgId.is_null())
+                    .add(room::Column::LastMsgId.lte(id)),
+            )
+            .exec(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_group_member(&self, room_id: i64, uid: i64) -> Result<bool, DbErr> {
+        if room_id == GLOBAL_ROOM_ID {
+            return Ok(true);
+        }
+        Ok(GroupService::new(self)
+            .member(room_id, uid)
+            .await?
+            .is_some())
+    }
+
+    async fn member_room_ids(&self, uid: i64) -> Result<Vec<i64>, DbErr> {
+        let groups: Vec<u64> = room::Entity::find_alive()
+            .select_only()
+            .column(room::Column::Id)
+            .filter(room::Column::Type.ne(RoomType::Single as i32))
+            .filter(
+                Condition::any()
+                    .add(room::Column::Id.eq(GLOBAL_ROOM_ID as u64))
+                    .add(
+                        room::Column::Id.in_subquery(
+                            Query::select()
+                                .column(group_member::Column::RoomId)
+                                .from(group_member::Entity)
+                                .and_where(group_member::Column::Uid.eq(uid))
+                                .to_owned(),
+                        ),
+                    ),
+            )
+            .into_tuple()
+            .all(self)
+            .await?;
+        let singles: Vec<i64> = room_friend::Entity::find()
+            .select_only()
+            .column(room_friend::Column::RoomId)
+            .filter(
+                Condition::any()
+                    .add(room_friend::Column::Uid1.eq(uid))
+                    .add(room_friend::Column::Uid2.eq(uid)),
+            )
+            .filter(room_friend::Column::Status.eq(RoomFriendStatus::Normal as i32))
+            .into_tuple()
+            .all(self)
+            .await?;
+        Ok(groups
+            .into_iter()
+            .map(|id| id as i64)
+            .chain(singles)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{
+        ConnectionTrait, Database, DbBackend, QuerySelect, QueryTrait, Statement, TransactionTrait,
+    };
+    use sea_orm_migration::MigratorTrait;
+
+    use crate::ip::IpDetail;
+    use crate::service::group::{GroupService, GLOBAL_ROOM_ID};
+    use crate::storage::migration::Migrator;
+    use crate::storage::model::room_read;
+    use crate::storage::repo::{
+        ip_update, marks_upsert, page_ids_select, read_cursors_upsert, readers_select, MarkWrite,
+        ReadCursor, RoomRepo,
+    };
+
+    #[test]
+    fn page_ids_sql() {
+        let sql = page_ids_select(1, Some(100), 21)
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "SELECT `message`.`id` FROM `message` \
+            WHERE `message`.`room_id` = 1 AND `message`.`status` = 0 AND `message`.`id` < 100 \
+            ORDER BY `message`.`id` DESC LIMIT 21"
+        );
+    }
+
+    #[test]
+    fn readers_sql() {
+        let sql = readers_select(1, 100, 2)
+            .select_only()
+            .column(room_read::Column::Uid)
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "SELECT `room_read`.`uid` FROM `room_read` \
+            WHERE `room_read`.`room_id` = 1 AND `room_read`.`read_msg_id` >= 100 \
+            AND `room_read`.`uid` <> 2"
+        );
+
+        // 其他房间只统计当前成员
+        let sql = readers_select(3, 100, 2)
+            .select_only()
+            .column(room_read::Column::Uid)
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "SELECT `room_read`.`uid` FROM `room_read` \
+            WHERE `room_read`.`room_id` = 3 AND `room_read`.`read_msg_id` >= 100 \
+            AND `room_read`.`uid` <> 2 \
+            AND (`room_read`.`uid` IN (SELECT `uid` FROM `group_member` WHERE `group_member`.`room_id` = 3) \
+            OR `room_read`.`uid` IN (SELECT `uid1` FROM `room_friend` WHERE `room_friend`.`room_id` = 3) \
+            OR `room_read`.`uid` IN (SELECT `uid2` FROM `room_friend` WHERE `room_friend`.`room_id` = 3))"
+        );
+    }
+
+    #[test]
+    fn upserts() {
+        let mark = MarkWrite {
+            msg_id: 1,
+            uid: 2,
+            mark_type: 1,
+            status: 0,
+        };
+        let sql = marks_upsert(&[mark, MarkWrite { uid: 3, ..mark }])
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "INSERT INTO `message_mark` (`msg_id`, `uid`, `type`, `status`) \
+            VALUES (1, 2, 1, 0), (1, 3, 1, 0) \
+            ON DUPLICATE KEY UPDATE `status` = VALUES(`status`)"
+        );
+
+        let cursor = ReadCursor {
+            uid: 2,
+            room_id: 1,
+            msg_id: 100,
+        };
+        let sql = read_cursors_upsert(&[cursor])
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "INSERT INTO `room_read` (`uid`, `room_id`, `read_msg_id`) VALUES (2, 1, 100) \
+            ON DUPLICATE KEY UPDATE `read_msg_id` = GREATEST(`read_msg_id`, VALUES(`read_msg_id`))"
+        );
+    }
+
+    #[test]
+    fn ip_update_sql() -> anyhow::Result<()> {
+        let detail = IpDetail {
+            ip: "1.1.1.1".to_string(),
+            ..Default::default()
+        };
+        let sql = ip_update(1, &detail)?.build(DbBackend::MySql).to_string();
+        // 内联到 SQL 中的字符串会转义引号，执行时使用绑定参数
+        let json =
+            r#"{\"ip\":\"1.1.1.1\",\"country\":null,\"region\":null,\"city\":null,\"isp\":null}"#;
+        assert_eq!(
+            sql,
+            format!(
+                "UPDATE `user` SET `ip_info` = JSON_SET(\
+                CASE WHEN IFNULL(JSON_UNQUOTE(JSON_EXTRACT(`ip_info`, '$.createIp')), 'null') = 'null' \
+                THEN JSON_OBJECT('createIp', '1.1.1.1', 'createIpDetail', CAST('{json}' AS JSON)) \
+                ELSE `ip_info` END, \
+                '$.updateIp', '1.1.1.1', '$.updateIpDetail', CAST('{json}' AS JSON)) \
+                WHERE `user`.`id` = 1 \
+                AND NOT (JSON_UNQUOTE(JSON_EXTRACT(`ip_info`, '$.updateIp')) <=> '1.1.1.1')"
+            )
+        );
+        Ok(())
+    }
+
+    /// 消息列表第一步只扫描索引，表增长后每页的耗时仍然有上界
+    #[tokio::test]
+    #[ignore = "需要 MySQL，设置 MALLCHAT_TEST_DATABASE_URL 后运行"]
+    async fn page_ids_explain() -> anyhow::Result<()> {
+        let url = std::env::var("MALLCHAT_TEST_DATABASE_URL")?;
+        let db = Database::connect(url).await?;
+        Migrator::up(&db, None).await?;
+
+        let sql = page_ids_select(1, Some(100), 21)
+            .build(DbBackend::MySql)
+            .to_string();
+        let plan = db
+            .query_one(Statement::from_string(
+                DbBackend::MySql,
+                format!("EXPLAIN {sql}"),
+            ))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("empty plan"))?;
+        let key: Option<String> = plan.try_get("", "key")?;
+        let extra: Option<String> = plan.try_get("", "Extra")?;
+        assert_eq!(key.as_deref(), Some("idx_room_status_id"));
+        assert!(
+            extra.is_some_and(|extra| extra.contains("Using index")),
+            "{plan:?}"
+        );
+        Ok(())
+    }
+
+    /// 通过邀请加入后才能访问创建的群聊，退出后不能再访问
+    #[tokio::test]
+    #[ignore = "需要 MySQL，设置 MALLCHAT_TEST_DATABASE_URL 后运行"]
+    async fn group_membership() -> anyhow::Result<()> {
+        let url = std::env::var("MALLCHAT_TEST_DATABASE_URL")?;
+        let db = Database::connect(url).await?;
+        Migrator::up(&db, None).await?;
+        let (owner, uid) = (900_000_011, 900_000_012);
+
+        let txn = db.begin().await?;
+        let room_id = GroupService::new(&txn)
+            .create(owner, "抹茶同好会")
+            .await?
+            .id as i64;
+        assert!(RoomRepo::is_group_member(&txn, room_id, owner).await?);
+        assert!(!RoomRepo::is_group_member(&txn, room_id, uid).await?);
+        assert!(RoomRepo::is_group_member(&txn, GLOBAL_ROOM_ID, uid).await?);
+        let room_ids = RoomRepo::member_room_ids(&txn, uid).await?;
+        assert!(room_ids.contains(&GLOBAL_ROOM_ID));
+        assert!(!room_ids.contains(&room_id));
+
+        GroupService::new(&txn).join(room_id, uid).await?;
+        assert!(RoomRepo::is_group_member(&txn, room_id, uid).await?);
+        assert!(RoomRepo::member_room_ids(&txn, uid)
+            .await?
+            .contains(&room_id));
+        let mut receivers = GroupService::new(&txn).receivers(room_id).await?;
+        receivers.iter_mut().for_each(|uids| uids.sort_unstable());
+        assert_eq!(receivers, Some(vec![owner, uid]));
+
+        GroupService::new(&txn).leave(room_id, uid).await?;
+        assert!(!RoomRepo::member_room_ids(&txn, uid)
+            .await?
+            .contains(&room_id));
+        txn.rollback().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! # 逻辑删除
+//!
+//! 用户、消息、房间和好友关系删除时只写入墓碑标记：`deleted_at` 记录删除时间，
+//! 有状态列的实体（消息、好友关系）同时把状态改为删除。记录本身保留，引用它们的历史数据仍然完整，可以恢复。
+//!
+//! 查询默认使用 [`SoftDelete::find_alive`] 过滤掉已删除的记录；
+//! 展示历史数据时（如消息的发送者）仍然使用 `find` 查询，已删除的记录也能找到。
+
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Select, UpdateMany};
+use time::PrimitiveDateTime;
+
+use crate::handler::chat::MessageStatus;
+use crate::handler::friend::DeleteStatus;
+use crate::storage::model::{message, room, user, user_friend};
+
+/// 支持逻辑删除的实体
+pub trait SoftDelete: EntityTrait {
+    /// 删除时间列
+    fn deleted_at() -> Self::Column;
+
+    /// 未删除的条件，默认为删除时间为空，有状态列的实体使用状态列以便命中已有的索引
+    fn alive() -> SimpleExpr {
+        Self::deleted_at().is_null()
+    }
+
+    /// 删除时需要同时修改的列
+    fn tombstone(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update
+    }
+
+    /// 恢复时需要同时修改的列
+    fn revive(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update
+    }
+
+    /// 查询未删除的记录
+    fn find_alive() -> Select<Self> {
+        Self::find().filter(Self::alive())
+    }
+
+    /// 查询已删除的记录
+    fn find_deleted() -> Select<Self> {
+        Self::find().filter(Self::alive().not())
+    }
+
+    /// 逻辑删除，需要再加上过滤条件，已删除的记录不会重复删除
+    fn soft_delete_many() -> UpdateMany<Self> {
+        Self::tombstone(Self::update_many())
+            .col_expr(Self::deleted_at(), Expr::cust("CURRENT_TIMESTAMP(3)"))
+            .filter(Self::alive())
+    }
+
+    /// 恢复已删除的记录，需要再加上过滤条件
+    fn restore_many() -> UpdateMany<Self> {
+        Self::revive(Self::update_many())
+            .col_expr(
+                Self::deleted_at(),
+                Expr::value(Option::<PrimitiveDateTime>::None),
+            )
+            .filter(Self::alive().not())
+    }
+}
+
+impl SoftDelete for user::Entity {
+    fn deleted_at() -> Self::Column {
+        user::Column::DeletedAt
+    }
+}
+
+impl SoftDelete for room::Entity {
+    fn deleted_at() -> Self::Column {
+        room::Column::DeletedAt
+    }
+}
+
+impl SoftDelete for message::Entity {
+    fn deleted_at() -> Self::Column {
+        message::Column::DeletedAt
+    }
+
+    fn alive() -> SimpleExpr {
+        message::Column::Status.eq(MessageStatus::Normal as i32)
+    }
+
+    fn tombstone(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update.col_expr(
+            message::Column::Status,
+            Expr::value(MessageStatus::Deleted as i32),
+        )
+    }
+
+    fn revive(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update.col_expr(
+            message::Column::Status,
+            Expr::value(MessageStatus::Normal as i32),
+        )
+    }
+}
+
+impl SoftDelete for user_friend::Entity {
+    fn deleted_at() -> Self::Column {
+        user_friend::Column::DeletedAt
+    }
+
+    fn alive() -> SimpleExpr {
+        user_friend::Column::DeleteStatus.eq(DeleteStatus::Normal as i32)
+    }
+
+    fn tombstone(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update.col_expr(
+            user_friend::Column::DeleteStatus,
+            Expr::value(DeleteStatus::Deleted as i32),
+        )
+    }
+
+    fn revive(update: UpdateMany<Self>) -> UpdateMany<Self> {
+        update.col_expr(
+            user_friend::Column::DeleteStatus,
+            Expr::value(DeleteStatus::Normal as i32),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ColumnTrait, DbBackend, QueryFilter, QueryTrait};
+
+    use crate::storage::model::{message, user};
+    use crate::storage::soft_delete::SoftDelete;
+
+    #[test]
+    fn queries() {
+        let sql = user::Entity::find_alive()
+            .filter(user::Column::Id.eq(1u64))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert!(
+            sql.ends_with("WHERE `user`.`deleted_at` IS NULL AND `user`.`id` = 1"),
+            "{sql}"
+        );
+
+        let sql = user::Entity::soft_delete_many()
+            .filter(user::Column::Id.eq(1u64))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "UPDATE `user` SET `deleted_at` = CURRENT_TIMESTAMP(3) \
+            WHERE `user`.`deleted_at` IS NULL AND `user`.`id` = 1"
+        );
+
+        let sql = user::Entity::restore_many()
+            .filter(user::Column::Id.eq(1u64))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "UPDATE `user` SET `deleted_at` = NULL \
+            WHERE NOT `user`.`deleted_at` IS NULL AND `user`.`id` = 1"
+        );
+    }
+
+    #[test]
+    fn status_column() {
+        let sql = message::Entity::soft_delete_many()
+            .filter(message::Column::Id.eq(1u64))
+            .build(DbBackend::MySql)
+            .to_string();
+        assert_eq!(
+            sql,
+            "UPDATE `message` SET `status` = 1, `deleted_at` = CURRENT_TIMESTAMP(3) \
+            WHERE `message`.`status` = 0 AND `message`.`id` = 1"
+        );
+
+        let sql = message::Entity::find_deleted()
+            .build(DbBackend::MySql)
+            .to_string();
+        assert!(sql.ends_with("WHERE NOT `message`.`status` = 0"), "{sql}");
+    }
+}
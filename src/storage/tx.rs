@@ -0,0 +1,117 @@
+//! # 事务
+//!
+//! 多表写入需要保证原子性时使用 [`with_txn`]：
+//!
+//! ```ignore
+//! let user = with_txn(&db, |txn| {
+//!     Box::pin(async move {
+//!         let user = UserRepo::create(txn, open_id).await?;
+//!         // ...
+//!         Ok::<_, DbErr>(user)
+//!     })
+//! })
+//! .await?;
+//! ```
+
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+
+use sea_orm::{DatabaseTransaction, DbErr, TransactionTrait};
+use tracing::Instrument;
+
+/// 在事务中执行 `callback`，返回 `Ok` 时提交，返回 `Err` 时回滚
+///
+/// 可以在事务中嵌套调用，内层使用 SAVEPOINT 实现
+pub async fn with_txn<C, F, T, E>(db: &C, callback: F) -> Result<T, E>
+where
+    C: TransactionTrait,
+    F: for<'c> FnOnce(
+            &'c DatabaseTransaction,
+        ) -> Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'c>>
+        + Send,
+    T: Send,
+    E: From<DbErr> + Display + Send,
+{
+    async move {
+        let txn = db.begin().await?;
+        match callback(&txn).await {
+            Ok(value) => {
+                txn.commit().await?;
+                Ok(value)
+            }
+            Err(error) => {
+                tracing::warn!(%error, "Transaction rolled back.");
+                if let Err(rollback_error) = txn.rollback().await {
+                    tracing::error!(%rollback_error, "Failed to rollback transaction.");
+                }
+                Err(error)
+            }
+        }
+    }
+    .instrument(tracing::info_span!("transaction"))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, Database, DbBackend, DbErr, Statement};
+
+    use crate::storage::tx::with_txn;
+
+    fn statement(sql: &str) -> Statement {
+        Statement::from_string(DbBackend::MySql, sql.to_string())
+    }
+
+    /// 返回 `Err` 时回滚；内层失败时只回滚到 SAVEPOINT，外层仍然提交
+    #[tokio::test]
+    #[ignore = "需要 MySQL，设置 MALLCHAT_TEST_DATABASE_URL 后运行"]
+    async fn rollback() -> anyhow::Result<()> {
+        let url = std::env::var("MALLCHAT_TEST_DATABASE_URL")?;
+        let db = Database::connect(url).await?;
+        db.execute(statement("DROP TABLE IF EXISTS `txn_test`"))
+            .await?;
+        db.execute(statement(
+            "CREATE TABLE `txn_test` (`id` BIGINT PRIMARY KEY) ENGINE = InnoDB",
+        ))
+        .await?;
+
+        let result = with_txn(&db, |txn| {
+            Box::pin(async move {
+                txn.execute(statement("INSERT INTO `txn_test` VALUES (1)"))
+                    .await?;
+                Err::<(), _>(DbErr::Custom("failed".to_string()))
+            })
+        })
+        .await;
+        assert!(result.is_err());
+
+        with_txn(&db, |txn| {
+            Box::pin(async move {
+                txn.execute(statement("INSERT INTO `txn_test` VALUES (2)"))
+                    .await?;
+                let inner = with_txn(txn, |txn| {
+                    Box::pin(async move {
+                        txn.execute(statement("INSERT INTO `txn_test` VALUES (3)"))
+                            .await?;
+                        Err::<(), _>(DbErr::Custom("failed".to_string()))
+                    })
+                })
+                .await;
+                assert!(inner.is_err());
+                Ok::<_, DbErr>(())
+            })
+        })
+        .await?;
+
+        let ids = db
+            .query_all(statement("SELECT `id` FROM `txn_test` ORDER BY `id`"))
+            .await?
+            .iter()
+            .map(|row| row.try_get::<i64>("", "id"))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ids, vec![2]);
+        db.execute(statement("DROP TABLE `txn_test`")).await?;
+        Ok(())
+    }
+}
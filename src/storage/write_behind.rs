@@ -0,0 +1,312 @@
+//! # 延迟批量写入
+//!
+//! 点赞、点踩和阅读进度这类写入频繁、单次数据很小，每次都写 MySQL 代价太高。[`WriteBehind`]
+//! 先在内存中按主键合并，定时（或积压超过 `max_pending` 时）通过
+//! [`MessageRepo::save_marks`] 和 [`MessageRepo::save_read_cursors`] 批量 upsert。
+//! 用户最后操作时间 `user.last_opt_time` 由 [`ActiveTracker`](crate::active::ActiveTracker)
+//! 以同样的方式合并写入。
+//!
+//! 关于崩溃安全：
+//!
+//! - 进程异常退出时最多丢失一个刷新周期内的写入，正常停止时会在关闭数据库连接前再刷新一次；
+//! - 写入是幂等的：标记以最后一次状态为准，阅读进度只增不减，重复执行同一批写入没有副作用；
+//! - 刷新失败时把这一批放回内存，不覆盖期间产生的新写入，下个周期重试。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use sea_orm::DbErr;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::storage::repo::{DynMessageRepo, MarkWrite, MessageRepo, ReadCursor};
+
+/// 延迟批量写入配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteBehindConfig {
+    /// 批量写入的间隔（秒）
+    #[serde(default = "default::flush_interval_secs")]
+    pub flush_interval_secs: u64,
+    /// 积压的写入超过该数量时立即刷新
+    #[serde(default = "default::max_pending")]
+    pub max_pending: usize,
+}
+
+mod default {
+    pub fn flush_interval_secs() -> u64 {
+        5
+    }
+
+    pub fn max_pending() -> usize {
+        10000
+    }
+}
+
+impl Default for WriteBehindConfig {
+    fn default() -> Self {
+        Self {
+            flush_interval_secs: default::flush_interval_secs(),
+            max_pending: default::max_pending(),
+        }
+    }
+}
+
+/// (msg_id, uid, mark_type) -> status
+type Marks = HashMap<(i64, i64, i32), i32>;
+
+/// (uid, room_id) -> msg_id
+type Reads = HashMap<(i64, i64), u64>;
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: WriteBehindConfig,
+    marks: Mutex<Marks>,
+    reads: Mutex<Reads>,
+    full: Notify,
+    stop: Notify,
+}
+
+/// 延迟批量写入
+#[derive(Debug, Clone, Default)]
+pub struct WriteBehind {
+    inner: Arc<Inner>,
+}
+
+impl WriteBehind {
+    /// 创建
+    pub fn new(config: WriteBehindConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// 记录消息标记，同一用户对同一消息的同类标记只保留最后的状态
+    pub fn mark(&self, write: MarkWrite) {
+        let len = {
+            let mut marks = self.inner.marks.lock();
+            marks.insert((write.msg_id, write.uid, write.mark_type), write.status);
+            marks.len()
+        };
+        self.notify_if_full(len);
+    }
+
+    /// 记录阅读进度，只保留最大的消息 ID
+    pub fn read(&self, cursor: ReadCursor) {
+        let len = {
+            let mut reads = self.inner.reads.lock();
+            let read = reads.entry((cursor.uid, cursor.room_id)).or_default();
+            *read = (*read).max(cursor.msg_id);
+            reads.len()
+        };
+        self.notify_if_full(len);
+    }
+
+    /// 等待写入的数量
+    pub fn pending(&self) -> usize {
+        self.inner.marks.lock().len() + self.inner.reads.lock().len()
+    }
+
+    fn notify_if_full(&self, len: usize) {
+        if len >= self.inner.config.max_pending {
+            self.inner.full.notify_one();
+        }
+    }
+
+    /// 写入积压的标记和阅读进度，返回写入的数量，失败时保留未写入的部分
+    pub async fn flush(&self, messages: &dyn MessageRepo) -> Result<usize, DbErr> {
+        let marks = std::mem::take(&mut *self.inner.marks.lock());
+        let reads = std::mem::take(&mut *self.inner.reads.lock());
+        let mut count = 0;
+
+        if !marks.is_empty() {
+            let mut writes: Vec<_> = marks
+                .iter()
+                .map(|(&(msg_id, uid, mark_type), &status)| MarkWrite {
+                    msg_id,
+                    uid,
+                    mark_type,
+                    status,
+                })
+                .collect();
+            // 固定顺序，减少并发 upsert 之间的锁冲突
+            writes.sort_unstable_by_key(|write| (write.msg_id, write.uid, write.mark_type));
+            if let Err(error) = messages.save_marks(&writes).await {
+                self.restore(marks, reads);
+                return Err(error);
+            }
+            count += writes.len();
+        }
+
+        if !reads.is_empty() {
+            let mut cursors: Vec<_> = reads
+                .iter()
+                .map(|(&(uid, room_id), &msg_id)| ReadCursor {
+                    uid,
+                    room_id,
+                    msg_id,
+                })
+                .collect();
+            cursors.sort_unstable_by_key(|cursor| (cursor.uid, cursor.room_id));
+            if let Err(error) = messages.save_read_cursors(&cursors).await {
+                self.restore(Marks::new(), reads);
+                return Err(error);
+            }
+            count += cursors.len();
+        }
+        Ok(count)
+    }
+
+    /// 放回写入失败的部分，期间产生的新写入优先
+    fn restore(&self, marks: Marks, reads: Reads) {
+        let mut pending = self.inner.marks.lock();
+        for (key, status) in marks {
+            pending.entry(key).or_insert(status);
+        }
+        drop(pending);
+        let mut pending = self.inner.reads.lock();
+        for (key, msg_id) in reads {
+            let read = pending.entry(key).or_default();
+            *read = (*read).max(msg_id);
+        }
+    }
+
+    /// 通知定时写入的任务退出，正在写入时等这一批写完再退出
+    pub fn stop(&self) {
+        self.inner.stop.notify_one();
+    }
+
+    /// 启动定时写入的任务
+    ///
+    /// 停止时先调用 [`WriteBehind::stop`] 并等待返回的任务结束，再调用一次 [`WriteBehind::flush`]。
+    /// 不能直接取消任务：[`WriteBehind::flush`] 开始时已经取出积压的写入，中途取消会丢失这一批
+    pub fn spawn(self, messages: DynMessageRepo) -> JoinHandle<()> {
+        let interval = Duration::from_secs(self.inner.config.flush_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = self.inner.full.notified() => {}
+                    _ = self.inner.stop.notified() => break,
+                }
+                let pending = self.pending();
+                if let Err(error) = self.flush(messages.as_ref()).await {
+                    tracing::warn!(%pending, %error, "Failed to flush write-behind buffer.");
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::storage::repo::{MarkWrite, MessageRepo, ReadCursor};
+    use crate::storage::write_behind::{WriteBehind, WriteBehindConfig};
+    use crate::testing::MemoryRepo;
+
+    #[tokio::test]
+    async fn flush() -> anyhow::Result<()> {
+        let repo = MemoryRepo::default();
+        let write_behind = WriteBehind::new(WriteBehindConfig::default());
+        assert_eq!(write_behind.flush(&repo).await?, 0);
+
+        let like = MarkWrite {
+            msg_id: 1,
+            uid: 2,
+            mark_type: 1,
+            status: 0,
+        };
+        write_behind.mark(like);
+        write_behind.mark(MarkWrite { status: 1, ..like });
+        write_behind.mark(MarkWrite { uid: 3, ..like });
+        let cursor = ReadCursor {
+            uid: 2,
+            room_id: 1,
+            msg_id: 10,
+        };
+        write_behind.read(cursor);
+        write_behind.read(ReadCursor {
+            msg_id: 5,
+            ..cursor
+        });
+        assert_eq!(write_behind.pending(), 3);
+
+        assert_eq!(write_behind.flush(&repo).await?, 3);
+        assert_eq!(write_behind.pending(), 0);
+        assert_eq!(repo.read_cursor(2, 1), Some(10));
+        // uid 2 已取消，只剩 uid 3 的点赞
+        assert!(repo.user_marks(2, &[1]).await?.is_empty());
+        assert_eq!(repo.user_marks(3, &[1]).await?, vec![(1, 1)]);
+
+        // 阅读进度只增不减
+        write_behind.read(ReadCursor {
+            msg_id: 8,
+            ..cursor
+        });
+        write_behind.flush(&repo).await?;
+        assert_eq!(repo.read_cursor(2, 1), Some(10));
+        Ok(())
+    }
+
+    /// 停止时等待任务退出再刷新，积压的写入不会丢失
+    #[tokio::test]
+    async fn stop() -> anyhow::Result<()> {
+        let repo = Arc::new(MemoryRepo::default());
+        let write_behind = WriteBehind::new(WriteBehindConfig {
+            flush_interval_secs: 60,
+            max_pending: 1,
+        });
+        let task = write_behind.clone().spawn(repo.clone());
+        for uid in 1..=100 {
+            write_behind.mark(MarkWrite {
+                msg_id: 1,
+                uid,
+                mark_type: 1,
+                status: 0,
+            });
+        }
+        write_behind.stop();
+        tokio::time::timeout(Duration::from_secs(5), task).await??;
+        write_behind.flush(repo.as_ref()).await?;
+        assert_eq!(write_behind.pending(), 0);
+        for uid in 1..=100 {
+            assert_eq!(repo.user_marks(uid, &[1]).await?, vec![(1, 1)]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn restore() {
+        let write_behind = WriteBehind::default();
+        let like = MarkWrite {
+            msg_id: 1,
+            uid: 2,
+            mark_type: 1,
+            status: 0,
+        };
+        let cursor = ReadCursor {
+            uid: 2,
+            room_id: 1,
+            msg_id: 10,
+        };
+        write_behind.mark(MarkWrite { status: 1, ..like });
+        write_behind.read(cursor);
+        write_behind.restore(
+            [((1, 2, 1), 0), ((1, 3, 1), 0)].into(),
+            [((2, 1), 20), ((2, 2), 1)].into(),
+        );
+        assert_eq!(write_behind.inner.marks.lock()[&(1, 2, 1)], 1);
+        assert_eq!(write_behind.inner.reads.lock()[&(2, 1)], 20);
+        assert_eq!(write_behind.pending(), 4);
+    }
+}
@@ -0,0 +1,1500 @@
+//! # 测试工具
+//!
+//! 启用 `test-util` 特性后可用，不依赖 MySQL、Redis、微信公众平台即可构造完整的路由：
+//!
+//! ```ignore
+//! let app = TestApp::new()?;
+//! let token = app.token(1)?;
+//! let response = app.router()?.oneshot(request).await?;
+//! ```
+//!
+//! 数据访问使用 [`MemoryRepo`]。实体主键为 `u64`，sea-orm 0.11 的 SQLite 驱动无法解码，
+//! 开启 mock 特性又会让 `DatabaseConnection` 无法 `Clone`，因此不提供内存数据库：
+//! 直接使用 `Extension<DatabaseConnection>` 的接口在测试中不可用。
+//! Redis 客户端只是创建，并不会连接，依赖缓存的接口在测试中会返回错误。
+
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::Router;
+use parking_lot::Mutex;
+use sea_orm::{DatabaseConnection, DbErr, Set, TryIntoModel};
+
+use crate::cache::Cache;
+use crate::handler::auth::{Claims, JwtKeys};
+use crate::handler::chat::{message_abstract, MarkStatus, MessageStatus};
+use crate::handler::ws::SessionManager;
+use crate::handler::RouterBuilder;
+use crate::ip::{IpDetail, IpInfo};
+use crate::log::LogFilterHandle;
+use crate::service::black::UserStatus;
+use crate::service::group::GLOBAL_ROOM_ID;
+use crate::service::room::RoomType;
+use crate::storage::model::{message, message_mark, room, user};
+use crate::storage::repo::{
+    MarkCount, MarkWrite, MessageRepo, ReadCursor, Repos, RoomRepo, UserRepo,
+};
+use crate::weixin::{
+    QrCodeTicket, WxApi, WxConfig, WxMessage, WxNewsArticle, WxTemplateMessage,
+    WxWebpageAccessToken,
+};
+
+/// 测试使用的 JWT 密钥
+const JWT_SECRET: &str = "omOFP+Ejj/r+u4XeHr+KImZNtP0AlNqgvjLe3C5qics=";
+
+/// 内存数据使用的固定时间
+const NOW: time::PrimitiveDateTime = time::macros::datetime!(2023-06-01 00:00:00);
+
+/// # 内存数据访问
+///
+/// 同时实现 [`UserRepo`]、[`MessageRepo`]、[`RoomRepo`]，数据保存在内存中
+#[derive(Debug, Default)]
+pub struct MemoryRepo {
+    users: Mutex<Vec<user::Model>>,
+    messages: Mutex<Vec<message::Model>>,
+    rooms: Mutex<Vec<room::Model>>,
+    group_members: Mutex<Vec<(i64, i64)>>,
+    marks: Mutex<Vec<message_mark::Model>>,
+    read_cursors: Mutex<BTreeMap<(i64, i64), u64>>,
+}
+
+impl MemoryRepo {
+    /// 添加一个用户，返回 uid
+    pub fn add_user(&self, open_id: &str, name: Option<&str>) -> i64 {
+        let mut users = self.users.lock();
+        let id = users.len() as u64 + 1;
+        users.push(user::Model {
+            id,
+            name: name.map(str::to_string),
+            avatar: None,
+            sex: None,
+            open_id: open_id.to_string(),
+            last_opt_time: NOW,
+            ip_info: None,
+            item_id: None,
+            status: None,
+            create_time: NOW,
+            update_time: NOW,
+            deleted_at: None,
+        });
+        id as i64
+    }
+
+    /// 拉黑用户，只修改用户状态
+    pub fn ban_user(&self, uid: i64) {
+        if let Some(user) = self
+            .users
+            .lock()
+            .iter_mut()
+            .find(|user| user.id as i64 == uid)
+        {
+            user.status = Some(UserStatus::Black as i32);
+        }
+    }
+
+    /// 添加一个房间，返回房间 ID
+    pub fn add_room(&self, name: &str, r#type: i32) -> i64 {
+        let mut rooms = self.rooms.lock();
+        let id = rooms.len() as u64 + 1;
+        rooms.push(room::Model {
+            id,
+            name: name.to_string(),
+            r#type,
+            member_count: 0,
+            active_time: NOW,
+            last_msg_id: None,
+            last_msg_abstract: None,
+            create_time: NOW,
+            update_time: NOW,
+            deleted_at: None,
+        });
+        id as i64
+    }
+
+    /// 添加群成员记录
+    pub fn add_group_member(&self, room_id: i64, uid: i64) {
+        self.group_members.lock().push((room_id, uid));
+    }
+
+    /// 逻辑删除房间，与解散群聊相同
+    pub fn delete_room(&self, room_id: i64) {
+        let mut rooms = self.rooms.lock();
+        if let Some(room) = rooms.iter_mut().find(|room| room.id as i64 == room_id) {
+            room.deleted_at = Some(NOW);
+        }
+    }
+
+    /// 所有消息
+    pub fn messages(&self) -> Vec<message::Model> {
+        self.messages.lock().clone()
+    }
+
+    /// 用户在房间中的阅读进度
+    pub fn read_cursor(&self, uid: i64, room_id: i64) -> Option<u64> {
+        self.read_cursors.lock().get(&(uid, room_id)).copied()
+    }
+
+    /// 添加一个消息标记
+    pub fn add_mark(&self, msg_id: u64, uid: i64, r#type: i32) {
+        self.save_mark(MarkWrite {
+            msg_id: msg_id as i64,
+            uid,
+            mark_type: r#type,
+            status: MarkStatus::Normal as i32,
+        });
+    }
+
+    fn save_mark(&self, write: MarkWrite) {
+        let mut marks = self.marks.lock();
+        if let Some(mark) = marks.iter_mut().find(|mark| {
+            mark.msg_id == write.msg_id && mark.uid == write.uid && mark.r#type == write.mark_type
+        }) {
+            mark.status = write.status;
+            return;
+        }
+        let id = marks.len() as u64 + 1;
+        marks.push(message_mark::Model {
+            id,
+            msg_id: write.msg_id,
+            uid: write.uid,
+            r#type: write.mark_type,
+            status: write.status,
+            create_time: NOW,
+            update_time: NOW,
+        });
+    }
+}
+
+fn page<T: Clone>(items: impl Iterator<Item = T>, offset: u64, limit: u64) -> Vec<T> {
+    items.skip(offset as usize).take(limit as usize).collect()
+}
+
+#[async_trait]
+impl UserRepo for MemoryRepo {
+    async fn find_by_id(&self, uid: i64) -> Result<Option<user::Model>, DbErr> {
+        let users = self.users.lock();
+        Ok(users
+            .iter()
+            .filter(|user| user.deleted_at.is_none())
+            .find(|user| user.id as i64 == uid)
+            .cloned())
+    }
+
+    async fn find_by_ids(&self, uids: &[i64]) -> Result<Vec<user::Model>, DbErr> {
+        let users = self.users.lock();
+        Ok(users
+            .iter()
+            .filter(|user| uids.contains(&(user.id as i64)))
+            .cloned()
+            .collect())
+    }
+
+    async fn find_by_open_id(&self, open_id: &str) -> Result<Option<user::Model>, DbErr> {
+        let users = self.users.lock();
+        Ok(users.iter().find(|user| user.open_id == open_id).cloned())
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<user::Model>, DbErr> {
+        let users = self.users.lock();
+        Ok(users
+            .iter()
+            .find(|user| user.name.as_deref() == Some(name))
+            .cloned())
+    }
+
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<user::Model>, DbErr> {
+        let users = self.users.lock();
+        let users = users.iter().rev().filter(|user| user.deleted_at.is_none());
+        Ok(page(users.cloned(), offset, limit))
+    }
+
+    async fn create(&self, open_id: &str) -> Result<user::Model, DbErr> {
+        let uid = self.add_user(open_id, None);
+        UserRepo::find_by_id(self, uid)
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound(uid.to_string()))
+    }
+
+    async fn update_name(&self, uid: i64, name: &str) -> Result<(), DbErr> {
+        let mut users = self.users.lock();
+        if let Some(user) = users.iter_mut().find(|user| user.id as i64 == uid) {
+            user.name = Some(name.to_string());
+        }
+        Ok(())
+    }
+
+    async fn update_avatar(&self, uid: i64, avatar: &str) -> Result<(), DbErr> {
+        let mut users = self.users.lock();
+        if let Some(user) = users.iter_mut().find(|user| user.id as i64 == uid) {
+            user.avatar = Some(avatar.to_string());
+        }
+        Ok(())
+    }
+
+    async fn update_ip(&self, uid: i64, detail: &IpDetail) -> Result<bool, DbErr> {
+        let mut users = self.users.lock();
+        let Some(user) = users.iter_mut().find(|user| user.id as i64 == uid) else {
+            return Ok(false);
+        };
+        let mut ip_info = IpInfo::from_json(user.ip_info.as_ref());
+        if !ip_info.refresh(detail.clone()) {
+            return Ok(false);
+        }
+        user.ip_info = serde_json::to_value(ip_info).ok();
+        Ok(true)
+    }
+
+    async fn refresh_active_time(&self, uids: &[i64]) -> Result<(), DbErr> {
+        let mut users = self.users.lock();
+        for user in users
+            .iter_mut()
+            .filter(|user| uids.contains(&(user.id as i64)))
+        {
+            user.last_opt_time = NOW;
+        }
+        Ok(())
+    }
+
+    async fn soft_delete(&self, uid: i64) -> Result<bool, DbErr> {
+        let mut users = self.users.lock();
+        Ok(users
+            .iter_mut()
+            .find(|user| user.id as i64 == uid && user.deleted_at.is_none())
+            .map(|user| user.deleted_at = Some(NOW))
+            .is_some())
+    }
+
+    async fn restore(&self, uid: i64) -> Result<bool, DbErr> {
+        let mut users = self.users.lock();
+        Ok(users
+            .iter_mut()
+            .find(|user| user.id as i64 == uid && user.deleted_at.is_some())
+            .map(|user| user.deleted_at = None)
+            .is_some())
+    }
+}
+
+#[async_trait]
+impl MessageRepo for MemoryRepo {
+    async fn page(
+        &self,
+        room_id: i64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        let messages = self.messages.lock();
+        let messages = messages
+            .iter()
+            .rev()
+            .filter(|message| message.room_id == room_id)
+            .cloned();
+        Ok(page(messages, offset, limit))
+    }
+
+    async fn page_ids(
+        &self,
+        room_id: i64,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<u64>, DbErr> {
+        let messages = self.messages.lock();
+        let ids = messages
+            .iter()
+            .rev()
+            .filter(|message| message.room_id == room_id)
+            .filter(|message| message.status == MessageStatus::Normal as i32)
+            .filter(|message| before.is_none_or(|before| message.id < before))
+            .map(|message| message.id);
+        Ok(page(ids, 0, limit))
+    }
+
+    async fn create(&self, mut message: message::ActiveModel) -> Result<message::Model, DbErr> {
+        let mut messages = self.messages.lock();
+        message.id = Set(messages.len() as u64 + 1);
+        message.create_time = Set(NOW);
+        message.update_time = Set(NOW);
+        // 数据库中可以为空的列，未设置时与插入后的默认值保持一致
+        if message.reply_msg_id.is_not_set() {
+            message.reply_msg_id = Set(None);
+        }
+        for optional in [&mut message.gap_count, &mut message.r#type] {
+            if optional.is_not_set() {
+                *optional = Set(None);
+            }
+        }
+        if message.extra.is_not_set() {
+            message.extra = Set(None);
+        }
+        if message.deleted_at.is_not_set() {
+            message.deleted_at = Set(None);
+        }
+        let message = message.try_into_model()?;
+        messages.push(message.clone());
+        Ok(message)
+    }
+
+    async fn find_by_id(&self, id: u64) -> Result<Option<message::Model>, DbErr> {
+        let messages = self.messages.lock();
+        Ok(messages.iter().find(|message| message.id == id).cloned())
+    }
+
+    async fn find_by_ids(&self, ids: &[u64]) -> Result<Vec<message::Model>, DbErr> {
+        let messages = self.messages.lock();
+        Ok(messages
+            .iter()
+            .filter(|message| ids.contains(&message.id))
+            .cloned()
+            .collect())
+    }
+
+    async fn count_marks(&self, msg_ids: &[u64]) -> Result<Vec<MarkCount>, DbErr> {
+        let marks = self.marks.lock();
+        let mut counts: Vec<MarkCount> = Vec::new();
+        for mark in marks
+            .iter()
+            .filter(|mark| msg_ids.contains(&(mark.msg_id as u64)))
+            .filter(|mark| mark.status == MarkStatus::Normal as i32)
+        {
+            match counts
+                .iter_mut()
+                .find(|count| count.msg_id == mark.msg_id && count.mark_type == mark.r#type)
+            {
+                Some(count) => count.count += 1,
+                None => counts.push(MarkCount {
+                    msg_id: mark.msg_id,
+                    mark_type: mark.r#type,
+                    count: 1,
+                }),
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn user_marks(&self, uid: i64, msg_ids: &[u64]) -> Result<Vec<(i64, i32)>, DbErr> {
+        let marks = self.marks.lock();
+        Ok(marks
+            .iter()
+            .filter(|mark| mark.uid == uid && msg_ids.contains(&(mark.msg_id as u64)))
+            .filter(|mark| mark.status == MarkStatus::Normal as i32)
+            .map(|mark| (mark.msg_id, mark.r#type))
+            .collect())
+    }
+
+    async fn save_marks(&self, marks: &[MarkWrite]) -> Result<(), DbErr> {
+        for mark in marks {
+            self.save_mark(*mark);
+        }
+        Ok(())
+    }
+
+    async fn save_read_cursors(&self, cursors: &[ReadCursor]) -> Result<(), DbErr> {
+        let mut read_cursors = self.read_cursors.lock();
+        for cursor in cursors {
+            let read = read_cursors
+                .entry((cursor.uid, cursor.room_id))
+                .or_default();
+            *read = (*read).max(cursor.msg_id);
+        }
+        Ok(())
+    }
+
+    async fn count_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+    ) -> Result<u64, DbErr> {
+        Ok(self
+            .page_readers(room_id, msg_id, exclude_uid, None, u64::MAX)
+            .await?
+            .len() as u64)
+    }
+
+    async fn page_readers(
+        &self,
+        room_id: i64,
+        msg_id: u64,
+        exclude_uid: i64,
+        before: Option<i64>,
+        limit: u64,
+    ) -> Result<Vec<i64>, DbErr> {
+        let group_members = self.group_members.lock();
+        Ok(self
+            .read_cursors
+            .lock()
+            .iter()
+            .rev()
+            .filter(|((uid, room), read)| {
+                *room == room_id
+                    && **read >= msg_id
+                    && *uid != exclude_uid
+                    && before.is_none_or(|before| *uid < before)
+                    && (room_id == GLOBAL_ROOM_ID || group_members.contains(&(room_id, *uid)))
+            })
+            .map(|((uid, _), _)| *uid)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn update_content(&self, id: u64, content: &str) -> Result<(), DbErr> {
+        let mut messages = self.messages.lock();
+        if let Some(message) = messages.iter_mut().find(|message| message.id == id) {
+            message.content = content.to_string();
+        }
+        Ok(())
+    }
+
+    async fn update_extra(&self, id: u64, extra: serde_json::Value) -> Result<(), DbErr> {
+        let mut messages = self.messages.lock();
+        if let Some(message) = messages.iter_mut().find(|message| message.id == id) {
+            message.extra = Some(extra);
+        }
+        Ok(())
+    }
+
+    async fn soft_delete(&self, id: u64) -> Result<bool, DbErr> {
+        let mut messages = self.messages.lock();
+        Ok(messages
+            .iter_mut()
+            .find(|message| message.id == id && message.status == MessageStatus::Normal as i32)
+            .map(|message| {
+                message.status = MessageStatus::Deleted as i32;
+                message.deleted_at = Some(NOW);
+            })
+            .is_some())
+    }
+
+    async fn restore(&self, id: u64) -> Result<bool, DbErr> {
+        let mut messages = self.messages.lock();
+        Ok(messages
+            .iter_mut()
+            .find(|message| message.id == id && message.status != MessageStatus::Normal as i32)
+            .map(|message| {
+                message.status = MessageStatus::Normal as i32;
+                message.deleted_at = None;
+            })
+            .is_some())
+    }
+
+    async fn list_after(
+        &self,
+        room_ids: &[i64],
+        after: u64,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        let messages = self.messages.lock();
+        let messages = messages
+            .iter()
+            .filter(|message| message.id > after && room_ids.contains(&message.room_id))
+            .filter(|message| message.status == MessageStatus::Normal as i32)
+            .cloned();
+        Ok(page(messages, 0, limit))
+    }
+
+    async fn search(
+        &self,
+        room_ids: &[i64],
+        keyword: &str,
+        before: Option<u64>,
+        limit: u64,
+    ) -> Result<Vec<message::Model>, DbErr> {
+        let keyword = keyword.to_lowercase();
+        let messages = self.messages.lock();
+        let messages = messages
+            .iter()
+            .rev()
+            .filter(|message| room_ids.contains(&message.room_id))
+            .filter(|message| message.status == MessageStatus::Normal as i32)
+            .filter(|message| before.is_none_or(|before| message.id < before))
+            .filter(|message| message.content.to_lowercase().contains(&keyword))
+            .cloned();
+        Ok(page(messages, 0, limit))
+    }
+}
+
+#[async_trait]
+impl RoomRepo for MemoryRepo {
+    async fn find_by_id(&self, room_id: i64) -> Result<Option<room::Model>, DbErr> {
+        let rooms = self.rooms.lock();
+        Ok(rooms
+            .iter()
+            .filter(|room| room.deleted_at.is_none())
+            .find(|room| room.id as i64 == room_id)
+            .cloned())
+    }
+
+    async fn find_by_ids(&self, room_ids: &[i64]) -> Result<Vec<room::Model>, DbErr> {
+        let rooms = self.rooms.lock();
+        Ok(rooms
+            .iter()
+            .filter(|room| room.deleted_at.is_none())
+            .filter(|room| room_ids.contains(&(room.id as i64)))
+            .cloned()
+            .collect())
+    }
+
+    async fn page(
+        &self,
+        exclude: &[i64],
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<room::Model>, DbErr> {
+        let mut rooms = self.rooms.lock().clone();
+        rooms.retain(|room| {
+            room.deleted_at.is_none()
+                && room.r#type != RoomType::Single as i32
+                && !exclude.contains(&(room.id as i64))
+        });
+        rooms.sort_by(|a, b| b.active_time.cmp(&a.active_time).then(b.id.cmp(&a.id)));
+        Ok(page(rooms.into_iter(), offset, limit))
+    }
+
+    async fn refresh_last_message(&self, message: &message::Model) -> Result<(), DbErr> {
+        let mut rooms = self.rooms.lock();
+        let id = message.id as i64;
+        if let Some(room) = rooms
+            .iter_mut()
+            .find(|room| room.id as i64 == message.room_id)
+            .filter(|room| room.last_msg_id.is_none_or(|last| last <= id))
+        {
+            room.active_time = room.active_time.max(message.create_time);
+            room.last_msg_id = Some(id);
+            room.last_msg_abstract = Some(message_abstract(message));
+        }
+        Ok(())
+    }
+
+    async fn is_group_member(&self, room_id: i64, uid: i64) -> Result<bool, DbErr> {
+        Ok(room_id == GLOBAL_ROOM_ID || self.group_members.lock().contains(&(room_id, uid)))
+    }
+
+    /// 内存中没有单聊房间成员，只返回全员群聊和有成员记录的群聊
+    async fn member_room_ids(&self, uid: i64) -> Result<Vec<i64>, DbErr> {
+        let rooms = self.rooms.lock();
+        let group_members = self.group_members.lock();
+        Ok(rooms
+            .iter()
+            .filter(|room| room.deleted_at.is_none())
+            .filter(|room| room.r#type != RoomType::Single as i32)
+            .map(|room| room.id as i64)
+            .filter(|&id| id == GLOBAL_ROOM_ID || group_members.contains(&(id, uid)))
+            .collect())
+    }
+}
+
+/// # 不访问网络的微信公众平台接口
+///
+/// 发送的客服消息和模板消息会被记录下来，便于断言
+#[derive(Debug)]
+pub struct MockWxClient {
+    config: WxConfig,
+    sent: Mutex<Vec<WxMessage>>,
+    news: Mutex<Vec<(String, Vec<WxNewsArticle>)>>,
+    templates: Mutex<Vec<WxTemplateMessage>>,
+}
+
+impl Default for MockWxClient {
+    fn default() -> Self {
+        Self::new("mock_app_id")
+    }
+}
+
+impl MockWxClient {
+    /// 使用 app_id 创建，原始 ID 为 `gh_` 加 app_id
+    pub fn new(app_id: &str) -> Self {
+        Self {
+            config: WxConfig {
+                callback_url: "http://localhost:8080".to_string(),
+                app_id: app_id.to_string(),
+                original_id: Some(format!("gh_{app_id}")),
+                app_secret: "mock_app_secret".to_string(),
+                app_secret_file: None,
+                token: "mock_token".to_string(),
+                encoding_aes_key: "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8"
+                    .parse()
+                    .expect("valid encoding aes key"),
+                timeout_secs: 1,
+                reply_timeout_millis: 100,
+                stable_token: false,
+            },
+            sent: Mutex::new(Vec::new()),
+            news: Mutex::new(Vec::new()),
+            templates: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 已发送的客服消息
+    pub fn sent_messages(&self) -> Vec<WxMessage> {
+        self.sent.lock().clone()
+    }
+
+    /// 已发送的图文消息：接收者和文章
+    pub fn sent_news(&self) -> Vec<(String, Vec<WxNewsArticle>)> {
+        self.news.lock().clone()
+    }
+
+    /// 已发送的模板消息
+    pub fn sent_templates(&self) -> Vec<WxTemplateMessage> {
+        self.templates.lock().clone()
+    }
+}
+
+#[async_trait]
+impl WxApi for MockWxClient {
+    fn config(&self) -> &WxConfig {
+        &self.config
+    }
+
+    async fn get_qrcode_tick_by_id(
+        &self,
+        expire_seconds: Option<u64>,
+        _limit: bool,
+        scene_id: NonZeroUsize,
+    ) -> anyhow::Result<QrCodeTicket> {
+        Ok(QrCodeTicket {
+            ticket: format!("ticket_{scene_id}"),
+            expire_seconds: expire_seconds.unwrap_or(60),
+            url: format!("http://weixin.qq.com/q/{scene_id}"),
+        })
+    }
+
+    async fn get_webpage_authorization_access_token(
+        &self,
+        code: &str,
+    ) -> anyhow::Result<WxWebpageAccessToken> {
+        anyhow::bail!("Webpage authorization is not mocked: {code}")
+    }
+
+    async fn send_custom_message(&self, message: &WxMessage) -> anyhow::Result<()> {
+        self.sent.lock().push(message.clone());
+        Ok(())
+    }
+
+    async fn send_custom_news(
+        &self,
+        to_user: &str,
+        articles: &[WxNewsArticle],
+    ) -> anyhow::Result<()> {
+        self.news
+            .lock()
+            .push((to_user.to_string(), articles.to_vec()));
+        Ok(())
+    }
+
+    async fn send_template_message(&self, message: &WxTemplateMessage) -> anyhow::Result<()> {
+        self.templates.lock().push(message.clone());
+        Ok(())
+    }
+
+    /// 素材 ID 为 `invalid` 时返回错误，否则返回一篇以素材 ID 为标题的文章
+    async fn get_news_material(&self, media_id: &str) -> anyhow::Result<Vec<WxNewsArticle>> {
+        if media_id == "invalid" {
+            anyhow::bail!("Weixin server responded with error: 40007 invalid media_id");
+        }
+        Ok(vec![WxNewsArticle {
+            title: media_id.to_string(),
+            description: String::new(),
+            url: format!("https://mp.weixin.qq.com/s/{media_id}"),
+            pic_url: None,
+        }])
+    }
+
+    async fn get_user_info(&self, _access_token: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// # 测试应用
+///
+/// 使用 [`MemoryRepo`]、内存中的 [`SessionManager`] 和 [`MockWxClient`] 构造的完整路由
+#[derive(Clone)]
+pub struct TestApp {
+    /// 内存数据
+    pub repo: Arc<MemoryRepo>,
+    /// WebSocket 连接管理
+    pub session_manager: SessionManager,
+    /// 微信公众平台接口
+    pub wx_client: Arc<MockWxClient>,
+    /// JWT 密钥
+    pub jwt_keys: JwtKeys,
+}
+
+impl TestApp {
+    /// 创建测试应用
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            repo: Arc::new(MemoryRepo::default()),
+            session_manager: SessionManager::default(),
+            wx_client: Arc::new(MockWxClient::default()),
+            jwt_keys: JwtKeys::try_from(JWT_SECRET)?,
+        })
+    }
+
+    /// 注入了全部 Extension 的路由构造器
+    pub fn builder(&self) -> anyhow::Result<RouterBuilder> {
+        Ok(RouterBuilder::new()
+            .storage(DatabaseConnection::Disconnected)
+            .repos(Repos {
+                users: self.repo.clone(),
+                messages: self.repo.clone(),
+                rooms: self.repo.clone(),
+            })
+            .cache(Cache::open("redis://127.0.0.1/")?)
+            .jwt_keys(self.jwt_keys.clone())
+            .wx_client(self.wx_client.clone())
+            .session_manager(self.session_manager.clone())
+            .log_filter(LogFilterHandle::default()))
+    }
+
+    /// 构造路由
+    pub fn router(&self) -> anyhow::Result<Router> {
+        Ok(self.builder()?.build())
+    }
+
+    /// 为用户签发 token
+    pub fn token(&self, uid: i64) -> anyhow::Result<String> {
+        Ok(self.jwt_keys.sign(&Claims::from(uid))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::ws::Message;
+    use axum::http::{header, Method, Request, StatusCode};
+    use tower::ServiceExt;
+
+    use std::sync::Arc;
+
+    use crate::storage::oss::local::{LocalConfig, LocalStore};
+    use crate::storage::oss::OssConfig;
+    use sea_orm::Set;
+
+    use crate::storage::model::message;
+    use crate::storage::repo::{MessageRepo, ReadCursor, RoomRepo, UserRepo};
+    use crate::storage::write_behind::WriteBehind;
+    use crate::testing::TestApp;
+
+    async fn request(
+        app: &TestApp,
+        method: Method,
+        uri: &str,
+        uid: i64,
+    ) -> anyhow::Result<(StatusCode, serde_json::Value)> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+            .body(Body::empty())?;
+        let response = app.router()?.oneshot(request).await?;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn member_page() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let first = app.repo.add_user("open_id_1", Some("抹茶"));
+        let second = app.repo.add_user("open_id_2", None);
+        let (_id, mut receiver) = app.session_manager.connect(first);
+
+        let uri = "/capi/chat/public/member/page?pageSize=10&pageNo=1";
+        let (status, resp) = request(&app, Method::GET, uri, second).await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+        assert_eq!(resp["data"][0]["uid"], second);
+        assert_eq!(resp["data"][0]["online"], false);
+        assert_eq!(resp["data"][1]["name"], "抹茶");
+        assert_eq!(resp["data"][1]["online"], true);
+
+        // 在线状态只对登录用户可见
+        let anonymous = Request::get(uri).body(Body::empty())?;
+        let response = app.router()?.oneshot(anonymous).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        app.session_manager
+            .close_all(std::time::Duration::ZERO)
+            .await;
+        assert!(
+            matches!(receiver.recv().await, Some(Message::Text(reconnect)) if reconnect.contains(r#""type":20"#))
+        );
+        assert!(matches!(receiver.recv().await, Some(Message::Close(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn banned_token() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let uri = "/capi/user/userInfo";
+        let (status, resp) = request(&app, Method::GET, uri, uid).await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+
+        // 拉黑前签发的 token 在拉黑后失效，与吊销记录是否保留无关
+        app.repo.ban_user(uid);
+        let (status, resp) = request(&app, Method::GET, uri, uid).await?;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{resp}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn room_page() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        app.repo.add_room("抹茶群聊", 1);
+        let single = app.repo.add_room("", 3);
+        let message = message::ActiveModel {
+            room_id: Set(single),
+            from_uid: Set(1),
+            content: Set("悄悄话".to_string()),
+            status: Set(0),
+            ..Default::default()
+        };
+        let message = MessageRepo::create(app.repo.as_ref(), message).await?;
+        RoomRepo::refresh_last_message(app.repo.as_ref(), &message).await?;
+
+        // 单聊房间和其中的消息摘要不会出现在公开的房间列表中
+        let uri = "/capi/chat/public/room/page?pageSize=10&pageNo=1";
+        let anonymous = Request::get(uri).body(Body::empty())?;
+        let response = app.router()?.oneshot(anonymous).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert!(!String::from_utf8_lossy(&body).contains("悄悄话"));
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["data"][0]["name"], "抹茶群聊");
+        assert_eq!(resp["data"].as_array().map(Vec::len), Some(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_user_info() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let first = app.repo.add_user("open_id_1", Some("抹茶"));
+        let second = app.repo.add_user("open_id_2", None);
+        let batch = |body: serde_json::Value| -> anyhow::Result<Request<Body>> {
+            Ok(Request::post("/capi/user/public/info/batch")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?)
+        };
+
+        let body = serde_json::json!({ "reqList": [{ "uid": first }, { "uid": second + 1 }] });
+        let response = app.router()?.oneshot(batch(body)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["data"].as_array().map(Vec::len), Some(1));
+        assert_eq!(resp["data"][0]["name"], "抹茶");
+        let version = resp["data"][0]["version"].clone();
+
+        // 版本号相同的用户不返回
+        let body = serde_json::json!({
+            "reqList": [{ "uid": first, "version": version }, { "uid": second }]
+        });
+        let response = app.router()?.oneshot(batch(body)?).await?;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["data"].as_array().map(Vec::len), Some(1));
+        assert_eq!(resp["data"][0]["uid"], second);
+
+        let body = serde_json::json!({ "reqList": [] });
+        let response = app.router()?.oneshot(batch(body)?).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn modify_avatar() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let (id, mut receiver) = app.session_manager.connect(uid + 1);
+        app.session_manager.authenticate(id, uid + 1);
+
+        let root = std::env::temp_dir().join(format!("mallchat-avatar-{}", std::process::id()));
+        let store = LocalStore::new(LocalConfig {
+            path: root.clone(),
+            ..Default::default()
+        });
+        let router = app
+            .builder()?
+            .object_store(Arc::new(store), OssConfig::default())
+            .build();
+        let avatar = |content_type: &str, body: Vec<u8>| -> anyhow::Result<Request<Body>> {
+            Ok(Request::put("/capi/user/avatar")
+                .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))?)
+        };
+
+        let png = b"\x89PNG\r\n\x1a\n....".to_vec();
+        let response = router.clone().oneshot(avatar("image/png", png)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        let Some(url) = resp["data"]["avatar"].as_str() else {
+            anyhow::bail!("unexpected response: {resp}");
+        };
+        assert!(url.starts_with("/oss/avatar/"));
+        let user = UserRepo::find_by_id(app.repo.as_ref(), uid).await?;
+        assert_eq!(user.and_then(|user| user.avatar).as_deref(), Some(url));
+        let Some(Message::Text(push)) = receiver.recv().await else {
+            anyhow::bail!("user info change not pushed");
+        };
+        assert!(push.contains(r#""type":12"#), "{push}");
+
+        let response = router
+            .clone()
+            .oneshot(avatar("image/svg+xml", b"<svg></svg>".to_vec())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let key = serde_json::json!({ "key": "avatar/202306/999/a.png" });
+        let response = router
+            .oneshot(avatar("application/json", serde_json::to_vec(&key)?)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        tokio::fs::remove_dir_all(root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_page() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let group = app.repo.add_room("抹茶群聊", 1);
+        let single = app.repo.add_room("", 3);
+        for (room_id, status) in [(group, 0), (single, 0), (group, 1), (group, 0)] {
+            let message = message::ActiveModel {
+                room_id: Set(room_id),
+                from_uid: Set(uid),
+                content: Set("抹茶".to_string()),
+                status: Set(status),
+                ..Default::default()
+            };
+            MessageRepo::create(app.repo.as_ref(), message).await?;
+        }
+        app.repo.add_mark(4, uid, 1);
+        let page = |uri: String| -> anyhow::Result<Request<Body>> {
+            Ok(Request::builder().uri(uri).body(Body::empty())?)
+        };
+
+        let uri = format!("/capi/chat/public/msg/page?roomId={group}&pageSize=1");
+        let response = app.router()?.oneshot(page(uri)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["data"]["isLast"], false);
+        assert_eq!(resp["data"]["list"][0]["message"]["id"], 4);
+        assert_eq!(resp["data"]["list"][0]["fromUser"]["name"], "抹茶");
+        assert_eq!(resp["data"]["list"][0]["mark"]["likeCount"], 1);
+
+        // 已删除的消息不返回
+        let uri = format!("/capi/chat/public/msg/page?roomId={group}&pageSize=1&cursor=4");
+        let response = app.router()?.oneshot(page(uri)?).await?;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["data"]["isLast"], true);
+        assert_eq!(resp["data"]["list"][0]["message"]["id"], 1);
+
+        let uri = format!("/capi/chat/public/msg/page?roomId={single}&pageSize=10");
+        let response = app.router()?.oneshot(page(uri)?).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_page_tombstones() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let group = app.repo.add_room("抹茶群聊", 1);
+        for _ in 0..2 {
+            let message = message::ActiveModel {
+                room_id: Set(group),
+                from_uid: Set(uid),
+                content: Set("抹茶".to_string()),
+                status: Set(0),
+                ..Default::default()
+            };
+            MessageRepo::create(app.repo.as_ref(), message).await?;
+        }
+        let uri = format!("/capi/chat/public/msg/page?roomId={group}&pageSize=10");
+
+        // 已注销用户的消息仍然显示发送者
+        assert!(UserRepo::soft_delete(app.repo.as_ref(), uid).await?);
+        assert!(!UserRepo::soft_delete(app.repo.as_ref(), uid).await?);
+        assert!(UserRepo::find_by_id(app.repo.as_ref(), uid)
+            .await?
+            .is_none());
+        assert!(MessageRepo::soft_delete(app.repo.as_ref(), 2).await?);
+        let (status, resp) = request(&app, Method::GET, &uri, uid).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(1));
+        assert_eq!(resp["data"]["list"][0]["fromUser"]["name"], "抹茶");
+
+        assert!(UserRepo::restore(app.repo.as_ref(), uid).await?);
+        assert!(MessageRepo::restore(app.repo.as_ref(), 2).await?);
+        let (_, resp) = request(&app, Method::GET, &uri, uid).await?;
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(2));
+
+        // 解散的群聊不能再访问，消息保留
+        app.repo.delete_room(group);
+        let (status, _) = request(&app, Method::GET, &uri, uid).await?;
+        assert_ne!(status, StatusCode::OK);
+        assert_eq!(app.repo.messages().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_mark() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let group = app.repo.add_room("抹茶群聊", 1);
+        let message = message::ActiveModel {
+            room_id: Set(group),
+            from_uid: Set(uid),
+            content: Set("抹茶".to_string()),
+            status: Set(0),
+            ..Default::default()
+        };
+        MessageRepo::create(app.repo.as_ref(), message).await?;
+        let put = |uri: &str, body: serde_json::Value| -> anyhow::Result<Request<Body>> {
+            Ok(Request::put(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?)
+        };
+        let mark = |mark_type: i32, act_type: i32| serde_json::json!({ "msgId": 1, "markType": mark_type, "actType": act_type });
+
+        // 未启用延迟写入时直接写数据库，点赞后点踩会取消点赞
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/mark", mark(1, 1))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(app.repo.user_marks(uid, &[1]).await?, vec![(1, 1)]);
+        app.router()?
+            .oneshot(put("/capi/chat/msg/mark", mark(2, 1))?)
+            .await?;
+        assert_eq!(app.repo.user_marks(uid, &[1]).await?, vec![(1, 2)]);
+
+        let body = serde_json::json!({ "msgId": 2, "markType": 1, "actType": 1 });
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/mark", body)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // 启用延迟写入时先合并在内存中
+        let write_behind = WriteBehind::default();
+        let router = app.builder()?.write_behind(write_behind.clone()).build();
+        let response = router
+            .clone()
+            .oneshot(put("/capi/chat/msg/mark", mark(2, 2))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let read = serde_json::json!({ "roomId": group, "msgId": 1 });
+        let response = router.oneshot(put("/capi/chat/msg/read", read)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(app.repo.user_marks(uid, &[1]).await?, vec![(1, 2)]);
+        assert_eq!(app.repo.read_cursor(uid, group), None);
+
+        assert_eq!(write_behind.flush(app.repo.as_ref()).await?, 2);
+        assert!(app.repo.user_marks(uid, &[1]).await?.is_empty());
+        assert_eq!(app.repo.read_cursor(uid, group), Some(1));
+
+        let read = serde_json::json!({ "roomId": group + 1, "msgId": 1 });
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/read", read)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_mark_member() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let sender = app.repo.add_user("open_id_1", Some("抹茶"));
+        let uid = app.repo.add_user("open_id_2", Some("拿铁"));
+        app.repo.add_room("抹茶群聊", 1);
+        let group = app.repo.add_room("抹茶同好会", 1);
+        app.repo.add_group_member(group, sender);
+        app.repo.rooms.lock()[1].member_count = 2;
+        let message = message::ActiveModel {
+            room_id: Set(group),
+            from_uid: Set(sender),
+            content: Set("抹茶".to_string()),
+            status: Set(0),
+            ..Default::default()
+        };
+        MessageRepo::create(app.repo.as_ref(), message).await?;
+        let put = |uri: &str, body: serde_json::Value| -> anyhow::Result<Request<Body>> {
+            Ok(Request::put(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?)
+        };
+        let mark = serde_json::json!({ "msgId": 1, "markType": 1, "actType": 1 });
+        let read = serde_json::json!({ "roomId": group, "msgId": 1 });
+
+        // 不是群成员时不能标记、上报阅读进度
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/mark", mark.clone())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/read", read.clone())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(app.repo.user_marks(uid, &[1]).await?.is_empty());
+        assert_eq!(app.repo.read_cursor(uid, group), None);
+
+        app.repo.add_group_member(group, uid);
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/mark", mark)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .router()?
+            .oneshot(put("/capi/chat/msg/read", read)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(app.repo.read_cursor(uid, group), Some(1));
+
+        // 已读数只统计当前成员
+        let other = app.repo.add_user("open_id_3", None);
+        app.repo
+            .save_read_cursors(&[ReadCursor {
+                uid: other,
+                room_id: group,
+                msg_id: 1,
+            }])
+            .await?;
+        let (status, resp) = request(
+            &app,
+            Method::GET,
+            "/capi/chat/msg/read/count?msgId=1",
+            sender,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+        assert_eq!(resp["data"]["readCount"], 1);
+        assert_eq!(resp["data"]["unreadCount"], 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_report() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let group = app.repo.add_room("抹茶群聊", 1);
+        let message = message::ActiveModel {
+            room_id: Set(group),
+            from_uid: Set(uid),
+            content: Set("抹茶".to_string()),
+            status: Set(0),
+            ..Default::default()
+        };
+        MessageRepo::create(app.repo.as_ref(), message).await?;
+        let report = |body: serde_json::Value| -> anyhow::Result<Request<Body>> {
+            Ok(Request::post("/capi/chat/msg/report")
+                .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?)
+        };
+
+        // 在写入审核队列之前校验
+        for (body, code) in [
+            (serde_json::json!({ "msgId": 1, "reason": "" }), 9001),
+            (serde_json::json!({ "msgId": 1, "reason": "广告" }), 9001),
+            (serde_json::json!({ "msgId": 2, "reason": "广告" }), 3007),
+        ] {
+            let response = app.router()?.oneshot(report(body)?).await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            let resp: serde_json::Value = serde_json::from_slice(&body)?;
+            assert_eq!(resp["errCode"], code, "{resp}");
+        }
+
+        // 错误消息按 Accept-Language 翻译
+        for (language, err_msg) in [
+            (None, "不能举报自己的消息"),
+            (Some("en-US,en;q=0.9"), "You cannot report your own message"),
+            (Some("zh-CN,en;q=0.5"), "不能举报自己的消息"),
+        ] {
+            let mut request = report(serde_json::json!({ "msgId": 1, "reason": "广告" }))?;
+            if let Some(language) = language {
+                request.headers_mut().insert(
+                    header::ACCEPT_LANGUAGE,
+                    header::HeaderValue::from_static(language),
+                );
+            }
+            let response = app.router()?.oneshot(request).await?;
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            let resp: serde_json::Value = serde_json::from_slice(&body)?;
+            assert_eq!(resp["errMsg"], err_msg, "{resp}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn msg_read_count() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let sender = app.repo.add_user("open_id_1", Some("抹茶"));
+        let readers = [
+            app.repo.add_user("open_id_2", Some("拿铁")),
+            app.repo.add_user("open_id_3", Some("摩卡")),
+            app.repo.add_user("open_id_4", None),
+        ];
+        let group = app.repo.add_room("抹茶群聊", 1);
+        app.repo.rooms.lock()[0].member_count = 5;
+        for content in ["第一条", "第二条"] {
+            let message = message::ActiveModel {
+                room_id: Set(group),
+                from_uid: Set(sender),
+                content: Set(content.to_string()),
+                status: Set(0),
+                ..Default::default()
+            };
+            MessageRepo::create(app.repo.as_ref(), message).await?;
+        }
+        // 发送者自己的阅读进度不计入已读数
+        let cursors = [
+            (sender, 2),
+            (readers[0], 1),
+            (readers[1], 2),
+            (readers[2], 2),
+        ]
+        .map(|(uid, msg_id)| ReadCursor {
+            uid,
+            room_id: group,
+            msg_id,
+        });
+        app.repo.save_read_cursors(&cursors).await?;
+
+        let get = |uri: &str, uid: i64| -> anyhow::Result<Request<Body>> {
+            Ok(Request::get(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {}", app.token(uid)?))
+                .body(Body::empty())?)
+        };
+        let json = |response: axum::response::Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            anyhow::Ok(serde_json::from_slice::<serde_json::Value>(&body)?)
+        };
+
+        for (msg_id, read_count, unread_count) in [(1, 3, 1), (2, 2, 2)] {
+            let uri = format!("/capi/chat/msg/read/count?msgId={msg_id}");
+            let response = app.router()?.oneshot(get(&uri, sender)?).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let resp = json(response).await?;
+            assert_eq!(
+                resp["data"],
+                serde_json::json!({ "msgId": msg_id, "readCount": read_count, "unreadCount": unread_count })
+            );
+        }
+
+        let uri = "/capi/chat/msg/read/page?msgId=2&pageSize=1";
+        let response = app.router()?.oneshot(get(uri, sender)?).await?;
+        let resp = json(response).await?;
+        assert_eq!(
+            resp["data"],
+            serde_json::json!({
+                "cursor": readers[2].to_string(),
+                "isLast": false,
+                "list": [{ "uid": readers[2], "name": null, "avatar": null }],
+            })
+        );
+        let uri = format!(
+            "/capi/chat/msg/read/page?msgId=2&pageSize=1&cursor={}",
+            readers[2]
+        );
+        let response = app.router()?.oneshot(get(&uri, sender)?).await?;
+        let resp = json(response).await?;
+        assert_eq!(resp["data"]["isLast"], true);
+        assert_eq!(resp["data"]["list"][0]["name"], "摩卡");
+
+        // 只有发送者可以查看
+        for uri in [
+            "/capi/chat/msg/read/count?msgId=1",
+            "/capi/chat/msg/read/page?msgId=1&pageSize=10",
+        ] {
+            let response = app.router()?.oneshot(get(uri, readers[0])?).await?;
+            assert_eq!(json(response).await?["errCode"], 1002);
+        }
+        let response = app
+            .router()?
+            .oneshot(get("/capi/chat/msg/read/count?msgId=3", sender)?)
+            .await?;
+        assert_eq!(json(response).await?["errCode"], 3007);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_message() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let group = app.repo.add_room("抹茶群聊", 1);
+        let single = app.repo.add_room("", 3);
+        for (room_id, content) in [
+            (group, "今天喝抹茶"),
+            (single, "抹茶拿铁"),
+            (group, "抹茶冰淇淋"),
+            (group, "咖啡"),
+            (group, "<i>抹茶</i>"),
+        ] {
+            let message = message::ActiveModel {
+                room_id: Set(room_id),
+                from_uid: Set(uid),
+                content: Set(content.to_string()),
+                status: Set(0),
+                ..Default::default()
+            };
+            MessageRepo::create(app.repo.as_ref(), message).await?;
+        }
+
+        let uri = "/capi/chat/msg/search?keyword=%E6%8A%B9%E8%8C%B6&pageSize=2";
+        let (status, resp) = request(&app, Method::GET, uri, uid).await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+        assert_eq!(resp["data"]["isLast"], false);
+        assert_eq!(resp["data"]["list"][0]["message"]["id"], 5);
+        assert_eq!(
+            resp["data"]["list"][0]["highlight"],
+            "&lt;i&gt;<em>抹茶</em>&lt;/i&gt;"
+        );
+        assert_eq!(resp["data"]["list"][1]["message"]["content"], "抹茶冰淇淋");
+
+        // 单聊中的消息不属于该用户，不返回
+        let cursor = resp["data"]["cursor"].as_str().unwrap_or_default();
+        let uri = format!("{uri}&cursor={cursor}");
+        let (status, resp) = request(&app, Method::GET, &uri, uid).await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+        assert_eq!(resp["data"]["isLast"], true);
+        assert_eq!(resp["data"]["list"][0]["highlight"], "今天喝<em>抹茶</em>");
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(1));
+
+        let uri = "/capi/chat/msg/search?keyword=a&pageSize=2";
+        let (status, _) = request(&app, Method::GET, uri, uid).await?;
+        assert_ne!(status, StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn group_member_access() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let uid = app.repo.add_user("open_id_1", Some("抹茶"));
+        let global = app.repo.add_room("抹茶群聊", 1);
+        let group = app.repo.add_room("抹茶同好会", 1);
+        for room_id in [global, group] {
+            let message = message::ActiveModel {
+                room_id: Set(room_id),
+                from_uid: Set(uid),
+                content: Set("抹茶".to_string()),
+                status: Set(0),
+                ..Default::default()
+            };
+            MessageRepo::create(app.repo.as_ref(), message).await?;
+        }
+        let page =
+            |room_id: i64| format!("/capi/chat/public/msg/page?roomId={room_id}&pageSize=10");
+        let anonymous = |uri: String| -> anyhow::Result<Request<Body>> {
+            Ok(Request::builder().uri(uri).body(Body::empty())?)
+        };
+        let search = "/capi/chat/msg/search?keyword=%E6%8A%B9%E8%8C%B6&pageSize=10";
+
+        // 全员群聊对所有人开放，创建的群聊只有成员可以查看
+        let response = app.router()?.oneshot(anonymous(page(global))?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.router()?.oneshot(anonymous(page(group))?).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let (status, _) = request(&app, Method::GET, &page(group), uid).await?;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (_, resp) = request(&app, Method::GET, search, uid).await?;
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(1));
+        assert_eq!(resp["data"]["list"][0]["message"]["roomId"], global);
+
+        app.repo.add_group_member(group, uid);
+        let (status, resp) = request(&app, Method::GET, &page(group), uid).await?;
+        assert_eq!(status, StatusCode::OK, "{resp}");
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(1));
+        let (_, resp) = request(&app, Method::GET, search, uid).await?;
+        assert_eq!(resp["data"]["list"].as_array().map(Vec::len), Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn qr() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let request = Request::builder()
+            .uri("/capi/qr?data=https%3A%2F%2Fexample.com%2Fs%2Fabcdefgh&size=128")
+            .body(Body::empty())?;
+        let response = app.router()?.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert!(body.starts_with(b"\x89PNG"));
+
+        let request = Request::builder()
+            .uri("/capi/qr?data=abc&size=4096")
+            .body(Body::empty())?;
+        let response = app.router()?.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unauthorized() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let request = Request::builder()
+            .uri("/capi/user/userInfo")
+            .body(Body::empty())?;
+        let response = app.router()?.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disabled_subsystem() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        let request = Request::builder()
+            .uri("/capi/chat/public/room/page?pageSize=10&pageNo=1")
+            .header(header::AUTHORIZATION, format!("Bearer {}", app.token(1)?))
+            .body(Body::empty())?;
+        let router = app.builder()?.chat(false).build();
+        let response = router.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prefix() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        app.repo.add_room("抹茶群聊", 1);
+        let request = Request::builder()
+            .uri("/mallchat/capi/chat/public/room/page?pageSize=10&pageNo=1")
+            .header(header::AUTHORIZATION, format!("Bearer {}", app.token(1)?))
+            .body(Body::empty())?;
+        let router = app.builder()?.prefix("/mallchat").build();
+        let response = router.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn open_api_key_required() -> anyhow::Result<()> {
+        let app = TestApp::new()?;
+        // 用户 token 不能调用开放接口
+        let request = Request::builder()
+            .uri("/capi/open/stats/online")
+            .header(header::AUTHORIZATION, format!("Bearer {}", app.token(1)?))
+            .body(Body::empty())?;
+        let response = app.router()?.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(resp["errCode"], 1001);
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! # 时间
+//!
+//! 数据库中的时间列为不带时区的 `datetime(3)`，统一保存 UTC 时间：
+//! 连接的会话时区为 `+00:00`，列默认值 `CURRENT_TIMESTAMP(3)` 也是 UTC，服务端计算的时间使用 [`now`]。
+//!
+//! 接口和 WebSocket 推送中的时间使用 [`iso8601`] 序列化为精确到毫秒的 UTC 时间，如 `2023-06-01T08:00:00.000Z`，
+//! 反序列化时接受任意时区的 RFC 3339 时间并转换为 UTC，也兼容之前的 `2023-06-01 08:00:00.0` 格式。
+//! 需要数值时使用毫秒时间戳，如 [`WebhookDelivery::create_time`](crate::webhook::WebhookDelivery::create_time)。
+
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+/// 当前的 UTC 时间，精确到毫秒，与数据库中的精度相同
+pub fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    now.replace_nanosecond(now.millisecond() as u32 * 1_000_000)
+        .unwrap_or(now)
+}
+
+/// UTC 时间对应的毫秒时间戳
+pub fn to_millis(time: PrimitiveDateTime) -> i64 {
+    (time.assume_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// 毫秒时间戳对应的 UTC 时间，超出范围时返回 `None`
+pub fn from_millis(millis: i64) -> Option<PrimitiveDateTime> {
+    let time = OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000).ok()?;
+    Some(PrimitiveDateTime::new(time.date(), time.time()))
+}
+
+/// 格式化为精确到毫秒的 ISO-8601 UTC 时间
+pub fn format(time: PrimitiveDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.millisecond()
+    )
+}
+
+/// 解析 RFC 3339 时间并转换为 UTC，或不带时区的 `2023-06-01 08:00:00.0` 格式（视为 UTC）
+pub fn parse(text: &str) -> Option<PrimitiveDateTime> {
+    let time = match OffsetDateTime::parse(text, &Rfc3339) {
+        Ok(time) => time,
+        Err(_) => {
+            OffsetDateTime::parse(&format!("{}Z", text.replacen(' ', "T", 1)), &Rfc3339).ok()?
+        }
+    };
+    let time = time.to_offset(UtcOffset::UTC);
+    Some(PrimitiveDateTime::new(time.date(), time.time()))
+}
+
+/// 以 ISO-8601 UTC 时间序列化，用法：`#[serde(with = "crate::timestamp::iso8601")]`
+pub mod iso8601 {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use time::PrimitiveDateTime;
+
+    /// 序列化
+    pub fn serialize<S: Serializer>(
+        time: &PrimitiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format(*time))
+    }
+
+    /// 反序列化
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PrimitiveDateTime, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid date time: {text}")))
+    }
+
+    /// 可选的时间，用法：`#[serde(default, with = "crate::timestamp::iso8601::option")]`
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use time::PrimitiveDateTime;
+
+        /// 序列化
+        pub fn serialize<S: Serializer>(
+            time: &Option<PrimitiveDateTime>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match time {
+                Some(time) => super::serialize(time, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        /// 反序列化
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<PrimitiveDateTime>, D::Error> {
+            match Option::<String>::deserialize(deserializer)? {
+                Some(text) => super::super::parse(&text)
+                    .map(Some)
+                    .ok_or_else(|| serde::de::Error::custom(format!("invalid date time: {text}"))),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use time::macros::datetime;
+    use time::PrimitiveDateTime;
+
+    use crate::timestamp::{format, from_millis, now, parse, to_millis};
+
+    #[test]
+    fn convert() {
+        let time = datetime!(2023-06-01 08:00:00.123);
+        assert_eq!(format(time), "2023-06-01T08:00:00.123Z");
+        assert_eq!(to_millis(time), 1_685_606_400_123);
+        assert_eq!(from_millis(1_685_606_400_123), Some(time));
+        assert_eq!(now().nanosecond() % 1_000_000, 0);
+
+        assert_eq!(parse("2023-06-01T08:00:00.123Z"), Some(time));
+        assert_eq!(parse("2023-06-01T16:00:00.123+08:00"), Some(time));
+        assert_eq!(parse("2023-06-01 08:00:00.123"), Some(time));
+        assert_eq!(parse("2023-06-01"), None);
+    }
+
+    #[test]
+    fn serde() -> anyhow::Result<()> {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Resp {
+            #[serde(with = "crate::timestamp::iso8601")]
+            send_time: PrimitiveDateTime,
+            #[serde(default, with = "crate::timestamp::iso8601::option")]
+            mute_until: Option<PrimitiveDateTime>,
+        }
+
+        let resp = Resp {
+            send_time: datetime!(2023-06-01 00:00:00),
+            mute_until: None,
+        };
+        let json = serde_json::to_value(&resp)?;
+        assert_eq!(
+            json,
+            serde_json::json!({ "sendTime": "2023-06-01T00:00:00.000Z", "muteUntil": null })
+        );
+        assert_eq!(serde_json::from_value::<Resp>(json)?, resp);
+
+        let resp: Resp = serde_json::from_str(
+            r#"{"sendTime":"2023-06-01 00:00:00.0","muteUntil":"2023-06-01T08:10:00+08:00"}"#,
+        )?;
+        assert_eq!(resp.send_time, datetime!(2023-06-01 00:00:00));
+        assert_eq!(resp.mute_until, Some(datetime!(2023-06-01 00:10:00)));
+        Ok(())
+    }
+}
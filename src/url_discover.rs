@@ -0,0 +1,530 @@
+//! # 链接预览
+//!
+//! 文本消息中包含链接时，后台抓取网页的标题、描述和图标，保存到消息 `extra` 的
+//! [`URL_CONTENT_MAP`] 字段中，与 MallChat 的 url-discover 保持一致。
+//!
+//! 抓取的地址由用户提供，为防止 SSRF：只允许 http、https，解析域名后拒绝内网、回环等地址，
+//! 连接时固定使用校验过的地址（避免 DNS 重绑定），不使用代理，重定向逐跳校验，
+//! 并限制超时时间和读取的响应大小。
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 消息 `extra` 中保存链接预览的字段
+pub const URL_CONTENT_MAP: &str = "urlContentMap";
+
+/// 链接预览配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlDiscoverConfig {
+    /// 是否启用
+    #[serde(default = "default::enabled")]
+    pub enabled: bool,
+    /// 每条消息最多解析的链接数
+    #[serde(default = "default::max_urls")]
+    pub max_urls: usize,
+    /// 每个链接的超时时间（秒），包括重定向
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+    /// 最多读取的响应大小（字节）
+    #[serde(default = "default::max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// 最多跟随的重定向次数
+    #[serde(default = "default::max_redirects")]
+    pub max_redirects: usize,
+    /// 允许访问内网地址，只用于测试
+    #[serde(default)]
+    pub allow_private_network: bool,
+}
+
+mod default {
+    pub fn enabled() -> bool {
+        true
+    }
+
+    pub fn max_urls() -> usize {
+        3
+    }
+
+    pub fn timeout_secs() -> u64 {
+        3
+    }
+
+    pub fn max_body_bytes() -> usize {
+        512 * 1024
+    }
+
+    pub fn max_redirects() -> usize {
+        3
+    }
+}
+
+impl Default for UrlDiscoverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default::enabled(),
+            max_urls: default::max_urls(),
+            timeout_secs: default::timeout_secs(),
+            max_body_bytes: default::max_body_bytes(),
+            max_redirects: default::max_redirects(),
+            allow_private_network: false,
+        }
+    }
+}
+
+/// 链接预览
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlInfo {
+    /// 标题
+    pub title: Option<String>,
+    /// 描述
+    pub description: Option<String>,
+    /// 网站图标
+    pub image: Option<String>,
+}
+
+impl UrlInfo {
+    /// 是否没有解析到任何内容
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image.is_none()
+    }
+}
+
+/// 标题的最大长度（字符）
+const MAX_TITLE_CHARS: usize = 100;
+/// 描述的最大长度（字符）
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// 提取文本中的 http、https 链接，去重后最多返回 `max` 个
+pub fn extract_urls(content: &str, max: usize) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = content;
+    while urls.len() < max {
+        let lower = rest.to_ascii_lowercase();
+        let Some(start) = ["http://", "https://"]
+            .iter()
+            .filter_map(|scheme| lower.find(scheme))
+            .min()
+        else {
+            break;
+        };
+        let candidate = &rest[start..];
+        // 链接只包含 URI 允许的 ASCII 字符，遇到空白、中文等字符结束
+        let end = candidate
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c)))
+            .unwrap_or(candidate.len());
+        let url = candidate[..end].trim_end_matches(|c: char| ".,;:!?'()[]".contains(c));
+        if Url::parse(url).is_ok_and(|url| url.host_str().is_some())
+            && !urls.iter().any(|u| u == url)
+        {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end.max(1)..];
+    }
+    urls
+}
+
+/// 是否为公网地址
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // 0.0.0.0/8
+        || a == 0
+        // 100.64.0.0/10 运营商级 NAT
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15 基准测试
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4 保留
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 唯一本地地址
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10 链路本地地址
+        || (first & 0xffc0) == 0xfe80
+        // 2001:db8::/32 文档
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+/// 解析 HTML 中的标题、描述和图标，优先使用 Open Graph 标签
+pub fn parse_html(html: &str, base: &Url) -> UrlInfo {
+    let mut title = None;
+    let mut og_title = None;
+    let mut description = None;
+    let mut og_description = None;
+    let mut icon = None;
+
+    // 只转换 ASCII 字符，字节位置与原文一致
+    let lower = html.to_ascii_lowercase();
+    let mut position = 0;
+    while let Some(offset) = lower[position..].find('<') {
+        let start = position + offset + 1;
+        let Some(length) = lower[start..].find('>') else {
+            break;
+        };
+        let end = start + length;
+        let tag = &html[start..end];
+        position = end + 1;
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        match &lower[start..start + name_end] {
+            "title" if title.is_none() => {
+                let text_end = lower[position..]
+                    .find("</title")
+                    .map_or(lower.len(), |offset| position + offset);
+                title = Some(&html[position..text_end]);
+                position = text_end;
+            }
+            "meta" => {
+                let attributes = attributes(&tag[name_end..]);
+                let key = attribute(&attributes, "property").or(attribute(&attributes, "name"));
+                let content = attribute(&attributes, "content");
+                match key.map(str::to_ascii_lowercase).as_deref() {
+                    Some("og:title") => og_title = og_title.or(content),
+                    Some("og:description") => og_description = og_description.or(content),
+                    Some("description") => description = description.or(content),
+                    _ => {}
+                }
+            }
+            "link" if icon.is_none() => {
+                let attributes = attributes(&tag[name_end..]);
+                let is_icon = attribute(&attributes, "rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("icon"))
+                });
+                if is_icon {
+                    icon = attribute(&attributes, "href");
+                }
+            }
+            // 正文中的 meta、link 不影响结果，不需要继续解析
+            "body" => break,
+            _ => {}
+        }
+    }
+
+    UrlInfo {
+        title: og_title.or(title).and_then(|t| clean(t, MAX_TITLE_CHARS)),
+        description: og_description
+            .or(description)
+            .and_then(|d| clean(d, MAX_DESCRIPTION_CHARS)),
+        image: icon
+            .map(decode_entities)
+            .and_then(|href| base.join(href.trim()).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(String::from),
+    }
+}
+
+/// 解析标签的属性，属性名转为小写
+fn attributes(tag: &str) -> Vec<(String, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                let (value, remain) = match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let value = &value[1..];
+                        let end = value.find(quote).unwrap_or(value.len());
+                        (&value[..end], value.get(end + 1..).unwrap_or_default())
+                    }
+                    _ => {
+                        let end = value
+                            .find(|c: char| c.is_ascii_whitespace())
+                            .unwrap_or(value.len());
+                        (&value[..end], &value[end..])
+                    }
+                };
+                rest = remain;
+                value
+            }
+            None => "",
+        };
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+    }
+    attributes
+}
+
+fn attribute<'a>(attributes: &[(String, &'a str)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| *value)
+}
+
+/// 解码 HTML 实体
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..=end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// 解码实体、合并空白，超出长度时截断
+fn clean(text: &str, max_chars: usize) -> Option<String> {
+    let text = decode_entities(text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text: String = text.chars().take(max_chars).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+/// 链接预览
+#[derive(Debug, Clone)]
+pub struct UrlDiscover {
+    config: UrlDiscoverConfig,
+}
+
+impl UrlDiscover {
+    /// 创建
+    pub fn new(config: UrlDiscoverConfig) -> Self {
+        Self { config }
+    }
+
+    /// 配置
+    pub fn config(&self) -> &UrlDiscoverConfig {
+        &self.config
+    }
+
+    /// 解析文本中的链接，抓取失败或没有内容的链接不返回
+    pub async fn discover(&self, content: &str) -> BTreeMap<String, UrlInfo> {
+        let mut result = BTreeMap::new();
+        for url in extract_urls(content, self.config.max_urls) {
+            let timeout = Duration::from_secs(self.config.timeout_secs);
+            match tokio::time::timeout(timeout, self.fetch(&url)).await {
+                Ok(Ok(Some(info))) => {
+                    result.insert(url, info);
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(error)) => tracing::debug!(%url, %error, "Failed to discover url."),
+                Err(_) => tracing::debug!(%url, "Discover url timeout."),
+            }
+        }
+        result
+    }
+
+    /// 抓取网页，不是 HTML 或者没有解析到内容时返回 `None`
+    pub async fn fetch(&self, url: &str) -> anyhow::Result<Option<UrlInfo>> {
+        let mut url = Url::parse(url)?;
+        for _ in 0..=self.config.max_redirects {
+            let client = self.client(&url).await?;
+            let mut response = client.get(url.clone()).send().await?;
+            if response.status().is_redirection() {
+                let Some(location) = response.headers().get(LOCATION) else {
+                    anyhow::bail!("redirect without location: {url}");
+                };
+                url = url.join(location.to_str()?)?;
+                continue;
+            }
+            let response_status = response.status();
+            anyhow::ensure!(response_status.is_success(), "{url}: {response_status}");
+            let is_html = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.to_ascii_lowercase().contains("text/html"));
+            if !is_html {
+                return Ok(None);
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                body.extend_from_slice(&chunk);
+                if body.len() >= self.config.max_body_bytes {
+                    body.truncate(self.config.max_body_bytes);
+                    break;
+                }
+            }
+            let info = parse_html(&String::from_utf8_lossy(&body), &url);
+            return Ok((!info.is_empty()).then_some(info));
+        }
+        anyhow::bail!("too many redirects: {url}")
+    }
+
+    /// 校验地址，返回固定连接到校验过的 IP 的客户端
+    async fn client(&self, url: &Url) -> anyhow::Result<reqwest::Client> {
+        anyhow::ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "unsupported scheme: {url}"
+        );
+        let Some(host) = url.host_str() else {
+            anyhow::bail!("url without host: {url}");
+        };
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        let Some(&addr) = addrs.first() else {
+            anyhow::bail!("failed to resolve {host}");
+        };
+        if !self.config.allow_private_network {
+            // 任意一个地址不是公网地址都拒绝，避免多条解析记录中混入内网地址
+            if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+                anyhow::bail!("{host} resolves to non-public address {}", addr.ip());
+            }
+        }
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .no_proxy()
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .user_agent(concat!("mallchat/", env!("CARGO_PKG_VERSION")))
+            .resolve(host, addr)
+            .build()?;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use reqwest::Url;
+
+    use crate::url_discover::{extract_urls, is_public_ip, parse_html, UrlInfo};
+
+    #[test]
+    fn extract() {
+        let content = "看看https://github.com/zongzibinbin/MallChat，还有 http://example.com/a?b=1&c=2. \
+            重复 https://github.com/zongzibinbin/MallChat (https://mallchat.cn) ftp://x.com http://";
+        assert_eq!(
+            extract_urls(content, 5),
+            vec![
+                "https://github.com/zongzibinbin/MallChat",
+                "http://example.com/a?b=1&c=2",
+                "https://mallchat.cn",
+            ]
+        );
+        assert_eq!(extract_urls(content, 1).len(), 1);
+        assert!(extract_urls("没有链接", 3).is_empty());
+    }
+
+    #[test]
+    fn public_ip() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(
+                is_public_ip(ip.parse::<IpAddr>().expect("valid ip")),
+                "{ip}"
+            );
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(
+                !is_public_ip(ip.parse::<IpAddr>().expect("valid ip")),
+                "{ip}"
+            );
+        }
+    }
+
+    #[test]
+    fn html() -> anyhow::Result<()> {
+        let base = Url::parse("https://example.com/post/1")?;
+        let html = r#"<!DOCTYPE html><html><HEAD>
+            <meta charset="utf-8">
+            <TITLE>  抹茶 &amp;
+              聊天 </TITLE>
+            <meta name="Description" content='一个 &quot;聊天&quot; 室'>
+            <link rel="shortcut icon" href="/favicon.png" />
+            </head><body><meta property="og:title" content="ignored"></body></html>"#;
+        assert_eq!(
+            parse_html(html, &base),
+            UrlInfo {
+                title: Some("抹茶 & 聊天".to_string()),
+                description: Some("一个 \"聊天\" 室".to_string()),
+                image: Some("https://example.com/favicon.png".to_string()),
+            }
+        );
+
+        let html = r#"<title>title</title><meta property="og:title" content="OG &#x27;title&#39;">
+            <meta property=og:description content=desc><link rel=icon href="javascript:alert(1)">"#;
+        assert_eq!(
+            parse_html(html, &base),
+            UrlInfo {
+                title: Some("OG 'title'".to_string()),
+                description: Some("desc".to_string()),
+                image: None,
+            }
+        );
+        assert!(parse_html("<p>plain", &base).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+//! # 事件 Webhook
+//!
+//! 将服务端事件 [`WebhookEvent`] 以 JSON 格式 POST 到订阅的地址，便于接入外部的审核、风控工具。
+//! 地址可以在配置文件的 `[[webhook.endpoints]]` 中配置，也可以通过管理接口 `/capi/admin/webhook` 注册，
+//! 保存在 `webhook` 表中。
+//!
+//! 发生事件时为每个订阅的地址发布一条 [`WebhookDelivery`] 到 [`WEBHOOK_TOPIC`]，由 [`Webhooks`] 消费后投递：
+//!
+//! - 配置了密钥时，请求头 [`SIGNATURE_HEADER`] 携带请求体的 HMAC-SHA256 签名（十六进制），与离线推送的
+//!   [Webhook](crate::push::webhook) 相同；[`EVENT_HEADER`]、[`DELIVERY_HEADER`] 为事件类型和投递 ID，
+//!   重试时投递 ID 不变，接收方可以据此去重；
+//! - 请求失败或响应状态码不是 2xx 时不确认消息，由消息队列在 `mq.retry_after_secs` 秒后重新投递，
+//!   超过 `mq.max_attempts` 次后转入死信队列（[`dead_letter_topic`](crate::mq::dead_letter_topic)）；
+//! - 没有消息队列时（如测试）在后台直接投递一次，不重试。
+
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::handler::auth::current_millisecond;
+use crate::handler::context;
+use crate::mq::{send_json, DynProducer, Handler};
+use crate::push::webhook::{sign, SIGNATURE_HEADER};
+use crate::service::webhook::WebhookService;
+
+/// 事件投递的主题
+pub const WEBHOOK_TOPIC: &str = "mallchat:mq:webhook";
+
+/// 事件投递的消费组
+pub const WEBHOOK_GROUP: &str = "webhook";
+
+/// 事件类型的请求头
+pub const EVENT_HEADER: &str = "X-MallChat-Event";
+
+/// 投递 ID 的请求头
+pub const DELIVERY_HEADER: &str = "X-MallChat-Delivery";
+
+/// 事件 Webhook 配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// 是否启用，未启用时不发布事件，管理接口注册的地址也不会收到回调
+    #[serde(default)]
+    pub enabled: bool,
+    /// 请求超时时间（秒）
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+    /// 配置文件中的地址，与管理接口注册的地址同时生效
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+}
+
+mod default {
+    pub fn timeout_secs() -> u64 {
+        5
+    }
+}
+
+/// 订阅事件的地址
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    /// 回调地址
+    pub url: String,
+    /// 签名密钥，为空时不签名
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// 订阅的事件，为空时订阅全部事件
+    #[serde(default)]
+    pub events: Vec<WebhookEventType>,
+}
+
+impl Debug for WebhookEndpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookEndpoint")
+            .field("url", &self.url)
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+impl WebhookEndpoint {
+    /// 是否订阅了该事件
+    pub fn subscribes(&self, event_type: WebhookEventType) -> bool {
+        self.events.is_empty() || self.events.contains(&event_type)
+    }
+}
+
+/// 事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    /// 用户注册
+    UserRegistered,
+    /// 消息被举报
+    MessageFlagged,
+    /// 用户被拉黑
+    UserBanned,
+}
+
+impl WebhookEventType {
+    /// 事件名，与 JSON 中的 `event` 字段相同
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UserRegistered => "user_registered",
+            Self::MessageFlagged => "message_flagged",
+            Self::UserBanned => "user_banned",
+        }
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user_registered" => Ok(Self::UserRegistered),
+            "message_flagged" => Ok(Self::MessageFlagged),
+            "user_banned" => Ok(Self::UserBanned),
+            other => Err(format!("unknown webhook event: {other}")),
+        }
+    }
+}
+
+/// 服务端事件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// 用户注册
+    UserRegistered(UserRegistered),
+    /// 消息被举报
+    MessageFlagged(MessageFlagged),
+    /// 用户被拉黑
+    UserBanned(UserBanned),
+}
+
+impl WebhookEvent {
+    /// 事件类型
+    pub fn event_type(&self) -> WebhookEventType {
+        match self {
+            Self::UserRegistered(_) => WebhookEventType::UserRegistered,
+            Self::MessageFlagged(_) => WebhookEventType::MessageFlagged,
+            Self::UserBanned(_) => WebhookEventType::UserBanned,
+        }
+    }
+}
+
+/// 用户注册
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRegistered {
+    /// 用户 ID
+    pub uid: i64,
+}
+
+/// 消息被举报
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageFlagged {
+    /// 消息 ID
+    pub msg_id: u64,
+    /// 房间 ID
+    pub room_id: i64,
+    /// 发送者 uid
+    pub from_uid: i64,
+    /// 举报者 uid
+    pub reporter_uid: i64,
+    /// 举报理由
+    pub reason: Option<String>,
+}
+
+/// 用户被拉黑
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserBanned {
+    /// 被拉黑的用户 uid
+    pub uid: i64,
+    /// 操作的管理员 uid
+    pub operator_uid: i64,
+    /// 原因
+    pub reason: Option<String>,
+}
+
+/// 投递到某个地址的事件，发布到 [`WEBHOOK_TOPIC`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    /// 投递 ID
+    pub id: String,
+    /// 回调地址
+    pub url: String,
+    /// 事件发生的时间（毫秒时间戳）
+    pub create_time: i64,
+    /// 事件
+    pub event: WebhookEvent,
+}
+
+/// 请求体，如 `{"id":"...","event":"user_banned","data":{"uid":1,...},"createTime":1690000000000}`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Body<'a> {
+    id: &'a str,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    create_time: i64,
+}
+
+/// 事件 Webhook
+#[derive(Clone)]
+pub struct Webhooks {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    endpoints: Vec<WebhookEndpoint>,
+    db: Option<DatabaseConnection>,
+    producer: Option<DynProducer>,
+    client: reqwest::Client,
+}
+
+impl Debug for Webhooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Webhooks")
+            .field("endpoints", &self.inner.endpoints)
+            .finish()
+    }
+}
+
+impl Webhooks {
+    /// 创建，`db` 为空时只使用配置文件中的地址，`producer` 为空时直接投递，不重试
+    pub fn new(
+        config: &WebhookConfig,
+        db: Option<DatabaseConnection>,
+        producer: Option<DynProducer>,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                endpoints: config.endpoints.clone(),
+                db,
+                producer,
+                client,
+            }),
+        })
+    }
+
+    /// 配置文件中和管理接口注册的地址，查询数据库失败时只使用配置文件中的地址
+    async fn endpoints(&self) -> Vec<WebhookEndpoint> {
+        let mut endpoints = self.inner.endpoints.clone();
+        let Some(db) = &self.inner.db else {
+            return endpoints;
+        };
+        match WebhookService::new(db).list().await {
+            Ok(registered) => endpoints.extend(registered.into_iter().map(WebhookEndpoint::from)),
+            Err(error) => tracing::warn!(%error, "Failed to load registered webhooks."),
+        }
+        endpoints
+    }
+
+    /// 发布事件，为每个订阅的地址投递一次，失败时只记录日志
+    pub async fn emit(&self, event: WebhookEvent) {
+        let event_type = event.event_type();
+        let create_time = current_millisecond();
+        for endpoint in self.endpoints().await {
+            if !endpoint.subscribes(event_type) {
+                continue;
+            }
+            let delivery = WebhookDelivery {
+                id: format!("{:032x}", rand::random::<u128>()),
+                url: endpoint.url,
+                create_time,
+                event: event.clone(),
+            };
+            match &self.inner.producer {
+                Some(producer) => {
+                    if let Err(error) = send_json(producer.as_ref(), WEBHOOK_TOPIC, &delivery).await
+                    {
+                        tracing::error!(%error, url = %delivery.url, event = event_type.as_str(), "Failed to publish webhook delivery.");
+                    }
+                }
+                None => {
+                    let webhooks = self.clone();
+                    context::spawn(async move {
+                        if let Err(error) = webhooks.deliver(&delivery).await {
+                            tracing::warn!(%error, url = %delivery.url, "Failed to deliver webhook.");
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// 投递到对应的地址，地址已删除时忽略
+    pub async fn deliver(&self, delivery: &WebhookDelivery) -> anyhow::Result<()> {
+        let event_type = delivery.event.event_type();
+        let Some(endpoint) = self
+            .endpoints()
+            .await
+            .into_iter()
+            .find(|endpoint| endpoint.url == delivery.url && endpoint.subscribes(event_type))
+        else {
+            tracing::info!(url = %delivery.url, id = %delivery.id, "Webhook unsubscribed, delivery dropped.");
+            return Ok(());
+        };
+        let body = serde_json::to_vec(&Body {
+            id: &delivery.id,
+            event: &delivery.event,
+            create_time: delivery.create_time,
+        })?;
+        let mut request = self
+            .inner
+            .client
+            .post(&endpoint.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event_type.as_str())
+            .header(DELIVERY_HEADER, &delivery.id);
+        if let Some(secret) = &endpoint.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("webhook responded with {status}");
+        }
+        tracing::info!(url = %delivery.url, id = %delivery.id, event = event_type.as_str(), "Webhook delivered.");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Handler for Webhooks {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn handle(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let delivery: WebhookDelivery = serde_json::from_slice(payload)?;
+        self.deliver(&delivery).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::sync::mpsc;
+
+    use crate::mq::memory::MemoryMq;
+    use crate::mq::{Consumer, Handler};
+    use crate::push::webhook::{sign, SIGNATURE_HEADER};
+    use crate::webhook::{
+        UserBanned, UserRegistered, WebhookConfig, WebhookEndpoint, WebhookEvent, WebhookEventType,
+        Webhooks, DELIVERY_HEADER, EVENT_HEADER, WEBHOOK_TOPIC,
+    };
+
+    fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    }
+
+    #[tokio::test]
+    async fn deliver() -> anyhow::Result<()> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let api = Router::new()
+            .route(
+                "/hook",
+                post(move |headers: HeaderMap, body: Bytes| async move {
+                    let _ = sender.send((headers, body));
+                    StatusCode::NO_CONTENT
+                }),
+            )
+            .route("/broken", post(|| async { StatusCode::BAD_GATEWAY }));
+        let server = tokio::spawn(async move { axum::serve(listener, api).await });
+
+        let mq = MemoryMq::default();
+        let consumer = mq.consumer(WEBHOOK_TOPIC, "webhook", 3);
+        let config = WebhookConfig {
+            enabled: true,
+            timeout_secs: 1,
+            endpoints: vec![
+                WebhookEndpoint {
+                    url: format!("http://{addr}/hook"),
+                    secret: Some("secret".to_string()),
+                    events: vec![WebhookEventType::UserBanned],
+                },
+                WebhookEndpoint {
+                    url: format!("http://{addr}/broken"),
+                    secret: None,
+                    events: vec![],
+                },
+            ],
+        };
+        let webhooks = Webhooks::new(&config, None, Some(Arc::new(mq.clone())))?;
+
+        // 只有订阅了全部事件的地址收到注册事件
+        webhooks
+            .emit(WebhookEvent::UserRegistered(UserRegistered { uid: 1 }))
+            .await;
+        let deliveries = consumer.poll().await?;
+        assert_eq!(deliveries.len(), 1);
+        assert!(webhooks.handle(&deliveries[0].payload).await.is_err());
+
+        let banned = WebhookEvent::UserBanned(UserBanned {
+            uid: 2,
+            operator_uid: 1,
+            reason: Some("spam".to_string()),
+        });
+        webhooks.emit(banned.clone()).await;
+        let deliveries = consumer.poll().await?;
+        // 包括未确认而重新投递的注册事件
+        assert_eq!(deliveries.len(), 3);
+        for delivery in &deliveries {
+            let _ = webhooks.handle(&delivery.payload).await;
+        }
+
+        let Some((headers, body)) = receiver.recv().await else {
+            anyhow::bail!("webhook not called");
+        };
+        assert_eq!(
+            header(&headers, SIGNATURE_HEADER),
+            Some(sign("secret", &body))
+        );
+        assert_eq!(
+            header(&headers, EVENT_HEADER).as_deref(),
+            Some("user_banned")
+        );
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            header(&headers, DELIVERY_HEADER).as_deref(),
+            body["id"].as_str()
+        );
+        assert_eq!(body["event"], "user_banned");
+        assert_eq!(
+            body["data"],
+            serde_json::json!({ "uid": 2, "operatorUid": 1, "reason": "spam" })
+        );
+        assert!(body["createTime"].is_i64());
+        assert!(receiver.try_recv().is_err());
+        server.abort();
+        Ok(())
+    }
+}
@@ -1,21 +1,31 @@
 //! # 微信公众平台访问相关
 //!
 
+pub mod crypto;
+pub mod reply;
+pub mod work;
 pub mod xml;
 
+use async_trait::async_trait;
 use base64::Engine;
 use reqwest::Method;
 use serde::de::{Error, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
 use tokio::sync::RwLock;
 use validator::Validate;
 
+use crate::secret;
+use crate::weixin::crypto::{constant_time_eq, WxCrypto};
+
 /// 微信公众平台配置
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WxConfig {
@@ -23,8 +33,15 @@ pub struct WxConfig {
     pub callback_url: String,
     /// 开发者ID
     pub app_id: String,
-    /// 开发者密码
+    /// 公众号的原始 ID（`gh_` 开头），即接收消息的 ToUserName，配置多个公众号时用于选择处理消息的公众号
+    #[serde(default)]
+    pub original_id: Option<String>,
+    /// 开发者密码，可以使用 `${ENV}` 引用环境变量
+    #[serde(default)]
     pub app_secret: String,
+    /// 从文件读取开发者密码，配置后忽略 `app_secret`
+    #[serde(default)]
+    pub app_secret_file: Option<PathBuf>,
     /// 令牌
     pub token: String,
     /// 消息加解密密钥
@@ -32,12 +49,183 @@ pub struct WxConfig {
     /// 超时时间
     #[serde(default = "default::timeout_secs")]
     pub timeout_secs: u64,
+    /// 被动回复的最长等待时间（毫秒），超时后改用客服消息接口回复
+    #[serde(default = "default::reply_timeout_millis")]
+    pub reply_timeout_millis: u64,
+    /// 使用 `cgi-bin/stable_token` 获取稳定版 access_token，调用频率限制更宽松，
+    /// 有效期内重复获取返回同一个 token，多个实例可以共用；关闭时使用 `cgi-bin/token`
+    #[serde(default)]
+    pub stable_token: bool,
 }
 
 mod default {
     pub fn timeout_secs() -> u64 {
         10
     }
+
+    pub fn reply_timeout_millis() -> u64 {
+        3000
+    }
+}
+
+/// 一个或多个公众号的配置
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WxConfigs {
+    /// 一个公众号，即 `[wx]`
+    One(WxConfig),
+    /// 多个公众号，即 `[[wx]]`，第一个为默认公众号
+    Many(Vec<WxConfig>),
+}
+
+impl WxConfig {
+    /// 解析 `app_secret`，`name` 为配置项的名称，见 [`secret`](crate::secret)
+    pub fn resolve_secrets(&mut self, name: &str) -> anyhow::Result<()> {
+        self.app_secret = secret::resolve(
+            &format!("{name}.app_secret"),
+            &self.app_secret,
+            self.app_secret_file.as_deref(),
+        )?;
+        Ok(())
+    }
+}
+
+impl WxConfigs {
+    /// 解析所有公众号的密钥
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        match self {
+            WxConfigs::One(config) => config.resolve_secrets("wx"),
+            WxConfigs::Many(configs) => configs
+                .iter_mut()
+                .enumerate()
+                .try_for_each(|(i, config)| config.resolve_secrets(&format!("wx[{i}]"))),
+        }
+    }
+}
+
+impl From<WxConfigs> for Vec<WxConfig> {
+    fn from(value: WxConfigs) -> Self {
+        match value {
+            WxConfigs::One(config) => vec![config],
+            WxConfigs::Many(configs) => configs,
+        }
+    }
+}
+
+/// 以 Extension 注入的微信公众平台接口，配置多个公众号时为默认公众号
+pub type DynWxApi = Arc<dyn WxApi>;
+
+/// # 多个公众号的接口
+///
+/// 以 Extension 注入，接收消息时按 ToUserName 选择公众号，调用接口时可以按 app_id 选择公众号。
+/// 第一个为默认公众号。
+#[derive(Debug, Clone)]
+pub struct WxClientRegistry {
+    clients: Arc<[DynWxApi]>,
+}
+
+impl WxClientRegistry {
+    /// 创建，至少需要一个公众号，app_id 不能重复
+    pub fn new(clients: Vec<DynWxApi>) -> anyhow::Result<Self> {
+        if clients.is_empty() {
+            anyhow::bail!("At least one weixin official account is required");
+        }
+        for (i, client) in clients.iter().enumerate() {
+            if clients[..i]
+                .iter()
+                .any(|other| other.app_id() == client.app_id())
+            {
+                anyhow::bail!("Duplicated weixin app_id: {}", client.app_id());
+            }
+        }
+        Ok(Self {
+            clients: clients.into(),
+        })
+    }
+
+    /// 默认公众号
+    pub fn default_client(&self) -> &DynWxApi {
+        &self.clients[0]
+    }
+
+    /// 按 app_id 选择公众号
+    pub fn get(&self, app_id: &str) -> Option<&DynWxApi> {
+        self.clients.iter().find(|client| client.app_id() == app_id)
+    }
+
+    /// 按接收消息的 ToUserName 选择公众号，只有一个公众号时总是选择它
+    pub fn route(&self, to_user_name: &str) -> Option<&DynWxApi> {
+        if self.clients.len() == 1 {
+            return self.clients.first();
+        }
+        self.clients
+            .iter()
+            .find(|client| client.config().original_id.as_deref() == Some(to_user_name))
+    }
+
+    /// 所有公众号
+    pub fn iter(&self) -> impl Iterator<Item = &DynWxApi> {
+        self.clients.iter()
+    }
+}
+
+impl From<DynWxApi> for WxClientRegistry {
+    fn from(client: DynWxApi) -> Self {
+        Self {
+            clients: Arc::new([client]),
+        }
+    }
+}
+
+/// # 微信公众平台接口
+///
+/// 处理器只依赖该 trait，测试时可以替换为不访问网络的实现
+#[async_trait]
+pub trait WxApi: Debug + Send + Sync {
+    /// 所有配置
+    fn config(&self) -> &WxConfig;
+    /// 开发者 ID
+    fn app_id(&self) -> &str {
+        self.config().app_id.as_str()
+    }
+    /// 令牌
+    fn token(&self) -> &str {
+        self.config().token.as_str()
+    }
+    /// 消息加解密密钥
+    fn encoding_aes_key(&self) -> &WxEncodingAesKey {
+        &self.config().encoding_aes_key
+    }
+    /// 消息加解密，接收方 ID 为开发者 ID
+    fn crypto(&self) -> WxCrypto<'_> {
+        WxCrypto::new(self.token(), self.encoding_aes_key(), self.app_id())
+    }
+    /// 通过场景值生成带参数的二维码
+    async fn get_qrcode_tick_by_id(
+        &self,
+        expire_seconds: Option<u64>,
+        limit: bool,
+        scene_id: NonZeroUsize,
+    ) -> anyhow::Result<QrCodeTicket>;
+    /// 获取网页授权 Access Token
+    async fn get_webpage_authorization_access_token(
+        &self,
+        code: &str,
+    ) -> anyhow::Result<WxWebpageAccessToken>;
+    /// 通过客服消息接口发送消息
+    async fn send_custom_message(&self, message: &WxMessage) -> anyhow::Result<()>;
+    /// 通过客服消息接口发送图文消息
+    async fn send_custom_news(
+        &self,
+        to_user: &str,
+        articles: &[WxNewsArticle],
+    ) -> anyhow::Result<()>;
+    /// 发送模板消息
+    async fn send_template_message(&self, message: &WxTemplateMessage) -> anyhow::Result<()>;
+    /// 获取永久图文素材
+    async fn get_news_material(&self, media_id: &str) -> anyhow::Result<Vec<WxNewsArticle>>;
+    /// 获取用户信息
+    async fn get_user_info(&self, access_token: &str) -> anyhow::Result<()>;
 }
 
 /// 微信公众平台客户端
@@ -93,26 +281,48 @@ impl WxClient {
     }
     /// 刷新 access_token
     pub async fn update_access_token(&self) -> anyhow::Result<()> {
+        self.refresh_access_token(0).await.map(|_| ())
+    }
+    /// 强制刷新 access_token，如接口返回 token 无效时
+    ///
+    /// 使用稳定版 access_token 时旧的 token 立即失效，多个实例共用时其他实例需要重新获取
+    pub async fn force_refresh_access_token(&self) -> anyhow::Result<()> {
+        let mut write = self.access_token.write().await;
+        let access_token = if self.config.stable_token {
+            Self::get_stable_access_token(&self.client, self.config.as_ref(), true).await?
+        } else {
+            Self::get_access_token(&self.client, self.config.as_ref()).await?
+        };
+        *write = access_token.into();
+        Ok(())
+    }
+    /// 在 access_token 过期前 `ahead_secs` 秒内提前刷新，返回是否刷新
+    pub async fn refresh_access_token(&self, ahead_secs: u64) -> anyhow::Result<bool> {
         let need_update = {
             let read = self.access_token.read().await;
-            read.expired()
+            read.expires_within(ahead_secs)
         };
         if need_update {
             let mut write = self.access_token.write().await;
-            let need_update = write.expired();
+            let need_update = write.expires_within(ahead_secs);
             if need_update {
                 let access_token =
                     Self::get_access_token(&self.client, self.config.as_ref()).await?;
                 *write = access_token.into();
+                return Ok(true);
             }
         }
-        Ok(())
+        Ok(false)
     }
-    /// 获取 access_token
+    /// 获取 access_token，配置了 `stable_token` 时获取稳定版 access_token
+    #[tracing::instrument(skip_all, fields(app_id = %wx_config.app_id), err)]
     pub async fn get_access_token(
         client: &reqwest::Client,
         wx_config: &WxConfig,
     ) -> anyhow::Result<AccessToken> {
+        if wx_config.stable_token {
+            return Self::get_stable_access_token(client, wx_config, false).await;
+        }
         #[derive(Serialize)]
         struct GetAccessToken<'a> {
             grant_type: &'a str,
@@ -138,7 +348,32 @@ impl WxClient {
         let result: WxResult<AccessToken> = resp.json().await?;
         result.into()
     }
+    /// 获取稳定版 access_token，`force_refresh` 为 `true` 时使之前的 token 失效并返回新的 token
+    #[tracing::instrument(skip(client, wx_config), fields(app_id = %wx_config.app_id), err)]
+    pub async fn get_stable_access_token(
+        client: &reqwest::Client,
+        wx_config: &WxConfig,
+        force_refresh: bool,
+    ) -> anyhow::Result<AccessToken> {
+        let resp = client
+            .request(
+                Method::POST,
+                "https://api.weixin.qq.com/cgi-bin/stable_token",
+            )
+            .json(&StableTokenReq::new(wx_config, force_refresh))
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxResult<AccessToken> = resp.json().await?;
+        result.into()
+    }
     /// 获取
+    #[tracing::instrument(skip(self, expire_seconds), err)]
     pub async fn get_qrcode_tick_by_id(
         &self,
         expire_seconds: impl Into<Option<u64>>,
@@ -192,6 +427,7 @@ impl WxClient {
     }
 
     /// 获取网页授权 Access Token
+    #[tracing::instrument(skip_all, err)]
     pub async fn get_webpage_authorization_access_token(
         &self,
         code: &str,
@@ -228,12 +464,242 @@ impl WxClient {
         Ok(access_token)
     }
 
+    /// 通过客服消息接口发送消息
+    ///
+    /// 只支持文本、图片、语音、视频消息
+    #[tracing::instrument(skip_all, fields(to_user = %message.to_user_name), err)]
+    pub async fn send_custom_message(&self, message: &WxMessage) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Text<'a> {
+            content: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Media<'a> {
+            media_id: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Video<'a> {
+            media_id: &'a str,
+            thumb_media_id: &'a str,
+        }
+        #[derive(Serialize)]
+        #[serde(tag = "msgtype", rename_all = "lowercase")]
+        enum Body<'a> {
+            Text { text: Text<'a> },
+            Image { image: Media<'a> },
+            Voice { voice: Media<'a> },
+            Video { video: Video<'a> },
+        }
+        #[derive(Serialize)]
+        struct CustomMessage<'a> {
+            touser: &'a str,
+            #[serde(flatten)]
+            body: Body<'a>,
+        }
+
+        let body = match &message.data {
+            WxMessageData::Text { content } => Body::Text {
+                text: Text { content },
+            },
+            WxMessageData::Image { media_id, .. } => Body::Image {
+                image: Media { media_id },
+            },
+            WxMessageData::Voice { media_id, .. } => Body::Voice {
+                voice: Media { media_id },
+            },
+            WxMessageData::Video {
+                media_id,
+                thumb_media_id,
+            } => Body::Video {
+                video: Video {
+                    media_id,
+                    thumb_media_id,
+                },
+            },
+            unsupported => anyhow::bail!("Unsupported custom message: {unsupported:?}"),
+        };
+
+        self.update_access_token().await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::POST,
+                "https://api.weixin.qq.com/cgi-bin/message/custom/send",
+            )
+            .query(&[read.query()])
+            .json(&CustomMessage {
+                touser: &message.to_user_name,
+                body,
+            })
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxStatus = resp.json().await?;
+        result.into()
+    }
+
+    /// 通过客服消息接口发送图文消息，点击后跳转到文章链接
+    ///
+    /// 微信只展示第一篇文章，多余的文章会被忽略
+    #[tracing::instrument(skip(self, articles), err)]
+    pub async fn send_custom_news(
+        &self,
+        to_user: &str,
+        articles: &[WxNewsArticle],
+    ) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct News<'a> {
+            articles: &'a [WxNewsArticle],
+        }
+        #[derive(Serialize)]
+        struct CustomNews<'a> {
+            touser: &'a str,
+            msgtype: &'a str,
+            news: News<'a>,
+        }
+
+        self.update_access_token().await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::POST,
+                "https://api.weixin.qq.com/cgi-bin/message/custom/send",
+            )
+            .query(&[read.query()])
+            .json(&CustomNews {
+                touser: to_user,
+                msgtype: "news",
+                news: News {
+                    articles: &articles[..articles.len().min(1)],
+                },
+            })
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxStatus = resp.json().await?;
+        result.into()
+    }
+
+    /// 发送模板消息
+    pub async fn send_template_message(&self, message: &WxTemplateMessage) -> anyhow::Result<()> {
+        self.update_access_token().await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::POST,
+                "https://api.weixin.qq.com/cgi-bin/message/template/send",
+            )
+            .query(&[read.query()])
+            .json(message)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxStatus = resp.json().await?;
+        result.into()
+    }
+
+    /// 获取永久图文素材，转换为图文消息中的文章
+    #[tracing::instrument(skip(self), err)]
+    pub async fn get_news_material(&self, media_id: &str) -> anyhow::Result<Vec<WxNewsArticle>> {
+        #[derive(Serialize)]
+        struct GetMaterial<'a> {
+            media_id: &'a str,
+        }
+
+        self.update_access_token().await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::POST,
+                "https://api.weixin.qq.com/cgi-bin/material/get_material",
+            )
+            .query(&[read.query()])
+            .json(&GetMaterial { media_id })
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxResult<WxNewsMaterial> = resp.json().await?;
+        let material: anyhow::Result<WxNewsMaterial> = result.into();
+        Ok(material?.into_articles())
+    }
+
     /// 获取用户信息
     pub async fn get_user_info(&self, _access_token: &str) -> anyhow::Result<()> {
         Ok(())
     }
 }
 
+#[async_trait]
+impl WxApi for WxClient {
+    fn config(&self) -> &WxConfig {
+        WxClient::config(self)
+    }
+
+    async fn get_qrcode_tick_by_id(
+        &self,
+        expire_seconds: Option<u64>,
+        limit: bool,
+        scene_id: NonZeroUsize,
+    ) -> anyhow::Result<QrCodeTicket> {
+        WxClient::get_qrcode_tick_by_id(self, expire_seconds, limit, scene_id).await
+    }
+
+    async fn get_webpage_authorization_access_token(
+        &self,
+        code: &str,
+    ) -> anyhow::Result<WxWebpageAccessToken> {
+        WxClient::get_webpage_authorization_access_token(self, code).await
+    }
+
+    async fn send_custom_message(&self, message: &WxMessage) -> anyhow::Result<()> {
+        WxClient::send_custom_message(self, message).await
+    }
+
+    async fn send_custom_news(
+        &self,
+        to_user: &str,
+        articles: &[WxNewsArticle],
+    ) -> anyhow::Result<()> {
+        WxClient::send_custom_news(self, to_user, articles).await
+    }
+
+    async fn send_template_message(&self, message: &WxTemplateMessage) -> anyhow::Result<()> {
+        WxClient::send_template_message(self, message).await
+    }
+
+    async fn get_news_material(&self, media_id: &str) -> anyhow::Result<Vec<WxNewsArticle>> {
+        WxClient::get_news_material(self, media_id).await
+    }
+
+    async fn get_user_info(&self, access_token: &str) -> anyhow::Result<()> {
+        WxClient::get_user_info(self, access_token).await
+    }
+}
+
 /// 微信服务器消息参数
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct WxServerParam<T> {
@@ -252,17 +718,11 @@ pub struct WxServerParam<T> {
 }
 
 impl<T> WxServerParam<T> {
-    /// 判断签名是否合法
+    /// 判断签名是否合法，以常量时间比较
     pub fn is_signature_valid(&self, token: &str) -> bool {
-        let mut array = [token, self.timestamp.as_str(), self.nonce.as_str()];
-        array.sort();
-        let mut hasher = Sha1::default();
-        for s in array {
-            hasher.update(s);
-        }
-        let result = hasher.finalize();
-        let calculated_signature = hex::encode(result);
-        calculated_signature == self.signature
+        let calculated_signature =
+            sha1_signature(&mut [token, self.timestamp.as_str(), self.nonce.as_str()]);
+        constant_time_eq(&calculated_signature, &self.signature)
     }
 }
 
@@ -305,6 +765,14 @@ impl Serialize for WxMessageType {
     }
 }
 
+/// 只解析接收方的 XML 消息，用于在验证签名和解密前选择公众号
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase", rename = "xml")]
+pub struct WxXmlRecipient {
+    /// 接收方，即公众号的原始 ID
+    pub to_user_name: String,
+}
+
 /// 原始加密过的 XMl 消息
 #[allow(missing_docs)]
 #[derive(Debug, Serialize, Deserialize)]
@@ -315,47 +783,24 @@ pub struct WxEncryptedRawXmlMessage {
 }
 
 impl WxEncryptedRawXmlMessage {
-    /// 使用 AES256 解密
-    ///
-    /// 返回：from_app_id 和 消息
-    pub fn aes_decrypt(
-        &self,
-        encoding_aes_key: &WxEncodingAesKey,
-    ) -> anyhow::Result<(String, WxMessage)> {
-        use aes::cipher::KeyIvInit;
-        use aes::cipher::{block_padding::NoPadding, BlockDecryptMut};
-        type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
-
-        let mut decrypted = base64::engine::general_purpose::STANDARD.decode(&self.encrypt)?;
-        let mut iv = [0u8; 16];
-        iv.copy_from_slice(&encoding_aes_key.data[0..16]);
-        let data = encoding_aes_key.data;
-        let pt = Aes256CbcDec::new(&data.into(), &iv.into())
-            .decrypt_padded_mut::<NoPadding>(&mut decrypted)
-            .map_err(|e| anyhow::anyhow!("Failed to decrypted data: {e}"))?;
-        let mut pad = pt[pt.len() - 1] as usize;
-        if !(1..=32).contains(&pad) {
-            pad = 0;
-        }
-        let no_padding = &pt[0..pt.len() - pad];
-        let no_padding_len = no_padding.len();
-        if no_padding_len < 20 {
-            anyhow::bail!("Data length is less than 20");
-        }
-        let xml_len = match &no_padding[16..20] {
-            [b0, b1, b2, b3] => u32::from_be_bytes([*b0, *b1, *b2, *b3]),
-            _ => anyhow::bail!("impossible: slice[16..20] length is not 4"),
-        } as usize;
-        if 20 + xml_len > no_padding_len {
-            anyhow::bail!("Not enough data: xml_len={xml_len}, no_padding_len={no_padding_len}");
-        }
-        let xml_content = std::str::from_utf8(&no_padding[20..20 + xml_len])?;
-        let from_appid = std::str::from_utf8(&no_padding[20 + xml_len..no_padding.len()])?;
-        let raw = serde_xml_rs::from_str::<WxRawXmlMessage>(xml_content)?;
-        Ok((from_appid.to_string(), WxMessage::try_from(raw)?))
+    /// 解密，校验接收方为 `crypto` 配置的 AppID
+    pub fn decrypt(&self, crypto: &WxCrypto) -> anyhow::Result<WxMessage> {
+        let envelope = crypto.decrypt(&self.encrypt)?;
+        let raw = quick_xml::de::from_str::<WxRawXmlMessage>(&envelope.message)?;
+        WxMessage::try_from(raw)
     }
 }
 
+/// 将参数排序后拼接计算 SHA1 签名
+pub(crate) fn sha1_signature(parts: &mut [&str]) -> String {
+    parts.sort();
+    let mut hasher = Sha1::default();
+    for s in parts.iter() {
+        hasher.update(s);
+    }
+    hex::encode(hasher.finalize())
+}
+
 /// 原始 XMl 消息
 #[allow(missing_docs)]
 #[derive(Debug, Serialize, Deserialize)]
@@ -363,7 +808,8 @@ impl WxEncryptedRawXmlMessage {
 pub struct WxRawXmlMessage {
     pub to_user_name: String,
     pub from_user_name: String,
-    pub create_time: i32,
+    #[serde(with = "time::serde::timestamp")]
+    pub create_time: OffsetDateTime,
     pub msg_type: WxMessageType,
     pub content: Option<String>,
     pub pic_url: Option<String>,
@@ -380,14 +826,14 @@ pub struct WxRawXmlMessage {
 }
 
 /// 消息
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WxMessage {
     /// 接收方微信号
     pub to_user_name: String,
     /// 发送方微信号，若为普通用户，则是一个OpenID
     pub from_user_name: String,
-    /// 消息创建时间
-    pub create_time: i32,
+    /// 消息创建时间，XML 中为秒级时间戳
+    pub create_time: OffsetDateTime,
     /// 消息数据
     pub data: WxMessageData,
     /// 消息id，64位整型
@@ -398,8 +844,35 @@ pub struct WxMessage {
     pub idx: Option<String>,
 }
 
+/// 模板消息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WxTemplateMessage {
+    /// 接收者的 OpenID
+    pub touser: String,
+    /// 模板 ID
+    pub template_id: String,
+    /// 点击模板消息后跳转的链接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// 模板数据，键为模板中的变量名
+    pub data: BTreeMap<String, WxTemplateValue>,
+}
+
+/// 模板变量的值
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WxTemplateValue {
+    /// 值
+    pub value: String,
+}
+
+impl From<String> for WxTemplateValue {
+    fn from(value: String) -> Self {
+        Self { value }
+    }
+}
+
 /// 消息数据
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum WxMessageData {
     /// 文本
     Text {
@@ -444,7 +917,7 @@ pub enum WxMessageData {
 }
 
 /// 微信事件类型
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum WxEventType {
     /// 订阅
     Subscribe,
@@ -482,7 +955,7 @@ impl Display for WxEventType {
 }
 
 /// 微信事件数据
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WxEvent {
     /// 事件类型
     pub event: WxEventType,
@@ -492,6 +965,27 @@ pub struct WxEvent {
     pub ticket: Option<String>,
 }
 
+impl WxMessage {
+    /// 消息去重使用的 key
+    ///
+    /// 普通消息使用 MsgId 排重，事件消息没有 MsgId，使用 FromUserName + CreateTime 排重
+    pub fn dedup_key(&self) -> String {
+        match self.msg_id {
+            Some(msg_id) => format!(
+                "wx:msg:{}:{}:{}",
+                self.from_user_name,
+                self.create_time.unix_timestamp(),
+                msg_id
+            ),
+            None => format!(
+                "wx:msg:{}:{}",
+                self.from_user_name,
+                self.create_time.unix_timestamp()
+            ),
+        }
+    }
+}
+
 impl From<WxMessage> for WxRawXmlMessage {
     fn from(msg: WxMessage) -> Self {
         let WxMessage {
@@ -712,7 +1206,12 @@ impl FromStr for WxEncodingAesKey {
         if s.len() != 43 {
             anyhow::bail!("The base64 string length is not 43");
         }
-        let decoded = base64::engine::general_purpose::STANDARD_NO_PAD
+        // 官方 SDK 在末尾补 `=` 后宽松解码，最后一个字符的低位可能不为 0
+        const ENGINE: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
+            &base64::alphabet::STANDARD,
+            base64::engine::general_purpose::NO_PAD.with_decode_allow_trailing_bits(true),
+        );
+        let decoded = ENGINE
             .decode(s)
             .map_err(|e| anyhow::anyhow!("Failed to decode base64 string: {e}"))?;
         let len = decoded.len();
@@ -783,6 +1282,26 @@ pub struct AccessToken {
     expires_in: u64,
 }
 
+/// 获取稳定版 access_token 的请求
+#[derive(Debug, Serialize)]
+struct StableTokenReq<'a> {
+    grant_type: &'a str,
+    appid: &'a str,
+    secret: &'a str,
+    force_refresh: bool,
+}
+
+impl<'a> StableTokenReq<'a> {
+    fn new(wx_config: &'a WxConfig, force_refresh: bool) -> Self {
+        Self {
+            grant_type: "client_credential",
+            appid: &wx_config.app_id,
+            secret: &wx_config.app_secret,
+            force_refresh,
+        }
+    }
+}
+
 /// 二维码结果
 #[derive(Debug, Deserialize)]
 pub struct QrCodeTicket {
@@ -821,7 +1340,11 @@ impl WxAccessToken {
     }
     /// 判断是否过期
     pub fn expired(&self) -> bool {
-        self.timestamp + self.token.expires_in <= current_second()
+        self.expires_within(0)
+    }
+    /// 判断是否会在 `secs` 秒内过期
+    pub fn expires_within(&self, secs: u64) -> bool {
+        self.timestamp + self.token.expires_in <= current_second() + secs
     }
 }
 
@@ -860,6 +1383,79 @@ impl<T> From<WxResult<T>> for anyhow::Result<T> {
     }
 }
 
+/// 微信接口返回的状态，用于没有返回数据的接口
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WxStatus {
+    /// 错误码，0 表示成功
+    pub errcode: u64,
+    /// 错误消息
+    pub errmsg: String,
+}
+
+impl From<WxStatus> for anyhow::Result<()> {
+    fn from(value: WxStatus) -> Self {
+        if value.errcode == 0 {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Weixin server responded with error: {} {}",
+                value.errcode,
+                value.errmsg
+            ))
+        }
+    }
+}
+
+/// 图文消息中的文章
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WxNewsArticle {
+    /// 标题
+    pub title: String,
+    /// 摘要
+    pub description: String,
+    /// 点击后跳转的链接
+    pub url: String,
+    /// 封面图片链接
+    #[serde(rename = "picurl")]
+    pub pic_url: Option<String>,
+}
+
+/// 永久图文素材
+#[derive(Debug, Deserialize)]
+pub struct WxNewsMaterial {
+    /// 多篇文章
+    pub news_item: Vec<WxNewsItem>,
+}
+
+/// 永久图文素材中的文章
+#[derive(Debug, Deserialize)]
+pub struct WxNewsItem {
+    /// 标题
+    pub title: String,
+    /// 摘要
+    #[serde(default)]
+    pub digest: String,
+    /// 文章链接
+    pub url: String,
+    /// 封面图片链接
+    pub thumb_url: Option<String>,
+}
+
+impl WxNewsMaterial {
+    /// 转换为图文消息中的文章
+    pub fn into_articles(self) -> Vec<WxNewsArticle> {
+        self.news_item
+            .into_iter()
+            .map(|item| WxNewsArticle {
+                title: item.title,
+                description: item.digest,
+                url: item.url,
+                pic_url: item.thumb_url,
+            })
+            .collect()
+    }
+}
+
 /// 网页授权access_token
 #[derive(Debug, Deserialize)]
 pub struct WxWebpageAccessToken {
@@ -883,7 +1479,85 @@ pub struct WxWebpageAccessToken {
 
 #[cfg(test)]
 mod tests {
-    use crate::weixin::{AccessToken, WxResult};
+    use std::sync::Arc;
+
+    use time::macros::datetime;
+
+    use crate::testing::MockWxClient;
+    use crate::weixin::{
+        xml, AccessToken, DynWxApi, StableTokenReq, WxClientRegistry, WxConfig, WxConfigs, WxEvent,
+        WxEventType, WxMessage, WxMessageData, WxNewsArticle, WxNewsMaterial, WxRawXmlMessage,
+        WxResult, WxStatus,
+    };
+
+    #[test]
+    fn registry() -> anyhow::Result<()> {
+        let first: DynWxApi = Arc::new(MockWxClient::new("first"));
+        let second: DynWxApi = Arc::new(MockWxClient::new("second"));
+        assert!(WxClientRegistry::new(vec![]).is_err());
+        assert!(WxClientRegistry::new(vec![first.clone(), first.clone()]).is_err());
+
+        let registry = WxClientRegistry::new(vec![first.clone(), second])?;
+        assert_eq!(registry.default_client().app_id(), "first");
+        assert_eq!(registry.get("second").map(|c| c.app_id()), Some("second"));
+        assert!(registry.get("third").is_none());
+        assert_eq!(
+            registry.route("gh_second").map(|c| c.app_id()),
+            Some("second")
+        );
+        assert!(registry.route("gh_third").is_none());
+
+        let single = WxClientRegistry::from(first);
+        assert_eq!(single.route("gh_third").map(|c| c.app_id()), Some("first"));
+        Ok(())
+    }
+
+    #[test]
+    fn configs() -> anyhow::Result<()> {
+        const ACCOUNT: &str = r#"
+            callback_url = "http://localhost:8080"
+            app_secret = "secret"
+            token = "token"
+            encoding_aes_key = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8"
+        "#;
+        #[derive(serde::Deserialize)]
+        struct Config {
+            wx: WxConfigs,
+        }
+        fn parse(toml: &str) -> anyhow::Result<Vec<WxConfig>> {
+            let config: Config = config::Config::builder()
+                .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+                .build()?
+                .try_deserialize()?;
+            Ok(config.wx.into())
+        }
+
+        let one = parse(&format!("[wx]\napp_id = \"first\"\n{ACCOUNT}"))?;
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0].original_id, None);
+        let many = parse(&format!(
+            "[[wx]]\napp_id = \"first\"\n{ACCOUNT}\n[[wx]]\napp_id = \"second\"\noriginal_id = \"gh_second\"\n{ACCOUNT}"
+        ))?;
+        assert_eq!(many.len(), 2);
+        assert_eq!(many[1].app_id, "second");
+        assert_eq!(many[1].original_id.as_deref(), Some("gh_second"));
+        assert!(!many[1].stable_token);
+
+        let stable = parse(&format!(
+            "[wx]\napp_id = \"first\"\nstable_token = true\n{ACCOUNT}"
+        ))?;
+        assert!(stable[0].stable_token);
+        assert_eq!(
+            serde_json::to_value(StableTokenReq::new(&stable[0], true))?,
+            serde_json::json!({
+                "grant_type": "client_credential",
+                "appid": "first",
+                "secret": "secret",
+                "force_refresh": true,
+            })
+        );
+        Ok(())
+    }
 
     #[test]
     fn wx_result() -> anyhow::Result<()> {
@@ -895,4 +1569,151 @@ mod tests {
         println!("{:?}\n{:?}", success, error);
         Ok(())
     }
+
+    #[test]
+    fn dedup_key() {
+        let mut message = WxMessage {
+            to_user_name: "to".to_string(),
+            from_user_name: "from".to_string(),
+            create_time: datetime!(2012-09-28 11:31:00 UTC),
+            data: WxMessageData::Text {
+                content: "this is a test".to_string(),
+            },
+            msg_id: Some(1234567890123456),
+            msg_data_id: None,
+            idx: None,
+        };
+        assert_eq!(
+            message.dedup_key(),
+            "wx:msg:from:1348831860:1234567890123456"
+        );
+        message.msg_id = None;
+        assert_eq!(message.dedup_key(), "wx:msg:from:1348831860");
+    }
+
+    #[test]
+    fn news_material() -> anyhow::Result<()> {
+        let material = r#"{"news_item":[{"title":"欢迎","thumb_media_id":"THUMB","show_cover_pic":1,
+            "author":"","digest":"抹茶聊天","content":"<p>正文</p>","url":"https://mp.weixin.qq.com/s/1",
+            "content_source_url":"","thumb_url":"https://mmbiz.qpic.cn/1"}]}"#;
+        let result = serde_json::from_str::<WxResult<WxNewsMaterial>>(material)?;
+        let material: anyhow::Result<WxNewsMaterial> = result.into();
+        assert_eq!(
+            material?.into_articles(),
+            [WxNewsArticle {
+                title: "欢迎".to_string(),
+                description: "抹茶聊天".to_string(),
+                url: "https://mp.weixin.qq.com/s/1".to_string(),
+                pic_url: Some("https://mmbiz.qpic.cn/1".to_string()),
+            }]
+        );
+
+        let error = r#"{"errcode":40007,"errmsg":"invalid media_id"}"#;
+        let result = serde_json::from_str::<WxResult<WxNewsMaterial>>(error)?;
+        assert!(anyhow::Result::<WxNewsMaterial>::from(result).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wx_status() -> anyhow::Result<()> {
+        let ok = serde_json::from_str::<WxStatus>(r#"{"errcode":0,"errmsg":"ok"}"#)?;
+        let error = serde_json::from_str::<WxStatus>(
+            r#"{"errcode":45015,"errmsg":"response out of time limit"}"#,
+        )?;
+        assert!(anyhow::Result::<()>::from(ok).is_ok());
+        assert!(anyhow::Result::<()>::from(error).is_err());
+        Ok(())
+    }
+
+    fn message(data: WxMessageData) -> WxMessage {
+        WxMessage {
+            to_user_name: "gh_to".to_string(),
+            from_user_name: "from".to_string(),
+            create_time: datetime!(2012-09-28 11:31:00 UTC),
+            data,
+            msg_id: Some(1234567890123456),
+            msg_data_id: None,
+            idx: None,
+        }
+    }
+
+    #[test]
+    fn xml_round_trip() -> anyhow::Result<()> {
+        let messages = [
+            message(WxMessageData::Text {
+                content: "<hello> & ]]> world".to_string(),
+            }),
+            message(WxMessageData::Image {
+                pic_url: "https://example.com/a.png?a=1&b=2".to_string(),
+                media_id: "media".to_string(),
+            }),
+            message(WxMessageData::Video {
+                media_id: "media".to_string(),
+                thumb_media_id: "thumb".to_string(),
+            }),
+            message(WxMessageData::Voice {
+                media_id: "media".to_string(),
+                format: "amr".to_string(),
+                recognition: Some("你好".to_string()),
+            }),
+            message(WxMessageData::ShortVideo {
+                media_id: "media".to_string(),
+                thumb_media_id: "thumb".to_string(),
+            }),
+            message(WxMessageData::Event {
+                event: WxEvent {
+                    event: WxEventType::Scan,
+                    event_key: Some("123".to_string()),
+                    ticket: Some("ticket".to_string()),
+                },
+            }),
+        ];
+        for message in messages {
+            let expected = format!("{message:?}");
+            let xml = xml::to_string(&WxRawXmlMessage::from(message))?;
+            assert!(xml.starts_with("<xml><ToUserName><![CDATA[gh_to]]></ToUserName>"));
+            assert!(xml.contains("<CreateTime>1348831860</CreateTime>"), "{xml}");
+            let raw = quick_xml::de::from_str::<WxRawXmlMessage>(&xml)?;
+            let actual = WxMessage::try_from(raw)?;
+            // 事件消息没有 MsgId
+            let expected = match actual.data {
+                WxMessageData::Event { .. } => expected.replace("Some(1234567890123456)", "None"),
+                _ => expected,
+            };
+            assert_eq!(format!("{actual:?}"), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_cdata() -> anyhow::Result<()> {
+        let xml = r#"<xml>
+            <ToUserName><![CDATA[toUser]]></ToUserName>
+            <FromUserName><![CDATA[FromUser]]></FromUserName>
+            <CreateTime>123456789</CreateTime>
+            <MsgType><![CDATA[event]]></MsgType>
+            <Event><![CDATA[subscribe]]></Event>
+            <EventKey><![CDATA[qrscene_123123]]></EventKey>
+            <Ticket><![CDATA[TICKET]]></Ticket>
+        </xml>"#;
+        let message = WxMessage::try_from(quick_xml::de::from_str::<WxRawXmlMessage>(xml)?)?;
+        assert_eq!(message.to_user_name, "toUser");
+        assert_eq!(message.create_time.unix_timestamp(), 123456789);
+        assert!(matches!(
+            message.data,
+            WxMessageData::Event {
+                event: WxEvent {
+                    event: WxEventType::Subscribe,
+                    event_key: Some(key),
+                    ..
+                }
+            } if key == "qrscene_123123"
+        ));
+
+        // 2038 年之后的时间戳
+        let xml = xml.replace("123456789", "4102444800");
+        let message = WxMessage::try_from(quick_xml::de::from_str::<WxRawXmlMessage>(&xml)?)?;
+        assert_eq!(message.create_time, datetime!(2100-01-01 00:00 UTC));
+        Ok(())
+    }
 }
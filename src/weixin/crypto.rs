@@ -0,0 +1,216 @@
+//! # 消息加解密
+//!
+//! 公众号的安全模式与企业微信的回调使用相同的方案：
+//!
+//! - 明文为 16 字节随机数、4 字节网络字节序的消息长度、消息、接收方 ID（公众号为 AppID，企业微信为企业 ID），
+//!   按 PKCS#7 填充到 32 字节的整数倍；
+//! - 使用 AES-256-CBC 加密后 base64 编码，密钥为 EncodingAESKey 解码后的 32 字节，IV 为密钥的前 16 字节；
+//! - 签名为令牌、时间戳、随机数和密文按字典序排序后拼接的 SHA1，明文模式的签名不包括密文。
+//!
+//! 解密时严格校验填充和接收方 ID，签名使用常量时间比较。
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::Engine;
+use subtle::ConstantTimeEq;
+
+use crate::weixin::{sha1_signature, WxEncodingAesKey};
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+
+/// PKCS#7 填充的块大小
+const PADDING_BLOCK_SIZE: usize = 32;
+
+/// 消息加解密
+#[derive(Debug, Clone, Copy)]
+pub struct WxCrypto<'a> {
+    token: &'a str,
+    key: &'a WxEncodingAesKey,
+    receive_id: &'a str,
+}
+
+/// 解密后的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WxEnvelope {
+    /// 随机数
+    pub random: [u8; 16],
+    /// 消息明文
+    pub message: String,
+    /// 接收方 ID，已校验与配置的相同
+    pub receive_id: String,
+}
+
+impl<'a> WxCrypto<'a> {
+    /// 使用令牌、加解密密钥和接收方 ID 构造，公众号的接收方 ID 为 AppID，企业微信为企业 ID
+    pub fn new(token: &'a str, key: &'a WxEncodingAesKey, receive_id: &'a str) -> Self {
+        Self {
+            token,
+            key,
+            receive_id,
+        }
+    }
+
+    /// 计算签名，明文模式的签名没有密文
+    pub fn signature(&self, timestamp: &str, nonce: &str, encrypt: Option<&str>) -> String {
+        match encrypt {
+            Some(encrypt) => sha1_signature(&mut [self.token, timestamp, nonce, encrypt]),
+            None => sha1_signature(&mut [self.token, timestamp, nonce]),
+        }
+    }
+
+    /// 以常量时间比较签名
+    pub fn verify_signature(
+        &self,
+        signature: &str,
+        timestamp: &str,
+        nonce: &str,
+        encrypt: Option<&str>,
+    ) -> bool {
+        constant_time_eq(&self.signature(timestamp, nonce, encrypt), signature)
+    }
+
+    /// 解密，填充不合法或接收方 ID 与配置的不同时返回错误
+    pub fn decrypt(&self, encrypt: &str) -> anyhow::Result<WxEnvelope> {
+        let mut data = base64::engine::general_purpose::STANDARD.decode(encrypt)?;
+        let decrypted = self
+            .decryptor()
+            .decrypt_padded_mut::<NoPadding>(&mut data)
+            .map_err(|e| anyhow::anyhow!("Failed to decrypt data: {e}"))?;
+        let plain = pkcs7_unpad(decrypted)?;
+        if plain.len() < 20 {
+            anyhow::bail!("Data length is less than 20");
+        }
+        let (random, rest) = plain.split_at(16);
+        let (len, rest) = rest.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if len > rest.len() {
+            anyhow::bail!(
+                "Not enough data: message_len={len}, data_len={}",
+                rest.len()
+            );
+        }
+        let (message, receive_id) = rest.split_at(len);
+        let receive_id = std::str::from_utf8(receive_id)?;
+        if receive_id != self.receive_id {
+            anyhow::bail!("Message is not sent to {}: {receive_id}", self.receive_id);
+        }
+        Ok(WxEnvelope {
+            random: random.try_into()?,
+            message: std::str::from_utf8(message)?.to_string(),
+            receive_id: receive_id.to_string(),
+        })
+    }
+
+    /// 加密，`random` 为 16 字节随机数
+    pub fn encrypt(&self, message: &str, random: [u8; 16]) -> String {
+        let mut plain = Vec::with_capacity(20 + message.len() + self.receive_id.len() + 32);
+        plain.extend_from_slice(&random);
+        plain.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        plain.extend_from_slice(message.as_bytes());
+        plain.extend_from_slice(self.receive_id.as_bytes());
+        let pad = PADDING_BLOCK_SIZE - plain.len() % PADDING_BLOCK_SIZE;
+        plain.resize(plain.len() + pad, pad as u8);
+        let len = plain.len();
+        let (key, iv) = self.key_iv();
+        let encrypted = Aes256CbcEnc::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<NoPadding>(&mut plain, len)
+            .expect("plain text is padded to the block size");
+        base64::engine::general_purpose::STANDARD.encode(encrypted)
+    }
+
+    fn decryptor(&self) -> Aes256CbcDec {
+        let (key, iv) = self.key_iv();
+        Aes256CbcDec::new(&key.into(), &iv.into())
+    }
+
+    fn key_iv(&self) -> ([u8; 32], [u8; 16]) {
+        let key = self.key.data;
+        let mut iv = [0u8; 16];
+        iv.copy_from_slice(&key[0..16]);
+        (key, iv)
+    }
+}
+
+/// 以常量时间比较两个字符串，长度不同时直接返回 `false`
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// 去掉 PKCS#7 填充，填充长度不在 1..=32 或填充字节不一致时返回错误
+fn pkcs7_unpad(data: &[u8]) -> anyhow::Result<&[u8]> {
+    let Some(&pad) = data.last() else {
+        anyhow::bail!("Empty data");
+    };
+    let pad = pad as usize;
+    if !(1..=PADDING_BLOCK_SIZE).contains(&pad) || pad > data.len() {
+        anyhow::bail!("Invalid padding length: {pad}");
+    }
+    let (plain, padding) = data.split_at(data.len() - pad);
+    if padding.iter().any(|&byte| byte as usize != pad) {
+        anyhow::bail!("Invalid padding");
+    }
+    Ok(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::weixin::crypto::{pkcs7_unpad, WxCrypto};
+    use crate::weixin::WxEncodingAesKey;
+
+    /// 企业微信官方文档「加解密方案说明」中验证回调 URL 的示例
+    #[test]
+    fn official_vector() -> anyhow::Result<()> {
+        let key: WxEncodingAesKey = "jWmYm7qr5nMoAUwZRjGtBxmz3KA1tkAj3ykkR6q2B2C".parse()?;
+        let crypto = WxCrypto::new("QDG6eK", &key, "wx5823bf96d3bd56c7");
+        let echostr = "P9nAzCzyDtyTWESHep1vC5X9xho/qYX3Zpb4yKa9SKld1DsH3Iyt3tP3zNdtp+4RPcs8TgAE7OaBO+FZXvnaqQ==";
+        assert!(crypto.verify_signature(
+            "5c45ff5e21c57e6ad56bac8758b79b1d9ac89fd3",
+            "1409659589",
+            "263014780",
+            Some(echostr),
+        ));
+        assert!(!crypto.verify_signature(
+            "5c45ff5e21c57e6ad56bac8758b79b1d9ac89fd4",
+            "1409659589",
+            "263014780",
+            Some(echostr),
+        ));
+
+        let envelope = crypto.decrypt(echostr)?;
+        assert_eq!(envelope.message, "1616140317555161061");
+        assert_eq!(envelope.receive_id, "wx5823bf96d3bd56c7");
+        assert_eq!(&envelope.random, b"c41b64491c2468d0");
+        assert_eq!(crypto.encrypt(&envelope.message, envelope.random), echostr);
+
+        let other = WxCrypto::new("QDG6eK", &key, "wx0000000000000000");
+        assert!(other.decrypt(echostr).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> anyhow::Result<()> {
+        let key: WxEncodingAesKey = "abcdefghijklmnopqrstuvwxyz0123456789ABCDEFG".parse()?;
+        let crypto = WxCrypto::new("pamtest", &key, "wxb11529c136998cb6");
+        for message in ["", "<xml><Content><![CDATA[你好]]></Content></xml>"] {
+            let encrypt = crypto.encrypt(message, [7; 16]);
+            assert_eq!(crypto.decrypt(&encrypt)?.message, message);
+        }
+        assert!(crypto.verify_signature(
+            "76480565cbe296026c53aaacd1ad523a1ddba24f",
+            "1409304348",
+            "xxxxxx",
+            None,
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(pkcs7_unpad(&[1, 2, 3, 2, 2]).ok(), Some(&[1, 2, 3][..]));
+        assert!(pkcs7_unpad(&[1, 2, 3, 1, 2]).is_err());
+        assert!(pkcs7_unpad(&[1, 2, 0]).is_err());
+        assert!(pkcs7_unpad(&[1, 33]).is_err());
+        assert!(pkcs7_unpad(&[]).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! # 被动回复
+//!
+//! 收到公众号消息后在响应中直接回复，使用 [`Xml`](crate::weixin::xml::Xml) 返回：
+//!
+//! ```
+//! # use mallchat::weixin::reply::WxReply;
+//! # use mallchat::weixin::xml::Xml;
+//! // 收到的消息由 oUser 发送给公众号 gh_mallchat，回复时交换收发方
+//! let reply = WxReply::text("gh_mallchat", "oUser", "登录成功");
+//! assert_eq!(reply.to_user_name, "oUser");
+//! let response = Xml(reply);
+//! ```
+//!
+//! 来不及被动回复时（超过微信服务器的等待时间）可以使用 [`WxReply::send_custom`] 改用客服消息接口发送。
+
+use serde::{Serialize, Serializer};
+use time::OffsetDateTime;
+
+use crate::weixin::{WxApi, WxMessage, WxMessageData, WxNewsArticle};
+
+/// 被动回复
+#[derive(Debug, Clone, PartialEq)]
+pub struct WxReply {
+    /// 接收方，收到的消息的发送方
+    pub to_user_name: String,
+    /// 发送方，收到的消息的接收方
+    pub from_user_name: String,
+    /// 回复时间
+    pub create_time: OffsetDateTime,
+    /// 回复内容
+    pub data: WxReplyData,
+}
+
+/// 被动回复的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WxReplyData {
+    /// 文本
+    Text {
+        /// 文本内容，可以包含链接
+        content: String,
+    },
+    /// 图片
+    Image {
+        /// 上传多媒体文件得到的媒体 ID
+        media_id: String,
+    },
+    /// 图文，最多 8 篇；回复用户发送的普通消息时只能回复 1 篇
+    News {
+        /// 文章
+        articles: Vec<WxNewsArticle>,
+    },
+    /// 转发到客服系统
+    TransferCustomerService {
+        /// 指定的客服账号，为空时由客服系统分配
+        kf_account: Option<String>,
+    },
+}
+
+impl WxReply {
+    /// 回复收到的消息，`to`、`from` 为收到的消息的接收方（公众号）和发送方（用户），回复时交换
+    pub fn new(to: impl Into<String>, from: impl Into<String>, data: WxReplyData) -> Self {
+        Self {
+            to_user_name: from.into(),
+            from_user_name: to.into(),
+            create_time: OffsetDateTime::now_utc(),
+            data,
+        }
+    }
+
+    /// 回复收到的消息，交换收发方
+    pub fn reply_to(message: &WxMessage, data: WxReplyData) -> Self {
+        Self::new(&message.to_user_name, &message.from_user_name, data)
+    }
+
+    /// 文本回复
+    pub fn text(
+        to: impl Into<String>,
+        from: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        Self::new(
+            to,
+            from,
+            WxReplyData::Text {
+                content: content.into(),
+            },
+        )
+    }
+
+    /// 图片回复
+    pub fn image(
+        to: impl Into<String>,
+        from: impl Into<String>,
+        media_id: impl Into<String>,
+    ) -> Self {
+        Self::new(
+            to,
+            from,
+            WxReplyData::Image {
+                media_id: media_id.into(),
+            },
+        )
+    }
+
+    /// 图文回复
+    pub fn news(
+        to: impl Into<String>,
+        from: impl Into<String>,
+        articles: Vec<WxNewsArticle>,
+    ) -> Self {
+        Self::new(to, from, WxReplyData::News { articles })
+    }
+
+    /// 转发到客服系统，`kf_account` 为空时由客服系统分配
+    pub fn transfer_customer_service(
+        to: impl Into<String>,
+        from: impl Into<String>,
+        kf_account: Option<String>,
+    ) -> Self {
+        Self::new(
+            to,
+            from,
+            WxReplyData::TransferCustomerService { kf_account },
+        )
+    }
+
+    /// 通过客服消息接口发送，转发到客服系统只能被动回复
+    pub async fn send_custom(&self, wx_client: &dyn WxApi) -> anyhow::Result<()> {
+        let data = match &self.data {
+            WxReplyData::Text { content } => WxMessageData::Text {
+                content: content.clone(),
+            },
+            WxReplyData::Image { media_id } => WxMessageData::Image {
+                pic_url: String::new(),
+                media_id: media_id.clone(),
+            },
+            WxReplyData::News { articles } => {
+                return wx_client
+                    .send_custom_news(&self.to_user_name, articles)
+                    .await;
+            }
+            WxReplyData::TransferCustomerService { .. } => {
+                anyhow::bail!("Transferring to customer service is only available as a reply")
+            }
+        };
+        let message = WxMessage {
+            to_user_name: self.to_user_name.clone(),
+            from_user_name: self.from_user_name.clone(),
+            create_time: self.create_time,
+            data,
+            msg_id: None,
+            msg_data_id: None,
+            idx: None,
+        };
+        wx_client.send_custom_message(&message).await
+    }
+}
+
+/// 被动回复的 XML 格式
+#[derive(Serialize)]
+#[serde(rename = "xml", rename_all = "PascalCase")]
+struct RawReply<'a> {
+    to_user_name: &'a str,
+    from_user_name: &'a str,
+    create_time: i64,
+    msg_type: &'static str,
+    content: Option<&'a str>,
+    image: Option<Media<'a>>,
+    article_count: Option<usize>,
+    articles: Option<Vec<Article<'a>>>,
+    trans_info: Option<TransInfo<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Media<'a> {
+    media_id: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Article<'a> {
+    title: &'a str,
+    description: &'a str,
+    pic_url: Option<&'a str>,
+    url: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct TransInfo<'a> {
+    kf_account: &'a str,
+}
+
+impl Serialize for WxReply {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut raw = RawReply {
+            to_user_name: &self.to_user_name,
+            from_user_name: &self.from_user_name,
+            create_time: self.create_time.unix_timestamp(),
+            msg_type: "text",
+            content: None,
+            image: None,
+            article_count: None,
+            articles: None,
+            trans_info: None,
+        };
+        match &self.data {
+            WxReplyData::Text { content } => raw.content = Some(content),
+            WxReplyData::Image { media_id } => {
+                raw.msg_type = "image";
+                raw.image = Some(Media { media_id });
+            }
+            WxReplyData::News { articles } => {
+                raw.msg_type = "news";
+                raw.article_count = Some(articles.len());
+                raw.articles = Some(
+                    articles
+                        .iter()
+                        .map(|article| Article {
+                            title: &article.title,
+                            description: &article.description,
+                            pic_url: article.pic_url.as_deref(),
+                            url: &article.url,
+                        })
+                        .collect(),
+                );
+            }
+            WxReplyData::TransferCustomerService { kf_account } => {
+                raw.msg_type = "transfer_customer_service";
+                raw.trans_info = kf_account
+                    .as_deref()
+                    .map(|kf_account| TransInfo { kf_account });
+            }
+        }
+        raw.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::testing::MockWxClient;
+    use crate::weixin::reply::{WxReply, WxReplyData};
+    use crate::weixin::xml::to_string;
+    use crate::weixin::{WxMessageData, WxNewsArticle};
+
+    fn at_epoch(mut reply: WxReply) -> WxReply {
+        reply.create_time = datetime!(2012-09-28 11:31:00 UTC);
+        reply
+    }
+
+    #[test]
+    fn xml() -> anyhow::Result<()> {
+        const HEAD: &str = "<xml><ToUserName><![CDATA[oUser]]></ToUserName>\
+            <FromUserName><![CDATA[gh_mallchat]]></FromUserName>\
+            <CreateTime>1348831860</CreateTime>";
+
+        let reply = at_epoch(WxReply::text("gh_mallchat", "oUser", "你好"));
+        assert_eq!(
+            to_string(&reply)?,
+            format!("{HEAD}<MsgType><![CDATA[text]]></MsgType><Content><![CDATA[你好]]></Content></xml>")
+        );
+
+        let reply = at_epoch(WxReply::image("gh_mallchat", "oUser", "MEDIA"));
+        assert_eq!(
+            to_string(&reply)?,
+            format!(
+                "{HEAD}<MsgType><![CDATA[image]]></MsgType>\
+                <Image><MediaId><![CDATA[MEDIA]]></MediaId></Image></xml>"
+            )
+        );
+
+        let article = WxNewsArticle {
+            title: "欢迎".to_string(),
+            description: "抹茶聊天".to_string(),
+            url: "https://mp.weixin.qq.com/s/1".to_string(),
+            pic_url: None,
+        };
+        let reply = at_epoch(WxReply::news("gh_mallchat", "oUser", vec![article]));
+        assert_eq!(
+            to_string(&reply)?,
+            format!(
+                "{HEAD}<MsgType><![CDATA[news]]></MsgType><ArticleCount>1</ArticleCount>\
+                <Articles><item><Title><![CDATA[欢迎]]></Title>\
+                <Description><![CDATA[抹茶聊天]]></Description>\
+                <Url><![CDATA[https://mp.weixin.qq.com/s/1]]></Url></item></Articles></xml>"
+            )
+        );
+
+        let reply = at_epoch(WxReply::transfer_customer_service(
+            "gh_mallchat",
+            "oUser",
+            None,
+        ));
+        assert_eq!(
+            to_string(&reply)?,
+            format!("{HEAD}<MsgType><![CDATA[transfer_customer_service]]></MsgType></xml>")
+        );
+        let reply = at_epoch(WxReply::transfer_customer_service(
+            "gh_mallchat",
+            "oUser",
+            Some("test1@test".to_string()),
+        ));
+        assert_eq!(
+            to_string(&reply)?,
+            format!(
+                "{HEAD}<MsgType><![CDATA[transfer_customer_service]]></MsgType>\
+                <TransInfo><KfAccount><![CDATA[test1@test]]></KfAccount></TransInfo></xml>"
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_custom() -> anyhow::Result<()> {
+        let wx_client = MockWxClient::default();
+        WxReply::text("gh_mallchat", "oUser", "你好")
+            .send_custom(&wx_client)
+            .await?;
+        WxReply::news("gh_mallchat", "oUser", vec![])
+            .send_custom(&wx_client)
+            .await?;
+        let reply = WxReply::new(
+            "gh_mallchat",
+            "oUser",
+            WxReplyData::TransferCustomerService { kf_account: None },
+        );
+        assert!(reply.send_custom(&wx_client).await.is_err());
+
+        let sent = wx_client.sent_messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to_user_name, "oUser");
+        assert!(matches!(&sent[0].data, WxMessageData::Text { content } if content == "你好"));
+        assert_eq!(wx_client.sent_news().len(), 1);
+        Ok(())
+    }
+}
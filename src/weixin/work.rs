@@ -0,0 +1,343 @@
+//! # 企业微信
+//!
+//! 企业微信自建应用的回调协议与公众号的不同之处：
+//!
+//! - 只有加密模式，签名 `msg_signature` 由令牌、时间戳、随机数和密文共同计算；
+//! - 验证回调地址时 `echostr` 也是密文，需要解密后返回明文；
+//! - 密文中的接收方 ID 为企业 ID；
+//! - 接口域名为 `qyapi.weixin.qq.com`，access_token 使用企业 ID 和应用的 Secret 获取。
+//!
+//! 解密后的消息与公众号的格式相同，交给同一个处理流程。企业微信没有带参数的二维码，
+//! 登录使用企业微信的网页扫码登录，`state` 为 WebSocket 连接 ID。
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::sync::RwLock;
+use validator::Validate;
+
+use crate::secret;
+use crate::weixin::crypto::{constant_time_eq, WxCrypto};
+use crate::weixin::{
+    sha1_signature, AccessToken, WxAccessToken, WxEncodingAesKey, WxMessage, WxRawXmlMessage,
+    WxResult, WxStatus,
+};
+
+/// 企业微信应用配置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkConfig {
+    /// 回调 URL
+    pub callback_url: String,
+    /// 企业 ID
+    pub corp_id: String,
+    /// 应用的 AgentId
+    pub agent_id: i64,
+    /// 应用的 Secret，可以使用 `${ENV}` 引用环境变量
+    pub secret: String,
+    /// 接收消息的令牌
+    pub token: String,
+    /// 接收消息的加解密密钥
+    pub encoding_aes_key: WxEncodingAesKey,
+    /// 超时时间
+    #[serde(default = "default::timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+mod default {
+    pub fn timeout_secs() -> u64 {
+        10
+    }
+}
+
+impl WorkConfig {
+    /// 解析 `secret` 中的环境变量
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        self.secret = secret::interpolate("wx_work.secret", &self.secret)?;
+        Ok(())
+    }
+}
+
+/// 企业微信回调参数
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct WorkCallbackParam {
+    /// 签名，由令牌、时间戳、随机数和密文计算
+    #[validate(length(min = 1))]
+    pub msg_signature: String,
+    /// 时间戳
+    #[validate(length(min = 1))]
+    pub timestamp: String,
+    /// 随机数
+    #[validate(length(min = 1))]
+    pub nonce: String,
+    /// 验证回调地址时的加密字符串
+    pub echostr: Option<String>,
+}
+
+impl WorkCallbackParam {
+    /// 判断签名是否合法，以常量时间比较
+    pub fn is_signature_valid(&self, token: &str, encrypt: &str) -> bool {
+        constant_time_eq(
+            &sha1_signature(&mut [token, self.timestamp.as_str(), self.nonce.as_str(), encrypt]),
+            &self.msg_signature,
+        )
+    }
+}
+
+/// 企业微信加密过的 XML 消息
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", rename = "xml")]
+pub struct WorkEncryptedXmlMessage {
+    pub to_user_name: String,
+    #[serde(rename = "AgentID")]
+    pub agent_id: Option<String>,
+    pub encrypt: String,
+}
+
+/// 企业微信用户在企业内的 userid 对应的 openid
+///
+/// userid 最长 64 字节，超过了用户表中 openid 的长度，因此使用企业 ID 和 userid 的 SHA1
+/// 的前 32 位，与公众号 28 位的 openid 不会冲突
+pub fn work_open_id(corp_id: &str, user_id: &str) -> String {
+    let mut hasher = Sha1::default();
+    hasher.update(corp_id);
+    hasher.update(":");
+    hasher.update(user_id);
+    let mut open_id = hex::encode(hasher.finalize());
+    open_id.truncate(32);
+    open_id
+}
+
+/// 企业微信应用客户端
+#[derive(Clone)]
+pub struct WorkClient {
+    config: Arc<WorkConfig>,
+    client: reqwest::Client,
+    access_token: Arc<RwLock<WxAccessToken>>,
+}
+
+impl Debug for WorkClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkClient")
+            .field("corp_id", &self.config.corp_id)
+            .field("agent_id", &self.config.agent_id)
+            .finish()
+    }
+}
+
+impl WorkClient {
+    /// 新建一个企业微信客户端
+    pub async fn new(config: WorkConfig) -> anyhow::Result<Self> {
+        let client = reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        let access_token = Self::get_access_token(&client, &config).await?;
+        Ok(Self {
+            config: Arc::new(config),
+            client,
+            access_token: Arc::new(RwLock::new(WxAccessToken::from(access_token))),
+        })
+    }
+
+    /// 所有配置
+    pub fn config(&self) -> &WorkConfig {
+        self.config.as_ref()
+    }
+
+    /// 解密回调的密文，并校验接收方为本企业
+    pub fn decrypt(&self, encrypt: &str) -> anyhow::Result<String> {
+        decrypt(&self.config, encrypt)
+    }
+
+    /// 解密回调的消息
+    pub fn decrypt_message(
+        &self,
+        encrypted: &WorkEncryptedXmlMessage,
+    ) -> anyhow::Result<WxMessage> {
+        let xml_content = self.decrypt(&encrypted.encrypt)?;
+        let raw = quick_xml::de::from_str::<WxRawXmlMessage>(&xml_content)?;
+        WxMessage::try_from(raw)
+    }
+
+    /// 网页扫码登录的地址，登录后重定向到 `{callback_url}/wx/work/callBack`
+    pub fn login_url(&self, state: &str) -> String {
+        let callback_url = format!("{}/wx/work/callBack", self.config.callback_url);
+        format!(
+            "https://login.work.weixin.qq.com/wwlogin/sso/login?login_type=CorpApp&appid={}&agentid={}&redirect_uri={}&state={}",
+            self.config.corp_id,
+            self.config.agent_id,
+            urlencoding::encode(&callback_url),
+            urlencoding::encode(state),
+        )
+    }
+
+    /// 在 access_token 过期前 `ahead_secs` 秒内提前刷新，返回是否刷新
+    pub async fn refresh_access_token(&self, ahead_secs: u64) -> anyhow::Result<bool> {
+        if !self.access_token.read().await.expires_within(ahead_secs) {
+            return Ok(false);
+        }
+        let mut write = self.access_token.write().await;
+        if !write.expires_within(ahead_secs) {
+            return Ok(false);
+        }
+        *write = Self::get_access_token(&self.client, &self.config)
+            .await?
+            .into();
+        Ok(true)
+    }
+
+    /// 获取 access_token
+    #[tracing::instrument(skip_all, fields(corp_id = %config.corp_id), err)]
+    pub async fn get_access_token(
+        client: &reqwest::Client,
+        config: &WorkConfig,
+    ) -> anyhow::Result<AccessToken> {
+        let resp = client
+            .request(Method::GET, "https://qyapi.weixin.qq.com/cgi-bin/gettoken")
+            .query(&[
+                ("corpid", config.corp_id.as_str()),
+                ("corpsecret", config.secret.as_str()),
+            ])
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxResult<AccessToken> = resp.json().await?;
+        result.into()
+    }
+
+    /// 使用网页登录的 code 获取成员的 userid
+    #[tracing::instrument(skip_all, err)]
+    pub async fn get_user_id(&self, code: &str) -> anyhow::Result<String> {
+        #[derive(Deserialize)]
+        struct UserId {
+            userid: String,
+        }
+
+        self.refresh_access_token(0).await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::GET,
+                "https://qyapi.weixin.qq.com/cgi-bin/auth/getuserinfo",
+            )
+            .query(&[read.query(), ("code", code)])
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxResult<UserId> = resp.json().await?;
+        anyhow::Result::from(result).map(|user| user.userid)
+    }
+
+    /// 通过应用消息接口向成员发送文本消息
+    #[tracing::instrument(skip(self, content), err)]
+    pub async fn send_text(&self, user_id: &str, content: &str) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Text<'a> {
+            content: &'a str,
+        }
+        #[derive(Serialize)]
+        struct TextMessage<'a> {
+            touser: &'a str,
+            msgtype: &'a str,
+            agentid: i64,
+            text: Text<'a>,
+        }
+
+        self.refresh_access_token(0).await?;
+        let read = self.access_token.read().await;
+        let resp = self
+            .client
+            .request(
+                Method::POST,
+                "https://qyapi.weixin.qq.com/cgi-bin/message/send",
+            )
+            .query(&[read.query()])
+            .json(&TextMessage {
+                touser: user_id,
+                msgtype: "text",
+                agentid: self.config.agent_id,
+                text: Text { content },
+            })
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Response status is not OK: {}", status);
+        }
+
+        let result: WxStatus = resp.json().await?;
+        result.into()
+    }
+}
+
+/// 解密回调的密文，并校验接收方为本企业
+fn decrypt(config: &WorkConfig, encrypt: &str) -> anyhow::Result<String> {
+    let crypto = WxCrypto::new(&config.token, &config.encoding_aes_key, &config.corp_id);
+    Ok(crypto.decrypt(encrypt)?.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::weixin::crypto::WxCrypto;
+    use crate::weixin::sha1_signature;
+    use crate::weixin::work::{decrypt, work_open_id, WorkCallbackParam, WorkConfig};
+
+    fn config() -> anyhow::Result<WorkConfig> {
+        Ok(WorkConfig {
+            callback_url: "http://localhost:8080".to_string(),
+            corp_id: "ww1234567890".to_string(),
+            agent_id: 1000002,
+            secret: "secret".to_string(),
+            token: "token".to_string(),
+            encoding_aes_key: "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8".parse()?,
+            timeout_secs: 1,
+        })
+    }
+
+    /// 与企业微信服务器相同的加密方式，`receive_id` 为接收方 ID
+    fn encrypt(config: &WorkConfig, content: &str, receive_id: &str) -> String {
+        WxCrypto::new(&config.token, &config.encoding_aes_key, receive_id).encrypt(content, [0; 16])
+    }
+
+    #[test]
+    fn callback() -> anyhow::Result<()> {
+        let config = config()?;
+        let echostr = encrypt(&config, "1616140317555161061", &config.corp_id);
+        let param = WorkCallbackParam {
+            msg_signature: sha1_signature(&mut ["token", "1409659589", "263014780", &echostr]),
+            timestamp: "1409659589".to_string(),
+            nonce: "263014780".to_string(),
+            echostr: Some(echostr.clone()),
+        };
+        assert!(param.is_signature_valid("token", &echostr));
+        assert!(!param.is_signature_valid("other", &echostr));
+        assert_eq!(decrypt(&config, &echostr)?, "1616140317555161061");
+
+        let other = encrypt(&config, "1616140317555161061", "ww0000000000");
+        assert!(decrypt(&config, &other).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn open_id() {
+        let open_id = work_open_id("ww1234567890", "zhangsan");
+        assert_eq!(open_id.len(), 32);
+        assert_ne!(open_id, work_open_id("ww1234567890", "lisi"));
+    }
+}
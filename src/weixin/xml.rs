@@ -1,35 +1,39 @@
 //! XML extractor
+//!
+//! 请求使用 quick-xml 解析，支持微信消息中的 CDATA；回复使用 [`to_writer`] 序列化，字符串字段以 CDATA 输出
 
-use axum::body::{Full, HttpBody};
+use std::io::Write;
+
+use axum::body::Body;
 use axum::extract::rejection::BytesRejection;
-use axum::extract::FromRequest;
-use axum::headers::{HeaderMap, HeaderValue};
-use axum::http::{header, Request, StatusCode};
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
-use axum::{body, BoxError};
-use bytes::{BufMut, Bytes};
+use bytes::{BufMut, Bytes, BytesMut};
+use quick_xml::events::{BytesCData, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{DeError, Writer};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::ser::{Impossible, SerializeSeq, SerializeStruct};
+use serde::{Serialize, Serializer};
+
+use crate::handler::api::ApiError;
 
 /// XML extractor
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Xml<T>(pub T);
 
 #[axum::async_trait]
-impl<T, S, B> FromRequest<S, B> for Xml<T>
+impl<T, S> FromRequest<S> for Xml<T>
 where
     T: DeserializeOwned,
-    B: HttpBody + Send + 'static,
-    B::Data: Send,
-    B::Error: Into<BoxError>,
     S: Send + Sync,
 {
     type Rejection = XmlRejection;
 
-    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
         if xml_content_type(req.headers()) {
             let bytes = Bytes::from_request(req, state).await?;
-            let value = serde_xml_rs::from_reader(&*bytes)?;
+            let value = quick_xml::de::from_reader(&*bytes)?;
             Ok(Xml(value))
         } else {
             Err(XmlRejection::MissingXMLContentType)
@@ -57,7 +61,7 @@ fn xml_content_type(headers: &HeaderMap) -> bool {
     };
 
     let is_xml_content_type = mime.type_() == "application"
-        && (mime.subtype() == "xml" || mime.suffix().map_or(false, |name| name == "xml"));
+        && (mime.subtype() == "xml" || mime.suffix().is_some_and(|name| name == "xml"));
 
     is_xml_content_type
 }
@@ -84,32 +88,33 @@ impl<T> From<T> for Xml<T> {
     }
 }
 
+/// 回复缓冲区的初始大小，足够容纳常见的文本、图片被动回复，避免序列化时扩容
+const INITIAL_CAPACITY: usize = 512;
+
 impl<T> IntoResponse for Xml<T>
 where
     T: Serialize,
 {
     fn into_response(self) -> Response {
-        // Use a small initial capacity of 128 bytes like serde_json::to_vec
-        // https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
-        let mut buf = bytes::BytesMut::with_capacity(128).writer();
-        match serde_xml_rs::to_writer(&mut buf, &self.0) {
+        // 序列化到 BytesMut 后 freeze 为响应体，不再复制
+        let mut buf = BytesMut::with_capacity(INITIAL_CAPACITY).writer();
+        match to_writer(&mut buf, &self.0) {
             Ok(()) => (
                 [(
                     header::CONTENT_TYPE,
                     HeaderValue::from_static("application/xml"),
                 )],
-                buf.into_inner().freeze(),
-            )
-                .into_response(),
-            Err(err) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                [(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
-                )],
-                err.to_string(),
+                Body::from(buf.into_inner().freeze()),
             )
                 .into_response(),
+            Err(error) => {
+                tracing::error!(%error, "Failed to serialize xml response");
+                ApiError::custom(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to serialize xml response: {error}"),
+                )
+                .into_response()
+            }
         }
     }
 }
@@ -119,7 +124,7 @@ where
 pub enum XmlRejection {
     /// Failed to parse the request body as XML
     #[error("Failed to parse the request body as XML")]
-    InvalidXMLBody(#[from] serde_xml_rs::Error),
+    InvalidXMLBody(#[from] DeError),
     /// Expected request with `Content-Type: application/xml`
     #[error("Expected request with `Content-Type: application/xml`")]
     MissingXMLContentType,
@@ -130,18 +135,394 @@ pub enum XmlRejection {
 
 impl IntoResponse for XmlRejection {
     fn into_response(self) -> Response {
-        match self {
-            e @ XmlRejection::InvalidXMLBody(_) => {
-                let mut res = Response::new(body::boxed(Full::from(format!("{}", e))));
-                *res.status_mut() = StatusCode::UNPROCESSABLE_ENTITY;
-                res
-            }
-            e @ XmlRejection::MissingXMLContentType => {
-                let mut res = Response::new(body::boxed(Full::from(format!("{}", e))));
-                *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
-                res
+        let status = match &self {
+            XmlRejection::InvalidXMLBody(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            XmlRejection::MissingXMLContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            XmlRejection::BytesRejection(rejection) => rejection.status(),
+        };
+        ApiError::custom(status, self.to_string()).into_response()
+    }
+}
+
+/// 序列化为微信消息格式的 XML 字符串，见 [`to_writer`]
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, DeError> {
+    let mut buf = Vec::with_capacity(128);
+    to_writer(&mut buf, value)?;
+    String::from_utf8(buf).map_err(|e| DeError::Custom(e.to_string()))
+}
+
+/// 序列化为微信消息格式的 XML
+///
+/// 只支持字段为字符串、数字、布尔值、`Option`、结构体或序列的结构体：结构体名为根元素，字段为子元素，
+/// 字符串以 CDATA 输出，值为 `None` 的字段不输出；结构体字段的各个字段为嵌套的子元素，
+/// 序列的每一项为名为 `item` 的子元素，如图文回复中的 `<Articles><item>...</item></Articles>`
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<(), DeError> {
+    value.serialize(RootSerializer {
+        writer: &mut Writer::new(writer),
+    })
+}
+
+fn unsupported(what: &str) -> DeError {
+    DeError::Custom(format!("unsupported wechat xml value: {what}"))
+}
+
+macro_rules! unsupported {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, DeError> {
+                Err(unsupported(stringify!($method)))
             }
-            XmlRejection::BytesRejection(e) => e.into_response(),
+        )*
+    };
+}
+
+/// 根元素，只接受结构体
+struct RootSerializer<'w, W: Write> {
+    writer: &'w mut Writer<W>,
+}
+
+impl<'w, W: Write> Serializer for RootSerializer<'w, W> {
+    type Ok = ();
+    type Error = DeError;
+    type SerializeSeq = Impossible<(), DeError>;
+    type SerializeTuple = Impossible<(), DeError>;
+    type SerializeTupleStruct = Impossible<(), DeError>;
+    type SerializeTupleVariant = Impossible<(), DeError>;
+    type SerializeMap = Impossible<(), DeError>;
+    type SerializeStruct = StructSerializer<'w, W>;
+    type SerializeStructVariant = Impossible<(), DeError>;
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, DeError> {
+        self.writer
+            .write_event(Event::Start(BytesStart::new(name)))?;
+        Ok(StructSerializer {
+            writer: self.writer,
+            name,
+        })
+    }
+
+    unsupported! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), DeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), DeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), DeError> {
+        Err(unsupported("serialize_newtype_variant"))
+    }
+}
+
+/// 结构体元素的字段
+struct StructSerializer<'w, W: Write> {
+    writer: &'w mut Writer<W>,
+    name: &'static str,
+}
+
+impl<W: Write> SerializeStruct for StructSerializer<'_, W> {
+    type Ok = ();
+    type Error = DeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), DeError> {
+        value.serialize(FieldSerializer {
+            writer: self.writer,
+            name: key,
+        })
+    }
+
+    fn end(self) -> Result<(), DeError> {
+        self.writer
+            .write_event(Event::End(BytesEnd::new(self.name)))?;
+        Ok(())
+    }
+}
+
+/// 字段元素，字符串以 CDATA 输出，其他值以文本输出
+struct FieldSerializer<'w, W: Write> {
+    writer: &'w mut Writer<W>,
+    name: &'static str,
+}
+
+/// 序列元素的各项
+struct SeqSerializer<'w, W: Write> {
+    writer: &'w mut Writer<W>,
+    name: &'static str,
+}
+
+/// 序列中每一项的元素名
+const SEQ_ITEM: &str = "item";
+
+impl<W: Write> SerializeSeq for SeqSerializer<'_, W> {
+    type Ok = ();
+    type Error = DeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DeError> {
+        value.serialize(FieldSerializer {
+            writer: self.writer,
+            name: SEQ_ITEM,
+        })
+    }
+
+    fn end(self) -> Result<(), DeError> {
+        self.writer
+            .write_event(Event::End(BytesEnd::new(self.name)))?;
+        Ok(())
+    }
+}
+
+impl<W: Write> FieldSerializer<'_, W> {
+    fn write<'a>(self, contents: impl IntoIterator<Item = Event<'a>>) -> Result<(), DeError> {
+        self.writer
+            .write_event(Event::Start(BytesStart::new(self.name)))?;
+        for content in contents {
+            self.writer.write_event(content)?;
         }
+        self.writer
+            .write_event(Event::End(BytesEnd::new(self.name)))?;
+        Ok(())
+    }
+
+    fn write_text(self, text: &str) -> Result<(), DeError> {
+        self.write([Event::Text(BytesText::new(text))])
+    }
+}
+
+/// 将字符串拆分为多个 CDATA 段，内容中的 `]]>` 拆到两个段中
+fn cdata_sections(mut content: &str) -> impl Iterator<Item = Event<'_>> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let section = match content.find("]]>") {
+            Some(index) => {
+                let (section, rest) = content.split_at(index + 2);
+                content = rest;
+                section
+            }
+            None => {
+                done = true;
+                content
+            }
+        };
+        Some(Event::CData(BytesCData::new(section)))
+    })
+}
+
+macro_rules! serialize_display {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<(), DeError> {
+                self.write_text(&v.to_string())
+            }
+        )*
+    };
+}
+
+impl<'w, W: Write> Serializer for FieldSerializer<'w, W> {
+    type Ok = ();
+    type Error = DeError;
+    type SerializeSeq = SeqSerializer<'w, W>;
+    type SerializeTuple = Impossible<(), DeError>;
+    type SerializeTupleStruct = Impossible<(), DeError>;
+    type SerializeTupleVariant = Impossible<(), DeError>;
+    type SerializeMap = Impossible<(), DeError>;
+    type SerializeStruct = StructSerializer<'w, W>;
+    type SerializeStructVariant = Impossible<(), DeError>;
+
+    serialize_display! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), DeError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), DeError> {
+        self.write(cdata_sections(v))
+    }
+
+    fn serialize_none(self) -> Result<(), DeError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), DeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqSerializer<'w, W>, DeError> {
+        self.writer
+            .write_event(Event::Start(BytesStart::new(self.name)))?;
+        Ok(SeqSerializer {
+            writer: self.writer,
+            name: self.name,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<StructSerializer<'w, W>, DeError> {
+        self.writer
+            .write_event(Event::Start(BytesStart::new(self.name)))?;
+        Ok(StructSerializer {
+            writer: self.writer,
+            name: self.name,
+        })
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), DeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), DeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), DeError> {
+        Err(unsupported("serialize_newtype_variant"))
+    }
+
+    unsupported! {
+        serialize_bytes(&[u8]) -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    use serde::{Deserialize, Serialize};
+
+    use crate::weixin::xml::{to_string, Xml};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase", rename = "xml")]
+    struct Reply {
+        content: String,
+        create_time: i64,
+        media_id: Option<String>,
+    }
+
+    #[test]
+    fn cdata() -> anyhow::Result<()> {
+        let reply = Reply {
+            content: "a < b ]]> c".to_string(),
+            create_time: 1348831860,
+            media_id: None,
+        };
+        let xml = to_string(&reply)?;
+        assert_eq!(
+            xml,
+            "<xml><Content><![CDATA[a < b ]]]]><![CDATA[> c]]></Content>\
+             <CreateTime>1348831860</CreateTime></xml>"
+        );
+        assert_eq!(quick_xml::de::from_str::<Reply>(&xml)?, reply);
+        assert!(to_string("text").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn response() -> anyhow::Result<()> {
+        let response = Xml(Reply {
+            content: "hello".to_string(),
+            create_time: 1,
+            media_id: Some("media".to_string()),
+        })
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/xml");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        assert_eq!(
+            &body[..],
+            b"<xml><Content><![CDATA[hello]]></Content><CreateTime>1</CreateTime>\
+              <MediaId><![CDATA[media]]></MediaId></xml>"
+        );
+
+        let response = Xml("not a struct").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["success"], false);
+        Ok(())
     }
 }